@@ -0,0 +1,39 @@
+//! Adding a command of your own to the app
+//!
+//! The command lives in a plugin, since the app's builder already holds
+//! its invoke handler. The frontend calls it as
+//! `invoke("plugin:library-stats|count_my_documents")`. Like any plugin
+//! command it needs a permission: declare the plugin in build.rs with
+//! `tauri_build::InlinedPlugin::new().commands(&["count_my_documents"])`
+//! and grant `library-stats:allow-count-my-documents` in a capability.
+
+use ai_knowledge_system_lib::error::AppResult;
+use ai_knowledge_system_lib::models::DocumentSort;
+use ai_knowledge_system_lib::processing::ProcessingRegistry;
+use ai_knowledge_system_lib::{build_app, AppState};
+use tauri::plugin::{Builder as PluginBuilder, TauriPlugin};
+use tauri::{State, Wry};
+
+/// Number of documents in the current user's library
+#[tauri::command]
+async fn count_my_documents(state: State<'_, AppState>) -> AppResult<usize> {
+    let user_id = state.session.current_user_id().await?;
+    let service = state.document_service.lock().await;
+    let documents = service
+        .get_documents_by_user(user_id, false, DocumentSort::default(), false, None, false)
+        .await?;
+    Ok(documents.len())
+}
+
+fn library_stats() -> TauriPlugin<Wry> {
+    PluginBuilder::new("library-stats")
+        .invoke_handler(tauri::generate_handler![count_my_documents])
+        .build()
+}
+
+fn main() {
+    build_app(ProcessingRegistry::with_builtin())
+        .plugin(library_stats())
+        .run(tauri::generate_context!())
+        .expect("error while running tauri application");
+}
@@ -0,0 +1,114 @@
+//! Library backups, written as packages (see `workspace_package`) into the
+//! configured backup directory with timestamped names
+
+use crate::error::{AppError, AppResult};
+use crate::file_store::FileStore;
+use crate::models::PackageExportSummary;
+use crate::services::{DocumentService, WorkspaceService};
+use crate::settings::AppSettings;
+use crate::storage;
+use crate::workspace_package;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// How often the scheduler checks whether a backup is due
+pub const BACKUP_CHECK_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+const FILE_PREFIX: &str = "backup-";
+const FILE_EXTENSION: &str = "zip";
+const TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+/// Write a backup of every document into `backup_dir`, then prune old backups
+///
+/// The archive is written under a temporary name and renamed when complete,
+/// so an interrupted run never counts as the latest backup. Files are read
+/// through `store`, so backups of an encrypted library hold plaintext.
+pub async fn export_backup(
+    documents: &DocumentService,
+    workspaces: &WorkspaceService,
+    store: Arc<dyn FileStore>,
+    backup_dir: &Path,
+    keep_last_n: usize,
+    cancel: CancellationToken,
+) -> AppResult<(PathBuf, PackageExportSummary)> {
+    if !backup_dir.is_dir() {
+        return Err(AppError::NotFound(format!("Backup directory {}", backup_dir.display())));
+    }
+
+    let now = Utc::now();
+    let file_name = format!("{}{}.{}", FILE_PREFIX, now.format(TIMESTAMP_FORMAT), FILE_EXTENSION);
+    let dest = backup_dir.join(&file_name);
+    let partial = backup_dir.join(format!("{}.partial", file_name));
+
+    let docs = documents.get_all_documents().await?;
+    // Compression only makes the archive smaller, so this is an upper bound
+    let needed: u64 = docs
+        .iter()
+        .filter_map(|d| d.file_size_bytes)
+        .map(|bytes| bytes.max(0) as u64)
+        .sum();
+    storage::ensure_disk_space(backup_dir, needed)?;
+
+    let name = format!("Backup {}", now.to_rfc3339());
+    let summary = workspace_package::export_documents(workspaces, documents, store, name, docs, &partial, cancel).await?;
+    if let Err(e) = std::fs::rename(&partial, &dest) {
+        let _ = std::fs::remove_file(&partial);
+        return Err(e.into());
+    }
+
+    if let Err(e) = prune_backups(backup_dir, keep_last_n) {
+        eprintln!("Failed to prune old backups in {}: {}", backup_dir.display(), e);
+    }
+
+    Ok((dest, summary))
+}
+
+/// Time of the newest completed backup in `backup_dir`, from its file name
+pub fn latest_backup_time(backup_dir: &Path) -> Option<DateTime<Utc>> {
+    list_backups(backup_dir).ok()?.into_iter().map(|(time, _)| time).max()
+}
+
+/// Whether a scheduled backup should run now
+///
+/// `last_run` is the later of the newest backup and the last attempt, so a
+/// failed attempt waits a full interval instead of retrying every check.
+pub fn is_due(settings: &AppSettings, last_run: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    if !settings.backup_enabled || settings.backup_dir.is_none() {
+        return false;
+    }
+    match last_run {
+        Some(last) => now - last >= chrono::Duration::hours(settings.backup_interval_hours as i64),
+        None => true,
+    }
+}
+
+/// Delete all but the newest `keep` backups
+fn prune_backups(backup_dir: &Path, keep: usize) -> std::io::Result<()> {
+    let mut backups = list_backups(backup_dir)?;
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.0));
+    for (_, path) in backups.into_iter().skip(keep) {
+        std::fs::remove_file(&path)?;
+    }
+    Ok(())
+}
+
+/// Completed backups in `backup_dir` with the time encoded in their names
+fn list_backups(backup_dir: &Path) -> std::io::Result<Vec<(DateTime<Utc>, PathBuf)>> {
+    let mut backups = Vec::new();
+    for entry in std::fs::read_dir(backup_dir)? {
+        let path = entry?.path();
+        let timestamp = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_prefix(FILE_PREFIX))
+            .and_then(|n| n.strip_suffix(&format!(".{}", FILE_EXTENSION)))
+            .and_then(|t| NaiveDateTime::parse_from_str(t, TIMESTAMP_FORMAT).ok());
+        if let Some(timestamp) = timestamp {
+            backups.push((timestamp.and_utc(), path));
+        }
+    }
+    Ok(backups)
+}
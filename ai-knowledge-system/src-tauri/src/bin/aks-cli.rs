@@ -0,0 +1,5 @@
+//! Command-line access to the library; see `ai_knowledge_system_lib::cli`
+
+fn main() -> std::process::ExitCode {
+    ai_knowledge_system_lib::cli::main()
+}
@@ -0,0 +1,352 @@
+//! Bulk renames: a rule of transforms applied in order to the title of
+//! every document a search matches
+//!
+//! A rule is compiled once per batch. Regexes are compiled with size and
+//! nesting limits; the regex crate never backtracks, so the limits are what
+//! keeps a pathological pattern, like a huge repetition count, from
+//! blowing up the compiled program.
+
+use crate::error::{AppError, AppResult};
+use crate::models::{BulkRenameResult, RenameCandidate, RenameCollision, RenamePreviewEntry, RenameRule, RenameStep};
+use regex::{Regex, RegexBuilder};
+use std::collections::HashMap;
+
+/// Most documents one bulk rename may cover
+pub const MAX_BATCH: usize = 5_000;
+
+/// Longest title a document can have
+pub const MAX_TITLE_CHARS: usize = 500;
+
+const MAX_PATTERN_CHARS: usize = 500;
+
+/// Bytes a compiled pattern may take
+const REGEX_SIZE_LIMIT: usize = 256 * 1024;
+
+/// Deepest nesting of groups and repetitions in a pattern
+const REGEX_NEST_LIMIT: u32 = 32;
+
+/// Longest file extension StripExtension removes
+const MAX_EXTENSION_CHARS: usize = 5;
+
+/// Widest zero padding of `{n:width}`
+const MAX_COUNTER_WIDTH: usize = 9;
+
+enum Segment {
+    Literal(String),
+    Title,
+    Authors,
+    Year,
+    PageCount,
+    Counter { width: usize },
+}
+
+enum Step {
+    StripExtension,
+    RegexReplace(Regex, String),
+    Prepend(Vec<Segment>),
+    Append(Vec<Segment>),
+    Set(Vec<Segment>),
+}
+
+/// A rename rule ready to apply
+pub struct CompiledRule {
+    steps: Vec<Step>,
+}
+
+impl CompiledRule {
+    pub fn compile(rule: &RenameRule) -> AppResult<Self> {
+        if rule.steps.is_empty() {
+            return Err(AppError::InvalidInput("A rename rule needs at least one step".to_string()));
+        }
+        let steps = rule
+            .steps
+            .iter()
+            .map(|step| match step {
+                RenameStep::StripExtension => Ok(Step::StripExtension),
+                RenameStep::RegexReplace { pattern, replacement } => {
+                    Ok(Step::RegexReplace(compile_pattern(pattern)?, replacement.clone()))
+                }
+                RenameStep::Prepend { text } => Ok(Step::Prepend(parse_template(text)?)),
+                RenameStep::Append { text } => Ok(Step::Append(parse_template(text)?)),
+                RenameStep::Set { text } => Ok(Step::Set(parse_template(text)?)),
+            })
+            .collect::<AppResult<_>>()?;
+        Ok(CompiledRule { steps })
+    }
+
+    /// The new title of `candidate`, the `n`th document of the batch
+    pub fn apply(&self, candidate: &RenameCandidate, n: usize) -> String {
+        let mut title = candidate.title.clone();
+        for step in &self.steps {
+            title = match step {
+                Step::StripExtension => strip_extension(&title).to_string(),
+                Step::RegexReplace(regex, replacement) => regex.replace_all(&title, replacement.as_str()).into_owned(),
+                Step::Prepend(template) => format!("{}{}", expand(template, &title, candidate, n), title),
+                Step::Append(template) => format!("{}{}", title, expand(template, &title, candidate, n)),
+                Step::Set(template) => expand(template, &title, candidate, n),
+            };
+        }
+        title.trim().to_string()
+    }
+}
+
+/// Apply a rule to a batch and check the result, without writing anything
+///
+/// Candidates are numbered from 1 in the order given.
+pub fn plan(rule: &CompiledRule, candidates: &[RenameCandidate]) -> BulkRenameResult {
+    let entries: Vec<RenamePreviewEntry> = candidates
+        .iter()
+        .enumerate()
+        .map(|(index, candidate)| RenamePreviewEntry {
+            document_id: candidate.id,
+            old_title: candidate.title.clone(),
+            new_title: rule.apply(candidate, index + 1),
+        })
+        .collect();
+
+    let mut by_title: HashMap<&str, Vec<uuid::Uuid>> = HashMap::new();
+    for entry in &entries {
+        if !entry.new_title.is_empty() {
+            by_title.entry(&entry.new_title).or_default().push(entry.document_id);
+        }
+    }
+    let mut collisions: Vec<RenameCollision> = by_title
+        .into_iter()
+        .filter(|(_, ids)| ids.len() > 1)
+        .map(|(title, document_ids)| RenameCollision {
+            title: title.to_string(),
+            document_ids,
+        })
+        .collect();
+    collisions.sort_by(|a, b| a.title.cmp(&b.title));
+
+    BulkRenameResult {
+        changed: entries.iter().filter(|e| e.new_title != e.old_title).count(),
+        empty_titles: entries
+            .iter()
+            .filter(|e| e.new_title.is_empty())
+            .map(|e| e.document_id)
+            .collect(),
+        too_long_titles: entries
+            .iter()
+            .filter(|e| e.new_title.chars().count() > MAX_TITLE_CHARS)
+            .map(|e| e.document_id)
+            .collect(),
+        collisions,
+        entries,
+        applied: false,
+    }
+}
+
+fn compile_pattern(pattern: &str) -> AppResult<Regex> {
+    if pattern.is_empty() {
+        return Err(AppError::InvalidInput("Pattern cannot be empty".to_string()));
+    }
+    if pattern.chars().count() > MAX_PATTERN_CHARS {
+        return Err(AppError::InvalidInput(format!(
+            "Pattern is longer than {} characters",
+            MAX_PATTERN_CHARS
+        )));
+    }
+    RegexBuilder::new(pattern)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .dfa_size_limit(REGEX_SIZE_LIMIT)
+        .nest_limit(REGEX_NEST_LIMIT)
+        .build()
+        .map_err(|e| AppError::InvalidInput(format!("Invalid pattern: {}", e)))
+}
+
+/// Parse literal text with `{placeholder}`s
+fn parse_template(text: &str) -> AppResult<Vec<Segment>> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => {
+                            return Err(AppError::InvalidInput(format!(
+                                "Unterminated \"{{{}\" in rename text; close it with \"}}\" or write \"{{{{\"",
+                                name
+                            )))
+                        }
+                    }
+                }
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(parse_placeholder(&name)?);
+            }
+            '}' => return Err(AppError::InvalidInput("Unmatched \"}\" in rename text; write \"}}\"".to_string())),
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+    Ok(segments)
+}
+
+fn parse_placeholder(name: &str) -> AppResult<Segment> {
+    let segment = match name {
+        "title" => Segment::Title,
+        "author" => Segment::Authors,
+        "year" => Segment::Year,
+        "page_count" => Segment::PageCount,
+        "n" => Segment::Counter { width: 0 },
+        _ => {
+            let width = name
+                .strip_prefix("n:")
+                .and_then(|width| width.parse().ok())
+                .filter(|&width| width <= MAX_COUNTER_WIDTH);
+            match width {
+                Some(width) => Segment::Counter { width },
+                None => return Err(AppError::InvalidInput(format!("Unknown placeholder {{{}}}", name))),
+            }
+        }
+    };
+    Ok(segment)
+}
+
+/// Fill in a template; missing metadata becomes empty text
+fn expand(template: &[Segment], title: &str, candidate: &RenameCandidate, n: usize) -> String {
+    let mut text = String::new();
+    for segment in template {
+        match segment {
+            Segment::Literal(literal) => text.push_str(literal),
+            Segment::Title => text.push_str(title),
+            Segment::Authors => text.push_str(candidate.authors.as_deref().unwrap_or_default()),
+            Segment::Year => text.push_str(candidate.year.as_deref().unwrap_or_default()),
+            Segment::PageCount => {
+                if let Some(count) = candidate.page_count {
+                    text.push_str(&count.to_string());
+                }
+            }
+            Segment::Counter { width } => text.push_str(&format!("{:0width$}", n, width = width)),
+        }
+    }
+    text
+}
+
+/// A title without a trailing ".ext" of letters or digits; a title that is
+/// only an extension, like ".env", is kept
+fn strip_extension(title: &str) -> &str {
+    match title.rfind('.') {
+        Some(dot) if dot > 0 => {
+            let extension = &title[dot + 1..];
+            let is_extension = (1..=MAX_EXTENSION_CHARS).contains(&extension.chars().count())
+                && extension.chars().all(|c| c.is_ascii_alphanumeric());
+            if is_extension {
+                &title[..dot]
+            } else {
+                title
+            }
+        }
+        _ => title,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(title: &str) -> RenameCandidate {
+        RenameCandidate {
+            id: uuid::Uuid::new_v4(),
+            title: title.to_string(),
+            authors: Some("Lovelace; Babbage".to_string()),
+            year: Some("1843".to_string()),
+            page_count: Some(66),
+        }
+    }
+
+    fn set(text: &str) -> AppResult<CompiledRule> {
+        CompiledRule::compile(&RenameRule {
+            steps: vec![RenameStep::Set { text: text.to_string() }],
+        })
+    }
+
+    fn invalid_input(result: AppResult<CompiledRule>) -> String {
+        match result {
+            Err(AppError::InvalidInput(message)) => message,
+            Err(e) => panic!("expected InvalidInput, got {:?}", e),
+            Ok(_) => panic!("expected InvalidInput, got a rule"),
+        }
+    }
+
+    #[test]
+    fn each_placeholder_is_filled_in() {
+        let notes = candidate("Notes.pdf");
+        let expanded = |text: &str| set(text).unwrap().apply(&notes, 7);
+        assert_eq!(expanded("{title}"), "Notes.pdf");
+        assert_eq!(expanded("{author}"), "Lovelace; Babbage");
+        assert_eq!(expanded("{year}"), "1843");
+        assert_eq!(expanded("{page_count} pages"), "66 pages");
+        assert_eq!(expanded("#{n}"), "#7");
+        assert_eq!(expanded("{n:3} {year} - {title}"), "007 1843 - Notes.pdf");
+        assert_eq!(expanded("{{literal}} {title}"), "{literal} Notes.pdf");
+    }
+
+    #[test]
+    fn missing_metadata_becomes_empty_text() {
+        let bare = RenameCandidate {
+            authors: None,
+            year: None,
+            page_count: None,
+            ..candidate("Notes")
+        };
+        assert_eq!(set("{title} ({year}) {author}{page_count}").unwrap().apply(&bare, 1), "Notes ()");
+    }
+
+    #[test]
+    fn unknown_placeholders_are_invalid_input() {
+        for text in ["{titel}", "{}", "{n:}", "{n:10}", "{n:x}", "{Title}"] {
+            let message = invalid_input(set(text));
+            assert!(message.starts_with("Unknown placeholder"), "{}: {}", text, message);
+        }
+    }
+
+    #[test]
+    fn unterminated_placeholders_are_invalid_input() {
+        for text in ["{title", "Report {year} {n:3", "{"] {
+            let message = invalid_input(set(text));
+            assert!(message.starts_with("Unterminated"), "{}: {}", text, message);
+        }
+        assert_eq!(invalid_input(set("{title")), r#"Unterminated "{title" in rename text; close it with "}" or write "{{""#);
+    }
+
+    #[test]
+    fn an_unmatched_closing_brace_is_invalid_input() {
+        assert!(invalid_input(set("title}")).starts_with("Unmatched"));
+    }
+
+    #[test]
+    fn steps_apply_in_order() {
+        let rule = CompiledRule::compile(&RenameRule {
+            steps: vec![
+                RenameStep::StripExtension,
+                RenameStep::RegexReplace {
+                    pattern: "_+".to_string(),
+                    replacement: " ".to_string(),
+                },
+                RenameStep::Prepend { text: "{year} ".to_string() },
+                RenameStep::Append { text: " ({n:2})".to_string() },
+            ],
+        })
+        .unwrap();
+        assert_eq!(rule.apply(&candidate("on_the__engine.pdf"), 3), "1843 on the engine (03)");
+    }
+}
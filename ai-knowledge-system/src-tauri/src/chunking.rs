@@ -0,0 +1,358 @@
+//! Splitting document text into overlapping chunks for embedding
+//!
+//! Chunks are filled by the approximate token counts of `tokens`; each
+//! chunk's `token_count` is its `estimate_tokens`, the same count the UI
+//! budgets with.
+//!
+//! Chinese and Japanese don't put spaces between words, so their text is
+//! split at sentence punctuation instead, and sentences longer than a
+//! chunk by character count.
+//!
+//! Text with pages is chunked page by page, so a page whose text is the
+//! same in a later extraction can keep its chunks.
+
+use crate::tokens;
+
+/// Version of the chunking below; bump it when chunks would come out
+/// differently, so `rebuild_derived_data` redoes older ones
+pub const CHUNKER_VERSION: i32 = 2;
+
+/// Tokens a chunk is filled up to
+const CHUNK_TOKENS: usize = 256;
+/// Tokens repeated from the end of one chunk at the start of the next
+const CHUNK_OVERLAP_TOKENS: usize = 32;
+
+/// Punctuation ending a Chinese or Japanese sentence
+const CJK_SENTENCE_ENDS: [char; 3] = ['。', '！', '？'];
+/// Non-whitespace chars looked at when guessing the script of a text
+const SCRIPT_SAMPLE_CHARS: usize = 2_000;
+
+/// A run of document text sized for one embedding
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextChunk {
+    /// Char (not byte) offset of the chunk within the document content
+    pub start_offset: usize,
+    pub content: String,
+    pub token_count: usize,
+    /// Page the chunk lies in, from 1; None for text without pages and
+    /// text after the last page
+    pub page_number: Option<i32>,
+}
+
+/// How a page of an extraction gets its chunks
+#[derive(Debug, Clone)]
+pub enum PageChunks {
+    /// The page's text is unchanged, so the chunks stored for page
+    /// `from_page` of the previous extraction are kept
+    Reused { from_page: i32 },
+    Chunked(Vec<TextChunk>),
+}
+
+/// A whitespace-separated word, or a sentence of CJK text, and where it
+/// sits in the text
+struct Word {
+    char_start: usize,
+    byte_start: usize,
+    byte_end: usize,
+    tokens: usize,
+}
+
+fn words(text: &str) -> Vec<Word> {
+    let mut words = Vec::new();
+    let mut current: Option<(usize, usize)> = None;
+    for (char_index, (byte_index, c)) in text.char_indices().enumerate() {
+        match (c.is_whitespace(), current) {
+            (false, None) => current = Some((char_index, byte_index)),
+            (true, Some((char_start, byte_start))) => {
+                words.push(Word {
+                    char_start,
+                    byte_start,
+                    byte_end: byte_index,
+                    tokens: tokens::word_tokens(&text[byte_start..byte_index]),
+                });
+                current = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some((char_start, byte_start)) = current {
+        words.push(Word {
+            char_start,
+            byte_start,
+            byte_end: text.len(),
+            tokens: tokens::word_tokens(&text[byte_start..]),
+        });
+    }
+    words
+}
+
+/// Whether the text is split at sentences rather than spaces
+///
+/// A language tag like "ja" or "zh-TW" decides; without one, text that is
+/// at least a third Han, kana or ideographic chars counts as CJK.
+fn is_cjk(text: &str, language: Option<&str>) -> bool {
+    if let Some(language) = language {
+        let primary = language.split(['-', '_']).next().unwrap_or_default();
+        return primary.eq_ignore_ascii_case("ja") || primary.eq_ignore_ascii_case("zh");
+    }
+    let (mut cjk, mut total) = (0, 0);
+    for c in text.chars().filter(|c| !c.is_whitespace()).take(SCRIPT_SAMPLE_CHARS) {
+        total += 1;
+        if is_cjk_char(c) {
+            cjk += 1;
+        }
+    }
+    total > 0 && cjk * 3 >= total
+}
+
+fn is_cjk_char(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}'     // Hiragana and Katakana
+        | '\u{3400}'..='\u{4DBF}'   // CJK Extension A
+        | '\u{4E00}'..='\u{9FFF}'   // CJK Unified Ideographs
+        | '\u{F900}'..='\u{FAFF}'   // CJK Compatibility Ideographs
+        | '\u{FF66}'..='\u{FF9F}'   // Halfwidth Katakana
+    )
+}
+
+/// Sentences of CJK text, ending after sentence punctuation or at
+/// whitespace; ones longer than a chunk are cut into chunk-sized pieces
+fn sentences(text: &str) -> Vec<Word> {
+    let mut sentences = Vec::new();
+    let mut current: Option<(usize, usize)> = None;
+    for (char_index, (byte_index, c)) in text.char_indices().enumerate() {
+        if c.is_whitespace() {
+            if let Some((char_start, byte_start)) = current.take() {
+                push_sentence(text, &mut sentences, char_start, byte_start, byte_index);
+            }
+            continue;
+        }
+        let (char_start, byte_start) = *current.get_or_insert((char_index, byte_index));
+        if CJK_SENTENCE_ENDS.contains(&c) {
+            push_sentence(text, &mut sentences, char_start, byte_start, byte_index + c.len_utf8());
+            current = None;
+        }
+    }
+    if let Some((char_start, byte_start)) = current {
+        push_sentence(text, &mut sentences, char_start, byte_start, text.len());
+    }
+    sentences
+}
+
+fn push_sentence(text: &str, sentences: &mut Vec<Word>, char_start: usize, byte_start: usize, byte_end: usize) {
+    // A CJK char is a token, so a chunk's worth of chars never overfills one
+    let mut piece = (char_start, byte_start);
+    let mut chars = 0;
+    for (offset, _) in text[byte_start..byte_end].char_indices() {
+        if chars == CHUNK_TOKENS {
+            let end = byte_start + offset;
+            sentences.push(Word {
+                char_start: piece.0,
+                byte_start: piece.1,
+                byte_end: end,
+                tokens: tokens::word_tokens(&text[piece.1..end]),
+            });
+            piece = (piece.0 + chars, end);
+            chars = 0;
+        }
+        chars += 1;
+    }
+    sentences.push(Word {
+        char_start: piece.0,
+        byte_start: piece.1,
+        byte_end,
+        tokens: tokens::word_tokens(&text[piece.1..byte_end]),
+    });
+}
+
+/// Split text into chunks of about `CHUNK_TOKENS` tokens on word boundaries,
+/// or sentence boundaries for Chinese and Japanese
+///
+/// `language` is the document's language tag when known; otherwise the
+/// script of the text decides. Consecutive chunks share up to
+/// `CHUNK_OVERLAP_TOKENS` tokens so a passage cut at a boundary still
+/// appears whole in one of them. A single word longer than a chunk becomes
+/// a chunk of its own.
+pub fn chunk_text(text: &str, language: Option<&str>) -> Vec<TextChunk> {
+    let words = if is_cjk(text, language) {
+        sentences(text)
+    } else {
+        words(text)
+    };
+    let mut chunks = Vec::new();
+    let mut start = 0;
+
+    while start < words.len() {
+        let mut end = start;
+        let mut tokens = 0;
+        while end < words.len() && (end == start || tokens + words[end].tokens <= CHUNK_TOKENS) {
+            tokens += words[end].tokens;
+            end += 1;
+        }
+        let content = &text[words[start].byte_start..words[end - 1].byte_end];
+        chunks.push(TextChunk {
+            start_offset: words[start].char_start,
+            content: content.to_string(),
+            token_count: tokens::estimate_tokens(content, None),
+            page_number: None,
+        });
+        if end == words.len() {
+            break;
+        }
+
+        // Step back for the overlap, always moving forward by at least a word
+        let mut next = end;
+        let mut overlap = 0;
+        while next > start + 1 && overlap + words[next - 1].tokens <= CHUNK_OVERLAP_TOKENS {
+            next -= 1;
+            overlap += words[next].tokens;
+        }
+        start = next;
+    }
+
+    chunks
+}
+
+/// Chunk one page; `page_start` is the char offset of the page within the
+/// document content
+pub fn chunk_page(page_number: i32, page_start: usize, page: &str, language: Option<&str>) -> Vec<TextChunk> {
+    let mut chunks = chunk_text(page, language);
+    for chunk in &mut chunks {
+        chunk.start_offset += page_start;
+        chunk.page_number = Some(page_number);
+    }
+    chunks
+}
+
+/// Chunk the text after the pages, e.g. filled-in form fields; the pages
+/// are stored with a newline after each
+pub fn chunk_after_pages(text: &str, pages: &[String], language: Option<&str>) -> Vec<TextChunk> {
+    let pages_end: usize = pages.iter().map(|page| page.chars().count() + 1).sum();
+    let rest = match text.char_indices().nth(pages_end) {
+        Some((start, _)) => &text[start..],
+        None => return Vec::new(),
+    };
+    let mut chunks = chunk_text(rest, language);
+    for chunk in &mut chunks {
+        chunk.start_offset += pages_end;
+    }
+    chunks
+}
+
+/// Chunk text with pages page by page, then the text after them
+pub fn chunk_pages(text: &str, pages: &[String], language: Option<&str>) -> Vec<TextChunk> {
+    let mut chunks = Vec::new();
+    let mut page_start = 0;
+    for (index, page) in pages.iter().enumerate() {
+        chunks.extend(chunk_page(index as i32 + 1, page_start, page, language));
+        page_start += page.chars().count() + 1;
+    }
+    chunks.extend(chunk_after_pages(text, pages, language));
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JAPANESE: &str = "今日は朝から雨が降っています。駅まで歩くのに傘が必要でした！会議は午後に延期されましたか？";
+    const CHINESE: &str = "我们研究个人知识库中的稀疏检索方法。实验表明学习到的词权重能提高短查询的召回率！索引成本足够低吗？";
+    const ENGLISH: &str = "Sparse retrieval keeps an inverted index, which suits incremental updates on a laptop. ";
+
+    /// Tokens of a chunk as it was filled, whatever `estimate_tokens` is built with
+    fn filled_tokens(chunk: &TextChunk) -> usize {
+        chunk.content.split_whitespace().map(tokens::word_tokens).sum()
+    }
+
+    /// Every chunk but the last is filled to within a sentence of the target
+    fn assert_sizes_in_range(chunks: &[TextChunk], longest_unit: usize) {
+        assert!(chunks.len() > 1);
+        for (index, chunk) in chunks.iter().enumerate() {
+            let tokens = filled_tokens(chunk);
+            assert!(tokens <= CHUNK_TOKENS, "chunk {} has {} tokens", index, tokens);
+            if index + 1 < chunks.len() {
+                assert!(tokens > CHUNK_TOKENS - longest_unit, "chunk {} has only {} tokens", index, tokens);
+            }
+        }
+    }
+
+    /// Each chunk is the text at its offset, and together they cover the
+    /// text, moving forward without gaps
+    fn assert_offsets_match(text: &str, chunks: &[TextChunk]) {
+        let chars: Vec<char> = text.chars().collect();
+        let (mut previous_start, mut previous_end) = (None, 0);
+        for chunk in chunks {
+            let len = chunk.content.chars().count();
+            let at_offset: String = chars[chunk.start_offset..chunk.start_offset + len].iter().collect();
+            assert_eq!(at_offset, chunk.content);
+            assert!(chunk.start_offset <= previous_end, "gap before offset {}", chunk.start_offset);
+            assert!(previous_start < Some(chunk.start_offset));
+            (previous_start, previous_end) = (Some(chunk.start_offset), chunk.start_offset + len);
+        }
+        assert_eq!(previous_end, chars.len());
+    }
+
+    #[test]
+    fn japanese_is_chunked_at_sentence_ends() {
+        let text = JAPANESE.repeat(40);
+        let chunks = chunk_text(&text, Some("ja"));
+
+        let longest_sentence = text.split_inclusive(CJK_SENTENCE_ENDS).map(|s| s.chars().count()).max().unwrap();
+        assert_sizes_in_range(&chunks, longest_sentence);
+        assert_offsets_match(&text, &chunks);
+        for chunk in &chunks {
+            assert!(chunk.content.ends_with(CJK_SENTENCE_ENDS), "{:?}", chunk.content);
+        }
+    }
+
+    #[test]
+    fn chinese_is_recognized_by_its_script() {
+        let text = CHINESE.repeat(40);
+        let chunks = chunk_text(&text, None);
+
+        let longest_sentence = text.split_inclusive(CJK_SENTENCE_ENDS).map(|s| s.chars().count()).max().unwrap();
+        assert_sizes_in_range(&chunks, longest_sentence);
+        assert_offsets_match(&text, &chunks);
+        assert_eq!(chunk_text(&text, Some("zh-TW")), chunks);
+    }
+
+    #[test]
+    fn cjk_sentences_longer_than_a_chunk_are_cut() {
+        let text = "稀".repeat(CHUNK_TOKENS * 2 + 10);
+        let chunks = chunk_text(&text, Some("zh"));
+        let sizes: Vec<usize> = chunks.iter().map(|c| c.content.chars().count()).collect();
+        assert_eq!(sizes, [CHUNK_TOKENS, CHUNK_TOKENS, 10]);
+    }
+
+    #[test]
+    fn english_is_chunked_at_words() {
+        let text = ENGLISH.repeat(40);
+        let chunks = chunk_text(&text, Some("en"));
+
+        let longest_word = text.split_whitespace().map(tokens::word_tokens).max().unwrap();
+        assert_sizes_in_range(&chunks, longest_word);
+        assert_offsets_match(text.trim_end(), &chunks);
+        // Consecutive chunks share up to CHUNK_OVERLAP_TOKENS
+        let shared = chunks[0].start_offset + chunks[0].content.chars().count() - chunks[1].start_offset;
+        let overlap: String = chunks[1].content.chars().take(shared).collect();
+        assert!(overlap.split_whitespace().map(tokens::word_tokens).sum::<usize>() <= CHUNK_OVERLAP_TOKENS);
+    }
+
+    #[test]
+    fn the_language_tag_overrides_the_script() {
+        // Tagged as English, CJK text has no spaces to split at
+        let text = JAPANESE.repeat(40);
+        assert_eq!(chunk_text(&text, Some("en")).len(), 1);
+    }
+
+    #[test]
+    fn pages_number_their_chunks_and_keep_document_offsets() {
+        let pages = ["First page.".to_string(), "第二页。".to_string()];
+        let text = format!("{}\n{}\nForm field\n", pages[0], pages[1]);
+        let chunks = chunk_pages(&text, &pages, None);
+
+        let found: Vec<(Option<i32>, usize, &str)> =
+            chunks.iter().map(|c| (c.page_number, c.start_offset, c.content.as_str())).collect();
+        assert_eq!(found, [(Some(1), 0, "First page."), (Some(2), 12, "第二页。"), (None, 17, "Form field")]);
+    }
+}
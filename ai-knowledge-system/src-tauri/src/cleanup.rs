@@ -0,0 +1,64 @@
+//! Suggestions for what to delete when storage runs low
+//!
+//! Each category is one query over the user's live documents; sizes count
+//! against the quota as recorded, even where imports share a stored file.
+
+use crate::error::AppResult;
+use crate::models::{CleanupCandidate, CleanupCategory, CleanupSuggestions, DuplicateGroup};
+use crate::services::DocumentService;
+use uuid::Uuid;
+
+/// Documents listed under "largest" by default
+pub const DEFAULT_LARGEST_COUNT: usize = 20;
+
+/// Unopened documents younger than this aren't suggested
+const NEVER_OPENED_MIN_AGE_DAYS: i64 = 183;
+
+pub async fn cleanup_suggestions(
+    service: &DocumentService,
+    user_id: Uuid,
+    largest_count: usize,
+) -> AppResult<CleanupSuggestions> {
+    let largest = service.largest_documents(user_id, largest_count as i64, None).await?;
+    let cutoff = chrono::Utc::now() - chrono::Duration::days(NEVER_OPENED_MIN_AGE_DAYS);
+    let never_opened = service.never_opened_documents(user_id, cutoff).await?;
+    let duplicates = service.duplicate_documents(user_id).await?;
+    let failed = service.failed_documents_with_files(user_id).await?;
+
+    Ok(CleanupSuggestions {
+        largest: category(largest),
+        never_opened: category(never_opened),
+        duplicates: group_duplicates(duplicates),
+        failed_with_files: category(failed),
+    })
+}
+
+fn category(documents: Vec<CleanupCandidate>) -> CleanupCategory {
+    let reclaimable_bytes = documents.iter().map(|d| d.file_size_bytes).sum();
+    CleanupCategory {
+        documents,
+        reclaimable_bytes,
+    }
+}
+
+/// Group rows sorted by hash, largest reclaimable group first
+fn group_duplicates(rows: Vec<(String, CleanupCandidate)>) -> Vec<DuplicateGroup> {
+    let mut groups: Vec<DuplicateGroup> = Vec::new();
+    for (file_hash, candidate) in rows {
+        match groups.last_mut() {
+            Some(group) if group.file_hash == file_hash => group.documents.push(candidate),
+            _ => groups.push(DuplicateGroup {
+                file_hash,
+                documents: vec![candidate],
+                reclaimable_bytes: 0,
+            }),
+        }
+    }
+
+    for group in &mut groups {
+        // The oldest copy is the one kept
+        group.reclaimable_bytes = group.documents.iter().skip(1).map(|d| d.file_size_bytes).sum();
+    }
+    groups.sort_by_key(|group| std::cmp::Reverse(group.reclaimable_bytes));
+    groups
+}
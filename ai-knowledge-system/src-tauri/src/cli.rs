@@ -0,0 +1,768 @@
+//! `aks-cli`: the library from the command line, for scripts and servers
+//!
+//! The tool reads the app's settings file and connects to the same
+//! DATABASE_URL and storage root, acting as the settings' active user.
+//! Listing, searching and exporting only read. Importing and reprocessing
+//! only queue documents; the app extracts them the next time it starts, so
+//! there is one pipeline to keep right. Both refuse to run while the app is
+//! open, which holds `db::APP_LOCK_KEY`, and imports into an encrypted
+//! library need the app, which holds the key once unlocked.
+//!
+//! Changes made here reach an open app's views only once it reloads them;
+//! they don't go through its change feed.
+
+use crate::collation;
+use crate::db;
+use crate::error::{AppError, AppResult};
+use crate::export;
+use crate::file_store::{FileStore, Keyring, LocalCopy};
+use crate::file_utils;
+use crate::folder_import;
+use crate::models::{CreateDocumentDto, Document, DocumentStatus, ImportMethod, MarkdownFidelity, ProvenanceInfo};
+use crate::processing::ProcessingRegistry;
+use crate::query_parser;
+use crate::quick_open::QuickOpenIndex;
+use crate::services::{DocumentChanges, DocumentService, UserService, WorkspaceService};
+use crate::settings::AppSettings;
+use crate::storage;
+use crate::virus_scan;
+use serde::Serialize;
+use sqlx::{PgConnection, PgPool};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// The app's Tauri identifier, which names its config and data directories
+const IDENTIFIER: &str = "com.sunilkumar.ai-knowledge-system";
+
+const USAGE: &str = "\
+Usage: aks-cli [--json] [--settings <file>] <command>
+
+Commands:
+  import [--reference] <path>...    Queue files, and the files in folders
+  list [--status <status>] [--limit <n>]
+  search [--limit <n>] <query>      Search with the app's search box syntax
+  export <doc-id> [--format md|html] [--output <file>]
+  reprocess <doc-id>                Queue a processed or failed document again
+
+Queued documents are processed the next time the app starts. Importing and
+reprocessing refuse to run while the app is open.
+
+Exit codes: 0 success, 1 failure, 2 usage or query error, 3 not found,
+4 the app is open";
+
+/// Bad arguments or an invalid search query
+const EXIT_USAGE: u8 = 2;
+const EXIT_NOT_FOUND: u8 = 3;
+/// A change was refused because the app holds the library
+const EXIT_APP_OPEN: u8 = 4;
+
+/// Documents listed or searched unless asked for another number
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 1_000;
+
+/// Longest title printed in a table before it is cut short
+const MAX_TABLE_TITLE_CHARS: usize = 80;
+
+struct Invocation {
+    json: bool,
+    settings_path: Option<PathBuf>,
+    command: Command,
+}
+
+enum Command {
+    Import { paths: Vec<PathBuf>, reference: bool },
+    List { status: Option<String>, limit: i64 },
+    Search { query: String, limit: i64 },
+    Export { document_id: Uuid, format: ExportFormat, output: Option<PathBuf> },
+    Reprocess { document_id: Uuid },
+}
+
+#[derive(Clone, Copy)]
+enum ExportFormat {
+    Markdown,
+    Html,
+}
+
+impl ExportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+        }
+    }
+}
+
+/// Run the tool with the process's arguments
+pub fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let invocation = match parse_args(args) {
+        Ok(invocation) => invocation,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Failed to start: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    match runtime.block_on(run(invocation)) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+fn exit_code(error: &AppError) -> u8 {
+    match error {
+        AppError::InvalidQuery { .. } => EXIT_USAGE,
+        AppError::NotFound(_) => EXIT_NOT_FOUND,
+        AppError::Busy(_) => EXIT_APP_OPEN,
+        _ => 1,
+    }
+}
+
+/// Options can come anywhere after the program name; the last of a
+/// repeated option wins
+fn parse_args(args: Vec<String>) -> Result<Invocation, String> {
+    let mut json = false;
+    let mut settings_path = None;
+    let mut options: Vec<(&'static str, Option<String>)> = Vec::new();
+    let mut positional = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let key = match arg.as_str() {
+            "--json" => {
+                json = true;
+                continue;
+            }
+            "--settings" => {
+                settings_path = Some(PathBuf::from(option_value("--settings", args.next())?));
+                continue;
+            }
+            "--" => {
+                positional.extend(args.by_ref());
+                continue;
+            }
+            "--reference" => {
+                options.push(("--reference", None));
+                continue;
+            }
+            "--status" => "--status",
+            "--limit" => "--limit",
+            "--format" => "--format",
+            "--output" | "-o" => "--output",
+            option if option.starts_with('-') && option.len() > 1 => {
+                return Err(format!("Unknown option {}", option));
+            }
+            _ => {
+                positional.push(arg);
+                continue;
+            }
+        };
+        options.push((key, Some(option_value(key, args.next())?)));
+    }
+
+    let mut positional = positional.into_iter();
+    let name = positional.next().ok_or("No command given")?;
+    let allowed: &[&str] = match name.as_str() {
+        "import" => &["--reference"],
+        "list" => &["--status", "--limit"],
+        "search" => &["--limit"],
+        "export" => &["--format", "--output"],
+        "reprocess" => &[],
+        _ => return Err(format!("Unknown command {}", name)),
+    };
+    if let Some((key, _)) = options.iter().find(|(key, _)| !allowed.contains(key)) {
+        return Err(format!("{} doesn't take {}", name, key));
+    }
+    let option = |key: &str| {
+        options
+            .iter()
+            .rev()
+            .find(|(k, _)| *k == key)
+            .and_then(|(_, value)| value.clone())
+    };
+    let limit = match option("--limit") {
+        Some(value) => value
+            .parse::<i64>()
+            .map_err(|_| format!("--limit needs a number, not {}", value))?
+            .clamp(1, MAX_LIMIT),
+        None => DEFAULT_LIMIT,
+    };
+    let rest: Vec<String> = positional.collect();
+
+    let command = match name.as_str() {
+        "import" => {
+            if rest.is_empty() {
+                return Err("import needs at least one path".to_string());
+            }
+            Command::Import {
+                paths: rest.into_iter().map(PathBuf::from).collect(),
+                reference: options.iter().any(|(key, _)| *key == "--reference"),
+            }
+        }
+        "list" => {
+            if let Some(extra) = rest.first() {
+                return Err(format!("list doesn't take {}", extra));
+            }
+            Command::List {
+                status: option("--status"),
+                limit,
+            }
+        }
+        "search" => {
+            if rest.is_empty() {
+                return Err("search needs a query".to_string());
+            }
+            Command::Search {
+                query: rest.join(" "),
+                limit,
+            }
+        }
+        "export" => {
+            let format = match option("--format").as_deref() {
+                None | Some("md") | Some("markdown") => ExportFormat::Markdown,
+                Some("html") => ExportFormat::Html,
+                Some(other) => return Err(format!("Unknown export format {}; use md or html", other)),
+            };
+            Command::Export {
+                document_id: document_id_arg(&name, rest)?,
+                format,
+                output: option("--output").map(PathBuf::from),
+            }
+        }
+        _ => Command::Reprocess {
+            document_id: document_id_arg(&name, rest)?,
+        },
+    };
+    Ok(Invocation {
+        json,
+        settings_path,
+        command,
+    })
+}
+
+fn option_value(key: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("{} needs a value", key))
+}
+
+fn document_id_arg(command: &str, rest: Vec<String>) -> Result<Uuid, String> {
+    match rest.as_slice() {
+        [id] => Uuid::parse_str(id).map_err(|_| format!("{} is not a document id", id)),
+        _ => Err(format!("{} needs exactly one document id", command)),
+    }
+}
+
+async fn run(invocation: Invocation) -> AppResult<ExitCode> {
+    let library = Library::open(invocation.settings_path).await?;
+    let json = invocation.json;
+    match invocation.command {
+        Command::List { status, limit } => {
+            // The search box syntax validates the status name
+            let query = status.map(|status| format!("status:{}", status)).unwrap_or_default();
+            print_documents(json, &library.search(&query, limit).await?)?;
+        }
+        Command::Search { query, limit } => {
+            print_documents(json, &library.search(&query, limit).await?)?;
+        }
+        Command::Export {
+            document_id,
+            format,
+            output,
+        } => {
+            let path = library.export(document_id, format, output).await?;
+            if json {
+                print_json(&serde_json::json!({ "document_id": document_id, "path": path }))?;
+            } else {
+                println!("{}", path.display());
+            }
+        }
+        Command::Reprocess { document_id } => {
+            let _lock = library.lock().await?;
+            let document = library.requeue(document_id).await?;
+            if json {
+                print_json(&document)?;
+            } else {
+                println!("{}  queued  {}", document.id, document.title);
+                eprintln!("The app processes it the next time it starts.");
+            }
+        }
+        Command::Import { paths, reference } => {
+            let _lock = library.lock().await?;
+            let outcomes = library.import(&paths, reference).await?;
+            if json {
+                print_json(&outcomes)?;
+            } else {
+                print_import(&outcomes);
+            }
+            if outcomes.iter().any(|outcome| outcome.error.is_some()) {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// How one file of an import went
+#[derive(Debug, Serialize)]
+struct ImportOutcome {
+    path: String,
+    /// The document queued for the file
+    document_id: Option<Uuid>,
+    /// The document that already has the file's content; the file was
+    /// skipped
+    duplicate_of: Option<Uuid>,
+    error: Option<String>,
+}
+
+enum Imported {
+    Queued(Uuid),
+    Duplicate(Uuid),
+}
+
+/// The library as the app sees it: its settings, database and active user
+struct Library {
+    settings: AppSettings,
+    /// Where the app keeps its data, e.g. the default storage root; None
+    /// when it can't be told on this system
+    data_dir: Option<PathBuf>,
+    pool: PgPool,
+    user_id: Uuid,
+    documents: DocumentService,
+    users: UserService,
+    workspaces: WorkspaceService,
+}
+
+impl Library {
+    async fn open(settings_path: Option<PathBuf>) -> AppResult<Self> {
+        let settings_path = settings_path
+            .or_else(|| app_config_dir().map(|dir| dir.join("settings.json")))
+            .ok_or_else(|| AppError::Other("Can't tell where the app's settings are; pass --settings".to_string()))?;
+        let (settings, corrupt_copy) = AppSettings::load(&settings_path);
+        if let Some(copy) = corrupt_copy {
+            eprintln!("Settings were unreadable and have been reset; the old file is at {}", copy.display());
+        }
+
+        dotenvy::dotenv().ok();
+        let db = db::Database::new()
+            .await
+            .map_err(|e| AppError::Other(format!("{} ({})", e.message, e.hint)))?;
+        let pool = db.pool().clone();
+        let quick_index = Arc::new(QuickOpenIndex::default());
+        let changes = DocumentChanges::new();
+
+        let users = UserService::new(pool.clone());
+        let user = match settings.active_user_id {
+            Some(user_id) => users.get_user(user_id).await?,
+            None => None,
+        };
+        let user_id = user.ok_or(AppError::NoActiveUser)?.id;
+
+        Ok(Library {
+            data_dir: app_data_dir(),
+            documents: DocumentService::new(pool.clone(), Arc::clone(&quick_index), changes.clone()),
+            workspaces: WorkspaceService::new(pool.clone(), quick_index, changes),
+            users,
+            user_id,
+            pool,
+            settings,
+        })
+    }
+
+    fn data_dir(&self) -> AppResult<&Path> {
+        self.data_dir
+            .as_deref()
+            .ok_or_else(|| AppError::Other("Can't tell where the app keeps its data on this system".to_string()))
+    }
+
+    /// Take the app lock for a change; dropping the connection releases it
+    async fn lock(&self) -> AppResult<PgConnection> {
+        db::try_app_lock(&self.pool).await?.ok_or_else(|| {
+            AppError::Busy("The app is open; close it before changing the library from the command line".to_string())
+        })
+    }
+
+    async fn document(&self, document_id: Uuid) -> AppResult<Document> {
+        self.documents
+            .get_document(document_id)
+            .await?
+            .filter(|d| d.user_id == self.user_id)
+            .ok_or_else(|| AppError::NotFound("Document".to_string()))
+    }
+
+    async fn search(&self, query: &str, limit: i64) -> AppResult<Vec<Document>> {
+        let query = query_parser::parse_query(query)?;
+        let mut documents = self.documents.search_documents(self.user_id, &query, limit, 0).await?;
+        // Listings never show content, and it can be large
+        for document in &mut documents {
+            document.content = None;
+        }
+        Ok(documents)
+    }
+
+    /// Export a document to `output`, or to `<id>.<extension>` in the
+    /// current directory; the path written
+    async fn export(&self, document_id: Uuid, format: ExportFormat, output: Option<PathBuf>) -> AppResult<PathBuf> {
+        let document = self.document(document_id).await?;
+        let pages: Vec<String> = self
+            .documents
+            .get_pages(document_id)
+            .await?
+            .into_iter()
+            .map(|p| p.content)
+            .collect();
+        let pages = (!pages.is_empty()).then_some(pages.as_slice());
+        let dest = output.unwrap_or_else(|| PathBuf::from(format!("{}.{}", document_id, format.extension())));
+
+        let result = match format {
+            ExportFormat::Markdown => {
+                // Word documents are read from the stored file for their
+                // paragraph styles when it can be read here
+                let is_docx = document.mime_type.as_deref() == Some(crate::DOCX_MIME_TYPE)
+                    || document.file_type.as_deref() == Some("DOCX");
+                let local = match document.file_path.as_deref().filter(|_| is_docx) {
+                    Some(path) => Keyring::default()
+                        .store(&self.settings)
+                        .and_then(|store| LocalCopy::new(&*store, Path::new(path)))
+                        .inspect_err(|e| eprintln!("Exporting from the extracted text: {}", e))
+                        .ok(),
+                    None => None,
+                };
+                export::markdown::write_document_markdown(
+                    &document,
+                    pages,
+                    local.as_ref().map(|l| l.path()),
+                    MarkdownFidelity::default(),
+                    &dest,
+                )
+            }
+            ExportFormat::Html => {
+                let thumbnail = self
+                    .data_dir
+                    .as_ref()
+                    .map(|dir| dir.join("thumbnails").join(format!("{}.png", document_id)))
+                    .filter(|path| path.exists());
+                export::html::write_document_html(&document, pages, thumbnail.as_deref(), &dest)
+            }
+        };
+        if result.is_err() {
+            // Don't leave a truncated export behind
+            let _ = std::fs::remove_file(&dest);
+        }
+        result?;
+        Ok(dest)
+    }
+
+    /// Queue a processed or failed document for the app to process again
+    async fn requeue(&self, document_id: Uuid) -> AppResult<Document> {
+        let mut document = self.document(document_id).await?;
+        if document.file_path.is_none() {
+            return Err(AppError::NotFound("Stored file".to_string()));
+        }
+        self.documents
+            .transition_status(document_id, document.status, DocumentStatus::Queued, None)
+            .await?;
+        document.status = DocumentStatus::Queued;
+        document.content = None;
+        Ok(document)
+    }
+
+    /// Queue files, and the files in folders, in the order given
+    ///
+    /// Files are checked like uploads in the app. One that can't be queued
+    /// is reported and the rest carry on.
+    async fn import(&self, paths: &[PathBuf], reference: bool) -> AppResult<Vec<ImportOutcome>> {
+        // Where stored copies go; referenced files stay where they are
+        let target = if reference {
+            None
+        } else {
+            if self.settings.encryption_enabled {
+                return Err(AppError::InvalidInput(
+                    "The library is encrypted; import from the app, or with --reference".to_string(),
+                ));
+            }
+            let store = Keyring::default().store(&self.settings)?;
+            let documents_dir = storage::online_documents_dir_in(self.data_dir()?, &self.settings)?;
+            Some((store, documents_dir))
+        };
+        let registry = ProcessingRegistry::with_builtin();
+
+        let mut outcomes = Vec::new();
+        for path in paths {
+            let (files, folder_root) = if path.is_dir() {
+                match folder_import::list_files(path) {
+                    Ok(files) => (files, Some(path.as_path())),
+                    Err(e) => {
+                        outcomes.push(ImportOutcome {
+                            path: path.display().to_string(),
+                            document_id: None,
+                            duplicate_of: None,
+                            error: Some(e.to_string()),
+                        });
+                        continue;
+                    }
+                }
+            } else {
+                (vec![path.clone()], None)
+            };
+
+            for file in files {
+                let imported = self.import_file(&registry, target.as_ref(), &file, folder_root).await;
+                let (document_id, duplicate_of, error) = match imported {
+                    Ok(Imported::Queued(id)) => (Some(id), None, None),
+                    Ok(Imported::Duplicate(id)) => (None, Some(id), None),
+                    Err(e) => (None, None, Some(e.to_string())),
+                };
+                outcomes.push(ImportOutcome {
+                    path: file.display().to_string(),
+                    document_id,
+                    duplicate_of,
+                    error,
+                });
+            }
+        }
+        Ok(outcomes)
+    }
+
+    async fn import_file(
+        &self,
+        registry: &ProcessingRegistry,
+        target: Option<&(Arc<dyn FileStore>, PathBuf)>,
+        file: &Path,
+        folder_root: Option<&Path>,
+    ) -> AppResult<Imported> {
+        let metadata = std::fs::metadata(file)?;
+        if !metadata.is_file() {
+            return Err(AppError::InvalidInput("Only files and folders can be imported".to_string()));
+        }
+        let file_size = metadata.len() as i64;
+        if file_size == 0 {
+            return Err(AppError::EmptyFile(file.display().to_string()));
+        }
+        let source = std::fs::canonicalize(file)?;
+        let file_name = source
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("unknown")
+            .to_string();
+        let extension = source
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .unwrap_or_default();
+        let mime_type = file_utils::detect_mime_type(&source)?;
+        let file_hash = file_utils::calculate_sha256(&source)?;
+
+        let same_size = self.documents.file_hashes_by_size(self.user_id, file_size).await?;
+        if let Some((existing, _)) = same_size.into_iter().find(|(_, hash)| *hash == file_hash) {
+            return Ok(Imported::Duplicate(existing));
+        }
+
+        if registry.find(&mime_type, &extension).is_none() {
+            if file_utils::is_executable(&mime_type, &extension) {
+                return Err(AppError::InvalidInput(format!("{} is a program, not a document", file_name)));
+            }
+            if !self.settings.accept_unsupported {
+                return Err(AppError::InvalidInput(format!("No extractor supports {} files", mime_type)));
+            }
+        }
+        if target.is_some() {
+            let usage = self
+                .users
+                .get_storage_usage(self.user_id)
+                .await?
+                .ok_or(AppError::NoActiveUser)?;
+            crate::ensure_quota(&usage, file_size)?;
+        }
+
+        // Filed and processed as an upload in the app would be
+        let workspace_id = self.users.default_workspace(self.user_id).await?;
+        let workspace_defaults = match workspace_id {
+            Some(workspace_id) => self.workspaces.processing_defaults(workspace_id).await?,
+            None => None,
+        };
+        let processing_options = workspace_defaults.unwrap_or_else(|| self.settings.processing_defaults.clone());
+
+        let dto = CreateDocumentDto {
+            user_id: self.user_id,
+            title: file_name.clone(),
+            file_name: file_name.clone(),
+            file_size_bytes: file_size,
+            file_type: file_utils::get_file_extension(&source),
+            mime_type: None,
+            parent_document_id: None,
+            workspace_id,
+            file_hash: None,
+            title_sort: collation::title_sort_key(&file_name, &self.settings.locale),
+            status: DocumentStatus::Queued,
+            external_file: target.is_none(),
+            provenance: ProvenanceInfo::now(
+                ImportMethod::Cli,
+                Some(&source),
+                folder_root,
+                self.settings.record_import_paths,
+            ),
+        };
+        let document_id = self.documents.create_document(dto).await?.id;
+
+        let stored = async {
+            self.documents.set_processing_options(document_id, &processing_options).await?;
+            // Scanned as an upload in the app would be
+            let scanner = virus_scan::Scanner::from_settings(&self.settings)?;
+            let (dest, scan) = match target {
+                Some((store, documents_dir)) => {
+                    let (copy, scan) = match &scanner {
+                        Some(scanner) => {
+                            let copy = virus_scan::ScanCopy::new(&source, documents_dir, document_id, &file_name)?;
+                            let scan = scanner.check(copy.path(), &file_name).await?;
+                            (Some(copy), Some(scan))
+                        }
+                        None => (None, None),
+                    };
+                    let stored_from = copy.as_ref().map_or(source.as_path(), |copy| copy.path());
+                    let dest = storage::store_file(&**store, stored_from, documents_dir, document_id, &file_name)?;
+                    (dest, scan)
+                }
+                None => {
+                    let scan = match &scanner {
+                        Some(scanner) => Some(scanner.check(&source, &file_name).await?),
+                        None => None,
+                    };
+                    (source.clone(), scan)
+                }
+            };
+            let recorded = self
+                .documents
+                .record_stored_file(document_id, &dest.to_string_lossy(), &mime_type, &file_hash)
+                .await;
+            if let Err(e) = recorded {
+                // An external file is the user's own and stays
+                if target.is_some() {
+                    let _ = std::fs::remove_file(&dest);
+                }
+                return Err(e.into());
+            }
+            if let Some(scan) = scan {
+                self.documents.record_scan(document_id, &scan).await?;
+            }
+            Ok::<(), AppError>(())
+        }
+        .await;
+        if let Err(e) = stored {
+            // Nothing was stored, so don't keep a row that counts against the quota
+            if let Err(discard) = self.documents.discard_upload(document_id).await {
+                eprintln!("Failed to discard {}: {}", document_id, discard);
+            }
+            return Err(e);
+        }
+
+        // Pick up notes kept next to the original, e.g. paper.pdf + paper.md
+        if let Some(sidecar) = file_utils::find_sidecar_note(&source) {
+            if let Err(e) = crate::attach_sidecar(&self.documents, document_id, &sidecar).await {
+                eprintln!("Failed to attach notes from {}: {}", sidecar.display(), e);
+            }
+        }
+        Ok(Imported::Queued(document_id))
+    }
+}
+
+/// Where Tauri puts the app's config directory on this system
+fn app_config_dir() -> Option<PathBuf> {
+    platform_dir("XDG_CONFIG_HOME", ".config").map(|dir| dir.join(IDENTIFIER))
+}
+
+/// Where Tauri puts the app's data directory on this system
+fn app_data_dir() -> Option<PathBuf> {
+    platform_dir("XDG_DATA_HOME", ".local/share").map(|dir| dir.join(IDENTIFIER))
+}
+
+/// The per-user directory Tauri resolves app directories in: roaming
+/// AppData on Windows, Application Support on macOS, and elsewhere the XDG
+/// directory `xdg_var` names, or `fallback` in the home directory
+fn platform_dir(xdg_var: &str, fallback: &str) -> Option<PathBuf> {
+    if cfg!(windows) {
+        return std::env::var_os("APPDATA").map(PathBuf::from);
+    }
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    if cfg!(target_os = "macos") {
+        return home.map(|home| home.join("Library/Application Support"));
+    }
+    std::env::var_os(xdg_var)
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| home.map(|home| home.join(fallback)))
+}
+
+fn print_json<T: Serialize + ?Sized>(value: &T) -> AppResult<()> {
+    let json = serde_json::to_string_pretty(value).map_err(|e| AppError::Other(e.to_string()))?;
+    println!("{}", json);
+    Ok(())
+}
+
+fn print_documents(json: bool, documents: &[Document]) -> AppResult<()> {
+    if json {
+        return print_json(documents);
+    }
+    for document in documents {
+        let mut title: String = document.title.chars().take(MAX_TABLE_TITLE_CHARS).collect();
+        if document.title.chars().count() > MAX_TABLE_TITLE_CHARS {
+            title.push('…');
+        }
+        println!(
+            "{}  {:<12}  {:<5}  {}",
+            document.id,
+            status_name(document.status),
+            document.file_type.as_deref().unwrap_or("-"),
+            title
+        );
+    }
+    Ok(())
+}
+
+fn print_import(outcomes: &[ImportOutcome]) {
+    let mut queued = 0;
+    for outcome in outcomes {
+        match (outcome.document_id, outcome.duplicate_of, &outcome.error) {
+            (Some(id), _, _) => {
+                queued += 1;
+                println!("{}  queued     {}", id, outcome.path);
+            }
+            (_, Some(id), _) => println!("{}  duplicate  {}", id, outcome.path),
+            (_, _, error) => println!(
+                "{:<36}  failed     {}: {}",
+                "-",
+                outcome.path,
+                error.as_deref().unwrap_or_default()
+            ),
+        }
+    }
+    if queued > 0 {
+        eprintln!("Queued {} documents; the app processes them the next time it starts.", queued);
+    }
+}
+
+/// A status as the search box's `status:` names it
+fn status_name(status: DocumentStatus) -> &'static str {
+    match status {
+        DocumentStatus::Queued => "queued",
+        DocumentStatus::Uploading => "uploading",
+        DocumentStatus::Processing => "processing",
+        DocumentStatus::Completed => "completed",
+        DocumentStatus::Failed => "failed",
+        DocumentStatus::MissingFile => "missing_file",
+    }
+}
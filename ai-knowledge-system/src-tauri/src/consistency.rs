@@ -0,0 +1,266 @@
+use crate::error::{AppError, AppResult};
+use crate::models::{
+    ConsistencyReport, DanglingDocument, DocumentStatus, OrphanFile, RepairOptions, RepairReport,
+};
+use crate::services::{ActivityLogger, DocumentService};
+use crate::storage;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+/// Delay before the startup scan so it doesn't compete with app launch
+pub const STARTUP_SCAN_DELAY: Duration = Duration::from_secs(60);
+
+/// Find files and rows that disagree with each other
+///
+/// Only regular files are inspected; symlinks are never followed, so the
+/// scan cannot wander outside the storage and thumbnail roots. When the
+/// storage root itself is missing, e.g. on an unmounted drive, every file
+/// would look missing, so only thumbnails and external files are checked
+/// and the report is marked `storage_offline`.
+pub async fn scan_inconsistencies(
+    service: &DocumentService,
+    storage_root: &Path,
+    thumbnails_dir: &Path,
+) -> AppResult<ConsistencyReport> {
+    let references = service.list_file_references().await?;
+    let live_ids: HashSet<Uuid> = service.list_live_document_ids().await?.into_iter().collect();
+
+    let storage_root = storage_root.to_path_buf();
+    let thumbnails_dir = thumbnails_dir.to_path_buf();
+
+    let report = tokio::task::spawn_blocking(move || {
+        let storage_offline = !storage_root.is_dir();
+        // External files live outside the root and are checked either way
+        let references: Vec<_> = if storage_offline {
+            references.into_iter().filter(|r| r.external).collect()
+        } else {
+            references
+        };
+        let referenced: HashSet<PathBuf> = references
+            .iter()
+            .map(|r| PathBuf::from(&r.file_path))
+            .collect();
+
+        let orphan_files = list_regular_files(&storage_root)
+            .into_iter()
+            .filter(|(path, _)| !referenced.contains(path))
+            .map(|(path, metadata)| OrphanFile {
+                path: path.to_string_lossy().to_string(),
+                size_bytes: metadata.len(),
+                modified_at: metadata.modified().ok().map(chrono::DateTime::from),
+            })
+            .collect();
+
+        let dangling_documents = references
+            .into_iter()
+            .filter(|r| r.status != DocumentStatus::MissingFile)
+            .filter(|r| std::fs::symlink_metadata(&r.file_path).is_err())
+            .map(|r| DanglingDocument {
+                document_id: r.id,
+                user_id: r.user_id,
+                file_path: r.file_path,
+                status: r.status,
+                external: r.external,
+            })
+            .collect();
+
+        let stale_thumbnails = list_regular_files(&thumbnails_dir)
+            .into_iter()
+            .filter(|(path, _)| {
+                let id = path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .and_then(|s| Uuid::parse_str(s).ok());
+                !matches!(id, Some(id) if live_ids.contains(&id))
+            })
+            .map(|(path, _)| path.to_string_lossy().to_string())
+            .collect();
+
+        ConsistencyReport {
+            orphan_files,
+            dangling_documents,
+            stale_thumbnails,
+            storage_offline,
+        }
+    })
+    .await?;
+
+    Ok(report)
+}
+
+/// Fix what a scan found, logging every change to the activity log
+///
+/// Refuses to run on a scan taken while the storage root was offline, and
+/// stops if the root disappears partway through, so an unplugged drive
+/// never gets its documents flagged or its files purged.
+pub async fn repair_inconsistencies(
+    service: &DocumentService,
+    activity: &ActivityLogger,
+    report: &ConsistencyReport,
+    options: &RepairOptions,
+    storage_root: &Path,
+) -> AppResult<RepairReport> {
+    if report.storage_offline {
+        return Err(AppError::StorageOffline(storage_root.display().to_string()));
+    }
+    let mut result = RepairReport::default();
+    let min_age = Duration::from_secs(options.orphan_min_age_hours * 3600);
+    let now = SystemTime::now();
+
+    if options.delete_orphans {
+        for orphan in &report.orphan_files {
+            // Recent files may belong to an upload that hasn't recorded its path yet
+            let old_enough = std::fs::symlink_metadata(&orphan.path)
+                .and_then(|m| m.modified())
+                .map(|modified| now.duration_since(modified).unwrap_or_default() >= min_age)
+                .unwrap_or(false);
+            if !old_enough {
+                result.orphans_skipped_recent += 1;
+                continue;
+            }
+            storage::ensure_online(storage_root)?;
+
+            match std::fs::remove_file(&orphan.path) {
+                Ok(()) => {
+                    result.orphans_deleted += 1;
+                    log_repair(activity, None, "consistency.orphan_deleted", None, &orphan.path).await;
+                }
+                Err(e) => result.errors.push(format!("{}: {}", orphan.path, e)),
+            }
+        }
+    }
+
+    if options.flag_missing_files {
+        for dangling in &report.dangling_documents {
+            // Re-check: the file may have come back since the scan
+            if Path::new(&dangling.file_path).exists() {
+                continue;
+            }
+            let error = if dangling.external {
+                // Not ours to lose: the user moved or deleted it, and relink_document can fix it
+                format!("External file moved or deleted: {}", dangling.file_path)
+            } else {
+                storage::ensure_online(storage_root)?;
+                format!("Stored file is missing: {}", dangling.file_path)
+            };
+            match service
+                .transition_status(dangling.document_id, dangling.status, DocumentStatus::MissingFile, Some(error))
+                .await
+            {
+                Ok(()) => {
+                    result.documents_flagged += 1;
+                    log_repair(
+                        activity,
+                        Some(dangling.user_id),
+                        "consistency.document_flagged_missing",
+                        Some(dangling.document_id),
+                        &dangling.file_path,
+                    )
+                    .await;
+                }
+                Err(e) => result.errors.push(format!("{}: {}", dangling.document_id, e)),
+            }
+        }
+    }
+
+    if options.remove_stale_thumbnails {
+        for thumbnail in &report.stale_thumbnails {
+            match std::fs::remove_file(thumbnail) {
+                Ok(()) => {
+                    result.thumbnails_removed += 1;
+                    log_repair(activity, None, "consistency.thumbnail_removed", None, thumbnail).await;
+                }
+                Err(e) => result.errors.push(format!("{}: {}", thumbnail, e)),
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Move MissingFile documents whose file is back, e.g. after a drive is
+/// reconnected, back to Completed; returns how many were restored
+///
+/// Stored files are only looked at while the storage root is online;
+/// external files are checked regardless.
+pub async fn restore_returned_files(
+    service: &DocumentService,
+    activity: &ActivityLogger,
+    storage_root: &Path,
+) -> AppResult<usize> {
+    let storage_online = storage_root.is_dir();
+    let returned: Vec<_> = service
+        .list_file_references()
+        .await?
+        .into_iter()
+        .filter(|r| r.status == DocumentStatus::MissingFile && (r.external || storage_online))
+        .filter(|r| Path::new(&r.file_path).is_file())
+        .collect();
+
+    let mut restored = 0;
+    for reference in returned {
+        match service
+            .transition_status(reference.id, DocumentStatus::MissingFile, DocumentStatus::Completed, None)
+            .await
+        {
+            Ok(()) => {
+                restored += 1;
+                log_repair(
+                    activity,
+                    Some(reference.user_id),
+                    "consistency.document_file_restored",
+                    Some(reference.id),
+                    &reference.file_path,
+                )
+                .await;
+            }
+            Err(e) => eprintln!("Failed to restore document {}: {}", reference.id, e),
+        }
+    }
+    Ok(restored)
+}
+
+async fn log_repair(
+    activity: &ActivityLogger,
+    user_id: Option<Uuid>,
+    action: &str,
+    document_id: Option<Uuid>,
+    path: &str,
+) {
+    let resource_type = if document_id.is_some() { "document" } else { "file" };
+    let metadata = serde_json::json!({ "path": path });
+    if let Err(e) = activity.log(user_id, action, resource_type, document_id, metadata).await {
+        eprintln!("Failed to record {} in activity log: {}", action, e);
+    }
+}
+
+/// Regular files under `root`, recursively, without following symlinks
+fn list_regular_files(root: &Path) -> Vec<(PathBuf, std::fs::Metadata)> {
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            // DirEntry::metadata does not traverse symlinks
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            let file_type = metadata.file_type();
+            if file_type.is_symlink() {
+                continue;
+            }
+            if file_type.is_dir() {
+                pending.push(entry.path());
+            } else if file_type.is_file() {
+                files.push((entry.path(), metadata));
+            }
+        }
+    }
+
+    files
+}
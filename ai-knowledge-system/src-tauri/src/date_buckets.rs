@@ -0,0 +1,102 @@
+//! Relative date groups for document lists: today, yesterday, earlier this
+//! week and earlier, in the caller's time zone
+//!
+//! Lists and header counts classify by the same bounds, so a document is
+//! always counted under the header it is listed under.
+
+use crate::models::DateBucket;
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveTime, Utc};
+
+/// Furthest a caller's time zone may be from UTC, in minutes
+pub const MAX_TZ_OFFSET_MINUTES: i32 = 14 * 60;
+
+/// Where each group starts
+#[derive(Debug, Clone, Copy)]
+pub struct BucketBounds {
+    pub today: DateTime<Utc>,
+    pub yesterday: DateTime<Utc>,
+    /// Monday's midnight, or `yesterday` early in the week so the groups
+    /// never overlap
+    pub week: DateTime<Utc>,
+}
+
+impl BucketBounds {
+    /// Bounds around `now` for a time zone `offset_minutes` east of UTC,
+    /// e.g. 120 for UTC+2; None beyond MAX_TZ_OFFSET_MINUTES
+    pub fn at(now: DateTime<Utc>, offset_minutes: i32) -> Option<Self> {
+        if !(-MAX_TZ_OFFSET_MINUTES..=MAX_TZ_OFFSET_MINUTES).contains(&offset_minutes) {
+            return None;
+        }
+        let offset = FixedOffset::east_opt(offset_minutes * 60)?;
+        let local_today = now.with_timezone(&offset).date_naive();
+        // A fixed offset has no DST, so local midnight is offset minutes away
+        // from UTC midnight and whole days can be subtracted
+        let today = local_today.and_time(NaiveTime::MIN).and_utc() - Duration::minutes(offset_minutes as i64);
+        let yesterday = today - Duration::days(1);
+        let days_into_week = local_today.weekday().num_days_from_monday() as i64;
+        let week = (today - Duration::days(days_into_week)).min(yesterday);
+        Some(BucketBounds { today, yesterday, week })
+    }
+
+    /// Group of a document created at `created_at`; later than now counts as today
+    pub fn bucket_of(&self, created_at: DateTime<Utc>) -> DateBucket {
+        if created_at >= self.today {
+            DateBucket::Today
+        } else if created_at >= self.yesterday {
+            DateBucket::Yesterday
+        } else if created_at >= self.week {
+            DateBucket::ThisWeek
+        } else {
+            DateBucket::Earlier
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn days_turn_over_at_local_midnight() {
+        // Wednesday 00:30 in UTC+2 is still Tuesday in UTC
+        let bounds = BucketBounds::at(utc("2024-06-12T00:30:00+02:00"), 120).unwrap();
+        assert_eq!(bounds.today, utc("2024-06-12T00:00:00+02:00"));
+        assert_eq!(bounds.bucket_of(utc("2024-06-12T00:00:00+02:00")), DateBucket::Today);
+        assert_eq!(bounds.bucket_of(utc("2024-06-11T23:59:59+02:00")), DateBucket::Yesterday);
+        assert_eq!(bounds.bucket_of(utc("2024-06-11T00:00:00+02:00")), DateBucket::Yesterday);
+        assert_eq!(bounds.bucket_of(utc("2024-06-10T23:59:59+02:00")), DateBucket::ThisWeek);
+        assert_eq!(bounds.bucket_of(utc("2024-06-13T09:00:00+02:00")), DateBucket::Today);
+    }
+
+    #[test]
+    fn the_week_starts_on_monday() {
+        let bounds = BucketBounds::at(utc("2024-06-15T18:00:00-05:00"), -300).unwrap();
+        assert_eq!(bounds.week, utc("2024-06-10T00:00:00-05:00"));
+        assert_eq!(bounds.bucket_of(utc("2024-06-10T00:00:00-05:00")), DateBucket::ThisWeek);
+        assert_eq!(bounds.bucket_of(utc("2024-06-09T23:59:59-05:00")), DateBucket::Earlier);
+    }
+
+    #[test]
+    fn early_in_the_week_the_groups_do_not_overlap() {
+        // On Monday, Sunday is yesterday and nothing is earlier this week
+        let monday = BucketBounds::at(utc("2024-06-10T08:00:00Z"), 0).unwrap();
+        assert_eq!(monday.week, monday.yesterday);
+        assert_eq!(monday.bucket_of(utc("2024-06-09T12:00:00Z")), DateBucket::Yesterday);
+        assert_eq!(monday.bucket_of(utc("2024-06-08T23:59:59Z")), DateBucket::Earlier);
+
+        let tuesday = BucketBounds::at(utc("2024-06-11T08:00:00Z"), 0).unwrap();
+        assert_eq!(tuesday.week, tuesday.yesterday);
+        assert_eq!(tuesday.bucket_of(utc("2024-06-09T23:59:59Z")), DateBucket::Earlier);
+    }
+
+    #[test]
+    fn offsets_beyond_fourteen_hours_are_refused() {
+        let now = utc("2024-06-12T12:00:00Z");
+        assert!(BucketBounds::at(now, MAX_TZ_OFFSET_MINUTES).is_some());
+        assert!(BucketBounds::at(now, -MAX_TZ_OFFSET_MINUTES - 1).is_none());
+    }
+}
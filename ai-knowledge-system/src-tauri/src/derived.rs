@@ -0,0 +1,114 @@
+//! Versions of data derived from documents
+//!
+//! Whatever writes derived data stamps it with the version of the code that
+//! made it, in documents.derived_versions, e.g. `{"chunks": 1}`; data from
+//! before stamping counts as version 0. When an upgrade changes how
+//! something is derived its version constant goes up, and a rebuild redoes
+//! only documents stamped with an older version. Each document is stamped
+//! as it is rebuilt, so an interrupted rebuild resumes where it stopped.
+
+use crate::chunking::{self, TextChunk};
+use crate::collation;
+use crate::error::AppResult;
+use crate::keywords;
+use crate::models::{DerivedTarget, Document, DocumentSummary, ProcessingOptions};
+use crate::services::DocumentService;
+use crate::settings::AppSettings;
+use crate::summarizer::{self, FileKind};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// Version the current code derives `target` with
+pub fn current_version(target: DerivedTarget) -> i32 {
+    match target {
+        DerivedTarget::Summaries => summarizer::SUMMARIZER_VERSION,
+        DerivedTarget::Chunks => chunking::CHUNKER_VERSION,
+        DerivedTarget::TextStats => keywords::TERM_COUNT_VERSION,
+        DerivedTarget::SortKeys => collation::SORT_KEY_VERSION,
+    }
+}
+
+/// `{target: current version}`, for merging into derived_versions with `||`
+pub fn stamp(target: DerivedTarget) -> serde_json::Value {
+    serde_json::json!({ target.as_str(): current_version(target) })
+}
+
+/// Data derived again, ready to store
+enum Derived {
+    /// None when the document is processed without a summary
+    Summary(Option<DocumentSummary>),
+    Chunks(Vec<TextChunk>),
+    Terms(Vec<(String, i32)>),
+    SortKey(String),
+}
+
+/// Derive `target` for a document with the current code and store it
+///
+/// Follows the processing options the document was processed with, so a
+/// document processed without a summary or chunks stays without. Documents
+/// deleted in the meantime are skipped.
+pub async fn rebuild_document(
+    service: &Mutex<DocumentService>,
+    settings: &AppSettings,
+    target: DerivedTarget,
+    doc_id: Uuid,
+) -> AppResult<()> {
+    let (document, pages, options) = {
+        let service = service.lock().await;
+        let Some(document) = service.get_document(doc_id).await? else {
+            return Ok(());
+        };
+        let pages: Vec<String> = match target {
+            DerivedTarget::Summaries | DerivedTarget::Chunks => {
+                service.get_pages(doc_id).await?.into_iter().map(|p| p.content).collect()
+            }
+            _ => Vec::new(),
+        };
+        let options = service
+            .get_processing_options(doc_id)
+            .await?
+            .unwrap_or_else(|| settings.processing_defaults.clone());
+        (document, pages, options)
+    };
+
+    let locale = settings.locale.clone();
+    let derived = tokio::task::spawn_blocking(move || derive(target, &document, &pages, &options, &locale)).await?;
+
+    let service = service.lock().await;
+    match derived {
+        Derived::Summary(Some(summary)) => service.set_summary(doc_id, &summary).await?,
+        Derived::Summary(None) => service.mark_derived_current(doc_id, target).await?,
+        Derived::Chunks(chunks) => service.replace_chunks(doc_id, &chunks).await?,
+        Derived::Terms(terms) => service.replace_terms(doc_id, &terms).await?,
+        Derived::SortKey(key) => {
+            service.set_title_sort_keys(&[doc_id], &[key]).await?;
+        }
+    }
+    Ok(())
+}
+
+fn derive(
+    target: DerivedTarget,
+    document: &Document,
+    pages: &[String],
+    options: &ProcessingOptions,
+    locale: &str,
+) -> Derived {
+    let content = document.content.as_deref().unwrap_or_default();
+    match target {
+        DerivedTarget::Summaries => Derived::Summary(
+            // Documents no extractor could read were stored without one
+            (options.generate_summary && document.content.is_some())
+                .then(|| summarizer::summarize(FileKind::of_document(document), content, pages)),
+        ),
+        DerivedTarget::Chunks if options.chunk && pages.is_empty() => {
+            Derived::Chunks(chunking::chunk_text(content, options.language_hint.as_deref()))
+        }
+        DerivedTarget::Chunks if options.chunk => {
+            Derived::Chunks(chunking::chunk_pages(content, pages, options.language_hint.as_deref()))
+        }
+        DerivedTarget::Chunks => Derived::Chunks(Vec::new()),
+        DerivedTarget::TextStats => Derived::Terms(keywords::term_counts(content)),
+        DerivedTarget::SortKeys => Derived::SortKey(collation::title_sort_key(&document.title, locale)),
+    }
+}
@@ -0,0 +1,281 @@
+//! Digest of recent library activity: what was added, what failed and what
+//! is still unopened, as a report and as Markdown
+
+use crate::display::{self, Formatter};
+use crate::error::AppResult;
+use crate::models::{Digest, DigestReport};
+use crate::services::DocumentService;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// Period a digest covers when no start is given
+pub const DIGEST_PERIOD_DAYS: i64 = 7;
+
+/// Items listed per section
+const ITEMS_PER_SECTION: usize = 5;
+
+/// Longest summary excerpt shown under a new document, in chars
+const SUMMARY_EXCERPT_CHARS: usize = 200;
+
+/// Notification kind digests are delivered as
+pub const DIGEST_NOTIFICATION_KIND: &str = "weekly_digest";
+
+/// Collect a user's activity since `since` and render it with `formatter`
+pub async fn generate_digest(
+    service: &DocumentService,
+    user_id: Uuid,
+    since: DateTime<Utc>,
+    formatter: &Formatter,
+) -> AppResult<Digest> {
+    let until = Utc::now();
+    let limit = ITEMS_PER_SECTION as i64;
+    let (new_count, new_bytes, failed_count) = service.activity_counts(user_id, since).await?;
+    let new_documents = service.documents_created_since(user_id, since, limit).await?;
+    let failures = service.documents_failed_since(user_id, since, limit).await?;
+    let largest_uploads = service.largest_documents(user_id, limit, Some(since)).await?;
+    let mut unopened = service.never_opened_documents(user_id, until).await?;
+    let unopened_count = unopened.len() as i64;
+    unopened.truncate(ITEMS_PER_SECTION);
+
+    let report = DigestReport {
+        since,
+        until,
+        new_count,
+        new_bytes,
+        failed_count,
+        unopened_count,
+        new_documents,
+        failures,
+        largest_uploads,
+        unopened,
+    };
+    let markdown = render_markdown(&report, formatter);
+    Ok(Digest { report, markdown })
+}
+
+/// Render a digest report as Markdown
+///
+/// Sections without items are left out; a period with nothing to report
+/// says so instead. Sizes and ages are formatted by `formatter`.
+pub fn render_markdown(report: &DigestReport, formatter: &Formatter) -> String {
+    let mut out = format!(
+        "# Digest\n\n{} to {}\n",
+        report.since.format("%Y-%m-%d"),
+        report.until.format("%Y-%m-%d")
+    );
+
+    if report.new_count == 0 && report.failed_count == 0 && report.unopened_count == 0 {
+        out.push_str("\nNo activity: nothing was added or failed, and every document has been opened.\n");
+        return out;
+    }
+
+    if report.new_count > 0 {
+        out.push_str(&format!(
+            "\n## New documents\n\n{} added, {} in total.\n\n",
+            report.new_count,
+            formatter.size(report.new_bytes)
+        ));
+        for doc in &report.new_documents {
+            out.push_str(&format!("- **{}**", escape_inline(&doc.title)));
+            if let Some(summary) = doc.summary.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+                out.push_str(&format!(": {}", escape_inline(&excerpt(summary))));
+            }
+            out.push('\n');
+        }
+        push_more(&mut out, report.new_count, report.new_documents.len());
+    }
+
+    if report.failed_count > 0 {
+        out.push_str(&format!("\n## Failed\n\n{} failed to process.\n\n", report.failed_count));
+        for failure in &report.failures {
+            let error = failure.error.as_deref().unwrap_or("unknown error");
+            out.push_str(&format!(
+                "- **{}**: {}\n",
+                escape_inline(&failure.title),
+                escape_inline(error)
+            ));
+        }
+        push_more(&mut out, report.failed_count, report.failures.len());
+    }
+
+    if !report.largest_uploads.is_empty() {
+        out.push_str("\n## Largest uploads\n\n");
+        for doc in &report.largest_uploads {
+            out.push_str(&format!(
+                "- {} ({})\n",
+                escape_inline(&doc.title),
+                formatter.size(doc.file_size_bytes)
+            ));
+        }
+    }
+
+    if report.unopened_count > 0 {
+        out.push_str(&format!("\n## Not opened yet\n\n{} never opened.\n\n", report.unopened_count));
+        for doc in &report.unopened {
+            out.push_str(&format!(
+                "- {} ({})\n",
+                escape_inline(&doc.title),
+                formatter.relative_time(doc.created_at)
+            ));
+        }
+        push_more(&mut out, report.unopened_count, report.unopened.len());
+    }
+
+    out
+}
+
+fn push_more(out: &mut String, total: i64, shown: usize) {
+    let more = total - shown as i64;
+    if more > 0 {
+        out.push_str(&format!("- and {} more\n", more));
+    }
+}
+
+/// Text for use inside a line of Markdown: whitespace runs, including line
+/// breaks, become one space and Markdown syntax characters are escaped
+fn escape_inline(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for (index, word) in text.split_whitespace().enumerate() {
+        if index > 0 {
+            escaped.push(' ');
+        }
+        for c in word.chars() {
+            if matches!(
+                c,
+                '\\' | '`' | '*' | '_' | '{' | '}' | '[' | ']' | '<' | '>' | '(' | ')' | '#' | '+' | '-' | '.'
+                    | '!' | '|' | '~'
+            ) {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+/// The start of a summary, cut at a word boundary
+fn excerpt(text: &str) -> String {
+    match display::preview(text, SUMMARY_EXCERPT_CHARS) {
+        (start, true) => format!("{}…", start),
+        (whole, false) => whole.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CleanupCandidate, DigestDocument, DigestFailure};
+    use chrono::TimeZone;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 11, 9, 0, 0).unwrap()
+    }
+
+    fn empty_report() -> DigestReport {
+        DigestReport {
+            since: now() - chrono::Duration::days(DIGEST_PERIOD_DAYS),
+            until: now(),
+            new_count: 0,
+            new_bytes: 0,
+            failed_count: 0,
+            unopened_count: 0,
+            new_documents: Vec::new(),
+            failures: Vec::new(),
+            largest_uploads: Vec::new(),
+            unopened: Vec::new(),
+        }
+    }
+
+    fn new_document(title: &str, summary: Option<&str>) -> DigestDocument {
+        DigestDocument {
+            document_id: Uuid::new_v4(),
+            title: title.to_string(),
+            summary: summary.map(str::to_string),
+            created_at: now(),
+        }
+    }
+
+    fn candidate(title: &str, file_size_bytes: i64, days_old: i64) -> CleanupCandidate {
+        CleanupCandidate {
+            document_id: Uuid::new_v4(),
+            title: title.to_string(),
+            file_size_bytes,
+            created_at: now() - chrono::Duration::days(days_old),
+        }
+    }
+
+    fn render(report: &DigestReport) -> String {
+        render_markdown(report, &Formatter::new("en", now()))
+    }
+
+    #[test]
+    fn an_empty_period_says_there_was_no_activity() {
+        assert_eq!(
+            render(&empty_report()),
+            "# Digest\n\n2024-03-04 to 2024-03-11\n\n\
+             No activity: nothing was added or failed, and every document has been opened.\n"
+        );
+    }
+
+    #[test]
+    fn markdown_and_html_in_titles_are_escaped() {
+        let mut report = empty_report();
+        report.new_count = 1;
+        report.new_documents = vec![new_document("*Q1* [draft](x) <script>#1_v2.pdf", None)];
+
+        let markdown = render(&report);
+        assert!(
+            markdown.contains(r"- **\*Q1\* \[draft\]\(x\) \<script\>\#1\_v2\.pdf**"),
+            "{}",
+            markdown
+        );
+        assert!(!markdown.contains("<script>"));
+    }
+
+    #[test]
+    fn line_breaks_in_titles_and_errors_cannot_start_new_blocks() {
+        let mut report = empty_report();
+        report.failed_count = 1;
+        report.failures = vec![DigestFailure {
+            document_id: Uuid::new_v4(),
+            title: "Notes\n# Not a heading".to_string(),
+            error: Some("line one\n\n- not a list item".to_string()),
+            failed_at: now(),
+        }];
+
+        let markdown = render(&report);
+        assert!(
+            markdown.contains("- **Notes \\# Not a heading**: line one \\- not a list item\n"),
+            "{}",
+            markdown
+        );
+        assert!(!markdown.lines().any(|line| line.starts_with("# Not")));
+    }
+
+    #[test]
+    fn sections_list_a_few_items_and_count_the_rest() {
+        let mut report = empty_report();
+        report.new_count = 7;
+        report.new_bytes = 3 * 1024 * 1024;
+        report.new_documents = vec![
+            new_document("Plan", Some("  First steps.  ")),
+            new_document("Blank", Some("   ")),
+            new_document("Long", Some(&"word ".repeat(100))),
+        ];
+        report.largest_uploads = vec![candidate("Scan", 2 * 1024 * 1024, 1)];
+        report.unopened_count = 1;
+        report.unopened = vec![candidate("Paper", 1024, 3)];
+
+        let markdown = render(&report);
+        assert!(markdown.contains("## New documents\n\n7 added, 3.0 MB in total.\n\n"), "{}", markdown);
+        assert!(markdown.contains("- **Plan**: First steps\\.\n"));
+        assert!(markdown.contains("- **Blank**\n"));
+        assert!(markdown.contains("- and 4 more\n"));
+        let long = markdown.lines().find(|line| line.starts_with("- **Long**")).unwrap();
+        assert!(long.ends_with('…') && long.chars().count() < SUMMARY_EXCERPT_CHARS + 20, "{}", long);
+        assert!(markdown.contains("## Largest uploads\n\n- Scan (2.0 MB)\n"));
+        assert!(markdown.contains("## Not opened yet\n\n1 never opened.\n\n- Paper (3 days ago)\n"));
+        assert!(!markdown.contains("## Failed"));
+        assert!(!markdown.contains("No activity"));
+    }
+}
@@ -0,0 +1,305 @@
+//! Text and values formatted for display, shared by document lists,
+//! digests, exports and notifications
+
+use crate::models::FormatRequest;
+use chrono::{DateTime, Utc};
+
+/// The start of `text`, at most `max_chars` chars, cut at a word boundary
+/// where there is one, and whether anything was cut
+pub fn preview(text: &str, max_chars: usize) -> (&str, bool) {
+    match text.char_indices().nth(max_chars) {
+        None => (text, false),
+        Some((end, _)) => {
+            let cut = text[..end].rfind(char::is_whitespace).unwrap_or(end);
+            (text[..cut].trim_end(), true)
+        }
+    }
+}
+
+/// Words read per minute in reading time estimates
+const WORDS_PER_MINUTE: usize = 200;
+
+/// Minutes it takes to read `word_count` words, rounded up; 0 for no words
+pub fn reading_time_minutes(word_count: usize) -> i64 {
+    word_count.div_ceil(WORDS_PER_MINUTE) as i64
+}
+
+/// Conventions a language formats values with
+struct Phrases {
+    decimal_separator: char,
+    /// Bytes, kilobytes, megabytes, gigabytes
+    size_units: [&'static str; 4],
+    /// Days, hours, minutes, seconds, abbreviated so they need no plural
+    duration_units: [&'static str; 4],
+    /// Singular and plural of minutes, hours, days, months and years, as
+    /// they read in `ago` and `ahead`
+    relative_units: [(&'static str, &'static str); 5],
+    /// Templates with {} for the amount and unit
+    ago: &'static str,
+    ahead: &'static str,
+    just_now: &'static str,
+}
+
+const ENGLISH: Phrases = Phrases {
+    decimal_separator: '.',
+    size_units: ["B", "KB", "MB", "GB"],
+    duration_units: ["d", "h", "min", "s"],
+    relative_units: [
+        ("minute", "minutes"),
+        ("hour", "hours"),
+        ("day", "days"),
+        ("month", "months"),
+        ("year", "years"),
+    ],
+    ago: "{} ago",
+    ahead: "in {}",
+    just_now: "just now",
+};
+
+const GERMAN: Phrases = Phrases {
+    decimal_separator: ',',
+    size_units: ["B", "KB", "MB", "GB"],
+    duration_units: ["T.", "Std.", "Min.", "Sek."],
+    relative_units: [
+        ("Minute", "Minuten"),
+        ("Stunde", "Stunden"),
+        ("Tag", "Tagen"),
+        ("Monat", "Monaten"),
+        ("Jahr", "Jahren"),
+    ],
+    ago: "vor {}",
+    ahead: "in {}",
+    just_now: "gerade eben",
+};
+
+const FRENCH: Phrases = Phrases {
+    decimal_separator: ',',
+    size_units: ["o", "Ko", "Mo", "Go"],
+    duration_units: ["j", "h", "min", "s"],
+    relative_units: [
+        ("minute", "minutes"),
+        ("heure", "heures"),
+        ("jour", "jours"),
+        ("mois", "mois"),
+        ("an", "ans"),
+    ],
+    ago: "il y a {}",
+    ahead: "dans {}",
+    just_now: "à l'instant",
+};
+
+/// Seconds in a day, hour and minute, and 1
+const DURATION_STEPS: [i64; 4] = [86_400, 3_600, 60, 1];
+
+/// Seconds in a minute, hour, day, month and year, as rounded for
+/// relative times
+const RELATIVE_STEPS: [i64; 5] = [60, 3_600, 86_400, 30 * 86_400, 365 * 86_400];
+
+/// Formats sizes, durations and relative times the way the locale setting
+/// reads them
+///
+/// The one place backend text gets such values from, for exports, digests
+/// and notifications; lists and details return them raw, bytes, seconds
+/// and RFC 3339 timestamps, for the frontend to format. Locales are
+/// matched by language; languages without phrases of their own read as
+/// English.
+pub struct Formatter {
+    phrases: &'static Phrases,
+    /// What relative times are relative to
+    now: DateTime<Utc>,
+}
+
+impl Formatter {
+    /// A formatter for `locale`, a BCP 47 tag such as "de" or "fr-CA"
+    pub fn new(locale: &str, now: DateTime<Utc>) -> Self {
+        let language = locale.split(['-', '_']).next().unwrap_or_default().to_lowercase();
+        let phrases = match language.as_str() {
+            "de" => &GERMAN,
+            "fr" => &FRENCH,
+            _ => &ENGLISH,
+        };
+        Formatter { phrases, now }
+    }
+
+    pub fn format(&self, request: &FormatRequest) -> String {
+        match *request {
+            FormatRequest::Bytes { value } => self.size(value),
+            FormatRequest::Duration { seconds } => self.duration(seconds),
+            FormatRequest::RelativeTime { timestamp } => self.relative_time(timestamp),
+        }
+    }
+
+    /// Size in B, KB, MB or GB, with one decimal above bytes
+    pub fn size(&self, bytes: i64) -> String {
+        let units = &self.phrases.size_units;
+        let mut size = bytes.max(0) as f64;
+        let mut unit = 0;
+        while size >= 1024.0 && unit < units.len() - 1 {
+            size /= 1024.0;
+            unit += 1;
+        }
+        if unit == 0 {
+            format!("{} {}", bytes.max(0), units[0])
+        } else {
+            format!("{} {}", self.decimal(size), units[unit])
+        }
+    }
+
+    /// The two largest units of a duration, such as "1 h 5 min"; negative
+    /// durations read as 0 s
+    pub fn duration(&self, seconds: i64) -> String {
+        let units = &self.phrases.duration_units;
+        let mut rest = seconds.max(0);
+        let mut parts = Vec::new();
+        for (step, unit) in DURATION_STEPS.iter().zip(units) {
+            let amount = rest / step;
+            rest %= step;
+            if amount > 0 || !parts.is_empty() {
+                parts.push(format!("{} {}", amount, unit));
+            }
+            if parts.len() == 2 {
+                break;
+            }
+        }
+        // A trailing zero, as in "1 h 0 min", says nothing
+        if parts.len() == 2 && parts[1].starts_with("0 ") {
+            parts.pop();
+        }
+        if parts.is_empty() {
+            parts.push(format!("0 {}", units[3]));
+        }
+        parts.join(" ")
+    }
+
+    /// How long ago `at` was, or how long until it, in its largest whole
+    /// unit, such as "3 days ago"; under a minute either way is "just now"
+    pub fn relative_time(&self, at: DateTime<Utc>) -> String {
+        let seconds = (self.now - at).num_seconds();
+        let distance = seconds.abs();
+        let Some(index) = RELATIVE_STEPS.iter().rposition(|&step| distance >= step) else {
+            return self.phrases.just_now.to_string();
+        };
+        let amount = distance / RELATIVE_STEPS[index];
+        let (singular, plural) = self.phrases.relative_units[index];
+        let phrase = format!("{} {}", amount, if amount == 1 { singular } else { plural });
+        let template = if seconds >= 0 { self.phrases.ago } else { self.phrases.ahead };
+        template.replace("{}", &phrase)
+    }
+
+    fn decimal(&self, value: f64) -> String {
+        format!("{:.1}", value).replace('.', &self.phrases.decimal_separator.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 3, 14, 9, 30, 0).unwrap()
+    }
+
+    /// Every kind of value, one per line, as `locale` reads them
+    fn snapshot(locale: &str) -> String {
+        let formatter = Formatter::new(locale, now());
+        let mut lines = Vec::new();
+        for seconds in [-30, 59, 60, 7_200, 86_400, 3 * 86_400, 45 * 86_400, 800 * 86_400] {
+            lines.push(formatter.relative_time(now() - Duration::seconds(seconds)));
+        }
+        lines.push(formatter.relative_time(now() + Duration::hours(1)));
+        for bytes in [-1, 0, 1023, 1024, 1536, 5 * 1024 * 1024 + 300_000, 3 * 1024 * 1024 * 1024] {
+            lines.push(formatter.size(bytes));
+        }
+        for words in [0, 1, 200, 201, 12_345] {
+            lines.push(formatter.duration(reading_time_minutes(words) * 60));
+        }
+        for seconds in [-5, 0, 45, 3_600, 3_900, 90_061] {
+            lines.push(formatter.duration(seconds));
+        }
+        lines.join("\n")
+    }
+
+    #[test]
+    fn english_snapshot() {
+        let expected = "\
+just now
+just now
+1 minute ago
+2 hours ago
+1 day ago
+3 days ago
+1 month ago
+2 years ago
+in 1 hour
+0 B
+0 B
+1023 B
+1.0 KB
+1.5 KB
+5.3 MB
+3.0 GB
+0 s
+1 min
+1 min
+2 min
+1 h 2 min
+0 s
+0 s
+45 s
+1 h
+1 h 5 min
+1 d 1 h";
+        assert_eq!(snapshot("en"), expected);
+    }
+
+    #[test]
+    fn german_snapshot() {
+        let expected = "\
+gerade eben
+gerade eben
+vor 1 Minute
+vor 2 Stunden
+vor 1 Tag
+vor 3 Tagen
+vor 1 Monat
+vor 2 Jahren
+in 1 Stunde
+0 B
+0 B
+1023 B
+1,0 KB
+1,5 KB
+5,3 MB
+3,0 GB
+0 Sek.
+1 Min.
+1 Min.
+2 Min.
+1 Std. 2 Min.
+0 Sek.
+0 Sek.
+45 Sek.
+1 Std.
+1 Std. 5 Min.
+1 T. 1 Std.";
+        assert_eq!(snapshot("de"), expected);
+    }
+
+    #[test]
+    fn locales_are_matched_by_language() {
+        assert_eq!(snapshot("de-AT"), snapshot("de"));
+        assert_eq!(snapshot("DE_ch"), snapshot("de"));
+        assert_eq!(snapshot("en-GB"), snapshot("en"));
+        assert_eq!(snapshot("pt-BR"), snapshot("en"));
+        assert_eq!(snapshot(""), snapshot("en"));
+    }
+
+    #[test]
+    fn previews_cut_at_a_word_boundary() {
+        assert_eq!(preview("short", 10), ("short", false));
+        assert_eq!(preview("a long sentence here", 9), ("a long", true));
+        assert_eq!(preview("unbroken", 4), ("unbr", true));
+        assert_eq!(preview("Grüße aus Köln", 8), ("Grüße", true));
+    }
+}
@@ -0,0 +1,172 @@
+use crate::models::DocumentStatus;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+
+/// Errors returned from Tauri commands
+///
+/// Serialized as `{ "kind": "...", "message": "..." }` so the frontend can
+/// branch on `kind` instead of parsing message strings.
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    #[error("No active user; switch to a user first")]
+    NoActiveUser,
+
+    #[error("{0} not found")]
+    NotFound(String),
+
+    #[error("{0} is outside the app's storage")]
+    PathOutsideScope(String),
+
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+
+    #[error("Invalid query at position {position}: {message}")]
+    InvalidQuery { position: usize, message: String },
+
+    #[error("Storage quota exceeded: {needed} bytes needed, {available} available")]
+    QuotaExceeded { needed: i64, available: i64 },
+
+    #[error("Not enough disk space: {needed} bytes needed, {available} available")]
+    InsufficientDiskSpace { needed: u64, available: u64 },
+
+    #[error("{0} is in use by another program; close it and try again")]
+    FileInUse(String),
+
+    #[error("{0} is empty")]
+    EmptyFile(String),
+
+    #[error("{file} was rejected by the virus scanner: {output}")]
+    ScanFailed { file: String, output: String },
+
+    #[error("{0}")]
+    ScannerUnavailable(String),
+
+    #[error("{0}")]
+    Busy(String),
+
+    #[error("Storage location {0} is unavailable; reconnect the drive holding it and try again")]
+    StorageOffline(String),
+
+    #[error("The library is locked; unlock it with the passphrase first")]
+    LibraryLocked,
+
+    #[error("Incorrect passphrase")]
+    WrongPassphrase,
+
+    #[error("Only an administrator can do this")]
+    Forbidden,
+
+    #[error("The database is read-only; changes can't be saved until write access is restored")]
+    ReadOnlyMode,
+
+    #[error("Cancelled")]
+    Cancelled,
+
+    #[error("Document is {status:?}; wait until it has been processed")]
+    DocumentNotReady { status: DocumentStatus },
+
+    #[error("Cannot move document from {from:?} to {to:?}")]
+    InvalidTransition { from: DocumentStatus, to: DocumentStatus },
+
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("{0}")]
+    Other(String),
+}
+
+impl AppError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AppError::NoActiveUser => "NoActiveUser",
+            AppError::NotFound(_) => "NotFound",
+            AppError::PathOutsideScope(_) => "PathOutsideScope",
+            AppError::InvalidInput(_) => "InvalidInput",
+            AppError::InvalidQuery { .. } => "InvalidQuery",
+            AppError::QuotaExceeded { .. } => "QuotaExceeded",
+            AppError::InsufficientDiskSpace { .. } => "InsufficientDiskSpace",
+            AppError::FileInUse(_) => "FileInUse",
+            AppError::EmptyFile(_) => "EmptyFile",
+            AppError::ScanFailed { .. } => "ScanFailed",
+            AppError::ScannerUnavailable(_) => "ScannerUnavailable",
+            AppError::Busy(_) => "Busy",
+            AppError::StorageOffline(_) => "StorageOffline",
+            AppError::LibraryLocked => "LibraryLocked",
+            AppError::WrongPassphrase => "WrongPassphrase",
+            AppError::Forbidden => "Forbidden",
+            AppError::ReadOnlyMode => "ReadOnlyMode",
+            AppError::Cancelled => "Cancelled",
+            AppError::DocumentNotReady { .. } => "DocumentNotReady",
+            AppError::InvalidTransition { .. } => "InvalidTransition",
+            AppError::Database(_) => "Database",
+            AppError::Io(_) => "Io",
+            AppError::Other(_) => "Other",
+        }
+    }
+}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AppError", 2)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+impl From<uuid::Error> for AppError {
+    fn from(e: uuid::Error) -> Self {
+        AppError::InvalidInput(e.to_string())
+    }
+}
+
+impl From<crate::query_parser::QueryParseError> for AppError {
+    fn from(e: crate::query_parser::QueryParseError) -> Self {
+        AppError::InvalidQuery {
+            position: e.position,
+            message: e.message,
+        }
+    }
+}
+
+impl From<tauri::Error> for AppError {
+    fn from(e: tauri::Error) -> Self {
+        AppError::Other(e.to_string())
+    }
+}
+
+impl From<tokio::task::JoinError> for AppError {
+    fn from(e: tokio::task::JoinError) -> Self {
+        AppError::Other(e.to_string())
+    }
+}
+
+impl From<zip::result::ZipError> for AppError {
+    fn from(e: zip::result::ZipError) -> Self {
+        AppError::Other(format!("Package error: {}", e))
+    }
+}
+
+impl From<csv::Error> for AppError {
+    fn from(e: csv::Error) -> Self {
+        if e.is_io_error() {
+            match e.into_kind() {
+                csv::ErrorKind::Io(io) => AppError::Io(io),
+                other => AppError::Other(format!("CSV error: {:?}", other)),
+            }
+        } else {
+            AppError::Other(format!("CSV error: {}", e))
+        }
+    }
+}
+
+impl From<String> for AppError {
+    fn from(message: String) -> Self {
+        AppError::Other(message)
+    }
+}
+
+pub type AppResult<T> = Result<T, AppError>;
@@ -0,0 +1,365 @@
+//! Quality checks for the summary and keyword pipelines
+//!
+//! Built with the `eval` feature, the pipelines run over the fixtures in
+//! `eval/fixtures.json` and their output is scored against golden
+//! summaries and keywords: ROUGE-1 (clipped unigram overlap) for summaries,
+//! precision and recall for keywords. A user's library has no golden
+//! output, so over it the pipelines are only described: how long summaries
+//! come out, how often they're empty, and how many keywords contain a
+//! stopword.
+//!
+//! `cargo test` scores the fixtures too and fails when a score drops
+//! below its threshold, so changes to the summarizer or keyword
+//! extraction can't quietly make them worse.
+
+use crate::keywords;
+use crate::models::EvalSample;
+use crate::stopwords;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Keywords extracted per document of a library sample
+const KEYWORDS_PER_DOCUMENT: usize = 10;
+
+/// Overlap of a pipeline's output with the expected output
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct OverlapScore {
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+}
+
+// Scoring only runs over the fixtures, which need the `eval` feature
+#[cfg_attr(not(feature = "eval"), allow(dead_code))]
+impl OverlapScore {
+    fn new(overlap: usize, produced: usize, expected: usize) -> Self {
+        let precision = ratio(overlap, produced);
+        let recall = ratio(overlap, expected);
+        let f1 = if precision + recall > 0.0 {
+            2.0 * precision * recall / (precision + recall)
+        } else {
+            0.0
+        };
+        OverlapScore { precision, recall, f1 }
+    }
+}
+
+/// ROUGE-1 of a summary against a reference: shared words, each counted at
+/// most as often as it occurs in the reference
+#[cfg_attr(not(feature = "eval"), allow(dead_code))]
+pub fn rouge1(summary: &str, reference: &str) -> OverlapScore {
+    let mut reference_counts: HashMap<String, usize> = HashMap::new();
+    let mut reference_len = 0;
+    for word in keywords::tokenize(reference) {
+        *reference_counts.entry(word).or_default() += 1;
+        reference_len += 1;
+    }
+
+    let mut summary_len = 0;
+    let mut overlap = 0;
+    for word in keywords::tokenize(summary) {
+        summary_len += 1;
+        if let Some(count) = reference_counts.get_mut(&word) {
+            if *count > 0 {
+                *count -= 1;
+                overlap += 1;
+            }
+        }
+    }
+    OverlapScore::new(overlap, summary_len, reference_len)
+}
+
+/// Keywords found among the expected ones, compared case-insensitively
+#[cfg_attr(not(feature = "eval"), allow(dead_code))]
+pub fn keyword_overlap(extracted: &[String], expected: &[String]) -> OverlapScore {
+    let extracted: HashSet<String> = extracted.iter().map(|k| k.trim().to_lowercase()).collect();
+    let expected: HashSet<String> = expected.iter().map(|k| k.trim().to_lowercase()).collect();
+    let overlap = extracted.intersection(&expected).count();
+    OverlapScore::new(overlap, extracted.len(), expected.len())
+}
+
+/// Min, median, 90th percentile, max and mean of some lengths
+#[derive(Debug, Clone, Serialize)]
+pub struct LengthDistribution {
+    pub min: usize,
+    pub median: usize,
+    pub p90: usize,
+    pub max: usize,
+    pub mean: f64,
+}
+
+impl LengthDistribution {
+    /// None without any lengths; percentiles are nearest-rank
+    fn of(mut lengths: Vec<usize>) -> Option<Self> {
+        if lengths.is_empty() {
+            return None;
+        }
+        lengths.sort_unstable();
+        let n = lengths.len();
+        Some(LengthDistribution {
+            min: lengths[0],
+            median: lengths[n.div_ceil(2) - 1],
+            p90: lengths[(n * 9).div_ceil(10) - 1],
+            max: lengths[n - 1],
+            mean: lengths.iter().sum::<usize>() as f64 / n as f64,
+        })
+    }
+}
+
+/// How the pipelines did on a sample of a user's completed documents
+#[derive(Debug, Clone, Serialize)]
+pub struct CorpusStats {
+    pub documents: usize,
+    /// Chars in the non-empty summaries; None when all are empty
+    pub summary_chars: Option<LengthDistribution>,
+    pub empty_summaries: usize,
+    pub empty_summary_rate: f64,
+    /// Documents by summary_source; "unknown" for summaries made before
+    /// sources were recorded
+    pub summary_sources: BTreeMap<String, usize>,
+    /// Keywords extracted from the documents' content
+    pub keywords: usize,
+    /// Keywords with a word that is a stopword in any language we have a
+    /// list for, e.g. from a document whose language was guessed wrong
+    pub stopword_keywords: usize,
+    pub stopword_contamination: f64,
+}
+
+/// Describe the summaries and keywords of sampled documents
+fn corpus_stats(samples: &[EvalSample]) -> CorpusStats {
+    let stop: HashSet<&str> = stopwords::LANGUAGES
+        .iter()
+        .filter_map(|code| stopwords::for_language(code))
+        .flatten()
+        .copied()
+        .collect();
+
+    let mut summary_lengths = Vec::with_capacity(samples.len());
+    let mut summary_sources: BTreeMap<String, usize> = BTreeMap::new();
+    let mut keyword_count = 0;
+    let mut stopword_keywords = 0;
+    for sample in samples {
+        match sample.summary.as_deref().map(str::trim) {
+            Some(summary) if !summary.is_empty() => summary_lengths.push(summary.chars().count()),
+            _ => {}
+        }
+        let source = sample.summary_source.as_deref().unwrap_or("unknown");
+        *summary_sources.entry(source.to_string()).or_default() += 1;
+
+        let language = keywords::detect_language(&sample.content);
+        for (keyword, _) in keywords::extract_keywords(&sample.content, language, KEYWORDS_PER_DOCUMENT) {
+            keyword_count += 1;
+            if keyword.split(' ').any(|word| stop.contains(word)) {
+                stopword_keywords += 1;
+            }
+        }
+    }
+
+    let empty_summaries = samples.len() - summary_lengths.len();
+    CorpusStats {
+        documents: samples.len(),
+        summary_chars: LengthDistribution::of(summary_lengths),
+        empty_summaries,
+        empty_summary_rate: ratio(empty_summaries, samples.len()),
+        summary_sources,
+        keywords: keyword_count,
+        stopword_keywords,
+        stopword_contamination: ratio(stopword_keywords, keyword_count),
+    }
+}
+
+/// Scores of one fixture
+#[cfg_attr(not(feature = "eval"), allow(dead_code))]
+#[derive(Debug, Clone, Serialize)]
+pub struct FixtureResult {
+    pub name: String,
+    pub summary: String,
+    pub summary_source: String,
+    pub summary_score: OverlapScore,
+    pub keywords: Vec<String>,
+    pub keyword_score: OverlapScore,
+}
+
+/// Scores over the fixture corpus
+#[cfg_attr(not(feature = "eval"), allow(dead_code))]
+#[derive(Debug, Clone, Serialize)]
+pub struct FixtureReport {
+    pub fixtures: Vec<FixtureResult>,
+    /// Means of the fixtures' scores
+    pub summary_score: OverlapScore,
+    pub keyword_score: OverlapScore,
+}
+
+/// What `eval_pipelines` reports
+#[derive(Debug, Clone, Serialize)]
+pub struct PipelineEvaluation {
+    pub corpus: CorpusStats,
+    /// Scores over the golden fixtures; only in builds with the `eval`
+    /// feature
+    pub fixtures: Option<FixtureReport>,
+}
+
+/// Describe the pipelines on a library sample, and score them on the
+/// fixtures when built to
+pub fn evaluate(samples: &[EvalSample]) -> PipelineEvaluation {
+    PipelineEvaluation {
+        corpus: corpus_stats(samples),
+        fixtures: evaluate_fixtures(),
+    }
+}
+
+/// Run the pipelines over the fixture corpus; None unless built with the
+/// `eval` feature
+#[cfg(feature = "eval")]
+fn evaluate_fixtures() -> Option<FixtureReport> {
+    Some(fixtures::evaluate())
+}
+
+/// Run the pipelines over the fixture corpus; None unless built with the
+/// `eval` feature
+#[cfg(not(feature = "eval"))]
+fn evaluate_fixtures() -> Option<FixtureReport> {
+    None
+}
+
+fn ratio(part: usize, whole: usize) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64
+    }
+}
+
+// Also built for tests, so `cargo test` holds the pipelines to the thresholds
+#[cfg(any(test, feature = "eval"))]
+mod fixtures {
+    use super::{keyword_overlap, rouge1, FixtureReport, FixtureResult, OverlapScore};
+    use crate::keywords;
+    use crate::summarizer::{self, FileKind};
+    use serde::Deserialize;
+
+    const CORPUS: &str = include_str!("../eval/fixtures.json");
+
+    #[derive(Deserialize)]
+    struct Fixture {
+        name: String,
+        kind: FileKind,
+        text: String,
+        /// Golden summary
+        summary: String,
+        /// Golden keywords, best first
+        keywords: Vec<String>,
+    }
+
+    /// Keywords are compared at as many as the fixture expects, so
+    /// precision and recall weigh the same
+    pub fn evaluate() -> FixtureReport {
+        let corpus: Vec<Fixture> = serde_json::from_str(CORPUS).expect("eval/fixtures.json is malformed");
+        let fixtures: Vec<FixtureResult> = corpus
+            .into_iter()
+            .map(|fixture| {
+                let summary = summarizer::summarize(fixture.kind, &fixture.text, &[]);
+                let language = keywords::detect_language(&fixture.text);
+                let extracted: Vec<String> =
+                    keywords::extract_keywords(&fixture.text, language, fixture.keywords.len())
+                        .into_iter()
+                        .map(|(keyword, _)| keyword)
+                        .collect();
+                FixtureResult {
+                    summary_score: rouge1(&summary.summary, &fixture.summary),
+                    keyword_score: keyword_overlap(&extracted, &fixture.keywords),
+                    name: fixture.name,
+                    summary: summary.summary,
+                    summary_source: summary.source.as_str().to_string(),
+                    keywords: extracted,
+                }
+            })
+            .collect();
+
+        FixtureReport {
+            summary_score: mean(fixtures.iter().map(|f| f.summary_score)),
+            keyword_score: mean(fixtures.iter().map(|f| f.keyword_score)),
+            fixtures,
+        }
+    }
+
+    fn mean(scores: impl ExactSizeIterator<Item = OverlapScore>) -> OverlapScore {
+        let n = scores.len();
+        if n == 0 {
+            return OverlapScore::default();
+        }
+        let mut total = OverlapScore::default();
+        for score in scores {
+            total.precision += score.precision;
+            total.recall += score.recall;
+            total.f1 += score.f1;
+        }
+        OverlapScore {
+            precision: total.precision / n as f64,
+            recall: total.recall / n as f64,
+            f1: total.f1 / n as f64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Floors under the fixture scores; raise them when the pipelines improve
+    const MIN_SUMMARY_F1: f64 = 0.9;
+    const MIN_KEYWORD_F1: f64 = 0.35;
+    /// Every fixture has to find at least one expected keyword
+    const MIN_FIXTURE_KEYWORD_RECALL: f64 = 0.2;
+
+    #[test]
+    fn rouge1_counts_each_reference_word_at_most_as_often_as_it_occurs() {
+        let score = rouge1("backup backup backup restore", "backup restore drill");
+        assert_eq!(score.precision, 0.5);
+        assert!((score.recall - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(rouge1("", "backup").f1, 0.0);
+    }
+
+    #[test]
+    fn keywords_are_compared_ignoring_case_and_padding() {
+        let extracted = ["Sparse Retrieval ".to_string(), "laptops".to_string()];
+        let expected = ["sparse retrieval".to_string(), "term weighting".to_string()];
+        let score = keyword_overlap(&extracted, &expected);
+        assert_eq!((score.precision, score.recall, score.f1), (0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn length_distribution_uses_nearest_rank() {
+        let lengths = LengthDistribution::of((1..=10).collect()).unwrap();
+        assert_eq!((lengths.min, lengths.median, lengths.p90, lengths.max), (1, 5, 9, 10));
+        assert_eq!(lengths.mean, 5.5);
+        assert!(LengthDistribution::of(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn fixtures_score_above_the_thresholds() {
+        let report = fixtures::evaluate();
+        assert!(!report.fixtures.is_empty());
+        for fixture in &report.fixtures {
+            assert!(
+                fixture.keyword_score.recall >= MIN_FIXTURE_KEYWORD_RECALL,
+                "{}: keywords {:?} scored {:?}",
+                fixture.name,
+                fixture.keywords,
+                fixture.keyword_score
+            );
+        }
+        assert!(
+            report.summary_score.f1 >= MIN_SUMMARY_F1,
+            "Summary ROUGE-1 {:?}: {:#?}",
+            report.summary_score,
+            report.fixtures
+        );
+        assert!(
+            report.keyword_score.f1 >= MIN_KEYWORD_F1,
+            "Keyword overlap {:?}: {:#?}",
+            report.keyword_score,
+            report.fixtures
+        );
+    }
+}
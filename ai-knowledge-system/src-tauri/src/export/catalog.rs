@@ -0,0 +1,293 @@
+use super::html::write_escaped;
+use super::markdown::escape_line_start;
+use crate::display;
+use crate::models::{CatalogEntry, CatalogFormat};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Longest summary preview that still fits a Markdown table; longer
+/// previews are written as a definition list
+pub const TABLE_SUMMARY_CHARS: usize = 120;
+
+const STYLE: &str = "body{font-family:-apple-system,Segoe UI,Helvetica,Arial,sans-serif;\
+margin:2rem;color:#1f2937}\
+h1{font-size:1.5rem}\
+table{border-collapse:collapse;font-size:.85rem;width:100%}\
+th,td{border:1px solid #e5e7eb;padding:.25rem .5rem;text-align:left;vertical-align:top}\
+th{background:#f9fafb}\
+td.number{text-align:right}\
+.count{color:#6b7280;font-size:.8rem}\
+@media print{body{margin:0}tr{break-inside:avoid}}";
+
+const COLUMNS: [&str; 6] = ["Title", "Authors", "Year", "Pages", "Tags", "Summary"];
+
+enum Output {
+    /// A table, or a definition list when `table` is false
+    Markdown { out: BufWriter<File>, table: bool },
+    Csv(Box<csv::Writer<BufWriter<File>>>),
+    Html(BufWriter<File>),
+}
+
+/// A library catalog written an entry at a time
+///
+/// Each format escapes what its syntax would otherwise read: pipes, angle
+/// brackets and emphasis characters in Markdown, commas, quotes and line
+/// breaks in CSV (left to the csv crate), and HTML's special characters.
+pub struct CatalogWriter {
+    output: Output,
+    summary_chars: usize,
+    written: usize,
+}
+
+impl CatalogWriter {
+    /// Create `dest` and write everything that comes before the entries
+    ///
+    /// Markdown is a table when previews of `summary_chars` fit one, else a
+    /// definition list; the choice is made up front so entries can be
+    /// written as they arrive.
+    pub fn create(dest: &Path, format: CatalogFormat, title: &str, summary_chars: usize) -> std::io::Result<Self> {
+        let file = BufWriter::new(File::create(dest)?);
+        let output = match format {
+            CatalogFormat::Markdown => {
+                let mut out = file;
+                let table = summary_chars <= TABLE_SUMMARY_CHARS;
+                writeln!(out, "# {}\n", escape_markdown(title))?;
+                if table {
+                    writeln!(out, "| {} |", COLUMNS.join(" | "))?;
+                    writeln!(out, "| --- | --- | --- | ---: | --- | --- |")?;
+                }
+                Output::Markdown { out, table }
+            }
+            CatalogFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(file);
+                writer.write_record(COLUMNS)?;
+                Output::Csv(Box::new(writer))
+            }
+            CatalogFormat::Html => {
+                let mut out = file;
+                writeln!(out, "<!DOCTYPE html>")?;
+                writeln!(out, "<html>")?;
+                writeln!(out, "<head>")?;
+                writeln!(out, "<meta charset=\"utf-8\">")?;
+                write!(out, "<title>")?;
+                write_escaped(&mut out, title)?;
+                writeln!(out, "</title>")?;
+                writeln!(out, "<style>{}</style>", STYLE)?;
+                writeln!(out, "</head>")?;
+                writeln!(out, "<body>")?;
+                write!(out, "<h1>")?;
+                write_escaped(&mut out, title)?;
+                writeln!(out, "</h1>")?;
+                writeln!(out, "<table>")?;
+                let header: String = COLUMNS.iter().map(|c| format!("<th>{}</th>", c)).collect();
+                writeln!(out, "<thead><tr>{}</tr></thead>", header)?;
+                writeln!(out, "<tbody>")?;
+                Output::Html(out)
+            }
+        };
+        Ok(CatalogWriter {
+            output,
+            summary_chars,
+            written: 0,
+        })
+    }
+
+    pub fn write_entry(&mut self, entry: &CatalogEntry) -> std::io::Result<()> {
+        let pages = entry.page_count.map(|count| count.to_string()).unwrap_or_default();
+        let summary = entry
+            .summary
+            .as_deref()
+            .map(|summary| preview(summary, self.summary_chars))
+            .unwrap_or_default();
+        let fields = [
+            entry.title.as_str(),
+            entry.authors.as_deref().unwrap_or_default(),
+            entry.year.as_deref().unwrap_or_default(),
+            pages.as_str(),
+            entry.tags.as_deref().unwrap_or_default(),
+            summary.as_str(),
+        ];
+
+        match &mut self.output {
+            Output::Markdown { out, table: true } => {
+                let cells: Vec<String> = fields.iter().map(|field| escape_markdown(field)).collect();
+                writeln!(out, "| {} |", cells.join(" | "))?;
+            }
+            Output::Markdown { out, table: false } => {
+                writeln!(out, "{}", escape_line_start(&escape_markdown(&entry.title)))?;
+                let details: Vec<String> = COLUMNS[1..5]
+                    .iter()
+                    .zip(&fields[1..5])
+                    .filter(|(_, value)| !value.is_empty())
+                    .map(|(label, value)| format!("{}: {}", label, escape_markdown(value)))
+                    .collect();
+                if !details.is_empty() {
+                    writeln!(out, ":   {}", details.join("; "))?;
+                }
+                if !summary.is_empty() {
+                    writeln!(out, ":   {}", escape_markdown(&summary))?;
+                }
+                writeln!(out)?;
+            }
+            Output::Csv(writer) => writer.write_record(fields)?,
+            Output::Html(out) => {
+                write!(out, "<tr>")?;
+                for (index, field) in fields.iter().enumerate() {
+                    // Year and page count
+                    let class = if matches!(index, 2 | 3) { " class=\"number\"" } else { "" };
+                    write!(out, "<td{}>", class)?;
+                    write_escaped(out, field)?;
+                    write!(out, "</td>")?;
+                }
+                writeln!(out, "</tr>")?;
+            }
+        }
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Write what follows the entries and flush; returns the entries written
+    pub fn finish(self) -> std::io::Result<usize> {
+        match self.output {
+            Output::Markdown { mut out, .. } => {
+                writeln!(out, "\n{} documents", self.written)?;
+                out.flush()?;
+            }
+            Output::Csv(mut writer) => writer.flush()?,
+            Output::Html(mut out) => {
+                writeln!(out, "</tbody>")?;
+                writeln!(out, "</table>")?;
+                writeln!(out, "<p class=\"count\">{} documents</p>", self.written)?;
+                writeln!(out, "</body>")?;
+                writeln!(out, "</html>")?;
+                out.flush()?;
+            }
+        }
+        Ok(self.written)
+    }
+}
+
+/// The start of a summary on one line, cut at a word boundary
+fn preview(summary: &str, chars: usize) -> String {
+    let line = summary.split_whitespace().collect::<Vec<_>>().join(" ");
+    match display::preview(&line, chars) {
+        (start, true) => format!("{}…", start),
+        (whole, false) => whole.to_string(),
+    }
+}
+
+/// Text for one line of Markdown, such as a table cell: whitespace runs
+/// become one space, and pipes, angle brackets and emphasis characters are
+/// backslash-escaped
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for (index, word) in text.split_whitespace().enumerate() {
+        if index > 0 {
+            escaped.push(' ');
+        }
+        for c in word.chars() {
+            if matches!(c, '\\' | '|' | '<' | '>' | '`' | '*' | '_' | '[' | ']') {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn tricky_entry() -> CatalogEntry {
+        CatalogEntry {
+            id: Uuid::new_v4(),
+            title: "A|B <script> *bold*".to_string(),
+            authors: Some("Lovelace, Ada; \"Babbage\", C.".to_string()),
+            year: Some("1843".to_string()),
+            page_count: Some(12),
+            tags: Some("math; history".to_string()),
+            summary: Some("Notes on the\nAnalytical Engine | with [links] & <tags>".to_string()),
+        }
+    }
+
+    fn write(format: CatalogFormat, summary_chars: usize, entries: &[CatalogEntry]) -> String {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("catalog");
+        let mut writer = CatalogWriter::create(&dest, format, "Thesis <draft> | v2", summary_chars).unwrap();
+        for entry in entries {
+            writer.write_entry(entry).unwrap();
+        }
+        assert_eq!(writer.finish().unwrap(), entries.len());
+        std::fs::read_to_string(dest).unwrap()
+    }
+
+    #[test]
+    fn markdown_escaping_keeps_cells_on_one_line() {
+        assert_eq!(escape_markdown("a|b"), "a\\|b");
+        assert_eq!(escape_markdown("<b>_x_ `y` [z]"), "\\<b\\>\\_x\\_ \\`y\\` \\[z\\]");
+        assert_eq!(escape_markdown("back\\slash"), "back\\\\slash");
+        assert_eq!(escape_markdown("two\nlines \t here"), "two lines here");
+    }
+
+    #[test]
+    fn markdown_table_rows_have_one_cell_per_column() {
+        let markdown = write(CatalogFormat::Markdown, TABLE_SUMMARY_CHARS, &[tricky_entry()]);
+        let lines: Vec<&str> = markdown.lines().collect();
+
+        assert_eq!(lines[0], "# Thesis \\<draft\\> \\| v2");
+        let row = lines.iter().find(|line| line.contains("A\\|B")).unwrap();
+        // Unescaped pipes are only the cell borders
+        let borders = row.matches('|').count() - row.matches("\\|").count();
+        assert_eq!(borders, COLUMNS.len() + 1, "{}", row);
+        assert!(row.contains("\\<script\\> \\*bold\\*"), "{}", row);
+        assert!(row.contains("Notes on the Analytical Engine \\| with \\[links\\] & \\<tags\\>"), "{}", row);
+        assert!(markdown.ends_with("\n1 documents\n"));
+    }
+
+    #[test]
+    fn long_summaries_make_a_definition_list() {
+        let mut entry = tricky_entry();
+        entry.title = "# Not a heading".to_string();
+        let markdown = write(CatalogFormat::Markdown, TABLE_SUMMARY_CHARS + 1, &[entry]);
+
+        assert!(!markdown.contains("| --- |"));
+        assert!(markdown.contains("\n\\# Not a heading\n"), "{}", markdown);
+        assert!(markdown.contains(":   Authors: Lovelace, Ada; \"Babbage\", C.; Year: 1843; Pages: 12; Tags: math; history\n"));
+    }
+
+    #[test]
+    fn csv_quotes_fields_with_commas_and_quotes() {
+        let csv = write(CatalogFormat::Csv, 500, &[tricky_entry()]);
+
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        assert_eq!(reader.headers().unwrap().iter().collect::<Vec<_>>(), COLUMNS);
+        let records: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        assert_eq!(records.len(), 1);
+        assert_eq!(&records[0][0], "A|B <script> *bold*");
+        assert_eq!(&records[0][1], "Lovelace, Ada; \"Babbage\", C.");
+        assert_eq!(&records[0][3], "12");
+        assert!(csv.contains("\"Lovelace, Ada; \"\"Babbage\"\", C.\""));
+    }
+
+    #[test]
+    fn html_escapes_every_field() {
+        let html = write(CatalogFormat::Html, 500, &[tricky_entry()]);
+
+        assert!(html.contains("<title>Thesis &lt;draft&gt; | v2</title>"));
+        assert!(html.contains("<td>A|B &lt;script&gt; *bold*</td>"));
+        assert!(html.contains("<td>Lovelace, Ada; &quot;Babbage&quot;, C.</td>"));
+        assert!(html.contains("<td class=\"number\">12</td>"));
+        assert!(html.contains("&amp; &lt;tags&gt;</td>"));
+        assert!(!html.contains("<script>"));
+        assert!(html.trim_end().ends_with("</html>"));
+    }
+
+    #[test]
+    fn previews_are_cut_on_one_line() {
+        assert_eq!(preview("Short\nsummary", 50), "Short summary");
+        assert_eq!(preview("A summary that goes on", 12), "A summary…");
+    }
+}
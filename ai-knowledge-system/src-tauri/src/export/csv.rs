@@ -0,0 +1,58 @@
+use crate::models::{CsvColumn, SearchExportRow};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// CSV of search matches, written a row at a time
+///
+/// Quoting of commas, quotes and line breaks is left to the csv crate.
+pub struct SearchResultsCsv {
+    writer: csv::Writer<BufWriter<File>>,
+    columns: Vec<CsvColumn>,
+}
+
+impl SearchResultsCsv {
+    /// Create `dest` and write the header row
+    pub fn create(dest: &Path, columns: Vec<CsvColumn>) -> Result<Self, csv::Error> {
+        let mut writer = csv::Writer::from_writer(BufWriter::new(File::create(dest)?));
+        writer.write_record(columns.iter().map(CsvColumn::as_str))?;
+        Ok(SearchResultsCsv { writer, columns })
+    }
+
+    pub fn write_row(&mut self, row: &SearchExportRow) -> Result<(), csv::Error> {
+        let fields: Vec<String> = self
+            .columns
+            .iter()
+            .map(|column| match column {
+                CsvColumn::Id => row.id.to_string(),
+                CsvColumn::Title => row.title.clone(),
+                CsvColumn::FileName => row.file_name.clone().unwrap_or_default(),
+                CsvColumn::CreatedAt => row.created_at.to_rfc3339(),
+                CsvColumn::Workspace => row.workspace.clone().unwrap_or_default(),
+                CsvColumn::Tags => row.tags.clone().unwrap_or_default(),
+                // One line per snippet, however the content was laid out
+                CsvColumn::Snippet => row
+                    .snippet
+                    .as_deref()
+                    .map(|s| s.split_whitespace().collect::<Vec<_>>().join(" "))
+                    .unwrap_or_default(),
+            })
+            .collect();
+        self.writer.write_record(&fields)
+    }
+
+    /// Mark the export as cut off after `rows` documents, in the first column
+    /// of a final row
+    pub fn write_truncation_marker(&mut self, rows: usize) -> Result<(), csv::Error> {
+        let mut fields = vec![String::new(); self.columns.len()];
+        if let Some(first) = fields.first_mut() {
+            *first = format!("TRUNCATED: more than {} documents matched; narrow the search to export the rest", rows);
+        }
+        self.writer.write_record(&fields)
+    }
+
+    pub fn finish(mut self) -> Result<(), csv::Error> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+use crate::models::Document;
+use base64::engine::general_purpose::STANDARD;
+use base64::write::EncoderWriter;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const STYLE: &str = "body{font-family:-apple-system,Segoe UI,Helvetica,Arial,sans-serif;\
+max-width:48rem;margin:2rem auto;padding:0 1rem;line-height:1.6;color:#1f2937}\
+h1{font-size:1.75rem;margin-bottom:.5rem}\
+.summary{background:#f3f4f6;border-left:4px solid #6366f1;padding:.75rem 1rem}\
+table{border-collapse:collapse;margin:1rem 0;font-size:.9rem}\
+th,td{border:1px solid #e5e7eb;padding:.25rem .75rem;text-align:left}\
+th{background:#f9fafb}\
+.thumbnail{max-width:12rem;float:right;margin:0 0 1rem 1rem;border:1px solid #e5e7eb}\
+.page{border-top:1px dashed #d1d5db;padding-top:.5rem}\
+.page-label{color:#9ca3af;font-size:.8rem}";
+
+/// Write a document as a single self-contained HTML file
+///
+/// Content is escaped and written paragraph by paragraph, so the output is
+/// never assembled in memory. When `pages` is given, each page gets its own
+/// `#page-N` anchor; otherwise the whole content is rendered as one section.
+pub fn write_document_html(
+    doc: &Document,
+    pages: Option<&[String]>,
+    thumbnail: Option<&Path>,
+    dest: &Path,
+) -> Result<(), std::io::Error> {
+    let file = File::create(dest)?;
+    let mut out = BufWriter::new(file);
+
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html>")?;
+    writeln!(out, "<head>")?;
+    writeln!(out, "<meta charset=\"utf-8\">")?;
+    write!(out, "<title>")?;
+    write_escaped(&mut out, &doc.title)?;
+    writeln!(out, "</title>")?;
+    writeln!(out, "<style>{}</style>", STYLE)?;
+    writeln!(out, "</head>")?;
+    writeln!(out, "<body>")?;
+
+    if let Some(thumbnail_path) = thumbnail {
+        write_thumbnail(&mut out, thumbnail_path)?;
+    }
+
+    write!(out, "<h1>")?;
+    write_escaped(&mut out, &doc.title)?;
+    writeln!(out, "</h1>")?;
+
+    if let Some(summary) = doc.summary.as_deref().filter(|s| !s.trim().is_empty()) {
+        write!(out, "<p class=\"summary\">")?;
+        write_escaped(&mut out, summary.trim())?;
+        writeln!(out, "</p>")?;
+    }
+
+    write_metadata_table(&mut out, doc)?;
+
+    match pages {
+        Some(pages) => {
+            for (index, page_text) in pages.iter().enumerate() {
+                let page_number = index + 1;
+                writeln!(out, "<section class=\"page\" id=\"page-{}\">", page_number)?;
+                writeln!(out, "<div class=\"page-label\">Page {}</div>", page_number)?;
+                write_paragraphs(&mut out, page_text)?;
+                writeln!(out, "</section>")?;
+            }
+        }
+        None => {
+            if let Some(content) = doc.content.as_deref() {
+                writeln!(out, "<section>")?;
+                write_paragraphs(&mut out, content)?;
+                writeln!(out, "</section>")?;
+            }
+        }
+    }
+
+    writeln!(out, "</body>")?;
+    writeln!(out, "</html>")?;
+    out.flush()
+}
+
+fn write_metadata_table<W: Write>(out: &mut W, doc: &Document) -> Result<(), std::io::Error> {
+    let status = format!("{:?}", doc.status);
+    let size = doc.file_size_bytes.map(|bytes| format!("{} bytes", bytes));
+    let created = doc.created_at.to_rfc3339();
+    let updated = doc.updated_at.to_rfc3339();
+
+    let rows: [(&str, Option<&str>); 7] = [
+        ("File name", doc.file_name.as_deref()),
+        ("File type", doc.file_type.as_deref()),
+        ("MIME type", doc.mime_type.as_deref()),
+        ("Size", size.as_deref()),
+        ("Status", Some(status.as_str())),
+        ("Created", Some(created.as_str())),
+        ("Updated", Some(updated.as_str())),
+    ];
+
+    writeln!(out, "<table>")?;
+    for (label, value) in rows {
+        if let Some(value) = value {
+            write!(out, "<tr><th>{}</th><td>", label)?;
+            write_escaped(out, value)?;
+            writeln!(out, "</td></tr>")?;
+        }
+    }
+    writeln!(out, "</table>")
+}
+
+/// Split text on blank lines and emit one `<p>` per paragraph
+fn write_paragraphs<W: Write>(out: &mut W, text: &str) -> Result<(), std::io::Error> {
+    for paragraph in text.split("\n\n") {
+        let mut lines = paragraph.lines().map(str::trim).filter(|l| !l.is_empty());
+        let Some(first) = lines.next() else {
+            continue;
+        };
+
+        write!(out, "<p>")?;
+        write_escaped(out, first)?;
+        for line in lines {
+            write!(out, " ")?;
+            write_escaped(out, line)?;
+        }
+        writeln!(out, "</p>")?;
+    }
+    Ok(())
+}
+
+/// Embed the thumbnail as a base64 data URI, streaming the encoding
+fn write_thumbnail<W: Write>(out: &mut W, path: &Path) -> Result<(), std::io::Error> {
+    let mime = match path.extension().and_then(|e| e.to_str()) {
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("webp") => "image/webp",
+        _ => "image/png",
+    };
+
+    let mut image = File::open(path)?;
+    write!(out, "<img class=\"thumbnail\" alt=\"\" src=\"data:{};base64,", mime)?;
+    {
+        let mut encoder = EncoderWriter::new(&mut *out, &STANDARD);
+        std::io::copy(&mut image, &mut encoder)?;
+        encoder.finish()?;
+    }
+    writeln!(out, "\">")
+}
+
+/// Write text with HTML special characters escaped
+pub fn write_escaped<W: Write>(out: &mut W, text: &str) -> Result<(), std::io::Error> {
+    let mut last = 0;
+    for (i, byte) in text.bytes().enumerate() {
+        let replacement = match byte {
+            b'&' => "&amp;",
+            b'<' => "&lt;",
+            b'>' => "&gt;",
+            b'"' => "&quot;",
+            b'\'' => "&#39;",
+            _ => continue,
+        };
+        out.write_all(&text.as_bytes()[last..i])?;
+        out.write_all(replacement.as_bytes())?;
+        last = i + 1;
+    }
+    out.write_all(&text.as_bytes()[last..])
+}
+
+/// Write a shared document as HTML: its title and text, without the file
+/// details an export includes
+pub fn write_shared_html<W: Write>(out: &mut W, title: &str, content: &str) -> Result<(), std::io::Error> {
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html>")?;
+    writeln!(out, "<head>")?;
+    writeln!(out, "<meta charset=\"utf-8\">")?;
+    write!(out, "<title>")?;
+    write_escaped(out, title)?;
+    writeln!(out, "</title>")?;
+    writeln!(out, "<style>{}</style>", STYLE)?;
+    writeln!(out, "</head>")?;
+    writeln!(out, "<body>")?;
+    write!(out, "<h1>")?;
+    write_escaped(out, title)?;
+    writeln!(out, "</h1>")?;
+    writeln!(out, "<section>")?;
+    write_paragraphs(out, content)?;
+    writeln!(out, "</section>")?;
+    writeln!(out, "</body>")?;
+    writeln!(out, "</html>")
+}
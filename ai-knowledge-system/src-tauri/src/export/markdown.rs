@@ -0,0 +1,583 @@
+use crate::models::{Document, MarkdownFidelity};
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Longest line, in chars, that can be taken for a heading
+const MAX_HEADING_CHARS: usize = 80;
+const MAX_HEADING_WORDS: usize = 12;
+/// Share of significant words that must be capitalized in a title-cased line
+const TITLE_CASE_SHARE: f32 = 0.75;
+/// Markdown's deepest heading level
+const MAX_HEADING_LEVEL: usize = 6;
+
+const BULLET_GLYPHS: [char; 8] = ['•', '◦', '▪', '▫', '‣', '∙', '·', '●'];
+
+/// `2.1 Results`, `3. Methods`, `IV. Discussion`
+fn numbered_heading_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"^((?:\d+\.)*\d+|[IVX]+)\.?\s+\p{Lu}").unwrap())
+}
+
+/// Write a document as Markdown
+///
+/// The title becomes the top-level heading and the summary a quote. With
+/// `Basic` fidelity the text follows as extracted. With `Structured`, a
+/// structure-detection pass turns short title-cased or numbered lines into
+/// headings and bullet glyphs into list items, and each page starts with a
+/// `<!-- page N -->` comment. For DOCX files, `docx` is the stored file;
+/// its paragraph styles are used instead of guessing.
+pub fn write_document_markdown(
+    doc: &Document,
+    pages: Option<&[String]>,
+    docx: Option<&Path>,
+    fidelity: MarkdownFidelity,
+    dest: &Path,
+) -> Result<(), std::io::Error> {
+    let file = File::create(dest)?;
+    let mut out = BufWriter::new(file);
+
+    writeln!(out, "# {}", single_line(&doc.title))?;
+    writeln!(out)?;
+    if let Some(summary) = doc.summary.as_deref().filter(|s| !s.trim().is_empty()) {
+        for line in summary.trim().lines() {
+            writeln!(out, "> {}", line.trim())?;
+        }
+        writeln!(out)?;
+    }
+
+    if let Some(path) = docx {
+        write_docx(&mut out, path, fidelity)?;
+        return out.flush();
+    }
+
+    match (fidelity, pages) {
+        (MarkdownFidelity::Structured, Some(pages)) => {
+            for (index, page_text) in pages.iter().enumerate() {
+                writeln!(out, "<!-- page {} -->", index + 1)?;
+                writeln!(out)?;
+                write_structured(&mut out, page_text)?;
+            }
+        }
+        (MarkdownFidelity::Structured, None) => {
+            write_structured(&mut out, doc.content.as_deref().unwrap_or_default())?;
+        }
+        (MarkdownFidelity::Basic, _) => {
+            for line in doc.content.as_deref().unwrap_or_default().lines() {
+                writeln!(out, "{}", escape_line_start(line.trim_end()))?;
+            }
+        }
+    }
+
+    out.flush()
+}
+
+/// How a line of extracted text reads
+#[derive(Debug, PartialEq)]
+enum Line<'a> {
+    Blank,
+    Heading(usize, &'a str),
+    Bullet(&'a str),
+    Body(&'a str),
+}
+
+/// Write extracted text with headings and list items detected
+///
+/// A candidate heading only counts when body text follows it, so runs of
+/// short lines such as table cells or an author list stay as text.
+fn write_structured<W: Write>(out: &mut W, text: &str) -> Result<(), std::io::Error> {
+    let lines: Vec<&str> = text.lines().map(str::trim).collect();
+    // Kind of the last line written, None right after a blank line
+    let mut previous: Option<Line> = None;
+
+    for (index, &line) in lines.iter().enumerate() {
+        let next = lines[index + 1..].iter().copied().find(|l| !l.is_empty());
+        let current = classify(line, next);
+        // Blocks of a different kind need a blank line between them
+        let separate = !matches!(
+            (&previous, &current),
+            (None, _)
+                | (_, Line::Blank)
+                | (Some(Line::Bullet(_)), Line::Bullet(_))
+                | (Some(Line::Body(_)), Line::Body(_))
+        );
+        if separate || (current == Line::Blank && previous.is_some()) {
+            writeln!(out)?;
+        }
+
+        match current {
+            Line::Blank => {
+                previous = None;
+                continue;
+            }
+            Line::Heading(level, heading) => {
+                writeln!(out, "{} {}", "#".repeat(level), heading)?;
+            }
+            Line::Bullet(item) => writeln!(out, "- {}", escape_line_start(item))?,
+            Line::Body(body) => writeln!(out, "{}", escape_line_start(body))?,
+        }
+        previous = Some(current);
+    }
+    if previous.is_some() {
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn classify<'a>(line: &'a str, next: Option<&str>) -> Line<'a> {
+    if line.is_empty() {
+        return Line::Blank;
+    }
+    if let Some(item) = bullet_item(line) {
+        return Line::Bullet(item);
+    }
+    if let Some(level) = heading_level(line) {
+        let followed_by_body = next.is_some_and(|n| heading_level(n).is_none() && bullet_item(n).is_none());
+        if followed_by_body {
+            return Line::Heading(level, line);
+        }
+    }
+    Line::Body(line)
+}
+
+/// The text after a leading bullet glyph, e.g. `• item`
+fn bullet_item(line: &str) -> Option<&str> {
+    let mut chars = line.chars();
+    let first = chars.next()?;
+    if !BULLET_GLYPHS.contains(&first) && !matches!(first, '-' | '*' | '–') {
+        return None;
+    }
+    let rest = chars.as_str();
+    rest.starts_with(char::is_whitespace)
+        .then_some(rest.trim_start())
+        .filter(|item| !item.is_empty())
+}
+
+/// Heading level for a line that looks like one, counting the document
+/// title as level 1
+///
+/// Numbered headings nest by their numbering (`2.1` is deeper than `2`);
+/// other headings are short lines in title case or capitals without
+/// closing punctuation.
+fn heading_level(line: &str) -> Option<usize> {
+    if line.chars().count() > MAX_HEADING_CHARS || line.ends_with(['.', ',', ';', ':']) {
+        return None;
+    }
+    let words: Vec<&str> = line.split_whitespace().collect();
+    if words.len() > MAX_HEADING_WORDS {
+        return None;
+    }
+
+    if let Some(captures) = numbered_heading_regex().captures(line) {
+        let depth = captures[1].split('.').count();
+        return Some((depth + 1).min(MAX_HEADING_LEVEL));
+    }
+
+    let letters: Vec<char> = line.chars().filter(|c| c.is_alphabetic()).collect();
+    if letters.len() < 3 {
+        return None;
+    }
+    if letters.iter().all(|c| c.is_uppercase()) {
+        return Some(2);
+    }
+
+    // Short words such as "of" and "and" stay lowercase in title case
+    let significant: Vec<&str> = words
+        .iter()
+        .copied()
+        .filter(|w| w.chars().filter(|c| c.is_alphabetic()).count() > 3)
+        .collect();
+    if significant.is_empty() {
+        return None;
+    }
+    let capitalized = significant
+        .iter()
+        .filter(|w| w.chars().find(|c| c.is_alphabetic()).is_some_and(char::is_uppercase))
+        .count();
+    (capitalized as f32 / significant.len() as f32 >= TITLE_CASE_SHARE).then_some(2)
+}
+
+/// Keep body text from being read as Markdown syntax at the start of a line
+pub(super) fn escape_line_start(line: &str) -> String {
+    if line.starts_with(['#', '>', '-', '+', '*', '|', '`', '=', ':']) {
+        return format!("\\{}", line);
+    }
+    // "1. " and "1) " would start an ordered list; escape the delimiter
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    if digits > 0 && line[digits..].starts_with(['.', ')']) {
+        return format!("{}\\{}", &line[..digits], &line[digits..]);
+    }
+    line.to_string()
+}
+
+fn single_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Convert a DOCX body to Markdown using its paragraph styles
+///
+/// Title and Heading 1-5 styles map to heading levels, with Heading 1 one
+/// below the document title; list styles and numbered paragraphs become
+/// list items. With `Basic` fidelity styles are ignored.
+fn write_docx<W: Write>(out: &mut W, path: &Path, fidelity: MarkdownFidelity) -> Result<(), std::io::Error> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    let document_xml = read_entry(&mut archive, "word/document.xml")?
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "Not a Word document"))?;
+    let style_names = match read_entry(&mut archive, "word/styles.xml")? {
+        Some(styles_xml) => style_names(&styles_xml),
+        None => HashMap::new(),
+    };
+
+    for paragraph in docx_paragraphs(&document_xml) {
+        let text = paragraph.text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        if fidelity == MarkdownFidelity::Basic {
+            writeln!(out, "{}", escape_line_start(text))?;
+            writeln!(out)?;
+            continue;
+        }
+
+        let style = paragraph
+            .style_id
+            .as_deref()
+            .map(|id| style_names.get(id).map(String::as_str).unwrap_or(id).to_lowercase())
+            .unwrap_or_default();
+        let style = style.replace(' ', "");
+        if let Some(level) = docx_heading_level(&style) {
+            writeln!(out, "{} {}", "#".repeat(level), single_line(text))?;
+            writeln!(out)?;
+        } else if paragraph.numbered || style.starts_with("list") {
+            writeln!(out, "- {}", escape_line_start(&single_line(text)))?;
+        } else {
+            for line in text.lines() {
+                writeln!(out, "{}", escape_line_start(line.trim()))?;
+            }
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Markdown level for a normalized style name such as "heading2" or "title"
+fn docx_heading_level(style: &str) -> Option<usize> {
+    if style == "title" {
+        return Some(1);
+    }
+    let depth: usize = style.strip_prefix("heading")?.parse().ok()?;
+    (depth >= 1).then_some((depth + 1).min(MAX_HEADING_LEVEL))
+}
+
+fn read_entry(archive: &mut zip::ZipArchive<File>, name: &str) -> Result<Option<String>, std::io::Error> {
+    let mut entry = match archive.by_name(name) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+    };
+    let mut xml = String::new();
+    entry.read_to_string(&mut xml)?;
+    Ok(Some(xml))
+}
+
+struct DocxParagraph {
+    style_id: Option<String>,
+    numbered: bool,
+    text: String,
+}
+
+/// Paragraphs of document.xml with their style id and text
+///
+/// A small scanner rather than a full XML parser: it only needs `w:p`,
+/// `w:pStyle`, `w:numPr`, `w:t`, `w:tab` and `w:br`.
+fn docx_paragraphs(xml: &str) -> Vec<DocxParagraph> {
+    let mut paragraphs = Vec::new();
+    let mut current: Option<DocxParagraph> = None;
+    let mut in_text = false;
+    // Tab stops in paragraph properties are also w:tab elements
+    let mut in_tab_stops = false;
+    let mut rest = xml;
+
+    while let Some(open) = rest.find('<') {
+        if in_text {
+            if let Some(paragraph) = current.as_mut() {
+                paragraph.text.push_str(&decode_entities(&rest[..open]));
+            }
+        }
+        let Some(close) = rest[open..].find('>') else {
+            break;
+        };
+        let tag = &rest[open + 1..open + close];
+        rest = &rest[open + close + 1..];
+
+        let name = tag.split(|c: char| c.is_whitespace() || c == '/').next().unwrap_or_default();
+        match name {
+            "w:p" => {
+                let paragraph = DocxParagraph {
+                    style_id: None,
+                    numbered: false,
+                    text: String::new(),
+                };
+                if tag.ends_with('/') {
+                    paragraphs.push(paragraph);
+                } else {
+                    current = Some(paragraph);
+                }
+            }
+            "" if tag == "/w:p" => paragraphs.extend(current.take()),
+            "w:pStyle" => {
+                if let Some(paragraph) = current.as_mut() {
+                    paragraph.style_id = attribute(tag, "w:val");
+                }
+            }
+            "w:numPr" => {
+                if let Some(paragraph) = current.as_mut() {
+                    paragraph.numbered = true;
+                }
+            }
+            "w:t" => in_text = !tag.ends_with('/'),
+            "" if tag == "/w:t" => in_text = false,
+            "w:tabs" => in_tab_stops = !tag.ends_with('/'),
+            "" if tag == "/w:tabs" => in_tab_stops = false,
+            "w:tab" if !in_tab_stops => {
+                if let Some(paragraph) = current.as_mut() {
+                    paragraph.text.push('\t');
+                }
+            }
+            "w:br" | "w:cr" => {
+                if let Some(paragraph) = current.as_mut() {
+                    paragraph.text.push('\n');
+                }
+            }
+            _ => {}
+        }
+    }
+    paragraphs
+}
+
+/// Style id to display name, e.g. "Heading1" -> "heading 1", from styles.xml
+///
+/// Localized documents use ids like "berschrift1" but keep English names.
+fn style_names(xml: &str) -> HashMap<String, String> {
+    let mut names = HashMap::new();
+    let mut current_id: Option<String> = None;
+    let mut rest = xml;
+
+    while let Some(open) = rest.find('<') {
+        let Some(close) = rest[open..].find('>') else {
+            break;
+        };
+        let tag = &rest[open + 1..open + close];
+        rest = &rest[open + close + 1..];
+
+        if tag.starts_with("w:style ") {
+            current_id = attribute(tag, "w:styleId");
+        } else if tag.starts_with("w:name ") {
+            if let (Some(id), Some(name)) = (current_id.take(), attribute(tag, "w:val")) {
+                names.insert(id, name);
+            }
+        }
+    }
+    names
+}
+
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let start = tag.find(&format!("{}=\"", name))? + name.len() + 2;
+    let end = tag[start..].find('"')? + start;
+    Some(decode_entities(&tag[start..end]))
+}
+
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let Some(semi) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..semi];
+        let replacement = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match replacement {
+            Some(c) => {
+                decoded.push(c);
+                rest = &rest[semi + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// A page of text as extracted from a paper
+    const PAPER_PAGE: &str = "\
+Sparse Retrieval for Personal Knowledge Bases
+Ada Lovelace
+Charles Babbage
+
+1 Introduction
+Search over personal documents differs from web search.
+Collections are small and queries short.
+
+2.1 Results
+Two findings stand out:
+• Recall improved on short queries
+▪   Indexing stays cheap
+# of queries: 40
+
+RELATED WORK
+Dense retrieval needs a GPU.
+";
+
+    const PAPER_MARKDOWN: &str = "\
+Sparse Retrieval for Personal Knowledge Bases
+Ada Lovelace
+Charles Babbage
+
+## 1 Introduction
+
+Search over personal documents differs from web search.
+Collections are small and queries short.
+
+### 2.1 Results
+
+Two findings stand out:
+
+- Recall improved on short queries
+- Indexing stays cheap
+
+\\# of queries: 40
+
+## RELATED WORK
+
+Dense retrieval needs a GPU.
+
+";
+
+    fn structured(text: &str) -> String {
+        let mut out = Vec::new();
+        write_structured(&mut out, text).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// A DOCX file with `body` as the content of its w:body
+    fn docx(body: &str) -> tempfile::NamedTempFile {
+        let styles = r#"<w:styles><w:style w:type="paragraph" w:styleId="berschrift1"><w:name w:val="heading 1"/></w:style></w:styles>"#;
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::FileOptions::default();
+        zip.start_file("word/document.xml", options).unwrap();
+        write!(zip, "<w:document><w:body>{}</w:body></w:document>", body).unwrap();
+        zip.start_file("word/styles.xml", options).unwrap();
+        zip.write_all(styles.as_bytes()).unwrap();
+        let bytes = zip.finish().unwrap().into_inner();
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&bytes).unwrap();
+        file
+    }
+
+    fn docx_markdown(body: &str, fidelity: MarkdownFidelity) -> String {
+        let file = docx(body);
+        let mut out = Vec::new();
+        write_docx(&mut out, file.path(), fidelity).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn structure_is_detected_in_extracted_text() {
+        assert_eq!(structured(PAPER_PAGE), PAPER_MARKDOWN);
+    }
+
+    #[test]
+    fn heading_candidates_without_body_text_stay_text() {
+        // Table cells and author lists are short title-cased lines too
+        assert_eq!(structured("Name\nRole\nAda Lovelace\nAnalyst\n"), "Name\nRole\nAda Lovelace\nAnalyst\n\n");
+        assert_eq!(heading_level("3.2.1 Ablation Study"), Some(4));
+        assert_eq!(heading_level("IV. Discussion"), Some(2));
+        assert_eq!(heading_level("This sentence ends with a period."), None);
+    }
+
+    #[test]
+    fn body_lines_are_not_read_as_markdown() {
+        assert_eq!(escape_line_start("# not a heading"), "\\# not a heading");
+        assert_eq!(escape_line_start("> not a quote"), "\\> not a quote");
+        assert_eq!(escape_line_start("1984. A year"), "1984\\. A year");
+        assert_eq!(escape_line_start("3) third"), "3\\) third");
+        assert_eq!(escape_line_start("2024 was a year"), "2024 was a year");
+    }
+
+    #[test]
+    fn docx_paragraph_styles_become_headings_and_lists() {
+        let body = concat!(
+            r#"<w:p><w:pPr><w:pStyle w:val="Title"/></w:pPr><w:r><w:t>Annual Report</w:t></w:r></w:p>"#,
+            r#"<w:p><w:pPr><w:pStyle w:val="berschrift1"/><w:tabs><w:tab w:val="left"/></w:tabs></w:pPr><w:r><w:t>Results</w:t></w:r></w:p>"#,
+            r#"<w:p><w:r><w:t xml:space="preserve">Revenue &amp; profit grew.</w:t><w:br/><w:t>- Not a list</w:t></w:r></w:p>"#,
+            r#"<w:p><w:pPr><w:pStyle w:val="ListParagraph"/></w:pPr><w:r><w:t>First</w:t></w:r></w:p>"#,
+            r#"<w:p><w:pPr><w:numPr><w:ilvl w:val="0"/></w:numPr></w:pPr><w:r><w:t>Second</w:t><w:tab/><w:t>item</w:t></w:r></w:p>"#,
+            r#"<w:p/>"#,
+        );
+
+        assert_eq!(
+            docx_markdown(body, MarkdownFidelity::Structured),
+            "# Annual Report\n\n## Results\n\nRevenue & profit grew.\n\\- Not a list\n\n- First\n- Second item\n"
+        );
+        assert_eq!(
+            docx_markdown(body, MarkdownFidelity::Basic),
+            "Annual Report\n\nResults\n\nRevenue & profit grew.\n- Not a list\n\nFirst\n\nSecond\titem\n\n"
+        );
+    }
+
+    #[test]
+    fn pages_are_marked_with_comments() {
+        let document: Document = serde_json::from_value(serde_json::json!({
+            "id": uuid::Uuid::nil(),
+            "user_id": uuid::Uuid::nil(),
+            "title": "Sparse\nRetrieval",
+            "summary": "We study retrieval.\nIt works.",
+            "status": "Completed",
+            "created_at": "2024-01-01T00:00:00Z",
+            "updated_at": "2024-01-01T00:00:00Z",
+            "is_pinned": false,
+            "external_file": false,
+            "is_unread": false
+        }))
+        .unwrap();
+        let pages = ["Intro text.".to_string(), "More text.".to_string()];
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("paper.md");
+
+        write_document_markdown(&document, Some(&pages), None, MarkdownFidelity::Structured, &dest).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&dest).unwrap(),
+            "# Sparse Retrieval\n\n> We study retrieval.\n> It works.\n\n\
+             <!-- page 1 -->\n\nIntro text.\n\n<!-- page 2 -->\n\nMore text.\n\n"
+        );
+    }
+}
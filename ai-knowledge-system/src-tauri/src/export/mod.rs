@@ -0,0 +1,4 @@
+pub mod csv;
+pub mod html;
+pub mod markdown;
+pub mod catalog;
@@ -0,0 +1,229 @@
+//! Field selection for document commands: a caller names the fields it
+//! wants and gets JSON objects with only those keys
+//!
+//! Fields that weren't asked for are left out of the object altogether,
+//! not sent as null, so a view's partial type matches what arrives. `id` is
+//! always included.
+
+use crate::error::{AppError, AppResult};
+use serde::{Serialize, Serializer};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Fields of a Document, as serialized
+pub const DOCUMENT_FIELDS: &[&str] = &[
+    "id",
+    "user_id",
+    "workspace_id",
+    "title",
+    "content",
+    "summary",
+    "file_path",
+    "file_name",
+    "file_size_bytes",
+    "file_type",
+    "mime_type",
+    "status",
+    "processing_error",
+    "created_at",
+    "updated_at",
+    "deleted_at",
+    "page_count",
+    "parent_document_id",
+    "is_pinned",
+    "pinned_order",
+    "external_file",
+    "is_unread",
+];
+
+/// Fields a DocumentListEntry adds to its document
+pub const LIST_ENTRY_FIELDS: &[&str] = &[
+    "bucket",
+    "summary_preview",
+    "summary_truncated",
+    "file_kind",
+];
+
+/// Fields DocumentDetails adds to its document
+pub const DETAILS_FIELDS: &[&str] = &["identifiers", "notes", "reading_position", "provenance"];
+
+/// Which fields to serialize; every field when none were named
+#[derive(Debug, Clone, Default)]
+pub struct FieldSelection {
+    fields: Option<HashSet<String>>,
+}
+
+impl FieldSelection {
+    /// Check requested field names against the fields a command has
+    ///
+    /// `extra` lists the fields the command adds to DOCUMENT_FIELDS.
+    pub fn parse(requested: Option<Vec<String>>, extra: &[&str]) -> AppResult<Self> {
+        let Some(requested) = requested else {
+            return Ok(FieldSelection::default());
+        };
+        let mut fields = HashSet::with_capacity(requested.len() + 1);
+        fields.insert("id".to_string());
+        for field in requested {
+            if !DOCUMENT_FIELDS.contains(&field.as_str()) && !extra.contains(&field.as_str()) {
+                return Err(AppError::InvalidInput(format!("Unknown field \"{}\"", field)));
+            }
+            fields.insert(field);
+        }
+        Ok(FieldSelection { fields: Some(fields) })
+    }
+
+    /// Whether `field` will be serialized
+    pub fn includes(&self, field: &str) -> bool {
+        match &self.fields {
+            Some(fields) => fields.contains(field),
+            None => true,
+        }
+    }
+
+    /// Whether `field` was named; fields that are only sent on request,
+    /// like a list's content, check this instead of `includes`
+    pub fn requested(&self, field: &str) -> bool {
+        self.fields.as_ref().is_some_and(|fields| fields.contains(field))
+    }
+}
+
+/// A value serialized with only the selected fields of its object
+#[derive(Debug, Clone)]
+pub struct Selected<T> {
+    value: T,
+    selection: Arc<FieldSelection>,
+}
+
+impl<T> Selected<T> {
+    pub fn new(value: T, selection: Arc<FieldSelection>) -> Self {
+        Selected { value, selection }
+    }
+}
+
+impl<T: Serialize> Serialize for Selected<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Some(fields) = &self.selection.fields else {
+            return self.value.serialize(serializer);
+        };
+        match serde_json::to_value(&self.value).map_err(serde::ser::Error::custom)? {
+            serde_json::Value::Object(mut object) => {
+                object.retain(|key, _| fields.contains(key));
+                object.serialize(serializer)
+            }
+            other => other.serialize(serializer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Document, DocumentListEntry, DocumentStatus};
+    use crate::summarizer::FileKind;
+    use serde_json::Value;
+    use uuid::Uuid;
+
+    fn document() -> Document {
+        Document {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            workspace_id: None,
+            title: "Quarterly report".to_string(),
+            content: Some("Revenue grew.".to_string()),
+            summary: None,
+            file_path: Some("/library/report.pdf".to_string()),
+            file_name: Some("report.pdf".to_string()),
+            file_size_bytes: Some(2048),
+            file_type: Some("PDF".to_string()),
+            mime_type: Some("application/pdf".to_string()),
+            status: DocumentStatus::Completed,
+            processing_error: None,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+            deleted_at: None,
+            page_count: Some(3),
+            parent_document_id: None,
+            is_pinned: false,
+            pinned_order: None,
+            external_file: false,
+            is_unread: true,
+        }
+    }
+
+    fn list_entry() -> DocumentListEntry {
+        DocumentListEntry {
+            document: document(),
+            bucket: None,
+            summary_preview: None,
+            summary_truncated: false,
+            file_kind: FileKind::Document,
+        }
+    }
+
+    fn selected<T: Serialize>(value: T, fields: &[&str], extra: &[&str]) -> serde_json::Map<String, Value> {
+        let requested = fields.iter().map(|f| f.to_string()).collect();
+        let selection = FieldSelection::parse(Some(requested), extra).unwrap();
+        match serde_json::to_value(Selected::new(value, Arc::new(selection))).unwrap() {
+            Value::Object(object) => object,
+            other => panic!("Expected an object, got {}", other),
+        }
+    }
+
+    fn keys(object: &serde_json::Map<String, Value>) -> Vec<&str> {
+        let mut keys: Vec<&str> = object.keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    #[test]
+    fn the_field_lists_match_what_is_serialized() {
+        let all = serde_json::to_value(Selected::new(list_entry(), Arc::default())).unwrap();
+        let mut expected: Vec<&str> = DOCUMENT_FIELDS.iter().chain(LIST_ENTRY_FIELDS).copied().collect();
+        expected.sort_unstable();
+        assert_eq!(keys(all.as_object().unwrap()), expected);
+    }
+
+    #[test]
+    fn unselected_fields_are_absent_not_null() {
+        let object = selected(list_entry(), &["title", "summary", "bucket"], LIST_ENTRY_FIELDS);
+
+        assert_eq!(keys(&object), ["bucket", "id", "summary", "title"]);
+        // Selected fields that are None are still sent, as null
+        assert_eq!(object["summary"], Value::Null);
+        assert_eq!(object["bucket"], Value::Null);
+        assert!(!object.contains_key("content"));
+        assert!(!object.contains_key("file_path"));
+    }
+
+    #[test]
+    fn id_is_always_included() {
+        let document = document();
+        let id = document.id.to_string();
+        let object = selected(document, &[], &[]);
+        assert_eq!(keys(&object), ["id"]);
+        assert_eq!(object["id"], Value::String(id));
+    }
+
+    #[test]
+    fn unknown_fields_are_invalid_input() {
+        let requested = Some(vec!["title".to_string(), "password_hash".to_string()]);
+        let error = FieldSelection::parse(requested, DETAILS_FIELDS).unwrap_err();
+        assert!(matches!(&error, AppError::InvalidInput(message) if message.contains("password_hash")), "{:?}", error);
+
+        // A field of one command isn't valid for another
+        let error = FieldSelection::parse(Some(vec!["bucket".to_string()]), DETAILS_FIELDS).unwrap_err();
+        assert!(matches!(error, AppError::InvalidInput(_)));
+    }
+
+    #[test]
+    fn heavy_fields_are_sent_only_when_named() {
+        let everything = FieldSelection::parse(None, LIST_ENTRY_FIELDS).unwrap();
+        assert!(everything.includes("content"));
+        assert!(!everything.requested("content"));
+
+        let named = FieldSelection::parse(Some(vec!["content".to_string()]), LIST_ENTRY_FIELDS).unwrap();
+        assert!(named.requested("content"));
+        assert!(named.requested("id"));
+        assert!(!named.includes("summary"));
+    }
+}
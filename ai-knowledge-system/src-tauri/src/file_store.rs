@@ -0,0 +1,549 @@
+//! Reading and writing stored document files, optionally encrypted at rest
+//!
+//! With encryption enabled, files are encrypted with XChaCha20-Poly1305 under
+//! a key derived from the library passphrase with Argon2id, in chunks using
+//! the STREAM construction. Each encrypted file starts with a header:
+//!
+//! - `AKSE` magic (4 bytes)
+//! - format version (1 byte)
+//! - STREAM nonce prefix (19 bytes)
+//!
+//! Only stored files are encrypted. Document content, summaries and pages in
+//! the database, thumbnails, and the temporary plaintext copy an extractor
+//! reads while processing are not.
+
+use crate::error::{AppError, AppResult};
+use crate::settings::AppSettings;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::generic_array::GenericArray;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::stream::{DecryptorBE32, EncryptorBE32};
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+const MAGIC: &[u8; 4] = b"AKSE";
+const FORMAT_VERSION: u8 = 1;
+const STREAM_NONCE_LEN: usize = 19;
+const HEADER_LEN: usize = MAGIC.len() + 1 + STREAM_NONCE_LEN;
+
+/// Plaintext bytes per encrypted chunk
+const CHUNK_LEN: usize = 64 * 1024;
+
+/// Poly1305 tag appended to every chunk
+const TAG_LEN: usize = 16;
+
+const SALT_LEN: usize = 16;
+
+const MIN_PASSPHRASE_CHARS: usize = 8;
+
+/// Encrypted with the derived key to recognise a wrong passphrase on unlock
+const KEY_CHECK_PLAINTEXT: &[u8] = b"ai-knowledge-system library key";
+
+/// How stored files are written and read back
+///
+/// Kept to whole-file streams so other backends can implement it later.
+pub trait FileStore: Send + Sync {
+    /// Write `source` to `dest`, returning the number of plaintext bytes
+    fn write(&self, source: &mut dyn Read, dest: &Path) -> io::Result<u64>;
+
+    /// Open a stored file as plaintext
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>>;
+}
+
+/// Files stored as-is
+pub struct PlainFileStore;
+
+impl FileStore for PlainFileStore {
+    fn write(&self, source: &mut dyn Read, dest: &Path) -> io::Result<u64> {
+        let mut out = BufWriter::new(File::create(dest)?);
+        let written = io::copy(source, &mut out)?;
+        out.flush()?;
+        Ok(written)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        if is_encrypted(path)? {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} is encrypted", path.display()),
+            ));
+        }
+        Ok(Box::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+/// Files encrypted with the unlocked library key
+///
+/// Files not encrypted yet (from before encryption was enabled) are read
+/// as-is until `encrypt_in_place` gets to them.
+#[derive(Clone)]
+pub struct EncryptedFileStore {
+    cipher: XChaCha20Poly1305,
+}
+
+impl FileStore for EncryptedFileStore {
+    fn write(&self, source: &mut dyn Read, dest: &Path) -> io::Result<u64> {
+        let mut nonce = [0u8; STREAM_NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+
+        let mut out = BufWriter::new(File::create(dest)?);
+        out.write_all(MAGIC)?;
+        out.write_all(&[FORMAT_VERSION])?;
+        out.write_all(&nonce)?;
+
+        let mut encryptor =
+            EncryptorBE32::from_aead(self.cipher.clone(), GenericArray::from_slice(&nonce));
+        let mut written = 0u64;
+        let mut current = vec![0u8; CHUNK_LEN];
+        let mut current_len = read_full(source, &mut current)?;
+        loop {
+            // Read ahead one chunk so the last one can be marked as such
+            let mut next = vec![0u8; CHUNK_LEN];
+            let next_len = if current_len == CHUNK_LEN {
+                read_full(source, &mut next)?
+            } else {
+                0
+            };
+            written += current_len as u64;
+
+            if next_len == 0 {
+                let sealed = encryptor
+                    .encrypt_last(&current[..current_len])
+                    .map_err(|_| crypto_error("Encryption failed"))?;
+                out.write_all(&sealed)?;
+                break;
+            }
+            let sealed = encryptor
+                .encrypt_next(&current[..current_len])
+                .map_err(|_| crypto_error("Encryption failed"))?;
+            out.write_all(&sealed)?;
+            current = next;
+            current_len = next_len;
+        }
+
+        out.flush()?;
+        Ok(written)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut header = [0u8; HEADER_LEN];
+        let header_len = read_full(&mut file, &mut header)?;
+        if header_len < HEADER_LEN || &header[..MAGIC.len()] != MAGIC {
+            return Ok(Box::new(BufReader::new(File::open(path)?)));
+        }
+        if header[MAGIC.len()] != FORMAT_VERSION {
+            return Err(crypto_error("Unsupported encrypted file version"));
+        }
+
+        let nonce = &header[MAGIC.len() + 1..];
+        let decryptor = DecryptorBE32::from_aead(self.cipher.clone(), GenericArray::from_slice(nonce));
+        let mut reader = DecryptingReader {
+            inner: file,
+            decryptor: Some(decryptor),
+            next: Vec::new(),
+            plain: Vec::new(),
+            position: 0,
+        };
+        reader.next = reader.read_chunk()?;
+        Ok(Box::new(reader))
+    }
+}
+
+struct DecryptingReader<R> {
+    inner: R,
+    /// Taken once the last chunk has been decrypted
+    decryptor: Option<DecryptorBE32<XChaCha20Poly1305>>,
+    /// Encrypted chunk read ahead of the one being returned
+    next: Vec<u8>,
+    plain: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> DecryptingReader<R> {
+    fn read_chunk(&mut self) -> io::Result<Vec<u8>> {
+        let mut chunk = vec![0u8; CHUNK_LEN + TAG_LEN];
+        let len = read_full(&mut self.inner, &mut chunk)?;
+        chunk.truncate(len);
+        Ok(chunk)
+    }
+
+    /// Decrypt the next chunk into `plain`; false at the end of the file
+    fn fill(&mut self) -> io::Result<bool> {
+        if self.decryptor.is_none() {
+            return Ok(false);
+        }
+        let current = std::mem::take(&mut self.next);
+        if current.is_empty() {
+            // Every file ends with a last chunk, so this one was cut short
+            return Err(crypto_error("Encrypted file is truncated"));
+        }
+
+        let following = self.read_chunk()?;
+        let decrypted = if following.is_empty() {
+            let decryptor = self.decryptor.take().expect("checked above");
+            decryptor.decrypt_last(current.as_slice())
+        } else {
+            self.next = following;
+            let decryptor = self.decryptor.as_mut().expect("checked above");
+            decryptor.decrypt_next(current.as_slice())
+        };
+        self.plain = decrypted
+            .map_err(|_| crypto_error("Encrypted file is corrupt or was encrypted with another key"))?;
+        self.position = 0;
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.plain.len() {
+            if !self.fill()? {
+                return Ok(0);
+            }
+        }
+        let len = buf.len().min(self.plain.len() - self.position);
+        buf[..len].copy_from_slice(&self.plain[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+/// Whether a stored file carries the encrypted file header
+pub fn is_encrypted(path: &Path) -> io::Result<bool> {
+    let mut magic = [0u8; MAGIC.len()];
+    let len = read_full(&mut File::open(path)?, &mut magic)?;
+    Ok(len == MAGIC.len() && &magic == MAGIC)
+}
+
+/// Encrypt a plaintext stored file, replacing it once the copy checks out
+///
+/// Returns false if the file was already encrypted.
+pub fn encrypt_in_place(store: &EncryptedFileStore, path: &Path) -> AppResult<bool> {
+    if is_encrypted(path)? {
+        return Ok(false);
+    }
+
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let partial = path.with_file_name(format!("{}.encrypting", file_name));
+    let result = (|| -> AppResult<bool> {
+        let mut source = BufReader::new(File::open(path)?);
+        let expected = crate::file_utils::sha256_reader(&mut source)?;
+        let mut source = BufReader::new(File::open(path)?);
+        store.write(&mut source, &partial)?;
+        if crate::file_utils::sha256_reader(&mut store.open(&partial)?)? != expected {
+            return Err(AppError::Other(format!("Verification failed for {}", file_name)));
+        }
+        std::fs::rename(&partial, path)?;
+        Ok(true)
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    result
+}
+
+/// A plaintext copy of a stored file for code that needs a path, e.g. extractors
+///
+/// Unencrypted files are used in place; decrypted copies are removed on drop.
+pub struct LocalCopy {
+    path: PathBuf,
+    temporary: bool,
+}
+
+impl LocalCopy {
+    pub fn new(store: &dyn FileStore, path: &Path) -> AppResult<Self> {
+        if !is_encrypted(path)? {
+            return Ok(LocalCopy {
+                path: path.to_path_buf(),
+                temporary: false,
+            });
+        }
+
+        let extension = path
+            .extension()
+            .map(|e| format!(".{}", e.to_string_lossy()))
+            .unwrap_or_default();
+        let temp = std::env::temp_dir().join(format!("aks-{}{}", uuid::Uuid::new_v4(), extension));
+        let copy = LocalCopy {
+            path: temp,
+            temporary: true,
+        };
+        let mut out = BufWriter::new(File::create(&copy.path)?);
+        io::copy(&mut store.open(path)?, &mut out)?;
+        out.flush()?;
+        Ok(copy)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for LocalCopy {
+    fn drop(&mut self) {
+        if self.temporary {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Salt and key check persisted in settings when encryption is enabled
+pub struct KeyMaterial {
+    pub salt: String,
+    pub check: String,
+}
+
+/// Holds the library key for the session once unlocked
+#[derive(Default)]
+pub struct Keyring {
+    store: RwLock<Option<EncryptedFileStore>>,
+}
+
+impl Keyring {
+    /// Derive a key for a new passphrase and unlock with it
+    ///
+    /// Slow by design (Argon2); call from a blocking task.
+    pub fn initialize(&self, passphrase: &str) -> AppResult<KeyMaterial> {
+        if passphrase.chars().count() < MIN_PASSPHRASE_CHARS {
+            return Err(AppError::InvalidInput(format!(
+                "Passphrase must be at least {} characters",
+                MIN_PASSPHRASE_CHARS
+            )));
+        }
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let cipher = derive_cipher(passphrase, &salt)?;
+
+        let mut nonce = [0u8; 24];
+        OsRng.fill_bytes(&mut nonce);
+        let sealed = cipher
+            .encrypt(XNonce::from_slice(&nonce), KEY_CHECK_PLAINTEXT)
+            .map_err(|_| AppError::Other("Encryption failed".to_string()))?;
+        let check = [nonce.as_slice(), &sealed].concat();
+
+        *self.store.write().expect("keyring lock poisoned") = Some(EncryptedFileStore { cipher });
+        Ok(KeyMaterial {
+            salt: BASE64.encode(salt),
+            check: BASE64.encode(check),
+        })
+    }
+
+    /// Derive the key from `passphrase` and keep it if it matches the key check
+    ///
+    /// Slow by design (Argon2); call from a blocking task.
+    pub fn unlock(&self, settings: &AppSettings, passphrase: &str) -> AppResult<()> {
+        let (Some(salt), Some(check)) = (&settings.encryption_salt, &settings.encryption_check) else {
+            return Err(AppError::InvalidInput("Encryption is not enabled".to_string()));
+        };
+        let salt = BASE64
+            .decode(salt)
+            .map_err(|e| AppError::Other(format!("Invalid encryption salt: {}", e)))?;
+        let check = BASE64
+            .decode(check)
+            .map_err(|e| AppError::Other(format!("Invalid key check: {}", e)))?;
+        if check.len() < 24 {
+            return Err(AppError::Other("Invalid key check".to_string()));
+        }
+
+        let cipher = derive_cipher(passphrase, &salt)?;
+        let (nonce, sealed) = check.split_at(24);
+        match cipher.decrypt(XNonce::from_slice(nonce), sealed) {
+            Ok(plain) if plain == KEY_CHECK_PLAINTEXT => {
+                *self.store.write().expect("keyring lock poisoned") = Some(EncryptedFileStore { cipher });
+                Ok(())
+            }
+            _ => Err(AppError::WrongPassphrase),
+        }
+    }
+
+    /// Forget the key; encrypted files can't be read until unlocked again
+    pub fn lock(&self) {
+        *self.store.write().expect("keyring lock poisoned") = None;
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        self.store.read().expect("keyring lock poisoned").is_some()
+    }
+
+    /// The encrypting store, if unlocked
+    pub fn encrypted_store(&self) -> AppResult<EncryptedFileStore> {
+        self.store
+            .read()
+            .expect("keyring lock poisoned")
+            .clone()
+            .ok_or(AppError::LibraryLocked)
+    }
+
+    /// The store matching the current settings
+    pub fn store(&self, settings: &AppSettings) -> AppResult<Arc<dyn FileStore>> {
+        if settings.encryption_enabled {
+            Ok(Arc::new(self.encrypted_store()?))
+        } else {
+            Ok(Arc::new(PlainFileStore))
+        }
+    }
+}
+
+fn derive_cipher(passphrase: &str, salt: &[u8]) -> AppResult<XChaCha20Poly1305> {
+    let mut key = [0u8; 32];
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| AppError::Other(format!("Key derivation failed: {}", e)))?;
+    Ok(XChaCha20Poly1305::new(GenericArray::from_slice(&key)))
+}
+
+/// Read until `buf` is full or the reader is exhausted
+fn read_full(reader: &mut dyn Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+fn crypto_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A store with a fixed key, without Argon2's deliberate slowness
+    fn store_with_key(byte: u8) -> EncryptedFileStore {
+        EncryptedFileStore {
+            cipher: XChaCha20Poly1305::new(GenericArray::from_slice(&[byte; 32])),
+        }
+    }
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    fn read_back(store: &dyn FileStore, path: &Path) -> io::Result<Vec<u8>> {
+        let mut plain = Vec::new();
+        store.open(path)?.read_to_end(&mut plain)?;
+        Ok(plain)
+    }
+
+    #[test]
+    fn files_of_every_chunk_boundary_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store_with_key(7);
+        for len in [0, 1, CHUNK_LEN - 1, CHUNK_LEN, CHUNK_LEN + 1, 3 * CHUNK_LEN + 5] {
+            let path = dir.path().join(format!("{}.bin", len));
+            let plain = sample(len);
+            assert_eq!(store.write(&mut plain.as_slice(), &path).unwrap(), len as u64);
+
+            let stored = std::fs::read(&path).unwrap();
+            let chunks = len.div_ceil(CHUNK_LEN).max(1);
+            assert_eq!(stored.len(), HEADER_LEN + len + chunks * TAG_LEN);
+            assert!(is_encrypted(&path).unwrap());
+            assert_eq!(read_back(&store, &path).unwrap(), plain, "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn truncated_files_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store_with_key(7);
+        let path = dir.path().join("paper.pdf");
+        store.write(&mut sample(2 * CHUNK_LEN + 100).as_slice(), &path).unwrap();
+        let stored = std::fs::read(&path).unwrap();
+
+        // Cut within the last chunk, at the end of a whole chunk, and
+        // before the first chunk
+        let chunk = CHUNK_LEN + TAG_LEN;
+        for len in [stored.len() - 1, HEADER_LEN + 2 * chunk, HEADER_LEN + chunk, HEADER_LEN] {
+            std::fs::write(&path, &stored[..len]).unwrap();
+            let error = read_back(&store, &path).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "cut to {} bytes: {}", len, error);
+        }
+    }
+
+    #[test]
+    fn files_encrypted_with_another_key_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("paper.pdf");
+        store_with_key(7).write(&mut sample(1000).as_slice(), &path).unwrap();
+
+        let error = read_back(&store_with_key(8), &path).unwrap_err();
+        assert_eq!(error.to_string(), "Encrypted file is corrupt or was encrypted with another key");
+    }
+
+    #[test]
+    fn tampered_files_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store_with_key(7);
+        let path = dir.path().join("paper.pdf");
+        store.write(&mut sample(1000).as_slice(), &path).unwrap();
+        let mut stored = std::fs::read(&path).unwrap();
+        stored[HEADER_LEN + 10] ^= 1;
+        std::fs::write(&path, &stored).unwrap();
+
+        assert!(read_back(&store, &path).is_err());
+    }
+
+    #[test]
+    fn plain_files_are_read_as_they_are_and_can_be_encrypted_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store_with_key(7);
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, b"stored before encryption was enabled").unwrap();
+        assert_eq!(read_back(&store, &path).unwrap(), b"stored before encryption was enabled");
+
+        assert!(encrypt_in_place(&store, &path).unwrap());
+        assert!(is_encrypted(&path).unwrap());
+        assert!(!encrypt_in_place(&store, &path).unwrap());
+        assert_eq!(read_back(&store, &path).unwrap(), b"stored before encryption was enabled");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn only_the_passphrase_the_library_was_set_up_with_unlocks_it() {
+        let keyring = Keyring::default();
+        let material = keyring.initialize("correct horse battery").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("paper.pdf");
+        keyring.encrypted_store().unwrap().write(&mut sample(500).as_slice(), &path).unwrap();
+
+        let settings = AppSettings {
+            encryption_enabled: true,
+            encryption_salt: Some(material.salt),
+            encryption_check: Some(material.check),
+            ..AppSettings::default()
+        };
+        let unlocked = Keyring::default();
+        assert!(matches!(
+            unlocked.unlock(&settings, "wrong horse battery"),
+            Err(AppError::WrongPassphrase)
+        ));
+        assert!(matches!(unlocked.store(&settings), Err(AppError::LibraryLocked)));
+
+        unlocked.unlock(&settings, "correct horse battery").unwrap();
+        assert_eq!(read_back(&*unlocked.store(&settings).unwrap(), &path).unwrap(), sample(500));
+        unlocked.lock();
+        assert!(!unlocked.is_unlocked());
+    }
+
+    #[test]
+    fn short_passphrases_are_refused() {
+        let keyring = Keyring::default();
+        assert!(matches!(keyring.initialize("short"), Err(AppError::InvalidInput(_))));
+        assert!(!keyring.is_unlocked());
+    }
+}
@@ -1,16 +1,20 @@
 use sha2::{Digest, Sha256};
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Write};
 use std::path::Path;
 
 /// Calculate SHA-256 hash of a file
 pub fn calculate_sha256(path: &Path) -> Result<String, std::io::Error> {
-    let mut file = File::open(path)?;
+    sha256_reader(&mut File::open(path)?)
+}
+
+/// Calculate SHA-256 hash of everything left in a reader
+pub fn sha256_reader(reader: &mut dyn Read) -> Result<String, std::io::Error> {
     let mut hasher = Sha256::new();
     let mut buffer = [0; 8192];
 
     loop {
-        let count = file.read(&mut buffer)?;
+        let count = reader.read(&mut buffer)?;
         if count == 0 {
             break;
         }
@@ -20,6 +24,14 @@ pub fn calculate_sha256(path: &Path) -> Result<String, std::io::Error> {
     Ok(format!("{:x}", hasher.finalize()))
 }
 
+/// SHA-256 of a text's UTF-8 bytes, hex
+pub fn sha256_text(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+/// MIME type of content that couldn't be recognized
+pub const UNKNOWN_MIME_TYPE: &str = "application/octet-stream";
+
 /// Detect MIME type from file content
 pub fn detect_mime_type(path: &Path) -> Result<String, std::io::Error> {
     match infer::get_from_path(path)? {
@@ -34,12 +46,150 @@ pub fn detect_mime_type(path: &Path) -> Result<String, std::io::Error> {
                     "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
                         .to_string(),
                 ),
-                _ => Ok("application/octet-stream".to_string()),
+                // Unknown extension: look at the bytes before giving up
+                _ => Ok(sniff_text_mime(path)?.unwrap_or(UNKNOWN_MIME_TYPE).to_string()),
             }
         }
     }
 }
 
+/// Bytes inspected when sniffing content
+const SNIFF_LEN: usize = 8192;
+
+/// Share of control characters above which content is treated as binary
+const MAX_CONTROL_RATIO: f64 = 0.02;
+
+/// Guess a text MIME type from the first few KB, or None for binary content
+///
+/// Accepts UTF-8 (with or without BOM) and BOM-marked UTF-16, then picks
+/// JSON, XML, CSV or Markdown by simple structural checks, else text/plain.
+fn sniff_text_mime(path: &Path) -> Result<Option<&'static str>, std::io::Error> {
+    let mut sample = Vec::with_capacity(SNIFF_LEN);
+    File::open(path)?.take(SNIFF_LEN as u64 + 1).read_to_end(&mut sample)?;
+    let truncated = sample.len() > SNIFF_LEN;
+    sample.truncate(SNIFF_LEN);
+
+    let Some(text) = decode_text_sample(&sample) else {
+        return Ok(None);
+    };
+
+    let total = text.chars().count();
+    let control = text
+        .chars()
+        .filter(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t' | '\u{c}'))
+        .count();
+    if total > 0 && control as f64 / total as f64 > MAX_CONTROL_RATIO {
+        return Ok(None);
+    }
+
+    Ok(Some(classify_text(text.trim_start_matches('\u{feff}'), truncated)))
+}
+
+/// Decode a sample as UTF-16 (BOM required) or UTF-8, tolerating a
+/// character cut off at the end of the sample
+pub fn decode_text_sample(sample: &[u8]) -> Option<String> {
+    match sample {
+        [0xFF, 0xFE, rest @ ..] => decode_utf16(rest, u16::from_le_bytes),
+        [0xFE, 0xFF, rest @ ..] => decode_utf16(rest, u16::from_be_bytes),
+        _ => {
+            if sample.contains(&0) {
+                return None;
+            }
+            match std::str::from_utf8(sample) {
+                Ok(text) => Some(text.to_string()),
+                // error_len() is None only when the input ends mid-character
+                Err(e) if e.error_len().is_none() => {
+                    Some(String::from_utf8_lossy(&sample[..e.valid_up_to()]).into_owned())
+                }
+                Err(_) => None,
+            }
+        }
+    }
+}
+
+fn decode_utf16(bytes: &[u8], to_unit: fn([u8; 2]) -> u16) -> Option<String> {
+    let units: Vec<u16> = bytes.chunks_exact(2).map(|pair| to_unit([pair[0], pair[1]])).collect();
+    let mut text = String::with_capacity(units.len());
+    let mut decoded = char::decode_utf16(units.iter().copied()).peekable();
+    while let Some(unit) = decoded.next() {
+        match unit {
+            Ok(c) => text.push(c),
+            // A lone surrogate is only acceptable as a pair split at the end
+            Err(_) if decoded.peek().is_none() => break,
+            Err(_) => return None,
+        }
+    }
+    Some(text)
+}
+
+fn classify_text(text: &str, truncated: bool) -> &'static str {
+    let trimmed = text.trim();
+
+    if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        let complete_json = !truncated && serde_json::from_str::<serde_json::Value>(trimmed).is_ok();
+        if complete_json || (truncated && trimmed.starts_with("{\"")) {
+            return "application/json";
+        }
+    }
+
+    if trimmed.starts_with("<?xml") || (trimmed.starts_with('<') && trimmed.contains("</")) {
+        return "application/xml";
+    }
+
+    let lines: Vec<&str> = trimmed.lines().filter(|l| !l.trim().is_empty()).take(20).collect();
+
+    let markdown_signals = lines
+        .iter()
+        .filter(|line| {
+            let line = line.trim_start();
+            line.starts_with("# ")
+                || line.starts_with("## ")
+                || line.starts_with("```")
+                || line.starts_with("- ")
+                || line.starts_with("* ")
+                || line.starts_with("> ")
+                || (line.contains("](") && line.contains('['))
+        })
+        .count();
+    if markdown_signals >= 2 {
+        return "text/markdown";
+    }
+
+    // Drop a possibly cut-off last line before comparing field counts
+    let complete_lines = if truncated && lines.len() > 1 { &lines[..lines.len() - 1] } else { &lines[..] };
+    if complete_lines.len() >= 2 {
+        let fields = complete_lines[0].matches(',').count();
+        if fields > 0 && complete_lines.iter().all(|line| line.matches(',').count() == fields) {
+            return "text/csv";
+        }
+    }
+
+    "text/plain"
+}
+
+/// MIME types of programs, as detected from content
+const EXECUTABLE_MIME_TYPES: &[&str] = &[
+    "application/vnd.microsoft.portable-executable",
+    "application/x-msdownload",
+    "application/x-executable",
+    "application/x-sharedlib",
+    "application/x-mach-binary",
+    "application/x-msi",
+    "application/vnd.android.package-archive",
+    "application/java-archive",
+];
+
+/// Extensions of programs and scripts, lowercase
+const EXECUTABLE_EXTENSIONS: &[&str] = &[
+    "exe", "dll", "com", "scr", "msi", "bat", "cmd", "ps1", "vbs", "sh", "jar", "apk", "app",
+];
+
+/// Whether a file is a program or script rather than a document, by its
+/// detected MIME type or lowercase extension
+pub fn is_executable(mime: &str, extension: &str) -> bool {
+    EXECUTABLE_MIME_TYPES.contains(&mime) || EXECUTABLE_EXTENSIONS.contains(&extension)
+}
+
 /// Get file extension as string
 pub fn get_file_extension(path: &Path) -> String {
     path.extension()
@@ -47,3 +197,284 @@ pub fn get_file_extension(path: &Path) -> String {
         .map(|e| e.to_uppercase())
         .unwrap_or_else(|| "FILE".to_string())
 }
+
+/// File type for a detected MIME type, for files named without an extension
+pub fn file_type_for_mime(mime: &str) -> Option<&'static str> {
+    match mime {
+        "application/pdf" => Some("PDF"),
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document" => Some("DOCX"),
+        "text/plain" => Some("TXT"),
+        "text/markdown" => Some("MD"),
+        "text/csv" => Some("CSV"),
+        "application/json" => Some("JSON"),
+        "application/xml" => Some("XML"),
+        _ => None,
+    }
+}
+
+/// Longest name `sanitize_filename` returns, leaving room under the
+/// 255-byte filesystem limit for prefixes and collision suffixes
+const MAX_FILE_NAME_BYTES: usize = 200;
+
+/// Longest trailing `.part` kept as the extension when a name is shortened
+const MAX_EXTENSION_BYTES: usize = 16;
+
+/// Names Windows reserves for devices, with or without an extension
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1",
+    "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// A file name made from a title or recorded name that is safe to create
+/// in a directory
+///
+/// Path separators, control characters and, on Windows, the characters it
+/// forbids become `_`. Leading spaces and trailing dots and spaces go, a
+/// reserved device name like `CON` gets a leading `_`, and long names are
+/// shortened keeping their extension. Nothing usable left gives `file`.
+/// Callers needing a unique name add their prefix or suffix afterwards.
+pub fn sanitize_filename(name: &str) -> String {
+    let replaced: String = name
+        .chars()
+        .map(|c| if c.is_control() || is_illegal_in_file_name(c) { '_' } else { c })
+        .collect();
+    let cleaned = replaced.trim_start().trim_end_matches(['.', ' ']);
+
+    let (stem, extension) = match cleaned.rfind('.') {
+        Some(dot)
+            if dot > 0
+                && cleaned.len() - dot <= MAX_EXTENSION_BYTES
+                && cleaned[dot + 1..].chars().all(char::is_alphanumeric) =>
+        {
+            cleaned.split_at(dot)
+        }
+        _ => (cleaned, ""),
+    };
+    let stem = match stem.trim_end_matches(['.', ' ']) {
+        "" => "file",
+        stem => stem,
+    };
+    // Windows reserves the device names whatever follows the first dot
+    let device = stem.split('.').next().unwrap_or(stem).trim_end();
+    let prefix = if RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(device)) { "_" } else { "" };
+
+    let mut end = stem.len().min(MAX_FILE_NAME_BYTES - prefix.len() - extension.len());
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}{}", prefix, stem[..end].trim_end_matches(['.', ' ']), extension)
+}
+
+/// Characters a file name can't hold; backslash counts everywhere since
+/// exported files are also read on Windows
+fn is_illegal_in_file_name(c: char) -> bool {
+    matches!(c, '/' | '\\') || (cfg!(windows) && matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*'))
+}
+
+/// Markdown notes kept next to a file as `{stem}.md` or `{stem}.notes.md`
+///
+/// Markdown files are never their own sidecar.
+pub fn find_sidecar_note(path: &Path) -> Option<std::path::PathBuf> {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    if extension.eq_ignore_ascii_case("md") {
+        return None;
+    }
+    let stem = path.file_stem()?.to_str()?;
+    let dir = path.parent()?;
+
+    [format!("{}.md", stem), format!("{}.notes.md", stem)]
+        .into_iter()
+        .map(|name| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Free space available to this process on the volume containing `path`
+pub fn available_space(path: &Path) -> Result<u64, std::io::Error> {
+    fs2::available_space(path)
+}
+
+/// Replace a file's contents so readers see either the old or the new file
+///
+/// The contents go to a temporary file in the same directory, which is
+/// synced to disk and renamed over `path`. A crash part way through leaves
+/// the previous file intact.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), std::io::Error> {
+    let dir = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Path has no file name"))?;
+    let temp = dir.join(format!(".{}.{}.tmp", file_name.to_string_lossy(), uuid::Uuid::new_v4()));
+
+    let written = File::create(&temp).and_then(|mut file| {
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|_| std::fs::rename(&temp, path)) {
+        let _ = std::fs::remove_file(&temp);
+        return Err(e);
+    }
+
+    // Make the rename itself durable; directories can't be opened on Windows
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+/// Check that a directory accepts new files by writing and removing a probe
+pub fn check_writable(dir: &Path) -> Result<(), std::io::Error> {
+    let probe = dir.join(format!(".write_probe_{}", std::process::id()));
+    std::fs::write(&probe, b"probe")?;
+    std::fs::remove_file(&probe)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitized_names_never_hold_a_path() {
+        let names = ["a/b\\c.txt", "../../etc/passwd", "..", ".", "/", "dir\\..\\up", " /leading", "tab\there\n.md"];
+        for name in names {
+            let sanitized = sanitize_filename(name);
+            assert!(!sanitized.contains(['/', '\\']), "{:?} became {:?}", name, sanitized);
+            assert!(!sanitized.chars().any(char::is_control), "{:?} became {:?}", name, sanitized);
+            assert!(!matches!(sanitized.as_str(), "" | "." | ".."), "{:?} became {:?}", name, sanitized);
+        }
+        assert_eq!(sanitize_filename("a/b\\c.txt"), "a_b_c.txt");
+        assert_eq!(sanitize_filename("tab\there\n.md"), "tab_here_.md");
+    }
+
+    #[test]
+    fn reserved_device_names_get_a_prefix() {
+        assert_eq!(sanitize_filename("CON"), "_CON");
+        assert_eq!(sanitize_filename("nul.txt"), "_nul.txt");
+        assert_eq!(sanitize_filename("Com1.tar.gz"), "_Com1.tar.gz");
+        assert_eq!(sanitize_filename("lpt9 .pdf"), "_lpt9.pdf");
+        assert_eq!(sanitize_filename("CONSOLE.txt"), "CONSOLE.txt");
+    }
+
+    #[test]
+    fn trailing_dots_and_spaces_go() {
+        assert_eq!(sanitize_filename("  report. . "), "report");
+        assert_eq!(sanitize_filename("notes .md"), "notes.md");
+        assert_eq!(sanitize_filename("..."), "file");
+        assert_eq!(sanitize_filename(".md"), ".md");
+    }
+
+    #[test]
+    fn long_names_are_shortened_keeping_the_extension() {
+        let sanitized = sanitize_filename(&format!("{}.pdf", "é".repeat(150)));
+        assert!(sanitized.len() <= MAX_FILE_NAME_BYTES);
+        assert!(sanitized.ends_with("é.pdf"), "{:?}", sanitized);
+
+        // A long "extension" is just part of the name
+        let sanitized = sanitize_filename(&format!("archive.{}", "x".repeat(300)));
+        assert_eq!(sanitized.len(), MAX_FILE_NAME_BYTES);
+        assert!(sanitized.starts_with("archive.x"));
+    }
+
+    /// MIME type detected for a file named `name` holding `contents`
+    fn detected(name: &str, contents: &[u8]) -> String {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        std::fs::write(&path, contents).unwrap();
+        detect_mime_type(&path).unwrap()
+    }
+
+    #[test]
+    fn extensionless_text_is_sniffed() {
+        let readme = b"# Project\n\nA knowledge base for your documents.\n\n- Import\n- Search\n";
+        assert_eq!(detected("README", readme), "text/markdown");
+        assert_eq!(detected("NOTES", b"Just some notes.\nNothing structured here.\n"), "text/plain");
+        assert_eq!(detected("app.log", b"2024-01-01 12:00:00 INFO started\n2024-01-01 12:00:01 WARN slow\n"), "text/plain");
+    }
+
+    #[test]
+    fn structured_text_gets_its_own_type() {
+        assert_eq!(detected("data", b"  {\"name\": \"Ada\", \"tags\": [1, 2]}\n"), "application/json");
+        assert_eq!(detected("feed", b"<feed><entry>One</entry></feed>"), "application/xml");
+        assert_eq!(detected("table", b"name,email,role\nAda,ada@example.com,admin\nBob,bob@example.com,user\n"), "text/csv");
+        // A brace alone doesn't make JSON
+        assert_eq!(detected("braces", b"{not json at all}"), "text/plain");
+    }
+
+    #[test]
+    fn utf16_with_a_bom_is_text() {
+        let mut contents = vec![0xFF, 0xFE];
+        contents.extend("Hello from Windows Notepad\r\n".encode_utf16().flat_map(u16::to_le_bytes));
+        assert_eq!(detected("notepad", &contents), "text/plain");
+
+        let mut contents = vec![0xFE, 0xFF];
+        contents.extend("[1, 2, 3]".encode_utf16().flat_map(u16::to_be_bytes));
+        assert_eq!(detected("numbers", &contents), "application/json");
+    }
+
+    #[test]
+    fn binary_content_stays_octet_stream() {
+        let blob: Vec<u8> = (0..4096u32).map(|i| (i * 7 % 251) as u8).collect();
+        assert_eq!(detected("blob", &blob), UNKNOWN_MIME_TYPE);
+        // Mostly text with a few control bytes is still binary
+        let mut noisy = b"text ".repeat(20);
+        noisy.extend([0x01, 0x02, 0x03, 0x04, 0x05]);
+        assert_eq!(detected("noisy", &noisy), UNKNOWN_MIME_TYPE);
+        assert_eq!(detected("bad-utf8", b"caf\xe9 au lait"), UNKNOWN_MIME_TYPE);
+    }
+
+    #[test]
+    fn samples_may_end_mid_character() {
+        // "é" cut after its first byte, as at the end of an 8 KB sample
+        assert_eq!(decode_text_sample(b"caf\xc3").as_deref(), Some("caf"));
+        let mut utf16 = vec![0xFF, 0xFE];
+        utf16.extend("😀".encode_utf16().take(1).flat_map(u16::to_le_bytes));
+        assert_eq!(decode_text_sample(&utf16).as_deref(), Some(""));
+    }
+
+    #[test]
+    fn long_text_files_are_classified_from_their_start() {
+        let mut csv = b"id,value\n".to_vec();
+        while csv.len() <= SNIFF_LEN {
+            csv.extend(b"1234,some value\n");
+        }
+        assert_eq!(detected("big", &csv), "text/csv");
+    }
+
+    /// Names in `dir`, sorted
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn atomic_writes_replace_the_file_without_leaving_temporaries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+
+        write_atomic(&path, b"{\"theme\": \"dark\"}").unwrap();
+        write_atomic(&path, b"{}").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"{}");
+        assert_eq!(entries(dir.path()), ["settings.json"]);
+    }
+
+    #[test]
+    fn failed_atomic_writes_keep_the_previous_file() {
+        let dir = tempfile::tempdir().unwrap();
+        // Renaming over a directory fails after the temporary file is written
+        let blocked = dir.path().join("manifest.json");
+        std::fs::create_dir(&blocked).unwrap();
+        std::fs::write(blocked.join("keep"), b"previous").unwrap();
+
+        assert!(write_atomic(&blocked, b"new contents").is_err());
+        assert_eq!(std::fs::read(blocked.join("keep")).unwrap(), b"previous");
+        assert_eq!(entries(dir.path()), ["manifest.json"]);
+
+        let no_name = write_atomic(Path::new("/"), b"").unwrap_err();
+        assert_eq!(no_name.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
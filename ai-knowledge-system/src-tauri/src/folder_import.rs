@@ -0,0 +1,145 @@
+//! Where files imported from a folder tree land in the library
+//!
+//! `import_folder` queues every file under the folder as an upload; this
+//! module lists the files and works out, from a file's directories below the
+//! imported folder, which workspace and tags it gets for the structure mode.
+
+use crate::models::{SourceFileAction, StructureMode};
+use std::path::{Component, Path, PathBuf};
+
+/// Most tags a file gets from its path; deeper directories are dropped
+pub const MAX_PATH_TAGS: usize = 8;
+
+/// Where `MoveToSubfolder` moves imported files, in the imported folder
+pub const IMPORTED_DIR: &str = "imported";
+
+/// Longest workspace name, as stored
+const MAX_WORKSPACE_NAME_CHARS: usize = 255;
+
+/// Longest tag name, as stored
+const MAX_TAG_NAME_CHARS: usize = 100;
+
+/// The workspace and tags a file gets from its place in the tree
+#[derive(Debug, Default)]
+pub struct Placement {
+    pub workspace: Option<String>,
+    pub tags: Vec<String>,
+    /// Directories left out once the file had `MAX_PATH_TAGS` tags
+    pub tags_dropped: usize,
+}
+
+/// Regular files under `root`, recursively and in path order
+///
+/// Symlinks aren't followed, so the import stays inside the tree, and
+/// hidden files and directories (starting with a dot) are skipped.
+pub fn list_files(root: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            // DirEntry::file_type does not traverse symlinks
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push(entry.path());
+            } else if file_type.is_file() {
+                files.push(entry.path());
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Workspace and tags for `file`, which lies under `root`
+///
+/// Files directly in `root` get neither. Tags are the file's directories
+/// from the outermost in, each once.
+pub fn placement(root: &Path, file: &Path, mode: StructureMode) -> Placement {
+    let directories: Vec<String> = file
+        .strip_prefix(root)
+        .ok()
+        .and_then(Path::parent)
+        .map(|parent| {
+            parent
+                .components()
+                .filter_map(|component| match component {
+                    Component::Normal(name) => Some(name.to_string_lossy().trim().to_string()),
+                    _ => None,
+                })
+                .filter(|name| !name.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    match mode {
+        StructureMode::Flat => Placement::default(),
+        StructureMode::TopLevelAsWorkspace => Placement {
+            workspace: directories
+                .first()
+                .map(|name| name.chars().take(MAX_WORKSPACE_NAME_CHARS).collect()),
+            ..Placement::default()
+        },
+        StructureMode::PathAsTags => {
+            let mut tags: Vec<String> = Vec::new();
+            for name in directories {
+                let name: String = name.chars().take(MAX_TAG_NAME_CHARS).collect();
+                if !tags.iter().any(|tag| tag.to_lowercase() == name.to_lowercase()) {
+                    tags.push(name);
+                }
+            }
+            let tags_dropped = tags.len().saturating_sub(MAX_PATH_TAGS);
+            tags.truncate(MAX_PATH_TAGS);
+            Placement {
+                tags,
+                tags_dropped,
+                ..Placement::default()
+            }
+        }
+    }
+}
+
+/// Delete or move `file`, which lies under `root`, as `action` says;
+/// returns where a moved file went
+///
+/// A moved file keeps its directories below `root`, and gets a numbered
+/// name when one from an earlier import is in its place.
+pub fn apply_source_action(root: &Path, file: &Path, action: SourceFileAction) -> std::io::Result<Option<PathBuf>> {
+    match action {
+        SourceFileAction::Keep => Ok(None),
+        SourceFileAction::Delete => {
+            std::fs::remove_file(file)?;
+            Ok(None)
+        }
+        SourceFileAction::MoveToSubfolder => {
+            let relative = file.strip_prefix(root).map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, "file is outside the imported folder")
+            })?;
+            let dest = free_path(&root.join(IMPORTED_DIR).join(relative));
+            if let Some(parent) = dest.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::rename(file, &dest)?;
+            Ok(Some(dest))
+        }
+    }
+}
+
+/// `path`, or when that is taken the first of "name (2).ext", "name (3).ext"
+/// and so on that is free
+fn free_path(path: &Path) -> PathBuf {
+    if !path.exists() {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    (2..)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, extension)))
+        .find(|candidate| !candidate.exists())
+        .expect("some numbered name is free")
+}
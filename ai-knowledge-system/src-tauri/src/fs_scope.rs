@@ -0,0 +1,124 @@
+//! File access for the webview, kept inside the app's own directories
+//!
+//! The webview has no filesystem plugin; the few files it needs are read
+//! through commands that resolve the requested path here first. Path and
+//! root are both canonicalized, which resolves `..` and symlinks, before the
+//! prefix check, so neither traversal nor a symlink leading out of the root
+//! reaches anything else.
+
+use crate::error::{AppError, AppResult};
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+
+/// Resolve `requested`, absolute or relative to `root`, to a regular file
+/// inside `root`
+///
+/// Fails with PathOutsideScope for anything resolving elsewhere. A missing
+/// file is NotFound only if it would have been inside `root`, so the errors
+/// don't tell which files exist outside it.
+pub fn resolve_in_scope(root: &Path, requested: &Path) -> AppResult<PathBuf> {
+    let outside = || AppError::PathOutsideScope(requested.display().to_string());
+    let missing = || AppError::NotFound(format!("File {}", requested.display()));
+
+    let root = match root.canonicalize() {
+        Ok(root) => root,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Err(missing()),
+        Err(e) => return Err(e.into()),
+    };
+    let joined = root.join(requested);
+    let resolved = match joined.canonicalize() {
+        Ok(resolved) => resolved,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Err(if normalize(&joined).starts_with(&root) {
+                missing()
+            } else {
+                outside()
+            });
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    if !resolved.starts_with(&root) {
+        return Err(outside());
+    }
+    if !resolved.is_file() {
+        return Err(missing());
+    }
+    Ok(resolved)
+}
+
+/// `path` with `.` and `..` removed without touching the filesystem
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A root with `inside.txt`, next to `secret.txt` outside it
+    fn scoped_dir() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("inside.txt"), "inside").unwrap();
+        std::fs::write(dir.path().join("secret.txt"), "outside").unwrap();
+        (dir, root)
+    }
+
+    #[test]
+    fn files_inside_the_root_resolve() {
+        let (_dir, root) = scoped_dir();
+        let resolved = resolve_in_scope(&root, Path::new("inside.txt")).unwrap();
+        assert_eq!(resolved, root.canonicalize().unwrap().join("inside.txt"));
+        assert!(resolve_in_scope(&root, &root.join("inside.txt")).is_ok());
+    }
+
+    #[test]
+    fn parent_traversal_is_outside_the_scope() {
+        let (dir, root) = scoped_dir();
+        let escapes = [
+            PathBuf::from("../secret.txt"),
+            PathBuf::from("sub/../../secret.txt"),
+            dir.path().join("secret.txt"),
+        ];
+        for requested in escapes {
+            let error = resolve_in_scope(&root, &requested).unwrap_err();
+            assert!(matches!(error, AppError::PathOutsideScope(_)), "{}: {:?}", requested.display(), error);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_leading_out_of_the_root_are_outside_the_scope() {
+        let (dir, root) = scoped_dir();
+        std::os::unix::fs::symlink(dir.path().join("secret.txt"), root.join("link.txt")).unwrap();
+        std::os::unix::fs::symlink(dir.path(), root.join("up")).unwrap();
+
+        for requested in ["link.txt", "up/secret.txt"] {
+            let error = resolve_in_scope(&root, Path::new(requested)).unwrap_err();
+            assert!(matches!(error, AppError::PathOutsideScope(_)), "{}: {:?}", requested, error);
+        }
+    }
+
+    #[test]
+    fn missing_files_outside_the_root_do_not_show_they_are_missing() {
+        let (_dir, root) = scoped_dir();
+        let inside = resolve_in_scope(&root, Path::new("gone.txt")).unwrap_err();
+        assert!(matches!(inside, AppError::NotFound(_)), "{:?}", inside);
+        let outside = resolve_in_scope(&root, Path::new("../gone.txt")).unwrap_err();
+        assert!(matches!(outside, AppError::PathOutsideScope(_)), "{:?}", outside);
+        let directory = resolve_in_scope(&root, Path::new(".")).unwrap_err();
+        assert!(matches!(directory, AppError::NotFound(_)), "{:?}", directory);
+    }
+}
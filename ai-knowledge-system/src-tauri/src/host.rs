@@ -0,0 +1,66 @@
+//! What uploads and processing need from the app they run in
+//!
+//! In the app this is the Tauri handle. Anything else, such as the
+//! integration tests, runs the same code with a host of its own and no
+//! window.
+
+use crate::error::AppResult;
+use serde::Serialize;
+use std::path::PathBuf;
+use tauri::plugin::PermissionState;
+use tauri::{Emitter, Manager};
+use tauri_plugin_notification::NotificationExt;
+
+pub trait Host: Send + Sync {
+    /// Where the app keeps its data, and its documents unless settings say
+    /// otherwise
+    fn app_data_dir(&self) -> AppResult<PathBuf>;
+
+    /// Tell an open UI about something; like every event, best effort
+    fn emit_value(&self, event: &str, payload: serde_json::Value);
+
+    /// Whether an app window has focus; a minimized or hidden one hasn't
+    fn focused(&self) -> bool;
+
+    /// Show a notification through the OS
+    fn show_native(&self, title: &str, body: Option<&str>) -> Result<(), String>;
+}
+
+impl dyn Host + '_ {
+    pub fn emit<S: Serialize>(&self, event: &str, payload: S) {
+        match serde_json::to_value(payload) {
+            Ok(payload) => self.emit_value(event, payload),
+            Err(e) => eprintln!("Failed to serialize {} event: {}", event, e),
+        }
+    }
+}
+
+impl Host for tauri::AppHandle {
+    fn app_data_dir(&self) -> AppResult<PathBuf> {
+        Ok(self.path().app_data_dir()?)
+    }
+
+    fn emit_value(&self, event: &str, payload: serde_json::Value) {
+        let _ = Emitter::emit(self, event, payload);
+    }
+
+    fn focused(&self) -> bool {
+        self.webview_windows()
+            .values()
+            .any(|window| window.is_focused().unwrap_or(false))
+    }
+
+    fn show_native(&self, title: &str, body: Option<&str>) -> Result<(), String> {
+        let notification = self.notification();
+        match notification.permission_state() {
+            Ok(PermissionState::Granted) => {}
+            Ok(state) => return Err(format!("permission is {:?}", state)),
+            Err(e) => return Err(e.to_string()),
+        }
+        let mut builder = notification.builder().title(title);
+        if let Some(body) = body {
+            builder = builder.body(body);
+        }
+        builder.show().map_err(|e| e.to_string())
+    }
+}
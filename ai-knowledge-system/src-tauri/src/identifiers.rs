@@ -0,0 +1,244 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::sync::OnceLock;
+
+/// Kinds of bibliographic identifiers detected in document text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdentifierKind {
+    Doi,
+    Arxiv,
+    Isbn,
+}
+
+impl IdentifierKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IdentifierKind::Doi => "doi",
+            IdentifierKind::Arxiv => "arxiv",
+            IdentifierKind::Isbn => "isbn",
+        }
+    }
+}
+
+fn doi_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r#"(?i)\b10\.\d{4,9}/[^\s"<>]+"#).unwrap())
+}
+
+fn arxiv_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"(?i)(?:arxiv:\s*|arxiv\.org/(?:abs|pdf)/)((?:\d{4}\.\d{4,5})|(?:[a-z][a-z\-]*(?:\.[a-z]{2})?/\d{7}))(?:v\d+)?",
+        )
+        .unwrap()
+    })
+}
+
+fn isbn_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"(?i)\bISBN(?:-1[03])?:?\s*((?:[0-9][\- ]?){9}[0-9X]|(?:[0-9][\- ]?){12}[0-9])\b").unwrap()
+    })
+}
+
+/// Find DOIs, arXiv ids and ISBNs in text, normalized and deduplicated
+pub fn detect_identifiers(text: &str) -> Vec<(IdentifierKind, String)> {
+    let mut found = BTreeSet::new();
+
+    for m in doi_regex().find_iter(text) {
+        if let Some(doi) = normalize_doi(m.as_str()) {
+            found.insert((IdentifierKind::Doi, doi));
+        }
+    }
+    for caps in arxiv_regex().captures_iter(text) {
+        found.insert((IdentifierKind::Arxiv, caps[1].to_lowercase()));
+    }
+    for caps in isbn_regex().captures_iter(text) {
+        if let Some(isbn) = normalize_isbn(&caps[1]) {
+            found.insert((IdentifierKind::Isbn, isbn));
+        }
+    }
+
+    found.into_iter().collect()
+}
+
+/// Normalize user input for lookups the same way detection does
+pub fn normalize(kind: IdentifierKind, value: &str) -> Option<String> {
+    let value = value.trim();
+    match kind {
+        IdentifierKind::Doi => {
+            let value = value
+                .trim_start_matches("https://doi.org/")
+                .trim_start_matches("http://dx.doi.org/")
+                .trim_start_matches("doi:");
+            normalize_doi(value)
+        }
+        IdentifierKind::Arxiv => {
+            let value = value.to_lowercase();
+            let id = strip_arxiv_version(value.trim_start_matches("arxiv:").trim());
+            (!id.is_empty()).then(|| id.to_string())
+        }
+        IdentifierKind::Isbn => normalize_isbn(value),
+    }
+}
+
+/// Drop a trailing version suffix such as "v2" from an arXiv id
+fn strip_arxiv_version(id: &str) -> &str {
+    match id.rsplit_once('v') {
+        Some((base, version))
+            if !base.is_empty()
+                && !version.is_empty()
+                && version.chars().all(|c| c.is_ascii_digit()) =>
+        {
+            base
+        }
+        _ => id,
+    }
+}
+
+/// Lowercase a DOI and strip punctuation picked up from surrounding prose
+fn normalize_doi(raw: &str) -> Option<String> {
+    let mut doi = raw.trim_end_matches(['.', ',', ';', ':', '\'', '"']);
+
+    // Drop closing brackets that have no opening partner inside the DOI
+    while let Some(last) = doi.chars().last() {
+        let open = match last {
+            ')' => '(',
+            ']' => '[',
+            '}' => '{',
+            _ => break,
+        };
+        if doi.matches(open).count() >= doi.matches(last).count() {
+            break;
+        }
+        doi = doi[..doi.len() - 1].trim_end_matches(['.', ',', ';', ':']);
+    }
+
+    let doi = doi.to_lowercase();
+    (doi.len() > "10.0000/".len()).then_some(doi)
+}
+
+/// Reduce an ISBN to its ISBN-13 digits, rejecting bad checksums
+fn normalize_isbn(raw: &str) -> Option<String> {
+    let chars: Vec<char> = raw
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == 'X' || *c == 'x')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+
+    match chars.len() {
+        10 => {
+            let sum: u32 = chars
+                .iter()
+                .enumerate()
+                .map(|(i, c)| {
+                    let digit = if *c == 'X' { 10 } else { c.to_digit(10).unwrap_or(0) };
+                    (10 - i as u32) * digit
+                })
+                .sum();
+            if !sum.is_multiple_of(11) || chars[..9].contains(&'X') {
+                return None;
+            }
+            // Convert to ISBN-13 so both forms of the same book match
+            let body = format!("978{}", chars[..9].iter().collect::<String>());
+            Some(format!("{}{}", body, isbn13_check_digit(&body)))
+        }
+        13 => {
+            if chars.contains(&'X') {
+                return None;
+            }
+            let body: String = chars[..12].iter().collect();
+            let check = chars[12].to_digit(10)?;
+            (isbn13_check_digit(&body) == check).then(|| chars.iter().collect())
+        }
+        _ => None,
+    }
+}
+
+fn isbn13_check_digit(body: &str) -> u32 {
+    let sum: u32 = body
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .enumerate()
+        .map(|(i, d)| if i % 2 == 0 { d } else { d * 3 })
+        .sum();
+    (10 - sum % 10) % 10
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use IdentifierKind::{Arxiv, Doi, Isbn};
+
+    /// Identifiers in `text` as "kind:value"
+    fn found(text: &str) -> Vec<String> {
+        detect_identifiers(text)
+            .into_iter()
+            .map(|(kind, value)| format!("{}:{}", kind.as_str(), value))
+            .collect()
+    }
+
+    #[test]
+    fn dois_lose_surrounding_punctuation() {
+        assert_eq!(found("See 10.1000/XYZ123."), ["doi:10.1000/xyz123"]);
+        assert_eq!(found("(doi 10.1000/abc(2001)12)"), ["doi:10.1000/abc(2001)12"]);
+        assert_eq!(found("as shown [10.1000/abc]; later"), ["doi:10.1000/abc"]);
+        assert_eq!(found("\"10.1000/quoted\""), ["doi:10.1000/quoted"]);
+    }
+
+    #[test]
+    fn arxiv_ids_of_both_schemes_lose_their_version() {
+        assert_eq!(found("arXiv:1706.03762v5 [cs.CL]"), ["arxiv:1706.03762"]);
+        assert_eq!(found("https://arxiv.org/abs/2101.00001"), ["arxiv:2101.00001"]);
+        assert_eq!(found("arxiv: hep-th/9901001v2"), ["arxiv:hep-th/9901001"]);
+        assert_eq!(found("arXiv:math.GT/0309136"), ["arxiv:math.gt/0309136"]);
+    }
+
+    #[test]
+    fn isbns_become_isbn_13() {
+        assert_eq!(found("ISBN 0-306-40615-2"), ["isbn:9780306406157"]);
+        assert_eq!(found("ISBN-13: 978-0-306-40615-7"), ["isbn:9780306406157"]);
+        assert_eq!(found("ISBN-10: 080442957X"), ["isbn:9780804429573"]);
+    }
+
+    #[test]
+    fn the_same_identifier_is_found_once() {
+        let text = "ISBN 0-306-40615-2 and ISBN 978-0-306-40615-7, cite 10.1000/ABC and 10.1000/abc";
+        assert_eq!(found(text), ["doi:10.1000/abc", "isbn:9780306406157"]);
+    }
+
+    #[test]
+    fn invalid_identifiers_are_ignored() {
+        // Bad check digits
+        assert!(found("ISBN 0-306-40615-3").is_empty());
+        assert!(found("ISBN 978-0-306-40615-8").is_empty());
+        // X only ends an ISBN-10
+        assert!(found("ISBN 03X6406152").is_empty());
+        // Too short to be a DOI, and no arXiv prefix
+        assert!(found("10.1000/ and 1706.03762").is_empty());
+    }
+
+    #[test]
+    fn lookups_accept_what_people_paste() {
+        assert_eq!(normalize(Doi, " https://doi.org/10.1000/ABC ").as_deref(), Some("10.1000/abc"));
+        assert_eq!(normalize(Doi, "http://dx.doi.org/10.1000/abc").as_deref(), Some("10.1000/abc"));
+        assert_eq!(normalize(Doi, "doi:10.1000/abc.").as_deref(), Some("10.1000/abc"));
+        assert_eq!(normalize(Arxiv, "arXiv:1706.03762v7").as_deref(), Some("1706.03762"));
+        assert_eq!(normalize(Arxiv, "hep-th/9901001").as_deref(), Some("hep-th/9901001"));
+        assert_eq!(normalize(Isbn, "0 306 40615 2").as_deref(), Some("9780306406157"));
+
+        assert_eq!(normalize(Doi, "10.1/x"), None);
+        assert_eq!(normalize(Arxiv, "arxiv:"), None);
+        assert_eq!(normalize(Isbn, "12345"), None);
+    }
+
+    #[test]
+    fn kinds_serialize_as_stored() {
+        for kind in [Doi, Arxiv, Isbn] {
+            assert_eq!(serde_json::to_value(kind).unwrap(), kind.as_str());
+        }
+    }
+}
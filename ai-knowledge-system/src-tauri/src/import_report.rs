@@ -0,0 +1,235 @@
+//! Reports on folder import sessions: how each file ended up, with
+//! failures grouped by cause
+//!
+//! Error messages usually name the file or document they are about, so
+//! two files failing for the same reason rarely share a message. Paths and
+//! ids are replaced with placeholders before messages are grouped.
+
+use crate::display::Formatter;
+use crate::models::{
+    DocumentStatus, ImportFailureGroup, ImportFileOutcome, ImportFileReport, ImportReport, ImportSession,
+    UnqueuedFile,
+};
+use crate::services::import_session::ImportedDocument;
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Notification kind a finished import is delivered as
+pub const IMPORT_NOTIFICATION_KIND: &str = "import_finished";
+
+/// Absolute Unix paths, Windows drive and UNC paths, at the start of the
+/// message or after a space, quote or bracket so "I/O" isn't one
+fn path_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r#"(^|[\s"'(\[=])(?:[A-Za-z]:[\\/]|\\\\|/)[^\s"':;,)\]]*"#).unwrap())
+}
+
+fn uuid_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"(?i)\b[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}\b").unwrap()
+    })
+}
+
+/// An error message with paths and ids replaced, for grouping
+pub fn normalize_error(message: &str) -> String {
+    let without_ids = uuid_regex().replace_all(message.trim(), "<id>");
+    path_regex().replace_all(&without_ids, "${1}<path>").into_owned()
+}
+
+/// Put a session's documents and unqueued files together into a report
+pub fn build_report(
+    session: ImportSession,
+    unqueued: Vec<UnqueuedFile>,
+    documents: Vec<ImportedDocument>,
+) -> ImportReport {
+    let mut files = Vec::with_capacity(documents.len() + unqueued.len());
+    let mut total_bytes = 0;
+    for document in documents {
+        total_bytes += document.file_size_bytes.unwrap_or(0);
+        let outcome = match document.status {
+            DocumentStatus::Completed => ImportFileOutcome::Completed,
+            DocumentStatus::Failed | DocumentStatus::MissingFile => ImportFileOutcome::Failed,
+            DocumentStatus::Queued | DocumentStatus::Uploading | DocumentStatus::Processing => {
+                ImportFileOutcome::Unfinished
+            }
+        };
+        files.push(ImportFileReport {
+            name: document.file_name.unwrap_or_default(),
+            document_id: Some(document.id),
+            outcome,
+            error: document.processing_error.filter(|_| outcome == ImportFileOutcome::Failed),
+        });
+    }
+    for file in unqueued {
+        let outcome = match file.duplicate_of {
+            Some(_) => ImportFileOutcome::Duplicate,
+            None => ImportFileOutcome::NotQueued,
+        };
+        files.push(ImportFileReport {
+            name: file.path,
+            document_id: file.duplicate_of,
+            outcome,
+            error: file.error,
+        });
+    }
+
+    let count = |outcome| files.iter().filter(|f| f.outcome == outcome).count();
+    let failed = count(ImportFileOutcome::Failed) + count(ImportFileOutcome::NotQueued);
+    let (completed, duplicates_skipped, unfinished) = (
+        count(ImportFileOutcome::Completed),
+        count(ImportFileOutcome::Duplicate),
+        count(ImportFileOutcome::Unfinished),
+    );
+
+    let mut groups: HashMap<String, usize> = HashMap::new();
+    for file in &files {
+        if matches!(file.outcome, ImportFileOutcome::Failed | ImportFileOutcome::NotQueued) {
+            let error = file.error.as_deref().unwrap_or("unknown error");
+            *groups.entry(normalize_error(error)).or_default() += 1;
+        }
+    }
+    let mut failure_groups: Vec<ImportFailureGroup> = groups
+        .into_iter()
+        .map(|(error, count)| ImportFailureGroup { error, count })
+        .collect();
+    failure_groups.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.error.cmp(&b.error)));
+
+    let elapsed_seconds = session
+        .finished_at
+        .map(|finished| (finished - session.started_at).num_seconds().max(0));
+    ImportReport {
+        session,
+        files,
+        completed,
+        failed,
+        duplicates_skipped,
+        unfinished,
+        failure_groups,
+        total_bytes,
+        elapsed_seconds,
+    }
+}
+
+/// One paragraph for the notification a finished import leaves
+pub fn summary(report: &ImportReport, formatter: &Formatter) -> String {
+    let mut totals = formatter.size(report.total_bytes);
+    if let Some(seconds) = report.elapsed_seconds {
+        totals = format!("{} in {}", totals, formatter.duration(seconds));
+    }
+    let mut body = format!(
+        "{}: {} of {} files imported ({})",
+        report.session.source_path,
+        report.completed,
+        report.files.len(),
+        totals
+    );
+    if report.failed > 0 {
+        body.push_str(&format!(", {} failed", report.failed));
+        if let Some(top) = report.failure_groups.first() {
+            body.push_str(&format!(" (most often \"{}\")", top.error));
+        }
+    }
+    if report.duplicates_skipped > 0 {
+        body.push_str(&format!(", {} duplicates skipped", report.duplicates_skipped));
+    }
+    if report.unfinished > 0 {
+        body.push_str(&format!(", {} unfinished", report.unfinished));
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use uuid::Uuid;
+
+    #[test]
+    fn paths_of_every_platform_become_placeholders() {
+        assert_eq!(normalize_error("Failed to read /home/ada/a.pdf"), "Failed to read <path>");
+        assert_eq!(normalize_error("/tmp/x.pdf: not a PDF"), "<path>: not a PDF");
+        assert_eq!(normalize_error(r"Cannot open C:\Users\Ada\report.docx"), "Cannot open <path>");
+        assert_eq!(normalize_error(r"Cannot open d:/scans/1.png, retrying"), "Cannot open <path>, retrying");
+        assert_eq!(normalize_error(r"Share \\server\docs\q1.xlsx is offline"), "Share <path> is offline");
+        assert_eq!(normalize_error("File \"/srv/in/a.txt\" is empty"), "File \"<path>\" is empty");
+        assert_eq!(normalize_error("Bad entry (/srv/in/a.txt) skipped"), "Bad entry (<path>) skipped");
+        assert_eq!(normalize_error("path=/srv/in/a.txt"), "path=<path>");
+    }
+
+    #[test]
+    fn slashes_inside_words_are_not_paths() {
+        assert_eq!(normalize_error("I/O error: disk full"), "I/O error: disk full");
+        assert_eq!(normalize_error("Read 3/4 pages"), "Read 3/4 pages");
+        assert_eq!(normalize_error("  Timed out after 30s  "), "Timed out after 30s");
+    }
+
+    #[test]
+    fn ids_become_placeholders_in_any_case() {
+        let id = Uuid::new_v4();
+        let message = format!("Document {} clashes with {}", id, id.to_string().to_uppercase());
+        assert_eq!(normalize_error(&message), "Document <id> clashes with <id>");
+    }
+
+    fn session() -> ImportSession {
+        let started_at = Utc.with_ymd_and_hms(2024, 3, 11, 9, 0, 0).unwrap();
+        ImportSession {
+            id: Uuid::new_v4(),
+            source_path: "/imports/q1".to_string(),
+            total_files: 6,
+            started_at,
+            finished_at: Some(started_at + chrono::Duration::seconds(90)),
+            interrupted: false,
+        }
+    }
+
+    fn document(name: &str, status: DocumentStatus, error: Option<&str>) -> ImportedDocument {
+        ImportedDocument {
+            id: Uuid::new_v4(),
+            file_name: Some(name.to_string()),
+            status,
+            processing_error: error.map(str::to_string),
+            file_size_bytes: Some(1024 * 1024),
+        }
+    }
+
+    #[test]
+    fn failures_with_the_same_cause_are_grouped() {
+        let documents = vec![
+            document("a.pdf", DocumentStatus::Completed, None),
+            document("b.pdf", DocumentStatus::Failed, Some("Failed to load PDF: /lib/b.pdf")),
+            document("c.pdf", DocumentStatus::Failed, Some("Failed to load PDF: /lib/c.pdf")),
+            document("d.pdf", DocumentStatus::Processing, None),
+        ];
+        let unqueued = vec![
+            UnqueuedFile {
+                path: "/imports/q1/e.pdf".to_string(),
+                error: Some("Storage quota exceeded".to_string()),
+                duplicate_of: None,
+            },
+            UnqueuedFile {
+                path: "/imports/q1/f.pdf".to_string(),
+                error: None,
+                duplicate_of: Some(Uuid::new_v4()),
+            },
+        ];
+        let report = build_report(session(), unqueued, documents);
+
+        assert_eq!(
+            (report.completed, report.failed, report.duplicates_skipped, report.unfinished),
+            (1, 3, 1, 1)
+        );
+        let groups: Vec<(&str, usize)> = report.failure_groups.iter().map(|g| (g.error.as_str(), g.count)).collect();
+        assert_eq!(groups, [("Failed to load PDF: <path>", 2), ("Storage quota exceeded", 1)]);
+        assert_eq!(report.total_bytes, 4 * 1024 * 1024);
+        assert_eq!(report.elapsed_seconds, Some(90));
+
+        let summary = summary(&report, &Formatter::new("en", Utc::now()));
+        assert_eq!(
+            summary,
+            "/imports/q1: 1 of 6 files imported (4.0 MB in 1 min 30 s), 3 failed \
+             (most often \"Failed to load PDF: <path>\"), 1 duplicates skipped, 1 unfinished"
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! Uploads in flight, so a second attempt at the same file joins the first
+//!
+//! An upload is in flight from the moment it is accepted until its file is
+//! stored or the attempt fails. It is registered under its owner and
+//! canonical source path, and while its file is stored under its owner and
+//! content hash too. Each entry is held by an `IngestGuard`, which removes
+//! it however the upload ends, so a failed attempt never blocks the next.
+
+use crate::models::UploadFileResponse;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum IngestKey {
+    /// Owner and canonical source path
+    Path(Uuid, PathBuf),
+    /// Owner and SHA-256 of the file
+    Hash(Uuid, String),
+}
+
+/// An in-flight upload's response, once it was accepted
+type Outcome = Option<UploadFileResponse>;
+
+pub struct IngestRegistry {
+    entries: Mutex<HashMap<IngestKey, watch::Receiver<Outcome>>>,
+}
+
+/// Result of claiming a key
+pub enum Claim {
+    /// Nobody had it; it is held until the guard drops
+    Claimed(IngestGuard),
+    /// Another upload holds it; pass to `IngestRegistry::outcome`
+    InFlight(watch::Receiver<Outcome>),
+}
+
+/// An upload's hold on a key; dropping it removes the entry and wakes
+/// whoever waits on it
+pub struct IngestGuard {
+    registry: Arc<IngestRegistry>,
+    key: IngestKey,
+    sender: watch::Sender<Outcome>,
+}
+
+impl IngestRegistry {
+    pub fn new() -> Arc<Self> {
+        Arc::new(IngestRegistry {
+            entries: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn claim(self: &Arc<Self>, key: IngestKey) -> Claim {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(receiver) = entries.get(&key) {
+            return Claim::InFlight(receiver.clone());
+        }
+        let (sender, receiver) = watch::channel(None);
+        entries.insert(key.clone(), receiver);
+        Claim::Claimed(IngestGuard {
+            registry: Arc::clone(self),
+            key,
+            sender,
+        })
+    }
+
+    /// Wait for an in-flight upload to be accepted or to end; its response
+    /// if it was accepted, None if it failed before that
+    pub async fn outcome(mut first: watch::Receiver<Outcome>) -> Outcome {
+        match first.wait_for(Option::is_some).await {
+            Ok(outcome) => outcome.clone(),
+            Err(_) => None,
+        }
+    }
+}
+
+impl IngestGuard {
+    /// Hand the upload's response to attempts waiting on it, and to those
+    /// arriving while the file is still being stored
+    pub fn accepted(&self, response: &UploadFileResponse) {
+        self.sender.send_replace(Some(response.clone()));
+    }
+}
+
+impl Drop for IngestGuard {
+    fn drop(&mut self) {
+        self.registry.entries.lock().unwrap().remove(&self.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Document, DocumentStatus};
+
+    fn path(owner: Uuid) -> IngestKey {
+        IngestKey::Path(owner, PathBuf::from("/papers/attention.pdf"))
+    }
+
+    fn claimed(registry: &Arc<IngestRegistry>, key: IngestKey) -> IngestGuard {
+        match registry.claim(key) {
+            Claim::Claimed(guard) => guard,
+            Claim::InFlight(_) => panic!("expected the key to be free"),
+        }
+    }
+
+    fn in_flight(registry: &Arc<IngestRegistry>, key: IngestKey) -> watch::Receiver<Outcome> {
+        match registry.claim(key) {
+            Claim::InFlight(receiver) => receiver,
+            Claim::Claimed(_) => panic!("expected the key to be held"),
+        }
+    }
+
+    fn response(owner: Uuid) -> UploadFileResponse {
+        UploadFileResponse {
+            document: Document {
+                id: Uuid::new_v4(),
+                user_id: owner,
+                workspace_id: None,
+                title: "attention".to_string(),
+                content: None,
+                summary: None,
+                file_path: None,
+                file_name: Some("attention.pdf".to_string()),
+                file_size_bytes: Some(2048),
+                file_type: Some("PDF".to_string()),
+                mime_type: Some("application/pdf".to_string()),
+                status: DocumentStatus::Queued,
+                processing_error: None,
+                created_at: chrono::Utc::now(),
+                updated_at: chrono::Utc::now(),
+                deleted_at: None,
+                page_count: None,
+                parent_document_id: None,
+                is_pinned: false,
+                pinned_order: None,
+                external_file: false,
+                is_unread: true,
+            },
+            storage_used_percent: 1.5,
+            tiny_file: false,
+            restored: false,
+        }
+    }
+
+    #[test]
+    fn a_key_is_held_until_its_guard_drops() {
+        let registry = IngestRegistry::new();
+        let owner = Uuid::new_v4();
+        let guard = claimed(&registry, path(owner));
+        in_flight(&registry, path(owner));
+
+        // Other keys, and the same path of another owner, stay free
+        let _hash = claimed(&registry, IngestKey::Hash(owner, "ab12".to_string()));
+        let _other_owner = claimed(&registry, path(Uuid::new_v4()));
+
+        drop(guard);
+        let _again = claimed(&registry, path(owner));
+    }
+
+    #[tokio::test]
+    async fn waiting_attempts_get_the_accepted_response() {
+        let registry = IngestRegistry::new();
+        let owner = Uuid::new_v4();
+        let guard = claimed(&registry, path(owner));
+        let waiting = tokio::spawn(IngestRegistry::outcome(in_flight(&registry, path(owner))));
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+
+        let accepted = response(owner);
+        guard.accepted(&accepted);
+        let outcome = waiting.await.unwrap().expect("the upload was accepted");
+        assert_eq!(outcome.document.id, accepted.document.id);
+
+        // An attempt arriving while the file is still stored gets it at once
+        let late = IngestRegistry::outcome(in_flight(&registry, path(owner))).await;
+        assert_eq!(late.map(|r| r.document.id), Some(accepted.document.id));
+    }
+
+    #[tokio::test]
+    async fn waiting_attempts_wake_with_nothing_when_the_upload_fails() {
+        let registry = IngestRegistry::new();
+        let owner = Uuid::new_v4();
+        let guard = claimed(&registry, IngestKey::Hash(owner, "ab12".to_string()));
+        let waiting = tokio::spawn(IngestRegistry::outcome(in_flight(
+            &registry,
+            IngestKey::Hash(owner, "ab12".to_string()),
+        )));
+        tokio::task::yield_now().await;
+
+        drop(guard);
+        assert!(waiting.await.unwrap().is_none());
+        let _retry = claimed(&registry, IngestKey::Hash(owner, "ab12".to_string()));
+    }
+
+    #[test]
+    fn the_entry_is_removed_once_an_accepted_upload_is_stored() {
+        let registry = IngestRegistry::new();
+        let owner = Uuid::new_v4();
+        let guard = claimed(&registry, path(owner));
+        guard.accepted(&response(owner));
+        drop(guard);
+        assert!(registry.entries.lock().unwrap().is_empty());
+        let _next = claimed(&registry, path(owner));
+    }
+}
@@ -0,0 +1,513 @@
+//! Key fields of invoice-like documents: number, vendor, dates and total
+//!
+//! Rules are rows in `RULES`: a pattern around a `value` group, what kind
+//! of value it reads and how confident a match is. Patterns use `{amount}`,
+//! `{money}`, `{date}` and `{id}` for the shared value patterns, so a new
+//! label is one more row. Every match becomes a candidate; a stretch of
+//! text goes to the field whose rule is most confident about it, and each
+//! field keeps its most confident value with the others as alternates.
+
+use chrono::NaiveDate;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::sync::OnceLock;
+
+/// Only the start of long documents is searched
+const MAX_SCAN_CHARS: usize = 50_000;
+
+/// Alternates kept per field besides the best value
+const MAX_ALTERNATES: usize = 5;
+
+/// Confidence factor for values that could be read more than one way,
+/// e.g. 03/04/2024
+const AMBIGUOUS_FACTOR: f64 = 0.8;
+
+/// Longest vendor name taken, in chars
+const MAX_NAME_CHARS: usize = 120;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InvoiceField {
+    InvoiceNumber,
+    Vendor,
+    InvoiceDate,
+    DueDate,
+    Subtotal,
+    Tax,
+    Total,
+}
+
+/// A value read from the text
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FieldValue {
+    /// `amount` is a plain decimal, e.g. "1234.50", whatever separators the
+    /// document used
+    Amount { amount: String, currency: Option<String> },
+    Date { date: NaiveDate },
+    Text { text: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldCandidate {
+    pub value: FieldValue,
+    /// The text the value was read from
+    pub source: String,
+    /// 0 to 1
+    pub confidence: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtractedField {
+    pub field: InvoiceField,
+    pub best: FieldCandidate,
+    /// Other values found for the field, most confident first
+    pub alternates: Vec<FieldCandidate>,
+}
+
+/// How a rule's `value` group is read
+#[derive(Clone, Copy)]
+enum ValueKind {
+    Amount,
+    Date,
+    Identifier,
+    Name,
+}
+
+struct FieldRule {
+    field: InvoiceField,
+    /// Matched case-insensitively with `^`/`$` at line ends
+    pattern: &'static str,
+    value: ValueKind,
+    confidence: f64,
+}
+
+const RULES: &[FieldRule] = &[
+    FieldRule {
+        field: InvoiceField::InvoiceNumber,
+        pattern: r"\binvoice[ \t]*(?:no\b\.?|number\b|num\b\.?|#)[ \t]*[:#]?[ \t]*{id}",
+        value: ValueKind::Identifier,
+        confidence: 0.9,
+    },
+    FieldRule {
+        field: InvoiceField::InvoiceNumber,
+        pattern: r"\brechnungs(?:nummer|-?nr\b\.?)[ \t]*:?[ \t]*{id}",
+        value: ValueKind::Identifier,
+        confidence: 0.9,
+    },
+    FieldRule {
+        field: InvoiceField::InvoiceNumber,
+        pattern: r"\binv[ \t]*(?:no\b\.?|#)[ \t]*:?[ \t]*{id}",
+        value: ValueKind::Identifier,
+        confidence: 0.8,
+    },
+    FieldRule {
+        field: InvoiceField::InvoiceNumber,
+        pattern: r"\b(?:reference|ref\b\.?)[ \t]*(?:no\b\.?|#)?[ \t]*:[ \t]*{id}",
+        value: ValueKind::Identifier,
+        confidence: 0.5,
+    },
+    FieldRule {
+        field: InvoiceField::Vendor,
+        pattern: r"^[ \t]*(?:vendor|supplier|seller|from|billed[ \t]+by|issued[ \t]+by|company)[ \t]*:[ \t]*(?P<value>[^\n]+)",
+        value: ValueKind::Name,
+        confidence: 0.85,
+    },
+    // Invoices usually open with the sender's name
+    FieldRule {
+        field: InvoiceField::Vendor,
+        pattern: r"\A\s*(?P<value>[^\n]+)",
+        value: ValueKind::Name,
+        confidence: 0.3,
+    },
+    FieldRule {
+        field: InvoiceField::InvoiceDate,
+        pattern: r"\b(?:invoice[ \t]+date|date[ \t]+of[ \t]+issue|issue[ \t]+date|issued(?:[ \t]+on)?|billing[ \t]+date|rechnungsdatum)\b[ \t]*:?[ \t]*{date}",
+        value: ValueKind::Date,
+        confidence: 0.95,
+    },
+    FieldRule {
+        field: InvoiceField::DueDate,
+        pattern: r"\b(?:due[ \t]+date|payment[ \t]+due|due[ \t]+by|due[ \t]+on|pay[ \t]+by|f[äa]llig(?:[ \t]+am)?)\b[ \t]*:?[ \t]*{date}",
+        value: ValueKind::Date,
+        confidence: 0.9,
+    },
+    FieldRule {
+        field: InvoiceField::InvoiceDate,
+        pattern: r"\b(?:date|datum)\b[ \t]*:?[ \t]*{date}",
+        value: ValueKind::Date,
+        confidence: 0.7,
+    },
+    FieldRule {
+        field: InvoiceField::InvoiceDate,
+        pattern: r"{date}",
+        value: ValueKind::Date,
+        confidence: 0.2,
+    },
+    FieldRule {
+        field: InvoiceField::Total,
+        pattern: r"\b(?:grand[ \t]+total|total[ \t]+due|amount[ \t]+due|balance[ \t]+due|total[ \t]+amount|amount[ \t]+payable|total[ \t]+payable|gesamtbetrag)\b[^\n\d$€£¥₹]{0,20}?{amount}",
+        value: ValueKind::Amount,
+        confidence: 0.95,
+    },
+    FieldRule {
+        field: InvoiceField::Total,
+        pattern: r"\b(?:total|summe)\b[^\n\d$€£¥₹]{0,20}?{amount}",
+        value: ValueKind::Amount,
+        confidence: 0.8,
+    },
+    FieldRule {
+        field: InvoiceField::Total,
+        pattern: r"\bamount\b[^\n\d$€£¥₹]{0,20}?{amount}",
+        value: ValueKind::Amount,
+        confidence: 0.6,
+    },
+    // Subtotal and tax lines outrank the plain "total" rule so that
+    // "Sub total" and "Total VAT" aren't taken for the total
+    FieldRule {
+        field: InvoiceField::Subtotal,
+        pattern: r"\b(?:sub[ \t-]?total|zwischensumme|net[ \t]+amount)\b[^\n\d$€£¥₹]{0,20}?{amount}",
+        value: ValueKind::Amount,
+        confidence: 0.85,
+    },
+    FieldRule {
+        field: InvoiceField::Tax,
+        pattern: r"^[ \t]*(?:total[ \t]+)?(?:tax|vat|gst|mwst|ust)\b(?:[^\n\d$€£¥₹]{0,20}?\d{1,2}(?:[.,]\d+)?[ \t]*%)?[^\n\d$€£¥₹]{0,20}?{amount}",
+        value: ValueKind::Amount,
+        confidence: 0.85,
+    },
+    FieldRule {
+        field: InvoiceField::Total,
+        pattern: r"{money}",
+        value: ValueKind::Amount,
+        confidence: 0.2,
+    },
+];
+
+/// Currency symbols and the codes they stand for
+const CURRENCY_SYMBOLS: &[(&str, &str)] = &[("$", "USD"), ("€", "EUR"), ("£", "GBP"), ("¥", "JPY"), ("₹", "INR")];
+
+const CURRENCY_CODES: &[&str] = &["USD", "EUR", "GBP", "JPY", "INR", "CHF", "CAD", "AUD"];
+
+const MONTHS: &[&str] = &[
+    "january", "february", "march", "april", "may", "june", "july", "august", "september", "october", "november",
+    "december",
+];
+
+/// A number with optional grouping and up to two decimals, e.g. 1,234.50,
+/// 1.234,50 or 1 234
+const NUMBER: &str = r"-?(?:\d{1,3}(?:[,.' ]\d{3})+|\d+)(?:[.,]\d{1,2})?";
+
+const CURRENCY: &str = r"(?:[$€£¥₹]|\b(?:USD|EUR|GBP|JPY|INR|CHF|CAD|AUD)\b)";
+
+const MONTH_NAME: &str =
+    r"(?:jan(?:uary)?|feb(?:ruary)?|mar(?:ch)?|apr(?:il)?|may|june?|july?|aug(?:ust)?|sep(?:t(?:ember)?)?|oct(?:ober)?|nov(?:ember)?|dec(?:ember)?)";
+
+fn expand(pattern: &str) -> String {
+    let amount = format!(r"(?P<value>{c}?[ \t]?{n}(?:[ \t]?{c})?)", c = CURRENCY, n = NUMBER);
+    let money = format!(r"(?P<value>{c}[ \t]?{n}|{n}[ \t]?{c})", c = CURRENCY, n = NUMBER);
+    let date = format!(
+        r"(?P<value>\b(?:\d{{4}}-\d{{1,2}}-\d{{1,2}}|\d{{1,2}}[./-]\d{{1,2}}[./-](?:\d{{4}}|\d{{2}})|\d{{1,2}}(?:st|nd|rd|th)?\.?[ \t]+{m}\.?,?[ \t]+\d{{4}}|{m}\.?[ \t]+\d{{1,2}}(?:st|nd|rd|th)?,?[ \t]+\d{{4}})\b)",
+        m = MONTH_NAME
+    );
+    let id = r"(?P<value>[A-Z0-9][A-Z0-9\-/.]*\d[A-Z0-9\-/]*)";
+    let pattern = pattern
+        .replace("{amount}", &amount)
+        .replace("{money}", &money)
+        .replace("{date}", &date)
+        .replace("{id}", id);
+    format!("(?im){}", pattern)
+}
+
+fn compiled_rules() -> &'static [(&'static FieldRule, Regex)] {
+    static RULES_RE: OnceLock<Vec<(&'static FieldRule, Regex)>> = OnceLock::new();
+    RULES_RE.get_or_init(|| RULES.iter().map(|rule| (rule, Regex::new(&expand(rule.pattern)).unwrap())).collect())
+}
+
+struct Found {
+    field: InvoiceField,
+    span: Range<usize>,
+    candidate: FieldCandidate,
+}
+
+/// Find invoice fields in text; fields with no value are left out
+pub fn extract_fields(text: &str) -> Vec<ExtractedField> {
+    let text = match text.char_indices().nth(MAX_SCAN_CHARS) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    };
+
+    let mut found = Vec::new();
+    for (rule, regex) in compiled_rules() {
+        for caps in regex.captures_iter(text) {
+            let Some(m) = caps.name("value") else {
+                continue;
+            };
+            let Some((value, certain)) = read_value(rule.value, m.as_str()) else {
+                continue;
+            };
+            let confidence = if certain { rule.confidence } else { rule.confidence * AMBIGUOUS_FACTOR };
+            found.push(Found {
+                field: rule.field,
+                span: m.range(),
+                candidate: FieldCandidate {
+                    value,
+                    source: m.as_str().trim().to_string(),
+                    confidence: (confidence * 100.0).round() / 100.0,
+                },
+            });
+        }
+    }
+    // Most confident first; ties go to whatever comes first in the text
+    found.sort_by(|a, b| {
+        b.candidate
+            .confidence
+            .total_cmp(&a.candidate.confidence)
+            .then(a.span.start.cmp(&b.span.start))
+    });
+
+    let mut claimed: Vec<(InvoiceField, Range<usize>)> = Vec::new();
+    let mut fields: Vec<ExtractedField> = Vec::new();
+    for found in found {
+        // Text read by a more confident rule for another field isn't reused,
+        // e.g. the date after "Due date:" isn't also the invoice date
+        let taken = claimed
+            .iter()
+            .any(|(field, span)| *field != found.field && span.start < found.span.end && found.span.start < span.end);
+        if taken {
+            continue;
+        }
+        claimed.push((found.field, found.span));
+
+        match fields.iter_mut().find(|f| f.field == found.field) {
+            Some(field) => {
+                let known = field.best.value == found.candidate.value
+                    || field.alternates.iter().any(|a| a.value == found.candidate.value);
+                if !known && field.alternates.len() < MAX_ALTERNATES {
+                    field.alternates.push(found.candidate);
+                }
+            }
+            None => fields.push(ExtractedField {
+                field: found.field,
+                best: found.candidate,
+                alternates: Vec::new(),
+            }),
+        }
+    }
+    fields.sort_by_key(|f| f.field);
+    fields
+}
+
+/// Read a matched value; the flag is false when it could be read another way
+fn read_value(kind: ValueKind, raw: &str) -> Option<(FieldValue, bool)> {
+    match kind {
+        ValueKind::Amount => read_amount(raw).map(|value| (value, true)),
+        ValueKind::Date => read_date(raw).map(|(date, certain)| (FieldValue::Date { date }, certain)),
+        ValueKind::Identifier => {
+            let id = raw.trim().trim_end_matches(['.', '-', '/']);
+            (!id.is_empty()).then(|| (FieldValue::Text { text: id.to_string() }, true))
+        }
+        ValueKind::Name => read_name(raw).map(|text| (FieldValue::Text { text }, true)),
+    }
+}
+
+fn read_amount(raw: &str) -> Option<FieldValue> {
+    let upper = raw.to_uppercase();
+    let currency = CURRENCY_SYMBOLS
+        .iter()
+        .find(|(symbol, _)| raw.contains(symbol))
+        .map(|(_, code)| *code)
+        .or_else(|| CURRENCY_CODES.iter().copied().find(|code| upper.contains(code)))
+        .map(str::to_string);
+
+    // Spaces and apostrophes only ever group digits
+    let number: String = raw.chars().filter(|c| c.is_ascii_digit() || matches!(c, '.' | ',' | '-')).collect();
+    let negative = number.starts_with('-');
+    let number = number.trim_start_matches('-');
+    // The last separator is the decimal point when one or two digits follow
+    let (whole, fraction) = match number.rfind(['.', ',']) {
+        Some(index) if (2..=3).contains(&(number.len() - index)) => (&number[..index], &number[index + 1..]),
+        _ => (number, ""),
+    };
+    let whole: String = whole.chars().filter(char::is_ascii_digit).collect();
+    if whole.is_empty() {
+        return None;
+    }
+
+    let mut amount = if negative { format!("-{}", whole) } else { whole };
+    if !fraction.is_empty() {
+        amount.push('.');
+        amount.push_str(fraction);
+        if fraction.len() == 1 {
+            amount.push('0');
+        }
+    }
+    Some(FieldValue::Amount { amount, currency })
+}
+
+/// Read a date; numeric dates where day and month could be swapped are
+/// read day first, as most of the world writes them, and flagged uncertain
+fn read_date(raw: &str) -> Option<(NaiveDate, bool)> {
+    let lower = raw.to_lowercase();
+    let numbers: Vec<&str> = lower.split(|c: char| !c.is_ascii_digit()).filter(|s| !s.is_empty()).collect();
+    let month_name = lower
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| word.len() >= 3)
+        .find_map(|word| MONTHS.iter().position(|month| month.starts_with(word)));
+
+    let number = |index: usize| numbers.get(index).and_then(|n| n.parse::<u32>().ok());
+    let full_year = |year: u32, digits: usize| if digits == 2 { 2000 + year } else { year };
+
+    let (year, month, day, certain) = match month_name {
+        Some(month) => (number(1)?, month as u32 + 1, number(0)?, true),
+        None if numbers.first()?.len() == 4 => (number(0)?, number(1)?, number(2)?, true),
+        None => {
+            let (a, b) = (number(0)?, number(1)?);
+            let year = full_year(number(2)?, numbers.get(2)?.len());
+            if a > 12 {
+                (year, b, a, true)
+            } else if b > 12 {
+                (year, a, b, true)
+            } else {
+                (year, b, a, a == b)
+            }
+        }
+    };
+    NaiveDate::from_ymd_opt(year as i32, month, day).map(|date| (date, certain))
+}
+
+/// A vendor name: one line, with letters and not itself a label
+fn read_name(raw: &str) -> Option<String> {
+    let name = raw.split_whitespace().collect::<Vec<_>>().join(" ");
+    let name = name.trim_end_matches([',', ';', ':']);
+    let usable = !name.is_empty()
+        && name.chars().count() <= MAX_NAME_CHARS
+        && name.chars().any(char::is_alphabetic)
+        && !name.to_lowercase().contains("invoice");
+    usable.then(|| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const US_INVOICE: &str = "Acme Hosting LLC
+123 Market Street, Springfield
+
+INVOICE
+Invoice No: INV-2024-0042
+Invoice Date: March 5, 2024
+Due Date: 04/04/2024
+
+Description                 Amount
+Cloud server (March)        $120.00
+Backups                     $30.00
+
+Subtotal: $150.00
+Tax (8.5%): $12.75
+Total Due: $162.75
+";
+
+    const GERMAN_INVOICE: &str = "Müller & Söhne GmbH
+Rechnung
+Rechnungsnummer: RE-7781
+Rechnungsdatum: 12.02.2024
+Fällig am: 26.02.2024
+
+Zwischensumme 1.000,00 €
+MwSt 19% 190,00 €
+Gesamtbetrag 1.190,00 €
+";
+
+    const RECEIPT: &str = "NORTHWIND TRADERS
+inv # 55012   date 2024-07-01
+2 x Coffee beans EUR 24,90
+Total EUR 24,90
+Amount paid EUR 30,00
+";
+
+    fn field(fields: &[ExtractedField], field: InvoiceField) -> &ExtractedField {
+        fields
+            .iter()
+            .find(|f| f.field == field)
+            .unwrap_or_else(|| panic!("No {:?} in {:#?}", field, fields))
+    }
+
+    fn text(value: &str) -> FieldValue {
+        FieldValue::Text { text: value.to_string() }
+    }
+
+    fn amount(amount: &str, currency: &str) -> FieldValue {
+        FieldValue::Amount {
+            amount: amount.to_string(),
+            currency: Some(currency.to_string()),
+        }
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> FieldValue {
+        FieldValue::Date {
+            date: NaiveDate::from_ymd_opt(y, m, d).unwrap(),
+        }
+    }
+
+    #[test]
+    fn labeled_us_invoice() {
+        let fields = extract_fields(US_INVOICE);
+
+        assert_eq!(field(&fields, InvoiceField::InvoiceNumber).best.value, text("INV-2024-0042"));
+        assert_eq!(field(&fields, InvoiceField::Vendor).best.value, text("Acme Hosting LLC"));
+        assert_eq!(field(&fields, InvoiceField::InvoiceDate).best.value, date(2024, 3, 5));
+        // 04/04 reads the same either way round, so it is certain
+        let due = &field(&fields, InvoiceField::DueDate).best;
+        assert_eq!((&due.value, due.confidence), (&date(2024, 4, 4), 0.9));
+        assert_eq!(field(&fields, InvoiceField::Subtotal).best.value, amount("150.00", "USD"));
+        assert_eq!(field(&fields, InvoiceField::Tax).best.value, amount("12.75", "USD"));
+
+        // The labeled total wins; bare line amounts are kept as alternates
+        let total = field(&fields, InvoiceField::Total);
+        assert_eq!((&total.best.value, total.best.confidence), (&amount("162.75", "USD"), 0.95));
+        let alternates: Vec<&FieldValue> = total.alternates.iter().map(|a| &a.value).collect();
+        assert_eq!(alternates, [&amount("120.00", "USD"), &amount("30.00", "USD")]);
+        assert!(total.alternates.iter().all(|a| a.confidence < total.best.confidence));
+    }
+
+    #[test]
+    fn german_invoice_with_decimal_commas() {
+        let fields = extract_fields(GERMAN_INVOICE);
+
+        assert_eq!(field(&fields, InvoiceField::InvoiceNumber).best.value, text("RE-7781"));
+        assert_eq!(field(&fields, InvoiceField::Vendor).best.value, text("Müller & Söhne GmbH"));
+        // 12.02.2024 could be December 2nd, so it is read day first and less sure
+        let issued = &field(&fields, InvoiceField::InvoiceDate).best;
+        assert_eq!(issued.value, date(2024, 2, 12));
+        assert!(issued.confidence < 0.95);
+        assert_eq!(field(&fields, InvoiceField::DueDate).best.value, date(2024, 2, 26));
+        assert_eq!(field(&fields, InvoiceField::Subtotal).best.value, amount("1000.00", "EUR"));
+        // The rate isn't taken for the tax amount
+        assert_eq!(field(&fields, InvoiceField::Tax).best.value, amount("190.00", "EUR"));
+        assert_eq!(field(&fields, InvoiceField::Total).best.value, amount("1190.00", "EUR"));
+    }
+
+    #[test]
+    fn terse_receipt_with_currency_codes() {
+        let fields = extract_fields(RECEIPT);
+
+        assert_eq!(field(&fields, InvoiceField::InvoiceNumber).best.value, text("55012"));
+        assert_eq!(field(&fields, InvoiceField::Vendor).best.value, text("NORTHWIND TRADERS"));
+        assert_eq!(field(&fields, InvoiceField::InvoiceDate).best.value, date(2024, 7, 1));
+        let total = field(&fields, InvoiceField::Total);
+        assert_eq!(total.best.value, amount("24.90", "EUR"));
+        assert_eq!(total.alternates.len(), 1);
+        assert_eq!(total.alternates[0].value, amount("30.00", "EUR"));
+        assert!(fields.iter().all(|f| f.field != InvoiceField::DueDate));
+    }
+
+    #[test]
+    fn text_without_invoice_fields_yields_little() {
+        let fields = extract_fields("Meeting notes\n\nWe agreed to review the budget next week.");
+        let found: Vec<InvoiceField> = fields.iter().map(|f| f.field).collect();
+        assert_eq!(found, [InvoiceField::Vendor]);
+        assert!(fields[0].best.confidence < 0.5);
+    }
+}
@@ -0,0 +1,252 @@
+use crate::stopwords;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Most tag suggestions stored per document
+pub const MAX_TAG_SUGGESTIONS: usize = 5;
+
+/// Version of `term_counts`; bump it when counts would come out
+/// differently, so `rebuild_derived_data` redoes older ones
+pub const TERM_COUNT_VERSION: i32 = 1;
+
+/// Longest phrase (in words) considered as a tag candidate
+const MAX_PHRASE_WORDS: usize = 3;
+
+/// Only the start of very long documents is scanned
+const MAX_SCAN_CHARS: usize = 200_000;
+
+/// Longest word counted as a term; longer ones are usually encoded data
+const MAX_TERM_CHARS: usize = 64;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagSuggestion {
+    pub name: String,
+    pub score: f64,
+    /// Whether the user already has a tag with this name
+    pub existing: bool,
+}
+
+/// Guess the language of a text by counting stopword hits
+///
+/// Returns None when no list matches a meaningful share of the words.
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let words: Vec<String> = tokenize(text).take(2_000).collect();
+    if words.is_empty() {
+        return None;
+    }
+
+    let (code, hits) = stopwords::LANGUAGES
+        .iter()
+        .map(|code| {
+            let list: HashSet<&str> = stopwords::for_language(code)
+                .unwrap_or_default()
+                .iter()
+                .copied()
+                .collect();
+            let hits = words.iter().filter(|w| list.contains(w.as_str())).count();
+            (*code, hits)
+        })
+        .max_by_key(|(_, hits)| *hits)?;
+
+    (hits * 20 >= words.len()).then_some(code)
+}
+
+/// Lowercased word tokens
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric() && c != '-' && c != '\'')
+        .map(|w| w.trim_matches(|c: char| c == '-' || c == '\''))
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+}
+
+/// RAKE-style keyword extraction
+///
+/// Candidate phrases are runs of non-stopwords between stopwords and
+/// punctuation. Each word scores degree / frequency and a phrase scores the
+/// sum of its words. Numbers-only and very short tokens never form phrases.
+pub fn extract_keywords(text: &str, language: Option<&str>, limit: usize) -> Vec<(String, f64)> {
+    let stop = stopword_set(language);
+
+    let scan = match text.char_indices().nth(MAX_SCAN_CHARS) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    };
+
+    let mut phrases: Vec<Vec<String>> = Vec::new();
+    for fragment in scan.split(['.', ',', ';', ':', '!', '?', '(', ')', '\n']) {
+        let mut current: Vec<String> = Vec::new();
+        for word in tokenize(fragment) {
+            if is_candidate_word(&word, &stop) {
+                current.push(word);
+            } else if !current.is_empty() {
+                phrases.push(std::mem::take(&mut current));
+            }
+        }
+        if !current.is_empty() {
+            phrases.push(current);
+        }
+    }
+
+    let mut frequency: HashMap<&str, f64> = HashMap::new();
+    let mut degree: HashMap<&str, f64> = HashMap::new();
+    for phrase in phrases.iter().filter(|p| p.len() <= MAX_PHRASE_WORDS) {
+        for word in phrase {
+            *frequency.entry(word.as_str()).or_default() += 1.0;
+            *degree.entry(word.as_str()).or_default() += phrase.len() as f64;
+        }
+    }
+
+    let mut scores: HashMap<String, f64> = HashMap::new();
+    for phrase in phrases.iter().filter(|p| p.len() <= MAX_PHRASE_WORDS) {
+        let score: f64 = phrase
+            .iter()
+            .map(|w| degree[w.as_str()] / frequency[w.as_str()])
+            .sum();
+        // Repeated phrases accumulate so recurring topics outrank one-offs
+        *scores.entry(phrase.join(" ")).or_default() += score;
+    }
+
+    let mut ranked: Vec<(String, f64)> = scores.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranked.truncate(limit);
+    ranked
+}
+
+/// How often each term occurs in a text, for library statistics
+///
+/// Unlike keyword extraction the whole text is counted. Terms are single
+/// words; stopwords of the detected language, numbers and words of one or
+/// two letters are left out.
+pub fn term_counts(text: &str) -> Vec<(String, i32)> {
+    let stop = stopword_set(detect_language(text));
+    let mut counts: HashMap<String, i32> = HashMap::new();
+    for word in tokenize(text) {
+        if word.chars().count() <= MAX_TERM_CHARS && is_candidate_word(&word, &stop) {
+            let count = counts.entry(word).or_default();
+            *count = count.saturating_add(1);
+        }
+    }
+    counts.into_iter().collect()
+}
+
+/// Stopwords for a language, English when it is unknown or has no list
+fn stopword_set(language: Option<&str>) -> HashSet<&'static str> {
+    stopwords::for_language(language.unwrap_or("en"))
+        .unwrap_or(stopwords::ENGLISH)
+        .iter()
+        .copied()
+        .collect()
+}
+
+fn is_candidate_word(word: &str, stop: &HashSet<&str>) -> bool {
+    word.chars().count() > 2
+        && !word.chars().all(|c| c.is_numeric() || c == '-')
+        && !stop.contains(word)
+}
+
+/// Rank tag suggestions, preferring keywords that match existing tags
+pub fn suggest_tags(text: &str, existing_tags: &[String]) -> Vec<TagSuggestion> {
+    let language = detect_language(text);
+    let keywords = extract_keywords(text, language, 50);
+    let existing: HashMap<String, &String> = existing_tags
+        .iter()
+        .map(|t| (t.to_lowercase(), t))
+        .collect();
+
+    let mut suggestions: Vec<TagSuggestion> = keywords
+        .into_iter()
+        .map(|(keyword, score)| match existing.get(&keyword) {
+            // Existing tags keep their original spelling and get a boost
+            Some(tag) => TagSuggestion {
+                name: (*tag).clone(),
+                score: score * 2.0,
+                existing: true,
+            },
+            None => TagSuggestion {
+                name: keyword,
+                score,
+                existing: false,
+            },
+        })
+        .collect();
+
+    suggestions.sort_by(|a, b| b.score.total_cmp(&a.score));
+    suggestions.truncate(MAX_TAG_SUGGESTIONS);
+    suggestions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keywords(text: &str, language: Option<&str>) -> Vec<String> {
+        extract_keywords(text, language, 10).into_iter().map(|(phrase, _)| phrase).collect()
+    }
+
+    #[test]
+    fn languages_are_told_apart_by_their_stopwords() {
+        assert_eq!(detect_language("The cat sat on the mat and looked at the dog."), Some("en"));
+        assert_eq!(detect_language("Der Hund und die Katze sind nicht im Haus."), Some("de"));
+        assert_eq!(detect_language("Le chat est sur la table et il dort."), Some("fr"));
+        assert_eq!(detect_language("Quantum chromodynamics lattice simulations"), None);
+        assert_eq!(detect_language(""), None);
+    }
+
+    #[test]
+    fn tokens_keep_inner_hyphens_and_apostrophes() {
+        let tokens: Vec<String> = tokenize("Don't RE-USE the X-ray's -- 'quoted' data_set").collect();
+        assert_eq!(tokens, ["don't", "re-use", "the", "x-ray's", "quoted", "data", "set"]);
+    }
+
+    #[test]
+    fn phrases_break_at_stopwords_and_punctuation() {
+        let found = keywords("Neural networks and decision trees, gradient boosting", None);
+        assert_eq!(found.len(), 3);
+        for phrase in ["neural networks", "decision trees", "gradient boosting"] {
+            assert!(found.contains(&phrase.to_string()), "{:?}", found);
+        }
+    }
+
+    #[test]
+    fn stopwords_numbers_and_short_words_never_form_phrases() {
+        let found = keywords("It is 2024 and we did it in 12-34 ms on an AI rig.", None);
+        assert_eq!(found, ["rig"]);
+        // The language picks the stopword list
+        assert_eq!(keywords("Ergebnisse und Methoden", Some("de")), ["ergebnisse", "methoden"]);
+        assert_eq!(keywords("Ergebnisse und Methoden", None), ["ergebnisse und methoden"]);
+    }
+
+    #[test]
+    fn recurring_and_longer_phrases_rank_first() {
+        let text = "Vector search is fast. Vector search is cheap. Caching helps. \
+                    We tried approximate nearest neighbour indexes.";
+        let ranked = extract_keywords(text, Some("en"), 10);
+        let phrases: Vec<&str> = ranked.iter().map(|(phrase, _)| phrase.as_str()).collect();
+        assert_eq!(phrases[0], "vector search");
+        assert_eq!(phrases[1], "caching helps");
+        // Runs longer than three words aren't candidates
+        assert!(!phrases.iter().any(|p| p.contains("neighbour")), "{:?}", phrases);
+        assert!(ranked.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+        assert_eq!(extract_keywords(text, Some("en"), 1).len(), 1);
+    }
+
+    #[test]
+    fn term_counts_skip_stopwords_numbers_and_encoded_data() {
+        let blob = "a".repeat(MAX_TERM_CHARS + 1);
+        let text = format!("The budget, the budget review and 2024 budget of it. {}", blob);
+        let mut counts = term_counts(&text);
+        counts.sort();
+        assert_eq!(counts, [("budget".to_string(), 3), ("review".to_string(), 1)]);
+    }
+
+    #[test]
+    fn existing_tags_keep_their_spelling_and_rank_higher() {
+        let text = "Kubernetes is great. Observability is key.";
+        let suggestions = suggest_tags(text, &["Observability".to_string()]);
+        assert_eq!(suggestions[0].name, "Observability");
+        assert!(suggestions[0].existing);
+        assert!(suggestions[1..].iter().all(|s| !s.existing));
+        assert_eq!(suggestions[0].score, 2.0 * suggestions[1].score);
+        assert!(suggestions.len() <= MAX_TAG_SUGGESTIONS);
+    }
+}
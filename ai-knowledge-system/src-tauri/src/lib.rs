@@ -1,21 +1,241 @@
-mod models;
-mod db;
-mod services;
+pub mod models;
+pub mod db;
+pub mod services;
 mod file_utils;
 mod pdf_processor;
+mod pdf_layout;
+mod pdf_repair;
+mod export;
+pub mod error;
+pub mod settings;
+mod session;
+pub mod processing;
+mod storage;
+mod identifiers;
+mod keywords;
+mod stopwords;
+mod text_search;
+mod consistency;
+mod workspace_package;
+mod backup;
+mod redaction;
+mod file_store;
+pub mod quick_open;
+mod cleanup;
+mod operations;
+mod query_parser;
+mod chunking;
+mod text_cleanup;
+mod collation;
+mod text_diff;
+mod digest;
+mod upload_queue;
+mod ingest_registry;
+mod summarizer;
+mod derived;
+mod fs_scope;
+mod folder_import;
+mod tokens;
+mod date_buckets;
+mod reading_position;
+mod display;
+mod invoice_fields;
+mod library_config;
+mod import_report;
+mod field_selection;
+mod notification_sink;
+mod local_api;
+mod bulk_rename;
+mod eval;
+mod virus_scan;
+mod text_compression;
+mod uuid_field;
+pub mod host;
+pub mod cli;
+#[cfg(test)]
+mod test_support;
+#[cfg(test)]
+mod tests;
 
-use tauri::Manager;
+use tauri::{Emitter, Manager};
 use tauri::State;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use tokio::sync::{Mutex, RwLock};
 use std::path::PathBuf;
 
-use models::{Document, CreateDocumentDto, UploadFileRequest, UploadFileResponse, DocumentStatus};
-use services::DocumentService;
+use db::InitStatus;
+use error::{AppError, AppResult};
+use models::{
+    Document, CreateDocumentDto, DocumentDetails, DocumentStatus, CreateDocumentRequest, UploadFileRequest, UploadFileResponse, StorageMode,
+    ExtractPagesRequest, ConsistencyReport, DanglingDocument, RepairOptions, RepairReport, StorageCorrection, StorageMigrationReport,
+    StorageStatus, User, NoteAttachResult, Tag, UserChangedEvent, ContentSlice, InDocumentSearchResult,
+    PackageExportSummary, WorkspaceImportReport, ProcessingHistory, BackupStatus,
+    CreateRedactionRuleRequest, RedactionMatchCount, RedactionRule, EncryptionProgress, EncryptionReport,
+    EncryptionStatus, MigrationFailure, QuickOpenMatch, StorageLevel, StorageHealth, SystemHealth, CleanupSuggestions, OperationInfo, PdfLayout,
+    DocumentWithOwner, Notification, MarkdownFidelity, EmbeddingEstimate, DocumentSort, ProcessingOptions,
+    DocumentChange, TermCount, TermTrendPoint, TrendBucket, DocumentDiff, Digest, PdfForm,
+    CsvColumn, SearchExportReport, DocumentStatusEntry, ChangedDocuments, DocumentSummary,
+    DocumentSearchResult, SearchPageMatch, DerivedTarget, RebuildProgress, RebuildReport, RebuildTargetReport,
+    ImportFolderRequest, FolderImportReport, FolderImportFailure, ExportSnapshot, ExportMatch, ExportVerification,
+    DocumentGrouping, DocumentListEntry, BucketCount, RedetectReport, FormatRequest, ContentCompressionReport, ReadingPosition, Anchoring,
+    TagDocumentCount, WorkspaceDocumentCount, ChangeFeed, Workspace, WorkspaceTemplate, WorkspaceTemplateInput,
+    MergeStrategy, LibraryConfigSummary, LibraryConfigImportReport, ReadingList, FolderImportDuplicate, UnqueuedFile,
+    ImportReport, UnsupportedProcessingReport, SupportedTypes, CatalogFormat, CatalogSort, CatalogReport,
+    FocusSession, FocusSessionReport, ShareToken, CreatedShareLink, RenameRule, BulkRenameResult,
+    ImportMethod, ProvenanceInfo, TagTreeEntry, CreateTagRequest, UpdateTagRequest, TrashedMatchAction, UploadOutcome,
+    SourceFileAction,
+};
+use services::notification::NewNotification;
+use services::user::StorageUsage;
+use services::{
+    ActivityLogger, ChangeFeedService, DocumentChanges, DocumentService, ExportSnapshotService, FocusSessionService, ImportSessionService, NotificationService, ProcessingRunService, RedactionRuleService, ShareTokenService, StorageMigrationService, TagService, UserService, WorkspaceService,
+};
+use file_store::{Keyring, LocalCopy};
+use processing::{ProcessingContext, ProcessingRegistry};
+use operations::{OperationHandle, OperationRegistry};
+use quick_open::QuickOpenIndex;
+use notification_sink::NotificationSink;
+use host::Host;
+use session::Session;
+use upload_queue::UploadQueue;
+use ingest_registry::{Claim, IngestGuard, IngestKey, IngestRegistry};
+use summarizer::FileKind;
+use field_selection::{FieldSelection, Selected};
+use settings::{AppSettings, SettingsStore, SettingsSubscriber, SettingsUpdate};
 
 // Application state
+#[derive(Clone)]
 pub struct AppState {
     pub document_service: Arc<Mutex<DocumentService>>,
+    pub user_service: Arc<Mutex<UserService>>,
+    pub tag_service: Arc<Mutex<TagService>>,
+    pub storage_migration_service: Arc<Mutex<StorageMigrationService>>,
+    pub activity_logger: Arc<Mutex<ActivityLogger>>,
+    pub workspace_service: Arc<Mutex<WorkspaceService>>,
+    pub processing_run_service: Arc<Mutex<ProcessingRunService>>,
+    pub redaction_rule_service: Arc<Mutex<RedactionRuleService>>,
+    pub notification_service: Arc<Mutex<NotificationService>>,
+    pub export_snapshot_service: Arc<Mutex<ExportSnapshotService>>,
+    pub change_feed_service: Arc<Mutex<ChangeFeedService>>,
+    pub import_session_service: Arc<Mutex<ImportSessionService>>,
+    pub focus_session_service: Arc<Mutex<FocusSessionService>>,
+    pub share_token_service: Arc<Mutex<ShareTokenService>>,
+    /// Every stored change to a document, as the services publish them
+    pub document_changes: DocumentChanges,
+    /// For outcomes of long-running work, which may also be shown natively
+    pub notifications: Arc<NotificationSink>,
+    /// The app window, for events and where data is kept
+    pub host: Arc<dyn Host>,
+    pub session: Arc<Session>,
+    pub settings: Arc<SettingsStore>,
+    pub processing_registry: Arc<ProcessingRegistry>,
+    /// Held by backups and storage migrations so they never overlap
+    pub file_jobs: Arc<Mutex<()>>,
+    /// Limits how many queued uploads are copied at once, in priority order
+    pub upload_queue: Arc<UploadQueue>,
+    /// Uploads from accepting them until their file is stored, so a
+    /// repeated attempt at the same file joins the one in flight
+    pub ingests: Arc<IngestRegistry>,
+    pub backup_status: Arc<RwLock<BackupStatus>>,
+    /// Library key once unlocked, when encryption is enabled
+    pub keyring: Arc<Keyring>,
+    /// Titles for the quick switcher, maintained by the document services
+    pub quick_index: Arc<QuickOpenIndex>,
+    /// Long-running commands the UI can list and cancel
+    pub operations: Arc<OperationRegistry>,
+    /// For checks that belong to no service, like probing write access
+    pub pool: sqlx::PgPool,
+    /// Set when the database refuses writes, e.g. a replica or a role
+    /// without write privileges; mutating commands fail with ReadOnlyMode
+    pub read_only: Arc<AtomicBool>,
+    /// Processing jobs that panicked since startup
+    pub processing_panics: Arc<AtomicU64>,
+}
+
+impl AppState {
+    /// Services on `pool`, with nobody signed in and writes allowed
+    pub fn new(
+        pool: sqlx::PgPool,
+        host: Arc<dyn Host>,
+        settings: Arc<SettingsStore>,
+        processing_registry: Arc<ProcessingRegistry>,
+        document_changes: DocumentChanges,
+        upload_concurrency: usize,
+    ) -> Self {
+        let quick_index = Arc::new(QuickOpenIndex::default());
+        let notification_service = Arc::new(Mutex::new(NotificationService::new(pool.clone())));
+        let notifications = Arc::new(NotificationSink::new(
+            Arc::clone(&host),
+            Arc::clone(&notification_service),
+            Arc::clone(&settings),
+        ));
+        AppState {
+            document_service: Arc::new(Mutex::new(DocumentService::new(
+                pool.clone(),
+                Arc::clone(&quick_index),
+                document_changes.clone(),
+            ))),
+            user_service: Arc::new(Mutex::new(UserService::new(pool.clone()))),
+            tag_service: Arc::new(Mutex::new(TagService::new(pool.clone(), document_changes.clone()))),
+            storage_migration_service: Arc::new(Mutex::new(StorageMigrationService::new(
+                pool.clone(),
+                document_changes.clone(),
+            ))),
+            activity_logger: Arc::new(Mutex::new(ActivityLogger::new(pool.clone()))),
+            workspace_service: Arc::new(Mutex::new(WorkspaceService::new(
+                pool.clone(),
+                Arc::clone(&quick_index),
+                document_changes.clone(),
+            ))),
+            document_changes,
+            processing_run_service: Arc::new(Mutex::new(ProcessingRunService::new(pool.clone()))),
+            redaction_rule_service: Arc::new(Mutex::new(RedactionRuleService::new(pool.clone()))),
+            notification_service,
+            export_snapshot_service: Arc::new(Mutex::new(ExportSnapshotService::new(pool.clone()))),
+            change_feed_service: Arc::new(Mutex::new(ChangeFeedService::new(pool.clone()))),
+            import_session_service: Arc::new(Mutex::new(ImportSessionService::new(pool.clone()))),
+            focus_session_service: Arc::new(Mutex::new(FocusSessionService::new(pool.clone()))),
+            share_token_service: Arc::new(Mutex::new(ShareTokenService::new(pool.clone()))),
+            notifications,
+            host,
+            session: Arc::new(Session::new(None)),
+            settings,
+            processing_registry,
+            file_jobs: Arc::new(Mutex::new(())),
+            upload_queue: UploadQueue::new(upload_concurrency),
+            ingests: IngestRegistry::new(),
+            backup_status: Arc::new(RwLock::new(BackupStatus::default())),
+            keyring: Arc::new(Keyring::default()),
+            quick_index,
+            operations: Arc::new(OperationRegistry::default()),
+            pool,
+            read_only: Arc::new(AtomicBool::new(false)),
+            processing_panics: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn processing_context(&self) -> ProcessingContext {
+        ProcessingContext {
+            document_service: Arc::clone(&self.document_service),
+            tag_service: Arc::clone(&self.tag_service),
+            registry: Arc::clone(&self.processing_registry),
+            settings: Arc::clone(&self.settings),
+            run_service: Arc::clone(&self.processing_run_service),
+            keyring: Arc::clone(&self.keyring),
+            notifications: Arc::clone(&self.notifications),
+            import_session_service: Arc::clone(&self.import_session_service),
+            panics: Arc::clone(&self.processing_panics),
+            host: Arc::clone(&self.host),
+        }
+    }
+}
+
+/// Whether startup finished; commands needing the database fail until it has
+#[tauri::command]
+fn init_status(status: State<'_, InitStatus>) -> InitStatus {
+    status.inner().clone()
 }
 
 #[tauri::command]
@@ -45,177 +265,5145 @@ async fn open_file_dialog(app: tauri::AppHandle) -> Result<Option<String>, Strin
 }
 
 #[tauri::command]
-async fn upload_file(
+async fn list_users(state: State<'_, AppState>) -> AppResult<Vec<User>> {
+    let service = state.user_service.lock().await;
+    Ok(service.list_users().await?)
+}
+
+/// Every user's live documents with their owners, newest first and
+/// without content; admins only
+#[tauri::command]
+async fn list_all_documents(state: State<'_, AppState>) -> AppResult<Vec<DocumentWithOwner>> {
+    run_list_all_documents(&state).await
+}
+
+/// list_all_documents's work
+async fn run_list_all_documents(state: &AppState) -> AppResult<Vec<DocumentWithOwner>> {
+    require_admin(state).await?;
+    
+    let owners: HashMap<uuid::Uuid, User> = {
+        let users = state.user_service.lock().await;
+        users.list_users().await?.into_iter().map(|u| (u.id, u)).collect()
+    };
+    let service = state.document_service.lock().await;
+    let documents = service.list_all_documents().await?;
+    
+    Ok(documents
+        .into_iter()
+        .map(|document| {
+            let owner = owners.get(&document.user_id);
+            DocumentWithOwner {
+                owner_email: owner.map(|u| u.email.clone()).unwrap_or_default(),
+                owner_name: owner.and_then(|u| u.full_name.clone()),
+                document,
+            }
+        })
+        .collect())
+}
+
+/// Change any user's storage quota; admins only
+#[tauri::command]
+async fn set_storage_limit(
+    state: State<'_, AppState>,
+    user_id: uuid::Uuid,
+    limit_bytes: i64,
+) -> AppResult<User> {
+    ensure_writable(&state)?;
+    run_set_storage_limit(&state, user_id, limit_bytes).await
+}
+
+/// set_storage_limit for a library known to be writable
+async fn run_set_storage_limit(state: &AppState, user_id: uuid::Uuid, limit_bytes: i64) -> AppResult<User> {
+    let admin_id = require_admin(state).await?;
+    if limit_bytes < 0 {
+        return Err(AppError::InvalidInput("Storage limit cannot be negative".to_string()));
+    }
+    
+    let user = {
+        let users = state.user_service.lock().await;
+        users
+            .set_storage_limit(user_id, limit_bytes)
+            .await?
+            .ok_or_else(|| AppError::NotFound("User".to_string()))?
+    };
+    
+    let activity = state.activity_logger.lock().await;
+    if let Err(e) = activity
+        .log(
+            Some(admin_id),
+            "set_storage_limit",
+            "user",
+            Some(user_id),
+            serde_json::json!({ "limit_bytes": limit_bytes }),
+        )
+        .await
+    {
+        eprintln!("Failed to log storage limit change: {}", e);
+    }
+    Ok(user)
+}
+
+#[tauri::command]
+async fn get_current_user(state: State<'_, AppState>) -> AppResult<User> {
+    let user_id = state.session.current_user_id().await?;
+    let service = state.user_service.lock().await;
+    service
+        .get_user(user_id)
+        .await?
+        .ok_or(AppError::NoActiveUser)
+}
+
+#[tauri::command]
+async fn switch_user(
     app: tauri::AppHandle,
     state: State<'_, AppState>,
+    user_id: uuid::Uuid,
+) -> AppResult<User> {
+    let user = run_switch_user(&state, user_id).await?;
+    
+    // Let the UI drop any lists belonging to the previous user
+    app.emit("session:user-changed", UserChangedEvent { user_id: user.id })?;
+    
+    // The active user is lost when settings are reset, so this is the first
+    // chance to say why
+    if let Some(corrupt_copy) = state.settings.take_corrupt_copy().await {
+        let body = format!(
+            "The settings file couldn't be read and was replaced with defaults. The unreadable copy was kept at {}.",
+            corrupt_copy.display()
+        );
+        notify(
+            &app,
+            &state.notification_service,
+            NewNotification {
+                user_id: user.id,
+                kind: "settings_reset",
+                title: "Settings were reset",
+                body: Some(&body),
+                document_id: None,
+            },
+        )
+        .await;
+    }
+    
+    Ok(user)
+}
+
+/// Make `user_id` the active user
+///
+/// Admin accounts can only be entered from an admin's session, or when no
+/// one is signed in yet, so a member can't pick one to get past
+/// require_admin.
+async fn run_switch_user(state: &AppState, user_id: uuid::Uuid) -> AppResult<User> {
+    let user = {
+        let service = state.user_service.lock().await;
+        let user = service
+            .get_user(user_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound("User".to_string()))?;
+        if user.role.is_admin() {
+            if let Ok(current_id) = state.session.current_user_id().await {
+                let current = service.get_user(current_id).await?;
+                if current_id != user.id && !current.is_some_and(|current| current.role.is_admin()) {
+                    return Err(AppError::Forbidden);
+                }
+            }
+        }
+        user
+    };
+    
+    state.session.set_active_user(Some(user.id)).await;
+    state
+        .settings
+        .update(|s| s.active_user_id = Some(user.id))
+        .await?;
+    Ok(user)
+}
+
+/// Accept a file for upload and return its queued document straight away
+///
+/// Only checks that don't read the file happen here: it exists, fits the
+/// quota and can be stored. Hashing, copying and extraction run in the
+/// background, at most `upload_concurrency` at a time; the UI follows them
+/// through "documents:changed" events. Uploading a file again while an
+/// earlier upload of it is still being stored, e.g. after a double click,
+/// returns the earlier upload's response instead of a second document.
+///
+/// A file only documents the user deleted have is handled as
+/// `on_trashed_match` says: by default the most recently deleted one comes
+/// back with its tags, notes and reading position instead of a new document
+/// being added. Deleted documents whose file is gone are passed over.
+#[tauri::command]
+async fn upload_file(
+    state: State<'_, AppState>,
+    request: UploadFileRequest,
+) -> AppResult<UploadOutcome> {
+    run_upload_file(&state, request).await
+}
+
+/// upload_file, asking first when the request says to
+async fn run_upload_file(state: &AppState, request: UploadFileRequest) -> AppResult<UploadOutcome> {
+    if request.on_trashed_match == TrashedMatchAction::Ask {
+        let user_id = state.session.current_user_id().await?;
+        let trashed = find_trashed_copies(state, user_id, std::path::Path::new(&request.source_path)).await?;
+        if !trashed.is_empty() {
+            return Ok(UploadOutcome::RequiresDecision { trashed });
+        }
+    }
+    Ok(UploadOutcome::Accepted(Box::new(queue_upload(state, request, None, None).await?)))
+}
+
+/// upload_file's work; a document queued for an import session is counted
+/// towards it and reported with it, and one found under `folder_root` is
+/// recorded as imported with that folder
+async fn queue_upload(
+    state: &AppState,
     request: UploadFileRequest,
-) -> Result<UploadFileResponse, String> {
-    let user_id = uuid::Uuid::parse_str(&request.user_id).map_err(|e| e.to_string())?;
+    import_session: Option<uuid::Uuid>,
+    folder_root: Option<&std::path::Path>,
+) -> AppResult<UploadFileResponse> {
+    ensure_writable(state)?;
+    let user_id = state.session.current_user_id().await?;
     let source_path = PathBuf::from(&request.source_path);
     
     // Validate source file exists
     if !source_path.exists() {
-        return Err("Source file does not exist".to_string());
+        return Err(AppError::NotFound("Source file".to_string()));
     }
     
+    // Join an upload of the same file that is still in flight; when that
+    // one failed before being accepted, try again ourselves
+    let canonical = std::fs::canonicalize(&source_path).unwrap_or_else(|_| source_path.clone());
+    let ingest = loop {
+        match state.ingests.claim(IngestKey::Path(user_id, canonical.clone())) {
+            Claim::Claimed(guard) => break guard,
+            Claim::InFlight(first) => {
+                if let Some(response) = IngestRegistry::outcome(first).await {
+                    return Ok(response);
+                }
+            }
+        }
+    };
+    
     // Get file metadata
-    let metadata = std::fs::metadata(&source_path).map_err(|e| e.to_string())?;
+    let metadata = std::fs::metadata(&source_path)?;
     let file_size = metadata.len() as i64;
     
+    // A referenced file is recorded by its absolute path and never copied
+    let external = request.storage_mode == StorageMode::Reference;
+    let source_path = if external {
+        if !metadata.is_file() {
+            return Err(AppError::InvalidInput("Only files can be imported by reference".to_string()));
+        }
+        std::fs::canonicalize(&source_path)?
+    } else {
+        source_path
+    };
+    
+    // An empty file has nothing to extract, so it isn't stored at all
+    if file_size == 0 {
+        return Err(AppError::EmptyFile(source_path.display().to_string()));
+    }
+    
+    // A file the user deleted a document of brings that document back
+    if request.on_trashed_match == TrashedMatchAction::Restore {
+        if let Some(trashed) = find_trashed_copies(state, user_id, &source_path).await?.into_iter().next() {
+            let document = restore_trashed(state, trashed).await?;
+            let usage = {
+                let users = state.user_service.lock().await;
+                users.get_storage_usage(user_id).await?
+            }
+            .ok_or(AppError::NoActiveUser)?;
+            let response = UploadFileResponse {
+                document,
+                storage_used_percent: storage::storage_status(&usage, &state.settings.get().await).percentage,
+                tiny_file: false,
+                restored: true,
+            };
+            ingest.accepted(&response);
+            return Ok(response);
+        }
+    }
+    
+    // Enforce the storage quota before copying anything
+    let usage_before = {
+        let users = state.user_service.lock().await;
+        users.get_storage_usage(user_id).await?
+    }
+    .ok_or(AppError::NoActiveUser)?;
+    if !external {
+        ensure_quota(&usage_before, file_size)?;
+    }
+    
+    // A locked library, a disconnected or full drive fails the command, not the background copy
+    let settings = state.settings.get().await;
+    if !external {
+        state.keyring.store(&settings)?;
+        let documents_dir = storage::online_documents_dir(&*state.host, &settings)?;
+        storage::ensure_disk_space(&documents_dir, metadata.len())?;
+    }
+    
+    // The workspace asked for wins over the user's default; without either
+    // the document stays unfiled
+    let workspace_id = match request.workspace_id {
+        Some(workspace_id) => Some(ensure_workspace_member(state, workspace_id, user_id).await?),
+        None => state.user_service.lock().await.default_workspace(user_id).await?,
+    };
+    
+    // Options given with the upload win over the workspace's, which win over settings
+    let workspace_defaults = match workspace_id {
+        Some(workspace_id) => state.workspace_service.lock().await.processing_defaults(workspace_id).await?,
+        None => None,
+    };
+    let processing_options = request
+        .processing_options
+        .or(workspace_defaults)
+        .unwrap_or_else(|| settings.processing_defaults.clone());
+    validate_processing_options(&processing_options)?;
+    
     let file_name = source_path
         .file_name()
         .and_then(|n| n.to_str())
         .unwrap_or("unknown")
         .to_string();
     
-    // Detect MIME type
-    let mime_type = file_utils::detect_mime_type(&source_path).map_err(|e| e.to_string())?;
-    
     // Get file extension
     let file_type = file_utils::get_file_extension(&source_path);
     
-    // Calculate SHA-256 hash
-    let file_hash = file_utils::calculate_sha256(&source_path).map_err(|e| e.to_string())?;
+    // A document this small is more likely a placeholder or a failed
+    // download than the real thing
+    let extension = source_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+    let tiny_file = (file_size as u64) < settings.tiny_file_bytes
+        && state.processing_registry.find("", &extension).is_some();
     
-    // Create app data directory
-    let app_data_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
-    let documents_dir = app_data_dir.join("documents");
-    std::fs::create_dir_all(&documents_dir).map_err(|e| e.to_string())?;
-    
-    // Generate unique filename using hash prefix + original name
-    let hash_prefix = &file_hash[..8];
-    let dest_filename = format!("{}_{}", hash_prefix, file_name);
-    let dest_path = documents_dir.join(&dest_filename);
-    
-    // Copy file to app directory
-    std::fs::copy(&source_path, &dest_path).map_err(|e| e.to_string())?;
+    let method = if folder_root.is_some() {
+        ImportMethod::FolderImport
+    } else if request.dropped {
+        ImportMethod::DragDrop
+    } else {
+        ImportMethod::Upload
+    };
     
-    // Create document in database
+    // Create document in database; its id names the stored file
     let dto = CreateDocumentDto {
         user_id,
         title: file_name.clone(),
-        file_name: file_name,
+        file_name: file_name.clone(),
         file_size_bytes: file_size,
-        file_type: file_type.clone(),
-        mime_type: mime_type.clone(),
+        file_type,
+        mime_type: None,
+        parent_document_id: None,
+        workspace_id,
+        file_hash: None,
+        title_sort: collation::title_sort_key(&file_name, &settings.locale),
+        status: DocumentStatus::Queued,
+        external_file: external,
+        provenance: ProvenanceInfo::now(method, Some(&canonical), folder_root, settings.record_import_paths),
+    };
+    let document = {
+        let service = state.document_service.lock().await;
+        let document = service.create_document(dto).await?;
+        service.set_processing_options(document.id, &processing_options).await?;
+        document
     };
+    // Without the session the document is simply reported on its own
+    if let Some(session_id) = import_session {
+        let sessions = state.import_session_service.lock().await;
+        if let Err(e) = sessions.add_document(session_id, document.id).await {
+            eprintln!("Failed to add {} to import session {}: {}", document.id, session_id, e);
+        }
+    }
     
-    let service = state.document_service.lock().await;
-    let mut document = service.create_document(dto).await.map_err(|e| e.to_string())?;
+    // Warn when this upload pushed usage over a threshold; the new row
+    // already counts against the quota
+    let usage_after = {
+        let users = state.user_service.lock().await;
+        users.get_storage_usage(user_id).await?
+    }
+    .unwrap_or(usage_before);
+    let before = storage::storage_status(&usage_before, &settings);
+    let after = storage::storage_status(&usage_after, &settings);
+    if after.level > before.level {
+        state.host.emit("storage:warning", &after);
+        let title = match after.level {
+            StorageLevel::Critical => "Storage is almost full",
+            _ => "Storage is filling up",
+        };
+        let body = format!("{:.0}% of your storage is in use", after.percentage);
+        notify(
+            &*state.host,
+            &state.notification_service,
+            NewNotification {
+                user_id,
+                kind: "storage_warning",
+                title,
+                body: Some(&body),
+                document_id: None,
+            },
+        )
+        .await;
+    }
     
-    // Update file_path in database
-    let dest_path_str = dest_path.to_string_lossy().to_string();
-    service.update_file_path(document.id, dest_path_str.clone()).await.map_err(|e| e.to_string())?;
+    let response = UploadFileResponse {
+        document,
+        storage_used_percent: after.percentage,
+        tiny_file,
+        restored: false,
+    };
+    ingest.accepted(&response);
+    tokio::spawn(ingest_upload(
+        state.clone(),
+        response.document.id,
+        user_id,
+        source_path,
+        file_name,
+        external,
+        ingest,
+    ));
+    Ok(response)
+}
+
+/// Queue every file in a folder tree as an upload
+///
+/// Directories become workspaces or tags as `structure_mode` says. Both are
+/// looked up by name before being created, so importing the same tree again
+/// reuses them; the report counts which were created and which reused.
+/// Each file goes through upload_file, so one that can't be queued, e.g.
+/// over quota, is reported and the rest carry on. Hidden files, symlinks
+/// and files whose content the library already has are skipped.
+///
+/// The files form an import session: instead of a notification per
+/// document, one notification with an ImportReport follows once every file
+/// is done; get_import_report returns the report at any time.
+///
+/// With `after_import` the folder works as an inbox: each file is deleted
+/// or moved aside once its document is processed, or at once when it was
+/// skipped as a duplicate, and each is recorded in the activity log.
+#[tauri::command]
+async fn import_folder(state: State<'_, AppState>, request: ImportFolderRequest) -> AppResult<FolderImportReport> {
+    ensure_writable(&state)?;
+    run_folder_import(&state, request).await
+}
+
+/// import_folder for a library known to be writable
+async fn run_folder_import(state: &AppState, request: ImportFolderRequest) -> AppResult<FolderImportReport> {
+    let user_id = state.session.current_user_id().await?;
+    let root = PathBuf::from(&request.folder_path);
+    if !root.is_dir() {
+        return Err(AppError::NotFound("Folder".to_string()));
+    }
+    // A document imported by reference needs its file where it is
+    if request.storage_mode == StorageMode::Reference && request.after_import != SourceFileAction::Keep {
+        return Err(AppError::InvalidInput(
+            "Files imported by reference can't be deleted or moved after importing".to_string(),
+        ));
+    }
+    let mut files = {
+        let root = root.clone();
+        tokio::task::spawn_blocking(move || folder_import::list_files(&root)).await??
+    };
+    if request.after_import == SourceFileAction::MoveToSubfolder {
+        let imported = root.join(folder_import::IMPORTED_DIR);
+        files.retain(|file| !file.starts_with(&imported));
+    }
+    let session_id = {
+        let sessions = state.import_session_service.lock().await;
+        sessions.start(user_id, &root.to_string_lossy(), files.len() as i32).await?
+    };
     
-    document.file_path = Some(dest_path_str);
+    let operation = start_operation(state, "folder_import");
+    let total = files.len();
+    let mut report = FolderImportReport {
+        import_session_id: Some(session_id),
+        ..Default::default()
+    };
+    // By lowercase name, so each is counted as created or reused once
+    let mut workspaces: HashMap<String, uuid::Uuid> = HashMap::new();
+    let mut tag_ids: HashMap<String, uuid::Uuid> = HashMap::new();
+    // Source files to act on once their document is processed
+    let mut pending_sources: HashMap<uuid::Uuid, PathBuf> = HashMap::new();
     
-    // Process PDF if applicable (spawn background task)
-    if mime_type == "application/pdf" || file_type == "PDF" {
-        let doc_id = document.id;
-        let pdf_path = dest_path.clone();
-        let service_clone = Arc::clone(&state.document_service);
+    for (index, file) in files.iter().enumerate() {
+        if operation.is_cancelled() {
+            report.cancelled_remaining = Some(total - index);
+            break;
+        }
+        let path = file.to_string_lossy().to_string();
+        match find_duplicate_file(state, user_id, file).await {
+            Ok(Some(duplicate_of)) => {
+                record_unqueued(state, session_id, UnqueuedFile {
+                    path: path.clone(),
+                    error: None,
+                    duplicate_of: Some(duplicate_of),
+                })
+                .await;
+                // The library has the content already, so the file is done with
+                act_on_source(state, user_id, &root, file, duplicate_of, request.after_import, true).await;
+                report.duplicates.push(FolderImportDuplicate { path, duplicate_of });
+                operation.set_progress(index + 1, total);
+                continue;
+            }
+            Ok(None) => {}
+            // Reading the file fails the upload too, with a better message
+            Err(e) => eprintln!("Failed to check {} for duplicates: {}", file.display(), e),
+        }
+        let placement = folder_import::placement(&root, file, request.structure_mode);
+        if placement.tags_dropped > 0 {
+            report.tags_truncated.push(path.clone());
+        }
         
-        tokio::spawn(async move {
-            // Update status to processing
-            if let Ok(service) = service_clone.try_lock() {
-                let _ = service.update_document_status(doc_id, DocumentStatus::Processing, None).await;
+        // A directory's workspace wins over the one the import targets, and
+        // is set when the document is inserted so its file is charged there
+        let queued = match placement_workspace(state, user_id, &placement, &mut workspaces, &mut report).await {
+            Ok(workspace_id) => {
+                let upload = UploadFileRequest {
+                    source_path: path.clone(),
+                    workspace_id: workspace_id.or(request.workspace_id),
+                    processing_options: request.processing_options.clone(),
+                    storage_mode: request.storage_mode,
+                    dropped: false,
+                    // Deleted documents are only brought back by upload_file
+                    on_trashed_match: TrashedMatchAction::Import,
+                };
+                queue_upload(state, upload, Some(session_id), Some(&root)).await
             }
-            
-            // Extract text from PDF
-            match pdf_processor::extract_text_from_pdf(&pdf_path) {
-                Ok(text) => {
-                    // Generate summary (first 500 chars)
-                    let summary = pdf_processor::generate_basic_summary(&text, 500);
-                    
-                    // Update database
-                    if let Ok(service) = service_clone.try_lock() {
-                        if let Err(e) = service.update_content_and_summary(doc_id, text, summary).await {
-                            eprintln!("Failed to update document content: {}", e);
-                            let _ = service.update_document_status(
-                                doc_id,
-                                DocumentStatus::Failed,
-                                Some(format!("Failed to save content: {}", e))
-                            ).await;
-                        }
-                    }
-                }
-                Err(e) => {
-                    eprintln!("Failed to extract PDF text: {}", e);
-                    if let Ok(service) = service_clone.try_lock() {
-                        let _ = service.update_document_status(
-                            doc_id,
-                            DocumentStatus::Failed,
-                            Some(format!("PDF extraction failed: {}", e))
-                        ).await;
-                    }
+            Err(e) => Err(e),
+        };
+        let placed = match queued {
+            Ok(response) => {
+                report.queued += 1;
+                if request.after_import != SourceFileAction::Keep {
+                    pending_sources.insert(response.document.id, file.clone());
                 }
+                tag_imported_document(state, user_id, response.document.id, &placement, &mut tag_ids, &mut report)
+                    .await
             }
-        });
+            Err(e) => {
+                record_unqueued(state, session_id, UnqueuedFile {
+                    path: path.clone(),
+                    error: Some(e.to_string()),
+                    duplicate_of: None,
+                })
+                .await;
+                Err(e)
+            }
+        };
+        if let Err(e) = placed {
+            report.failed.push(FolderImportFailure {
+                path,
+                error: e.to_string(),
+            });
+        }
+        operation.set_progress(index + 1, total);
     }
     
-    Ok(UploadFileResponse {
-        document,
-        file_hash,
-    })
+    if !pending_sources.is_empty() {
+        tokio::spawn(act_on_processed_sources(
+            state.clone(),
+            user_id,
+            root.clone(),
+            request.after_import,
+            pending_sources,
+        ));
+    }
+    
+    // Files not reached after a cancel aren't part of the session
+    let reached = total - report.cancelled_remaining.unwrap_or(0);
+    let queued = {
+        let sessions = state.import_session_service.lock().await;
+        sessions.mark_queued(session_id, reached as i32).await
+    };
+    match queued {
+        // Every document may be done already, e.g. when none could be queued
+        Ok(()) => {
+            finish_import_session(&*state.host, &state.import_session_service, &state.notifications, session_id).await
+        }
+        Err(e) => eprintln!("Failed to mark import session {} queued: {}", session_id, e),
+    }
+    Ok(report)
 }
 
-#[tauri::command]
-async fn create_document(
-    state: State<'_, AppState>,
-    dto: CreateDocumentDto,
-) -> Result<Document, String> {
+/// A document of the user's with the same size and content as `path`
+///
+/// Files are only hashed when the library has a file of their size.
+async fn find_duplicate_file(
+    state: &AppState,
+    user_id: uuid::Uuid,
+    path: &std::path::Path,
+) -> AppResult<Option<uuid::Uuid>> {
+    let size = std::fs::metadata(path)?.len() as i64;
+    // Empty files all share one hash without being copies of each other;
+    // they are refused as empty instead
+    if size == 0 {
+        return Ok(None);
+    }
+    let candidates = state.document_service.lock().await.file_hashes_by_size(user_id, size).await?;
+    if candidates.is_empty() {
+        return Ok(None);
+    }
+    let path = path.to_path_buf();
+    let hash = tokio::task::spawn_blocking(move || file_utils::calculate_sha256(&path)).await??;
+    Ok(candidates.into_iter().find(|(_, candidate)| *candidate == hash).map(|(id, _)| id))
+}
+
+/// Soft-deleted documents of the user's with the same content as `path`,
+/// most recently deleted first, whose file is still there; none when a live
+/// document has the content too
+async fn find_trashed_copies(
+    state: &AppState,
+    user_id: uuid::Uuid,
+    path: &std::path::Path,
+) -> AppResult<Vec<Document>> {
+    // A missing file is for the upload to report
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return Ok(Vec::new());
+    };
+    let size = metadata.len() as i64;
+    if size == 0 {
+        return Ok(Vec::new());
+    }
+    let candidates = state.document_service.lock().await.trashed_file_hashes_by_size(user_id, size).await?;
+    if candidates.is_empty() {
+        return Ok(Vec::new());
+    }
+    let hash = {
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || file_utils::calculate_sha256(&path)).await??
+    };
+    let matching: Vec<uuid::Uuid> = candidates
+        .into_iter()
+        .filter(|(_, candidate)| *candidate == hash)
+        .map(|(id, _)| id)
+        .collect();
+    if matching.is_empty() {
+        return Ok(Vec::new());
+    }
+    
     let service = state.document_service.lock().await;
-    service
-        .create_document(dto)
-        .await
-        .map_err(|e| e.to_string())
+    let live = service.file_hashes_by_size(user_id, size).await?;
+    if live.iter().any(|(_, candidate)| *candidate == hash) {
+        return Ok(Vec::new());
+    }
+    let trashed = service.get_trashed_documents(&matching).await?;
+    Ok(trashed
+        .into_iter()
+        .filter(|document| document.file_path.as_deref().is_some_and(|p| std::path::Path::new(p).exists()))
+        .collect())
+}
+
+/// Restore a soft-deleted document; its file was counted all along
+async fn restore_trashed(state: &AppState, mut document: Document) -> AppResult<Document> {
+    state.document_service.lock().await.restore_document(document.id).await?;
+    document.deleted_at = None;
+    Ok(document)
 }
 
+/// Bring back a document the user deleted, e.g. one upload_file listed for
+/// the user to choose
 #[tauri::command]
-async fn get_user_documents(
-    state: State<'_, AppState>,
-    user_id: String,
-) -> Result<Vec<Document>, String> {
-    let uuid = uuid::Uuid::parse_str(&user_id).map_err(|e| e.to_string())?;
-    let service = state.document_service.lock().await;
-    service
-        .get_documents_by_user(uuid)
+async fn restore_document(state: State<'_, AppState>, document_id: uuid::Uuid) -> AppResult<Document> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    let document = {
+        let service = state.document_service.lock().await;
+        service.get_trashed_documents(&[document_id]).await?
+    }
+    .into_iter()
+    .find(|document| document.user_id == user_id)
+    .ok_or_else(|| AppError::NotFound("Deleted document".to_string()))?;
+    restore_trashed(&state, document).await
+}
+
+/// Note a file left out of an import session; failures are only logged
+async fn record_unqueued(state: &AppState, session_id: uuid::Uuid, file: UnqueuedFile) {
+    let sessions = state.import_session_service.lock().await;
+    if let Err(e) = sessions.record_unqueued(session_id, &file).await {
+        eprintln!("Failed to record {} in import session {}: {}", file.path, session_id, e);
+    }
+}
+
+/// Finish an import session if its last file is done and leave the owner
+/// its report
+///
+/// Called whenever one of the session's files is done; only the call that
+/// finishes the session reports it.
+async fn finish_import_session(
+    host: &dyn Host,
+    sessions: &Mutex<ImportSessionService>,
+    notifications: &NotificationSink,
+    session_id: uuid::Uuid,
+) {
+    let finished = sessions.lock().await.try_finish(session_id).await;
+    match finished {
+        Ok(Some(user_id)) => notify_import_finished(host, sessions, notifications, user_id, session_id).await,
+        Ok(None) => {}
+        Err(e) => eprintln!("Failed to finish import session {}: {}", session_id, e),
+    }
+}
+
+/// Leave the owner of a finished import session a summary, and tell an
+/// open UI with the full report as "import:finished"
+async fn notify_import_finished(
+    host: &dyn Host,
+    sessions: &Mutex<ImportSessionService>,
+    notifications: &NotificationSink,
+    user_id: uuid::Uuid,
+    session_id: uuid::Uuid,
+) {
+    let report = match import_session_report(sessions, user_id, session_id).await {
+        Ok(Some(report)) => report,
+        Ok(None) => return,
+        Err(e) => {
+            eprintln!("Failed to build report of import session {}: {}", session_id, e);
+            return;
+        }
+    };
+    let title = if report.session.interrupted {
+        "Import interrupted"
+    } else {
+        "Import finished"
+    };
+    let body = import_report::summary(&report, &notifications.formatter().await);
+    notifications
+        .notify(NewNotification {
+            user_id,
+            kind: import_report::IMPORT_NOTIFICATION_KIND,
+            title,
+            body: Some(&body),
+            document_id: None,
+        })
+        .await;
+    host.emit("import:finished", &report);
+}
+
+async fn import_session_report(
+    sessions: &Mutex<ImportSessionService>,
+    user_id: uuid::Uuid,
+    session_id: uuid::Uuid,
+) -> AppResult<Option<ImportReport>> {
+    let sessions = sessions.lock().await;
+    let Some((session, unqueued)) = sessions.get_session(user_id, session_id).await? else {
+        return Ok(None);
+    };
+    let documents = sessions.session_documents(session_id).await?;
+    Ok(Some(import_report::build_report(session, unqueued, documents)))
+}
+
+/// How each file of a folder import ended up, so far or once finished
+#[tauri::command]
+async fn get_import_report(state: State<'_, AppState>, session_id: uuid::Uuid) -> AppResult<ImportReport> {
+    let user_id = state.session.current_user_id().await?;
+    import_session_report(&state.import_session_service, user_id, session_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Import session".to_string()))
+}
+
+/// Start a focus session for the current user; documents opened and
+/// searches run until it ends are recorded against it
+///
+/// A session already active is ended first.
+#[tauri::command]
+async fn start_session(state: State<'_, AppState>, name: String) -> AppResult<FocusSession> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::InvalidInput("Session name can't be empty".to_string()));
+    }
+    
+    let sessions = state.focus_session_service.lock().await;
+    Ok(sessions.start(user_id, name).await?)
+}
+
+/// End one of the current user's focus sessions; ending one that already
+/// ended returns it unchanged
+#[tauri::command]
+async fn end_session(state: State<'_, AppState>, session_id: uuid::Uuid) -> AppResult<FocusSession> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    
+    let sessions = state.focus_session_service.lock().await;
+    if let Some(session) = sessions.end(user_id, session_id).await? {
+        return Ok(session);
+    }
+    sessions
+        .get_session(user_id, session_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Focus session".to_string()))
+}
+
+/// How long a focus session ran, with the documents opened and searches
+/// run during it
+#[tauri::command]
+async fn get_session_report(state: State<'_, AppState>, session_id: uuid::Uuid) -> AppResult<FocusSessionReport> {
+    let user_id = state.session.current_user_id().await?;
+    
+    let sessions = state.focus_session_service.lock().await;
+    let session = sessions
+        .get_session(user_id, session_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Focus session".to_string()))?;
+    let documents = sessions.session_documents(session_id).await?;
+    let searches = sessions.session_searches(session_id).await?;
+    let ended_at = session.ended_at.unwrap_or_else(chrono::Utc::now);
+    Ok(FocusSessionReport {
+        duration_seconds: (ended_at - session.started_at).num_seconds().max(0),
+        session,
+        documents,
+        searches,
+    })
+}
+
+/// Act on the source files of imported documents as each is processed
+///
+/// A file whose document fails or is deleted first stays where it is.
+/// Documents processed before this started listening are found by their
+/// status, as are any whose changes it missed.
+async fn act_on_processed_sources(
+    state: AppState,
+    user_id: uuid::Uuid,
+    root: PathBuf,
+    action: SourceFileAction,
+    mut pending: HashMap<uuid::Uuid, PathBuf>,
+) {
+    use tokio::sync::broadcast::error::RecvError;
+    let mut changes = state.document_changes.subscribe();
+    let mut check_statuses = true;
+    while !pending.is_empty() {
+        if check_statuses {
+            check_statuses = false;
+            let ids: Vec<uuid::Uuid> = pending.keys().copied().collect();
+            let statuses = state.document_service.lock().await.statuses(user_id, &ids).await;
+            let statuses = match statuses {
+                Ok(statuses) => statuses,
+                Err(e) => {
+                    eprintln!("Failed to check on imported documents: {}", e);
+                    return;
+                }
+            };
+            // Purged ones aren't listed
+            let listed: HashSet<uuid::Uuid> = statuses.iter().map(|entry| entry.document_id).collect();
+            pending.retain(|id, _| listed.contains(id));
+            for entry in statuses {
+                let status = (!entry.deleted).then_some(entry.status);
+                settle_source(&state, user_id, &root, action, &mut pending, entry.document_id, status).await;
+            }
+            continue;
+        }
+        match changes.recv().await {
+            Ok(change) => {
+                if let Some(status) = change.status {
+                    settle_source(&state, user_id, &root, action, &mut pending, change.document_id, Some(status)).await;
+                }
+            }
+            Err(RecvError::Lagged(_)) => check_statuses = true,
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Act on a pending source file whose document has `status`, None once
+/// deleted, and stop following it when the document is done
+async fn settle_source(
+    state: &AppState,
+    user_id: uuid::Uuid,
+    root: &std::path::Path,
+    action: SourceFileAction,
+    pending: &mut HashMap<uuid::Uuid, PathBuf>,
+    document_id: uuid::Uuid,
+    status: Option<DocumentStatus>,
+) {
+    match status {
+        Some(DocumentStatus::Completed) => {
+            if let Some(file) = pending.remove(&document_id) {
+                act_on_source(state, user_id, root, &file, document_id, action, false).await;
+            }
+        }
+        None | Some(DocumentStatus::Failed | DocumentStatus::MissingFile) => {
+            pending.remove(&document_id);
+        }
+        Some(DocumentStatus::Queued | DocumentStatus::Uploading | DocumentStatus::Processing) => {}
+    }
+}
+
+/// Delete or move an imported file as the import asked, and record it in
+/// the activity log against the document that has its content
+async fn act_on_source(
+    state: &AppState,
+    user_id: uuid::Uuid,
+    root: &std::path::Path,
+    file: &std::path::Path,
+    document_id: uuid::Uuid,
+    action: SourceFileAction,
+    duplicate: bool,
+) {
+    let activity_action = match action {
+        SourceFileAction::Keep => return,
+        SourceFileAction::Delete => "delete_imported_source",
+        SourceFileAction::MoveToSubfolder => "move_imported_source",
+    };
+    let moved_to = match folder_import::apply_source_action(root, file, action) {
+        Ok(moved_to) => moved_to,
+        Err(e) => {
+            eprintln!("Failed to {} {}: {}", activity_action.replace('_', " "), file.display(), e);
+            return;
+        }
+    };
+    let activity = state.activity_logger.lock().await;
+    if let Err(e) = activity
+        .log(
+            Some(user_id),
+            activity_action,
+            "document",
+            Some(document_id),
+            serde_json::json!({ "path": file, "moved_to": moved_to, "duplicate": duplicate }),
+        )
         .await
-        .map_err(|e| e.to_string())
+    {
+        eprintln!("Failed to log {} of {}: {}", activity_action, file.display(), e);
+    }
 }
 
-#[cfg_attr(mobile, tauri::mobile_entry_point)]
-pub fn run() {
-    dotenvy::dotenv().ok(); // Load .env file
+/// The workspace an imported file's directory puts it in, creating it on
+/// first use
+async fn placement_workspace(
+    state: &AppState,
+    user_id: uuid::Uuid,
+    placement: &folder_import::Placement,
+    workspaces: &mut HashMap<String, uuid::Uuid>,
+    report: &mut FolderImportReport,
+) -> AppResult<Option<uuid::Uuid>> {
+    let Some(name) = &placement.workspace else {
+        return Ok(None);
+    };
+    if let Some(&id) = workspaces.get(&name.to_lowercase()) {
+        return Ok(Some(id));
+    }
+    let service = state.workspace_service.lock().await;
+    let workspace = match service.find_owned_workspace(user_id, name).await? {
+        Some(workspace) => {
+            report.workspaces_reused += 1;
+            workspace
+        }
+        None => {
+            report.workspaces_created += 1;
+            service.create_workspace(user_id, name).await?
+        }
+    };
+    workspaces.insert(name.to_lowercase(), workspace.id);
+    Ok(Some(workspace.id))
+}
+
+/// Attach the tags an imported document's path gives it, creating them on
+/// first use
+async fn tag_imported_document(
+    state: &AppState,
+    user_id: uuid::Uuid,
+    doc_id: uuid::Uuid,
+    placement: &folder_import::Placement,
+    tag_ids: &mut HashMap<String, uuid::Uuid>,
+    report: &mut FolderImportReport,
+) -> AppResult<()> {
+    let tags = state.tag_service.lock().await;
+    for name in &placement.tags {
+        let tag_id = match tag_ids.get(&name.to_lowercase()) {
+            Some(&id) => id,
+            None => {
+                let (tag, created) = tags.get_or_create_tag(user_id, name).await?;
+                if created {
+                    report.tags_created += 1;
+                } else {
+                    report.tags_reused += 1;
+                }
+                tag_ids.insert(name.to_lowercase(), tag.id);
+                tag.id
+            }
+        };
+        tags.attach_tag(doc_id, tag_id).await?;
+    }
+    Ok(())
+}
+
+/// Most uploads `upload_concurrency` may allow at the same time
+const MAX_UPLOAD_CONCURRENCY: usize = 16;
+
+/// Longest `db_retry_window_secs`; a document saving longer than this looks hung
+const MAX_DB_RETRY_WINDOW_SECS: u64 = 300;
+
+/// How long update_settings waits for subsystems to apply a change
+const SETTINGS_ACK_WAIT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Resize the upload queue whenever `upload_concurrency` changes
+async fn follow_upload_concurrency(queue: Arc<UploadQueue>, mut settings: SettingsSubscriber) {
+    while let Some(changed) = settings.changed().await {
+        queue.resize(changed.upload_concurrency);
+        settings.acknowledge();
+    }
+}
+
+/// Store a queued upload and start extracting it
+///
+/// If the file can't be stored the document is removed again, which frees
+/// its quota, and the owner is notified, or, for a document of an import
+/// session, the failure is kept for the session's report.
+async fn ingest_upload(
+    state: AppState,
+    doc_id: uuid::Uuid,
+    user_id: uuid::Uuid,
+    source_path: PathBuf,
+    file_name: String,
+    external: bool,
+    ingest: IngestGuard,
+) {
+    let stored = {
+        let _slot = state.upload_queue.acquire(doc_id).await;
+        store_upload(&state, doc_id, user_id, &source_path, &file_name, external).await
+    };
+    // Stored or failed, the upload is no longer in flight
+    drop(ingest);
     
-    tauri::Builder::default()
-        .plugin(tauri_plugin_opener::init())
-        .plugin(tauri_plugin_dialog::init())
-        .plugin(tauri_plugin_fs::init())
-        .setup(|app| {
-            // Initialize database connection
-            let runtime = tokio::runtime::Runtime::new().unwrap();
-            let db = runtime.block_on(async {
-                db::Database::new().await.expect("Failed to connect to database")
-            });
-            
-            let document_service = Arc::<Mutex<DocumentService>>::new(Mutex::new(
-                DocumentService::new(db.pool().clone())
-            ));
+    match stored {
+        Ok((dest_path, mime_type)) => {
+            // Pick up notes kept next to the original, e.g. paper.pdf + paper.md
+            if let Some(sidecar) = file_utils::find_sidecar_note(&source_path) {
+                let service = state.document_service.lock().await;
+                if let Err(e) = attach_sidecar(&service, doc_id, &sidecar).await {
+                    eprintln!("Failed to attach notes from {}: {}", sidecar.display(), e);
+                }
+            }
             
-            app.manage(AppState {
-                document_service,
+            // Extract content with whichever extractor supports the file
+            processing::spawn_processing(
+                state.processing_context(),
+                doc_id,
+                dest_path,
+                mime_type,
+                DocumentStatus::Queued,
+                PdfLayout::Auto,
+            );
+        }
+        Err(e) => {
+            eprintln!("Upload of {} failed: {}", source_path.display(), e);
+            // Looked up first, since discarding removes the document from it
+            let session = state.import_session_service.lock().await.open_session_of(doc_id).await;
+            let session = session.unwrap_or_else(|e| {
+                eprintln!("Failed to look up import session of {}: {}", doc_id, e);
+                None
             });
-            
-            Ok(())
+            if let Some(session_id) = session {
+                let file = UnqueuedFile {
+                    path: source_path.to_string_lossy().to_string(),
+                    error: Some(e.to_string()),
+                    duplicate_of: None,
+                };
+                record_unqueued(&state, session_id, file).await;
+            }
+            // Nothing was stored, so don't keep a row that counts against the quota
+            if let Err(e) = state.document_service.lock().await.discard_upload(doc_id).await {
+                eprintln!("Failed to discard upload {}: {}", doc_id, e);
+            }
+            if let Some(session_id) = session {
+                finish_import_session(&*state.host, &state.import_session_service, &state.notifications, session_id).await;
+                return;
+            }
+            let body = format!("{}: {}", file_name, e);
+            notify(
+                &*state.host,
+                &state.notification_service,
+                NewNotification {
+                    user_id,
+                    kind: "upload_failed",
+                    title: "Upload failed",
+                    body: Some(&body),
+                    document_id: None,
+                },
+            )
+            .await;
+        }
+    }
+}
+
+/// Store a queued upload ahead of the others waiting, e.g. the one open in the UI
+///
+/// Returns whether the document was waiting. An upload already being
+/// stored, or a document that is past that, is left alone and still counts
+/// as success.
+#[tauri::command]
+async fn boost_processing_priority(state: State<'_, AppState>, document_id: uuid::Uuid) -> AppResult<bool> {
+    let user_id = state.session.current_user_id().await?;
+    ensure_document_owner(&state, document_id, user_id).await?;
+    Ok(state.upload_queue.boost(document_id))
+}
+
+/// Hash and copy a queued upload into storage, returning the stored path and MIME type
+///
+/// An external upload is only hashed; its own path is recorded instead.
+/// Uploads of the same content are stored one after the other. With a
+/// virus scanner configured, the file is scanned first: a copy of it, which
+/// is then what gets stored, or an external file where it is.
+async fn store_upload(
+    state: &AppState,
+    doc_id: uuid::Uuid,
+    user_id: uuid::Uuid,
+    source_path: &std::path::Path,
+    file_name: &str,
+    external: bool,
+) -> AppResult<(PathBuf, String)> {
+    // Detect MIME type and calculate SHA-256 hash, waiting out programs that
+    // briefly hold the source open
+    let (mime_type, file_hash) = {
+        let (source, name) = (source_path.to_path_buf(), file_name.to_string());
+        tokio::task::spawn_blocking(move || {
+            storage::retry_if_locked(&name, || {
+                Ok((file_utils::detect_mime_type(&source)?, file_utils::calculate_sha256(&source)?))
+            })
         })
-        .invoke_handler(tauri::generate_handler![
-            greet,
-            open_file_dialog,
+        .await??
+    };
+    let _same_content = loop {
+        match state.ingests.claim(IngestKey::Hash(user_id, file_hash.clone())) {
+            Claim::Claimed(guard) => break guard,
+            Claim::InFlight(first) => {
+                IngestRegistry::outcome(first).await;
+            }
+        }
+    };
+    
+    // Files no extractor reads are kept only when settings allow it, and
+    // programs never are
+    let extension = source_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+    if state.processing_registry.find(&mime_type, &extension).is_none() {
+        if file_utils::is_executable(&mime_type, &extension) {
+            return Err(AppError::InvalidInput(format!("{} is a program, not a document", file_name)));
+        }
+        if !state.settings.get().await.accept_unsupported {
+            return Err(AppError::InvalidInput(format!("No extractor supports {} files", mime_type)));
+        }
+    }
+    
+    // Copy file to app directory, retrying while another program has it locked
+    let settings = state.settings.get().await;
+    let scanner = virus_scan::Scanner::from_settings(&settings)?;
+    let (dest_path, scan) = if external {
+        let scan = match &scanner {
+            Some(scanner) => Some(scanner.check(source_path, file_name).await?),
+            None => None,
+        };
+        (source_path.to_path_buf(), scan)
+    } else {
+        let store = state.keyring.store(&settings)?;
+        let documents_dir = storage::online_documents_dir(&*state.host, &settings)?;
+        let (source, name) = (source_path.to_path_buf(), file_name.to_string());
+        let (copy, scan) = match &scanner {
+            Some(scanner) => {
+                let (source, dir, name) = (source.clone(), documents_dir.clone(), name.clone());
+                let copy =
+                    tokio::task::spawn_blocking(move || virus_scan::ScanCopy::new(&source, &dir, doc_id, &name)).await??;
+                let scan = scanner.check(copy.path(), file_name).await?;
+                (Some(copy), Some(scan))
+            }
+            None => (None, None),
+        };
+        let dest = tokio::task::spawn_blocking(move || {
+            let source = copy.as_ref().map_or(source.as_path(), |copy| copy.path());
+            storage::store_file(&*store, source, &documents_dir, doc_id, &name)
+        })
+        .await??;
+        (dest, scan)
+    };
+    
+    let service = state.document_service.lock().await;
+    let recorded = service
+        .record_stored_file(doc_id, &dest_path.to_string_lossy(), &mime_type, &file_hash)
+        .await;
+    if let Err(e) = recorded {
+        // The row is about to be discarded, so don't keep its file either;
+        // an external file is the user's own and stays
+        if !external {
+            let _ = std::fs::remove_file(&dest_path);
+        }
+        return Err(e.into());
+    }
+    if let Some(scan) = scan {
+        if let Err(e) = service.record_scan(doc_id, &scan).await {
+            eprintln!("Failed to record the virus scan of {}: {}", doc_id, e);
+        }
+    }
+    Ok((dest_path, mime_type))
+}
+
+/// Fields of a filled-in PDF form, for showing next to the document
+///
+/// PDFs processed before form fields were extracted are read from their
+/// file; None when the document isn't a PDF.
+#[tauri::command]
+async fn get_form_fields(state: State<'_, AppState>, document_id: uuid::Uuid) -> AppResult<Option<PdfForm>> {
+    let user_id = state.session.current_user_id().await?;
+    let document = ensure_document_owner(&state, document_id, user_id).await?;
+    
+    let stored = {
+        let service = state.document_service.lock().await;
+        service.form_fields(document_id).await?
+    };
+    if stored.is_some() || document.mime_type.as_deref() != Some("application/pdf") {
+        return Ok(stored);
+    }
+    let Some(path) = document.file_path.map(PathBuf::from) else {
+        return Ok(None);
+    };
+    if !document.external_file {
+        ensure_storage_online(&state).await?;
+    }
+    let store = state.keyring.store(&state.settings.get().await)?;
+    let form = tokio::task::spawn_blocking(move || {
+        let local = LocalCopy::new(&*store, &path).map_err(|e| e.to_string())?;
+        pdf_processor::extract_form_fields(local.path())
+    })
+    .await?
+    .map_err(AppError::Other)?;
+    Ok(Some(form))
+}
+
+/// Invoice fields found in a document, each with its most confident value
+/// and alternates
+///
+/// None when the document was processed without `extract_fields`; reprocess
+/// it with the option set to fill them in.
+#[tauri::command]
+async fn get_extracted_fields(
+    state: State<'_, AppState>,
+    document_id: uuid::Uuid,
+) -> AppResult<Option<Vec<invoice_fields::ExtractedField>>> {
+    let user_id = state.session.current_user_id().await?;
+    ensure_document_owner(&state, document_id, user_id).await?;
+    let service = state.document_service.lock().await;
+    Ok(service.extracted_fields(document_id).await?)
+}
+
+/// Summarize a document again from its extracted text
+///
+/// Uses the strategy for the document's kind of file and returns which one
+/// made the summary. The file itself isn't read.
+#[tauri::command]
+async fn resummarize_document(state: State<'_, AppState>, document_id: uuid::Uuid) -> AppResult<DocumentSummary> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    let document = ensure_document_owner(&state, document_id, user_id).await?;
+    let kind = FileKind::of_document(&document);
+    let content = document
+        .content
+        .ok_or_else(|| AppError::InvalidInput("Document has no extracted text to summarize".to_string()))?;
+    
+    let pages: Vec<String> = {
+        let service = state.document_service.lock().await;
+        service.get_pages(document_id).await?.into_iter().map(|p| p.content).collect()
+    };
+    let summary = tokio::task::spawn_blocking(move || summarizer::summarize(kind, &content, &pages)).await?;
+    state.document_service.lock().await.set_summary(document_id, &summary).await?;
+    Ok(summary)
+}
+
+/// Copy the file of a document imported by reference into the storage root
+///
+/// The copy counts against the storage quota from then on; the original is
+/// left where it is. Like relinking, the file must still hash to what was
+/// imported.
+#[tauri::command]
+async fn convert_to_managed(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    document_id: uuid::Uuid,
+) -> AppResult<Document> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    let document = ensure_document_owner(&state, document_id, user_id).await?;
+    if !document.external_file {
+        return Err(AppError::InvalidInput("The document's file is already in storage".to_string()));
+    }
+    let expected_hash = {
+        let service = state.document_service.lock().await;
+        service.file_hash(document_id).await?
+    }
+    .ok_or_else(|| AppError::InvalidInput("The document's file hasn't been imported yet".to_string()))?;
+    let source = document
+        .file_path
+        .map(PathBuf::from)
+        .filter(|path| path.is_file())
+        .ok_or_else(|| AppError::NotFound("File".to_string()))?;
+    let file_name = document.file_name.unwrap_or_else(|| document.title.clone());
+    
+    let actual_hash = {
+        let path = source.clone();
+        tokio::task::spawn_blocking(move || file_utils::calculate_sha256(&path)).await??
+    };
+    if actual_hash != expected_hash {
+        return Err(AppError::InvalidInput(
+            "The file has changed since it was imported; reprocess it first".to_string(),
+        ));
+    }
+    
+    let usage = {
+        let users = state.user_service.lock().await;
+        users.get_storage_usage(user_id).await?
+    }
+    .ok_or(AppError::NoActiveUser)?;
+    ensure_quota(&usage, std::fs::metadata(&source)?.len() as i64)?;
+    
+    let settings = state.settings.get().await;
+    let store = state.keyring.store(&settings)?;
+    let documents_dir = storage::online_documents_dir(&app, &settings)?;
+    let dest_path = tokio::task::spawn_blocking(move || {
+        storage::store_file(&*store, &source, &documents_dir, document_id, &file_name)
+    })
+    .await??;
+    
+    let service = state.document_service.lock().await;
+    let converted = service.convert_to_managed(document_id, &dest_path.to_string_lossy()).await;
+    match converted {
+        Ok(true) => {}
+        Ok(false) => {
+            let _ = std::fs::remove_file(&dest_path);
+            return Err(AppError::NotFound("Document".to_string()));
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&dest_path);
+            return Err(e.into());
+        }
+    }
+    service
+        .get_document(document_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Document".to_string()))
+}
+
+/// Point a document imported by reference at the new location of its file
+///
+/// The file there must hash to what was imported, so a different file with
+/// the same name can't take the document's place. A document flagged
+/// MissingFile becomes Completed again.
+#[tauri::command]
+async fn relink_document(state: State<'_, AppState>, document_id: uuid::Uuid, new_path: String) -> AppResult<Document> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    let document = ensure_document_owner(&state, document_id, user_id).await?;
+    if !document.external_file {
+        return Err(AppError::InvalidInput("Only documents imported by reference can be relinked".to_string()));
+    }
+    let expected_hash = {
+        let service = state.document_service.lock().await;
+        service.file_hash(document_id).await?
+    }
+    .ok_or_else(|| AppError::InvalidInput("The document's file hasn't been imported yet".to_string()))?;
+    
+    let new_path = PathBuf::from(&new_path);
+    if !new_path.is_file() {
+        return Err(AppError::NotFound("File".to_string()));
+    }
+    let new_path = std::fs::canonicalize(&new_path)?;
+    let actual_hash = {
+        let path = new_path.clone();
+        tokio::task::spawn_blocking(move || file_utils::calculate_sha256(&path)).await??
+    };
+    if actual_hash != expected_hash {
+        return Err(AppError::InvalidInput(
+            "The file at the new location differs from the one that was imported".to_string(),
+        ));
+    }
+    
+    let service = state.document_service.lock().await;
+    service
+        .relink_external_file(document_id, &new_path.to_string_lossy())
+        .await?
+        .ok_or_else(|| AppError::NotFound("Document".to_string()))?;
+    service
+        .get_document(document_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Document".to_string()))
+}
+
+#[tauri::command]
+async fn create_document(
+    state: State<'_, AppState>,
+    request: CreateDocumentRequest,
+) -> AppResult<Document> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    let title_sort = collation::title_sort_key(&request.title, &state.settings.get().await.locale);
+    let dto = CreateDocumentDto {
+        user_id,
+        title: request.title,
+        file_name: request.file_name,
+        file_size_bytes: request.file_size_bytes,
+        file_type: request.file_type,
+        mime_type: Some(request.mime_type),
+        parent_document_id: None,
+        workspace_id: None,
+        file_hash: None,
+        title_sort,
+        status: DocumentStatus::Uploading,
+        external_file: false,
+        provenance: ProvenanceInfo::now(ImportMethod::Manual, None, None, false),
+    };
+    
+    let service = state.document_service.lock().await;
+    Ok(service.create_document(dto).await?)
+}
+
+/// Summary preview length in lists when the caller doesn't choose one
+const DEFAULT_SUMMARY_PREVIEW_CHARS: usize = 200;
+
+/// Longest summary preview a list may ask for
+const MAX_SUMMARY_PREVIEW_CHARS: usize = 2_000;
+
+/// The current user's documents
+///
+/// Entries carry a preview of `summary_preview_chars` chars instead of the
+/// summary, no content, and the display size and file kind. Grouped by
+/// `CreatedBucket`, each document carries its date group and the groups
+/// come in order, each in one run. `tz_offset_minutes` is the caller's
+/// offset east of UTC, e.g. 120 for UTC+2, and is required then.
+///
+/// `fields` limits each entry to the named fields; `content` and the full
+/// `summary` are only sent when named.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)] // Tauri passes arguments by name, so options stay flat
+async fn get_user_documents(
+    state: State<'_, AppState>,
+    include_pinned_first: Option<bool>,
+    sort: Option<DocumentSort>,
+    unread_only: Option<bool>,
+    group_by: Option<DocumentGrouping>,
+    tz_offset_minutes: Option<i32>,
+    summary_preview_chars: Option<usize>,
+    fields: Option<Vec<String>>,
+) -> AppResult<Vec<Selected<DocumentListEntry>>> {
+    let preview_chars = summary_preview_chars
+        .unwrap_or(DEFAULT_SUMMARY_PREVIEW_CHARS)
+        .clamp(1, MAX_SUMMARY_PREVIEW_CHARS);
+    let selection = Arc::new(FieldSelection::parse(fields, field_selection::LIST_ENTRY_FIELDS)?);
+    let user_id = state.session.current_user_id().await?;
+    let buckets = match group_by {
+        Some(DocumentGrouping::CreatedBucket) => Some(bucket_bounds(tz_offset_minutes)?),
+        None => None,
+    };
+    let service = state.document_service.lock().await;
+    let documents = service
+        .get_documents_by_user(
+            user_id,
+            include_pinned_first.unwrap_or(false),
+            sort.unwrap_or_default(),
+            unread_only.unwrap_or(false),
+            buckets.as_ref(),
+            selection.requested("content"),
+        )
+        .await?;
+    Ok(list_entries(documents, preview_chars, buckets, &selection))
+}
+
+/// Documents as list entries: the summary cut to a preview of
+/// `preview_chars` chars, and sent whole only when `selection` names it
+fn list_entries(
+    documents: Vec<Document>,
+    preview_chars: usize,
+    buckets: Option<date_buckets::BucketBounds>,
+    selection: &Arc<FieldSelection>,
+) -> Vec<Selected<DocumentListEntry>> {
+    let with_summary = selection.requested("summary");
+    documents
+        .into_iter()
+        .map(|mut document| {
+            let summary = document.summary.take();
+            let preview = summary.as_deref().map(|s| display::preview(s, preview_chars));
+            let entry = DocumentListEntry {
+                bucket: buckets.map(|b| b.bucket_of(document.created_at)),
+                summary_preview: preview.map(|(start, _)| start.to_string()),
+                summary_truncated: preview.is_some_and(|(_, truncated)| truncated),
+                file_kind: FileKind::of_document(&document),
+                document: Document {
+                    summary: summary.filter(|_| with_summary),
+                    ..document
+                },
+            };
+            Selected::new(entry, Arc::clone(selection))
+        })
+        .collect()
+}
+
+/// How many of the current user's documents fall in each date group, for
+/// group headers; matches get_user_documents grouped by `CreatedBucket`
+/// with the same `unread_only` and offset
+#[tauri::command]
+async fn get_document_counts_by_bucket(
+    state: State<'_, AppState>,
+    tz_offset_minutes: Option<i32>,
+    unread_only: Option<bool>,
+) -> AppResult<Vec<BucketCount>> {
+    let user_id = state.session.current_user_id().await?;
+    let buckets = bucket_bounds(tz_offset_minutes)?;
+    let service = state.document_service.lock().await;
+    Ok(service
+        .counts_by_bucket(user_id, unread_only.unwrap_or(false), &buckets)
+        .await?)
+}
+
+/// Date group bounds for now in the caller's time zone
+fn bucket_bounds(tz_offset_minutes: Option<i32>) -> AppResult<date_buckets::BucketBounds> {
+    let offset = tz_offset_minutes
+        .ok_or_else(|| AppError::InvalidInput("Grouping by date needs the time zone offset".to_string()))?;
+    date_buckets::BucketBounds::at(chrono::Utc::now(), offset).ok_or_else(|| {
+        AppError::InvalidInput(format!(
+            "Time zone offset must be within {} minutes of UTC",
+            date_buckets::MAX_TZ_OFFSET_MINUTES
+        ))
+    })
+}
+
+/// Mark a document read without opening it
+#[tauri::command]
+async fn mark_as_read(state: State<'_, AppState>, document_id: uuid::Uuid) -> AppResult<()> {
+    set_document_unread(&state, document_id, false).await
+}
+
+/// Mark a document unread; it stays unread until it is next opened
+#[tauri::command]
+async fn mark_as_unread(state: State<'_, AppState>, document_id: uuid::Uuid) -> AppResult<()> {
+    set_document_unread(&state, document_id, true).await
+}
+
+async fn set_document_unread(state: &AppState, document_id: uuid::Uuid, unread: bool) -> AppResult<()> {
+    ensure_writable(state)?;
+    let user_id = state.session.current_user_id().await?;
+    let service = state.document_service.lock().await;
+    if !service.set_unread(user_id, document_id, unread).await? {
+        return Err(AppError::NotFound("Document".to_string()));
+    }
+    Ok(())
+}
+
+/// Most ids get_documents_status accepts at once
+const MAX_STATUS_IDS: usize = 1_000;
+
+/// Most documents get_documents_changed_since returns
+const MAX_CHANGED_DOCUMENTS: i64 = 5_000;
+
+/// Most changes poll_changes returns at once
+const MAX_FEED_CHANGES: i64 = 1_000;
+
+/// Status, last update and version of many documents at once, for the UI
+/// to reconcile after missing "documents:changed" events
+///
+/// Ids that don't exist or belong to someone else are absent from the map.
+#[tauri::command]
+async fn get_documents_status(
+    state: State<'_, AppState>,
+    document_ids: Vec<uuid::Uuid>,
+) -> AppResult<HashMap<uuid::Uuid, DocumentStatusEntry>> {
+    if document_ids.len() > MAX_STATUS_IDS {
+        return Err(AppError::InvalidInput(format!(
+            "At most {} documents can be checked at once",
+            MAX_STATUS_IDS
+        )));
+    }
+    let user_id = state.session.current_user_id().await?;
+    let service = state.document_service.lock().await;
+    Ok(service
+        .statuses(user_id, &document_ids)
+        .await?
+        .into_iter()
+        .map(|entry| (entry.document_id, entry))
+        .collect())
+}
+
+/// The current user's documents updated after `since`, including ones
+/// soft-deleted since
+///
+/// Meant for after the UI reconnects its event listener; pass the time of
+/// the last event seen, a little earlier to be safe, since updates are
+/// stamped when their transaction starts.
+#[tauri::command]
+async fn get_documents_changed_since(
+    state: State<'_, AppState>,
+    since: chrono::DateTime<chrono::Utc>,
+) -> AppResult<ChangedDocuments> {
+    let user_id = state.session.current_user_id().await?;
+    let service = state.document_service.lock().await;
+    let mut documents = service.changed_since(user_id, since, MAX_CHANGED_DOCUMENTS + 1).await?;
+    let truncated = documents.len() as i64 > MAX_CHANGED_DOCUMENTS;
+    documents.truncate(MAX_CHANGED_DOCUMENTS as usize);
+    Ok(ChangedDocuments { documents, truncated })
+}
+
+/// The current user's document changes after `since_seq`, from the
+/// change feed
+///
+/// For consumers that don't receive "documents:changed", e.g. a second
+/// window. Start with 0, which only returns the current cursor and asks
+/// for a resync; after loading the list, poll with `latest_seq`.
+#[tauri::command]
+async fn poll_changes(state: State<'_, AppState>, since_seq: i64) -> AppResult<ChangeFeed> {
+    let user_id = state.session.current_user_id().await?;
+    let feed = state.change_feed_service.lock().await;
+    Ok(feed.poll(user_id, since_seq, MAX_FEED_CHANGES).await?)
+}
+
+/// How many of the current user's documents are unread, for the inbox badge
+#[tauri::command]
+async fn get_unread_document_count(state: State<'_, AppState>) -> AppResult<i64> {
+    let user_id = state.session.current_user_id().await?;
+    let service = state.document_service.lock().await;
+    Ok(service.unread_count(user_id).await?)
+}
+
+/// Recompute every document's title sort key for the current locale
+#[tauri::command]
+async fn rebuild_sort_keys(state: State<'_, AppState>) -> AppResult<u64> {
+    ensure_writable(&state)?;
+    rebuild_title_sort_keys(&state, false).await
+}
+
+/// Compute title sort keys for all documents, or only those without one
+async fn rebuild_title_sort_keys(state: &AppState, only_missing: bool) -> AppResult<u64> {
+    let locale = state.settings.get().await.locale;
+    let service = state.document_service.lock().await;
+    let (ids, keys): (Vec<uuid::Uuid>, Vec<String>) = service
+        .list_titles(only_missing)
+        .await?
+        .into_iter()
+        .map(|(id, title)| (id, collation::title_sort_key(&title, &locale)))
+        .unzip();
+    if ids.is_empty() {
+        return Ok(0);
+    }
+    Ok(service.set_title_sort_keys(&ids, &keys).await?)
+}
+
+/// Redo derived data that older code made, e.g. after an upgrade
+///
+/// For each target only the current user's documents stamped with an older
+/// version than the current code's are rebuilt, one at a time and without
+/// holding the document service in between, so uploads and processing go
+/// on alongside. Progress is reported with "maintenance:rebuild-progress"
+/// events. A cancelled rebuild picks up where it stopped when run again.
+#[tauri::command]
+async fn rebuild_derived_data(state: State<'_, AppState>, targets: Vec<DerivedTarget>) -> AppResult<RebuildReport> {
+    const BATCH: i64 = 100;
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    let settings = state.settings.get().await;
+    
+    let operation = start_operation(&state, "rebuild_derived_data");
+    let mut report = RebuildReport::default();
+    for target in targets {
+        let version = derived::current_version(target);
+        let total = {
+            let service = state.document_service.lock().await;
+            service.count_outdated(user_id, target, version).await?
+        } as usize;
+        let mut target_report = RebuildTargetReport {
+            target,
+            rebuilt: 0,
+            failed: Vec::new(),
+        };
+        let mut processed = 0;
+        let mut after = None;
+        
+        // Failed documents stay outdated, so the batches go by id
+        'batches: loop {
+            let batch = {
+                let service = state.document_service.lock().await;
+                service.outdated_documents(user_id, target, version, after, BATCH).await?
+            };
+            let Some(&last_id) = batch.last() else {
+                break;
+            };
+            after = Some(last_id);
+            
+            for doc_id in batch {
+                if operation.is_cancelled() {
+                    report.cancelled = true;
+                    break 'batches;
+                }
+                match derived::rebuild_document(&state.document_service, &settings, target, doc_id).await {
+                    Ok(()) => target_report.rebuilt += 1,
+                    Err(e) => target_report.failed.push(MigrationFailure {
+                        document_id: doc_id,
+                        error: e.to_string(),
+                    }),
+                }
+                
+                processed += 1;
+                // Documents can become outdated while the rebuild runs
+                let total = total.max(processed);
+                operation.set_progress(processed, total);
+                state.host.emit(
+                    "maintenance:rebuild-progress",
+                    RebuildProgress {
+                        operation_id: operation.id(),
+                        target,
+                        processed,
+                        total,
+                        document_id: doc_id,
+                    },
+                );
+                tokio::task::yield_now().await;
+            }
+        }
+        
+        report.targets.push(target_report);
+        if report.cancelled {
+            break;
+        }
+    }
+    
+    Ok(report)
+}
+
+/// Process the current user's documents stored without an extractor that
+/// one now supports
+///
+/// Maintenance does the same for every user at startup, so this is only
+/// needed for documents that were left for later, e.g. with storage offline.
+#[tauri::command]
+async fn process_unsupported(state: State<'_, AppState>) -> AppResult<UnsupportedProcessingReport> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    process_newly_supported(&state, Some(user_id)).await
+}
+
+/// The registered extractors, and which types of the current user's
+/// documents are stored waiting for one
+#[tauri::command]
+async fn get_supported_types(state: State<'_, AppState>) -> AppResult<SupportedTypes> {
+    let user_id = state.session.current_user_id().await?;
+    let unprocessed = state.document_service.lock().await.unprocessed_type_counts(user_id).await?;
+    Ok(SupportedTypes {
+        extractors: state.processing_registry.extractors(),
+        unprocessed,
+    })
+}
+
+/// Start processing documents flagged as needing an extractor whose type
+/// an extractor now supports; of one user, or of everyone for None
+async fn process_newly_supported(
+    state: &AppState,
+    user_id: Option<uuid::Uuid>,
+) -> AppResult<UnsupportedProcessingReport> {
+    let candidates = state.document_service.lock().await.documents_needing_extractor(user_id).await?;
+    let storage_online = ensure_storage_online(state).await.is_ok();
+    
+    let mut report = UnsupportedProcessingReport::default();
+    for (doc_id, file_path, mime_type, external) in candidates {
+        report.checked += 1;
+        let path = file_path.map(PathBuf::from);
+        let mime_type = mime_type.unwrap_or_else(|| file_utils::UNKNOWN_MIME_TYPE.to_string());
+        let extension = path
+            .as_deref()
+            .and_then(|p| p.extension())
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .unwrap_or_default();
+        let Some(path) = path.filter(|_| state.processing_registry.find(&mime_type, &extension).is_some()) else {
+            report.still_unsupported += 1;
+            continue;
+        };
+        if !external && !storage_online {
+            report.storage_offline += 1;
+            continue;
+        }
+        
+        let service = state.document_service.lock().await;
+        match service.transition_status(doc_id, DocumentStatus::Completed, DocumentStatus::Processing, None).await {
+            Ok(()) => {
+                processing::spawn_processing(
+                    state.processing_context(),
+                    doc_id,
+                    path,
+                    mime_type,
+                    DocumentStatus::Processing,
+                    PdfLayout::default(),
+                );
+                report.processing_started += 1;
+            }
+            Err(e) => report.failed.push(MigrationFailure {
+                document_id: doc_id,
+                error: e.to_string(),
+            }),
+        }
+    }
+    Ok(report)
+}
+
+/// Detect the type of the current user's documents stored as
+/// application/octet-stream or without one, e.g. uploads from before
+/// content sniffing, and process those an extractor now accepts
+///
+/// Only completed and failed documents are looked at, so nothing being
+/// processed is touched. Documents whose file is gone are counted and
+/// skipped.
+#[tauri::command]
+async fn redetect_file_types(state: State<'_, AppState>) -> AppResult<RedetectReport> {
+    ensure_writable(&state)?;
+    run_redetect_file_types(&state).await
+}
+
+/// redetect_file_types for a library known to be writable
+async fn run_redetect_file_types(state: &AppState) -> AppResult<RedetectReport> {
+    let user_id = state.session.current_user_id().await?;
+    ensure_storage_online(state).await?;
+    let store = state.keyring.store(&state.settings.get().await)?;
+    let candidates = {
+        let service = state.document_service.lock().await;
+        service.untyped_documents(user_id).await?
+    };
+    
+    let operation = start_operation(state, "redetect_file_types");
+    let mut report = RedetectReport::default();
+    let total = candidates.len();
+    for (index, (doc_id, file_path, file_type, status)) in candidates.into_iter().enumerate() {
+        if operation.is_cancelled() {
+            report.cancelled = true;
+            break;
+        }
+        operation.set_progress(index, total);
+        report.checked += 1;
+        
+        let Some(path) = file_path.map(PathBuf::from) else {
+            report.missing_files += 1;
+            continue;
+        };
+        let detected = {
+            let (store, path) = (Arc::clone(&store), path.clone());
+            tokio::task::spawn_blocking(move || -> AppResult<Option<String>> {
+                if !path.is_file() {
+                    return Ok(None);
+                }
+                // Encrypted files are detected on a decrypted copy
+                let local = LocalCopy::new(&*store, &path)?;
+                Ok(Some(file_utils::detect_mime_type(local.path())?))
+            })
+            .await?
+        };
+        let mime_type = match detected {
+            Ok(Some(mime_type)) => mime_type,
+            Ok(None) => {
+                report.missing_files += 1;
+                continue;
+            }
+            Err(e) => {
+                report.failed.push(MigrationFailure {
+                    document_id: doc_id,
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        };
+        if mime_type == file_utils::UNKNOWN_MIME_TYPE {
+            report.still_unknown += 1;
+            continue;
+        }
+        
+        // Only files named without an extension get a file type from content
+        let file_type = match file_type.as_deref() {
+            None | Some("FILE") => file_utils::file_type_for_mime(&mime_type),
+            Some(_) => None,
+        };
+        let service = state.document_service.lock().await;
+        if !service.set_detected_type(doc_id, &mime_type, file_type).await? {
+            continue;
+        }
+        *report.reclassified.entry(mime_type.clone()).or_insert(0) += 1;
+        
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .unwrap_or_default();
+        if state.processing_registry.find(&mime_type, &extension).is_none() {
+            continue;
+        }
+        match service.transition_status(doc_id, status, DocumentStatus::Processing, None).await {
+            Ok(()) => {
+                processing::spawn_processing(
+                    state.processing_context(),
+                    doc_id,
+                    path,
+                    mime_type,
+                    DocumentStatus::Processing,
+                    PdfLayout::default(),
+                );
+                report.processing_started += 1;
+            }
+            Err(e) => report.failed.push(MigrationFailure {
+                document_id: doc_id,
+                error: e.to_string(),
+            }),
+        }
+    }
+    
+    Ok(report)
+}
+
+/// Compress the extracted text of the current user's documents that is
+/// still stored plain, i.e. written before text was compressed at rest
+///
+/// Each document is compressed in a transaction of its own, so a cancelled
+/// run keeps what it did and the next run picks up the rest. Documents that
+/// fail stay plain and readable.
+#[tauri::command]
+async fn compress_existing_content(state: State<'_, AppState>) -> AppResult<ContentCompressionReport> {
+    ensure_writable(&state)?;
+    run_compress_existing_content(&state).await
+}
+
+/// compress_existing_content for a library known to be writable
+async fn run_compress_existing_content(state: &AppState) -> AppResult<ContentCompressionReport> {
+    const BATCH: i64 = 100;
+    let user_id = state.session.current_user_id().await?;
+    let total = {
+        let service = state.document_service.lock().await;
+        service.count_plain_text(user_id).await?
+    } as usize;
+    
+    let operation = start_operation(state, "compress_existing_content");
+    let mut report = ContentCompressionReport::default();
+    let mut processed = 0;
+    let mut after = None;
+    // Failed documents stay plain, so the batches go by id
+    'batches: loop {
+        let batch = {
+            let service = state.document_service.lock().await;
+            service.documents_with_plain_text(user_id, after, BATCH).await?
+        };
+        let Some(&last_id) = batch.last() else {
+            break;
+        };
+        after = Some(last_id);
+        
+        for doc_id in batch {
+            if operation.is_cancelled() {
+                report.cancelled = true;
+                break 'batches;
+            }
+            let compressed = {
+                let service = state.document_service.lock().await;
+                service.compress_content(doc_id, &mut report).await
+            };
+            if let Err(e) = compressed {
+                report.failed.push(MigrationFailure {
+                    document_id: doc_id,
+                    error: e.to_string(),
+                });
+            }
+            
+            processed += 1;
+            operation.set_progress(processed, total.max(processed));
+            tokio::task::yield_now().await;
+        }
+    }
+    
+    if report.compressed_bytes > 0 {
+        report.compression_ratio = report.plain_bytes as f64 / report.compressed_bytes as f64;
+    }
+    Ok(report)
+}
+
+#[tauri::command]
+async fn pin_document(state: State<'_, AppState>, document_id: uuid::Uuid) -> AppResult<()> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    
+    let service = state.document_service.lock().await;
+    service.pin_document(user_id, document_id).await
+}
+
+#[tauri::command]
+async fn unpin_document(state: State<'_, AppState>, document_id: uuid::Uuid) -> AppResult<()> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    
+    let service = state.document_service.lock().await;
+    service.unpin_document(user_id, document_id).await
+}
+
+/// Set the order of pinned documents; every pinned document must be listed
+#[tauri::command]
+async fn reorder_pinned(state: State<'_, AppState>, document_ids: Vec<uuid::Uuid>) -> AppResult<()> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    
+    let service = state.document_service.lock().await;
+    service.reorder_pinned(user_id, &document_ids).await
+}
+
+/// A document with its identifiers, notes, reading position and provenance
+///
+/// `fields` limits the result to the named fields; related data that isn't
+/// named isn't loaded either. Getting a document counts as opening it,
+/// except in read-only mode, where nothing is written.
+#[tauri::command]
+async fn get_document(
+    state: State<'_, AppState>,
+    document_id: uuid::Uuid,
+    fields: Option<Vec<String>>,
+) -> AppResult<Selected<DocumentDetails>> {
+    run_get_document(&state, document_id, fields).await
+}
+
+/// get_document's work
+async fn run_get_document(
+    state: &AppState,
+    document_id: uuid::Uuid,
+    fields: Option<Vec<String>>,
+) -> AppResult<Selected<DocumentDetails>> {
+    let selection = FieldSelection::parse(fields, field_selection::DETAILS_FIELDS)?;
+    let user_id = state.session.current_user_id().await?;
+    
+    let service = state.document_service.lock().await;
+    let document = service
+        .get_document(document_id)
+        .await?
+        .filter(|d| d.user_id == user_id)
+        .ok_or_else(|| AppError::NotFound("Document".to_string()))?;
+    let identifiers = if selection.includes("identifiers") {
+        service.get_identifiers(document_id).await?
+    } else {
+        Vec::new()
+    };
+    let notes = if selection.includes("notes") {
+        service.get_notes(document_id).await?
+    } else {
+        None
+    };
+    let reading_position = if selection.includes("reading_position") {
+        locate_reading_position(&service, user_id, &document).await?
+    } else {
+        None
+    };
+    let provenance = if selection.includes("provenance") {
+        service.get_provenance(document_id).await?
+    } else {
+        None
+    };
+    // Recording the open is best effort and never fails the read
+    if !state.read_only.load(Ordering::Relaxed) {
+        if let Err(e) = service.record_open(document_id).await {
+            eprintln!("Failed to record open of {}: {}", document_id, e);
+        }
+        let recorded = state.focus_session_service.lock().await.record_document_opened(user_id, document_id).await;
+        if let Err(e) = recorded {
+            eprintln!("Failed to record open of {} in focus session: {}", document_id, e);
+        }
+    }
+    // The user is looking at it, so store it before the rest of an import
+    if document.status == DocumentStatus::Queued {
+        state.upload_queue.boost(document_id);
+    }
+    
+    let details = DocumentDetails {
+        document,
+        identifiers,
+        notes,
+        reading_position,
+        provenance,
+    };
+    Ok(Selected::new(details, Arc::new(selection)))
+}
+
+/// Where the current user stopped reading a document, found in its current
+/// content; None if no position was saved
+#[tauri::command]
+async fn get_reading_position(
+    state: State<'_, AppState>,
+    document_id: uuid::Uuid,
+) -> AppResult<Option<ReadingPosition>> {
+    let user_id = state.session.current_user_id().await?;
+    let document = ensure_document_owner(&state, document_id, user_id).await?;
+    let service = state.document_service.lock().await;
+    locate_reading_position(&service, user_id, &document).await
+}
+
+/// Remember where the current user is in a document
+///
+/// `char_offset` is into the document's content. `percent` defaults to the
+/// offset's share of the content.
+#[tauri::command]
+async fn save_reading_position(
+    state: State<'_, AppState>,
+    document_id: uuid::Uuid,
+    char_offset: i32,
+    page_number: Option<i32>,
+    percent: Option<f32>,
+) -> AppResult<ReadingPosition> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    let document = ensure_document_owner(&state, document_id, user_id).await?;
+    
+    let content = document.content.as_deref().unwrap_or_default();
+    let content_chars = content.chars().count();
+    if char_offset < 0 || char_offset as usize > content_chars {
+        return Err(AppError::InvalidInput(format!(
+            "Offset must be between 0 and {}",
+            content_chars
+        )));
+    }
+    if let Some(page) = page_number {
+        if page < 1 || document.page_count.is_some_and(|count| page > count) {
+            return Err(AppError::InvalidInput(format!("Page {} is not in the document", page)));
+        }
+    }
+    let percent = match percent {
+        Some(percent) if !(0.0..=100.0).contains(&percent) => {
+            return Err(AppError::InvalidInput("Percent must be between 0 and 100".to_string()));
+        }
+        Some(percent) => percent,
+        None if content_chars == 0 => 0.0,
+        None => char_offset as f32 * 100.0 / content_chars as f32,
+    };
+    
+    let anchor = reading_position::anchor_at(content, char_offset as usize);
+    let service = state.document_service.lock().await;
+    let saved = service
+        .save_reading_position(user_id, document_id, char_offset, page_number, percent, &anchor)
+        .await?;
+    if percent >= READ_PERCENT && state.settings.get().await.reading_list_auto_remove {
+        if let Err(e) = service.remove_from_reading_list(user_id, document_id).await {
+            eprintln!("Failed to remove {} from the reading list: {}", document_id, e);
+        }
+    }
+    Ok(ReadingPosition {
+        char_offset: saved.char_offset,
+        page_number: saved.page_number,
+        percent: saved.percent,
+        updated_at: saved.updated_at,
+        anchoring: Anchoring::Exact,
+    })
+}
+
+/// Share of a document read, in percent, at which it counts as read for
+/// `reading_list_auto_remove`
+const READ_PERCENT: f32 = 90.0;
+
+/// Put a document at the end of the current user's reading list; one
+/// listed already moves to the end
+#[tauri::command]
+async fn add_to_reading_list(state: State<'_, AppState>, document_id: uuid::Uuid) -> AppResult<()> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    
+    let service = state.document_service.lock().await;
+    service.add_to_reading_list(user_id, document_id).await
+}
+
+#[tauri::command]
+async fn remove_from_reading_list(state: State<'_, AppState>, document_id: uuid::Uuid) -> AppResult<()> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    
+    let service = state.document_service.lock().await;
+    if !service.remove_from_reading_list(user_id, document_id).await? {
+        return Err(AppError::NotFound("Reading list entry".to_string()));
+    }
+    Ok(())
+}
+
+/// Set the order of the reading list; every listed document must be given
+#[tauri::command]
+async fn reorder_reading_list(state: State<'_, AppState>, document_ids: Vec<uuid::Uuid>) -> AppResult<()> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    
+    let service = state.document_service.lock().await;
+    service.reorder_reading_list(user_id, &document_ids).await
+}
+
+/// The current user's reading list in order, with each document's reading
+/// time and the total
+///
+/// Opening a document doesn't take it off the list; with
+/// `reading_list_auto_remove` on, reading most of it does.
+#[tauri::command]
+async fn get_reading_list(state: State<'_, AppState>) -> AppResult<ReadingList> {
+    let user_id = state.session.current_user_id().await?;
+    
+    let service = state.document_service.lock().await;
+    let entries = service.get_reading_list(user_id).await?;
+    let total_reading_time_minutes = entries.iter().map(|entry| entry.reading_time_minutes).sum();
+    Ok(ReadingList {
+        entries,
+        total_reading_time_minutes,
+    })
+}
+
+/// A user's saved position in `document`, re-anchored in its current content
+async fn locate_reading_position(
+    service: &DocumentService,
+    user_id: uuid::Uuid,
+    document: &Document,
+) -> AppResult<Option<ReadingPosition>> {
+    let Some(saved) = service.get_reading_position(user_id, document.id).await? else {
+        return Ok(None);
+    };
+    let page_start = match saved.page_number {
+        Some(page) => service.page_start_offset(document.id, page).await?,
+        None => None,
+    };
+    let content = document.content.as_deref().unwrap_or_default();
+    Ok(Some(reading_position::relocate(&saved, content, page_start)))
+}
+
+/// Link a Markdown file as the document's notes
+#[tauri::command]
+async fn attach_note_file(
+    state: State<'_, AppState>,
+    document_id: uuid::Uuid,
+    path: String,
+) -> AppResult<NoteAttachResult> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    ensure_document_owner(&state, document_id, user_id).await?;
+    
+    let service = state.document_service.lock().await;
+    attach_sidecar(&service, document_id, &PathBuf::from(path)).await
+}
+
+/// Re-read the linked notes file if it changed since it was imported
+#[tauri::command]
+async fn sync_note_file(state: State<'_, AppState>, document_id: uuid::Uuid) -> AppResult<NoteAttachResult> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    ensure_document_owner(&state, document_id, user_id).await?;
+    
+    let service = state.document_service.lock().await;
+    let source = service
+        .get_notes(document_id)
+        .await?
+        .and_then(|note| note.source_path)
+        .ok_or_else(|| AppError::NotFound("Linked notes file".to_string()))?;
+    attach_sidecar(&service, document_id, &PathBuf::from(source)).await
+}
+
+async fn attach_sidecar(
+    service: &DocumentService,
+    doc_id: uuid::Uuid,
+    path: &std::path::Path,
+) -> AppResult<NoteAttachResult> {
+    if !path.is_file() {
+        return Err(AppError::NotFound("Notes file".to_string()));
+    }
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| AppError::InvalidInput(format!("Notes must be UTF-8 text: {}", e)))?;
+    let content_hash = file_utils::calculate_sha256(path)?;
+    
+    let changed = service
+        .set_notes(doc_id, &content, &path.to_string_lossy(), &content_hash)
+        .await?;
+    let note = service
+        .get_notes(doc_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Document".to_string()))?;
+    
+    Ok(NoteAttachResult { note, changed })
+}
+
+/// Look up documents by DOI, arXiv id or ISBN, e.g. before downloading a paper again
+#[tauri::command]
+async fn find_document_by_identifier(
+    state: State<'_, AppState>,
+    kind: identifiers::IdentifierKind,
+    value: String,
+) -> AppResult<Vec<Document>> {
+    let user_id = state.session.current_user_id().await?;
+    let normalized = identifiers::normalize(kind, &value)
+        .ok_or_else(|| AppError::InvalidInput(format!("Not a valid {}: {}", kind.as_str(), value)))?;
+    
+    let service = state.document_service.lock().await;
+    Ok(service
+        .find_documents_by_identifier(user_id, kind, &normalized)
+        .await?)
+}
+
+#[tauri::command]
+async fn get_tag_suggestions(
+    state: State<'_, AppState>,
+    document_id: uuid::Uuid,
+) -> AppResult<Vec<keywords::TagSuggestion>> {
+    let user_id = state.session.current_user_id().await?;
+    ensure_document_owner(&state, document_id, user_id).await?;
+    
+    let tags = state.tag_service.lock().await;
+    Ok(tags.get_suggested_tags(document_id).await?)
+}
+
+/// Attach the chosen suggestions, creating any tags that don't exist yet
+#[tauri::command]
+async fn confirm_suggested_tags(
+    state: State<'_, AppState>,
+    document_id: uuid::Uuid,
+    tag_names: Vec<String>,
+) -> AppResult<Vec<Tag>> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    ensure_document_owner(&state, document_id, user_id).await?;
+    
+    let tags = state.tag_service.lock().await;
+    let mut attached = Vec::new();
+    for name in tag_names.iter().map(|n| n.trim()).filter(|n| !n.is_empty()) {
+        let (tag, _) = tags.get_or_create_tag(user_id, name).await?;
+        tags.attach_tag(document_id, tag.id).await?;
+        attached.push(tag);
+    }
+    
+    // Drop accepted suggestions so they aren't offered again
+    let remaining: Vec<keywords::TagSuggestion> = tags
+        .get_suggested_tags(document_id)
+        .await?
+        .into_iter()
+        .filter(|s| !attached.iter().any(|t| t.name.eq_ignore_ascii_case(&s.name)))
+        .collect();
+    tags.set_suggested_tags(document_id, &remaining).await?;
+    
+    Ok(attached)
+}
+
+/// Search the current user's documents with the search box query language
+///
+/// e.g. `type:pdf tag:tax after:2024-01-01 "capital gains"`; see
+/// `query_parser` for the syntax. Syntax errors come back as InvalidQuery
+/// with the position of the problem. Each hit lists the first pages a text
+/// term appears on, with snippets.
+#[tauri::command]
+async fn search_advanced(
+    state: State<'_, AppState>,
+    query_string: String,
+    limit: Option<i64>,
+    offset: Option<i64>,
+) -> AppResult<Vec<DocumentSearchResult>> {
+    let user_id = state.session.current_user_id().await?;
+    let query = query_parser::parse_query(&query_string)?;
+    let limit = limit.unwrap_or(50).clamp(1, 200);
+    let offset = offset.unwrap_or(0).max(0);
+    
+    let service = state.document_service.lock().await;
+    let documents = service.search_documents(user_id, &query, limit, offset).await?;
+    let ids: Vec<uuid::Uuid> = documents.iter().map(|d| d.id).collect();
+    let mut pages: HashMap<uuid::Uuid, Vec<SearchPageMatch>> = HashMap::new();
+    for (doc_id, page) in service.matching_pages(&ids, &query.text_terms, MATCHING_PAGES_PER_HIT).await? {
+        pages.entry(doc_id).or_default().push(page);
+    }
+    // Later pages of the same search aren't another search
+    if offset == 0 {
+        let sessions = state.focus_session_service.lock().await;
+        if let Err(e) = sessions.record_search(user_id, &query_string, documents.len() as i32).await {
+            eprintln!("Failed to record search in focus session: {}", e);
+        }
+    }
+    
+    Ok(documents
+        .into_iter()
+        .map(|document| DocumentSearchResult {
+            matching_pages: pages.remove(&document.id).unwrap_or_default(),
+            document,
+        })
+        .collect())
+}
+
+/// Matching pages listed per search hit
+const MATCHING_PAGES_PER_HIT: i64 = 3;
+
+/// Most rows a search export writes unless asked for fewer
+const DEFAULT_EXPORT_ROWS: usize = 10_000;
+
+/// Upper bound on the row cap of a search export
+const MAX_EXPORT_ROWS: usize = 100_000;
+
+/// Matches fetched and written at a time
+const EXPORT_BATCH_SIZE: usize = 500;
+
+/// Write the matches of a search box query to a CSV file
+///
+/// Matches are read and written in batches, newest first, without loading
+/// document content; `columns` defaults to all of them. Past `max_rows`
+/// matches a final row marks the export as truncated.
+#[tauri::command]
+async fn export_search_results(
+    state: State<'_, AppState>,
+    query_string: String,
+    dest_path: String,
+    columns: Option<Vec<CsvColumn>>,
+    max_rows: Option<usize>,
+) -> AppResult<SearchExportReport> {
+    let user_id = state.session.current_user_id().await?;
+    let query = query_parser::parse_query(&query_string)?;
+    let columns = columns.filter(|c| !c.is_empty()).unwrap_or_else(|| CsvColumn::ALL.to_vec());
+    let max_rows = max_rows.unwrap_or(DEFAULT_EXPORT_ROWS).clamp(1, MAX_EXPORT_ROWS);
+    
+    let dest = PathBuf::from(&dest_path);
+    let result = write_search_export(&state, user_id, &query, &dest, columns, max_rows).await;
+    if result.is_err() {
+        // Don't leave a truncated export behind
+        let _ = std::fs::remove_file(&dest);
+    }
+    result
+}
+
+async fn write_search_export(
+    state: &AppState,
+    user_id: uuid::Uuid,
+    query: &query_parser::DocumentQuery,
+    dest: &std::path::Path,
+    columns: Vec<CsvColumn>,
+    max_rows: usize,
+) -> AppResult<SearchExportReport> {
+    let mut writer = {
+        let dest = dest.to_path_buf();
+        tokio::task::spawn_blocking(move || export::csv::SearchResultsCsv::create(&dest, columns)).await??
+    };
+    let mut rows_written = 0;
+    let mut after = None;
+    let truncated = loop {
+        // One row past the cap tells whether there is more
+        let wanted = (max_rows - rows_written).min(EXPORT_BATCH_SIZE) + 1;
+        let mut rows = {
+            let service = state.document_service.lock().await;
+            service.search_export_page(user_id, query, after, wanted as i64).await?
+        };
+        let more = rows.len() == wanted;
+        if more {
+            rows.pop();
+        }
+        after = rows.last().map(|row| (row.created_at, row.id));
+        rows_written += rows.len();
+        let full = rows_written >= max_rows;
+        
+        writer = tokio::task::spawn_blocking(move || {
+            for row in &rows {
+                writer.write_row(row)?;
+            }
+            if more && full {
+                writer.write_truncation_marker(max_rows)?;
+            }
+            Ok::<_, AppError>(writer)
+        })
+        .await??;
+        
+        if !more || full {
+            break more && full;
+        }
+    };
+    tokio::task::spawn_blocking(move || writer.finish()).await??;
+    
+    Ok(SearchExportReport {
+        rows_written,
+        truncated,
+    })
+}
+
+/// Summary preview length of a catalog unless asked for another
+const DEFAULT_CATALOG_SUMMARY_CHARS: usize = 100;
+
+/// Upper bound on a catalog's summary previews
+const MAX_CATALOG_SUMMARY_CHARS: usize = 1_000;
+
+/// Write the user's documents, or a workspace's, to a catalog file
+///
+/// One entry per document with title, authors, year, page count, tags and
+/// the start of its summary, sorted by `sort` (title by default). Entries
+/// are read and written in batches without loading document content.
+#[tauri::command]
+async fn export_catalog(
+    state: State<'_, AppState>,
+    workspace_id: Option<uuid::Uuid>,
+    format: CatalogFormat,
+    dest_path: String,
+    sort: Option<CatalogSort>,
+    summary_preview_chars: Option<usize>,
+) -> AppResult<CatalogReport> {
+    let user_id = state.session.current_user_id().await?;
+    let title = match workspace_id {
+        Some(id) => {
+            ensure_workspace_member(&state, id, user_id).await?;
+            let workspace = state.workspace_service.lock().await.get_workspace(id).await?;
+            workspace
+                .ok_or_else(|| AppError::NotFound("Workspace".to_string()))?
+                .name
+        }
+        None => "Library".to_string(),
+    };
+    let sort = sort.unwrap_or_default();
+    let summary_chars = summary_preview_chars
+        .unwrap_or(DEFAULT_CATALOG_SUMMARY_CHARS)
+        .min(MAX_CATALOG_SUMMARY_CHARS);
+    
+    let dest = PathBuf::from(&dest_path);
+    let result = write_catalog(&state, user_id, workspace_id, &dest, format, &title, sort, summary_chars).await;
+    if result.is_err() {
+        // Don't leave a partial catalog behind
+        let _ = std::fs::remove_file(&dest);
+    }
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn write_catalog(
+    state: &AppState,
+    user_id: uuid::Uuid,
+    workspace_id: Option<uuid::Uuid>,
+    dest: &std::path::Path,
+    format: CatalogFormat,
+    title: &str,
+    sort: CatalogSort,
+    summary_chars: usize,
+) -> AppResult<CatalogReport> {
+    let mut writer = {
+        let dest = dest.to_path_buf();
+        let title = title.to_string();
+        tokio::task::spawn_blocking(move || {
+            export::catalog::CatalogWriter::create(&dest, format, &title, summary_chars)
+        })
+        .await??
+    };
+    let mut offset = 0;
+    loop {
+        let entries = {
+            let service = state.document_service.lock().await;
+            service
+                .catalog_page(user_id, workspace_id, sort, offset, EXPORT_BATCH_SIZE as i64)
+                .await?
+        };
+        let more = entries.len() == EXPORT_BATCH_SIZE;
+        offset += entries.len() as i64;
+        
+        writer = tokio::task::spawn_blocking(move || {
+            for entry in &entries {
+                writer.write_entry(entry)?;
+            }
+            Ok::<_, AppError>(writer)
+        })
+        .await??;
+        
+        if !more {
+            break;
+        }
+    }
+    let entries_written = tokio::task::spawn_blocking(move || writer.finish()).await??;
+    
+    Ok(CatalogReport { entries_written })
+}
+
+/// Titles the current user's documents matching `filter`, a search box
+/// query, would get from `rule`, without renaming anything
+///
+/// Documents are taken in title order, which is also the order `{n}`
+/// counts in.
+#[tauri::command]
+async fn preview_bulk_rename(
+    state: State<'_, AppState>,
+    filter: String,
+    rule: RenameRule,
+) -> AppResult<BulkRenameResult> {
+    let user_id = state.session.current_user_id().await?;
+    plan_bulk_rename(&state, user_id, &filter, &rule).await
+}
+
+/// Rename the current user's documents matching `filter` as the preview
+/// shows, in one transaction
+///
+/// Nothing is renamed, and `applied` is false, when a title would come out
+/// empty, too long or the same as another's in the batch. Each rename is
+/// recorded in the activity log.
+#[tauri::command]
+async fn apply_bulk_rename(
+    state: State<'_, AppState>,
+    filter: String,
+    rule: RenameRule,
+) -> AppResult<BulkRenameResult> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    let mut result = plan_bulk_rename(&state, user_id, &filter, &rule).await?;
+    if !result.empty_titles.is_empty() || !result.too_long_titles.is_empty() || !result.collisions.is_empty() {
+        return Ok(result);
+    }
+    
+    let locale = state.settings.get().await.locale;
+    let renames: Vec<(uuid::Uuid, String, String, String)> = result
+        .entries
+        .iter()
+        .filter(|entry| entry.new_title != entry.old_title)
+        .map(|entry| {
+            let key = collation::title_sort_key(&entry.new_title, &locale);
+            (entry.document_id, entry.old_title.clone(), entry.new_title.clone(), key)
+        })
+        .collect();
+    if !renames.is_empty() {
+        state.document_service.lock().await.rename_documents(user_id, &renames).await?;
+    }
+    result.applied = true;
+    
+    // Groups the batch's entries in the activity log
+    let batch = uuid::Uuid::new_v4();
+    let activity = state.activity_logger.lock().await;
+    for (document_id, old_title, new_title, _) in &renames {
+        let metadata = serde_json::json!({
+            "old_title": old_title,
+            "new_title": new_title,
+            "bulk_rename": batch,
+        });
+        if let Err(e) = activity.log(Some(user_id), "rename", "document", Some(*document_id), metadata).await {
+            eprintln!("Failed to log rename of {}: {}", document_id, e);
+        }
+    }
+    Ok(result)
+}
+
+/// The documents a bulk rename covers and their new titles
+async fn plan_bulk_rename(
+    state: &AppState,
+    user_id: uuid::Uuid,
+    filter: &str,
+    rule: &RenameRule,
+) -> AppResult<BulkRenameResult> {
+    let query = query_parser::parse_query(filter)?;
+    let rule = bulk_rename::CompiledRule::compile(rule)?;
+    let candidates = {
+        let service = state.document_service.lock().await;
+        service
+            .rename_candidates(user_id, &query, bulk_rename::MAX_BATCH as i64 + 1)
+            .await?
+    };
+    if candidates.len() > bulk_rename::MAX_BATCH {
+        return Err(AppError::InvalidInput(format!(
+            "More than {} documents match; narrow the filter",
+            bulk_rename::MAX_BATCH
+        )));
+    }
+    Ok(bulk_rename::plan(&rule, &candidates))
+}
+
+/// Documents `eval_pipelines` samples unless asked for another number
+const DEFAULT_EVAL_SAMPLE: usize = 200;
+
+/// Upper bound on the documents `eval_pipelines` samples
+const MAX_EVAL_SAMPLE: usize = 2_000;
+
+/// Describe how the summary and keyword pipelines did on the current
+/// user's most recent completed documents
+///
+/// Only runs with `debug_tools_enabled`. Keywords are extracted again from
+/// each document's content, so a large sample takes a while.
+#[tauri::command]
+async fn eval_pipelines(state: State<'_, AppState>, sample_size: Option<usize>) -> AppResult<eval::PipelineEvaluation> {
+    if !state.settings.get().await.debug_tools_enabled {
+        return Err(AppError::InvalidInput("Debug tools are turned off in settings".to_string()));
+    }
+    let user_id = state.session.current_user_id().await?;
+    let sample_size = sample_size.unwrap_or(DEFAULT_EVAL_SAMPLE).clamp(1, MAX_EVAL_SAMPLE);
+    let samples = state
+        .document_service
+        .lock()
+        .await
+        .eval_samples(user_id, sample_size as i64)
+        .await?;
+
+    Ok(tokio::task::spawn_blocking(move || eval::evaluate(&samples)).await?)
+}
+
+/// Word-level diff of two of the current user's processed documents
+///
+/// Whitespace-only differences are ignored when `normalize_whitespace` is
+/// set. Offsets in the hunks are chars into each document's content.
+#[tauri::command]
+async fn diff_documents(
+    state: State<'_, AppState>,
+    document_id_a: uuid::Uuid,
+    document_id_b: uuid::Uuid,
+    normalize_whitespace: Option<bool>,
+) -> AppResult<DocumentDiff> {
+    let user_id = state.session.current_user_id().await?;
+    let mut contents = Vec::with_capacity(2);
+    for document_id in [document_id_a, document_id_b] {
+        let document = ensure_document_owner(&state, document_id, user_id).await?;
+        if document.status != DocumentStatus::Completed {
+            return Err(AppError::DocumentNotReady { status: document.status });
+        }
+        contents.push(document.content.unwrap_or_default());
+    }
+    
+    let normalize_whitespace = normalize_whitespace.unwrap_or(false);
+    let b = contents.pop().unwrap_or_default();
+    let a = contents.pop().unwrap_or_default();
+    let diff = tokio::task::spawn_blocking(move || text_diff::diff_texts(&a, &b, normalize_whitespace)).await?;
+    Ok(diff)
+}
+
+/// Find all occurrences of `query` in a document for the reader's find bar
+#[tauri::command]
+async fn search_in_document(
+    state: State<'_, AppState>,
+    document_id: uuid::Uuid,
+    query: String,
+) -> AppResult<InDocumentSearchResult> {
+    let user_id = state.session.current_user_id().await?;
+    let document = ensure_document_owner(&state, document_id, user_id).await?;
+    let pages = {
+        let service = state.document_service.lock().await;
+        service.get_pages(document_id).await?
+    };
+    
+    let content = document.content.unwrap_or_default();
+    let result = tokio::task::spawn_blocking(move || {
+        text_search::search_text(&content, &pages, &query)
+    })
+    .await?;
+    
+    Ok(result)
+}
+
+/// Fetch part of a document's content by char offset, e.g. to jump to a match
+///
+/// Compressed content is decompressed whole to cut the slice; zstd frames
+/// can't be entered at a char offset, and decompressing is fast next to
+/// reading the row.
+#[tauri::command]
+async fn get_document_content(
+    state: State<'_, AppState>,
+    document_id: uuid::Uuid,
+    offset: usize,
+    length: usize,
+) -> AppResult<ContentSlice> {
+    let user_id = state.session.current_user_id().await?;
+    let document = ensure_document_owner(&state, document_id, user_id).await?;
+    
+    let content = document.content.unwrap_or_default();
+    Ok(ContentSlice {
+        offset,
+        text: text_search::char_slice(&content, offset, length),
+        total_chars: content.chars().count(),
+    })
+}
+
+/// Stop a document that is being processed; its extraction result is discarded
+#[tauri::command]
+async fn cancel_processing(state: State<'_, AppState>, document_id: uuid::Uuid) -> AppResult<()> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    ensure_document_owner(&state, document_id, user_id).await?;
+    
+    let service = state.document_service.lock().await;
+    service
+        .transition_status(
+            document_id,
+            DocumentStatus::Processing,
+            DocumentStatus::Failed,
+            Some("Cancelled by user".to_string()),
+        )
+        .await
+}
+
+/// Processing attempts for a document, newest first
+#[tauri::command]
+async fn get_processing_history(
+    state: State<'_, AppState>,
+    document_id: uuid::Uuid,
+    limit: Option<i64>,
+    offset: Option<i64>,
+) -> AppResult<ProcessingHistory> {
+    let user_id = state.session.current_user_id().await?;
+    ensure_document_owner(&state, document_id, user_id).await?;
+    
+    let limit = limit.unwrap_or(20).clamp(1, 100);
+    let offset = offset.unwrap_or(0).max(0);
+    let runs_service = state.processing_run_service.lock().await;
+    // Fetch one extra row to learn whether another page exists
+    let mut runs = runs_service.get_history(document_id, limit + 1, offset).await?;
+    let has_more = runs.len() as i64 > limit;
+    runs.truncate(limit as usize);
+    
+    Ok(ProcessingHistory { runs, has_more })
+}
+
+/// Run processing again for a failed document with its stored options
+#[tauri::command]
+async fn retry_processing(state: State<'_, AppState>, document_id: uuid::Uuid) -> AppResult<()> {
+    ensure_writable(&state)?;
+    restart_processing(&state, document_id, &[DocumentStatus::Failed], PdfLayout::Auto, None).await
+}
+
+/// Run processing again for a completed or failed document
+///
+/// `pdf_layout` overrides the reading order for this run, e.g. `StreamOrder`
+/// for a PDF wrongly detected as multi-column. Defaults to `Auto`.
+/// `processing_options` replace the document's stored options; without
+/// them the choices from upload or the last reprocess are reused.
+#[tauri::command]
+async fn reprocess_document(
+    state: State<'_, AppState>,
+    document_id: uuid::Uuid,
+    pdf_layout: Option<PdfLayout>,
+    processing_options: Option<ProcessingOptions>,
+) -> AppResult<()> {
+    ensure_writable(&state)?;
+    restart_processing(
+        &state,
+        document_id,
+        &[DocumentStatus::Completed, DocumentStatus::Failed],
+        pdf_layout.unwrap_or_default(),
+        processing_options,
+    )
+    .await
+}
+
+/// Claim a document for processing synchronously, then extract in the background
+async fn restart_processing(
+    state: &AppState,
+    doc_id: uuid::Uuid,
+    allowed_from: &[DocumentStatus],
+    pdf_layout: PdfLayout,
+    processing_options: Option<ProcessingOptions>,
+) -> AppResult<()> {
+    if let Some(options) = &processing_options {
+        validate_processing_options(options)?;
+    }
+    let user_id = state.session.current_user_id().await?;
+    let document = ensure_document_owner(state, doc_id, user_id).await?;
+    if !document.external_file {
+        ensure_storage_online(state).await?;
+    }
+    
+    if !allowed_from.contains(&document.status) {
+        return Err(AppError::InvalidTransition {
+            from: document.status,
+            to: DocumentStatus::Processing,
+        });
+    }
+    let path = document
+        .file_path
+        .map(PathBuf::from)
+        .ok_or_else(|| AppError::NotFound("Stored file".to_string()))?;
+    
+    {
+        let service = state.document_service.lock().await;
+        if let Some(options) = &processing_options {
+            service.set_processing_options(doc_id, options).await?;
+        }
+        service
+            .transition_status(doc_id, document.status, DocumentStatus::Processing, None)
+            .await?;
+    }
+    
+    processing::spawn_processing(
+        state.processing_context(),
+        doc_id,
+        path,
+        document.mime_type.unwrap_or_default(),
+        DocumentStatus::Processing,
+        pdf_layout,
+    );
+    Ok(())
+}
+
+/// Reject processing options the pipeline can't use
+fn validate_processing_options(options: &ProcessingOptions) -> AppResult<()> {
+    match options.language_hint.as_deref() {
+        Some(hint) if !is_language_tag(hint) => Err(AppError::InvalidInput(format!(
+            "\"{}\" is not a language tag like \"de\" or \"pt-BR\"",
+            hint
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// A BCP 47 style tag: a 2-3 letter language, then subtags of 1-8 letters or digits
+fn is_language_tag(tag: &str) -> bool {
+    let mut parts = tag.split(['-', '_']);
+    let language_ok = parts
+        .next()
+        .is_some_and(|language| (2..=3).contains(&language.len()) && language.chars().all(|c| c.is_ascii_alphabetic()));
+    language_ok && parts.all(|part| (1..=8).contains(&part.len()) && part.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// Fail with NotFound unless the document exists and belongs to `user_id`
+async fn ensure_document_owner(
+    state: &AppState,
+    doc_id: uuid::Uuid,
+    user_id: uuid::Uuid,
+) -> AppResult<Document> {
+    let service = state.document_service.lock().await;
+    service
+        .get_document(doc_id)
+        .await?
+        .filter(|d| d.user_id == user_id)
+        .ok_or_else(|| AppError::NotFound("Document".to_string()))
+}
+
+/// Most bytes `read_export_preview` returns
+const MAX_PREVIEW_BYTES: usize = 64 * 1024;
+
+/// Bytes of a cover thumbnail, for showing it in the webview
+///
+/// `path` is a file in the thumbnails directory, absolute or relative to
+/// it; paths resolving anywhere else fail with PathOutsideScope. Only
+/// thumbnails of the current user's documents can be read.
+#[tauri::command]
+async fn read_thumbnail_bytes(state: State<'_, AppState>, path: String) -> AppResult<Vec<u8>> {
+    let user_id = state.session.current_user_id().await?;
+    let thumbnails_dir = state.host.app_data_dir()?.join("thumbnails");
+    let requested = PathBuf::from(&path);
+    let resolved = tokio::task::spawn_blocking(move || fs_scope::resolve_in_scope(&thumbnails_dir, &requested)).await??;
+    
+    // Thumbnails are named after their document
+    let doc_id = resolved
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .and_then(|stem| uuid::Uuid::parse_str(stem).ok())
+        .ok_or_else(|| AppError::NotFound("Thumbnail".to_string()))?;
+    ensure_document_owner(&state, doc_id, user_id).await?;
+    Ok(tokio::fs::read(&resolved).await?)
+}
+
+/// The start of a stored file as text, for previewing what an export of
+/// its document will contain
+///
+/// `path` is a stored file, absolute or relative to the storage root; paths
+/// resolving anywhere else fail with PathOutsideScope. The file must be the
+/// one a document of the current user points at. Encrypted files are
+/// decrypted; bytes that aren't UTF-8 come back as replacement characters.
+#[tauri::command]
+async fn read_export_preview(
+    state: State<'_, AppState>,
+    path: String,
+    max_bytes: Option<usize>,
+) -> AppResult<String> {
+    let user_id = state.session.current_user_id().await?;
+    let settings = state.settings.get().await;
+    let root = storage::online_documents_dir(&*state.host, &settings)?;
+    let requested = PathBuf::from(&path);
+    let resolved = tokio::task::spawn_blocking(move || fs_scope::resolve_in_scope(&root, &requested)).await??;
+    
+    let not_found = || AppError::NotFound("Document file".to_string());
+    let doc_id = storage::stored_document_id(&resolved).ok_or_else(not_found)?;
+    let document = ensure_document_owner(&state, doc_id, user_id).await?;
+    let stored = document.file_path.as_deref().map(PathBuf::from).ok_or_else(not_found)?;
+    if stored.canonicalize().ok().as_deref() != Some(resolved.as_path()) {
+        return Err(not_found());
+    }
+    
+    let store = state.keyring.store(&settings)?;
+    let limit = max_bytes.unwrap_or(MAX_PREVIEW_BYTES).min(MAX_PREVIEW_BYTES) as u64;
+    let bytes = tokio::task::spawn_blocking(move || {
+        use std::io::Read;
+        let mut bytes = Vec::new();
+        store.open(&resolved)?.take(limit).read_to_end(&mut bytes)?;
+        Ok::<_, std::io::Error>(bytes)
+    })
+    .await??;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Longest a share link stays valid
+const MAX_SHARE_TTL_SECONDS: i64 = 7 * 24 * 60 * 60;
+
+/// Create a one-time link other apps on this machine can fetch a document
+/// from through the local HTTP API
+///
+/// The link serves the extracted content as HTML, or the original file with
+/// `include_original`, once, and stops working after `ttl_seconds` if it
+/// wasn't used. The token is returned only here; just its hash is stored.
+#[tauri::command]
+async fn create_share_token(
+    state: State<'_, AppState>,
+    document_id: uuid::Uuid,
+    ttl_seconds: i64,
+    include_original: bool,
+) -> AppResult<CreatedShareLink> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    let settings = state.settings.get().await;
+    if !settings.local_api_enabled {
+        return Err(AppError::InvalidInput("Turn on the local HTTP API to share documents".to_string()));
+    }
+    if !(1..=MAX_SHARE_TTL_SECONDS).contains(&ttl_seconds) {
+        return Err(AppError::InvalidInput(format!(
+            "A share link lasts between 1 and {} seconds",
+            MAX_SHARE_TTL_SECONDS
+        )));
+    }
+    let document = ensure_document_owner(&state, document_id, user_id).await?;
+    if include_original && document.file_path.is_none() {
+        return Err(AppError::NotFound("Document file".to_string()));
+    }
+    
+    let token = local_api::new_token();
+    let expires_at = chrono::Utc::now() + chrono::Duration::seconds(ttl_seconds);
+    let share = {
+        let shares = state.share_token_service.lock().await;
+        shares
+            .create(user_id, document_id, &local_api::token_hash(&token), include_original, expires_at)
+            .await?
+    };
+    Ok(CreatedShareLink {
+        url: local_api::share_url(settings.local_api_port, &token),
+        token,
+        share,
+    })
+}
+
+/// The current user's share links, newest first, whether or not they can
+/// still be used
+#[tauri::command]
+async fn list_share_tokens(state: State<'_, AppState>) -> AppResult<Vec<ShareToken>> {
+    let user_id = state.session.current_user_id().await?;
+    let shares = state.share_token_service.lock().await;
+    Ok(shares.list(user_id).await?)
+}
+
+#[tauri::command]
+async fn revoke_share_token(state: State<'_, AppState>, share_id: uuid::Uuid) -> AppResult<()> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    let shares = state.share_token_service.lock().await;
+    if !shares.revoke(user_id, share_id).await? {
+        return Err(AppError::NotFound("Active share link".to_string()));
+    }
+    Ok(())
+}
+
+/// Fail with Forbidden unless the active user is an admin; returns their id
+async fn require_admin(state: &AppState) -> AppResult<uuid::Uuid> {
+    let user_id = state.session.current_user_id().await?;
+    let service = state.user_service.lock().await;
+    let user = service.get_user(user_id).await?.ok_or(AppError::NoActiveUser)?;
+    if !user.role.is_admin() {
+        return Err(AppError::Forbidden);
+    }
+    Ok(user_id)
+}
+
+/// Export a document as standalone HTML
+///
+/// With `redact`, the built-in patterns and the user's redaction rules are
+/// blacked out of the title, summary and text, and the thumbnail is left out
+/// since it may show the same details. With `record_snapshot`, the export is
+/// recorded with a hash of the unredacted text and returned.
+#[tauri::command]
+async fn export_document_html(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    document_id: uuid::Uuid,
+    dest_path: String,
+    redact: Option<bool>,
+    record_snapshot: Option<bool>,
+) -> AppResult<Option<ExportSnapshot>> {
+    let user_id = state.session.current_user_id().await?;
+    let record_snapshot = record_snapshot.unwrap_or(false);
+    if record_snapshot {
+        ensure_writable(&state)?;
+    }
+    
+    let mut document = ensure_document_owner(&state, document_id, user_id).await?;
+    let snapshot = record_snapshot.then(|| snapshot_source(&document));
+    let mut pages: Vec<String> = {
+        let service = state.document_service.lock().await;
+        service.get_pages(document_id).await?
+    }
+    .into_iter()
+    .map(|p| p.content)
+    .collect();
+    
+    let redact = redact.unwrap_or(false);
+    let thumbnail = if redact {
+        let rules = {
+            let service = state.redaction_rule_service.lock().await;
+            service.list_rules(user_id).await?
+        };
+        let redactor = redaction::Redactor::new(&rules);
+        document.title = redactor.redact(&document.title);
+        document.summary = document.summary.map(|s| redactor.redact(&s));
+        document.content = document.content.map(|c| redactor.redact(&c));
+        for page in &mut pages {
+            *page = redactor.redact(page);
+        }
+        None
+    } else {
+        // Embed the cover thumbnail when one has been generated
+        let app_data_dir = app.path().app_data_dir()?;
+        let thumbnail_path = app_data_dir.join("thumbnails").join(format!("{}.png", document_id));
+        thumbnail_path.exists().then_some(thumbnail_path)
+    };
+    
+    let dest = PathBuf::from(&dest_path);
+    tokio::task::spawn_blocking(move || {
+        let pages = (!pages.is_empty()).then_some(pages.as_slice());
+        let result = export::html::write_document_html(&document, pages, thumbnail.as_deref(), &dest);
+        if result.is_err() {
+            // Don't leave a truncated export behind
+            let _ = std::fs::remove_file(&dest);
+        }
+        result
+    })
+    .await??;
+    
+    record_export_snapshot(&state, user_id, document_id, snapshot, "html", &dest_path).await
+}
+
+/// Title and content hash of a document as it is exported
+fn snapshot_source(document: &Document) -> (String, String) {
+    let content = document.content.as_deref().unwrap_or_default();
+    (document.title.clone(), file_utils::sha256_text(content))
+}
+
+/// Record a finished export when a snapshot was asked for
+async fn record_export_snapshot(
+    state: &AppState,
+    user_id: uuid::Uuid,
+    doc_id: uuid::Uuid,
+    source: Option<(String, String)>,
+    format: &str,
+    dest_path: &str,
+) -> AppResult<Option<ExportSnapshot>> {
+    let Some((title, content_hash)) = source else {
+        return Ok(None);
+    };
+    let snapshots = state.export_snapshot_service.lock().await;
+    let snapshot = snapshots
+        .record(user_id, doc_id, &title, &content_hash, format, dest_path)
+        .await?;
+    Ok(Some(snapshot))
+}
+
+/// Exports of a document recorded with a snapshot, newest first
+///
+/// Works for deleted documents too; their snapshots are marked
+/// `document_deleted`.
+#[tauri::command]
+async fn list_export_snapshots(state: State<'_, AppState>, document_id: uuid::Uuid) -> AppResult<Vec<ExportSnapshot>> {
+    let user_id = state.session.current_user_id().await?;
+    let snapshots = state.export_snapshot_service.lock().await;
+    Ok(snapshots.list_for_document(user_id, document_id).await?)
+}
+
+/// Whether a document's content is still what a recorded export was made from
+#[tauri::command]
+async fn verify_export(state: State<'_, AppState>, snapshot_id: uuid::Uuid) -> AppResult<ExportVerification> {
+    let user_id = state.session.current_user_id().await?;
+    let snapshot = {
+        let snapshots = state.export_snapshot_service.lock().await;
+        snapshots.get(user_id, snapshot_id).await?
+    }
+    .ok_or_else(|| AppError::NotFound("Export snapshot".to_string()))?;
+    
+    let document = if snapshot.document_deleted {
+        None
+    } else {
+        let service = state.document_service.lock().await;
+        service.get_document(snapshot.document_id).await?
+    };
+    // Deleted between the two reads counts as deleted
+    let Some(document) = document else {
+        return Ok(ExportVerification {
+            snapshot,
+            status: ExportMatch::DocumentDeleted,
+            current_hash: None,
+        });
+    };
+    
+    let (_, current_hash) = snapshot_source(&document);
+    let status = if current_hash == snapshot.content_hash {
+        ExportMatch::Matches
+    } else {
+        ExportMatch::Changed
+    };
+    Ok(ExportVerification {
+        snapshot,
+        status,
+        current_hash: Some(current_hash),
+    })
+}
+
+/// MIME type of Word documents, which keep their headings as paragraph styles
+const DOCX_MIME_TYPE: &str = "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+
+#[tauri::command]
+async fn export_document_markdown(
+    state: State<'_, AppState>,
+    document_id: uuid::Uuid,
+    dest_path: String,
+    fidelity: Option<MarkdownFidelity>,
+    record_snapshot: Option<bool>,
+) -> AppResult<Option<ExportSnapshot>> {
+    let user_id = state.session.current_user_id().await?;
+    let record_snapshot = record_snapshot.unwrap_or(false);
+    if record_snapshot {
+        ensure_writable(&state)?;
+    }
+    
+    let document = ensure_document_owner(&state, document_id, user_id).await?;
+    let snapshot = record_snapshot.then(|| snapshot_source(&document));
+    let pages: Vec<String> = {
+        let service = state.document_service.lock().await;
+        service.get_pages(document_id).await?
+    }
+    .into_iter()
+    .map(|p| p.content)
+    .collect();
+    let fidelity = fidelity.unwrap_or_default();
+    
+    // Word documents are read from the stored file for their paragraph styles
+    let is_docx = document.mime_type.as_deref() == Some(DOCX_MIME_TYPE) || document.file_type.as_deref() == Some("DOCX");
+    let store = if is_docx && fidelity == MarkdownFidelity::Structured {
+        if !document.external_file {
+            ensure_storage_online(&state).await?;
+        }
+        Some(state.keyring.store(&state.settings.get().await)?)
+    } else {
+        None
+    };
+    
+    let dest = PathBuf::from(&dest_path);
+    tokio::task::spawn_blocking(move || -> AppResult<()> {
+        let local = match (&store, document.file_path.as_deref()) {
+            (Some(store), Some(path)) => Some(LocalCopy::new(&**store, std::path::Path::new(path))?),
+            _ => None,
+        };
+        let pages = (!pages.is_empty()).then_some(pages.as_slice());
+        let result = export::markdown::write_document_markdown(
+            &document,
+            pages,
+            local.as_ref().map(|l| l.path()),
+            fidelity,
+            &dest,
+        );
+        if result.is_err() {
+            // Don't leave a truncated export behind
+            let _ = std::fs::remove_file(&dest);
+        }
+        Ok(result?)
+    })
+    .await??;
+    
+    record_export_snapshot(&state, user_id, document_id, snapshot, "markdown", &dest_path).await
+}
+
+#[tauri::command]
+async fn list_redaction_rules(state: State<'_, AppState>) -> AppResult<Vec<RedactionRule>> {
+    let user_id = state.session.current_user_id().await?;
+    let service = state.redaction_rule_service.lock().await;
+    Ok(service.list_rules(user_id).await?)
+}
+
+#[tauri::command]
+async fn add_redaction_rule(
+    state: State<'_, AppState>,
+    request: CreateRedactionRuleRequest,
+) -> AppResult<RedactionRule> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    redaction::compile_rule(&request.kind, &request.pattern).map_err(AppError::InvalidInput)?;
+    let label = request.label.as_deref().map(str::trim).filter(|l| !l.is_empty());
+    
+    let service = state.redaction_rule_service.lock().await;
+    Ok(service.add_rule(user_id, &request.kind, &request.pattern, label).await?)
+}
+
+#[tauri::command]
+async fn delete_redaction_rule(state: State<'_, AppState>, rule_id: uuid::Uuid) -> AppResult<()> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    
+    let service = state.redaction_rule_service.lock().await;
+    if !service.delete_rule(user_id, rule_id).await? {
+        return Err(AppError::NotFound("Redaction rule".to_string()));
+    }
+    Ok(())
+}
+
+/// How many matches each rule would black out of a document, before exporting it
+#[tauri::command]
+async fn preview_redaction(
+    state: State<'_, AppState>,
+    document_id: uuid::Uuid,
+) -> AppResult<Vec<RedactionMatchCount>> {
+    let user_id = state.session.current_user_id().await?;
+    let document = ensure_document_owner(&state, document_id, user_id).await?;
+    let pages = {
+        let service = state.document_service.lock().await;
+        service.get_pages(document_id).await?
+    };
+    let rules = {
+        let service = state.redaction_rule_service.lock().await;
+        service.list_rules(user_id).await?
+    };
+    
+    let counts = tokio::task::spawn_blocking(move || {
+        let redactor = redaction::Redactor::new(&rules);
+        // Page text repeats the content, so count the content only when there are no pages
+        let mut texts = vec![document.title.as_str()];
+        texts.extend(document.summary.as_deref());
+        if pages.is_empty() {
+            texts.extend(document.content.as_deref());
+        } else {
+            texts.extend(pages.iter().map(|p| p.content.as_str()));
+        }
+        redactor.count_matches(texts.iter().copied())
+    })
+    .await?;
+    
+    Ok(counts)
+}
+
+#[tauri::command]
+async fn extract_pages(
+    state: State<'_, AppState>,
+    request: ExtractPagesRequest,
+) -> AppResult<Document> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    let doc_id = request.document_id;
+    
+    let source = {
+        let service = state.document_service.lock().await;
+        service.get_document(doc_id).await?
+    }
+    .filter(|d| d.user_id == user_id)
+    .ok_or_else(|| AppError::NotFound("Document".to_string()))?;
+    
+    if source.mime_type.as_deref() != Some("application/pdf") {
+        return Err(AppError::InvalidInput("Only PDF documents support page extraction".to_string()));
+    }
+    ensure_storage_online(&state).await?;
+    let source_path = source
+        .file_path
+        .as_deref()
+        .map(PathBuf::from)
+        .ok_or_else(|| AppError::NotFound("Source file".to_string()))?;
+    
+    // Validate against the page count recorded during processing
+    let page_count = source.page_count.ok_or_else(|| {
+        AppError::InvalidInput("Page count is not known until the document has been processed".to_string())
+    })?;
+    let (from_page, to_page) = (request.from_page, request.to_page);
+    if from_page == 0 || from_page > to_page || to_page > page_count as u32 {
+        return Err(AppError::InvalidInput(format!(
+            "Page range {}-{} is outside 1-{}",
+            from_page, to_page, page_count
+        )));
+    }
+    
+    let title = request.new_title.trim().to_string();
+    if title.is_empty() {
+        return Err(AppError::InvalidInput("Title cannot be empty".to_string()));
+    }
+    
+    // Build the new PDF next to the other stored documents
+    let documents_dir = source_path
+        .parent()
+        .map(PathBuf::from)
+        .ok_or_else(|| AppError::Other("Source file has no parent directory".to_string()))?;
+    let file_name = file_utils::sanitize_filename(&format!("{}.pdf", title));
+    let temp_path = documents_dir.join(format!(".extract_{}.pdf", uuid::Uuid::new_v4()));
+    
+    let temp_for_task = temp_path.clone();
+    let store = state.keyring.store(&state.settings.get().await)?;
+    let extracted = tokio::task::spawn_blocking(move || {
+        let local = LocalCopy::new(&*store, &source_path).map_err(|e| e.to_string())?;
+        pdf_processor::extract_page_range(local.path(), &temp_for_task, from_page, to_page)
+    })
+    .await?;
+    if let Err(e) = extracted {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(AppError::Other(e));
+    }
+    
+    let registered = register_extracted_pdf(
+        &state,
+        user_id,
+        doc_id,
+        &temp_path,
+        &documents_dir,
+        &title,
+        &file_name,
+    )
+    .await;
+    
+    match registered {
+        Ok((document, dest_path)) => {
+            processing::spawn_processing(
+                state.processing_context(),
+                document.id,
+                dest_path,
+                "application/pdf".to_string(),
+                DocumentStatus::Uploading,
+                PdfLayout::Auto,
+            );
+            Ok(document)
+        }
+        Err(e) => {
+            // Clean up whatever part of the new file made it to disk
+            let _ = std::fs::remove_file(&temp_path);
+            Err(e)
+        }
+    }
+}
+
+/// Move an extracted PDF into place and create its document row
+async fn register_extracted_pdf(
+    state: &AppState,
+    user_id: uuid::Uuid,
+    parent_id: uuid::Uuid,
+    temp_path: &std::path::Path,
+    documents_dir: &std::path::Path,
+    title: &str,
+    file_name: &str,
+) -> AppResult<(Document, PathBuf)> {
+    let file_size = std::fs::metadata(temp_path)?.len() as i64;
+    let file_hash = file_utils::calculate_sha256(temp_path)?;
+    let dest_path = documents_dir.join(format!("{}_{}", &file_hash[..8], file_name));
+    
+    // Written through the store so it is encrypted like any other upload
+    let store = state.keyring.store(&state.settings.get().await)?;
+    let (source, dest) = (temp_path.to_path_buf(), dest_path.clone());
+    tokio::task::spawn_blocking(move || {
+        let written = std::fs::File::open(&source).and_then(|mut file| store.write(&mut file, &dest));
+        if written.is_err() {
+            let _ = std::fs::remove_file(&dest);
+        }
+        written
+    })
+    .await??;
+    std::fs::remove_file(temp_path)?;
+    
+    let dto = CreateDocumentDto {
+        user_id,
+        title: title.to_string(),
+        file_name: file_name.to_string(),
+        file_size_bytes: file_size,
+        file_type: "PDF".to_string(),
+        mime_type: Some("application/pdf".to_string()),
+        parent_document_id: Some(parent_id),
+        workspace_id: None,
+        file_hash: Some(file_hash),
+        title_sort: collation::title_sort_key(title, &state.settings.get().await.locale),
+        status: DocumentStatus::Uploading,
+        external_file: false,
+        provenance: ProvenanceInfo::now(ImportMethod::PdfSplit, None, None, false),
+    };
+    
+    let service = state.document_service.lock().await;
+    let result = async {
+        let mut document = service.create_document(dto).await?;
+        let dest_path_str = dest_path.to_string_lossy().to_string();
+        service.update_file_path(document.id, dest_path_str.clone()).await?;
+        document.file_path = Some(dest_path_str);
+        Ok::<_, AppError>(document)
+    }
+    .await;
+    
+    match result {
+        Ok(document) => Ok((document, dest_path)),
+        Err(e) => {
+            let _ = std::fs::remove_file(&dest_path);
+            Err(e)
+        }
+    }
+}
+
+#[tauri::command]
+async fn get_settings(state: State<'_, AppState>) -> AppResult<AppSettings> {
+    Ok(state.settings.get().await)
+}
+
+/// Replace user-editable settings; session and storage location have their own commands
+#[tauri::command]
+async fn update_settings(
+    state: State<'_, AppState>,
+    settings: AppSettings,
+) -> AppResult<SettingsUpdate> {
+    run_update_settings(&state, settings).await
+}
+
+/// update_settings' work
+async fn run_update_settings(state: &AppState, settings: AppSettings) -> AppResult<SettingsUpdate> {
+    if settings.storage_warning_percent > settings.storage_critical_percent {
+        return Err(AppError::InvalidInput(
+            "Warning threshold must not exceed the critical threshold".to_string(),
+        ));
+    }
+    if settings.extraction_timeout_secs == 0 || settings.page_timeout_secs == 0 {
+        return Err(AppError::InvalidInput("Extraction timeouts must be at least one second".to_string()));
+    }
+    if settings.backup_enabled && settings.backup_dir.is_none() {
+        return Err(AppError::InvalidInput("Choose a backup directory before enabling backups".to_string()));
+    }
+    if settings.backup_interval_hours == 0 || settings.keep_last_n == 0 {
+        return Err(AppError::InvalidInput(
+            "Backup interval and number of backups kept must be at least 1".to_string(),
+        ));
+    }
+    if settings.embedding_model.trim().is_empty() {
+        return Err(AppError::InvalidInput("Embedding model must not be empty".to_string()));
+    }
+    let price = settings.embedding_price_per_1k_tokens;
+    if !price.is_finite() || price < 0.0 {
+        return Err(AppError::InvalidInput("Embedding price must be zero or more".to_string()));
+    }
+    validate_processing_options(&settings.processing_defaults)?;
+    if settings.upload_concurrency == 0 || settings.upload_concurrency > MAX_UPLOAD_CONCURRENCY {
+        return Err(AppError::InvalidInput(format!(
+            "Concurrent uploads must be between 1 and {}",
+            MAX_UPLOAD_CONCURRENCY
+        )));
+    }
+    if settings.db_retry_window_secs > MAX_DB_RETRY_WINDOW_SECS {
+        return Err(AppError::InvalidInput(format!(
+            "Database retry window must be at most {} seconds",
+            MAX_DB_RETRY_WINDOW_SECS
+        )));
+    }
+    if !is_language_tag(&settings.locale) {
+        return Err(AppError::InvalidInput(format!(
+            "\"{}\" is not a language tag like \"de\" or \"sv-SE\"",
+            settings.locale
+        )));
+    }
+    if settings.virus_scan_timeout_secs == 0 {
+        return Err(AppError::InvalidInput("Virus scan timeout must be at least one second".to_string()));
+    }
+    virus_scan::Scanner::from_settings(&settings)?;
+    
+    let previous_locale = state.settings.get().await.locale;
+    // A new locale rebuilds every title sort key in the database
+    if settings.locale != previous_locale {
+        ensure_writable(state)?;
+    }
+    // Running subsystems pick the change up from the settings store
+    let updated = state
+        .settings
+        .update_acknowledged(
+            |current| {
+                *current = AppSettings {
+                    active_user_id: current.active_user_id,
+                    storage_root: current.storage_root.clone(),
+                    encryption_enabled: current.encryption_enabled,
+                    encryption_salt: current.encryption_salt.clone(),
+                    encryption_check: current.encryption_check.clone(),
+                    ..settings
+                };
+            },
+            SETTINGS_ACK_WAIT,
+        )
+        .await?;
+    
+    if updated.settings.locale != previous_locale {
+        let rebuilt = rebuild_title_sort_keys(state, false).await?;
+        eprintln!("Rebuilt {} title sort keys for locale {}", rebuilt, updated.settings.locale);
+    }
+    Ok(updated)
+}
+
+/// Estimated tokens in a document's text or in raw text, e.g. a selection,
+/// for showing what sending it to a model would use
+///
+/// Give either `document_id` or `text`. Counts are for the configured
+/// embedding model, with the same estimate chunk token counts use.
+#[tauri::command]
+async fn count_tokens(
+    state: State<'_, AppState>,
+    document_id: Option<uuid::Uuid>,
+    text: Option<String>,
+) -> AppResult<usize> {
+    let text = match (document_id, text) {
+        (Some(document_id), None) => {
+            let user_id = state.session.current_user_id().await?;
+            ensure_document_owner(&state, document_id, user_id).await?.content.unwrap_or_default()
+        }
+        (None, Some(text)) => text,
+        _ => return Err(AppError::InvalidInput("Give either a document or text".to_string())),
+    };
+    let model = state.settings.get().await.embedding_model;
+    Ok(tokio::task::spawn_blocking(move || tokens::estimate_tokens(&text, Some(&model))).await?)
+}
+
+/// What embedding the documents not yet embedded with the configured model
+/// would cost; nothing is sent anywhere
+#[tauri::command]
+async fn estimate_embedding_job(state: State<'_, AppState>) -> AppResult<EmbeddingEstimate> {
+    let user_id = state.session.current_user_id().await?;
+    let settings = state.settings.get().await;
+    let (chunk_count, total_tokens) = {
+        let service = state.document_service.lock().await;
+        service.count_unembedded_chunks(user_id, &settings.embedding_model).await?
+    };
+    
+    Ok(EmbeddingEstimate {
+        model: settings.embedding_model,
+        chunk_count,
+        total_tokens,
+        price_per_1k_tokens: settings.embedding_price_per_1k_tokens,
+        estimated_cost: total_tokens as f64 / 1000.0 * settings.embedding_price_per_1k_tokens,
+    })
+}
+
+/// Summary of the current user's library activity since `since`, by
+/// default over the past week, as a report and as Markdown
+#[tauri::command]
+async fn generate_digest(
+    state: State<'_, AppState>,
+    since: Option<chrono::DateTime<chrono::Utc>>,
+) -> AppResult<Digest> {
+    let user_id = state.session.current_user_id().await?;
+    let now = chrono::Utc::now();
+    let since = since.unwrap_or_else(|| now - chrono::Duration::days(digest::DIGEST_PERIOD_DAYS));
+    if since > now {
+        return Err(AppError::InvalidInput("Digest start must not be in the future".to_string()));
+    }
+    
+    let formatter = display::Formatter::new(&state.settings.get().await.locale, now);
+    let service = state.document_service.lock().await;
+    digest::generate_digest(&service, user_id, since, &formatter).await
+}
+
+/// Format raw values the way the locale setting reads them, in order
+///
+/// Lists and details return sizes in bytes, durations in seconds and
+/// RFC 3339 timestamps. This is the formatting the backend itself uses in
+/// exports, digests and notifications, for screens that need to match it.
+#[tauri::command]
+async fn format_human(state: State<'_, AppState>, values: Vec<FormatRequest>) -> AppResult<Vec<String>> {
+    let formatter = display::Formatter::new(&state.settings.get().await.locale, chrono::Utc::now());
+    Ok(values.iter().map(|value| formatter.format(value)).collect())
+}
+
+/// Send each user a digest notification when their last one is a week old
+async fn send_due_digests(state: &AppState) -> AppResult<usize> {
+    let users = state.user_service.lock().await.list_users().await?;
+    let now = chrono::Utc::now();
+    let period = chrono::Duration::days(digest::DIGEST_PERIOD_DAYS);
+    let formatter = display::Formatter::new(&state.settings.get().await.locale, now);
+    let mut sent = 0;
+    for user in users {
+        let last_sent = {
+            let notifications = state.notification_service.lock().await;
+            notifications.last_sent(user.id, digest::DIGEST_NOTIFICATION_KIND).await?
+        };
+        if last_sent.is_some_and(|last| now - last < period) {
+            continue;
+        }
+        
+        // Pick up where the previous digest left off
+        let since = last_sent.unwrap_or(now - period);
+        let digest = {
+            let service = state.document_service.lock().await;
+            digest::generate_digest(&service, user.id, since, &formatter).await?
+        };
+        notify(
+            &*state.host,
+            &state.notification_service,
+            NewNotification {
+                user_id: user.id,
+                kind: digest::DIGEST_NOTIFICATION_KIND,
+                title: "Your weekly digest",
+                body: Some(&digest.markdown),
+                document_id: None,
+            },
+        )
+        .await;
+        sent += 1;
+    }
+    Ok(sent)
+}
+
+/// Every tag of the current user with its document count, unused tags
+/// included with 0
+#[tauri::command]
+async fn get_tag_counts(state: State<'_, AppState>) -> AppResult<Vec<TagDocumentCount>> {
+    let user_id = state.session.current_user_id().await?;
+    let tags = state.tag_service.lock().await;
+    Ok(tags.document_counts(user_id).await?)
+}
+
+/// The current user's tags as a tree, flattened depth-first with each
+/// tag's parent id and depth
+#[tauri::command]
+async fn list_tags(state: State<'_, AppState>) -> AppResult<Vec<TagTreeEntry>> {
+    let user_id = state.session.current_user_id().await?;
+    let tags = state.tag_service.lock().await;
+    Ok(tags.tag_tree(user_id).await?)
+}
+
+/// Create a tag, nested under `parent_tag_id` when given
+#[tauri::command]
+async fn create_tag(state: State<'_, AppState>, request: CreateTagRequest) -> AppResult<Tag> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    let name = validate_tag_name(&request.name)?;
+    validate_tag_color(request.color.as_deref())?;
+    if let Some(workspace_id) = request.workspace_id {
+        ensure_workspace_member(&state, workspace_id, user_id).await?;
+    }
+
+    let tags = state.tag_service.lock().await;
+    tags.create_tag(
+        user_id,
+        request.workspace_id,
+        name,
+        request.color.as_deref(),
+        request.description.as_deref(),
+        request.parent_tag_id,
+    )
+    .await
+}
+
+/// Replace a tag's name, color, description and parent
+///
+/// Documents keep the tag through a rename or a move; filters by name pick
+/// up the new name.
+#[tauri::command]
+async fn update_tag(state: State<'_, AppState>, tag_id: uuid::Uuid, request: UpdateTagRequest) -> AppResult<Tag> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    let tag = ensure_tag_owner(&state, tag_id, user_id).await?;
+    let name = validate_tag_name(&request.name)?;
+    validate_tag_color(request.color.as_deref())?;
+
+    let tags = state.tag_service.lock().await;
+    if tag.name != name {
+        tags.rename_tag(&tag, name).await?;
+    }
+    if tag.parent_tag_id != request.parent_tag_id {
+        tags.set_parent(&tag, request.parent_tag_id).await?;
+    }
+    tags.update_tag_style(tag_id, request.color.as_deref(), request.description.as_deref())
+        .await?;
+    tags.get_tag(tag_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Tag".to_string()))
+}
+
+/// Delete a tag, detaching it from its documents
+///
+/// A tag with nested tags is refused unless `reparent_children` is set, in
+/// which case they move up to the deleted tag's parent.
+#[tauri::command]
+async fn delete_tag(
+    state: State<'_, AppState>,
+    tag_id: uuid::Uuid,
+    reparent_children: Option<bool>,
+) -> AppResult<()> {
+    ensure_writable(&state)?;
+    run_delete_tag(&state, tag_id, reparent_children.unwrap_or(false)).await
+}
+
+/// delete_tag for a library known to be writable
+async fn run_delete_tag(state: &AppState, tag_id: uuid::Uuid, reparent_children: bool) -> AppResult<()> {
+    let user_id = state.session.current_user_id().await?;
+    let tag = ensure_tag_owner(state, tag_id, user_id).await?;
+    let tags = state.tag_service.lock().await;
+    tags.delete_tag(&tag, reparent_children).await
+}
+
+async fn ensure_tag_owner(state: &AppState, tag_id: uuid::Uuid, user_id: uuid::Uuid) -> AppResult<Tag> {
+    let tags = state.tag_service.lock().await;
+    tags.get_tag(tag_id)
+        .await?
+        .filter(|tag| tag.user_id == user_id)
+        .ok_or_else(|| AppError::NotFound("Tag".to_string()))
+}
+
+fn validate_tag_name(name: &str) -> AppResult<&str> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::InvalidInput("Tag name can't be empty".to_string()));
+    }
+    if name.chars().count() > MAX_TAG_NAME_CHARS {
+        return Err(AppError::InvalidInput(format!(
+            "Tag name is longer than {} characters",
+            MAX_TAG_NAME_CHARS
+        )));
+    }
+    Ok(name)
+}
+
+fn validate_tag_color(color: Option<&str>) -> AppResult<()> {
+    match color {
+        Some(color) if !library_config::is_hex_color(color) => Err(AppError::InvalidInput(format!(
+            "\"{}\" is not a color like \"#6366f1\"",
+            color
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Every workspace the current user belongs to with its document count,
+/// empty workspaces included with 0
+#[tauri::command]
+async fn get_workspace_counts(state: State<'_, AppState>) -> AppResult<Vec<WorkspaceDocumentCount>> {
+    let user_id = state.session.current_user_id().await?;
+    let workspaces = state.workspace_service.lock().await;
+    Ok(workspaces.document_counts(user_id).await?)
+}
+
+/// Choose the workspace the current user's uploads go in when they don't
+/// name one; None clears it, so they stay unfiled
+#[tauri::command]
+async fn set_default_workspace(state: State<'_, AppState>, workspace_id: Option<uuid::Uuid>) -> AppResult<()> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    if let Some(workspace_id) = workspace_id {
+        ensure_workspace_member(&state, workspace_id, user_id).await?;
+    }
+    let users = state.user_service.lock().await;
+    if !users.set_default_workspace(user_id, workspace_id).await? {
+        return Err(AppError::NoActiveUser);
+    }
+    Ok(())
+}
+
+/// Longest workspace or template name, matching workspaces.name
+const MAX_WORKSPACE_NAME_CHARS: usize = 255;
+
+/// Longest tag name, matching tags.name
+const MAX_TAG_NAME_CHARS: usize = 100;
+
+/// Create a workspace owned by the current user, optionally starting it
+/// with a template's tags and processing defaults
+#[tauri::command]
+async fn create_workspace(
+    state: State<'_, AppState>,
+    name: String,
+    template_id: Option<uuid::Uuid>,
+) -> AppResult<Workspace> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    let name = validate_workspace_name(&name)?;
+
+    let workspaces = state.workspace_service.lock().await;
+    let template = match template_id {
+        Some(id) => Some(
+            workspaces
+                .get_template(user_id, id)
+                .await?
+                .ok_or_else(|| AppError::NotFound("Workspace template".to_string()))?,
+        ),
+        None => None,
+    };
+    Ok(workspaces
+        .create_workspace_from_template(user_id, name, template.as_ref())
+        .await?)
+}
+
+#[tauri::command]
+async fn list_workspace_templates(state: State<'_, AppState>) -> AppResult<Vec<WorkspaceTemplate>> {
+    let user_id = state.session.current_user_id().await?;
+    let workspaces = state.workspace_service.lock().await;
+    Ok(workspaces.list_templates(user_id).await?)
+}
+
+#[tauri::command]
+async fn create_workspace_template(
+    state: State<'_, AppState>,
+    template: WorkspaceTemplateInput,
+) -> AppResult<WorkspaceTemplate> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    let template = normalize_template(template)?;
+
+    let workspaces = state.workspace_service.lock().await;
+    Ok(workspaces.create_template(user_id, &template).await?)
+}
+
+/// Replace a template's name, tags and options; workspaces it was applied
+/// to keep what they got
+#[tauri::command]
+async fn update_workspace_template(
+    state: State<'_, AppState>,
+    template_id: uuid::Uuid,
+    template: WorkspaceTemplateInput,
+) -> AppResult<WorkspaceTemplate> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    let template = normalize_template(template)?;
+
+    let workspaces = state.workspace_service.lock().await;
+    workspaces
+        .update_template(user_id, template_id, &template)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Workspace template".to_string()))
+}
+
+#[tauri::command]
+async fn delete_workspace_template(state: State<'_, AppState>, template_id: uuid::Uuid) -> AppResult<()> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+
+    let workspaces = state.workspace_service.lock().await;
+    if !workspaces.delete_template(user_id, template_id).await? {
+        return Err(AppError::NotFound("Workspace template".to_string()));
+    }
+    Ok(())
+}
+
+/// Apply a template to an existing workspace, returning how many tags were
+/// created
+///
+/// Only adds: tags the workspace already has, including ones added by hand,
+/// are kept, so applying the same template again changes nothing.
+#[tauri::command]
+async fn apply_workspace_template(
+    state: State<'_, AppState>,
+    workspace_id: uuid::Uuid,
+    template_id: uuid::Uuid,
+) -> AppResult<u64> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    ensure_workspace_member(&state, workspace_id, user_id).await?;
+
+    let workspaces = state.workspace_service.lock().await;
+    let template = workspaces
+        .get_template(user_id, template_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Workspace template".to_string()))?;
+    Ok(workspaces.apply_template(workspace_id, user_id, &template).await?)
+}
+
+/// A workspace or template name, trimmed
+fn validate_workspace_name(name: &str) -> AppResult<&str> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::InvalidInput("Name can't be empty".to_string()));
+    }
+    if name.chars().count() > MAX_WORKSPACE_NAME_CHARS {
+        return Err(AppError::InvalidInput(format!(
+            "Name is longer than {} characters",
+            MAX_WORKSPACE_NAME_CHARS
+        )));
+    }
+    Ok(name)
+}
+
+/// Check a template and tidy its tag names: trimmed, without empty ones
+/// and without repeats, ignoring case
+fn normalize_template(template: WorkspaceTemplateInput) -> AppResult<WorkspaceTemplateInput> {
+    let name = validate_workspace_name(&template.name)?.to_string();
+    if let Some(options) = &template.processing_options {
+        validate_processing_options(options)?;
+    }
+    let mut seen = HashSet::new();
+    let mut tag_names = Vec::new();
+    for tag in template.tag_names.iter().map(|tag| tag.trim()).filter(|tag| !tag.is_empty()) {
+        if tag.chars().count() > MAX_TAG_NAME_CHARS {
+            return Err(AppError::InvalidInput(format!(
+                "Tag \"{}\" is longer than {} characters",
+                tag, MAX_TAG_NAME_CHARS
+            )));
+        }
+        if seen.insert(tag.to_lowercase()) {
+            tag_names.push(tag.to_string());
+        }
+    }
+    Ok(WorkspaceTemplateInput {
+        name,
+        tag_names,
+        processing_options: template.processing_options,
+    })
+}
+
+/// Most frequent terms in the current user's documents, or in a workspace
+/// they belong to
+#[tauri::command]
+async fn get_top_terms(
+    state: State<'_, AppState>,
+    workspace_id: Option<uuid::Uuid>,
+    limit: Option<i64>,
+) -> AppResult<Vec<TermCount>> {
+    let user_id = state.session.current_user_id().await?;
+    let workspace_id = match workspace_id {
+        Some(id) => Some(ensure_workspace_member(&state, id, user_id).await?),
+        None => None,
+    };
+    let limit = limit.unwrap_or(50).clamp(1, 500);
+    
+    let service = state.document_service.lock().await;
+    Ok(service.top_terms(user_id, workspace_id, limit).await?)
+}
+
+/// How use of a term changed over time, by when documents were created
+#[tauri::command]
+async fn get_term_trend(
+    state: State<'_, AppState>,
+    term: String,
+    bucket: Option<TrendBucket>,
+    workspace_id: Option<uuid::Uuid>,
+) -> AppResult<Vec<TermTrendPoint>> {
+    let user_id = state.session.current_user_id().await?;
+    // Terms are stored lowercased, as the tokenizer produces them
+    let term = term.trim().to_lowercase();
+    if term.is_empty() {
+        return Err(AppError::InvalidInput("Term must not be empty".to_string()));
+    }
+    let workspace_id = match workspace_id {
+        Some(id) => Some(ensure_workspace_member(&state, id, user_id).await?),
+        None => None,
+    };
+    
+    let service = state.document_service.lock().await;
+    Ok(service
+        .term_trend(user_id, workspace_id, &term, bucket.unwrap_or_default())
+        .await?)
+}
+
+/// A workspace id the user is a member of, else NotFound
+async fn ensure_workspace_member(
+    state: &AppState,
+    workspace_id: uuid::Uuid,
+    user_id: uuid::Uuid,
+) -> AppResult<uuid::Uuid> {
+    let workspaces = state.workspace_service.lock().await;
+    if !workspaces.is_member(workspace_id, user_id).await? {
+        return Err(AppError::NotFound("Workspace".to_string()));
+    }
+    Ok(workspace_id)
+}
+
+/// Count terms of completed documents that have none yet, e.g. those
+/// processed before term statistics existed
+async fn backfill_term_stats(state: &AppState) -> AppResult<usize> {
+    const BATCH: i64 = 100;
+    let mut counted = 0;
+    let mut after = None;
+    loop {
+        let batch = {
+            let service = state.document_service.lock().await;
+            service.documents_without_terms(after, BATCH).await?
+        };
+        let Some((last_id, _)) = batch.last() else {
+            break;
+        };
+        after = Some(*last_id);
+        
+        let counts = tokio::task::spawn_blocking(move || {
+            batch
+                .into_iter()
+                .map(|(id, content)| (id, keywords::term_counts(&content)))
+                .collect::<Vec<_>>()
+        })
+        .await?;
+        
+        let service = state.document_service.lock().await;
+        for (id, terms) in counts {
+            service.replace_terms(id, &terms).await?;
+            counted += 1;
+        }
+    }
+    Ok(counted)
+}
+
+#[tauri::command]
+async fn get_storage_status(state: State<'_, AppState>) -> AppResult<StorageStatus> {
+    let user_id = state.session.current_user_id().await?;
+    let usage = {
+        let users = state.user_service.lock().await;
+        users.get_storage_usage(user_id).await?
+    }
+    .ok_or(AppError::NoActiveUser)?;
+    
+    Ok(storage::storage_status(&usage, &state.settings.get().await))
+}
+
+/// Fail with `QuotaExceeded` unless `bytes` more of managed files fit
+fn ensure_quota(usage: &StorageUsage, bytes: i64) -> AppResult<()> {
+    if usage.managed_bytes + bytes > usage.limit_bytes {
+        return Err(AppError::QuotaExceeded {
+            needed: bytes,
+            available: (usage.limit_bytes - usage.managed_bytes).max(0),
+        });
+    }
+    Ok(())
+}
+
+/// What the current user could delete to free quota, by category
+#[tauri::command]
+async fn get_cleanup_suggestions(
+    state: State<'_, AppState>,
+    largest_count: Option<usize>,
+) -> AppResult<CleanupSuggestions> {
+    let user_id = state.session.current_user_id().await?;
+    let service = state.document_service.lock().await;
+    cleanup::cleanup_suggestions(
+        &service,
+        user_id,
+        largest_count.unwrap_or(cleanup::DEFAULT_LARGEST_COUNT),
+    )
+    .await
+}
+
+/// Fix drift in the managed and referenced storage figures by recomputing
+/// them from the documents table
+#[tauri::command]
+async fn recompute_storage_usage(state: State<'_, AppState>) -> AppResult<StorageCorrection> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    // Referenced paths are canonical, so compare them with the canonical root
+    let root = storage::documents_dir(&*state.host, &state.settings.get().await)?;
+    let root = std::fs::canonicalize(&root).unwrap_or(root);
+    let users = state.user_service.lock().await;
+    users
+        .recompute_storage_usage(user_id, &root)
+        .await?
+        .ok_or(AppError::NoActiveUser)
+}
+
+#[tauri::command]
+async fn migrate_storage(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    new_path: String,
+    dry_run: Option<bool>,
+) -> AppResult<StorageMigrationReport> {
+    ensure_writable(&state)?;
+    let current_root = storage::documents_dir(&app, &state.settings.get().await)?;
+    let target_root = PathBuf::from(&new_path);
+    if !target_root.is_absolute() {
+        return Err(AppError::InvalidInput("Storage location must be an absolute path".to_string()));
+    }
+    
+    let _file_jobs = state
+        .file_jobs
+        .try_lock()
+        .map_err(|_| AppError::Busy("A backup is running; try again when it finishes".to_string()))?;
+    let operation = start_operation(&state, "storage_migration");
+    let service = state.storage_migration_service.lock().await;
+    storage::migrate_storage(
+        &app,
+        &service,
+        &state.settings,
+        &current_root,
+        &target_root,
+        dry_run.unwrap_or(false),
+        &operation,
+    )
+    .await
+}
+
+#[tauri::command]
+async fn get_backup_status(state: State<'_, AppState>) -> AppResult<BackupStatus> {
+    let settings = state.settings.get().await;
+    let mut status = state.backup_status.read().await.clone();
+    status.next_due_at = settings
+        .backup_enabled
+        .then(|| last_backup_run(&settings, &status))
+        .map(|last| match last {
+            Some(last) => last + chrono::Duration::hours(settings.backup_interval_hours as i64),
+            None => chrono::Utc::now(),
+        });
+    Ok(status)
+}
+
+/// Back up now regardless of the schedule
+#[tauri::command]
+async fn run_backup_now(state: State<'_, AppState>) -> AppResult<BackupStatus> {
+    run_backup(&state).await?;
+    Ok(state.backup_status.read().await.clone())
+}
+
+/// Later of the newest backup on disk and the last attempt this session
+fn last_backup_run(settings: &AppSettings, status: &BackupStatus) -> Option<chrono::DateTime<chrono::Utc>> {
+    let on_disk = settings.backup_dir.as_deref().and_then(backup::latest_backup_time);
+    on_disk.max(status.last_attempt_at)
+}
+
+/// Run one backup, recording the outcome in `backup_status`
+///
+/// Failures are reported with a "backup:warning" event; the scheduler won't
+/// try again until the next interval.
+async fn run_backup(state: &AppState) -> AppResult<()> {
+    let _file_jobs = state.file_jobs.try_lock().map_err(|_| {
+        AppError::Busy("Another backup or a storage migration is already running".to_string())
+    })?;
+    ensure_storage_online(state).await?;
+    let settings = state.settings.get().await;
+    let backup_dir = settings
+        .backup_dir
+        .clone()
+        .ok_or_else(|| AppError::InvalidInput("No backup directory is configured".to_string()))?;
+    
+    let operation = start_operation(state, "backup");
+    {
+        let mut status = state.backup_status.write().await;
+        status.running = true;
+        status.operation_id = Some(operation.id());
+        status.last_attempt_at = Some(chrono::Utc::now());
+    }
+    
+    let result = match state.keyring.store(&settings) {
+        Ok(store) => {
+            let documents = state.document_service.lock().await;
+            let workspaces = state.workspace_service.lock().await;
+            let cancel = operation.token();
+            backup::export_backup(&documents, &workspaces, store, &backup_dir, settings.keep_last_n, cancel).await
+        }
+        Err(e) => Err(e),
+    };
+    
+    let mut status = state.backup_status.write().await;
+    status.running = false;
+    status.operation_id = None;
+    let (outcome, notification) = match result {
+        Ok((path, summary)) => {
+            status.last_success_at = status.last_attempt_at;
+            status.last_path = Some(path.to_string_lossy().to_string());
+            status.last_document_count = Some(summary.document_count);
+            status.last_error = None;
+            let body = format!("{} documents saved to {}", summary.document_count, path.display());
+            (Ok(()), Some(("backup_succeeded", "Backup finished", body)))
+        }
+        Err(e) => {
+            eprintln!("Backup failed: {}", e);
+            status.last_error = Some(e.to_string());
+            let notification = if matches!(e, AppError::Cancelled) {
+                None
+            } else {
+                state.host.emit("backup:warning", &e);
+                Some(("backup_failed", "Backup failed", e.to_string()))
+            };
+            (Err(e), notification)
+        }
+    };
+    drop(status);
+    
+    // Backups cover every user; the outcome goes to whoever is signed in
+    if let (Some((kind, title, body)), Ok(user_id)) = (notification, state.session.current_user_id().await) {
+        state
+            .notifications
+            .notify(NewNotification {
+                user_id,
+                kind,
+                title,
+                body: Some(&body),
+                document_id: None,
+            })
+            .await;
+    }
+    outcome
+}
+
+/// Check on launch and then periodically whether a scheduled backup is due
+async fn run_backup_scheduler(app: tauri::AppHandle) {
+    let mut settings_changes = app.state::<AppState>().settings.subscribe("backup_scheduler");
+    loop {
+        let state = app.state::<AppState>();
+        let settings = state.settings.get().await;
+        let last_run = last_backup_run(&settings, &*state.backup_status.read().await);
+        let due = backup::is_due(&settings, last_run, chrono::Utc::now());
+        settings_changes.acknowledge();
+        if due {
+            // Errors are already recorded and reported by run_backup
+            let _ = run_backup(&state).await;
+        }
+        // A changed schedule, e.g. backups just enabled, is checked right away
+        tokio::select! {
+            _ = tokio::time::sleep(backup::BACKUP_CHECK_INTERVAL) => {}
+            _ = settings_changes.changed() => {}
+        }
+    }
+}
+
+/// Package one of the current user's workspaces so another local user can import it
+#[tauri::command]
+async fn export_workspace_package(
+    state: State<'_, AppState>,
+    workspace_id: uuid::Uuid,
+    dest_path: String,
+) -> AppResult<PackageExportSummary> {
+    let user_id = state.session.current_user_id().await?;
+    ensure_storage_online(&state).await?;
+    
+    let workspaces = state.workspace_service.lock().await;
+    workspaces
+        .get_workspace(workspace_id)
+        .await?
+        .filter(|w| w.owner_id == user_id)
+        .ok_or_else(|| AppError::NotFound("Workspace".to_string()))?;
+    
+    let store = state.keyring.store(&state.settings.get().await)?;
+    let operation = start_operation(&state, "workspace_export");
+    let documents = state.document_service.lock().await;
+    workspace_package::export_workspace(
+        &workspaces,
+        &documents,
+        store,
+        workspace_id,
+        &PathBuf::from(dest_path),
+        operation.token(),
+    )
+    .await
+}
+
+/// Import a workspace package as a new workspace owned by `as_user_id`
+#[tauri::command]
+async fn import_workspace_package(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    path: String,
+    as_user_id: uuid::Uuid,
+) -> AppResult<WorkspaceImportReport> {
+    ensure_writable(&state)?;
+    {
+        let users = state.user_service.lock().await;
+        users
+            .get_user(as_user_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound("User".to_string()))?;
+    }
+    
+    let documents_dir = storage::online_documents_dir(&app, &state.settings.get().await)?;
+    let operation = start_operation(&state, "workspace_import");
+    let workspaces = state.workspace_service.lock().await;
+    workspace_package::import_workspace(
+        &workspaces,
+        &state.processing_context(),
+        &PathBuf::from(path),
+        as_user_id,
+        &documents_dir,
+        &operation,
+    )
+    .await
+}
+
+/// Write the current user's tags and workspace names to a JSON file, without
+/// any documents, to share with someone else
+#[tauri::command]
+async fn export_library_config(state: State<'_, AppState>, dest_path: String) -> AppResult<LibraryConfigSummary> {
+    let user_id = state.session.current_user_id().await?;
+    let workspaces = state.workspace_service.lock().await;
+    let tags = state.tag_service.lock().await;
+    library_config::export_config(&workspaces, &tags, user_id, &PathBuf::from(dest_path)).await
+}
+
+/// Add the tags and workspaces of a library config file to the current
+/// user's library; names already taken are handled as `merge_strategy` says
+#[tauri::command]
+async fn import_library_config(
+    state: State<'_, AppState>,
+    path: String,
+    merge_strategy: Option<MergeStrategy>,
+) -> AppResult<LibraryConfigImportReport> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    let workspaces = state.workspace_service.lock().await;
+    let tags = state.tag_service.lock().await;
+    library_config::import_config(
+        &workspaces,
+        &tags,
+        user_id,
+        &PathBuf::from(path),
+        merge_strategy.unwrap_or_default(),
+    )
+    .await
+}
+
+/// Fuzzy match the current user's document titles for the quick switcher
+#[tauri::command]
+async fn fuzzy_find(
+    state: State<'_, AppState>,
+    query: String,
+    limit: Option<usize>,
+) -> AppResult<Vec<QuickOpenMatch>> {
+    let user_id = state.session.current_user_id().await?;
+    let index = Arc::clone(&state.quick_index);
+    let matches = tokio::task::spawn_blocking(move || {
+        index.find(user_id, &query, limit.unwrap_or(20))
+    })
+    .await?;
+    
+    Ok(matches)
+}
+
+/// Reload the quick switcher index from the database, returning its size
+#[tauri::command]
+async fn rebuild_quick_index(state: State<'_, AppState>) -> AppResult<usize> {
+    let service = state.document_service.lock().await;
+    Ok(service.rebuild_quick_index().await?)
+}
+
+#[tauri::command]
+async fn get_encryption_status(state: State<'_, AppState>) -> AppResult<EncryptionStatus> {
+    Ok(EncryptionStatus {
+        enabled: state.settings.get().await.encryption_enabled,
+        unlocked: state.keyring.is_unlocked(),
+    })
+}
+
+/// Turn on encryption at rest; new files are encrypted from now on
+///
+/// Files stored earlier stay readable and are converted by
+/// `encrypt_existing_files`. There is no way to recover the passphrase.
+#[tauri::command]
+async fn enable_encryption(state: State<'_, AppState>, passphrase: String) -> AppResult<EncryptionStatus> {
+    if state.settings.get().await.encryption_enabled {
+        return Err(AppError::InvalidInput("Encryption is already enabled".to_string()));
+    }
+    
+    let keyring = Arc::clone(&state.keyring);
+    let material = tokio::task::spawn_blocking(move || keyring.initialize(&passphrase)).await??;
+    state
+        .settings
+        .update(|s| {
+            s.encryption_enabled = true;
+            s.encryption_salt = Some(material.salt);
+            s.encryption_check = Some(material.check);
+        })
+        .await?;
+    
+    get_encryption_status(state).await
+}
+
+/// Derive the library key from the passphrase and hold it for this session
+#[tauri::command]
+async fn unlock_library(state: State<'_, AppState>, passphrase: String) -> AppResult<EncryptionStatus> {
+    let settings = state.settings.get().await;
+    if !settings.encryption_enabled {
+        return Err(AppError::InvalidInput("Encryption is not enabled".to_string()));
+    }
+    
+    let keyring = Arc::clone(&state.keyring);
+    tokio::task::spawn_blocking(move || keyring.unlock(&settings, &passphrase)).await??;
+    get_encryption_status(state).await
+}
+
+#[tauri::command]
+async fn lock_library(state: State<'_, AppState>) -> AppResult<EncryptionStatus> {
+    state.keyring.lock();
+    get_encryption_status(state).await
+}
+
+/// Encrypt files stored before encryption was enabled
+///
+/// Each file is encrypted to a temporary copy, verified and then swapped in,
+/// so the command can be re-run after an interruption. Progress is reported
+/// with "storage:encryption-progress" events.
+#[tauri::command]
+async fn encrypt_existing_files(state: State<'_, AppState>) -> AppResult<EncryptionReport> {
+    if !state.settings.get().await.encryption_enabled {
+        return Err(AppError::InvalidInput("Enable encryption first".to_string()));
+    }
+    let store = state.keyring.encrypted_store()?;
+    let _file_jobs = state.file_jobs.try_lock().map_err(|_| {
+        AppError::Busy("A backup or storage migration is running; try again when it finishes".to_string())
+    })?;
+    ensure_storage_online(&state).await?;
+    
+    // External files belong to the user and are left as they are
+    let files: Vec<_> = {
+        let service = state.document_service.lock().await;
+        service.list_file_references().await?
+    }
+    .into_iter()
+    .filter(|file| !file.external)
+    .collect();
+    
+    let operation = start_operation(&state, "encrypt_files");
+    let total = files.len();
+    let mut report = EncryptionReport::default();
+    for (index, file) in files.into_iter().enumerate() {
+        if operation.is_cancelled() {
+            report.cancelled_after = Some(index);
+            break;
+        }
+        let path = PathBuf::from(&file.file_path);
+        if path.is_file() {
+            let store = store.clone();
+            match tokio::task::spawn_blocking(move || file_store::encrypt_in_place(&store, &path)).await? {
+                Ok(true) => report.encrypted += 1,
+                Ok(false) => report.already_encrypted += 1,
+                Err(e) => report.failed.push(MigrationFailure {
+                    document_id: file.id,
+                    error: e.to_string(),
+                }),
+            }
+        } else {
+            report.missing.push(file.id);
+        }
+        
+        operation.set_progress(index + 1, total);
+        state.host.emit(
+            "storage:encryption-progress",
+            EncryptionProgress {
+                operation_id: operation.id(),
+                processed: index + 1,
+                total,
+                document_id: file.id,
+            },
+        );
+    }
+    
+    Ok(report)
+}
+
+/// Register a long-running command and announce it with "operation:started"
+fn start_operation(state: &AppState, kind: &str) -> OperationHandle {
+    let operation = state.operations.start(kind);
+    state.host.emit(
+        "operation:started",
+        serde_json::json!({ "operation_id": operation.id(), "kind": kind }),
+    );
+    operation
+}
+
+/// Running long-running commands with their progress
+#[tauri::command]
+async fn list_operations(state: State<'_, AppState>) -> AppResult<Vec<OperationInfo>> {
+    Ok(state.operations.list())
+}
+
+/// Ask a running operation to stop after its current item
+///
+/// Unknown or already finished operations are ignored.
+#[tauri::command]
+async fn cancel_operation(state: State<'_, AppState>, operation_id: uuid::Uuid) -> AppResult<()> {
+    state.operations.cancel(operation_id);
+    Ok(())
+}
+
+async fn scan_consistency(app: &tauri::AppHandle, state: &AppState) -> AppResult<ConsistencyReport> {
+    let storage_root = storage::documents_dir(app, &state.settings.get().await)?;
+    let thumbnails_dir = app.path().app_data_dir()?.join("thumbnails");
+    
+    let service = state.document_service.lock().await;
+    consistency::scan_inconsistencies(&service, &storage_root, &thumbnails_dir).await
+}
+
+/// Report stored files and document rows that no longer match up
+#[tauri::command]
+async fn check_consistency(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> AppResult<ConsistencyReport> {
+    scan_consistency(&app, &state).await
+}
+
+/// Rescan and fix inconsistencies; orphans younger than the minimum age are kept
+#[tauri::command]
+async fn repair_inconsistencies(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    options: Option<RepairOptions>,
+) -> AppResult<RepairReport> {
+    ensure_writable(&state)?;
+    let report = scan_consistency(&app, &state).await?;
+    let storage_root = storage::documents_dir(&app, &state.settings.get().await)?;
+    
+    let service = state.document_service.lock().await;
+    let activity = state.activity_logger.lock().await;
+    consistency::repair_inconsistencies(
+        &service,
+        &activity,
+        &report,
+        &options.unwrap_or_default(),
+        &storage_root,
+    )
+    .await
+}
+
+/// Whether the storage root is reachable
+///
+/// When it is, documents flagged missing whose files are back, e.g. after
+/// the drive was reconnected, are restored on the way.
+#[tauri::command]
+async fn get_storage_health(app: tauri::AppHandle, state: State<'_, AppState>) -> AppResult<StorageHealth> {
+    let storage_root = storage::documents_dir(&app, &state.settings.get().await)?;
+    let storage_online = storage::ensure_online(&storage_root).is_ok();
+    // Restoring marks documents as present again, which needs writes; external
+    // files can come back even while the storage root is offline
+    let restored_documents = if !state.read_only.load(Ordering::Relaxed) {
+        let service = state.document_service.lock().await;
+        let activity = state.activity_logger.lock().await;
+        consistency::restore_returned_files(&service, &activity, &storage_root).await?
+    } else {
+        0
+    };
+    
+    Ok(StorageHealth {
+        storage_root: storage_root.to_string_lossy().to_string(),
+        storage_online,
+        restored_documents,
+    })
+}
+
+/// Whether the database is writable and the storage root reachable, and
+/// how many processing jobs panicked
+#[tauri::command]
+async fn get_system_health(app: tauri::AppHandle, state: State<'_, AppState>) -> AppResult<SystemHealth> {
+    let storage_root = storage::documents_dir(&app, &state.settings.get().await)?;
+    let storage_online = storage::ensure_online(&storage_root).is_ok();
+    let storage_free_bytes = if storage_online {
+        file_utils::available_space(&storage_root).ok()
+    } else {
+        None
+    };
+    Ok(SystemHealth {
+        read_only: state.read_only.load(Ordering::Relaxed),
+        storage_root: storage_root.to_string_lossy().to_string(),
+        storage_online,
+        storage_free_bytes,
+        processing_panics: state.processing_panics.load(Ordering::Relaxed),
+    })
+}
+
+/// Check again whether the database accepts writes, e.g. after its
+/// permissions were fixed, and leave or enter read-only mode to match
+///
+/// Returns whether writes are accepted now.
+#[tauri::command]
+async fn probe_write_access(app: tauri::AppHandle, state: State<'_, AppState>) -> AppResult<bool> {
+    let writable = db::probe_write_access(&state.pool).await?;
+    let was_read_only = state.read_only.swap(!writable, Ordering::Relaxed);
+    if was_read_only == writable {
+        eprintln!("Database is {}", if writable { "writable again" } else { "now read-only" });
+        let _ = app.emit("system:read-only-changed", !writable);
+    }
+    Ok(writable)
+}
+
+/// Fail with ReadOnlyMode while the database refuses writes
+fn ensure_writable(state: &AppState) -> AppResult<()> {
+    if state.read_only.load(Ordering::Relaxed) {
+        return Err(AppError::ReadOnlyMode);
+    }
+    Ok(())
+}
+
+/// Fail with StorageOffline if the current storage root can't be reached
+async fn ensure_storage_online(state: &AppState) -> AppResult<()> {
+    storage::online_documents_dir(&*state.host, &state.settings.get().await).map(|_| ())
+}
+
+/// Relay stored document changes to the UI as "documents:changed"
+async fn forward_document_changes(
+    host: Arc<dyn Host>,
+    mut changes: tokio::sync::broadcast::Receiver<DocumentChange>,
+) {
+    use tokio::sync::broadcast::error::RecvError;
+    loop {
+        match changes.recv().await {
+            Ok(change) => host.emit("documents:changed", &change),
+            Err(RecvError::Lagged(missed)) => {
+                // Too many to replay one by one; have the UI reload instead
+                eprintln!("Dropped {} document change events", missed);
+                host.emit("documents:resync", ());
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Wait between attempts at the app lock while aks-cli holds it
+const APP_LOCK_RETRY: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Hold `db::APP_LOCK_KEY` for as long as the app runs, so aks-cli leaves
+/// the library alone meanwhile
+///
+/// While the tool holds the lock, e.g. during an import, it is taken as
+/// soon as the tool is done.
+async fn hold_app_lock(pool: sqlx::PgPool) {
+    loop {
+        match db::try_app_lock(&pool).await {
+            Ok(Some(_lock)) => return std::future::pending().await,
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to take the app lock: {}", e),
+        }
+        tokio::time::sleep(APP_LOCK_RETRY).await;
+    }
+}
+
+/// Start processing the documents left queued with their file stored
+///
+/// Those are documents aks-cli imported or requeued while the app was
+/// closed, and uploads the app was closed during after their file was
+/// stored. Documents whose storage is offline wait for the next start.
+async fn process_queued(state: &AppState) -> AppResult<usize> {
+    let queued = state.document_service.lock().await.queued_with_files().await?;
+    let storage_online = ensure_storage_online(state).await.is_ok();
+    
+    let mut started = 0;
+    for (doc_id, file_path, mime_type, external) in queued {
+        if !external && !storage_online {
+            continue;
+        }
+        processing::spawn_processing(
+            state.processing_context(),
+            doc_id,
+            PathBuf::from(file_path),
+            mime_type.unwrap_or_else(|| file_utils::UNKNOWN_MIME_TYPE.to_string()),
+            DocumentStatus::Queued,
+            PdfLayout::Auto,
+        );
+        started += 1;
+    }
+    Ok(started)
+}
+
+/// Background upkeep run once after startup
+///
+/// Inconsistencies are only reported; repairs are left to the user.
+async fn run_maintenance(app: &tauri::AppHandle, launched_at: chrono::DateTime<chrono::Utc>) {
+    let state = app.state::<AppState>();
+    match scan_consistency(app, &state).await {
+        Ok(report) => {
+            eprintln!(
+                "Consistency check: {} orphan files, {} missing files, {} stale thumbnails",
+                report.orphan_files.len(),
+                report.dangling_documents.len(),
+                report.stale_thumbnails.len()
+            );
+            if !state.read_only.load(Ordering::Relaxed) {
+                notify_missing_files(&state, &report).await;
+            }
+        }
+        Err(e) => eprintln!("Consistency check failed: {}", e),
+    }
+    
+    // Everything below writes to the database
+    if state.read_only.load(Ordering::Relaxed) {
+        eprintln!("Database is read-only; skipping maintenance");
+        return;
+    }
+    
+    match storage::documents_dir(app, &state.settings.get().await) {
+        Ok(storage_root) => {
+            let service = state.document_service.lock().await;
+            let activity = state.activity_logger.lock().await;
+            match consistency::restore_returned_files(&service, &activity, &storage_root).await {
+                Ok(0) => {}
+                Ok(restored) => eprintln!("Restored {} documents whose files are back", restored),
+                Err(e) => eprintln!("Failed to restore returned files: {}", e),
+            }
+        }
+        Err(e) => eprintln!("Failed to resolve storage root: {}", e),
+    }
+    
+    // Imports the app was closed during never finish on their own; their
+    // documents still waiting are reported as unfinished
+    let interrupted = state.import_session_service.lock().await.finish_interrupted(launched_at).await;
+    match interrupted {
+        Ok(sessions) => {
+            for (session_id, user_id) in sessions {
+                notify_import_finished(app, &state.import_session_service, &state.notifications, user_id, session_id)
+                    .await;
+            }
+        }
+        Err(e) => eprintln!("Failed to finish interrupted imports: {}", e),
+    }
+    
+    // Focus sessions the app was closed during
+    match state.focus_session_service.lock().await.close_interrupted(launched_at).await {
+        Ok(0) => {}
+        Ok(closed) => eprintln!("Auto-closed {} focus sessions left open", closed),
+        Err(e) => eprintln!("Failed to close interrupted focus sessions: {}", e),
+    }
+    
+    // Extractors registered since documents were stored without one
+    match process_newly_supported(&state, None).await {
+        Ok(report) if report.processing_started > 0 => {
+            eprintln!("Processing {} documents an extractor now supports", report.processing_started)
+        }
+        Ok(_) => {}
+        Err(e) => eprintln!("Failed to process newly supported documents: {}", e),
+    }
+    
+    match process_queued(&state).await {
+        Ok(0) => {}
+        Ok(started) => eprintln!("Processing {} queued documents", started),
+        Err(e) => eprintln!("Failed to process queued documents: {}", e),
+    }
+    
+    // Documents created before sort keys existed
+    match rebuild_title_sort_keys(&state, true).await {
+        Ok(0) => {}
+        Ok(built) => eprintln!("Built {} missing title sort keys", built),
+        Err(e) => eprintln!("Failed to build title sort keys: {}", e),
+    }
+    
+    if state.settings.get().await.weekly_digest_enabled {
+        match send_due_digests(&state).await {
+            Ok(0) => {}
+            Ok(sent) => eprintln!("Sent {} weekly digests", sent),
+            Err(e) => eprintln!("Failed to send weekly digests: {}", e),
+        }
+    }
+    
+    // Documents processed before term statistics existed
+    match backfill_term_stats(&state).await {
+        Ok(0) => {}
+        Ok(counted) => eprintln!("Counted terms of {} documents", counted),
+        Err(e) => eprintln!("Failed to count document terms: {}", e),
+    }
+    
+    {
+        let runs = state.processing_run_service.lock().await;
+        match runs.prune(services::processing_run::RUNS_KEPT_PER_DOCUMENT).await {
+            Ok(0) => {}
+            Ok(pruned) => eprintln!("Pruned {} old processing runs", pruned),
+            Err(e) => eprintln!("Failed to prune processing history: {}", e),
+        }
+    }
+    
+    {
+        let feed = state.change_feed_service.lock().await;
+        match feed.prune(services::change_feed::CHANGES_KEPT_DAYS).await {
+            Ok(0) => {}
+            Ok(pruned) => eprintln!("Trimmed {} old change feed entries", pruned),
+            Err(e) => eprintln!("Failed to trim the change feed: {}", e),
+        }
+    }
+    
+    let notifications = state.notification_service.lock().await;
+    match notifications.prune_read(services::notification::READ_NOTIFICATIONS_KEPT_DAYS).await {
+        Ok(0) => {}
+        Ok(pruned) => eprintln!("Pruned {} old notifications", pruned),
+        Err(e) => eprintln!("Failed to prune notifications: {}", e),
+    }
+}
+
+/// Tell each owner about documents whose files have newly gone missing
+///
+/// Documents already marked MissingFile were reported when they were marked.
+async fn notify_missing_files(state: &AppState, report: &ConsistencyReport) {
+    let mut by_user: HashMap<uuid::Uuid, Vec<&DanglingDocument>> = HashMap::new();
+    for dangling in &report.dangling_documents {
+        if dangling.status != DocumentStatus::MissingFile {
+            by_user.entry(dangling.user_id).or_default().push(dangling);
+        }
+    }
+    
+    for (user_id, dangling) in by_user {
+        let document_ids: Vec<uuid::Uuid> = dangling.iter().map(|d| d.document_id).collect();
+        let external = dangling.iter().filter(|d| d.external).count();
+        let mut body = format!(
+            "{} document(s) no longer have their stored file; run a repair to mark them",
+            document_ids.len()
+        );
+        if external > 0 {
+            body.push_str(&format!(
+                ". {} of them are external files that were moved or deleted; relink them to their new location",
+                external
+            ));
+        }
+        notify(
+            &*state.host,
+            &state.notification_service,
+            NewNotification {
+                user_id,
+                kind: "missing_files",
+                title: "Stored files are missing",
+                body: Some(&body),
+                document_id: match document_ids.as_slice() {
+                    [only] => Some(*only),
+                    _ => None,
+                },
+            },
+        )
+        .await;
+    }
+}
+
+/// Record a notification and tell an open UI about it right away
+///
+/// Failures are only logged; a lost notification shouldn't fail the work
+/// it reports on.
+async fn notify(host: &dyn Host, notifications: &Mutex<NotificationService>, new: NewNotification<'_>) {
+    let created = notifications.lock().await.create(new).await;
+    match created {
+        Ok(notification) => host.emit("notification:created", &notification),
+        Err(e) => eprintln!("Failed to record notification: {}", e),
+    }
+}
+
+/// The current user's notifications, newest first
+#[tauri::command]
+async fn list_notifications(
+    state: State<'_, AppState>,
+    unread_only: Option<bool>,
+    limit: Option<i64>,
+) -> AppResult<Vec<Notification>> {
+    let user_id = state.session.current_user_id().await?;
+    let limit = limit.unwrap_or(50).clamp(1, 200);
+    let notifications = state.notification_service.lock().await;
+    Ok(notifications.list(user_id, unread_only.unwrap_or(false), limit).await?)
+}
+
+#[tauri::command]
+async fn mark_notification_read(state: State<'_, AppState>, notification_id: uuid::Uuid) -> AppResult<()> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    let notifications = state.notification_service.lock().await;
+    if !notifications.mark_read(user_id, notification_id).await? {
+        return Err(AppError::NotFound("Notification".to_string()));
+    }
+    Ok(())
+}
+
+/// Mark every notification read; returns how many were unread
+#[tauri::command]
+async fn mark_all_read(state: State<'_, AppState>) -> AppResult<u64> {
+    ensure_writable(&state)?;
+    let user_id = state.session.current_user_id().await?;
+    let notifications = state.notification_service.lock().await;
+    Ok(notifications.mark_all_read(user_id).await?)
+}
+
+#[tauri::command]
+async fn get_unread_notification_count(state: State<'_, AppState>) -> AppResult<i64> {
+    let user_id = state.session.current_user_id().await?;
+    let notifications = state.notification_service.lock().await;
+    Ok(notifications.unread_count(user_id).await?)
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    run_with_registry(ProcessingRegistry::with_builtin())
+}
+
+/// Run the app with a custom set of extractors
+///
+/// Embedders can start from `ProcessingRegistry::with_builtin()`, call
+/// `register_extractor` for their own formats, and pass the registry here.
+pub fn run_with_registry(registry: ProcessingRegistry) {
+    build_app(registry)
+        .run(tauri::generate_context!())
+        .expect("error while running tauri application");
+}
+
+/// The app's builder with its plugins, setup hook and commands, not yet run
+///
+/// Embedders can add plugins and managed state before running it, e.g.
+/// `build_app(registry).plugin(extra).run(context)`. Extra commands belong
+/// in a plugin: the builder holds one invoke handler and one setup hook, so
+/// calling `invoke_handler` or `setup` on it replaces the app's own. Once
+/// setup succeeds, `AppState` is managed and its services can be used from
+/// those commands. See examples/custom_command.rs.
+///
+/// The public modules (`services`, `models`, `settings`, `db`, `error`,
+/// `processing`, `quick_open`) follow the app's needs and may change
+/// between versions without notice.
+pub fn build_app(registry: ProcessingRegistry) -> tauri::Builder<tauri::Wry> {
+    dotenvy::dotenv().ok(); // Load .env file
+    let processing_registry = Arc::new(registry);
+    
+    tauri::Builder::default()
+        .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_notification::init())
+        .setup(|app| {
+            let settings = Arc::new(SettingsStore::load(
+                app.path().app_config_dir()?.join("settings.json"),
+            ));
+            
+            // Initialize database connection
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            let db = match runtime.block_on(db::Database::new()) {
+                Ok(db) => db,
+                Err(e) => {
+                    // Keep the window up so the frontend can explain what to fix
+                    eprintln!("Database setup failed: {} ({})", e.message, e.hint);
+                    app.manage(InitStatus { ready: false, error: Some(e) });
+                    return Ok(());
+                }
+            };
+            
+            let document_changes = DocumentChanges::new();
+            let state = AppState::new(
+                db.pool().clone(),
+                Arc::new(app.handle().clone()),
+                Arc::clone(&settings),
+                processing_registry,
+                document_changes.clone(),
+                runtime.block_on(settings.get()).upload_concurrency,
+            );
+            
+            let read_only = match runtime.block_on(db::probe_write_access(db.pool())) {
+                Ok(writable) => !writable,
+                Err(e) => {
+                    eprintln!("Could not probe database write access: {}", e);
+                    false
+                }
+            };
+            if read_only {
+                eprintln!("Database refuses writes; starting in read-only mode");
+                state.read_only.store(true, Ordering::Relaxed);
+            }
+            tauri::async_runtime::spawn(hold_app_lock(db.pool().clone()));
+            
+            if let Err(e) = runtime.block_on(async { state.document_service.lock().await.rebuild_quick_index().await }) {
+                eprintln!("Failed to build quick open index: {}", e);
+            }
+            
+            // Restore the last active user if they still exist
+            runtime.block_on(async {
+                let saved = settings.get().await.active_user_id?;
+                let user = state.user_service.lock().await.get_user(saved).await.ok().flatten()?;
+                state.session.set_active_user(Some(user.id)).await;
+                Some(())
+            });
+            
+            tauri::async_runtime::spawn(follow_upload_concurrency(
+                Arc::clone(&state.upload_queue),
+                settings.subscribe("upload_queue"),
+            ));
+            let local_api_changes = settings.subscribe("local_api");
+            
+            app.manage(state);
+            app.manage(InitStatus { ready: true, error: None });
+            
+            // Maintenance waits for the app to settle before touching disk and DB
+            let handle = app.handle().clone();
+            let launched_at = chrono::Utc::now();
+            tauri::async_runtime::spawn(async move {
+                tokio::time::sleep(consistency::STARTUP_SCAN_DELAY).await;
+                run_maintenance(&handle, launched_at).await;
+            });
+            
+            tauri::async_runtime::spawn(run_backup_scheduler(app.handle().clone()));
+            tauri::async_runtime::spawn(local_api::serve(app.handle().clone(), local_api_changes));
+            tauri::async_runtime::spawn(forward_document_changes(
+                Arc::new(app.handle().clone()),
+                document_changes.subscribe(),
+            ));
+            
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            init_status,
+            greet,
+            open_file_dialog,
+            list_users,
+            list_notifications,
+            mark_notification_read,
+            mark_all_read,
+            get_unread_notification_count,
+            list_all_documents,
+            set_storage_limit,
+            get_current_user,
+            switch_user,
             upload_file,
+            restore_document,
             create_document,
-            get_user_documents
+            get_user_documents,
+            get_document_counts_by_bucket,
+            pin_document,
+            unpin_document,
+            reorder_pinned,
+            get_document,
+            get_reading_position,
+            save_reading_position,
+            add_to_reading_list,
+            remove_from_reading_list,
+            reorder_reading_list,
+            get_reading_list,
+            get_import_report,
+            attach_note_file,
+            sync_note_file,
+            find_document_by_identifier,
+            get_tag_suggestions,
+            confirm_suggested_tags,
+            search_in_document,
+            diff_documents,
+            search_advanced,
+            get_document_content,
+            cancel_processing,
+            get_processing_history,
+            retry_processing,
+            reprocess_document,
+            export_document_html,
+            export_document_markdown,
+            list_export_snapshots,
+            verify_export,
+            estimate_embedding_job,
+            count_tokens,
+            generate_digest,
+            format_human,
+            get_top_terms,
+            get_tag_counts,
+            list_tags,
+            create_tag,
+            update_tag,
+            delete_tag,
+            get_workspace_counts,
+            set_default_workspace,
+            create_workspace,
+            list_workspace_templates,
+            create_workspace_template,
+            update_workspace_template,
+            delete_workspace_template,
+            apply_workspace_template,
+            get_term_trend,
+            rebuild_sort_keys,
+            rebuild_derived_data,
+            redetect_file_types,
+            compress_existing_content,
+            process_unsupported,
+            get_supported_types,
+            read_thumbnail_bytes,
+            read_export_preview,
+            import_folder,
+            list_redaction_rules,
+            add_redaction_rule,
+            delete_redaction_rule,
+            preview_redaction,
+            extract_pages,
+            get_settings,
+            update_settings,
+            get_storage_status,
+            recompute_storage_usage,
+            convert_to_managed,
+            get_cleanup_suggestions,
+            migrate_storage,
+            get_backup_status,
+            run_backup_now,
+            check_consistency,
+            export_workspace_package,
+            import_workspace_package,
+            export_library_config,
+            import_library_config,
+            fuzzy_find,
+            rebuild_quick_index,
+            list_operations,
+            cancel_operation,
+            get_encryption_status,
+            enable_encryption,
+            unlock_library,
+            lock_library,
+            encrypt_existing_files,
+            repair_inconsistencies,
+            get_storage_health,
+            get_system_health,
+            probe_write_access,
+            relink_document,
+            get_form_fields,
+            get_extracted_fields,
+            resummarize_document,
+            boost_processing_priority,
+            export_search_results,
+            export_catalog,
+            start_session,
+            end_session,
+            get_session_report,
+            create_share_token,
+            list_share_tokens,
+            revoke_share_token,
+            preview_bulk_rename,
+            apply_bulk_rename,
+            eval_pipelines,
+            mark_as_read,
+            mark_as_unread,
+            get_unread_document_count,
+            get_documents_status,
+            get_documents_changed_since,
+            poll_changes
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
 }
@@ -0,0 +1,279 @@
+//! Library configuration files: a user's tags and workspaces as JSON,
+//! without any documents, for sharing a taxonomy with someone else
+//!
+//! Workspaces are carried by name and each tag names its workspace, so an
+//! import resolves both to the importer's own ids. Name collisions are
+//! settled by the chosen MergeStrategy. A tag or workspace that can't be
+//! imported is reported and the rest carry on; nothing is deleted.
+
+use crate::error::{AppError, AppResult};
+use crate::models::{
+    ConfigImportFailure, ConfigRename, LibraryConfigImportReport, LibraryConfigSummary, MergeStrategy, Tag,
+};
+use crate::services::{TagService, WorkspaceService};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use uuid::Uuid;
+
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct LibraryConfig {
+    format_version: u32,
+    exported_at: chrono::DateTime<chrono::Utc>,
+    workspaces: Vec<ConfigWorkspace>,
+    tags: Vec<ConfigTag>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ConfigWorkspace {
+    name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ConfigTag {
+    name: String,
+    #[serde(default)]
+    color: Option<String>,
+    #[serde(default)]
+    description: Option<String>,
+    /// Name of the tag's workspace; None for tags outside workspaces
+    #[serde(default)]
+    workspace: Option<String>,
+}
+
+/// Tags by workspace and lowercase name
+type TagIndex = HashMap<(Option<Uuid>, String), Tag>;
+
+/// Write the user's workspace names and tags to `dest`
+///
+/// Workspaces with the same name, ignoring case, are written once. Tags of
+/// deleted workspaces are left out.
+pub async fn export_config(
+    workspaces: &WorkspaceService,
+    tags: &TagService,
+    user_id: Uuid,
+    dest: &Path,
+) -> AppResult<LibraryConfigSummary> {
+    let listed = workspaces.list_workspaces(user_id).await?;
+    let names: HashMap<Uuid, &str> = listed.iter().map(|w| (w.id, w.name.as_str())).collect();
+    let mut seen = HashSet::new();
+    let config_workspaces = listed
+        .iter()
+        .filter(|w| seen.insert(w.name.to_lowercase()))
+        .map(|w| ConfigWorkspace { name: w.name.clone() })
+        .collect();
+
+    let mut config_tags = Vec::new();
+    for tag in tags.list_tags(user_id).await? {
+        let workspace = match tag.workspace_id {
+            Some(id) => match names.get(&id) {
+                Some(name) => Some(name.to_string()),
+                None => continue,
+            },
+            None => None,
+        };
+        config_tags.push(ConfigTag {
+            name: tag.name,
+            color: tag.color,
+            description: tag.description,
+            workspace,
+        });
+    }
+
+    let config = LibraryConfig {
+        format_version: FORMAT_VERSION,
+        exported_at: chrono::Utc::now(),
+        workspaces: config_workspaces,
+        tags: config_tags,
+    };
+    let json = serde_json::to_string_pretty(&config).map_err(|e| AppError::Other(e.to_string()))?;
+    std::fs::write(dest, json)?;
+
+    Ok(LibraryConfigSummary {
+        workspace_count: config.workspaces.len(),
+        tag_count: config.tags.len(),
+    })
+}
+
+/// Create the workspaces and tags of a config file for `user_id`
+///
+/// Workspaces are matched by name, ignoring case, against those the user
+/// owns; tags against the user's tags in the same workspace. A workspace
+/// named only by a tag is imported as if it were listed.
+pub async fn import_config(
+    workspaces: &WorkspaceService,
+    tags: &TagService,
+    user_id: Uuid,
+    path: &Path,
+    strategy: MergeStrategy,
+) -> AppResult<LibraryConfigImportReport> {
+    let raw = std::fs::read_to_string(path)?;
+    let config: LibraryConfig =
+        serde_json::from_str(&raw).map_err(|e| AppError::InvalidInput(format!("Invalid library config: {}", e)))?;
+    if config.format_version != FORMAT_VERSION {
+        return Err(AppError::InvalidInput(format!(
+            "Unsupported library config version {}",
+            config.format_version
+        )));
+    }
+
+    let mut report = LibraryConfigImportReport::default();
+    let mut owned: HashMap<String, Uuid> = workspaces
+        .list_workspaces(user_id)
+        .await?
+        .into_iter()
+        .filter(|w| w.owner_id == user_id)
+        .map(|w| (w.name.to_lowercase(), w.id))
+        .collect();
+    // The file's workspace names, lowercase, to the importer's ids
+    let mut resolved: HashMap<String, Uuid> = HashMap::new();
+    let named = config
+        .workspaces
+        .iter()
+        .map(|w| w.name.trim())
+        .chain(config.tags.iter().filter_map(|t| t.workspace.as_deref().map(str::trim)));
+    for name in named {
+        let key = name.to_lowercase();
+        if resolved.contains_key(&key) {
+            continue;
+        }
+        match import_workspace(workspaces, user_id, name, strategy, &mut owned, &mut report).await {
+            Ok(id) => {
+                resolved.insert(key, id);
+            }
+            Err(e) => report.failed.push(ConfigImportFailure {
+                name: name.to_string(),
+                workspace: None,
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    let mut existing: TagIndex = tags
+        .list_tags(user_id)
+        .await?
+        .into_iter()
+        .map(|tag| ((tag.workspace_id, tag.name.to_lowercase()), tag))
+        .collect();
+    for tag in &config.tags {
+        if let Err(e) = import_tag(tags, user_id, tag, strategy, &resolved, &mut existing, &mut report).await {
+            report.failed.push(ConfigImportFailure {
+                name: tag.name.clone(),
+                workspace: tag.workspace.clone(),
+                error: e.to_string(),
+            });
+        }
+    }
+    Ok(report)
+}
+
+async fn import_workspace(
+    workspaces: &WorkspaceService,
+    user_id: Uuid,
+    name: &str,
+    strategy: MergeStrategy,
+    owned: &mut HashMap<String, Uuid>,
+    report: &mut LibraryConfigImportReport,
+) -> AppResult<Uuid> {
+    if name.is_empty() {
+        return Err(AppError::InvalidInput("Workspace name can't be empty".to_string()));
+    }
+    let imported_as = match owned.get(&name.to_lowercase()) {
+        None => name.to_string(),
+        Some(&id) if strategy != MergeStrategy::Rename => {
+            report.workspaces_reused += 1;
+            return Ok(id);
+        }
+        Some(_) => free_name(name, |candidate| owned.contains_key(&candidate.to_lowercase())),
+    };
+
+    let workspace = workspaces.create_workspace(user_id, &imported_as).await?;
+    owned.insert(imported_as.to_lowercase(), workspace.id);
+    if imported_as == name {
+        report.workspaces_created += 1;
+    } else {
+        report.workspaces_renamed.push(ConfigRename {
+            name: name.to_string(),
+            imported_as,
+        });
+    }
+    Ok(workspace.id)
+}
+
+async fn import_tag(
+    tags: &TagService,
+    user_id: Uuid,
+    tag: &ConfigTag,
+    strategy: MergeStrategy,
+    resolved: &HashMap<String, Uuid>,
+    existing: &mut TagIndex,
+    report: &mut LibraryConfigImportReport,
+) -> AppResult<()> {
+    let name = tag.name.trim();
+    if name.is_empty() {
+        return Err(AppError::InvalidInput("Tag name can't be empty".to_string()));
+    }
+    if name.chars().count() > crate::MAX_TAG_NAME_CHARS {
+        return Err(AppError::InvalidInput(format!(
+            "Tag name is longer than {} characters",
+            crate::MAX_TAG_NAME_CHARS
+        )));
+    }
+    let color = tag.color.as_deref();
+    if let Some(color) = color.filter(|color| !is_hex_color(color)) {
+        return Err(AppError::InvalidInput(format!("\"{}\" is not a color like \"#6366f1\"", color)));
+    }
+    let workspace_id = match tag.workspace.as_deref().map(str::trim) {
+        Some(workspace) => Some(
+            *resolved
+                .get(&workspace.to_lowercase())
+                .ok_or_else(|| AppError::InvalidInput(format!("Workspace \"{}\" wasn't imported", workspace)))?,
+        ),
+        None => None,
+    };
+
+    let imported_as = match existing.get(&(workspace_id, name.to_lowercase())) {
+        None => name.to_string(),
+        Some(_) if strategy == MergeStrategy::Skip => {
+            report.tags_skipped += 1;
+            return Ok(());
+        }
+        Some(found) if strategy == MergeStrategy::Overwrite => {
+            tags.update_tag_style(found.id, color, tag.description.as_deref()).await?;
+            report.tags_overwritten += 1;
+            return Ok(());
+        }
+        Some(_) => free_name(name, |candidate| {
+            existing.contains_key(&(workspace_id, candidate.to_lowercase()))
+        }),
+    };
+
+    let created = tags
+        .create_tag(user_id, workspace_id, &imported_as, color, tag.description.as_deref(), None)
+        .await?;
+    if imported_as == name {
+        report.tags_created += 1;
+    } else {
+        report.tags_renamed.push(ConfigRename {
+            name: name.to_string(),
+            imported_as: imported_as.clone(),
+        });
+    }
+    existing.insert((workspace_id, imported_as.to_lowercase()), created);
+    Ok(())
+}
+
+/// The first of "name (2)", "name (3)", ... that isn't taken
+fn free_name(name: &str, taken: impl Fn(&str) -> bool) -> String {
+    (2..)
+        .map(|n| format!("{} ({})", name, n))
+        .find(|candidate| !taken(candidate))
+        .unwrap_or_else(|| name.to_string())
+}
+
+/// A color as tags store it: "#" and six hex digits
+pub fn is_hex_color(color: &str) -> bool {
+    color.len() == 7 && color.starts_with('#') && color[1..].chars().all(|c| c.is_ascii_hexdigit())
+}
@@ -0,0 +1,357 @@
+//! Local HTTP API, bound to the loopback interface so other apps on this
+//! machine can reach it but nothing on the network can
+//!
+//! It serves one route: `GET /share/<token>`, a document shared with
+//! create_share_token, as HTML or as its original file. A token is served
+//! once. Unknown, used, expired and revoked tokens all get the same 404, so
+//! a response never tells whether a token existed. Failed guesses are rate
+//! limited per source port and in total.
+
+use crate::error::AppResult;
+use crate::export;
+use crate::file_utils;
+use crate::settings::{AppSettings, SettingsSubscriber};
+use crate::AppState;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use std::collections::HashMap;
+use std::io::Read;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::Manager;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Random bytes in a share token
+const TOKEN_BYTES: usize = 32;
+
+/// Length of a share token, URL-safe base64 without padding
+const TOKEN_LEN: usize = 43;
+
+/// Longest request head read; anything longer is refused
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+
+/// Time a client gets to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Window failed token guesses are counted over
+const GUESS_WINDOW: Duration = Duration::from_secs(60);
+
+/// Failed guesses allowed per source port within the window
+const GUESSES_PER_PORT: u32 = 5;
+
+/// Failed guesses allowed from all ports together within the window
+const GUESSES_TOTAL: u32 = 30;
+
+/// Bytes of an original file sent at a time
+const CHUNK_LEN: usize = 64 * 1024;
+
+/// A new share token; only its hash is stored
+pub fn new_token() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Hash a share token is stored and looked up by
+pub fn token_hash(token: &str) -> String {
+    file_utils::sha256_text(token)
+}
+
+pub fn share_url(port: u16, token: &str) -> String {
+    format!("http://127.0.0.1:{}/share/{}", port, token)
+}
+
+/// Run the API while `local_api_enabled` is set, binding again when it or
+/// `local_api_port` changes
+pub async fn serve(app: tauri::AppHandle, mut changes: SettingsSubscriber) {
+    let limiter = Arc::new(GuessLimiter::default());
+    let mut settings = app.state::<AppState>().settings.get().await;
+    loop {
+        let bound = (settings.local_api_enabled, settings.local_api_port);
+        let listener = if settings.local_api_enabled {
+            bind(settings.local_api_port).await
+        } else {
+            None
+        };
+        tokio::select! {
+            _ = accept(&app, listener, &limiter) => {}
+            next = next_change(&mut changes, bound) => match next {
+                Some(next) => settings = next,
+                None => return,
+            },
+        }
+    }
+}
+
+async fn bind(port: u16) -> Option<TcpListener> {
+    match TcpListener::bind((Ipv4Addr::LOCALHOST, port)).await {
+        Ok(listener) => Some(listener),
+        Err(e) => {
+            eprintln!("Local API couldn't listen on port {}: {}", port, e);
+            None
+        }
+    }
+}
+
+/// Wait for a change to whether or where the API listens
+async fn next_change(changes: &mut SettingsSubscriber, bound: (bool, u16)) -> Option<AppSettings> {
+    loop {
+        let settings = changes.changed().await?;
+        changes.acknowledge();
+        if (settings.local_api_enabled, settings.local_api_port) != bound {
+            return Some(settings);
+        }
+    }
+}
+
+/// Serve connections until dropped; never returns without a listener
+async fn accept(app: &tauri::AppHandle, listener: Option<TcpListener>, limiter: &Arc<GuessLimiter>) {
+    let Some(listener) = listener else {
+        return std::future::pending().await;
+    };
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                let app = app.clone();
+                let limiter = Arc::clone(limiter);
+                tauri::async_runtime::spawn(async move {
+                    if let Err(e) = handle(&app, stream, peer, &limiter).await {
+                        eprintln!("Local API request from port {} failed: {}", peer.port(), e);
+                    }
+                });
+            }
+            Err(e) => {
+                eprintln!("Local API failed to accept a connection: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+    }
+}
+
+async fn handle(
+    app: &tauri::AppHandle,
+    mut stream: TcpStream,
+    peer: SocketAddr,
+    limiter: &GuessLimiter,
+) -> std::io::Result<()> {
+    let request = match tokio::time::timeout(REQUEST_TIMEOUT, read_request_line(&mut stream)).await {
+        Ok(request) => request?,
+        Err(_) => None,
+    };
+    let Some((method, target)) = request else {
+        return send_status(&mut stream, "400 Bad Request").await;
+    };
+    let Some(token) = target.strip_prefix("/share/") else {
+        return send_status(&mut stream, "404 Not Found").await;
+    };
+    if method != "GET" {
+        return send_status(&mut stream, "405 Method Not Allowed").await;
+    }
+    if limiter.limited(peer.port()) {
+        return send_status(&mut stream, "429 Too Many Requests").await;
+    }
+
+    let state = app.state::<AppState>();
+    match shared_document(&state, token).await {
+        Ok(Some(Shared::Html(body))) => {
+            let length = body.len().to_string();
+            let headers = [
+                ("Content-Type", "text/html; charset=utf-8"),
+                ("Content-Length", length.as_str()),
+                ("Content-Security-Policy", "default-src 'none'; style-src 'unsafe-inline'"),
+            ];
+            write_head(&mut stream, "200 OK", &headers).await?;
+            stream.write_all(&body).await?;
+            stream.shutdown().await
+        }
+        Ok(Some(Shared::File { reader, mime_type, file_name })) => {
+            let disposition = format!("attachment; filename=\"{}\"", header_safe(&file_name));
+            let headers = [
+                ("Content-Type", mime_type.as_str()),
+                ("Content-Disposition", disposition.as_str()),
+            ];
+            write_head(&mut stream, "200 OK", &headers).await?;
+            send_file(&mut stream, reader).await?;
+            stream.shutdown().await
+        }
+        Ok(None) => {
+            limiter.record_failure(peer.port());
+            send_status(&mut stream, "404 Not Found").await
+        }
+        Err(e) => {
+            eprintln!("Failed to serve a shared document: {}", e);
+            send_status(&mut stream, "500 Internal Server Error").await
+        }
+    }
+}
+
+/// What a share token serves
+enum Shared {
+    Html(Vec<u8>),
+    File {
+        reader: Box<dyn Read + Send>,
+        mime_type: String,
+        file_name: String,
+    },
+}
+
+/// Prepare what `token` shares and use the token up; None if it can't be
+/// redeemed
+///
+/// The token is only used up once the response is ready, so a failure to
+/// read the document leaves it for another try.
+async fn shared_document(state: &AppState, token: &str) -> AppResult<Option<Shared>> {
+    if token.len() != TOKEN_LEN || !token.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_') {
+        return Ok(None);
+    }
+    let Some(share) = state.share_token_service.lock().await.find_valid(&token_hash(token)).await? else {
+        return Ok(None);
+    };
+    let Some(document) = state.document_service.lock().await.get_document(share.document_id).await? else {
+        return Ok(None);
+    };
+
+    let shared = if share.include_original {
+        let Some(path) = document.file_path.clone() else {
+            return Ok(None);
+        };
+        let store = state.keyring.store(&state.settings.get().await)?;
+        let reader = tokio::task::spawn_blocking(move || store.open(std::path::Path::new(&path))).await??;
+        Shared::File {
+            reader,
+            mime_type: document.mime_type.unwrap_or_else(|| file_utils::UNKNOWN_MIME_TYPE.to_string()),
+            file_name: document.file_name.unwrap_or_else(|| document.title.clone()),
+        }
+    } else {
+        let body = tokio::task::spawn_blocking(move || {
+            let mut body = Vec::new();
+            let content = document.content.as_deref().unwrap_or_default();
+            export::html::write_shared_html(&mut body, &document.title, content)?;
+            Ok::<_, std::io::Error>(body)
+        })
+        .await??;
+        Shared::Html(body)
+    };
+
+    if !state.share_token_service.lock().await.consume(share.id).await? {
+        return Ok(None);
+    }
+    Ok(Some(shared))
+}
+
+/// Method and target of the request, once its head has arrived; None for
+/// anything that isn't an HTTP request
+async fn read_request_line(stream: &mut TcpStream) -> std::io::Result<Option<(String, String)>> {
+    let mut head = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        let count = stream.read(&mut buffer).await?;
+        if count == 0 || head.len() + count > MAX_REQUEST_HEAD {
+            return Ok(None);
+        }
+        head.extend_from_slice(&buffer[..count]);
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let mut parts = head.lines().next().unwrap_or_default().split(' ');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version)) if version.starts_with("HTTP/1.") => {
+            Ok(Some((method.to_string(), target.to_string())))
+        }
+        _ => Ok(None),
+    }
+}
+
+async fn write_head(stream: &mut TcpStream, status: &str, headers: &[(&str, &str)]) -> std::io::Result<()> {
+    let mut head = format!("HTTP/1.1 {}\r\n", status);
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    // Nothing shared is to be kept or linked on from
+    head.push_str("Cache-Control: no-store\r\n");
+    head.push_str("Referrer-Policy: no-referrer\r\n");
+    head.push_str("X-Content-Type-Options: nosniff\r\n");
+    head.push_str("Connection: close\r\n\r\n");
+    stream.write_all(head.as_bytes()).await
+}
+
+/// A response with the status as its only content
+async fn send_status(stream: &mut TcpStream, status: &str) -> std::io::Result<()> {
+    let length = status.len().to_string();
+    let headers = [
+        ("Content-Type", "text/plain; charset=utf-8"),
+        ("Content-Length", length.as_str()),
+    ];
+    write_head(stream, status, &headers).await?;
+    stream.write_all(status.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Stream a file read on a blocking thread; the response ends when the
+/// connection closes, as its decrypted length isn't known up front
+async fn send_file(stream: &mut TcpStream, mut reader: Box<dyn Read + Send>) -> std::io::Result<()> {
+    let (sender, mut chunks) = tokio::sync::mpsc::channel::<std::io::Result<Vec<u8>>>(4);
+    tokio::task::spawn_blocking(move || loop {
+        let mut buffer = vec![0u8; CHUNK_LEN];
+        let chunk = match reader.read(&mut buffer) {
+            Ok(0) => return,
+            Ok(count) => {
+                buffer.truncate(count);
+                Ok(buffer)
+            }
+            Err(e) => Err(e),
+        };
+        let failed = chunk.is_err();
+        // The receiver is gone once the client hung up
+        if sender.blocking_send(chunk).is_err() || failed {
+            return;
+        }
+    });
+    while let Some(chunk) = chunks.recv().await {
+        stream.write_all(&chunk?).await?;
+    }
+    Ok(())
+}
+
+/// A file name for a quoted header value: ASCII without quotes, backslashes
+/// or control characters
+fn header_safe(file_name: &str) -> String {
+    file_name
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => '_',
+            c if c.is_ascii() && !c.is_ascii_control() => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Counts of failed token guesses in the current window, per source port
+/// and, under None, from every port together
+#[derive(Default)]
+struct GuessLimiter {
+    failures: Mutex<HashMap<Option<u16>, (Instant, u32)>>,
+}
+
+impl GuessLimiter {
+    fn limited(&self, port: u16) -> bool {
+        let failures = self.failures.lock().unwrap();
+        let count = |key| match failures.get(&key) {
+            Some((started, count)) if started.elapsed() < GUESS_WINDOW => *count,
+            _ => 0,
+        };
+        count(Some(port)) >= GUESSES_PER_PORT || count(None) >= GUESSES_TOTAL
+    }
+
+    fn record_failure(&self, port: u16) {
+        let mut failures = self.failures.lock().unwrap();
+        failures.retain(|_, (started, _)| started.elapsed() < GUESS_WINDOW);
+        for key in [Some(port), None] {
+            failures.entry(key).or_insert_with(|| (Instant::now(), 0)).1 += 1;
+        }
+    }
+}
@@ -1,4 +1,6 @@
 // Database models
+use crate::summarizer::FileKind;
+use crate::uuid_field;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
@@ -21,20 +23,269 @@ pub struct Document {
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub updated_at: chrono::DateTime<chrono::Utc>,
     pub deleted_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub page_count: Option<i32>,
+    pub parent_document_id: Option<Uuid>,
+    pub is_pinned: bool,
+    pub pinned_order: Option<i32>,
+    /// `file_path` is the user's own file, imported by reference
+    pub external_file: bool,
+    /// Never opened, or marked unread since it was last opened
+    pub is_unread: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::Type)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
 #[sqlx(type_name = "document_status", rename_all = "lowercase")]
 pub enum DocumentStatus {
+    /// Accepted by upload_file, its file not copied yet; or stored by
+    /// aks-cli and waiting for the app to process it
+    Queued,
     Uploading,
     Processing,
     Completed,
     Failed,
+    #[sqlx(rename = "missing_file")]
+    MissingFile,
+}
+
+impl DocumentStatus {
+    /// Allowed moves in the processing lifecycle
+    ///
+    /// Uploading -> Processing -> Completed | Failed, with Failed -> Processing
+    /// for retries and Completed -> Processing for reprocessing. Uploads start
+    /// Queued and go straight to Processing once their file is stored. An
+    /// upload can also fail before processing starts. Settled documents whose file has
+    /// vanished become MissingFile, and return to Completed if it reappears.
+    /// aks-cli queues Completed and Failed documents again for reprocessing.
+    pub fn can_transition_to(self, next: DocumentStatus) -> bool {
+        use DocumentStatus::*;
+        matches!(
+            (self, next),
+            (Uploading, Processing)
+                | (Uploading, Failed)
+                | (Queued, Processing)
+                | (Queued, Failed)
+                | (Completed, Queued)
+                | (Failed, Queued)
+                | (Processing, Completed)
+                | (Processing, Failed)
+                | (Failed, Processing)
+                | (Completed, Processing)
+                | (Completed, MissingFile)
+                | (Failed, MissingFile)
+                | (MissingFile, Completed)
+                | (MissingFile, Processing)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DocumentIdentifier {
+    pub kind: String,
+    pub value: String,
+}
+
+/// A single document with its related data, as returned by get_document
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentDetails {
+    #[serde(flatten)]
+    pub document: Document,
+    pub identifiers: Vec<DocumentIdentifier>,
+    pub notes: Option<DocumentNote>,
+    /// Where the user stopped reading, found in the current content
+    pub reading_position: Option<ReadingPosition>,
+    /// How and from where the document was imported; None for documents
+    /// imported before this was recorded
+    pub provenance: Option<ProvenanceInfo>,
+}
+
+/// A reading position as saved
+#[derive(Debug, Clone, FromRow)]
+pub struct StoredReadingPosition {
+    pub char_offset: i32,
+    pub page_number: Option<i32>,
+    pub percent: f32,
+    /// Content from char_offset on, as it was when saved
+    pub anchor: String,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// How a saved reading position was found in the current content
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Anchoring {
+    /// At the text it was saved at, possibly moved
+    Exact,
+    /// Near it, by part of that text or the start of the saved page
+    Approximate,
+    /// The text is gone; char_offset is the saved one, within the content
+    NotFound,
+}
+
+/// Where a user stopped reading a document
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadingPosition {
+    /// Char offset into the current content
+    pub char_offset: i32,
+    pub page_number: Option<i32>,
+    /// Share of the document read when saved, 0 to 100
+    pub percent: f32,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub anchoring: Anchoring,
+}
+
+/// Markdown notes linked to a document from a sidecar file
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DocumentNote {
+    pub content: String,
+    pub source_path: Option<String>,
+    pub content_hash: Option<String>,
+    pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteAttachResult {
+    pub note: DocumentNote,
+    /// False when the sidecar matched the stored hash and nothing was rewritten
+    pub changed: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateDocumentDto {
     pub user_id: Uuid,
+    pub title: String,
+    pub file_name: String,
+    pub file_size_bytes: i64,
+    pub file_type: String,
+    /// Unknown until a queued upload has been read
+    pub mime_type: Option<String>,
+    pub parent_document_id: Option<Uuid>,
+    /// Filed here from the start, so the file is charged to the workspace
+    pub workspace_id: Option<Uuid>,
+    /// SHA-256 of the stored file, when there is one
+    pub file_hash: Option<String>,
+    /// Key from `collation::title_sort_key` for the title
+    pub title_sort: String,
+    pub status: DocumentStatus,
+    /// Imported by reference: the file stays where it is and uses no quota
+    pub external_file: bool,
+    pub provenance: ProvenanceInfo,
+}
+
+/// How a document came into the library
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMethod {
+    /// upload_file with a file picked in a dialog
+    Upload,
+    /// upload_file with a file dropped onto the window
+    DragDrop,
+    FolderImport,
+    /// A file embedded in a PDF
+    Attachment,
+    /// Pages split out of another PDF
+    PdfSplit,
+    WorkspacePackage,
+    /// create_document, with the file supplied separately
+    Manual,
+    /// `aks-cli import`
+    Cli,
+}
+
+impl ImportMethod {
+    pub const ALL: [ImportMethod; 8] = [
+        ImportMethod::Upload,
+        ImportMethod::DragDrop,
+        ImportMethod::FolderImport,
+        ImportMethod::Attachment,
+        ImportMethod::PdfSplit,
+        ImportMethod::WorkspacePackage,
+        ImportMethod::Manual,
+        ImportMethod::Cli,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportMethod::Upload => "upload",
+            ImportMethod::DragDrop => "drag_drop",
+            ImportMethod::FolderImport => "folder_import",
+            ImportMethod::Attachment => "attachment",
+            ImportMethod::PdfSplit => "pdf_split",
+            ImportMethod::WorkspacePackage => "workspace_package",
+            ImportMethod::Manual => "manual",
+            ImportMethod::Cli => "cli",
+        }
+    }
+}
+
+/// Where a document came from, recorded when it is imported; stored in
+/// documents.provenance
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProvenanceInfo {
+    pub method: ImportMethod,
+    /// Absolute path of the file imported, e.g. the package a workspace
+    /// document came in; None without a file of its own or with
+    /// `record_import_paths` off
+    pub source_path: Option<String>,
+    /// Folder a folder import started from
+    pub folder_root: Option<String>,
+    /// Version of the app that imported it
+    pub app_version: String,
+    pub imported_at: chrono::DateTime<chrono::Utc>,
+    /// The virus scan the file passed before it was stored; None without a
+    /// scanner configured, or for documents imported before scanning
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scan: Option<ScanRecord>,
+}
+
+/// How the virus scan of an upload went; part of its provenance
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanRecord {
+    /// Program the scan ran, e.g. "clamscan"
+    pub scanner: String,
+    pub outcome: ScanOutcome,
+    pub scanned_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Outcome of a scan that let the file in; a rejected file isn't stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanOutcome {
+    /// The scanner passed the file
+    Clean,
+    /// The scanner couldn't be started, and `virus_scan_fail_open` let the
+    /// file in unscanned
+    ScannerMissing,
+    /// The scanner ran past `virus_scan_timeout_secs`, and
+    /// `virus_scan_fail_open` let the file in unscanned
+    TimedOut,
+}
+
+impl ProvenanceInfo {
+    /// Provenance of a document imported now; paths are only kept when
+    /// `record_paths` is set
+    pub fn now(
+        method: ImportMethod,
+        source_path: Option<&std::path::Path>,
+        folder_root: Option<&std::path::Path>,
+        record_paths: bool,
+    ) -> Self {
+        let path = |path: Option<&std::path::Path>| {
+            path.filter(|_| record_paths).map(|p| p.to_string_lossy().to_string())
+        };
+        ProvenanceInfo {
+            method,
+            source_path: path(source_path),
+            folder_root: path(folder_root),
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+            imported_at: chrono::Utc::now(),
+            scan: None,
+        }
+    }
+}
+
+/// Document creation input from the frontend; the owner comes from the session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateDocumentRequest {
     pub title: String,
     pub file_name: String,
     pub file_size_bytes: i64,
@@ -44,14 +295,473 @@ pub struct CreateDocumentDto {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UploadFileRequest {
-    pub user_id: String,
     pub source_path: String,
+    /// Workspace to put the document in; without one it goes in the user's
+    /// default workspace, if they have one
+    #[serde(default, alias = "target_workspace_id", deserialize_with = "uuid_field::workspace_id")]
+    pub workspace_id: Option<Uuid>,
+    /// Overrides the workspace's processing defaults, or those from
+    /// settings, for this document
+    #[serde(default)]
+    pub processing_options: Option<ProcessingOptions>,
+    #[serde(default)]
+    pub storage_mode: StorageMode,
+    /// The file was dropped onto the window rather than picked, for the
+    /// document's provenance
+    #[serde(default)]
+    pub dropped: bool,
+    /// What to do when the user deleted a document of the same file
+    #[serde(default)]
+    pub on_trashed_match: TrashedMatchAction,
+}
+
+/// What an upload does with a file only soft-deleted documents have; one a
+/// live document has too is uploaded as usual
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrashedMatchAction {
+    /// Bring the most recently deleted one back instead of adding a document
+    #[default]
+    Restore,
+    /// Queue nothing and list the deleted documents, for the user to choose
+    /// between restore_document and uploading with `Import`
+    Ask,
+    /// Add a new document regardless
+    Import,
+}
+
+/// Import of every file in a folder tree as uploads
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportFolderRequest {
+    pub folder_path: String,
+    #[serde(default)]
+    pub structure_mode: StructureMode,
+    /// Workspace to put the files in, as for upload_file; files that
+    /// `TopLevelAsWorkspace` places in a workspace of their own go there
+    #[serde(default, alias = "target_workspace_id", deserialize_with = "uuid_field::workspace_id")]
+    pub workspace_id: Option<Uuid>,
+    /// Overrides the defaults from settings for every file
+    #[serde(default)]
+    pub processing_options: Option<ProcessingOptions>,
+    #[serde(default)]
+    pub storage_mode: StorageMode,
+    /// Done to each file once the library has its content: when its
+    /// document is processed, or straight away for a file skipped as a
+    /// duplicate. A file whose document fails stays for another try.
+    #[serde(default)]
+    pub after_import: SourceFileAction,
+}
+
+/// What a folder import does with a file it has imported
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SourceFileAction {
+    #[default]
+    Keep,
+    Delete,
+    /// Move it into the imported folder's `imported` directory, at the same
+    /// place in the tree; later imports of the folder skip that directory
+    MoveToSubfolder,
+}
+
+/// How a folder import maps the tree's directories onto the library
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StructureMode {
+    /// Directories are ignored
+    #[default]
+    Flat,
+    /// Each first-level directory becomes a workspace of the same name,
+    /// reused if the user already owns one
+    TopLevelAsWorkspace,
+    /// Each directory between the folder and a file becomes a tag on it
+    PathAsTags,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FolderImportReport {
+    /// Files queued; later progress arrives as "documents:changed"
+    pub queued: usize,
+    pub failed: Vec<FolderImportFailure>,
+    pub workspaces_created: usize,
+    pub workspaces_reused: usize,
+    pub tags_created: usize,
+    pub tags_reused: usize,
+    /// Files nested deeper than the tag limit; their innermost directories
+    /// got no tag
+    pub tags_truncated: Vec<String>,
+    /// Files not reached because the import was cancelled
+    pub cancelled_remaining: Option<usize>,
+    /// Files already in the library with the same content; not imported
+    pub duplicates: Vec<FolderImportDuplicate>,
+    /// Session the import is reported under once every file is done
+    pub import_session_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FolderImportDuplicate {
+    pub path: String,
+    pub duplicate_of: Uuid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FolderImportFailure {
+    pub path: String,
+    pub error: String,
+}
+
+/// A file of an import session that never became a document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnqueuedFile {
+    pub path: String,
+    /// Why it couldn't be queued or stored; None for duplicates
+    pub error: Option<String>,
+    /// Document with the same content the file was skipped for
+    pub duplicate_of: Option<Uuid>,
+}
+
+/// One folder import, followed until each of its files is done
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportSession {
+    pub id: Uuid,
+    pub source_path: String,
+    pub total_files: i32,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Finalized by the startup pass after the app closed mid-import
+    pub interrupted: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ImportFileOutcome {
+    Completed,
+    Failed,
+    /// Skipped because the library has a file with the same content
+    Duplicate,
+    /// Couldn't be queued or stored, e.g. over quota
+    NotQueued,
+    /// Still queued or processing when the session was finalized
+    Unfinished,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportFileReport {
+    /// File name for documents, the source path for files never stored
+    pub name: String,
+    pub document_id: Option<Uuid>,
+    pub outcome: ImportFileOutcome,
+    pub error: Option<String>,
+}
+
+/// Failures whose messages match once paths and ids are taken out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportFailureGroup {
+    pub error: String,
+    pub count: usize,
+}
+
+/// How every file of an import session ended up
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub session: ImportSession,
+    pub files: Vec<ImportFileReport>,
+    pub completed: usize,
+    pub failed: usize,
+    pub duplicates_skipped: usize,
+    pub unfinished: usize,
+    /// Largest group first
+    pub failure_groups: Vec<ImportFailureGroup>,
+    /// Size of the files that became documents
+    pub total_bytes: i64,
+    /// From start to finish; None while the session is still running
+    pub elapsed_seconds: Option<i64>,
+}
+
+/// A named stretch of work whose document opens and searches are recorded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FocusSession {
+    pub id: Uuid,
+    pub name: String,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    /// None while the session is active
+    pub ended_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Closed by the startup pass after the app was closed mid-session
+    pub auto_closed: bool,
+}
+
+/// A document opened during a focus session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FocusSessionDocument {
+    /// None once the document was purged
+    pub document_id: Option<Uuid>,
+    pub title: Option<String>,
+    pub open_count: i64,
+    pub first_opened_at: chrono::DateTime<chrono::Utc>,
+    pub last_opened_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A search query run during a focus session, however many times
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FocusSessionSearch {
+    pub query: String,
+    pub run_count: i64,
+    /// Results of the latest run
+    pub result_count: Option<i32>,
+    pub last_run_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// What a focus session consulted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FocusSessionReport {
+    pub session: FocusSession,
+    /// Up to now while the session is active
+    pub duration_seconds: i64,
+    /// Most opened first
+    pub documents: Vec<FocusSessionDocument>,
+    /// In the order first run
+    pub searches: Vec<FocusSessionSearch>,
+}
+
+/// A one-time link to a document, without its token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShareToken {
+    pub id: Uuid,
+    pub document_id: Uuid,
+    /// Serves the original file rather than the extracted content as HTML
+    pub include_original: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+    pub used_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub revoked_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// A share link as created; the token can't be retrieved again
+#[derive(Debug, Clone, Serialize)]
+pub struct CreatedShareLink {
+    pub token: String,
+    pub url: String,
+    pub share: ShareToken,
+}
+
+/// One transform of a bulk rename rule; steps apply in order
+///
+/// Literal text may contain the placeholders `{title}` (the title so far),
+/// `{author}`, `{year}`, `{page_count}` and `{n}`, the document's position
+/// in the batch, optionally zero-padded as `{n:3}`. `{{` and `}}` are
+/// literal braces.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RenameStep {
+    /// Drop a trailing ".ext" of up to 5 letters or digits
+    StripExtension,
+    /// Replace every match; `$1` and `${name}` in `replacement` insert groups
+    RegexReplace { pattern: String, replacement: String },
+    Prepend { text: String },
+    Append { text: String },
+    /// Replace the whole title
+    Set { text: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenameRule {
+    pub steps: Vec<RenameStep>,
+}
+
+/// A document's title before and after a bulk rename
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenamePreviewEntry {
+    pub document_id: Uuid,
+    pub old_title: String,
+    pub new_title: String,
+}
+
+/// A title a bulk rename would give more than one document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenameCollision {
+    pub title: String,
+    pub document_ids: Vec<Uuid>,
+}
+
+/// A bulk rename, previewed or applied
+///
+/// Only applied when no title comes out empty, too long or shared;
+/// otherwise `applied` is false and nothing was written.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkRenameResult {
+    /// Every matching document in batch order, unchanged titles included
+    pub entries: Vec<RenamePreviewEntry>,
+    /// Documents whose title would change
+    pub changed: usize,
+    pub empty_titles: Vec<Uuid>,
+    pub too_long_titles: Vec<Uuid>,
+    pub collisions: Vec<RenameCollision>,
+    pub applied: bool,
+}
+
+/// A document a bulk rename may retitle, with the metadata its
+/// placeholders read
+#[derive(Debug, Clone)]
+pub struct RenameCandidate {
+    pub id: Uuid,
+    pub title: String,
+    /// Joined by "; " when the metadata lists several
+    pub authors: Option<String>,
+    pub year: Option<String>,
+    pub page_count: Option<i32>,
+}
+
+/// A completed document's summary and content, for describing how the
+/// pipelines did on it
+#[derive(Debug, Clone)]
+pub struct EvalSample {
+    pub summary: Option<String>,
+    pub summary_source: Option<String>,
+    pub content: String,
+}
+
+/// Whether an upload copies the file into storage or leaves it in place
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StorageMode {
+    /// Copy into the storage root, counting against the quota
+    #[default]
+    Copy,
+    /// Keep the original where it is and record its path; nothing is
+    /// copied and the file is never moved or deleted by the app
+    Reference,
+}
+
+/// Per-document choices for the processing pipeline
+///
+/// Stored with the document so reprocessing repeats them unless new ones
+/// are given.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProcessingOptions {
+    /// Tidy extracted text (control characters, hyphenation, blank lines)
+    pub clean_text: bool,
+    /// OCR even when the file has a text layer, if the extractor can
+    pub force_ocr: bool,
+    pub generate_summary: bool,
+    /// Split the text into chunks for embedding
+    pub chunk: bool,
+    /// Language of the document as a BCP 47 tag, e.g. "de"
+    pub language_hint: Option<String>,
+    /// Look for invoice fields (number, vendor, dates, amounts) in the text
+    pub extract_fields: bool,
+}
+
+impl Default for ProcessingOptions {
+    fn default() -> Self {
+        ProcessingOptions {
+            clean_text: true,
+            force_ocr: false,
+            generate_summary: true,
+            chunk: true,
+            language_hint: None,
+            extract_fields: false,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UploadFileResponse {
+    /// The queued document; later progress arrives as "documents:changed"
     pub document: Document,
-    pub file_hash: String,
+    pub storage_used_percent: f64,
+    /// The file is smaller than the `tiny_file_bytes` setting though text is
+    /// extracted from its type, so it may not be the document meant
+    pub tiny_file: bool,
+    /// `document` is a deleted document of the same file brought back, with
+    /// its tags, notes and reading position; nothing was queued
+    #[serde(default)]
+    pub restored: bool,
+}
+
+/// What upload_file did with a file
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum UploadOutcome {
+    /// Queued as a new document, or a deleted one restored
+    Accepted(Box<UploadFileResponse>),
+    /// Only deleted documents have the file and the upload asked to choose;
+    /// they are listed most recently deleted first
+    RequiresDecision { trashed: Vec<Document> },
+}
+
+/// A raw value for format_human to format
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FormatRequest {
+    /// A size, such as "1.4 MB"
+    Bytes { value: i64 },
+    /// A duration, such as "1 h 5 min"
+    Duration { seconds: i64 },
+    /// How long ago, or until, such as "3 days ago"
+    RelativeTime { timestamp: chrono::DateTime<chrono::Utc> },
+}
+
+/// Payload of "documents:changed", sent after every stored change to a document
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentChange {
+    pub document_id: Uuid,
+    /// What changed: column names such as "status" or "file_path", related
+    /// data such as "pages" or "tags", or "created" and "deleted"
+    pub changed_fields: Vec<&'static str>,
+    /// Status after the change, when the change set or created it
+    pub status: Option<DocumentStatus>,
+}
+
+/// Where a document stands, for reconciling the UI after missed events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentStatusEntry {
+    pub document_id: Uuid,
+    pub status: DocumentStatus,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    /// Bumped on every update of the row
+    pub version: i32,
+    /// Soft-deleted since the UI last saw it
+    pub deleted: bool,
+}
+
+/// Documents updated after a given instant
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangedDocuments {
+    /// Oldest first
+    pub documents: Vec<DocumentStatusEntry>,
+    /// More changed than were returned; reload everything instead
+    pub truncated: bool,
+}
+
+/// What happened to a document, as recorded in the change feed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "document_change_kind", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum FeedChangeKind {
+    Created,
+    Updated,
+    /// Soft-deleted, or removed outright
+    Deleted,
+    Restored,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct FeedChange {
+    pub seq: i64,
+    pub document_id: Uuid,
+    pub change_kind: FeedChangeKind,
+    pub changed_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Changes after a cursor, for consumers that don't get the app's events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeFeed {
+    /// Oldest first
+    pub changes: Vec<FeedChange>,
+    /// Cursor for the next poll
+    pub latest_seq: i64,
+    /// The cursor can't be caught up from; reload everything, then poll
+    /// from `latest_seq`
+    pub resync_required: bool,
+    /// More changes are waiting; poll again straight away
+    pub has_more: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -59,7 +769,1188 @@ pub struct User {
     pub id: Uuid,
     pub email: String,
     pub full_name: Option<String>,
-    pub role: String,
+    pub role: Role,
     pub storage_used_bytes: i64,
     pub storage_limit_bytes: i64,
 }
+
+/// Account tier from the user_role column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "user_role", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Free,
+    Pro,
+    TeamMember,
+    TeamAdmin,
+    Enterprise,
+}
+
+impl Role {
+    /// Whether the role may act on other users' data
+    pub fn is_admin(self) -> bool {
+        self == Role::TeamAdmin
+    }
+}
+
+/// A document and who owns it, for admins looking across users
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentWithOwner {
+    pub document: Document,
+    pub owner_email: String,
+    pub owner_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtractPagesRequest {
+    #[serde(deserialize_with = "uuid_field::document_id")]
+    pub document_id: Uuid,
+    pub from_page: u32,
+    pub to_page: u32,
+    pub new_title: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UserChangedEvent {
+    pub user_id: Uuid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationFailure {
+    pub document_id: Uuid,
+    pub error: String,
+}
+
+/// Result of redetect_file_types
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RedetectReport {
+    pub checked: usize,
+    /// Documents given a type, by the MIME type detected
+    pub reclassified: std::collections::HashMap<String, usize>,
+    /// Reclassified documents an extractor accepts, now being processed
+    pub processing_started: usize,
+    pub still_unknown: usize,
+    /// Skipped because the stored file is gone
+    pub missing_files: usize,
+    pub failed: Vec<MigrationFailure>,
+    pub cancelled: bool,
+}
+
+/// Documents stored without an extractor that were looked at again
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UnsupportedProcessingReport {
+    pub checked: usize,
+    /// Supported now and being processed
+    pub processing_started: usize,
+    pub still_unsupported: usize,
+    /// Supported now, but left for later because storage is offline
+    pub storage_offline: usize,
+    pub failed: Vec<MigrationFailure>,
+}
+
+/// A registered extractor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtractorInfo {
+    pub name: String,
+    pub version: Option<String>,
+    pub priority: i32,
+    pub supports_ocr: bool,
+}
+
+/// Documents waiting for an extractor for their type
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnprocessedType {
+    pub mime_type: String,
+    pub count: i64,
+}
+
+/// What can be extracted, and what is stored waiting for an extractor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SupportedTypes {
+    pub extractors: Vec<ExtractorInfo>,
+    /// The current user's, most common first
+    pub unprocessed: Vec<UnprocessedType>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageMigrationReport {
+    pub migration_id: Option<Uuid>,
+    pub dry_run: bool,
+    pub resumed: bool,
+    pub document_count: usize,
+    pub required_bytes: u64,
+    pub available_bytes: u64,
+    pub migrated: usize,
+    pub failed: Vec<MigrationFailure>,
+    /// Files copied before the run was cancelled; it resumes when re-invoked
+    pub cancelled_after: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageMigrationProgress {
+    pub operation_id: Uuid,
+    pub migration_id: Uuid,
+    pub phase: String,
+    pub processed: usize,
+    pub total: usize,
+    pub document_id: Option<Uuid>,
+}
+
+/// Order of document lists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DocumentSort {
+    /// Most recently created first
+    #[default]
+    Newest,
+    /// By title, using the locale's sort keys
+    Title,
+}
+
+/// How document lists are grouped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DocumentGrouping {
+    /// By how long ago the document was created; see DateBucket
+    CreatedBucket,
+}
+
+/// Group of a document by creation date, in the caller's time zone
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DateBucket {
+    Today,
+    Yesterday,
+    /// Since Monday, before yesterday
+    ThisWeek,
+    Earlier,
+}
+
+/// A document in a list, with its group when the list is grouped
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentListEntry {
+    /// Without content or the full summary; get_document has those
+    #[serde(flatten)]
+    pub document: Document,
+    pub bucket: Option<DateBucket>,
+    /// Start of the summary, cut at a word boundary
+    pub summary_preview: Option<String>,
+    /// Whether the summary goes on past summary_preview
+    pub summary_truncated: bool,
+    pub file_kind: FileKind,
+}
+
+/// A document on the reading list
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadingListEntry {
+    /// Without content
+    #[serde(flatten)]
+    pub document: Document,
+    pub added_at: chrono::DateTime<chrono::Utc>,
+    /// Estimated from the word count; 0 for documents without text
+    pub reading_time_minutes: i64,
+}
+
+/// The reading list in order, with the time it takes to read all of it
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadingList {
+    pub entries: Vec<ReadingListEntry>,
+    pub total_reading_time_minutes: i64,
+}
+
+/// Number of documents in a group, for headers shown before the list loads
+#[derive(Debug, Clone, Serialize)]
+pub struct BucketCount {
+    pub bucket: DateBucket,
+    pub count: i64,
+}
+
+/// Reading order used when extracting PDF text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PdfLayout {
+    /// Column order on pages detected as multi-column, content-stream order elsewhere
+    #[default]
+    Auto,
+    /// Always order text by position, column by column
+    Columns,
+    /// Content-stream order, as the PDF was written
+    StreamOrder,
+}
+
+/// How much structure a Markdown export recovers from extracted text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MarkdownFidelity {
+    /// The extracted text as is
+    Basic,
+    /// Headings, list items and page anchors detected from the text, or
+    /// taken from paragraph styles for DOCX files
+    #[default]
+    Structured,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum StorageLevel {
+    Ok,
+    Warning,
+    Critical,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageStatus {
+    /// Size of the files in the storage root, which count against the limit
+    pub managed_bytes: i64,
+    /// Size of the files imported by reference; informational only
+    pub referenced_bytes: i64,
+    pub limit_bytes: i64,
+    /// Of the limit, used by managed files
+    pub percentage: f64,
+    pub level: StorageLevel,
+}
+
+/// What recompute_storage_usage changed; the unprefixed figures are the
+/// managed ones
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageCorrection {
+    pub previous_bytes: i64,
+    pub recomputed_bytes: i64,
+    pub delta_bytes: i64,
+    pub previous_referenced_bytes: i64,
+    pub recomputed_referenced_bytes: i64,
+    pub referenced_delta_bytes: i64,
+    /// Documents imported by reference whose file is in the storage root,
+    /// now counted as managed
+    pub converted_documents: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Tag {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub workspace_id: Option<Uuid>,
+    pub name: String,
+    pub color: Option<String>,
+    pub description: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Tag this one is nested under; None at the top level
+    pub parent_tag_id: Option<Uuid>,
+}
+
+/// A tag in the hierarchy, as list_tags returns it: depth-first, each
+/// tag right after its parent, siblings by name
+#[derive(Debug, Clone, Serialize)]
+pub struct TagTreeEntry {
+    #[serde(flatten)]
+    pub tag: Tag,
+    /// 0 for top-level tags
+    pub depth: i32,
+    /// Names from the top-level tag down, e.g. "Finance/Tax"
+    pub path: String,
+}
+
+/// A new tag, in a workspace or unscoped
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateTagRequest {
+    pub name: String,
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Tag to nest it under, in the same workspace
+    #[serde(default, deserialize_with = "uuid_field::parent_tag_id")]
+    pub parent_tag_id: Option<Uuid>,
+    #[serde(default, deserialize_with = "uuid_field::workspace_id")]
+    pub workspace_id: Option<Uuid>,
+}
+
+/// Everything about a tag that can be changed; fields left out are cleared,
+/// so a None parent moves the tag to the top level
+#[derive(Debug, Clone, Deserialize)]
+pub struct UpdateTagRequest {
+    pub name: String,
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default, deserialize_with = "uuid_field::parent_tag_id")]
+    pub parent_tag_id: Option<Uuid>,
+}
+
+/// A tag and how many documents carry it, for sidebar badges
+#[derive(Debug, Clone, Serialize)]
+pub struct TagDocumentCount {
+    pub tag_id: Uuid,
+    pub name: String,
+    pub color: Option<String>,
+    pub workspace_id: Option<Uuid>,
+    /// Documents not soft-deleted; 0 for unused tags
+    pub document_count: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DocumentPage {
+    pub page_number: i32,
+    pub content: String,
+    /// Char offset of the page within the document content
+    pub start_offset: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InDocumentMatch {
+    /// Char offset into the document content
+    pub char_offset: usize,
+    /// Match length in chars
+    pub length: usize,
+    pub page_number: Option<i32>,
+    pub snippet: String,
+}
+
+/// A page of a search hit with one of the search terms on it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchPageMatch {
+    pub page_number: i32,
+    /// Char offset of the page's first match into the document content
+    pub char_offset: i32,
+    pub snippet: String,
+}
+
+/// A document matching a search, with the pages the terms were found on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentSearchResult {
+    #[serde(flatten)]
+    pub document: Document,
+    /// The first few matching pages; empty for documents without pages and
+    /// for matches only in the title or file name
+    pub matching_pages: Vec<SearchPageMatch>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InDocumentSearchResult {
+    pub matches: Vec<InDocumentMatch>,
+    pub has_more: bool,
+}
+
+/// Kind of an AcroForm field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FormFieldType {
+    Text,
+    Checkbox,
+    Radio,
+    /// List or combo box
+    Choice,
+    Signature,
+}
+
+/// A filled-in field of a PDF form
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FormField {
+    /// Fully qualified name, parent names joined with "."
+    pub name: String,
+    pub field_type: FormFieldType,
+    /// Text as entered; the export value of a checked box or chosen radio
+    /// button; None when empty or unchecked
+    pub value: Option<String>,
+}
+
+/// AcroForm fields of a PDF, stored in the document's metadata
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PdfForm {
+    pub fields: Vec<FormField>,
+    /// The form is XFA only; its fields can't be read
+    pub xfa_unsupported: bool,
+}
+
+/// What a diff hunk does to the first document's text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DiffKind {
+    Equal,
+    /// Only in the second document
+    Insert,
+    /// Only in the first document
+    Delete,
+}
+
+/// A run of one kind in a document diff, as char ranges into both contents
+///
+/// Inserts have an empty range in the first document and deletes an empty
+/// range in the second, positioned where the change happens.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiffHunk {
+    pub kind: DiffKind,
+    pub a_start: usize,
+    pub a_end: usize,
+    pub b_start: usize,
+    pub b_end: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentDiff {
+    pub hunks: Vec<DiffHunk>,
+    /// The documents differed too much for a precise diff, or there were
+    /// more hunks than are returned
+    pub truncated: bool,
+}
+
+/// One match from a library-wide search, as a char range in a document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub document_id: Uuid,
+    pub start: usize,
+    pub end: usize,
+    pub score: f32,
+}
+
+/// A document's best hits after overlapping ones are merged
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentSearchHits {
+    pub document_id: Uuid,
+    /// Best first, capped per document
+    pub hits: Vec<SearchHit>,
+    /// Distinct hits before the cap, for "and N more matches"
+    pub total_hits_in_document: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContentSlice {
+    pub offset: usize,
+    pub text: String,
+    pub total_chars: usize,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct FileReference {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub file_path: String,
+    pub status: DocumentStatus,
+    /// Imported by reference, outside the storage root
+    pub external: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrphanFile {
+    pub path: String,
+    pub size_bytes: u64,
+    pub modified_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DanglingDocument {
+    pub document_id: Uuid,
+    pub user_id: Uuid,
+    pub file_path: String,
+    pub status: DocumentStatus,
+    /// An external file that was moved or deleted, rather than a stored one
+    pub external: bool,
+}
+
+/// What embedding the rest of a user's library would take, without calling any API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingEstimate {
+    pub model: String,
+    /// Chunks of live documents not yet embedded with `model`
+    pub chunk_count: i64,
+    /// Approximate tokens in those chunks
+    pub total_tokens: i64,
+    pub price_per_1k_tokens: f64,
+    pub estimated_cost: f64,
+}
+
+/// How often a term occurs across a set of documents
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TermCount {
+    pub term: String,
+    pub occurrences: i64,
+    /// Documents containing the term at least once
+    pub document_count: i64,
+}
+
+/// Period a term trend is grouped by, going by when documents were created
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TrendBucket {
+    #[default]
+    Month,
+    Quarter,
+    Year,
+}
+
+impl TrendBucket {
+    /// The matching Postgres date_trunc unit
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TrendBucket::Month => "month",
+            TrendBucket::Quarter => "quarter",
+            TrendBucket::Year => "year",
+        }
+    }
+}
+
+/// A term's use in documents created during one period
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TermTrendPoint {
+    pub period_start: chrono::NaiveDate,
+    pub occurrences: i64,
+    pub document_count: i64,
+}
+
+/// What currently limits the app, for the status bar
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemHealth {
+    /// The database refused writes when last probed; changes are rejected
+    pub read_only: bool,
+    pub storage_root: String,
+    pub storage_online: bool,
+    /// Free space on the storage root's volume; None while it is offline
+    pub storage_free_bytes: Option<u64>,
+    /// Processing jobs that panicked since startup; their documents failed
+    pub processing_panics: u64,
+}
+
+/// Whether the storage root can be reached, for the status bar
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageHealth {
+    pub storage_root: String,
+    pub storage_online: bool,
+    /// Documents flagged missing whose files were found again on this check
+    pub restored_documents: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConsistencyReport {
+    pub orphan_files: Vec<OrphanFile>,
+    pub dangling_documents: Vec<DanglingDocument>,
+    pub stale_thumbnails: Vec<String>,
+    /// The storage root itself was missing, so stored files weren't checked
+    pub storage_offline: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RepairOptions {
+    pub delete_orphans: bool,
+    pub orphan_min_age_hours: u64,
+    pub flag_missing_files: bool,
+    pub remove_stale_thumbnails: bool,
+}
+
+impl Default for RepairOptions {
+    fn default() -> Self {
+        RepairOptions {
+            delete_orphans: true,
+            orphan_min_age_hours: 24,
+            flag_missing_files: true,
+            remove_stale_thumbnails: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RepairReport {
+    pub orphans_deleted: usize,
+    pub orphans_skipped_recent: usize,
+    pub documents_flagged: usize,
+    pub thumbnails_removed: usize,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Workspace {
+    pub id: Uuid,
+    pub name: String,
+    pub owner_id: Uuid,
+    pub storage_limit_bytes: i64,
+    pub storage_used_bytes: i64,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A workspace and how many documents are in it, for sidebar badges
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkspaceDocumentCount {
+    pub workspace_id: Uuid,
+    pub name: String,
+    /// Documents not soft-deleted; 0 for empty workspaces
+    pub document_count: i64,
+    /// Uploads that name no workspace go in this one
+    pub is_default: bool,
+}
+
+/// Tags and processing defaults to start a workspace with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceTemplate {
+    pub id: Uuid,
+    pub name: String,
+    /// Created in the workspace unless it has a tag of that name already
+    pub tag_names: Vec<String>,
+    /// Become the workspace's processing defaults; None leaves them as they are
+    pub processing_options: Option<ProcessingOptions>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A workspace template as created or edited
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceTemplateInput {
+    pub name: String,
+    #[serde(default)]
+    pub tag_names: Vec<String>,
+    #[serde(default)]
+    pub processing_options: Option<ProcessingOptions>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageExportSummary {
+    pub document_count: usize,
+    /// Documents exported without a file because it was missing on disk
+    pub missing_files: Vec<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportedDocument {
+    /// Id of the document in the exporting user's library
+    pub source_id: Uuid,
+    pub document_id: Uuid,
+    /// Whether an identical stored file was reused instead of copied
+    pub deduplicated: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageImportFailure {
+    pub source_id: Uuid,
+    pub title: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceImportReport {
+    pub workspace: Workspace,
+    pub imported: Vec<ImportedDocument>,
+    pub failed: Vec<PackageImportFailure>,
+    /// Documents handled before the import was cancelled; those stay imported
+    pub cancelled_after: Option<usize>,
+}
+
+/// What an import does with a tag or workspace whose name is taken
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MergeStrategy {
+    /// Keep what the library has; tags of a workspace go into the existing one
+    #[default]
+    Skip,
+    /// Import under the first free name, e.g. "Clients (2)"
+    Rename,
+    /// Take the imported tag's color and description; workspaces, which
+    /// only have a name, are reused as with Skip
+    Overwrite,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryConfigSummary {
+    pub workspace_count: usize,
+    pub tag_count: usize,
+}
+
+/// An imported tag or workspace whose name was taken, and the name it got
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigRename {
+    pub name: String,
+    pub imported_as: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigImportFailure {
+    pub name: String,
+    /// Workspace of a tag; None for workspaces and tags outside one
+    pub workspace: Option<String>,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LibraryConfigImportReport {
+    pub workspaces_created: usize,
+    /// Existing workspaces of the same name used instead of creating one
+    pub workspaces_reused: usize,
+    pub workspaces_renamed: Vec<ConfigRename>,
+    pub tags_created: usize,
+    pub tags_skipped: usize,
+    pub tags_overwritten: usize,
+    pub tags_renamed: Vec<ConfigRename>,
+    pub failed: Vec<ConfigImportFailure>,
+}
+
+/// One processing attempt for a document
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ProcessingRun {
+    pub id: Uuid,
+    pub document_id: Uuid,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// running, completed, failed, cancelled or timed_out
+    pub outcome: String,
+    pub error: Option<String>,
+    pub extractor_name: Option<String>,
+    pub extractor_version: Option<String>,
+    pub pages_processed: Option<i32>,
+    /// Files found embedded in a PDF; None for other documents and older runs
+    pub attachments_found: Option<i32>,
+    /// Bytes of extracted text dropped or replaced so it could be stored, e.g.
+    /// NUL characters; None for runs that didn't complete and older runs
+    pub sanitized_bytes: Option<i32>,
+    /// Of the pages processed, those whose text was unchanged since the
+    /// previous run and kept their chunks and embeddings; the rest were
+    /// chunked again. None for documents without pages and older runs
+    pub pages_reused: Option<i32>,
+    /// The PDF was damaged and its text was read from a copy repaired in
+    /// memory
+    pub repaired: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessingHistory {
+    pub runs: Vec<ProcessingRun>,
+    pub has_more: bool,
+}
+
+/// Payload of the "processing:progress" event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessingProgressEvent {
+    pub document_id: Uuid,
+    /// Absent only if the run could not be recorded
+    pub run_id: Option<Uuid>,
+    /// started, extracting, saving or finished
+    pub stage: String,
+    /// Set when stage is finished
+    pub outcome: Option<String>,
+    /// Pages extracted so far while extracting a paged format; only goes up
+    pub pages_done: Option<usize>,
+    pub page_count: Option<usize>,
+}
+
+/// Outcome of the latest backup, as returned by get_backup_status
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BackupStatus {
+    pub running: bool,
+    /// Operation to pass to cancel_operation while running
+    pub operation_id: Option<Uuid>,
+    pub last_attempt_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_success_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_path: Option<String>,
+    pub last_document_count: Option<usize>,
+    pub last_error: Option<String>,
+    pub next_due_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// A persisted message about the outcome of background work
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Notification {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    /// e.g. "processing_failed", "storage_warning", "backup_failed"
+    pub kind: String,
+    pub title: String,
+    pub body: Option<String>,
+    pub document_id: Option<Uuid>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub read_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// An export recorded with a hash of the content it was made from
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ExportSnapshot {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub document_id: Uuid,
+    /// Title when exported, for snapshots whose document is gone
+    pub document_title: String,
+    /// SHA-256 of the document's extracted text when exported
+    pub content_hash: String,
+    /// "html" or "markdown"
+    pub format: String,
+    pub dest_path: String,
+    pub exported_at: chrono::DateTime<chrono::Utc>,
+    /// Whether the document has been deleted since; the snapshot is kept
+    pub document_deleted: bool,
+}
+
+/// How an export compares with its document today
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportMatch {
+    Matches,
+    /// The content changed, e.g. by reprocessing, after the export
+    Changed,
+    DocumentDeleted,
+}
+
+/// Result of verify_export
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportVerification {
+    pub snapshot: ExportSnapshot,
+    pub status: ExportMatch,
+    /// Hash of the content now; None once the document is deleted
+    pub current_hash: Option<String>,
+}
+
+/// A user's own redaction pattern
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct RedactionRule {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    /// "literal" (case-insensitive) or "regex"
+    pub kind: String,
+    pub pattern: String,
+    pub label: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateRedactionRuleRequest {
+    pub kind: String,
+    pub pattern: String,
+    pub label: Option<String>,
+}
+
+/// How often one rule matched, as returned by preview_redaction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedactionMatchCount {
+    /// None for the built-in email, phone and SSN rules
+    pub rule_id: Option<Uuid>,
+    pub label: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptionStatus {
+    pub enabled: bool,
+    /// Whether the key is held for this session
+    pub unlocked: bool,
+}
+
+/// Emitted as "storage:encryption-progress" while existing files are encrypted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptionProgress {
+    pub operation_id: Uuid,
+    pub processed: usize,
+    pub total: usize,
+    pub document_id: Uuid,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EncryptionReport {
+    pub encrypted: usize,
+    pub already_encrypted: usize,
+    /// Documents whose stored file no longer exists
+    pub missing: Vec<Uuid>,
+    pub failed: Vec<MigrationFailure>,
+    /// Files handled before the run was cancelled
+    pub cancelled_after: Option<usize>,
+}
+
+/// A quick switcher result with the matched char positions to highlight
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuickOpenMatch {
+    pub document_id: Uuid,
+    pub title: String,
+    pub file_name: Option<String>,
+    pub score: i32,
+    /// Char indices into the title; empty when the file name matched instead
+    pub title_positions: Vec<usize>,
+    /// Char indices into the file name, when it matched better than the title
+    pub file_name_positions: Vec<usize>,
+}
+
+/// A document that could be deleted to free quota
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct CleanupCandidate {
+    pub document_id: Uuid,
+    pub title: String,
+    pub file_size_bytes: i64,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Documents with identical files, oldest first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateGroup {
+    pub file_hash: String,
+    pub documents: Vec<CleanupCandidate>,
+    /// Freed by deleting every copy but the first
+    pub reclaimable_bytes: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CleanupCategory {
+    pub documents: Vec<CleanupCandidate>,
+    /// Freed by deleting every document listed
+    pub reclaimable_bytes: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CleanupSuggestions {
+    pub largest: CleanupCategory,
+    /// Never opened and older than six months
+    pub never_opened: CleanupCategory,
+    pub duplicates: Vec<DuplicateGroup>,
+    /// Failed documents whose stored file is still on disk
+    pub failed_with_files: CleanupCategory,
+}
+
+/// A document added during a digest period
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DigestDocument {
+    pub document_id: Uuid,
+    pub title: String,
+    pub summary: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A document whose processing failed during a digest period
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DigestFailure {
+    pub document_id: Uuid,
+    pub title: String,
+    pub error: Option<String>,
+    pub failed_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// What happened in a user's library since a given time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DigestReport {
+    pub since: chrono::DateTime<chrono::Utc>,
+    pub until: chrono::DateTime<chrono::Utc>,
+    pub new_count: i64,
+    pub new_bytes: i64,
+    pub failed_count: i64,
+    /// Live documents never opened, whenever they were added
+    pub unopened_count: i64,
+    /// Newest first, at most a handful of each
+    pub new_documents: Vec<DigestDocument>,
+    pub failures: Vec<DigestFailure>,
+    /// Largest documents added during the period
+    pub largest_uploads: Vec<CleanupCandidate>,
+    pub unopened: Vec<CleanupCandidate>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Digest {
+    pub report: DigestReport,
+    pub markdown: String,
+}
+
+/// A long-running command, as shown by list_operations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OperationInfo {
+    pub id: Uuid,
+    /// e.g. "backup", "storage_migration", "workspace_import"
+    pub kind: String,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub processed: usize,
+    pub total: Option<usize>,
+    /// Cancellation was requested and the operation is winding down
+    pub cancelling: bool,
+}
+
+/// File format of a library catalog
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CatalogFormat {
+    Markdown,
+    Csv,
+    /// One self-contained file, for printing
+    Html,
+}
+
+/// Order of a library catalog; documents without the value come last
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CatalogSort {
+    #[default]
+    Title,
+    Authors,
+    Year,
+    PageCount,
+    CreatedAt,
+}
+
+impl CatalogSort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CatalogSort::Title => "title",
+            CatalogSort::Authors => "authors",
+            CatalogSort::Year => "year",
+            CatalogSort::PageCount => "page_count",
+            CatalogSort::CreatedAt => "created_at",
+        }
+    }
+}
+
+/// A document as a library catalog lists it
+#[derive(Debug, Clone, FromRow)]
+pub struct CatalogEntry {
+    pub id: Uuid,
+    pub title: String,
+    /// From the extraction metadata, when the extractor found any
+    pub authors: Option<String>,
+    pub year: Option<String>,
+    pub page_count: Option<i32>,
+    /// Tag names joined by "; "
+    pub tags: Option<String>,
+    pub summary: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalogReport {
+    pub entries_written: usize,
+}
+
+/// A column of a search results CSV export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CsvColumn {
+    Id,
+    Title,
+    FileName,
+    CreatedAt,
+    Workspace,
+    /// Tag names joined by ";"
+    Tags,
+    /// Content around the first match of the first search term
+    Snippet,
+}
+
+impl CsvColumn {
+    pub const ALL: [CsvColumn; 7] = [
+        CsvColumn::Id,
+        CsvColumn::Title,
+        CsvColumn::FileName,
+        CsvColumn::CreatedAt,
+        CsvColumn::Workspace,
+        CsvColumn::Tags,
+        CsvColumn::Snippet,
+    ];
+
+    /// Header of the column in the CSV
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CsvColumn::Id => "id",
+            CsvColumn::Title => "title",
+            CsvColumn::FileName => "file_name",
+            CsvColumn::CreatedAt => "created_at",
+            CsvColumn::Workspace => "workspace",
+            CsvColumn::Tags => "tags",
+            CsvColumn::Snippet => "snippet",
+        }
+    }
+}
+
+/// One search match as exported, without the document's content
+#[derive(Debug, Clone)]
+pub struct SearchExportRow {
+    pub id: Uuid,
+    pub title: String,
+    pub file_name: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub workspace: Option<String>,
+    pub tags: Option<String>,
+    pub snippet: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchExportReport {
+    /// Matching documents written, not counting the header or a truncation marker
+    pub rows_written: usize,
+    /// More documents matched than the row cap allowed
+    pub truncated: bool,
+}
+
+/// How a document's summary was made; stored in summary_source
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SummarySource {
+    /// Column names and row count of a CSV
+    CsvColumns,
+    /// Subject and first lines of an email's body
+    Email,
+    /// The abstract of a paper
+    Abstract,
+    /// The first comment block of a code or text file
+    CommentBlock,
+    /// First paragraph or leading text, for anything else
+    Generic,
+}
+
+impl SummarySource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SummarySource::CsvColumns => "csv_columns",
+            SummarySource::Email => "email",
+            SummarySource::Abstract => "abstract",
+            SummarySource::CommentBlock => "comment_block",
+            SummarySource::Generic => "generic",
+        }
+    }
+}
+
+/// Data derived from a document that a code upgrade can make outdated; the
+/// keys of documents.derived_versions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DerivedTarget {
+    Summaries,
+    /// Chunks for embedding; rebuilt chunks lose their embeddings
+    Chunks,
+    /// Term counts behind term stats and trends
+    TextStats,
+    /// Title sort keys
+    SortKeys,
+}
+
+impl DerivedTarget {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DerivedTarget::Summaries => "summaries",
+            DerivedTarget::Chunks => "chunks",
+            DerivedTarget::TextStats => "text_stats",
+            DerivedTarget::SortKeys => "sort_keys",
+        }
+    }
+}
+
+/// Emitted as "maintenance:rebuild-progress" while derived data is rebuilt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RebuildProgress {
+    pub operation_id: Uuid,
+    pub target: DerivedTarget,
+    pub processed: usize,
+    pub total: usize,
+    pub document_id: Uuid,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RebuildReport {
+    /// In the order asked for; targets not reached before a cancel are missing
+    pub targets: Vec<RebuildTargetReport>,
+    pub cancelled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RebuildTargetReport {
+    pub target: DerivedTarget,
+    pub rebuilt: usize,
+    /// Left outdated, so a later rebuild tries them again
+    pub failed: Vec<MigrationFailure>,
+}
+
+/// Result of compress_existing_content
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContentCompressionReport {
+    pub documents_compressed: usize,
+    pub pages_compressed: usize,
+    /// UTF-8 size of the text compressed
+    pub plain_bytes: u64,
+    pub compressed_bytes: u64,
+    /// plain_bytes over compressed_bytes; 0 when nothing was compressed
+    pub compression_ratio: f64,
+    pub failed: Vec<MigrationFailure>,
+    pub cancelled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentSummary {
+    pub summary: String,
+    pub source: SummarySource,
+}
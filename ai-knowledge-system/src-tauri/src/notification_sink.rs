@@ -0,0 +1,64 @@
+//! Notifications for long-running work: always recorded in-app, and shown
+//! by the OS as well while no app window has focus
+//!
+//! Native notifications are best effort. Once one can't be shown, e.g.
+//! because permission was denied, the sink logs why and sticks to in-app
+//! notifications for the rest of the run.
+
+use crate::display::Formatter;
+use crate::host::Host;
+use crate::services::notification::NewNotification;
+use crate::services::NotificationService;
+use crate::settings::SettingsStore;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+pub struct NotificationSink {
+    host: Arc<dyn Host>,
+    notifications: Arc<Mutex<NotificationService>>,
+    settings: Arc<SettingsStore>,
+    /// Set after the first native notification that couldn't be shown
+    native_unavailable: AtomicBool,
+}
+
+impl NotificationSink {
+    pub fn new(
+        host: Arc<dyn Host>,
+        notifications: Arc<Mutex<NotificationService>>,
+        settings: Arc<SettingsStore>,
+    ) -> Self {
+        NotificationSink {
+            host,
+            notifications,
+            settings,
+            native_unavailable: AtomicBool::new(false),
+        }
+    }
+
+    /// Formats values in notification text for the locale setting
+    pub async fn formatter(&self) -> Formatter {
+        Formatter::new(&self.settings.get().await.locale, chrono::Utc::now())
+    }
+
+    /// Record a notification in-app, and show it natively when enabled by
+    /// `notifications_native` and the app isn't in front
+    ///
+    /// Failures are only logged, like the in-app notification's.
+    pub async fn notify(&self, new: NewNotification<'_>) {
+        crate::notify(&*self.host, &self.notifications, new).await;
+
+        if self.native_unavailable.load(Ordering::Relaxed)
+            || !self.settings.get().await.notifications_native
+            || self.host.focused()
+        {
+            return;
+        }
+        if let Err(reason) = self.host.show_native(new.title, new.body) {
+            // Log once; every later notification would fail the same way
+            if !self.native_unavailable.swap(true, Ordering::Relaxed) {
+                eprintln!("Native notifications unavailable, showing them in-app only: {}", reason);
+            }
+        }
+    }
+}
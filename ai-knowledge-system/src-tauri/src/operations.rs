@@ -0,0 +1,117 @@
+//! Registry of long-running commands so the UI can follow and cancel them
+//!
+//! A command registers itself with `OperationRegistry::start`, checks its
+//! token between items and reports progress on the handle. The entry is
+//! removed when the handle is dropped, however the command ends.
+
+use crate::models::OperationInfo;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+struct Operation {
+    kind: String,
+    started_at: chrono::DateTime<chrono::Utc>,
+    processed: usize,
+    total: Option<usize>,
+    token: CancellationToken,
+}
+
+#[derive(Default)]
+pub struct OperationRegistry {
+    operations: Mutex<HashMap<Uuid, Operation>>,
+}
+
+impl OperationRegistry {
+    /// Register a running operation of `kind`, e.g. "backup"
+    pub fn start(self: &Arc<Self>, kind: &str) -> OperationHandle {
+        let id = Uuid::new_v4();
+        let token = CancellationToken::new();
+        self.operations.lock().expect("operation registry poisoned").insert(
+            id,
+            Operation {
+                kind: kind.to_string(),
+                started_at: chrono::Utc::now(),
+                processed: 0,
+                total: None,
+                token: token.clone(),
+            },
+        );
+        OperationHandle {
+            id,
+            token,
+            registry: Arc::clone(self),
+        }
+    }
+
+    /// Ask an operation to stop; unknown or finished ids are ignored
+    pub fn cancel(&self, id: Uuid) {
+        if let Some(operation) = self.operations.lock().expect("operation registry poisoned").get(&id) {
+            operation.token.cancel();
+        }
+    }
+
+    /// Running operations, oldest first
+    pub fn list(&self) -> Vec<OperationInfo> {
+        let operations = self.operations.lock().expect("operation registry poisoned");
+        let mut list: Vec<OperationInfo> = operations
+            .iter()
+            .map(|(&id, operation)| OperationInfo {
+                id,
+                kind: operation.kind.clone(),
+                started_at: operation.started_at,
+                processed: operation.processed,
+                total: operation.total,
+                cancelling: operation.token.is_cancelled(),
+            })
+            .collect();
+        list.sort_by_key(|info| info.started_at);
+        list
+    }
+}
+
+/// A registered operation; dropping it removes the registry entry
+pub struct OperationHandle {
+    id: Uuid,
+    token: CancellationToken,
+    registry: Arc<OperationRegistry>,
+}
+
+impl OperationHandle {
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    /// Token for work that runs outside the command, e.g. on a blocking thread
+    pub fn token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    pub fn set_progress(&self, processed: usize, total: usize) {
+        if let Some(operation) = self
+            .registry
+            .operations
+            .lock()
+            .expect("operation registry poisoned")
+            .get_mut(&self.id)
+        {
+            operation.processed = processed;
+            operation.total = Some(total);
+        }
+    }
+}
+
+impl Drop for OperationHandle {
+    fn drop(&mut self) {
+        self.registry
+            .operations
+            .lock()
+            .expect("operation registry poisoned")
+            .remove(&self.id);
+    }
+}
@@ -0,0 +1,366 @@
+//! Position-aware PDF text extraction for multi-column pages
+//!
+//! lopdf's `extract_text` follows the content stream, which on two-column
+//! papers often alternates between the columns line by line. Here the page's
+//! text operators are interpreted to place each fragment on the page; pages
+//! with a clear vertical gutter are then read column by column, with
+//! full-width blocks such as titles breaking the column flow.
+//!
+//! Glyph widths aren't looked up, so fragment extents are estimated from the
+//! font size. That is accurate enough to find gutters and order lines, not
+//! to reproduce spacing.
+
+use lopdf::content::Content;
+use lopdf::{Document, Object, ObjectId};
+use std::collections::BTreeMap;
+
+/// Estimated advance of one glyph, as a fraction of the font size
+const AVERAGE_GLYPH_WIDTH: f32 = 0.5;
+/// Bins the text extent is split into when looking for gutters
+const COVERAGE_BINS: usize = 100;
+/// Gutters are only looked for in this middle share of the text extent
+const GUTTER_SEARCH_RANGE: (usize, usize) = (20, 80);
+/// A bin is empty if at most this share of fragments crosses it
+const GUTTER_MAX_COVERAGE: f32 = 0.03;
+/// Narrowest gutter, in bins
+const MIN_GUTTER_BINS: usize = 2;
+/// Each column must start at least this share of the page's fragments
+const MIN_COLUMN_SHARE: f32 = 0.15;
+/// Pages with fewer fragments are never treated as multi-column
+const MIN_FRAGMENTS: usize = 12;
+
+type Matrix = [f32; 6];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// `a` applied first, then `b`
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    [
+        a[0] * b[0] + a[1] * b[2],
+        a[0] * b[1] + a[1] * b[3],
+        a[2] * b[0] + a[3] * b[2],
+        a[2] * b[1] + a[3] * b[3],
+        a[4] * b[0] + a[5] * b[2] + b[4],
+        a[4] * b[1] + a[5] * b[3] + b[5],
+    ]
+}
+
+fn translation(tx: f32, ty: f32) -> Matrix {
+    [1.0, 0.0, 0.0, 1.0, tx, ty]
+}
+
+/// A run of text shown by one operator, in page space
+#[derive(Debug)]
+struct Fragment {
+    x: f32,
+    y: f32,
+    /// Estimated right edge
+    end: f32,
+    size: f32,
+    text: String,
+}
+
+/// A page's text fragments in page space
+pub struct PageLayout {
+    fragments: Vec<Fragment>,
+}
+
+impl PageLayout {
+    /// Place the text on a page, or None if its content can't be decoded
+    pub fn read(doc: &Document, page_id: ObjectId) -> Option<Self> {
+        let encodings: BTreeMap<Vec<u8>, &str> = doc
+            .get_page_fonts(page_id)
+            .into_iter()
+            .map(|(name, font)| (name, font.get_font_encoding()))
+            .collect();
+        let content = Content::decode(&doc.get_page_content(page_id).ok()?).ok()?;
+
+        let mut state = TextState::default();
+        let mut fragments = Vec::new();
+        for operation in &content.operations {
+            let operands = &operation.operands;
+            match operation.operator.as_str() {
+                "q" => state.saved.push(state.ctm),
+                "Q" => state.ctm = state.saved.pop().unwrap_or(IDENTITY),
+                "cm" => {
+                    if let Some(m) = matrix(operands) {
+                        state.ctm = multiply(&m, &state.ctm);
+                    }
+                }
+                "BT" => {
+                    state.line = IDENTITY;
+                    state.text = IDENTITY;
+                }
+                "Tf" => {
+                    state.encoding = operands
+                        .first()
+                        .and_then(|name| name.as_name().ok())
+                        .and_then(|name| encodings.get(name).copied());
+                    state.font_size = operands.get(1).and_then(number).unwrap_or(state.font_size);
+                }
+                "TL" => state.leading = operands.first().and_then(number).unwrap_or(state.leading),
+                "Td" | "TD" => {
+                    let (tx, ty) = (
+                        operands.first().and_then(number).unwrap_or(0.0),
+                        operands.get(1).and_then(number).unwrap_or(0.0),
+                    );
+                    if operation.operator == "TD" {
+                        state.leading = -ty;
+                    }
+                    state.move_line(tx, ty);
+                }
+                "Tm" => {
+                    if let Some(m) = matrix(operands) {
+                        state.line = m;
+                        state.text = m;
+                    }
+                }
+                "T*" => state.next_line(),
+                "Tj" | "TJ" => state.show(operands, &mut fragments),
+                "'" => {
+                    state.next_line();
+                    state.show(operands, &mut fragments);
+                }
+                "\"" => {
+                    state.next_line();
+                    state.show(operands.get(2..).unwrap_or_default(), &mut fragments);
+                }
+                _ => {}
+            }
+        }
+
+        Some(PageLayout { fragments })
+    }
+
+    /// Left and right edges of each column, or None for a single-column page
+    pub fn columns(&self) -> Option<Vec<(f32, f32)>> {
+        if self.fragments.len() < MIN_FRAGMENTS {
+            return None;
+        }
+        let left = self.fragments.iter().map(|f| f.x).fold(f32::INFINITY, f32::min);
+        let right = self.fragments.iter().map(|f| f.end).fold(f32::NEG_INFINITY, f32::max);
+        let bin_width = (right - left) / COVERAGE_BINS as f32;
+        if bin_width <= 0.0 {
+            return None;
+        }
+        let bin = |x: f32| (((x - left) / bin_width) as usize).min(COVERAGE_BINS - 1);
+
+        // How many fragments cross each vertical strip of the text extent
+        let mut coverage = [0usize; COVERAGE_BINS];
+        for fragment in &self.fragments {
+            for count in &mut coverage[bin(fragment.x)..=bin(fragment.end)] {
+                *count += 1;
+            }
+        }
+
+        let max_coverage = (self.fragments.len() as f32 * GUTTER_MAX_COVERAGE) as usize;
+        let mut gutters = Vec::new();
+        let mut run_start = None;
+        for (index, &count) in coverage.iter().enumerate() {
+            let empty = (GUTTER_SEARCH_RANGE.0..GUTTER_SEARCH_RANGE.1).contains(&index) && count <= max_coverage;
+            match (empty, run_start) {
+                (true, None) => run_start = Some(index),
+                (false, Some(start)) => {
+                    if index - start >= MIN_GUTTER_BINS {
+                        gutters.push(left + (start + index) as f32 / 2.0 * bin_width);
+                    }
+                    run_start = None;
+                }
+                _ => {}
+            }
+        }
+        if gutters.is_empty() {
+            return None;
+        }
+
+        let mut edges = vec![left];
+        edges.extend(gutters);
+        edges.push(right);
+        let columns: Vec<(f32, f32)> = edges.windows(2).map(|w| (w[0], w[1])).collect();
+
+        // Both sides of a gutter need real text, not a stray page number
+        let min_starts = (self.fragments.len() as f32 * MIN_COLUMN_SHARE) as usize;
+        let balanced = columns.iter().all(|&(start, end)| {
+            self.fragments.iter().filter(|f| f.x >= start && f.x < end).count() >= min_starts.max(1)
+        });
+        balanced.then_some(columns)
+    }
+
+    /// Text read column by column, left to right
+    ///
+    /// Fragments that run well into the next column are full-width: the
+    /// columns above them are read out first, then the fragment, then the
+    /// columns continue below it.
+    pub fn column_text(&self, columns: &[(f32, f32)]) -> String {
+        let mut fragments: Vec<&Fragment> = self.fragments.iter().collect();
+        fragments.sort_by(|a, b| b.y.total_cmp(&a.y).then(a.x.total_cmp(&b.x)));
+
+        let column_of = |f: &Fragment| columns.iter().rposition(|&(start, _)| f.x >= start).unwrap_or(0);
+        let spans = |f: &Fragment, column: usize| {
+            columns
+                .get(column + 1)
+                .is_some_and(|&(start, end)| f.end > start + (end - start) / 2.0)
+        };
+
+        let mut text = String::new();
+        let mut pending: Vec<Vec<&Fragment>> = vec![Vec::new(); columns.len()];
+        for fragment in fragments {
+            let column = column_of(fragment);
+            if spans(fragment, column) {
+                flush_columns(&mut pending, &mut text);
+                text.push_str(&lines_text(&[fragment]));
+            } else {
+                pending[column].push(fragment);
+            }
+        }
+        flush_columns(&mut pending, &mut text);
+        text
+    }
+
+    /// Text of the page read top to bottom, ignoring columns
+    pub fn positional_text(&self) -> String {
+        let mut fragments: Vec<&Fragment> = self.fragments.iter().collect();
+        fragments.sort_by(|a, b| b.y.total_cmp(&a.y).then(a.x.total_cmp(&b.x)));
+        lines_text(&fragments)
+    }
+}
+
+/// Append the text of each column in turn and empty them
+fn flush_columns(columns: &mut [Vec<&Fragment>], text: &mut String) {
+    for column in columns.iter_mut() {
+        if !column.is_empty() {
+            text.push_str(&lines_text(column));
+            column.clear();
+        }
+    }
+}
+
+/// Join fragments, already sorted top to bottom, into lines
+///
+/// Fragments within half a font size of each other vertically share a line
+/// and are ordered by x; a space goes between them unless they touch.
+fn lines_text(fragments: &[&Fragment]) -> String {
+    let mut lines: Vec<Vec<&Fragment>> = Vec::new();
+    for &fragment in fragments {
+        match lines.last_mut() {
+            Some(line) if (line[0].y - fragment.y).abs() <= line[0].size.max(1.0) / 2.0 => {
+                line.push(fragment)
+            }
+            _ => lines.push(vec![fragment]),
+        }
+    }
+
+    let mut text = String::new();
+    for mut line in lines {
+        line.sort_by(|a, b| a.x.total_cmp(&b.x));
+        let mut previous: Option<&Fragment> = None;
+        for fragment in line {
+            if let Some(previous) = previous {
+                let touching = fragment.x - previous.end < previous.size * 0.15;
+                if !touching && !previous.text.ends_with(' ') && !fragment.text.starts_with(' ') {
+                    text.push(' ');
+                }
+            }
+            text.push_str(&fragment.text);
+            previous = Some(fragment);
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Graphics and text state needed to place text
+struct TextState<'a> {
+    ctm: Matrix,
+    saved: Vec<Matrix>,
+    /// Start of the current line
+    line: Matrix,
+    text: Matrix,
+    leading: f32,
+    font_size: f32,
+    encoding: Option<&'a str>,
+}
+
+impl Default for TextState<'_> {
+    fn default() -> Self {
+        TextState {
+            ctm: IDENTITY,
+            saved: Vec::new(),
+            line: IDENTITY,
+            text: IDENTITY,
+            leading: 0.0,
+            font_size: 1.0,
+            encoding: None,
+        }
+    }
+}
+
+impl TextState<'_> {
+    fn move_line(&mut self, tx: f32, ty: f32) {
+        self.line = multiply(&translation(tx, ty), &self.line);
+        self.text = self.line;
+    }
+
+    fn next_line(&mut self) {
+        self.move_line(0.0, -self.leading);
+    }
+
+    /// Append a shown string to `text`, returning its estimated advance
+    fn decode(&self, bytes: &[u8], text: &mut String) -> f32 {
+        let decoded = Document::decode_text(self.encoding, bytes);
+        text.push_str(&decoded);
+        decoded.chars().count() as f32 * AVERAGE_GLYPH_WIDTH * self.font_size
+    }
+
+    /// Record the strings in `operands` as one fragment and advance past it
+    fn show(&mut self, operands: &[Object], fragments: &mut Vec<Fragment>) {
+        let mut text = String::new();
+        let mut advance = 0.0;
+        for operand in operands {
+            match operand {
+                Object::String(bytes, _) => advance += self.decode(bytes, &mut text),
+                Object::Array(items) => {
+                    for item in items {
+                        match item {
+                            Object::String(bytes, _) => advance += self.decode(bytes, &mut text),
+                            // Kerning in thousandths of an em; large negative values are word gaps
+                            other => {
+                                if let Some(offset) = number(other) {
+                                    if offset < -100.0 && !text.ends_with(' ') {
+                                        text.push(' ');
+                                    }
+                                    advance -= offset / 1000.0 * self.font_size;
+                                }
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let start = multiply(&self.text, &self.ctm);
+        self.text = multiply(&translation(advance, 0.0), &self.text);
+        if text.trim().is_empty() {
+            return;
+        }
+        let end = multiply(&self.text, &self.ctm);
+        let scale = start[2].hypot(start[3]);
+        fragments.push(Fragment {
+            x: start[4],
+            y: start[5],
+            end: end[4].max(start[4]),
+            size: self.font_size * if scale > 0.0 { scale } else { 1.0 },
+            text,
+        });
+    }
+}
+
+fn number(object: &Object) -> Option<f32> {
+    object.as_float().ok()
+}
+
+fn matrix(operands: &[Object]) -> Option<Matrix> {
+    let values: Vec<f32> = operands.iter().filter_map(number).collect();
+    values.try_into().ok()
+}
@@ -1,51 +1,696 @@
-use lopdf::Document;
-use std::path::Path;
+use crate::models::{FormField, FormFieldType, PdfForm, PdfLayout};
+use crate::pdf_layout::PageLayout;
+use crate::pdf_repair;
+use crate::processing::ExtractionThreads;
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+/// Progress is reported about this many times per document
+const PROGRESS_STEPS: usize = 100;
+
+/// Text extracted from a PDF, whole and per page
+pub struct PdfText {
+    pub text: String,
+    pub pages: Vec<String>,
+    /// Pages abandoned because extraction exceeded the per-page timeout
+    pub skipped_pages: Vec<u32>,
+    /// Pages read column by column
+    pub multi_column_pages: Vec<u32>,
+    /// AcroForm fields; empty for PDFs without a form
+    pub form: PdfForm,
+    /// The file only loaded after its cross-reference table was rebuilt
+    pub repaired: bool,
+}
+
+/// Load a PDF, repairing it in memory when lopdf refuses it as it is; also
+/// whether it was repaired
+///
+/// A file that can't be repaired either fails with lopdf's error.
+fn load_document(path: &Path) -> Result<(Document, bool), String> {
+    let error = match Document::load(path) {
+        Ok(doc) => return Ok((doc, false)),
+        Err(e) => e,
+    };
+    match std::fs::read(path).ok().and_then(|data| pdf_repair::repair(&data)) {
+        Some(doc) => {
+            eprintln!("Repaired {} after it failed to load: {}", path.display(), error);
+            Ok((doc, true))
+        }
+        None => Err(format!("Failed to load PDF: {} (repairing it failed too)", error)),
+    }
+}
 
 /// Extract text content from a PDF file
-pub fn extract_text_from_pdf(path: &Path) -> Result<String, String> {
-    let doc = Document::load(path).map_err(|e| format!("Failed to load PDF: {}", e))?;
+///
+/// Pages are extracted in parallel on threads from `ExtractionThreads`,
+/// each on its own thread, and put back in page order. With a
+/// `page_timeout`, a page that takes longer is skipped, so one pathological
+/// page can't stall the whole document; the runaway thread is left to
+/// finish on its own and its thread is handed on.
+///
+/// `layout` picks the reading order; see `PdfLayout`. `progress` is called
+/// with (pages done, page count) as pages finish, in whatever order.
+pub fn extract_text_from_pdf(
+    path: &Path,
+    page_timeout: Option<Duration>,
+    layout: PdfLayout,
+    progress: &dyn Fn(usize, usize),
+) -> Result<PdfText, String> {
+    extract_on(ExtractionThreads::global(), path, page_timeout, layout, progress)
+}
+
+/// extract_text_from_pdf's work, on threads from `threads`
+fn extract_on(
+    threads: &ExtractionThreads,
+    path: &Path,
+    page_timeout: Option<Duration>,
+    layout: PdfLayout,
+    progress: &dyn Fn(usize, usize),
+) -> Result<PdfText, String> {
+    let (doc, repaired) = load_document(path)?;
+    let doc = Arc::new(doc);
+    let pages: Vec<(u32, ObjectId)> = doc.get_pages().into_iter().collect();
+    let total = pages.len();
+    let report_every = (total / PROGRESS_STEPS).max(1);
+
+    let mut results: Vec<Option<(String, bool)>> = vec![None; total];
+    let mut skipped_pages = Vec::new();
+    // Deadline of each page being extracted, by index into `pages`
+    let mut running: HashMap<usize, Option<Instant>> = HashMap::new();
+    let (tx, rx) = mpsc::channel();
+    let mut share = threads.join();
+    let (mut next, mut done, mut reported) = (0, 0, 0);
+
+    while done < total {
+        while next < total && share.acquire() {
+            let (page_num, page_id) = pages[next];
+            let (tx, doc) = (tx.clone(), Arc::clone(&doc));
+            std::thread::spawn(move || {
+                // A page that panics comes back empty rather than never
+                let result = panic::catch_unwind(AssertUnwindSafe(|| extract_page(&doc, page_num, page_id, layout)))
+                    .unwrap_or_default();
+                let _ = tx.send((next, result));
+            });
+            running.insert(next, page_timeout.map(|limit| Instant::now() + limit));
+            next += 1;
+        }
+
+        let deadline = running.values().flatten().min().copied();
+        let received = match deadline {
+            Some(deadline) => rx.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            None => rx.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+        };
+        let finished = match received {
+            // Pages given up on may still report; they stay skipped
+            Ok((index, result)) => match running.remove(&index) {
+                Some(_) => {
+                    results[index] = Some(result);
+                    1
+                }
+                None => 0,
+            },
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                let now = Instant::now();
+                let expired: Vec<usize> = running
+                    .iter()
+                    .filter(|(_, deadline)| deadline.is_some_and(|deadline| deadline <= now))
+                    .map(|(index, _)| *index)
+                    .collect();
+                for index in &expired {
+                    running.remove(index);
+                    let page_num = pages[*index].0;
+                    eprintln!("Skipping page {} of {}: extraction timed out", page_num, path.display());
+                    skipped_pages.push(page_num);
+                }
+                expired.len()
+            }
+            // The sender kept here means this can't happen
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+
+        for _ in 0..finished {
+            share.release();
+        }
+        done += finished;
+        if finished > 0 && (done - reported >= report_every || done == total) {
+            progress(done, total);
+            reported = done;
+        }
+    }
+    drop(share);
+    skipped_pages.sort_unstable();
 
     let mut text = String::new();
-    let pages = doc.get_pages();
+    let mut page_texts = Vec::with_capacity(total);
+    let mut multi_column_pages = Vec::new();
+    for ((page_num, _), result) in pages.iter().zip(results) {
+        // Keep an empty entry for unreadable pages so numbering stays aligned
+        let (page_text, multi_column) = result.unwrap_or_default();
+        if multi_column {
+            multi_column_pages.push(*page_num);
+        }
+        text.push_str(&page_text);
+        text.push('\n');
+        page_texts.push(page_text);
+    }
 
-    for (page_num, _) in pages.iter() {
-        if let Ok(page_text) = doc.extract_text(&[*page_num]) {
-            text.push_str(&page_text);
-            text.push('\n');
+    Ok(PdfText {
+        text,
+        pages: page_texts,
+        skipped_pages,
+        multi_column_pages,
+        form: read_form(&doc),
+        repaired,
+    })
+}
+
+/// Text of one page in the order `layout` asks for, and whether it was read
+/// column by column
+///
+/// Pages whose content can't be placed fall back to content-stream order.
+fn extract_page(doc: &Document, page_num: u32, page_id: ObjectId, layout: PdfLayout) -> (String, bool) {
+    if layout != PdfLayout::StreamOrder {
+        if let Some(page) = PageLayout::read(doc, page_id) {
+            match page.columns() {
+                Some(columns) => return (page.column_text(&columns), true),
+                None if layout == PdfLayout::Columns => return (page.positional_text(), false),
+                None => {}
+            }
         }
     }
+    (doc.extract_text(&[page_num]).unwrap_or_default(), false)
+}
+
+/// Field flag (Ff) bit of a radio button group
+const RADIO_FLAG: i64 = 1 << 15;
+
+/// Field flag (Ff) bit of a push button, which holds no value
+const PUSHBUTTON_FLAG: i64 = 1 << 16;
+
+/// Deepest field hierarchy followed; guards against reference cycles
+const MAX_FIELD_DEPTH: usize = 32;
+
+/// Read the AcroForm fields of a PDF
+pub fn extract_form_fields(path: &Path) -> Result<PdfForm, String> {
+    let (doc, _) = load_document(path)?;
+    Ok(read_form(&doc))
+}
+
+fn read_form(doc: &Document) -> PdfForm {
+    let acro_form = doc
+        .trailer
+        .get(b"Root")
+        .ok()
+        .and_then(|root| resolve_dict(doc, root))
+        .and_then(|catalog| catalog.get(b"AcroForm").ok())
+        .and_then(|form| resolve_dict(doc, form));
+    let Some(acro_form) = acro_form else {
+        return PdfForm::default();
+    };
+
+    let mut fields = Vec::new();
+    let roots = acro_form
+        .get(b"Fields")
+        .ok()
+        .and_then(|f| resolve(doc, f))
+        .and_then(|f| f.as_array().ok());
+    for root in roots.into_iter().flatten() {
+        collect_fields(doc, root, &Inherited::default(), 0, &mut fields);
+    }
+
+    // XFA forms keep their data in XML packets; with no AcroForm fields
+    // alongside there is nothing to read
+    let xfa_unsupported = acro_form.has(b"XFA") && fields.is_empty();
+    PdfForm {
+        fields,
+        xfa_unsupported,
+    }
+}
+
+/// Field attributes passed down from ancestors in the field tree
+#[derive(Default, Clone)]
+struct Inherited<'a> {
+    name: String,
+    field_type: Option<&'a [u8]>,
+    flags: i64,
+    value: Option<&'a Object>,
+}
+
+fn collect_fields<'a>(
+    doc: &'a Document,
+    node: &'a Object,
+    parent: &Inherited<'a>,
+    depth: usize,
+    fields: &mut Vec<FormField>,
+) {
+    if depth > MAX_FIELD_DEPTH {
+        return;
+    }
+    let Some(dict) = resolve_dict(doc, node) else {
+        return;
+    };
+
+    let mut inherited = parent.clone();
+    if let Some(part) = dict.get(b"T").ok().and_then(|t| resolve(doc, t)).and_then(|t| t.as_str().ok()) {
+        let part = decode_text_string(part);
+        inherited.name = if inherited.name.is_empty() {
+            part
+        } else {
+            format!("{}.{}", inherited.name, part)
+        };
+    }
+    if let Ok(field_type) = dict.get(b"FT").and_then(Object::as_name) {
+        inherited.field_type = Some(field_type);
+    }
+    if let Ok(flags) = dict.get(b"Ff").and_then(Object::as_i64) {
+        inherited.flags = flags;
+    }
+    if let Ok(value) = dict.get(b"V") {
+        inherited.value = Some(value);
+    }
+
+    // Named kids are child fields; unnamed ones are this field's widgets
+    let kid_objects: &[Object] = dict
+        .get(b"Kids")
+        .ok()
+        .and_then(|k| resolve(doc, k))
+        .and_then(|k| k.as_array().ok())
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let kids: Vec<&Dictionary> = kid_objects.iter().filter_map(|kid| resolve_dict(doc, kid)).collect();
+    if kids.iter().any(|kid| kid.has(b"T")) {
+        for kid in kid_objects {
+            collect_fields(doc, kid, &inherited, depth + 1, fields);
+        }
+        return;
+    }
+
+    let widgets = if kids.is_empty() { vec![dict] } else { kids };
+    if let Some(field) = read_field(doc, dict, &inherited, &widgets) {
+        fields.push(field);
+    }
+}
+
+/// A terminal field and its value; None for push buttons and unknown types
+fn read_field(doc: &Document, dict: &Dictionary, inherited: &Inherited, widgets: &[&Dictionary]) -> Option<FormField> {
+    let field_type = match inherited.field_type? {
+        b"Tx" => FormFieldType::Text,
+        b"Btn" if inherited.flags & PUSHBUTTON_FLAG != 0 => return None,
+        b"Btn" if inherited.flags & RADIO_FLAG != 0 => FormFieldType::Radio,
+        b"Btn" => FormFieldType::Checkbox,
+        b"Ch" => FormFieldType::Choice,
+        b"Sig" => FormFieldType::Signature,
+        _ => return None,
+    };
+
+    let value = inherited.value.and_then(|v| resolve(doc, v));
+    let value = match field_type {
+        FormFieldType::Text => value.and_then(text_value),
+        FormFieldType::Choice => value.and_then(|v| choice_value(doc, v)),
+        FormFieldType::Checkbox | FormFieldType::Radio => button_value(doc, dict, value, widgets),
+        FormFieldType::Signature => value.map(|_| "Signed".to_string()),
+    };
+
+    Some(FormField {
+        name: inherited.name.clone(),
+        field_type,
+        value: value.filter(|v| !v.trim().is_empty()),
+    })
+}
+
+fn text_value(object: &Object) -> Option<String> {
+    object.as_str().ok().map(decode_text_string)
+}
+
+/// A choice field holds one string, or an array of them for multi-select
+fn choice_value(doc: &Document, object: &Object) -> Option<String> {
+    match object {
+        Object::Array(items) => {
+            let chosen: Vec<String> = items
+                .iter()
+                .filter_map(|item| resolve(doc, item).and_then(text_value))
+                .collect();
+            (!chosen.is_empty()).then(|| chosen.join(", "))
+        }
+        other => text_value(other),
+    }
+}
+
+/// Export value of a checked box or chosen radio button
+///
+/// The value is the on-state name of the selected widget, or, when the
+/// field has an Opt array, the export value at that name's index.
+fn button_value(doc: &Document, dict: &Dictionary, value: Option<&Object>, widgets: &[&Dictionary]) -> Option<String> {
+    // Some writers only set the widgets' appearance state
+    let state = value.and_then(|v| v.as_name().ok()).or_else(|| {
+        widgets
+            .iter()
+            .filter_map(|widget| widget.get(b"AS").and_then(Object::as_name).ok())
+            .find(|state| *state != b"Off")
+    })?;
+    if state == b"Off" {
+        return None;
+    }
+
+    let export = dict
+        .get(b"Opt")
+        .ok()
+        .and_then(|o| resolve(doc, o))
+        .and_then(|o| o.as_array().ok())
+        .zip(std::str::from_utf8(state).ok().and_then(|s| s.parse::<usize>().ok()))
+        .and_then(|(options, index)| options.get(index))
+        .and_then(|option| resolve(doc, option))
+        .and_then(text_value);
+    Some(export.unwrap_or_else(|| String::from_utf8_lossy(state).into_owned()))
+}
+
+/// Filled-in fields as "Name: value" lines, to append to a document's
+/// content so they are searchable; empty when no field has a value
+pub fn form_fields_text(form: &PdfForm) -> String {
+    let lines: Vec<String> = form
+        .fields
+        .iter()
+        .filter_map(|field| {
+            let value = field.value.as_deref()?;
+            // Keep each field on one line
+            let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+            Some(format!("{}: {}\n", field.name, value))
+        })
+        .collect();
+    if lines.is_empty() {
+        return String::new();
+    }
+    format!("\nForm fields\n{}", lines.concat())
+}
+
+/// Decode a PDF text string: UTF-16BE or UTF-8 with a byte order mark,
+/// otherwise PDFDocEncoding, read as Latin-1
+fn decode_text_string(bytes: &[u8]) -> String {
+    if let Some(utf16) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        let units: Vec<u16> = utf16
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        return String::from_utf16_lossy(&units);
+    }
+    if let Some(utf8) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        return String::from_utf8_lossy(utf8).into_owned();
+    }
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+/// Deepest EmbeddedFiles name tree followed; guards against reference cycles
+const MAX_NAME_TREE_DEPTH: usize = 32;
+
+/// A file embedded in a PDF, written out to a temporary path
+pub struct EmbeddedFile {
+    /// Name from the file specification, without any directories
+    pub name: String,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// The temporary file goes away with the value
+impl Drop for EmbeddedFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
 
-    Ok(text)
+pub struct EmbeddedFiles {
+    pub written: Vec<EmbeddedFile>,
+    /// Names of attachments left out because they would pass the size cap
+    pub over_limit: Vec<String>,
+    /// Attachments in the PDF, including those left out or unreadable
+    pub found: usize,
 }
 
-/// Generate a preview from text (first N characters)
-pub fn generate_preview(text: &str, max_chars: usize) -> String {
-    let trimmed = text.trim();
-    if trimmed.len() <= max_chars {
-        trimmed.to_string()
-    } else {
-        let preview = &trimmed[..max_chars];
-        // Try to break at word boundary
-        if let Some(last_space) = preview.rfind(' ') {
-            format!("{}...", &preview[..last_space])
+/// Write the files in a PDF's EmbeddedFiles name tree into `dir`
+///
+/// Each goes to a new hidden name in `dir`, in name tree order, until they
+/// would add up to more than `max_total_bytes`; later ones that still fit
+/// are written. Attachments whose data can't be decoded are skipped.
+pub fn extract_embedded_files(path: &Path, dir: &Path, max_total_bytes: u64) -> Result<EmbeddedFiles, String> {
+    let (doc, _) = load_document(path)?;
+    let tree = doc
+        .trailer
+        .get(b"Root")
+        .ok()
+        .and_then(|root| resolve_dict(&doc, root))
+        .and_then(|catalog| catalog.get(b"Names").ok())
+        .and_then(|names| resolve_dict(&doc, names))
+        .and_then(|names| names.get(b"EmbeddedFiles").ok());
+    let mut specs = Vec::new();
+    if let Some(tree) = tree {
+        collect_file_specs(&doc, tree, 0, &mut specs);
+    }
+
+    let mut files = EmbeddedFiles {
+        written: Vec::new(),
+        over_limit: Vec::new(),
+        found: specs.len(),
+    };
+    let mut total: u64 = 0;
+    for spec in specs {
+        let name = attachment_name(spec);
+        let Some(stream) = embedded_stream(&doc, spec) else {
+            continue;
+        };
+        // Compressed data is no larger than what it decodes to, so this
+        // avoids decoding attachments that can't fit anyway
+        if total + stream.content.len() as u64 > max_total_bytes {
+            files.over_limit.push(name);
+            continue;
+        }
+        let data = if stream.dict.has(b"Filter") {
+            match stream.decompressed_content() {
+                Ok(data) => data,
+                Err(_) => continue,
+            }
         } else {
-            format!("{}...", preview)
+            stream.content.clone()
+        };
+        let size = data.len() as u64;
+        if total + size > max_total_bytes {
+            files.over_limit.push(name);
+            continue;
+        }
+
+        let dest = dir.join(format!(".attachment_{}", uuid::Uuid::new_v4()));
+        if let Err(e) = std::fs::write(&dest, &data) {
+            let _ = std::fs::remove_file(&dest);
+            return Err(format!("Failed to write attachment {}: {}", name, e));
         }
+        total += size;
+        files.written.push(EmbeddedFile { name, path: dest, size });
     }
+    Ok(files)
 }
 
-/// Generate a basic summary from text (first paragraph or N chars)
-pub fn generate_basic_summary(text: &str, max_chars: usize) -> String {
-    let trimmed = text.trim();
+/// File specifications in a name tree: the values of its Names arrays,
+/// which alternate keys and values, at any depth of Kids
+fn collect_file_specs<'a>(doc: &'a Document, node: &'a Object, depth: usize, specs: &mut Vec<&'a Dictionary>) {
+    if depth > MAX_NAME_TREE_DEPTH {
+        return;
+    }
+    let Some(dict) = resolve_dict(doc, node) else {
+        return;
+    };
+    let names = dict
+        .get(b"Names")
+        .ok()
+        .and_then(|n| resolve(doc, n))
+        .and_then(|n| n.as_array().ok());
+    for value in names.into_iter().flatten().skip(1).step_by(2) {
+        specs.extend(resolve_dict(doc, value));
+    }
+    let kids = dict
+        .get(b"Kids")
+        .ok()
+        .and_then(|k| resolve(doc, k))
+        .and_then(|k| k.as_array().ok());
+    for kid in kids.into_iter().flatten() {
+        collect_file_specs(doc, kid, depth + 1, specs);
+    }
+}
+
+/// Last path component of a file specification's name, without control
+/// characters; "attachment" when nothing usable is left
+fn attachment_name(spec: &Dictionary) -> String {
+    let raw = [b"UF".as_slice(), b"F"]
+        .into_iter()
+        .find_map(|key| spec.get(key).ok().and_then(text_value))
+        .unwrap_or_default();
+    let name: String = raw
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect();
+    match name.trim() {
+        "" | "." | ".." => "attachment".to_string(),
+        name => name.to_string(),
+    }
+}
 
-    // Try to get first paragraph
-    if let Some(first_para_end) = trimmed.find("\n\n") {
-        let first_para = &trimmed[..first_para_end];
-        if first_para.len() <= max_chars {
-            return first_para.to_string();
+/// The embedded file stream of a file specification
+fn embedded_stream<'a>(doc: &'a Document, spec: &'a Dictionary) -> Option<&'a Stream> {
+    let files = resolve_dict(doc, spec.get(b"EF").ok()?)?;
+    let file = files.get(b"UF").or_else(|_| files.get(b"F")).ok()?;
+    resolve(doc, file)?.as_stream().ok()
+}
+
+fn resolve<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Object> {
+    match object {
+        Object::Reference(id) => doc.get_object(*id).ok(),
+        other => Some(other),
+    }
+}
+
+fn resolve_dict<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Dictionary> {
+    resolve(doc, object)?.as_dict().ok()
+}
+
+/// Write a new PDF containing only pages `from_page..=to_page` (1-based)
+pub fn extract_page_range(
+    source: &Path,
+    dest: &Path,
+    from_page: u32,
+    to_page: u32,
+) -> Result<(), String> {
+    let (mut doc, _) = load_document(source)?;
+    let pages = doc.get_pages();
+
+    if from_page == 0 || from_page > to_page || to_page as usize > pages.len() {
+        return Err(format!(
+            "Invalid page range {}-{} for a {}-page document",
+            from_page,
+            to_page,
+            pages.len()
+        ));
+    }
+
+    // Kept pages must carry their own resources once the tree is pruned
+    for (_, page_id) in pages.range(from_page..=to_page) {
+        inline_inherited_attributes(&mut doc, *page_id)
+            .map_err(|e| format!("Failed to copy page resources: {}", e))?;
+    }
+
+    let removed: Vec<u32> = pages
+        .keys()
+        .copied()
+        .filter(|n| *n < from_page || *n > to_page)
+        .collect();
+    doc.delete_pages(&removed);
+    doc.prune_objects();
+    doc.renumber_objects();
+    doc.compress();
+
+    doc.save(dest).map_err(|e| format!("Failed to write PDF: {}", e))?;
+    Ok(())
+}
+
+/// Copy attributes a page inherits from its ancestors onto the page itself
+fn inline_inherited_attributes(doc: &mut Document, page_id: ObjectId) -> Result<(), lopdf::Error> {
+    const INHERITABLE: [&[u8]; 4] = [b"Resources", b"MediaBox", b"CropBox", b"Rotate"];
+
+    let page = doc.get_dictionary(page_id)?;
+    let mut missing: Vec<&[u8]> = INHERITABLE.iter().copied().filter(|k| !page.has(k)).collect();
+    let mut parent = page.get(b"Parent").and_then(Object::as_reference).ok();
+    let mut inherited = Vec::new();
+
+    while let Some(parent_id) = parent {
+        if missing.is_empty() {
+            break;
         }
+        let node = doc.get_dictionary(parent_id)?;
+        missing.retain(|key| match node.get(key) {
+            Ok(value) => {
+                inherited.push((key.to_vec(), value.clone()));
+                false
+            }
+            Err(_) => true,
+        });
+        parent = node.get(b"Parent").and_then(Object::as_reference).ok();
     }
 
-    // Fall back to character limit
-    generate_preview(trimmed, max_chars)
+    let page = doc.get_object_mut(page_id)?.as_dict_mut()?;
+    for (key, value) in inherited {
+        page.set(key, value);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::paged_pdf;
+
+    /// A PDF of `count` pages, each with its number on it
+    fn numbered_pages(count: usize) -> tempfile::NamedTempFile {
+        let texts: Vec<String> = (1..=count).map(|n| format!("This is page number {}", n)).collect();
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), paged_pdf(&texts.iter().map(String::as_str).collect::<Vec<_>>())).unwrap();
+        file
+    }
+
+    fn extract(threads: usize, path: &Path) -> PdfText {
+        extract_on(&ExtractionThreads::new(threads), path, None, PdfLayout::Auto, &|_, _| {}).unwrap()
+    }
+
+    #[test]
+    fn pages_come_back_in_order_whatever_the_thread_count() {
+        let file = numbered_pages(60);
+        let one = extract(1, file.path());
+        for (n, page) in one.pages.iter().enumerate() {
+            assert!(page.contains(&format!("page number {}", n + 1)), "page {}: {:?}", n + 1, page);
+        }
+        let many = extract(8, file.path());
+        assert_eq!(many.pages, one.pages);
+        assert_eq!(many.text, one.text);
+    }
+
+    #[test]
+    fn progress_only_goes_up() {
+        let file = numbered_pages(250);
+        let reports = std::sync::Mutex::new(Vec::new());
+        let threads = ExtractionThreads::new(8);
+        extract_on(&threads, file.path(), None, PdfLayout::Auto, &|done, total| {
+            reports.lock().unwrap().push((done, total))
+        })
+        .unwrap();
+        let reports = reports.into_inner().unwrap();
+        assert!(reports.windows(2).all(|pair| pair[0].0 < pair[1].0), "{:?}", reports);
+        assert_eq!(reports.last(), Some(&(250, 250)));
+    }
+
+    /// Run with `cargo test --release -- --ignored`; it needs several cores
+    /// and the machine to itself
+    #[test]
+    #[ignore = "benchmark"]
+    fn extraction_speeds_up_with_the_cores() {
+        let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get()).min(8);
+        if cores < 2 {
+            eprintln!("Skipping: one core");
+            return;
+        }
+        let file = numbered_pages(2_000);
+        let time = |threads| {
+            let started = Instant::now();
+            extract(threads, file.path());
+            started.elapsed()
+        };
+        time(cores); // warm up
+        let serial = time(1);
+        let parallel = time(cores);
+        let speedup = serial.as_secs_f64() / parallel.as_secs_f64();
+        eprintln!("{:?} on 1 thread, {:?} on {}: {:.1}x", serial, parallel, cores, speedup);
+        // Near-linear: at least two thirds of the ideal
+        assert!(speedup >= cores as f64 * 2.0 / 3.0, "{:.1}x on {} cores", speedup, cores);
+    }
 }
@@ -0,0 +1,115 @@
+//! Repair of PDFs lopdf refuses to load as they are
+//!
+//! Many PDFs that other readers open only have a damaged tail: no %%EOF
+//! after a truncated download, or a cross-reference table whose offsets are
+//! wrong after a careless edit. Such a file is repaired in memory by
+//! scanning it for `N G obj` headers and appending a cross-reference table
+//! and trailer rebuilt from the objects found. The file on disk is never
+//! changed.
+//!
+//! Objects that only exist inside object streams are found through the
+//! stream, not the scan, so a file whose stream objects are damaged stays
+//! unreadable. Encrypted files aren't repaired.
+
+use lopdf::Document;
+use regex::bytes::Regex;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::OnceLock;
+
+/// The file with a rebuilt cross-reference table, if that loads and has
+/// pages
+pub fn repair(data: &[u8]) -> Option<Document> {
+    let rebuilt = rebuild_xref(data)?;
+    Document::load_mem(&rebuilt)
+        .ok()
+        .filter(|doc| !doc.get_pages().is_empty())
+}
+
+fn object_header_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?:^|[\s>\]])(\d{1,10})[ \t\r\n]+(\d{1,5})[ \t\r\n]+obj\b").unwrap())
+}
+
+fn reference_regex(key: &str) -> Regex {
+    Regex::new(&format!(r"/{}[ \t\r\n]*(\d{{1,10}})[ \t\r\n]+(\d{{1,5}})[ \t\r\n]+R\b", key)).unwrap()
+}
+
+fn catalog_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"/Type[ \t\r\n]*/Catalog\b").unwrap())
+}
+
+/// Byte offset, generation and end of each complete object by object
+/// number; for an object defined more than once, e.g. by an incremental
+/// update, the last definition
+fn scan_objects(data: &[u8]) -> BTreeMap<u32, (usize, u16, usize)> {
+    let headers: Vec<(usize, usize, u32, u16)> = object_header_regex()
+        .captures_iter(data)
+        .filter_map(|caps| {
+            let number = caps.get(1)?;
+            let id = std::str::from_utf8(number.as_bytes()).ok()?.parse().ok()?;
+            let generation = std::str::from_utf8(caps.get(2)?.as_bytes()).ok()?.parse().ok()?;
+            Some((number.start(), caps.get(0)?.end(), id, generation))
+        })
+        .collect();
+
+    let mut objects = BTreeMap::new();
+    for (index, &(start, body, id, generation)) in headers.iter().enumerate() {
+        let next = headers.get(index + 1).map_or(data.len(), |header| header.0);
+        // An object cut off by truncation would fail the whole load
+        let Some(end) = find(&data[body..next], b"endobj").map(|at| body + at) else {
+            continue;
+        };
+        if id > 0 {
+            objects.insert(id, (start, generation, end));
+        }
+    }
+    objects
+}
+
+/// The file followed by a cross-reference table of the objects found and a
+/// trailer pointing at its catalog; None without objects or a catalog
+fn rebuild_xref(data: &[u8]) -> Option<Vec<u8>> {
+    if reference_regex("Encrypt").is_match(data) {
+        return None;
+    }
+    let objects = scan_objects(data);
+    let last_reference = |key: &str| {
+        let caps = reference_regex(key).captures_iter(data).last()?;
+        let id: u32 = std::str::from_utf8(caps.get(1)?.as_bytes()).ok()?.parse().ok()?;
+        objects.get(&id).map(|&(_, generation, _)| (id, generation))
+    };
+    // The trailer's catalog, or else the last object that is one
+    let root = last_reference("Root").or_else(|| {
+        objects
+            .iter()
+            .rev()
+            .find(|(_, &(start, _, end))| catalog_regex().is_match(&data[start..end]))
+            .map(|(&id, &(_, generation, _))| (id, generation))
+    })?;
+    let info = last_reference("Info");
+    let size = objects.keys().next_back()? + 1;
+
+    let mut rebuilt = data.to_vec();
+    rebuilt.push(b'\n');
+    let xref_offset = rebuilt.len();
+    // Entries are exactly 20 bytes each, as the format requires
+    writeln!(rebuilt, "xref\n0 {}\n0000000000 65535 f ", size).ok()?;
+    for id in 1..size {
+        match objects.get(&id) {
+            Some(&(offset, generation, _)) => writeln!(rebuilt, "{:010} {:05} n ", offset, generation).ok()?,
+            None => rebuilt.extend_from_slice(b"0000000000 00000 f \n"),
+        }
+    }
+    write!(rebuilt, "trailer\n<< /Size {} /Root {} {} R", size, root.0, root.1).ok()?;
+    if let Some((id, generation)) = info {
+        write!(rebuilt, " /Info {} {} R", id, generation).ok()?;
+    }
+    writeln!(rebuilt, " >>\nstartxref\n{}\n%%EOF", xref_offset).ok()?;
+    Some(rebuilt)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
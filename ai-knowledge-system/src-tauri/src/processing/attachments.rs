@@ -0,0 +1,149 @@
+//! Files embedded in PDFs, stored as documents of their own
+//!
+//! Each attachment becomes a child of the PDF, is stored like an upload
+//! under the usual `{document id}_{name}` file name and goes through the
+//! pipeline by its own MIME type.
+
+use super::ProcessingContext;
+use crate::collation;
+use crate::error::{AppError, AppResult};
+use crate::file_store::LocalCopy;
+use crate::file_utils;
+use crate::models::{CreateDocumentDto, Document, DocumentStatus, ImportMethod, PdfLayout, ProvenanceInfo};
+use crate::pdf_processor::{self, EmbeddedFile, EmbeddedFiles};
+use crate::settings::AppSettings;
+use crate::storage;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Most bytes of attachments taken from one PDF
+pub const MAX_ATTACHMENT_BYTES: u64 = 100 * 1024 * 1024;
+
+/// Write a PDF's attachments to temporary files in the documents directory
+pub async fn extract(ctx: &ProcessingContext, settings: &AppSettings, pdf_path: PathBuf) -> AppResult<EmbeddedFiles> {
+    let store = ctx.keyring.store(settings)?;
+    let documents_dir = storage::online_documents_dir(&*ctx.host, settings)?;
+    tokio::task::spawn_blocking(move || {
+        let local = LocalCopy::new(&*store, &pdf_path).map_err(|e| format!("Failed to read stored file: {}", e))?;
+        pdf_processor::extract_embedded_files(local.path(), &documents_dir, MAX_ATTACHMENT_BYTES)
+    })
+    .await?
+    .map_err(AppError::Other)
+}
+
+/// Warning for a run whose PDF had attachments over the size cap
+pub fn over_limit_warning(files: &EmbeddedFiles) -> Option<String> {
+    if files.over_limit.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "{} attachment(s) left out to stay under {} MB: {}",
+        files.over_limit.len(),
+        MAX_ATTACHMENT_BYTES / (1024 * 1024),
+        files.over_limit.join(", ")
+    ))
+}
+
+/// Create a child document of `parent_id` for each attachment and start
+/// processing it
+///
+/// Attachments with the same content as a child the PDF already has, e.g.
+/// from an earlier run, aren't added again. An attachment that can't be
+/// stored is logged and skipped.
+pub async fn register(ctx: &ProcessingContext, parent_id: Uuid, files: &[EmbeddedFile]) {
+    if files.is_empty() {
+        return;
+    }
+    let (parent, existing) = {
+        let service = ctx.document_service.lock().await;
+        (service.get_document(parent_id).await, service.child_file_hashes(parent_id).await)
+    };
+    let Ok(Some(parent)) = parent else {
+        return;
+    };
+    let mut existing: HashSet<String> = existing.unwrap_or_default().into_iter().collect();
+
+    for file in files {
+        if let Err(e) = register_one(ctx, &parent, file, &mut existing).await {
+            eprintln!("Failed to store attachment {} of {}: {}", file.name, parent_id, e);
+        }
+    }
+}
+
+async fn register_one(
+    ctx: &ProcessingContext,
+    parent: &Document,
+    file: &EmbeddedFile,
+    existing: &mut HashSet<String>,
+) -> AppResult<()> {
+    let (mime_type, file_hash) = {
+        let source = file.path.clone();
+        tokio::task::spawn_blocking(move || {
+            Ok::<_, std::io::Error>((file_utils::detect_mime_type(&source)?, file_utils::calculate_sha256(&source)?))
+        })
+        .await??
+    };
+    if !existing.insert(file_hash.clone()) {
+        return Ok(());
+    }
+
+    let settings = ctx.settings.get().await;
+    let dto = CreateDocumentDto {
+        user_id: parent.user_id,
+        title: file.name.clone(),
+        file_name: file.name.clone(),
+        file_size_bytes: file.size as i64,
+        file_type: file_utils::get_file_extension(Path::new(&file.name)),
+        mime_type: Some(mime_type.clone()),
+        parent_document_id: Some(parent.id),
+        workspace_id: None,
+        file_hash: Some(file_hash),
+        title_sort: collation::title_sort_key(&file.name, &settings.locale),
+        status: DocumentStatus::Uploading,
+        external_file: false,
+        provenance: ProvenanceInfo::now(ImportMethod::Attachment, None, None, settings.record_import_paths),
+    };
+    let document = {
+        let service = ctx.document_service.lock().await;
+        service.create_document(dto).await?
+    };
+
+    let stored = async {
+        let store = ctx.keyring.store(&settings)?;
+        let documents_dir = storage::online_documents_dir(&*ctx.host, &settings)?;
+        let (source, doc_id, name) = (file.path.clone(), document.id, file.name.clone());
+        let dest = tokio::task::spawn_blocking(move || {
+            storage::store_file(&*store, &source, &documents_dir, doc_id, &name)
+        })
+        .await??;
+        let service = ctx.document_service.lock().await;
+        if let Err(e) = service.update_file_path(doc_id, dest.to_string_lossy().to_string()).await {
+            let _ = std::fs::remove_file(&dest);
+            return Err(e.into());
+        }
+        Ok::<_, AppError>(dest)
+    }
+    .await;
+
+    match stored {
+        Ok(dest) => {
+            super::spawn_processing(
+                ctx.clone(),
+                document.id,
+                dest,
+                mime_type,
+                DocumentStatus::Uploading,
+                PdfLayout::Auto,
+            );
+            Ok(())
+        }
+        Err(e) => {
+            let service = ctx.document_service.lock().await;
+            if let Err(discard) = service.discard_upload(document.id).await {
+                eprintln!("Failed to remove attachment document {}: {}", document.id, discard);
+            }
+            Err(e)
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use super::extractor::{ExtractionLimits, ExtractionResult, Extractor};
+use crate::file_utils;
+use crate::models::PdfLayout;
+use crate::pdf_processor;
+use std::path::Path;
+use std::sync::Arc;
+
+pub fn builtin_extractors() -> Vec<Arc<dyn Extractor>> {
+    vec![
+        Arc::new(PdfExtractor),
+        Arc::new(PlainTextExtractor),
+        Arc::new(MarkdownExtractor),
+    ]
+}
+
+pub struct PdfExtractor;
+
+impl Extractor for PdfExtractor {
+    fn name(&self) -> &str {
+        "pdf"
+    }
+
+    fn version(&self) -> Option<&str> {
+        Some(env!("CARGO_PKG_VERSION"))
+    }
+
+    fn supports(&self, mime: &str, extension: &str) -> bool {
+        mime == "application/pdf" || extension == "pdf"
+    }
+
+    fn extract(&self, path: &Path) -> Result<ExtractionResult, String> {
+        pdf_result(pdf_processor::extract_text_from_pdf(path, None, PdfLayout::Auto, &|_, _| {})?)
+    }
+
+    fn extract_with_limits(
+        &self,
+        path: &Path,
+        limits: &ExtractionLimits,
+    ) -> Result<ExtractionResult, String> {
+        let progress = |done, total| {
+            if let Some(progress) = &limits.progress {
+                progress.report(done, total);
+            }
+        };
+        pdf_result(pdf_processor::extract_text_from_pdf(
+            path,
+            Some(limits.page_timeout),
+            limits.pdf_layout,
+            &progress,
+        )?)
+    }
+}
+
+fn pdf_result(extracted: pdf_processor::PdfText) -> Result<ExtractionResult, String> {
+    let mut metadata = serde_json::Map::new();
+    metadata.insert("page_count".to_string(), extracted.pages.len().into());
+    metadata.insert(
+        "multi_column_pages".to_string(),
+        extracted.multi_column_pages.len().into(),
+    );
+    let form = serde_json::to_value(&extracted.form).map_err(|e| format!("Failed to record form fields: {}", e))?;
+    metadata.insert("form_fields".to_string(), form);
+    metadata.insert("repaired".to_string(), extracted.repaired.into());
+
+    Ok(ExtractionResult {
+        text: extracted.text,
+        pages: extracted.pages,
+        metadata,
+        skipped_pages: extracted.skipped_pages,
+    })
+}
+
+pub struct PlainTextExtractor;
+
+impl Extractor for PlainTextExtractor {
+    fn name(&self) -> &str {
+        "text"
+    }
+
+    fn version(&self) -> Option<&str> {
+        Some(env!("CARGO_PKG_VERSION"))
+    }
+
+    fn supports(&self, mime: &str, extension: &str) -> bool {
+        matches!(mime, "text/plain" | "text/csv" | "application/json" | "application/xml")
+            || matches!(extension, "txt" | "text" | "log")
+    }
+
+    fn extract(&self, path: &Path) -> Result<ExtractionResult, String> {
+        read_text(path)
+    }
+}
+
+pub struct MarkdownExtractor;
+
+impl Extractor for MarkdownExtractor {
+    fn name(&self) -> &str {
+        "markdown"
+    }
+
+    fn version(&self) -> Option<&str> {
+        Some(env!("CARGO_PKG_VERSION"))
+    }
+
+    fn supports(&self, mime: &str, extension: &str) -> bool {
+        mime == "text/markdown" || matches!(extension, "md" | "markdown")
+    }
+
+    fn extract(&self, path: &Path) -> Result<ExtractionResult, String> {
+        read_text(path)
+    }
+}
+
+/// Read a file as text, decoding BOM-marked UTF-16 and replacing invalid UTF-8
+fn read_text(path: &Path) -> Result<ExtractionResult, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read file: {}", e))?;
+    let text = match bytes.as_slice() {
+        [0xFF, 0xFE, ..] | [0xFE, 0xFF, ..] => file_utils::decode_text_sample(&bytes)
+            .ok_or_else(|| "Invalid UTF-16 text".to_string())?,
+        _ => String::from_utf8_lossy(&bytes).into_owned(),
+    };
+
+    Ok(ExtractionResult {
+        text: text.trim_start_matches('\u{feff}').to_string(),
+        ..Default::default()
+    })
+}
@@ -0,0 +1,284 @@
+use crate::models::{ExtractorInfo, PdfLayout};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex, OnceLock, RwLock};
+use std::time::Duration;
+
+/// Output of a text extractor
+#[derive(Debug, Clone, Default)]
+pub struct ExtractionResult {
+    pub text: String,
+    /// Per-page text for paged formats; empty otherwise. When present, `text`
+    /// must be the pages joined with a newline after each.
+    pub pages: Vec<String>,
+    /// Stored with the document; the PDF extractor's "form_fields" is also
+    /// appended to the content
+    pub metadata: serde_json::Map<String, serde_json::Value>,
+    /// 1-based pages left empty because they hit the per-page timeout
+    pub skipped_pages: Vec<u32>,
+}
+
+/// Limits and options applied while extracting
+#[derive(Debug, Clone)]
+pub struct ExtractionLimits {
+    /// How long a single page may take before it is skipped
+    pub page_timeout: Duration,
+    /// Reading order for PDF text; other formats ignore it
+    pub pdf_layout: PdfLayout,
+    /// OCR the file even when it has a text layer; only honoured by
+    /// extractors that report `supports_ocr`
+    pub force_ocr: bool,
+    /// Language of the document, e.g. "de", for OCR and language-aware extractors
+    pub language_hint: Option<String>,
+    /// Told how many pages are done; paged extractors should call it as
+    /// pages finish
+    pub progress: Option<PageProgress>,
+}
+
+/// Callback taking (pages done, page count)
+#[derive(Clone)]
+pub struct PageProgress(Arc<dyn Fn(usize, usize) + Send + Sync>);
+
+impl PageProgress {
+    pub fn new(report: impl Fn(usize, usize) + Send + Sync + 'static) -> Self {
+        PageProgress(Arc::new(report))
+    }
+
+    pub fn report(&self, done: usize, total: usize) {
+        (self.0)(done, total)
+    }
+}
+
+impl fmt::Debug for PageProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PageProgress")
+    }
+}
+
+/// Threads all extractions share, one per core, so a huge document can't
+/// take every core from the others
+///
+/// Extractors working on several pages at once take a thread per page
+/// through a `ThreadShare`. Each document being extracted gets an even
+/// share of the threads, and always at least one.
+pub struct ExtractionThreads {
+    state: Mutex<ThreadState>,
+    freed: Condvar,
+}
+
+struct ThreadState {
+    total: usize,
+    in_use: usize,
+    documents: usize,
+}
+
+impl ExtractionThreads {
+    pub fn new(total: usize) -> Self {
+        ExtractionThreads {
+            state: Mutex::new(ThreadState {
+                total: total.max(1),
+                in_use: 0,
+                documents: 0,
+            }),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// The threads shared by every extraction in the process
+    pub fn global() -> &'static ExtractionThreads {
+        static THREADS: OnceLock<ExtractionThreads> = OnceLock::new();
+        THREADS.get_or_init(|| {
+            ExtractionThreads::new(std::thread::available_parallelism().map_or(1, |cores| cores.get()))
+        })
+    }
+
+    /// Start taking threads for one document
+    pub fn join(&self) -> ThreadShare<'_> {
+        self.state.lock().unwrap().documents += 1;
+        ThreadShare { threads: self, held: 0 }
+    }
+}
+
+/// One document's threads; all are given back when it is dropped
+pub struct ThreadShare<'a> {
+    threads: &'a ExtractionThreads,
+    held: usize,
+}
+
+impl ThreadShare<'_> {
+    /// Take a thread, waiting for one only when the document holds none
+    ///
+    /// Returns false when the document has its share, or holds threads and
+    /// none are free; it should then wait for one of its own to finish.
+    pub fn acquire(&mut self) -> bool {
+        let mut state = self.threads.state.lock().unwrap();
+        loop {
+            let share = state.total.div_ceil(state.documents.max(1));
+            if self.held >= share {
+                return false;
+            }
+            if state.in_use < state.total {
+                state.in_use += 1;
+                self.held += 1;
+                return true;
+            }
+            if self.held > 0 {
+                return false;
+            }
+            state = self.threads.freed.wait(state).unwrap();
+        }
+    }
+
+    /// Give back a thread taken with `acquire`
+    pub fn release(&mut self) {
+        if self.held == 0 {
+            return;
+        }
+        self.held -= 1;
+        self.threads.state.lock().unwrap().in_use -= 1;
+        self.threads.freed.notify_one();
+    }
+}
+
+impl Drop for ThreadShare<'_> {
+    fn drop(&mut self) {
+        let mut state = self.threads.state.lock().unwrap();
+        state.in_use -= self.held;
+        state.documents -= 1;
+        drop(state);
+        self.threads.freed.notify_all();
+    }
+}
+
+/// Turns a stored file into text
+///
+/// Implement this to add support for a new format and register it with
+/// `ProcessingRegistry::register_extractor`.
+pub trait Extractor: Send + Sync {
+    /// Short identifier used in logs and processing reports
+    fn name(&self) -> &str;
+
+    /// Version recorded in the processing history, if the extractor has one
+    fn version(&self) -> Option<&str> {
+        None
+    }
+
+    /// Whether this extractor handles the given MIME type / lowercase extension
+    fn supports(&self, mime: &str, extension: &str) -> bool;
+
+    /// Whether this extractor can OCR files when asked to with `force_ocr`
+    fn supports_ocr(&self) -> bool {
+        false
+    }
+
+    /// Extract text from the file. Called on a blocking thread.
+    fn extract(&self, path: &Path) -> Result<ExtractionResult, String>;
+
+    /// Extract honouring `limits`. Paged formats should override this to
+    /// skip pages that exceed the per-page timeout; the default ignores limits.
+    fn extract_with_limits(
+        &self,
+        path: &Path,
+        _limits: &ExtractionLimits,
+    ) -> Result<ExtractionResult, String> {
+        self.extract(path)
+    }
+}
+
+struct Registration {
+    priority: i32,
+    extractor: Arc<dyn Extractor>,
+}
+
+/// The set of extractors the processing pipeline dispatches to
+///
+/// When several extractors claim the same file, the highest priority wins;
+/// on equal priority the most recently registered one wins, so embedders can
+/// override built-ins by registering at the default priority.
+pub struct ProcessingRegistry {
+    extractors: RwLock<Vec<Registration>>,
+    warned_conflicts: Mutex<HashSet<String>>,
+}
+
+/// Priority used by the built-in extractors
+pub const BUILTIN_PRIORITY: i32 = 0;
+
+impl ProcessingRegistry {
+    /// An empty registry with no extractors
+    pub fn new() -> Self {
+        ProcessingRegistry {
+            extractors: RwLock::new(Vec::new()),
+            warned_conflicts: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// A registry preloaded with the PDF, text and Markdown extractors
+    pub fn with_builtin() -> Self {
+        let registry = Self::new();
+        for extractor in super::builtin::builtin_extractors() {
+            registry.register_extractor(BUILTIN_PRIORITY, extractor);
+        }
+        registry
+    }
+
+    pub fn register_extractor(&self, priority: i32, extractor: Arc<dyn Extractor>) {
+        let mut extractors = self.extractors.write().unwrap();
+        extractors.push(Registration {
+            priority,
+            extractor,
+        });
+    }
+
+    /// The registered extractors, highest priority first
+    pub fn extractors(&self) -> Vec<ExtractorInfo> {
+        let extractors = self.extractors.read().unwrap();
+        let mut ordered: Vec<(usize, &Registration)> = extractors.iter().enumerate().collect();
+        ordered.sort_by_key(|(index, r)| std::cmp::Reverse((r.priority, *index)));
+        ordered
+            .into_iter()
+            .map(|(_, r)| ExtractorInfo {
+                name: r.extractor.name().to_string(),
+                version: r.extractor.version().map(str::to_string),
+                priority: r.priority,
+                supports_ocr: r.extractor.supports_ocr(),
+            })
+            .collect()
+    }
+
+    /// Pick the extractor for a file, logging once per MIME type on conflicts
+    pub fn find(&self, mime: &str, extension: &str) -> Option<Arc<dyn Extractor>> {
+        let extractors = self.extractors.read().unwrap();
+        let candidates: Vec<(usize, &Registration)> = extractors
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r.extractor.supports(mime, extension))
+            .collect();
+
+        let (_, chosen) = candidates
+            .iter()
+            .max_by_key(|(index, r)| (r.priority, *index))?;
+
+        if candidates.len() > 1 {
+            let key = format!("{}|{}", mime, extension);
+            if self.warned_conflicts.lock().unwrap().insert(key) {
+                let names: Vec<&str> = candidates.iter().map(|(_, r)| r.extractor.name()).collect();
+                eprintln!(
+                    "Multiple extractors support {} (.{}): {}; using {}",
+                    mime,
+                    extension,
+                    names.join(", "),
+                    chosen.extractor.name()
+                );
+            }
+        }
+
+        Some(Arc::clone(&chosen.extractor))
+    }
+}
+
+impl Default for ProcessingRegistry {
+    fn default() -> Self {
+        Self::with_builtin()
+    }
+}
@@ -0,0 +1,713 @@
+mod attachments;
+mod builtin;
+pub mod extractor;
+
+pub use extractor::{ExtractionLimits, ExtractionResult, ExtractionThreads, Extractor, PageProgress, ProcessingRegistry};
+
+use crate::chunking::{self, PageChunks, TextChunk};
+use crate::db;
+use crate::text_cleanup;
+use crate::error::{AppError, AppResult};
+use crate::file_store::{FileStore, Keyring, LocalCopy};
+use crate::file_utils;
+use crate::host::Host;
+use crate::identifiers;
+use crate::invoice_fields;
+use crate::keywords;
+use crate::models::{DocumentStatus, PdfForm, PdfLayout, ProcessingProgressEvent};
+use crate::pdf_processor;
+use crate::services::processing_run::{RunFinish, RunOutcome};
+use crate::services::notification::NewNotification;
+use crate::notification_sink::NotificationSink;
+use crate::services::{DocumentService, ImportSessionService, ProcessingRunService, TagService};
+use crate::settings::SettingsStore;
+use crate::storage;
+use crate::summarizer::{self, FileKind};
+use std::any::Any;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::task::JoinError;
+use uuid::Uuid;
+
+/// Services the background pipeline needs
+#[derive(Clone)]
+pub struct ProcessingContext {
+    pub document_service: Arc<Mutex<DocumentService>>,
+    pub tag_service: Arc<Mutex<TagService>>,
+    pub registry: Arc<ProcessingRegistry>,
+    pub settings: Arc<SettingsStore>,
+    pub run_service: Arc<Mutex<ProcessingRunService>>,
+    pub keyring: Arc<Keyring>,
+    pub notifications: Arc<NotificationSink>,
+    pub import_session_service: Arc<Mutex<ImportSessionService>>,
+    /// Processing jobs that panicked since startup
+    pub panics: Arc<AtomicU64>,
+    pub host: Arc<dyn Host>,
+}
+
+impl ProcessingContext {
+    /// Store for reading and writing files under the current settings
+    pub async fn file_store(&self) -> AppResult<Arc<dyn FileStore>> {
+        self.keyring.store(&self.settings.get().await)
+    }
+}
+
+/// Extract text and a summary for a stored file in the background
+///
+/// `from` is the status the document is expected to be in. Unless it is
+/// already `Processing` (claimed by the caller), the task first claims the
+/// document with a `from -> Processing` transition and gives up if another
+/// task got there first. The extractor is chosen from the registry by MIME
+/// type and extension; files no extractor supports complete without content
+/// and are flagged to be processed once one does.
+/// Extraction that outlives `extraction_timeout_secs` fails the document;
+/// the blocking thread is detached since it can't be interrupted. A panic,
+/// e.g. in a parser, fails the document too, with the panic message as the
+/// error. `pdf_layout` is the reading order PDFs are extracted in.
+pub fn spawn_processing(
+    ctx: ProcessingContext,
+    doc_id: Uuid,
+    path: PathBuf,
+    mime_type: String,
+    from: DocumentStatus,
+    pdf_layout: PdfLayout,
+) {
+    tokio::spawn(async move {
+        run_processing(ctx, doc_id, path, mime_type, from, pdf_layout).await;
+    });
+}
+
+async fn run_processing(
+    ctx: ProcessingContext,
+    doc_id: Uuid,
+    path: PathBuf,
+    mime_type: String,
+    from: DocumentStatus,
+    pdf_layout: PdfLayout,
+) {
+    if from != DocumentStatus::Processing {
+        let window = retry_window(&ctx).await;
+        let service = &ctx.document_service;
+        let claimed = db::retry_transient(window, move || async move {
+            let service = service.lock().await;
+            service.transition_status(doc_id, from, DocumentStatus::Processing, None).await
+        })
+        .await;
+        if let Err(e) = claimed {
+            eprintln!("Skipping processing of {}: {}", doc_id, e);
+            return;
+        }
+    }
+
+    let run_id = {
+        let runs = ctx.run_service.lock().await;
+        runs.start_run(doc_id)
+            .await
+            .map_err(|e| eprintln!("Failed to record processing run for {}: {}", doc_id, e))
+            .ok()
+    };
+    emit_progress(&ctx, doc_id, run_id, "started", None);
+
+    // The work runs as a task of its own so a panic ends only that task and
+    // the run can still be recorded
+    let job = tokio::spawn({
+        let ctx = ctx.clone();
+        async move { process_claimed(&ctx, doc_id, run_id, path, mime_type, pdf_layout).await }
+    });
+    let finish = match job.await {
+        Ok(finish) => finish,
+        Err(e) => {
+            let finish = RunFinish {
+                outcome: RunOutcome::Failed,
+                error: Some(join_error(&ctx, doc_id, e)),
+                extractor_name: None,
+                extractor_version: None,
+                pages_processed: None,
+                attachments_found: None,
+                sanitized_bytes: None,
+                pages_reused: None,
+                repaired: false,
+            };
+            fail(&ctx, doc_id, finish).await
+        }
+    };
+
+    emit_progress(&ctx, doc_id, run_id, "finished", Some(finish.outcome));
+    // Documents of a running import are reported with the whole import
+    let session = ctx.import_session_service.lock().await.open_session_of(doc_id).await;
+    match session {
+        Ok(Some(session_id)) => {
+            crate::finish_import_session(&*ctx.host, &ctx.import_session_service, &ctx.notifications, session_id)
+                .await
+        }
+        Ok(None) => notify_outcome(&ctx, doc_id, &finish).await,
+        Err(e) => {
+            eprintln!("Failed to look up import session of {}: {}", doc_id, e);
+            notify_outcome(&ctx, doc_id, &finish).await;
+        }
+    }
+    if let Some(run_id) = run_id {
+        let runs = ctx.run_service.lock().await;
+        if let Err(e) = runs.finish_run(run_id, finish).await {
+            eprintln!("Failed to record end of processing run {}: {}", run_id, e);
+        }
+    }
+}
+
+/// Leave the owner a notification about how processing ended; cancelled
+/// runs were the user's own doing and get none
+async fn notify_outcome(ctx: &ProcessingContext, doc_id: Uuid, finish: &RunFinish) {
+    let (kind, title) = match finish.outcome {
+        RunOutcome::Completed => ("processing_completed", "Document ready"),
+        RunOutcome::Failed | RunOutcome::TimedOut => ("processing_failed", "Processing failed"),
+        RunOutcome::Cancelled => return,
+    };
+    let document = {
+        let service = ctx.document_service.lock().await;
+        service.get_document(doc_id).await
+    };
+    let Ok(Some(document)) = document else {
+        return;
+    };
+    let body = match &finish.error {
+        Some(error) => format!("{}: {}", document.title, error),
+        None => document.title.clone(),
+    };
+
+    ctx.notifications
+        .notify(NewNotification {
+            user_id: document.user_id,
+            kind,
+            title,
+            body: Some(&body),
+            document_id: Some(doc_id),
+        })
+        .await;
+}
+
+/// Extract and store a document already moved to Processing
+///
+/// The document's processing_error always ends up as this run's error, so
+/// it stays a copy of the latest entry in the processing history.
+async fn process_claimed(
+    ctx: &ProcessingContext,
+    doc_id: Uuid,
+    run_id: Option<Uuid>,
+    path: PathBuf,
+    mime_type: String,
+    pdf_layout: PdfLayout,
+) -> RunFinish {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+
+    let Some(extractor) = ctx.registry.find(&mime_type, &extension) else {
+        let window = retry_window(ctx).await;
+        let service = &ctx.document_service;
+        let completed = db::retry_transient(window, move || async move {
+            service.lock().await.complete_without_extractor(doc_id).await
+        })
+        .await;
+        let finish = |outcome, error| RunFinish {
+            outcome,
+            error,
+            extractor_name: None,
+            extractor_version: None,
+            pages_processed: None,
+            attachments_found: None,
+            sanitized_bytes: None,
+            pages_reused: None,
+            repaired: false,
+        };
+        return match completed {
+            Ok(()) => finish(RunOutcome::Completed, None),
+            // Cancelled meanwhile; the cancel already set the final status
+            Err(AppError::InvalidTransition { .. }) => finish(RunOutcome::Cancelled, None),
+            Err(e) => {
+                let error = format!("Failed to save document: {}", e);
+                fail(ctx, doc_id, finish(RunOutcome::Failed, Some(error))).await
+            }
+        };
+    };
+
+    let existing_tags = {
+        let tags = ctx.tag_service.lock().await;
+        tags.tag_names_for_document_owner(doc_id).await.unwrap_or_default()
+    };
+
+    let settings = ctx.settings.get().await;
+    // Choices made at upload or reprocess time, else the current defaults
+    let options = {
+        let service = ctx.document_service.lock().await;
+        service.get_processing_options(doc_id).await
+    }
+    .unwrap_or_else(|e| {
+        eprintln!("Failed to load processing options for {}: {}", doc_id, e);
+        None
+    })
+    .unwrap_or_else(|| settings.processing_defaults.clone());
+
+    let timeout = Duration::from_secs(settings.extraction_timeout_secs);
+    let page_timeout = Duration::from_secs(settings.page_timeout_secs);
+    let progress_ctx = ctx.clone();
+    let limits = ExtractionLimits {
+        page_timeout,
+        pdf_layout,
+        force_ocr: options.force_ocr,
+        language_hint: options.language_hint.clone(),
+        progress: Some(PageProgress::new(move |done, total| {
+            emit_page_progress(&progress_ctx, doc_id, run_id, done, total)
+        })),
+    };
+    let ocr_unavailable = options.force_ocr && !extractor.supports_ocr();
+    let clean_text = options.clean_text;
+    let extract_fields = options.extract_fields;
+
+    let extractor_name = extractor.name().to_string();
+    let extractor_version = extractor.version().map(str::to_string);
+    let finish = |outcome, error: Option<String>, pages_processed| RunFinish {
+        outcome,
+        error,
+        extractor_name: Some(extractor_name.clone()),
+        extractor_version: extractor_version.clone(),
+        pages_processed,
+        attachments_found: None,
+        sanitized_bytes: None,
+        pages_reused: None,
+        repaired: false,
+    };
+
+    // A disconnected drive fails with a clear error rather than a raw IO one
+    if let Some(root) = &settings.storage_root {
+        if let Err(e) = storage::ensure_online(root) {
+            return fail(ctx, doc_id, finish(RunOutcome::Failed, Some(e.to_string()), None)).await;
+        }
+    }
+
+    let store = match ctx.keyring.store(&settings) {
+        Ok(store) => store,
+        Err(e) => return fail(ctx, doc_id, finish(RunOutcome::Failed, Some(e.to_string()), None)).await,
+    };
+
+    let pdf_path = (mime_type == "application/pdf").then(|| path.clone());
+    emit_progress(ctx, doc_id, run_id, "extracting", None);
+    let task = tokio::task::spawn_blocking(move || {
+        // Encrypted files are decrypted to a temporary copy for the extractor
+        let local = LocalCopy::new(&*store, &path).map_err(|e| format!("Failed to read stored file: {}", e))?;
+        let mut extracted = extractor.extract_with_limits(local.path(), &limits)?;
+        if clean_text {
+            extracted = clean_extraction(extracted);
+        }
+        let sanitized = sanitize_extraction(&mut extracted);
+        // Filled-in form fields follow the pages so they are searchable
+        if let Some(form) = extracted.metadata.get("form_fields") {
+            let form: PdfForm =
+                serde_json::from_value(form.clone()).map_err(|e| format!("Invalid form fields: {}", e))?;
+            extracted.text.push_str(&pdf_processor::form_fields_text(&form));
+        }
+        if extract_fields {
+            let fields = invoice_fields::extract_fields(&extracted.text);
+            let fields = serde_json::to_value(fields).map_err(|e| format!("Invalid extracted fields: {}", e))?;
+            extracted.metadata.insert("extracted_fields".to_string(), fields);
+        }
+        let suggestions = keywords::suggest_tags(&extracted.text, &existing_tags);
+        let terms = keywords::term_counts(&extracted.text);
+        Ok::<_, String>((extracted, suggestions, terms, sanitized))
+    });
+    let extracted = match tokio::time::timeout(timeout, task).await {
+        Ok(Ok(extracted)) => extracted,
+        Ok(Err(e)) => {
+            let error = join_error(ctx, doc_id, e);
+            return fail(ctx, doc_id, finish(RunOutcome::Failed, Some(error), None)).await;
+        }
+        Err(_) => {
+            eprintln!("Extraction of {} with {} timed out", doc_id, extractor_name);
+            let error = format!("Extraction timed out after {}s", timeout.as_secs());
+            return fail(ctx, doc_id, finish(RunOutcome::TimedOut, Some(error), None)).await;
+        }
+    };
+
+    let (extracted, suggestions, terms, sanitized) = match extracted {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("Extraction with {} failed: {}", extractor_name, e);
+            let error = format!("Extraction failed: {}", e);
+            return fail(ctx, doc_id, finish(RunOutcome::Failed, Some(error), None)).await;
+        }
+    };
+
+    let summary = options.generate_summary.then(|| {
+        summarizer::summarize(FileKind::detect(&mime_type, &extension), &extracted.text, &extracted.pages)
+    });
+    let page_count = (!extracted.pages.is_empty()).then_some(extracted.pages.len() as i32);
+    let pages_processed = page_count.map(|count| count - extracted.skipped_pages.len() as i32);
+    let detected = identifiers::detect_identifiers(&extracted.text);
+    // With chunking off, any chunks from an earlier run are dropped. Pages
+    // whose text is the same as in the last run keep their chunks, and with
+    // them their embeddings.
+    let language = options.language_hint.as_deref();
+    let chunks = if !options.chunk {
+        ChunkPlan::Whole(Vec::new())
+    } else if extracted.pages.is_empty() {
+        ChunkPlan::Whole(chunking::chunk_text(&extracted.text, language))
+    } else {
+        let reusable = {
+            let service = ctx.document_service.lock().await;
+            service.reusable_chunk_pages(doc_id).await
+        }
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to load page hashes of {}: {}", doc_id, e);
+            HashMap::new()
+        });
+        plan_page_chunks(&extracted.text, &extracted.pages, &reusable, language)
+    };
+    let pages = extracted.pages;
+    let metadata = extracted.metadata;
+    let mut warnings = Vec::new();
+    if !extracted.skipped_pages.is_empty() {
+        let skipped: Vec<String> = extracted.skipped_pages.iter().map(|p| p.to_string()).collect();
+        warnings.push(format!(
+            "Skipped page(s) {}: extraction timed out after {}s",
+            skipped.join(", "),
+            page_timeout.as_secs()
+        ));
+    }
+    if metadata
+        .get("form_fields")
+        .and_then(|form| form.get("xfa_unsupported"))
+        .and_then(|xfa| xfa.as_bool())
+        .unwrap_or(false)
+    {
+        warnings.push("XFA forms aren't supported; form fields weren't extracted".to_string());
+    }
+    let repaired = metadata.get("repaired").and_then(|r| r.as_bool()).unwrap_or(false);
+    if repaired {
+        warnings.push("The file is damaged; its text was read from a repaired copy".to_string());
+    }
+    let embedded = match pdf_path {
+        Some(pdf_path) => match attachments::extract(ctx, &settings, pdf_path).await {
+            Ok(files) => Some(files),
+            Err(e) => {
+                warnings.push(format!("Attachments couldn't be extracted: {}", e));
+                None
+            }
+        },
+        None => None,
+    };
+    warnings.extend(embedded.as_ref().and_then(attachments::over_limit_warning));
+    if ocr_unavailable {
+        warnings.push(format!("OCR was requested but {} can't OCR; used the text layer", extractor_name));
+    }
+    let warning = (!warnings.is_empty()).then(|| warnings.join("; "));
+
+    // Update database; each write takes the lock per try so nobody waits
+    // on it while a retry backs off
+    emit_progress(ctx, doc_id, run_id, "saving", None);
+    let window = Duration::from_secs(settings.db_retry_window_secs);
+    let service = &ctx.document_service;
+    let (text, summary, warning_text) = (extracted.text.as_str(), summary.as_ref(), warning.as_deref());
+    let mut pages_reused = None;
+    let mut write_errors = Vec::new();
+    let saved = db::retry_transient(window, move || async move {
+        let service = service.lock().await;
+        service
+            .update_content_and_summary(doc_id, text, summary, page_count, warning_text)
+            .await
+    })
+    .await;
+    match saved {
+        Ok(()) => {
+            let (pages, metadata, detected, terms) = (&pages, &metadata, &detected, &terms);
+            match &chunks {
+                ChunkPlan::Whole(chunks) => {
+                    let stored = db::retry_transient(window, move || async move {
+                        service.lock().await.replace_pages(doc_id, pages).await
+                    });
+                    if let Err(e) = stored.await {
+                        write_errors.push(format!("Failed to store document pages: {}", e));
+                    }
+                    let stored = db::retry_transient(window, move || async move {
+                        service.lock().await.replace_chunks(doc_id, chunks).await
+                    });
+                    if let Err(e) = stored.await {
+                        write_errors.push(format!("Failed to store document chunks: {}", e));
+                    }
+                }
+                ChunkPlan::Paged { pages: plan, trailing } => {
+                    let stored = db::retry_transient(window, move || async move {
+                        service.lock().await.replace_pages_and_chunks(doc_id, pages, plan, trailing).await
+                    });
+                    match stored.await {
+                        Ok(()) => {
+                            let reused = plan.iter().filter(|page| matches!(page, PageChunks::Reused { .. })).count();
+                            pages_reused = Some(reused as i32);
+                        }
+                        Err(e) => write_errors.push(format!("Failed to store document pages and chunks: {}", e)),
+                    }
+                }
+            }
+            let stored = db::retry_transient(window, move || async move {
+                service.lock().await.set_extraction_metadata(doc_id, metadata).await
+            });
+            if let Err(e) = stored.await {
+                write_errors.push(format!("Failed to store extraction metadata: {}", e));
+            }
+            let stored = db::retry_transient(window, move || async move {
+                service.lock().await.replace_identifiers(doc_id, detected).await
+            });
+            if let Err(e) = stored.await {
+                write_errors.push(format!("Failed to store document identifiers: {}", e));
+            }
+            let stored = db::retry_transient(window, move || async move {
+                service.lock().await.replace_terms(doc_id, terms).await
+            });
+            if let Err(e) = stored.await {
+                write_errors.push(format!("Failed to store document terms: {}", e));
+            }
+        }
+        Err(AppError::InvalidTransition { .. }) => {
+            // Cancelled while extracting; the cancel already set the final status
+            eprintln!("Discarding extraction result for {}: no longer processing", doc_id);
+            return finish(RunOutcome::Cancelled, None, pages_processed);
+        }
+        Err(e) => {
+            eprintln!("Failed to update document content: {}", e);
+            let error = format!("Failed to save content: {}", e);
+            return fail(ctx, doc_id, finish(RunOutcome::Failed, Some(error), pages_processed)).await;
+        }
+    }
+
+    // The content is saved, so the document stays completed, but what
+    // couldn't be stored with it is its error until it is reprocessed
+    let mut warning = warning;
+    if !write_errors.is_empty() {
+        let error = warning.into_iter().chain(write_errors).collect::<Vec<_>>().join("; ");
+        eprintln!("Processing of {} completed with errors: {}", doc_id, error);
+        let error_text = error.as_str();
+        let recorded = db::retry_transient(window, move || async move {
+            service.lock().await.record_processing_error(doc_id, error_text).await
+        });
+        if let Err(e) = recorded.await {
+            eprintln!("Failed to record processing error of {}: {}", doc_id, e);
+        }
+        warning = Some(error);
+    }
+
+    // Suggestions are regenerated on every run and never applied automatically
+    let tags = &ctx.tag_service;
+    let suggestions = &suggestions;
+    let stored = db::retry_transient(window, move || async move {
+        tags.lock().await.set_suggested_tags(doc_id, suggestions).await
+    });
+    if let Err(e) = stored.await {
+        eprintln!("Failed to store tag suggestions: {}", e);
+    }
+
+    let mut completed = finish(RunOutcome::Completed, warning, pages_processed);
+    completed.sanitized_bytes = Some(sanitized as i32);
+    completed.pages_reused = pages_reused;
+    completed.repaired = repaired;
+    if let Some(files) = embedded {
+        attachments::register(ctx, doc_id, &files.written).await;
+        completed.attachments_found = Some(files.found as i32);
+    }
+    completed
+}
+
+/// Chunks of an extraction, ready to store
+enum ChunkPlan {
+    /// Chunks of text without pages, or none when chunking is off
+    Whole(Vec<TextChunk>),
+    /// A plan per page, and the chunks of the text after the pages
+    Paged { pages: Vec<PageChunks>, trailing: Vec<TextChunk> },
+}
+
+/// Chunk the pages whose text isn't in `reusable`, the last run's pages by
+/// the hash of their text; the others keep their stored chunks
+fn plan_page_chunks(
+    text: &str,
+    pages: &[String],
+    reusable: &HashMap<String, i32>,
+    language: Option<&str>,
+) -> ChunkPlan {
+    let mut plan = Vec::with_capacity(pages.len());
+    let mut page_start = 0;
+    for (index, page) in pages.iter().enumerate() {
+        plan.push(match reusable.get(&file_utils::sha256_text(page)) {
+            Some(&from_page) => PageChunks::Reused { from_page },
+            None => PageChunks::Chunked(chunking::chunk_page(index as i32 + 1, page_start, page, language)),
+        });
+        page_start += page.chars().count() + 1;
+    }
+    ChunkPlan::Paged {
+        pages: plan,
+        trailing: chunking::chunk_after_pages(text, pages, language),
+    }
+}
+
+/// Clean extracted text page by page, keeping `text` the pages joined with
+/// a newline after each
+fn clean_extraction(mut extracted: ExtractionResult) -> ExtractionResult {
+    if extracted.pages.is_empty() {
+        extracted.text = text_cleanup::clean_text(&extracted.text);
+        return extracted;
+    }
+    extracted.pages = extracted.pages.iter().map(|page| text_cleanup::clean_text(page)).collect();
+    extracted.text = extracted.pages.iter().map(|page| format!("{}\n", page)).collect();
+    extracted
+}
+
+/// Make the text, pages and metadata of an extraction storable, returning
+/// how many bytes of them were changed
+///
+/// Runs before anything is derived from the text, so the summary, chunks and
+/// terms are built from what gets stored. Pages are counted rather than
+/// `text`, which repeats them.
+fn sanitize_extraction(extracted: &mut ExtractionResult) -> usize {
+    let mut altered = 0;
+    if extracted.pages.is_empty() {
+        altered += sanitize_in_place(&mut extracted.text);
+    } else {
+        altered += extracted.pages.iter_mut().map(sanitize_in_place).sum::<usize>();
+        if altered > 0 {
+            extracted.text = extracted.pages.iter().map(|page| format!("{}\n", page)).collect();
+        }
+    }
+    // jsonb can't hold "\u0000" either
+    altered + extracted.metadata.values_mut().map(sanitize_json).sum::<usize>()
+}
+
+fn sanitize_in_place(text: &mut String) -> usize {
+    let (sanitized, altered) = text_cleanup::sanitize_for_storage(text);
+    if let Cow::Owned(sanitized) = sanitized {
+        *text = sanitized;
+    }
+    altered
+}
+
+fn sanitize_json(value: &mut serde_json::Value) -> usize {
+    match value {
+        serde_json::Value::String(text) => sanitize_in_place(text),
+        serde_json::Value::Array(items) => items.iter_mut().map(sanitize_json).sum(),
+        serde_json::Value::Object(map) => map.values_mut().map(sanitize_json).sum(),
+        _ => 0,
+    }
+}
+
+/// Error for a processing task that didn't return, counting it if it panicked
+fn join_error(ctx: &ProcessingContext, doc_id: Uuid, e: JoinError) -> String {
+    match e.try_into_panic() {
+        Ok(payload) => {
+            ctx.panics.fetch_add(1, Ordering::Relaxed);
+            let message = panic_message(payload.as_ref());
+            eprintln!("Processing of {} panicked: {}", doc_id, message);
+            format!("Internal error during extraction: {}", message)
+        }
+        Err(e) => format!("Processing task failed: {}", e),
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => payload
+            .downcast_ref::<String>()
+            .cloned()
+            .unwrap_or_else(|| "unknown panic".to_string()),
+    }
+}
+
+/// Move the document to Failed with the run's error; a cancel that got there
+/// first turns the run into a cancelled one
+async fn fail(ctx: &ProcessingContext, doc_id: Uuid, mut finish: RunFinish) -> RunFinish {
+    let window = retry_window(ctx).await;
+    let (service, error) = (&ctx.document_service, &finish.error);
+    let failed = db::retry_transient(window, move || async move {
+        let service = service.lock().await;
+        service
+            .transition_status(doc_id, DocumentStatus::Processing, DocumentStatus::Failed, error.clone())
+            .await
+    })
+    .await;
+    match failed {
+        Ok(()) => {}
+        Err(AppError::InvalidTransition { .. }) => finish.outcome = RunOutcome::Cancelled,
+        Err(e) => eprintln!("Failed to mark {} as failed: {}", doc_id, e),
+    }
+    finish
+}
+
+/// How long pipeline writes retry transient database errors
+async fn retry_window(ctx: &ProcessingContext) -> Duration {
+    Duration::from_secs(ctx.settings.get().await.db_retry_window_secs)
+}
+
+fn emit_progress(
+    ctx: &ProcessingContext,
+    doc_id: Uuid,
+    run_id: Option<Uuid>,
+    stage: &str,
+    outcome: Option<RunOutcome>,
+) {
+    ctx.host.emit(
+        "processing:progress",
+        ProcessingProgressEvent {
+            document_id: doc_id,
+            run_id,
+            stage: stage.to_string(),
+            outcome: outcome.map(|o| o.as_str().to_string()),
+            pages_done: None,
+            page_count: None,
+        },
+    );
+}
+
+fn emit_page_progress(ctx: &ProcessingContext, doc_id: Uuid, run_id: Option<Uuid>, done: usize, total: usize) {
+    ctx.host.emit(
+        "processing:progress",
+        ProcessingProgressEvent {
+            document_id: doc_id,
+            run_id,
+            stage: "extracting".to_string(),
+            outcome: None,
+            pages_done: Some(done),
+            page_count: Some(total),
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extractions_with_nuls_and_lone_surrogates_become_storable() {
+        // A PDF extraction with NUL padding, a lone surrogate and CRLF
+        let mut extracted = ExtractionResult {
+            pages: vec![
+                "Title\0\0\r\nBody".to_string(),
+                String::from_utf16_lossy(&[0x0050, 0xDC00, 0x0000, 0x0032]),
+            ],
+            metadata: serde_json::json!({ "author": "Ada\0", "keywords": ["x\0y", 3] })
+                .as_object()
+                .unwrap()
+                .clone(),
+            ..Default::default()
+        };
+        extracted.text = extracted.pages.iter().map(|page| format!("{}\n", page)).collect();
+
+        assert_eq!(sanitize_extraction(&mut extracted), 6);
+        assert_eq!(extracted.pages, ["Title\nBody", "P\u{fffd}2"]);
+        assert_eq!(extracted.text, "Title\nBody\nP\u{fffd}2\n");
+        assert_eq!(extracted.metadata["author"], "Ada");
+        assert_eq!(extracted.metadata["keywords"], serde_json::json!(["xy", 3]));
+        assert_eq!(sanitize_extraction(&mut extracted), 0);
+    }
+}
@@ -0,0 +1,321 @@
+//! Search box query language
+//!
+//! `type:pdf tag:tax after:2024-01-01 "capital gains"` parses into a
+//! `DocumentQuery`. Supported fields are type, tag, workspace, status,
+//! source (how the document was imported, e.g. `source:folder-import`),
+//! after, before, and size with `>` or `<`. `tag:finance/*` matches tags
+//! nested under Finance as well as Finance itself. Values may be quoted to
+//! include spaces. Anything that isn't a known field, including words with
+//! a colon such as URLs, is searched as text: a substring of the title or
+//! file name, or words of the content, matched by full-text search.
+//!
+//! Repeating a field widens it (`type:pdf type:md` matches either), except
+//! tag, where every tag must be present. Different fields and text terms
+//! all have to match. Positions in errors are 0-based char offsets.
+
+use crate::models::ImportMethod;
+use chrono::{DateTime, NaiveDate, Utc};
+
+/// A parsed search box query
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DocumentQuery {
+    /// Bare words and quoted phrases, each matched as a substring of the
+    /// title or file name or as words of the content; content is stored
+    /// compressed, so only its search vector can be queried, which stems
+    /// words and ignores stop words
+    pub text_terms: Vec<String>,
+    /// Lowercase file extensions, e.g. "pdf"
+    pub file_types: Vec<String>,
+    /// Lowercase tag names
+    pub tags: Vec<String>,
+    /// Lowercase tag names from `tag:name/*`, each matched by the tag or
+    /// any tag nested under it
+    pub tag_trees: Vec<String>,
+    /// Lowercase workspace names
+    pub workspaces: Vec<String>,
+    /// Status names as stored, e.g. "missing_file"
+    pub statuses: Vec<String>,
+    /// Import method names as stored, e.g. "folder_import", or "unknown"
+    /// for documents imported before provenance was recorded
+    pub sources: Vec<String>,
+    /// Created on or after this instant
+    pub created_after: Option<DateTime<Utc>>,
+    /// Created before this instant
+    pub created_before: Option<DateTime<Utc>>,
+    pub larger_than_bytes: Option<i64>,
+    pub smaller_than_bytes: Option<i64>,
+}
+
+/// Why a query couldn't be parsed, and where
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryParseError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl QueryParseError {
+    fn new(position: usize, message: impl Into<String>) -> Self {
+        QueryParseError {
+            position,
+            message: message.into(),
+        }
+    }
+}
+
+const STATUSES: [&str; 6] = ["queued", "uploading", "processing", "completed", "failed", "missing_file"];
+
+/// What `source:` matches documents without a recorded import method as
+const UNKNOWN_SOURCE: &str = "unknown";
+
+/// Parse a search box query
+pub fn parse_query(input: &str) -> Result<DocumentQuery, QueryParseError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut query = DocumentQuery::default();
+    let mut position = 0;
+
+    while position < chars.len() {
+        if chars[position].is_whitespace() {
+            position += 1;
+            continue;
+        }
+
+        if chars[position] == '"' {
+            let (phrase, next) = read_quoted(&chars, position)?;
+            if !phrase.trim().is_empty() {
+                query.text_terms.push(phrase);
+            }
+            position = next;
+            continue;
+        }
+
+        let start = position;
+        while position < chars.len() && !chars[position].is_whitespace() && chars[position] != '"' {
+            position += 1;
+        }
+        let word: String = chars[start..position].iter().collect();
+
+        let Some((field, operator, value)) = split_field(&word) else {
+            query.text_terms.push(word);
+            continue;
+        };
+        let value_start = start + field.chars().count() + 1;
+
+        // A value that opens a quote runs to the closing quote
+        let value = if value.is_empty() && chars.get(position) == Some(&'"') {
+            let (quoted, next) = read_quoted(&chars, position)?;
+            position = next;
+            quoted
+        } else {
+            value.to_string()
+        };
+        if value.trim().is_empty() {
+            return Err(QueryParseError::new(
+                value_start,
+                format!("Missing value after {}{}", field, operator),
+            ));
+        }
+
+        apply_field(&mut query, &field, operator, value.trim(), value_start)?;
+    }
+
+    Ok(query)
+}
+
+/// The phrase in the quote opening at `open`, and the position after its closing quote
+fn read_quoted(chars: &[char], open: usize) -> Result<(String, usize), QueryParseError> {
+    let close = chars[open + 1..]
+        .iter()
+        .position(|&c| c == '"')
+        .map(|offset| open + 1 + offset)
+        .ok_or_else(|| QueryParseError::new(open, "Unclosed quote"))?;
+    Ok((chars[open + 1..close].iter().collect(), close + 1))
+}
+
+/// Split `field:value`, `size>value` or `size<value` for known fields
+fn split_field(word: &str) -> Option<(String, char, &str)> {
+    let split = word.find([':', '>', '<'])?;
+    let field = word[..split].to_lowercase();
+    let operator = word[split..].chars().next()?;
+    let value = &word[split + 1..];
+
+    let known = match operator {
+        ':' => matches!(field.as_str(), "type" | "tag" | "workspace" | "status" | "source" | "after" | "before" | "size"),
+        _ => field == "size",
+    };
+    known.then_some((field, operator, value))
+}
+
+fn apply_field(
+    query: &mut DocumentQuery,
+    field: &str,
+    operator: char,
+    value: &str,
+    position: usize,
+) -> Result<(), QueryParseError> {
+    match field {
+        "type" => query.file_types.push(value.trim_start_matches('.').to_lowercase()),
+        "tag" => match value.strip_suffix("/*") {
+            Some(tag) => query.tag_trees.push(tag.to_lowercase()),
+            None => query.tags.push(value.to_lowercase()),
+        },
+        "workspace" => query.workspaces.push(value.to_lowercase()),
+        "status" => {
+            let status = value.to_lowercase().replace('-', "_");
+            if !STATUSES.contains(&status.as_str()) {
+                return Err(QueryParseError::new(
+                    position,
+                    format!("Unknown status \"{}\"; expected one of {}", value, STATUSES.join(", ")),
+                ));
+            }
+            query.statuses.push(status);
+        }
+        "source" => {
+            let source = value.to_lowercase().replace('-', "_");
+            let known = source == UNKNOWN_SOURCE || ImportMethod::ALL.iter().any(|m| m.as_str() == source);
+            if !known {
+                let names: Vec<&str> = ImportMethod::ALL
+                    .iter()
+                    .map(|m| m.as_str())
+                    .chain([UNKNOWN_SOURCE])
+                    .collect();
+                return Err(QueryParseError::new(
+                    position,
+                    format!("Unknown source \"{}\"; expected one of {}", value, names.join(", ")),
+                ));
+            }
+            query.sources.push(source);
+        }
+        "after" => query.created_after = Some(parse_date(value, position)?),
+        "before" => query.created_before = Some(parse_date(value, position)?),
+        _ => {
+            let bytes = parse_size(value, position)?;
+            match operator {
+                '>' => query.larger_than_bytes = Some(bytes),
+                '<' => query.smaller_than_bytes = Some(bytes),
+                _ => {
+                    return Err(QueryParseError::new(
+                        position - 1,
+                        "size needs > or <, e.g. size>10mb",
+                    ))
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Start of a YYYY-MM-DD day in UTC
+fn parse_date(value: &str, position: usize) -> Result<DateTime<Utc>, QueryParseError> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|start| start.and_utc())
+        .ok_or_else(|| QueryParseError::new(position, format!("\"{}\" is not a date like 2024-01-31", value)))
+}
+
+/// A size such as `500`, `20kb` or `1.5MB`; units are powers of 1024
+fn parse_size(value: &str, position: usize) -> Result<i64, QueryParseError> {
+    let lower = value.to_lowercase();
+    let split = lower
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(lower.len());
+    let (number, unit) = lower.split_at(split);
+
+    let multiplier: f64 = match unit {
+        "" | "b" => 1.0,
+        "k" | "kb" => 1024.0,
+        "m" | "mb" => 1024.0 * 1024.0,
+        "g" | "gb" => 1024.0 * 1024.0 * 1024.0,
+        _ => {
+            return Err(QueryParseError::new(
+                position + number.chars().count(),
+                format!("Unknown size unit \"{}\"; use b, kb, mb or gb", unit),
+            ))
+        }
+    };
+    let number: f64 = number
+        .parse()
+        .map_err(|_| QueryParseError::new(position, format!("\"{}\" is not a size like 10mb", value)))?;
+
+    Ok((number * multiplier).round() as i64)
+}
+
+/// A text term as an ILIKE pattern with `%`, `_` and `\` matched literally
+pub fn like_pattern(term: &str) -> String {
+    let mut pattern = String::with_capacity(term.len() + 2);
+    pattern.push('%');
+    for c in term.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_at(input: &str) -> usize {
+        parse_query(input).unwrap_err().position
+    }
+
+    #[test]
+    fn each_field_fills_its_part_of_the_query() {
+        let query = parse_query(
+            "type:.PDF tag:Tax tag:finance/* workspace:\"Client A\" status:missing-file source:folder-import \
+             after:2024-01-01 before:2024-02-01",
+        )
+        .unwrap();
+        let day = |d: &str| NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap().and_hms_opt(0, 0, 0).unwrap().and_utc();
+        assert_eq!(
+            query,
+            DocumentQuery {
+                file_types: vec!["pdf".to_string()],
+                tags: vec!["tax".to_string()],
+                tag_trees: vec!["finance".to_string()],
+                workspaces: vec!["client a".to_string()],
+                statuses: vec!["missing_file".to_string()],
+                sources: vec!["folder_import".to_string()],
+                created_after: Some(day("2024-01-01")),
+                created_before: Some(day("2024-02-01")),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn sizes_take_an_operator_and_a_unit() {
+        let query = parse_query("size>1.5MB size<20kb").unwrap();
+        assert_eq!(query.larger_than_bytes, Some(1_572_864));
+        assert_eq!(query.smaller_than_bytes, Some(20_480));
+        assert_eq!(parse_query("size<500").unwrap().smaller_than_bytes, Some(500));
+
+        // The colon form has no direction; the error points at the colon
+        assert_eq!(error_at("size:10mb"), 4);
+        assert_eq!(error_at("size>10tb"), 7);
+    }
+
+    #[test]
+    fn unknown_fields_and_phrases_are_text() {
+        let query = parse_query("author:smith https://example.com \"capital gains\" \"\"").unwrap();
+        assert_eq!(query.text_terms, ["author:smith", "https://example.com", "capital gains"]);
+        assert_eq!(query.file_types, Vec::<String>::new());
+    }
+
+    #[test]
+    fn errors_point_at_what_is_wrong() {
+        assert_eq!(error_at("tax \"capital gains"), 4);
+        assert_eq!(error_at("tag:\"unclosed tag"), 4);
+        assert_eq!(error_at("type:pdf status:lost"), 16);
+        assert_eq!(error_at("after:yesterday"), 6);
+        assert_eq!(error_at("tag:"), 4);
+    }
+
+    #[test]
+    fn like_patterns_match_wildcards_literally() {
+        assert_eq!(like_pattern("50%_off\\"), "%50\\%\\_off\\\\%");
+    }
+}
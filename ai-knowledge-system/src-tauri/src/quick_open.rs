@@ -0,0 +1,334 @@
+//! In-memory title index and fuzzy matcher for the quick switcher
+//!
+//! The index is kept current by the services that create and remove
+//! documents, so lookups never touch the database. Matching is a subsequence
+//! match scored like Sublime Text's: bonuses for matches at word boundaries
+//! and for runs of consecutive characters, small penalties for gaps.
+
+use crate::models::QuickOpenMatch;
+use crate::text_search;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use uuid::Uuid;
+
+/// Most results fuzzy_find returns, whatever limit is asked for
+pub const MAX_RESULTS: usize = 100;
+
+const MATCH_SCORE: i32 = 16;
+const BOUNDARY_BONUS: i32 = 30;
+const CONSECUTIVE_BONUS: i32 = 24;
+/// Per character skipped between two matched characters
+const GAP_PENALTY: i32 = -1;
+/// Per character before the first match, down to MAX_LEADING_PENALTY
+const LEADING_PENALTY: i32 = -3;
+const MAX_LEADING_PENALTY: i32 = -9;
+
+/// Text prepared for matching: folded chars, where each came from, and
+/// which ones start a word
+struct Prepared {
+    folded: Vec<char>,
+    origins: Vec<usize>,
+    boundaries: Vec<bool>,
+}
+
+impl Prepared {
+    fn new(text: &str) -> Self {
+        let original: Vec<char> = text.chars().collect();
+        let (folded, origins) = text_search::fold_with_origins(text);
+        let boundaries = origins
+            .iter()
+            .enumerate()
+            .map(|(index, &origin)| {
+                // Only the first char folded from an original char can start a word
+                if index > 0 && origins[index - 1] == origin {
+                    return false;
+                }
+                if origin == 0 {
+                    return true;
+                }
+                let (previous, current) = (original[origin - 1], original[origin]);
+                !previous.is_alphanumeric() || (previous.is_lowercase() && current.is_uppercase())
+            })
+            .collect();
+        Prepared {
+            folded,
+            origins,
+            boundaries,
+        }
+    }
+
+    /// Best score and matched positions in the original text, if `query` is a subsequence
+    fn score(&self, query: &[char]) -> Option<(i32, Vec<usize>)> {
+        let (positions, score) = fuzzy_match(query, &self.folded, &self.boundaries)?;
+        let mut original: Vec<usize> = positions.into_iter().map(|p| self.origins[p]).collect();
+        original.dedup();
+        Some((score, original))
+    }
+}
+
+struct Entry {
+    user_id: Uuid,
+    title: String,
+    file_name: Option<String>,
+    prepared_title: Prepared,
+    prepared_file_name: Option<Prepared>,
+}
+
+/// Titles and file names of every live document, by document id
+#[derive(Default)]
+pub struct QuickOpenIndex {
+    entries: RwLock<HashMap<Uuid, Entry>>,
+}
+
+impl QuickOpenIndex {
+    /// Add or refresh one document
+    pub fn upsert(&self, document_id: Uuid, user_id: Uuid, title: &str, file_name: Option<&str>) {
+        let entry = Entry {
+            user_id,
+            title: title.to_string(),
+            file_name: file_name.map(str::to_string),
+            prepared_title: Prepared::new(title),
+            prepared_file_name: file_name.map(Prepared::new),
+        };
+        self.entries
+            .write()
+            .expect("quick open index poisoned")
+            .insert(document_id, entry);
+    }
+
+    pub fn remove(&self, document_id: Uuid) {
+        self.entries
+            .write()
+            .expect("quick open index poisoned")
+            .remove(&document_id);
+    }
+
+    /// Replace the whole index, e.g. at startup or on rebuild_quick_index
+    pub fn replace_all(&self, documents: impl IntoIterator<Item = (Uuid, Uuid, String, Option<String>)>) {
+        let entries = documents
+            .into_iter()
+            .map(|(document_id, user_id, title, file_name)| {
+                let entry = Entry {
+                    prepared_title: Prepared::new(&title),
+                    prepared_file_name: file_name.as_deref().map(Prepared::new),
+                    user_id,
+                    title,
+                    file_name,
+                };
+                (document_id, entry)
+            })
+            .collect();
+        *self.entries.write().expect("quick open index poisoned") = entries;
+    }
+
+    pub fn document_count(&self) -> usize {
+        self.entries.read().expect("quick open index poisoned").len()
+    }
+
+    /// A user's documents matching `query`, best first
+    ///
+    /// Titles are preferred; the file name is matched when it scores higher.
+    pub fn find(&self, user_id: Uuid, query: &str, limit: usize) -> Vec<QuickOpenMatch> {
+        let (query, _) = text_search::fold_with_origins(query.trim());
+        let query: Vec<char> = query.into_iter().filter(|c| !c.is_whitespace()).collect();
+        if query.is_empty() || limit == 0 {
+            return Vec::new();
+        }
+
+        let entries = self.entries.read().expect("quick open index poisoned");
+        let mut matches: Vec<QuickOpenMatch> = entries
+            .iter()
+            .filter(|(_, entry)| entry.user_id == user_id)
+            .filter_map(|(&document_id, entry)| {
+                let title = entry.prepared_title.score(&query);
+                let file_name = entry.prepared_file_name.as_ref().and_then(|p| p.score(&query));
+                let (score, title_positions, file_name_positions) = match (title, file_name) {
+                    (Some(t), Some(f)) if f.0 > t.0 => (f.0, Vec::new(), f.1),
+                    (Some(t), _) => (t.0, t.1, Vec::new()),
+                    (None, Some(f)) => (f.0, Vec::new(), f.1),
+                    (None, None) => return None,
+                };
+                Some(QuickOpenMatch {
+                    document_id,
+                    title: entry.title.clone(),
+                    file_name: entry.file_name.clone(),
+                    score,
+                    title_positions,
+                    file_name_positions,
+                })
+            })
+            .collect();
+
+        matches.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then_with(|| a.title.chars().count().cmp(&b.title.chars().count()))
+        });
+        matches.truncate(limit.min(MAX_RESULTS));
+        matches
+    }
+}
+
+/// Best alignment of `query` as a subsequence of `text`
+///
+/// Both are already folded. Returns the matched indices into `text` and the
+/// score. Runs in O(query × text) by carrying the best gapped predecessor
+/// along each row instead of rescanning it.
+fn fuzzy_match(query: &[char], text: &[char], boundaries: &[bool]) -> Option<(Vec<usize>, i32)> {
+    let (n, m) = (query.len(), text.len());
+    if n == 0 || n > m {
+        return None;
+    }
+    let bonus = |j: usize| if boundaries[j] { BOUNDARY_BONUS } else { 0 };
+
+    // scores[i][j]: best score with query[i] matched at text[j]; from[i][j]: where query[i - 1] matched
+    let mut scores = vec![vec![None::<i32>; m]; n];
+    let mut from = vec![vec![0usize; m]; n];
+
+    for (j, &c) in text.iter().enumerate() {
+        if c == query[0] {
+            let leading = (LEADING_PENALTY * j as i32).max(MAX_LEADING_PENALTY);
+            scores[0][j] = Some(MATCH_SCORE + bonus(j) + leading);
+        }
+    }
+
+    for (i, &q) in query.iter().enumerate().skip(1) {
+        // Best predecessor at least two chars back, already charged for its gap
+        let mut gapped: Option<(i32, usize)> = None;
+        for (j, &c) in text.iter().enumerate().skip(1) {
+            if let Some(g) = gapped.as_mut() {
+                g.0 += GAP_PENALTY;
+            }
+            if j >= 2 {
+                if let Some(s) = scores[i - 1][j - 2] {
+                    let candidate = s + GAP_PENALTY;
+                    if !matches!(gapped, Some((score, _)) if score >= candidate) {
+                        gapped = Some((candidate, j - 2));
+                    }
+                }
+            }
+            if c != q {
+                continue;
+            }
+
+            let mut best = gapped;
+            if let Some(s) = scores[i - 1][j - 1] {
+                let candidate = s + CONSECUTIVE_BONUS;
+                if !matches!(best, Some((score, _)) if score >= candidate) {
+                    best = Some((candidate, j - 1));
+                }
+            }
+            if let Some((s, k)) = best {
+                scores[i][j] = Some(s + MATCH_SCORE + bonus(j));
+                from[i][j] = k;
+            }
+        }
+    }
+
+    let (mut j, score) = scores[n - 1]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, s)| s.map(|s| (j, s)))
+        .max_by_key(|&(_, s)| s)?;
+
+    let mut positions = vec![0; n];
+    for (i, position) in positions.iter_mut().enumerate().rev() {
+        *position = j;
+        j = from[i][j];
+    }
+    Some((positions, score))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(titles: &[&str]) -> (QuickOpenIndex, Uuid) {
+        let index = QuickOpenIndex::default();
+        let user_id = Uuid::new_v4();
+        for title in titles {
+            index.upsert(Uuid::new_v4(), user_id, title, None);
+        }
+        (index, user_id)
+    }
+
+    fn titles(index: &QuickOpenIndex, user_id: Uuid, query: &str) -> Vec<String> {
+        index.find(user_id, query, 10).into_iter().map(|m| m.title).collect()
+    }
+
+    #[test]
+    fn prefixes_beat_word_starts_beat_scattered_letters() {
+        let (index, user_id) = index(&["Rebuttal drafts", "Annual budget", "Budget report"]);
+        assert_eq!(titles(&index, user_id, "bud"), ["Budget report", "Annual budget", "Rebuttal drafts"]);
+    }
+
+    #[test]
+    fn word_starts_include_camel_case_and_punctuation() {
+        let (index, user_id) = index(&["quickopenindex", "QuickOpenIndex", "quick_open_index"]);
+        let found = index.find(user_id, "qoi", 10);
+        let scores: HashMap<&str, i32> = found.iter().map(|m| (m.title.as_str(), m.score)).collect();
+        assert!(scores["QuickOpenIndex"] > scores["quickopenindex"]);
+        assert!(scores["quick_open_index"] > scores["quickopenindex"]);
+        let camel = found.iter().find(|m| m.title == "QuickOpenIndex").unwrap();
+        assert_eq!(camel.title_positions, [0, 5, 9]);
+    }
+
+    #[test]
+    fn consecutive_letters_beat_gaps() {
+        let (index, user_id) = index(&["pxlxaxnx", "planning"]);
+        assert_eq!(titles(&index, user_id, "plan"), ["planning", "pxlxaxnx"]);
+    }
+
+    #[test]
+    fn equal_scores_prefer_the_shorter_title() {
+        let (index, user_id) = index(&["Notes from the long meeting", "Notes"]);
+        assert_eq!(titles(&index, user_id, "notes"), ["Notes", "Notes from the long meeting"]);
+    }
+
+    #[test]
+    fn queries_ignore_case_accents_and_spaces() {
+        let (index, user_id) = index(&["Café Résumé"]);
+        let found = index.find(user_id, " cafe  RES ", 10);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].title_positions, [0, 1, 2, 3, 5, 6, 7]);
+        assert!(index.find(user_id, "xyz", 10).is_empty());
+        assert!(index.find(user_id, "   ", 10).is_empty());
+    }
+
+    #[test]
+    fn file_names_match_when_they_score_higher() {
+        let index = QuickOpenIndex::default();
+        let user_id = Uuid::new_v4();
+        index.upsert(Uuid::new_v4(), user_id, "Minutes", Some("board-2024.pdf"));
+        let found = index.find(user_id, "board", 10);
+        assert_eq!(found.len(), 1);
+        assert!(found[0].title_positions.is_empty());
+        assert_eq!(found[0].file_name_positions, [0, 1, 2, 3, 4]);
+
+        let found = index.find(user_id, "min", 10);
+        assert_eq!(found[0].title_positions, [0, 1, 2]);
+        assert!(found[0].file_name_positions.is_empty());
+    }
+
+    #[test]
+    fn only_the_users_own_documents_are_found() {
+        let (index, ada) = index(&["Ada's plan"]);
+        let bob = Uuid::new_v4();
+        let bobs = Uuid::new_v4();
+        index.upsert(bobs, bob, "Bob's plan", None);
+
+        assert_eq!(titles(&index, ada, "plan"), ["Ada's plan"]);
+        index.remove(bobs);
+        assert!(titles(&index, bob, "plan").is_empty());
+        assert_eq!(index.document_count(), 1);
+    }
+
+    #[test]
+    fn results_stop_at_the_limit() {
+        let many: Vec<String> = (0..150).map(|n| format!("Report {}", n)).collect();
+        let (index, user_id) = index(&many.iter().map(String::as_str).collect::<Vec<_>>());
+        assert_eq!(index.find(user_id, "report", 5).len(), 5);
+        assert_eq!(index.find(user_id, "report", 1_000).len(), MAX_RESULTS);
+        assert!(index.find(user_id, "report", 0).is_empty());
+    }
+}
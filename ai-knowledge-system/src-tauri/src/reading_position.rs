@@ -0,0 +1,179 @@
+//! Reading positions that survive reprocessing
+//!
+//! A position is saved with the text at its offset. When the content has
+//! been extracted again since, the offset is found by that text; failing
+//! that, by part of it or by the start of the saved page.
+
+use crate::models::{Anchoring, ReadingPosition, StoredReadingPosition};
+
+/// Chars of content saved with a position
+pub const ANCHOR_CHARS: usize = 64;
+
+/// Shortest part of an anchor searched for before falling back to the page
+const MIN_PARTIAL_ANCHOR_CHARS: usize = 16;
+
+/// The anchor saved for a position at `char_offset`
+pub fn anchor_at(content: &str, char_offset: usize) -> String {
+    content.chars().skip(char_offset).take(ANCHOR_CHARS).collect()
+}
+
+/// Find a saved position in the current content
+///
+/// `page_start` is the char offset where the saved page starts now, if the
+/// document still has that page.
+pub fn relocate(saved: &StoredReadingPosition, content: &str, page_start: Option<i32>) -> ReadingPosition {
+    let offset = saved.char_offset.max(0) as usize;
+    let at = |char_offset: usize, anchoring| ReadingPosition {
+        char_offset: char_offset as i32,
+        page_number: saved.page_number,
+        percent: saved.percent,
+        updated_at: saved.updated_at,
+        anchoring,
+    };
+    let content_chars = content.chars().count();
+
+    if offset <= content_chars && anchor_at(content, offset) == saved.anchor {
+        return at(offset, Anchoring::Exact);
+    }
+    if !saved.anchor.is_empty() {
+        if let Some(found) = nearest_match(content, &saved.anchor, offset) {
+            return at(found, Anchoring::Exact);
+        }
+    }
+
+    // The text around the position was edited; look for either end of it
+    let anchor: Vec<char> = saved.anchor.chars().collect();
+    let mut len = anchor.len() / 2;
+    while len >= MIN_PARTIAL_ANCHOR_CHARS {
+        let head: String = anchor[..len].iter().collect();
+        if let Some(found) = nearest_match(content, &head, offset) {
+            return at(found, Anchoring::Approximate);
+        }
+        let tail: String = anchor[anchor.len() - len..].iter().collect();
+        if let Some(found) = nearest_match(content, &tail, offset) {
+            // The tail started this far into the anchor
+            let found = found.saturating_sub(anchor.len() - len);
+            return at(found, Anchoring::Approximate);
+        }
+        len /= 2;
+    }
+
+    match page_start {
+        Some(start) => at(start.max(0) as usize, Anchoring::Approximate),
+        None => at(offset.min(content_chars), Anchoring::NotFound),
+    }
+}
+
+/// Char offset of the occurrence of `needle` closest to `near`
+fn nearest_match(content: &str, needle: &str, near: usize) -> Option<usize> {
+    let mut best: Option<usize> = None;
+    let (mut chars, mut counted_to) = (0, 0);
+    for (byte, _) in content.match_indices(needle) {
+        chars += content[counted_to..byte].chars().count();
+        counted_to = byte;
+        // Occurrences come in order, so past the closest one they only get further
+        if let Some(best) = best {
+            if chars.abs_diff(near) >= best.abs_diff(near) {
+                break;
+            }
+        }
+        best = Some(chars);
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sentences distinct enough that any 16 chars of them occur once
+    fn content() -> String {
+        (1..=20)
+            .map(|n| format!("Sentence number {:02} talks about topic {:02}. ", n, n * 7))
+            .collect()
+    }
+
+    fn saved_at(content: &str, char_offset: usize) -> StoredReadingPosition {
+        StoredReadingPosition {
+            char_offset: char_offset as i32,
+            page_number: Some(2),
+            percent: 40.0,
+            anchor: anchor_at(content, char_offset),
+            updated_at: chrono::Utc::now(),
+        }
+    }
+
+    fn found(position: &ReadingPosition) -> (usize, Anchoring) {
+        (position.char_offset as usize, position.anchoring)
+    }
+
+    /// Char offset of `needle` in `text`
+    fn char_offset(text: &str, needle: &str) -> usize {
+        text[..text.find(needle).unwrap()].chars().count()
+    }
+
+    #[test]
+    fn unchanged_content_keeps_the_offset() {
+        let content = content();
+        let saved = saved_at(&content, 200);
+        let position = relocate(&saved, &content, Some(150));
+        assert_eq!(found(&position), (200, Anchoring::Exact));
+        assert_eq!((position.page_number, position.percent), (Some(2), 40.0));
+    }
+
+    #[test]
+    fn the_anchor_is_followed_when_text_moves_it() {
+        let before = content();
+        let saved = saved_at(&before, char_offset(&before, "Sentence number 08"));
+        // Multibyte text added in front shifts it by chars, not bytes
+        let after = format!("Préface — überarbeitet. {}", before);
+        let position = relocate(&saved, &after, None);
+        assert_eq!(found(&position), (char_offset(&after, "Sentence number 08"), Anchoring::Exact));
+    }
+
+    #[test]
+    fn of_repeated_anchors_the_nearest_wins() {
+        let before = content();
+        let offset = char_offset(&before, "Sentence number 15");
+        let saved = saved_at(&before, offset);
+        // The anchored text now also appears near the start, and the original shifted by one
+        let repeated: String = before.chars().skip(offset).take(ANCHOR_CHARS).collect();
+        let after = format!("{}x{}", repeated, before);
+        assert_eq!(found(&relocate(&saved, &after, None)), (offset + ANCHOR_CHARS + 1, Anchoring::Exact));
+    }
+
+    #[test]
+    fn an_edit_inside_the_anchor_finds_its_other_half() {
+        let before = content();
+        let offset = char_offset(&before, "Sentence number 10");
+        let saved = saved_at(&before, offset);
+
+        // The end of the anchor was rewritten; its start is still there
+        let after = before.replace("Sentence number 11 talks about topic 77", "An entirely new sentence");
+        assert_eq!(found(&relocate(&saved, &after, None)), (offset, Anchoring::Approximate));
+
+        // The start was rewritten; its end places the position
+        let after = before.replace("Sentence number 10 talks", "Rewritten and longer opening");
+        let position = relocate(&saved, &after, None);
+        assert_eq!(position.anchoring, Anchoring::Approximate);
+        let start = offset + "Rewritten and longer opening".len() - "Sentence number 10 talks".len();
+        assert_eq!(position.char_offset as usize, start);
+    }
+
+    #[test]
+    fn lost_text_falls_back_to_the_page_then_stays_in_range() {
+        let before = content();
+        let saved = saved_at(&before, before.chars().count() - 10);
+        let after = "Completely different text after OCR.".to_string();
+
+        assert_eq!(found(&relocate(&saved, &after, Some(5))), (5, Anchoring::Approximate));
+        assert_eq!(found(&relocate(&saved, &after, None)), (after.chars().count(), Anchoring::NotFound));
+    }
+
+    #[test]
+    fn anchors_are_the_next_chars_of_content() {
+        assert_eq!(anchor_at("añb", 1), "ñb");
+        assert_eq!(anchor_at("abc", 5), "");
+        assert_eq!(anchor_at(&"x".repeat(100), 0).chars().count(), ANCHOR_CHARS);
+    }
+}
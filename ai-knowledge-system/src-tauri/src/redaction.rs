@@ -0,0 +1,219 @@
+use crate::models::{RedactionMatchCount, RedactionRule};
+use regex::Regex;
+use std::ops::Range;
+use std::sync::OnceLock;
+
+/// Character that replaces each redacted character
+pub const REDACTION_CHAR: char = '█';
+
+fn email_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?i)\b[a-z0-9._%+-]+@[a-z0-9.-]+\.[a-z]{2,}\b").unwrap())
+}
+
+fn phone_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"(?:\+\d{1,3}[\s.-]?)?(?:\(\d{2,4}\)[\s.-]?|\b\d{2,4}[\s.-])\d{3,4}[\s.-]?\d{3,4}\b").unwrap()
+    })
+}
+
+fn ssn_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\b\d{3}-\d{2}-\d{4}\b").unwrap())
+}
+
+/// Compile a user rule's pattern; literals match case-insensitively
+pub fn compile_rule(kind: &str, pattern: &str) -> Result<Regex, String> {
+    if pattern.trim().is_empty() {
+        return Err("Pattern cannot be empty".to_string());
+    }
+    match kind {
+        "literal" => Regex::new(&format!("(?i){}", regex::escape(pattern))).map_err(|e| e.to_string()),
+        "regex" => Regex::new(pattern).map_err(|e| e.to_string()),
+        other => Err(format!("Unknown rule kind: {}", other)),
+    }
+}
+
+struct CompiledRule {
+    rule_id: Option<uuid::Uuid>,
+    label: String,
+    regex: Regex,
+}
+
+/// The built-in classes plus a user's own rules
+pub struct Redactor {
+    rules: Vec<CompiledRule>,
+}
+
+impl Redactor {
+    /// Build from saved rules; rules that no longer compile are skipped
+    pub fn new(user_rules: &[RedactionRule]) -> Self {
+        let builtin = [
+            ("Email addresses", email_regex()),
+            ("Phone numbers", phone_regex()),
+            ("SSN-like numbers", ssn_regex()),
+        ];
+        let mut rules: Vec<CompiledRule> = builtin
+            .into_iter()
+            .map(|(label, regex)| CompiledRule {
+                rule_id: None,
+                label: label.to_string(),
+                regex: regex.clone(),
+            })
+            .collect();
+
+        for rule in user_rules {
+            match compile_rule(&rule.kind, &rule.pattern) {
+                Ok(regex) => rules.push(CompiledRule {
+                    rule_id: Some(rule.id),
+                    label: rule.label.clone().unwrap_or_else(|| rule.pattern.clone()),
+                    regex,
+                }),
+                Err(e) => eprintln!("Skipping redaction rule {}: {}", rule.id, e),
+            }
+        }
+
+        Redactor { rules }
+    }
+
+    /// Replace every match with one block character per character matched
+    pub fn redact(&self, text: &str) -> String {
+        let mut ranges: Vec<Range<usize>> = self
+            .rules
+            .iter()
+            .flat_map(|rule| rule.regex.find_iter(text).map(|m| m.range()))
+            .filter(|range| !range.is_empty())
+            .collect();
+        ranges.sort_by_key(|range| range.start);
+
+        let mut redacted = String::with_capacity(text.len());
+        let mut position = 0;
+        for range in ranges {
+            // Overlapping matches from different rules merge into one block
+            let start = range.start.max(position);
+            if start >= range.end {
+                continue;
+            }
+            redacted.push_str(&text[position..start]);
+            let length = text[start..range.end].chars().count();
+            redacted.extend(std::iter::repeat_n(REDACTION_CHAR, length));
+            position = range.end;
+        }
+        redacted.push_str(&text[position..]);
+        redacted
+    }
+
+    /// Matches per rule across `texts`, built-in rules first
+    pub fn count_matches<'a>(&self, texts: impl IntoIterator<Item = &'a str> + Clone) -> Vec<RedactionMatchCount> {
+        self.rules
+            .iter()
+            .map(|rule| RedactionMatchCount {
+                rule_id: rule.rule_id,
+                label: rule.label.clone(),
+                count: texts.clone().into_iter().map(|t| rule.regex.find_iter(t).count()).sum(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn rule(kind: &str, pattern: &str, label: Option<&str>) -> RedactionRule {
+        RedactionRule {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            kind: kind.to_string(),
+            pattern: pattern.to_string(),
+            label: label.map(str::to_string),
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    /// `text` with `#` in place of each redacted character, for readable
+    /// expectations
+    fn redacted(redactor: &Redactor, text: &str) -> String {
+        redactor.redact(text).replace(REDACTION_CHAR, "#")
+    }
+
+    #[test]
+    fn emails_are_redacted() {
+        let redactor = Redactor::new(&[]);
+        assert_eq!(redacted(&redactor, "Mail ada.l+tag@Example.co.uk now"), "Mail ####################### now");
+        assert_eq!(redacted(&redactor, "not@an-address and @handle"), "not@an-address and @handle");
+    }
+
+    #[test]
+    fn phone_numbers_are_redacted() {
+        let redactor = Redactor::new(&[]);
+        assert_eq!(redacted(&redactor, "Call +44 20 7946 0958."), "Call ################.");
+        assert_eq!(redacted(&redactor, "or (555) 123-4567 today"), "or ############## today");
+        assert_eq!(redacted(&redactor, "Room 12, 3 floors"), "Room 12, 3 floors");
+    }
+
+    #[test]
+    fn ssn_like_numbers_are_redacted() {
+        let redactor = Redactor::new(&[]);
+        assert_eq!(redacted(&redactor, "SSN 078-05-1120 on file"), "SSN ########### on file");
+        assert_eq!(redacted(&redactor, "ISO date 2024-01-15"), "ISO date 2024-01-15");
+    }
+
+    #[test]
+    fn user_rules_redact_literals_without_case_and_regexes_as_written() {
+        let rules = [rule("literal", "Project X.", None), rule("regex", r"ACME-\d+", Some("Tickets"))];
+        let redactor = Redactor::new(&rules);
+        assert_eq!(redacted(&redactor, "project x. ships; ACME-42, acme-7"), "########## ships; #######, acme-7");
+    }
+
+    #[test]
+    fn overlapping_matches_merge_into_one_block() {
+        // The literal overlaps the email's end and the SSN's start
+        let rules = [rule("literal", "com 123", None)];
+        let redactor = Redactor::new(&rules);
+        assert_eq!(redacted(&redactor, "ada@example.com 123-45-6789!"), "###########################!");
+        // One match inside another adds nothing
+        let rules = [rule("literal", "example", None)];
+        assert_eq!(redacted(&Redactor::new(&rules), "ada@example.com."), "###############.");
+    }
+
+    #[test]
+    fn each_redacted_character_becomes_one_block() {
+        let rules = [rule("literal", "Zürich", None)];
+        let output = Redactor::new(&rules).redact("Zürich office");
+        assert_eq!(output, format!("{} office", REDACTION_CHAR.to_string().repeat(6)));
+    }
+
+    #[test]
+    fn rules_that_dont_compile_are_rejected_or_skipped() {
+        assert_eq!(compile_rule("literal", "  ").unwrap_err(), "Pattern cannot be empty");
+        assert!(compile_rule("regex", "(unclosed").is_err());
+        assert!(compile_rule("glob", "*.pdf").unwrap_err().contains("glob"));
+        // Literals are escaped, so regex syntax in them is plain text
+        assert!(compile_rule("literal", "(unclosed").unwrap().is_match("an (UNCLOSED note"));
+
+        let redactor = Redactor::new(&[rule("regex", "(unclosed", None), rule("literal", "secret", None)]);
+        let labels: Vec<String> = redactor.count_matches(["a secret"]).into_iter().map(|c| c.label).collect();
+        assert_eq!(labels, ["Email addresses", "Phone numbers", "SSN-like numbers", "secret"]);
+    }
+
+    #[test]
+    fn matches_are_counted_per_rule_across_texts() {
+        let tickets = rule("regex", r"ACME-\d+", Some("Tickets"));
+        let redactor = Redactor::new(std::slice::from_ref(&tickets));
+        let counts = redactor.count_matches(["ada@example.com ACME-1", "ACME-2 and bob@example.org"]);
+        let counts: Vec<(Option<Uuid>, &str, usize)> =
+            counts.iter().map(|c| (c.rule_id, c.label.as_str(), c.count)).collect();
+        assert_eq!(
+            counts,
+            [
+                (None, "Email addresses", 2),
+                (None, "Phone numbers", 0),
+                (None, "SSN-like numbers", 0),
+                (Some(tickets.id), "Tickets", 2),
+            ]
+        );
+    }
+}
@@ -0,0 +1,44 @@
+use crate::db;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Writes audit entries to the activity_log table
+pub struct ActivityLogger {
+    pool: PgPool,
+}
+
+impl ActivityLogger {
+    pub fn new(pool: PgPool) -> Self {
+        ActivityLogger { pool }
+    }
+
+    /// Record an entry, retrying for a while if the connection fails
+    pub async fn log(
+        &self,
+        user_id: Option<Uuid>,
+        action: &str,
+        resource_type: &str,
+        resource_id: Option<Uuid>,
+        metadata: serde_json::Value,
+    ) -> Result<(), sqlx::Error> {
+        let (pool, metadata) = (&self.pool, &metadata);
+        db::retry_transient(db::DEFAULT_RETRY_WINDOW, move || async move {
+            sqlx::query!(
+                r#"
+                INSERT INTO activity_log (user_id, action, resource_type, resource_id, metadata)
+                VALUES ($1, $2, $3, $4, $5)
+                "#,
+                user_id,
+                action,
+                resource_type,
+                resource_id,
+                metadata
+            )
+            .execute(pool)
+            .await
+        })
+        .await?;
+
+        Ok(())
+    }
+}
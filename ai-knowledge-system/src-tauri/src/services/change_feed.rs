@@ -0,0 +1,91 @@
+use crate::models::{ChangeFeed, FeedChange, FeedChangeKind};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Days feed entries are kept before maintenance trims them
+pub const CHANGES_KEPT_DAYS: i32 = 7;
+
+/// The document_changes feed, which triggers append to with every write to
+/// a document
+pub struct ChangeFeedService {
+    pool: PgPool,
+}
+
+impl ChangeFeedService {
+    pub fn new(pool: PgPool) -> Self {
+        ChangeFeedService { pool }
+    }
+
+    /// A user's changes after `since_seq`, oldest first, at most `limit`
+    ///
+    /// A cursor of 0, one from before the oldest kept entry or one past the
+    /// newest (e.g. from another database) can't be caught up from; the
+    /// feed then only carries the newest seq and asks for a resync.
+    ///
+    /// Seqs are taken when a change is written, so a long transaction can
+    /// commit entries behind a cursor already handed out; writes here are
+    /// short enough that consumers also listening for events won't notice.
+    pub async fn poll(&self, user_id: Uuid, since_seq: i64, limit: i64) -> Result<ChangeFeed, sqlx::Error> {
+        let bounds = sqlx::query!("SELECT MIN(seq) as oldest, MAX(seq) as latest FROM document_changes")
+            .fetch_one(&self.pool)
+            .await?;
+        let latest = bounds.latest.unwrap_or(0);
+        let trimmed = bounds.oldest.is_some_and(|oldest| since_seq < oldest - 1);
+        if since_seq <= 0 || since_seq > latest || trimmed {
+            return Ok(ChangeFeed {
+                changes: Vec::new(),
+                latest_seq: latest,
+                resync_required: true,
+                has_more: false,
+            });
+        }
+
+        let mut changes = sqlx::query_as!(
+            FeedChange,
+            r#"
+            SELECT seq, document_id, change_kind as "change_kind: FeedChangeKind", changed_at
+            FROM document_changes
+            WHERE user_id = $1 AND seq > $2 AND seq <= $3
+            ORDER BY seq
+            LIMIT $4
+            "#,
+            user_id,
+            since_seq,
+            latest,
+            limit + 1
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let has_more = changes.len() as i64 > limit;
+        changes.truncate(limit as usize);
+        // Resume after the last change returned when some were held back
+        let latest_seq = match changes.last() {
+            Some(last) if has_more => last.seq,
+            _ => latest,
+        };
+        Ok(ChangeFeed {
+            changes,
+            latest_seq,
+            resync_required: false,
+            has_more,
+        })
+    }
+
+    /// Drop entries older than `days`; the newest entry is always kept so
+    /// cursors can still be told apart from trimmed ones
+    pub async fn prune(&self, days: i32) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM document_changes
+            WHERE changed_at < NOW() - make_interval(days => $1)
+              AND seq < (SELECT MAX(seq) FROM document_changes)
+            "#,
+            days
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,44 @@
+use crate::models::{DocumentChange, DocumentStatus};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// Changes buffered for a slow subscriber before it starts missing some
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// Broadcast of every stored change to a document
+///
+/// Each service that writes to documents holds a clone and publishes after
+/// the write has been committed, so every code path reports its changes.
+/// The app forwards them to the UI as "documents:changed".
+#[derive(Clone)]
+pub struct DocumentChanges {
+    sender: broadcast::Sender<DocumentChange>,
+}
+
+impl DocumentChanges {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        DocumentChanges { sender }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<DocumentChange> {
+        self.sender.subscribe()
+    }
+
+    /// Report that `fields` of a document changed; `status` is its status
+    /// afterwards when the change touched or revealed it
+    pub fn publish(&self, document_id: Uuid, fields: &[&'static str], status: Option<DocumentStatus>) {
+        // Sending only fails when nobody is subscribed, e.g. before startup finishes
+        let _ = self.sender.send(DocumentChange {
+            document_id,
+            changed_fields: fields.to_vec(),
+            status,
+        });
+    }
+}
+
+impl Default for DocumentChanges {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,14 +1,46 @@
-use crate::models::{Document, CreateDocumentDto, DocumentStatus};
-use sqlx::PgPool;
+use super::changes::DocumentChanges;
+use crate::chunking::{PageChunks, TextChunk};
+use crate::date_buckets::BucketBounds;
+use crate::derived;
+use crate::error::AppError;
+use crate::identifiers::IdentifierKind;
+use crate::invoice_fields::ExtractedField;
+use crate::keywords;
+use crate::query_parser::{self, DocumentQuery};
+use crate::quick_open::QuickOpenIndex;
+use crate::models::{
+    Document, CreateDocumentDto, DocumentIdentifier, DocumentNote, DocumentPage, DocumentSort, DocumentStatus,
+    ProcessingOptions, TermCount, TermTrendPoint, TrendBucket, DigestDocument, DigestFailure,
+    FileReference, CleanupCandidate, PdfForm, SearchExportRow, DocumentStatusEntry, DocumentSummary,
+    SearchPageMatch, DerivedTarget, BucketCount, DateBucket, StoredReadingPosition, ReadingListEntry,
+    UnprocessedType, CatalogEntry, CatalogSort, RenameCandidate, EvalSample, ProvenanceInfo, ScanRecord,
+    ContentCompressionReport,
+};
+use crate::display;
+use crate::file_utils;
+use crate::text_compression;
+use crate::text_search;
+use sqlx::types::Json;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+use std::sync::Arc;
 use uuid::Uuid;
 
 pub struct DocumentService {
     pool: PgPool,
+    /// Kept in step with every insert and delete made here
+    quick_index: Arc<QuickOpenIndex>,
+    /// Told about every change made here
+    changes: DocumentChanges,
 }
 
 impl DocumentService {
-    pub fn new(pool: PgPool) -> Self {
-        DocumentService { pool }
+    pub fn new(pool: PgPool, quick_index: Arc<QuickOpenIndex>, changes: DocumentChanges) -> Self {
+        DocumentService {
+            pool,
+            quick_index,
+            changes,
+        }
     }
     
     pub async fn create_document(&self, dto: CreateDocumentDto) -> Result<Document, sqlx::Error> {
@@ -16,25 +48,40 @@ impl DocumentService {
             Document,
             r#"
             INSERT INTO documents (
-                user_id, title, file_name, file_size_bytes, file_type, mime_type, status
+                user_id, title, file_name, file_size_bytes, file_type, mime_type, status,
+                parent_document_id, file_hash, title_sort, external_file, derived_versions, provenance,
+                workspace_id
             )
-            VALUES ($1, $2, $3, $4, $5, $6, 'uploading')
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
             RETURNING 
                 id, user_id, workspace_id, title, content, summary, 
                 file_path, file_name, file_size_bytes, file_type, mime_type,
                 status as "status!: DocumentStatus",
-                processing_error, created_at, updated_at, deleted_at
+                processing_error, created_at, updated_at, deleted_at,
+                page_count, parent_document_id, is_pinned, pinned_order, external_file,
+                (manually_unread OR last_opened_at IS NULL) as "is_unread!"
             "#,
             dto.user_id,
             dto.title,
             dto.file_name,
             dto.file_size_bytes,
             dto.file_type,
-            dto.mime_type
+            dto.mime_type,
+            dto.status as DocumentStatus,
+            dto.parent_document_id,
+            dto.file_hash,
+            dto.title_sort,
+            dto.external_file,
+            derived::stamp(DerivedTarget::SortKeys),
+            Json(&dto.provenance) as _,
+            dto.workspace_id
         )
         .fetch_one(&self.pool)
         .await?;
         
+        self.quick_index
+            .upsert(doc.id, doc.user_id, &doc.title, doc.file_name.as_deref());
+        self.changes.publish(doc.id, &["created"], Some(doc.status));
         Ok(doc)
     }
     
@@ -51,71 +98,2800 @@ impl DocumentService {
         .execute(&self.pool)
         .await?;
         
+        self.changes.publish(doc_id, &["file_path"], None);
         Ok(())
     }
     
-    pub async fn update_content_and_summary(
+    /// Ids and hashes of a user's stored files of exactly `size` bytes, for
+    /// spotting a file that is imported twice without hashing every one
+    pub async fn file_hashes_by_size(&self, user_id: Uuid, size: i64) -> Result<Vec<(Uuid, String)>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, file_hash as "file_hash!"
+            FROM documents
+            WHERE user_id = $1 AND file_size_bytes = $2 AND file_hash IS NOT NULL AND deleted_at IS NULL
+            "#,
+            user_id,
+            size
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(rows.into_iter().map(|row| (row.id, row.file_hash)).collect())
+    }
+    
+    /// `file_hashes_by_size` over a user's soft-deleted documents
+    pub async fn trashed_file_hashes_by_size(
+        &self,
+        user_id: Uuid,
+        size: i64,
+    ) -> Result<Vec<(Uuid, String)>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, file_hash as "file_hash!"
+            FROM documents
+            WHERE user_id = $1 AND file_size_bytes = $2 AND file_hash IS NOT NULL AND deleted_at IS NOT NULL
+            "#,
+            user_id,
+            size
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(rows.into_iter().map(|row| (row.id, row.file_hash)).collect())
+    }
+    
+    /// Those of `doc_ids` that are soft-deleted, most recently deleted first
+    pub async fn get_trashed_documents(&self, doc_ids: &[Uuid]) -> Result<Vec<Document>, sqlx::Error> {
+        let mut docs = sqlx::query_as!(
+            Document,
+            r#"
+            SELECT
+                id, user_id, workspace_id, title, content, summary,
+                file_path, file_name, file_size_bytes, file_type, mime_type,
+                status as "status!: DocumentStatus",
+                processing_error, created_at, updated_at, deleted_at,
+                page_count, parent_document_id, is_pinned, pinned_order, external_file,
+                (manually_unread OR last_opened_at IS NULL) as "is_unread!"
+            FROM documents
+            WHERE id = ANY($1) AND deleted_at IS NOT NULL
+            ORDER BY deleted_at DESC
+            "#,
+            doc_ids
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        inflate_content(&self.pool, &mut docs).await?;
+        Ok(docs)
+    }
+    
+    /// Bring a soft-deleted document back as it was, tags, notes and
+    /// reading position included; false if it wasn't deleted
+    pub async fn restore_document(&self, doc_id: Uuid) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let restored = sqlx::query!(
+            r#"
+            UPDATE documents SET deleted_at = NULL, updated_at = NOW()
+            WHERE id = $1 AND deleted_at IS NOT NULL
+            RETURNING status as "status!: DocumentStatus", content, content_zstd
+            "#,
+            doc_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(restored) = restored else {
+            return Ok(false);
+        };
+        
+        // Trashing dropped the document's terms, so they count again
+        if restored.status == DocumentStatus::Completed {
+            let content = text_compression::stored_text(restored.content, restored.content_zstd.as_deref())?;
+            let terms = keywords::term_counts(&content.unwrap_or_default());
+            insert_terms(&mut tx, doc_id, &terms).await?;
+        }
+        tx.commit().await?;
+        
+        self.changes.publish(doc_id, &["deleted_at", "terms"], None);
+        Ok(true)
+    }
+    
+    /// Record where a queued upload was stored and what the copy turned out to be
+    pub async fn record_stored_file(
         &self,
         doc_id: Uuid,
-        content: String,
-        summary: String,
+        file_path: &str,
+        mime_type: &str,
+        file_hash: &str,
     ) -> Result<(), sqlx::Error> {
         sqlx::query!(
             r#"
             UPDATE documents
-            SET content = $2, summary = $3, status = 'completed', updated_at = NOW()
+            SET file_path = $2, mime_type = $3, file_hash = $4, updated_at = NOW()
             WHERE id = $1
             "#,
             doc_id,
-            content,
-            summary
+            file_path,
+            mime_type,
+            file_hash
         )
         .execute(&self.pool)
         .await?;
         
+        self.changes.publish(doc_id, &["file_path", "mime_type", "file_hash"], None);
         Ok(())
     }
     
-    pub async fn get_documents_by_user(&self, user_id: Uuid) -> Result<Vec<Document>, sqlx::Error> {
-        let docs = sqlx::query_as!(
-            Document,
+    /// Add the virus scan an upload passed to its provenance
+    pub async fn record_scan(&self, doc_id: Uuid, scan: &ScanRecord) -> Result<(), sqlx::Error> {
+        sqlx::query!(
             r#"
-            SELECT 
-                id, user_id, workspace_id, title, content, summary,
-                file_path, file_name, file_size_bytes, file_type, mime_type,
-                status as "status!: DocumentStatus",
-                processing_error, created_at, updated_at, deleted_at
+            UPDATE documents
+            SET provenance = jsonb_set(provenance, '{scan}', $2)
+            WHERE id = $1 AND provenance IS NOT NULL
+            "#,
+            doc_id,
+            Json(scan) as _
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Hashes of the files of a document's live children, such as its attachments
+    pub async fn child_file_hashes(&self, parent_id: Uuid) -> Result<Vec<String>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT file_hash as "file_hash!"
+            FROM documents
+            WHERE parent_document_id = $1 AND deleted_at IS NULL AND file_hash IS NOT NULL
+            "#,
+            parent_id
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+    
+    /// SHA-256 recorded for a document's file, if it has been hashed
+    pub async fn file_hash(&self, doc_id: Uuid) -> Result<Option<String>, sqlx::Error> {
+        let hash = sqlx::query_scalar!("SELECT file_hash FROM documents WHERE id = $1", doc_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(hash.flatten())
+    }
+    
+    /// Point an external document at its file's new location
+    ///
+    /// A document flagged MissingFile is Completed again; returns the status
+    /// afterwards, or None when the document isn't external or is gone.
+    pub async fn relink_external_file(
+        &self,
+        doc_id: Uuid,
+        file_path: &str,
+    ) -> Result<Option<DocumentStatus>, sqlx::Error> {
+        let status = sqlx::query_scalar!(
+            r#"
+            UPDATE documents
+            SET file_path = $2,
+                status = CASE WHEN status = 'missing_file' THEN 'completed' ELSE status END,
+                processing_error = CASE WHEN status = 'missing_file' THEN NULL ELSE processing_error END,
+                updated_at = NOW()
+            WHERE id = $1 AND external_file AND deleted_at IS NULL
+            RETURNING status as "status!: DocumentStatus"
+            "#,
+            doc_id,
+            file_path
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        if let Some(status) = status {
+            self.changes.publish(doc_id, &["file_path", "status", "processing_error"], Some(status));
+        }
+        Ok(status)
+    }
+    
+    /// Point a document imported by reference at its copy in the storage
+    /// root; from then on the copy is the app's and counts against the quota
+    ///
+    /// Returns false when the document isn't external any more or is gone.
+    pub async fn convert_to_managed(&self, doc_id: Uuid, file_path: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE documents
+            SET file_path = $2, external_file = FALSE, updated_at = NOW()
+            WHERE id = $1 AND external_file AND deleted_at IS NULL
+            "#,
+            doc_id,
+            file_path
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        let converted = result.rows_affected() > 0;
+        if converted {
+            self.changes.publish(doc_id, &["file_path", "external_file"], None);
+        }
+        Ok(converted)
+    }
+    
+    /// Remove a document row outright, e.g. when its upload never stored a file
+    pub async fn discard_upload(&self, doc_id: Uuid) -> Result<(), sqlx::Error> {
+        let result = sqlx::query!(
+            "DELETE FROM documents WHERE id = $1 AND status IN ('queued', 'uploading')",
+            doc_id
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        if result.rows_affected() > 0 {
+            self.quick_index.remove(doc_id);
+            self.changes.publish(doc_id, &["deleted"], None);
+        }
+        Ok(())
+    }
+    
+    /// Count a document being opened, for never-opened cleanup suggestions
+    ///
+    /// Opening reads the document, so it also clears a manual unread mark.
+    pub async fn record_open(&self, doc_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE documents
+            SET open_count = open_count + 1, last_opened_at = NOW(), manually_unread = FALSE
+            WHERE id = $1
+            "#,
+            doc_id
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        self.changes.publish(doc_id, &["open_count", "last_opened_at", "is_unread"], None);
+        Ok(())
+    }
+    
+    /// Mark one of a user's documents read or unread; returns whether it exists
+    ///
+    /// Unread is kept in manually_unread until the next open. Marking a
+    /// never-opened document read sets last_opened_at without counting an
+    /// open, so it still shows up in never-opened cleanup suggestions.
+    pub async fn set_unread(&self, user_id: Uuid, doc_id: Uuid, unread: bool) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE documents
+            SET manually_unread = $3,
+                last_opened_at = CASE WHEN $3 THEN last_opened_at ELSE COALESCE(last_opened_at, NOW()) END
+            WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL
+            "#,
+            doc_id,
+            user_id,
+            unread
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        let found = result.rows_affected() > 0;
+        if found {
+            self.changes.publish(doc_id, &["is_unread"], None);
+        }
+        Ok(found)
+    }
+    
+    /// Status of those of `ids` that belong to the user, deleted or not;
+    /// other ids are left out
+    pub async fn statuses(&self, user_id: Uuid, ids: &[Uuid]) -> Result<Vec<DocumentStatusEntry>, sqlx::Error> {
+        sqlx::query_as!(
+            DocumentStatusEntry,
+            r#"
+            SELECT id as document_id, status as "status!: DocumentStatus", updated_at, version,
+                deleted_at IS NOT NULL as "deleted!"
+            FROM documents
+            WHERE id = ANY($2) AND user_id = $1
+            "#,
+            user_id,
+            ids
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+    
+    /// A user's documents updated after `since`, oldest first, at most `limit`
+    pub async fn changed_since(
+        &self,
+        user_id: Uuid,
+        since: chrono::DateTime<chrono::Utc>,
+        limit: i64,
+    ) -> Result<Vec<DocumentStatusEntry>, sqlx::Error> {
+        sqlx::query_as!(
+            DocumentStatusEntry,
+            r#"
+            SELECT id as document_id, status as "status!: DocumentStatus", updated_at, version,
+                deleted_at IS NOT NULL as "deleted!"
+            FROM documents
+            WHERE user_id = $1 AND updated_at > $2
+            ORDER BY updated_at, id
+            LIMIT $3
+            "#,
+            user_id,
+            since,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+    
+    /// Live documents of a user that are unread
+    pub async fn unread_count(&self, user_id: Uuid) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) as "count!"
+            FROM documents
+            WHERE user_id = $1 AND deleted_at IS NULL AND (manually_unread OR last_opened_at IS NULL)
+            "#,
+            user_id
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+    
+    /// A user's largest stored documents, optionally only those created since a time
+    pub async fn largest_documents(
+        &self,
+        user_id: Uuid,
+        limit: i64,
+        created_since: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<Vec<CleanupCandidate>, sqlx::Error> {
+        sqlx::query_as!(
+            CleanupCandidate,
+            r#"
+            SELECT id as document_id, title, file_size_bytes as "file_size_bytes!", created_at
+            FROM documents
+            WHERE user_id = $1 AND deleted_at IS NULL AND file_size_bytes IS NOT NULL
+                AND ($3::timestamptz IS NULL OR created_at >= $3)
+            ORDER BY file_size_bytes DESC
+            LIMIT $2
+            "#,
+            user_id,
+            limit,
+            created_since
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+    
+    /// Documents created since a time and their total size, and documents
+    /// that failed since then
+    pub async fn activity_counts(
+        &self,
+        user_id: Uuid,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> Result<(i64, i64, i64), sqlx::Error> {
+        let counts = sqlx::query!(
+            r#"
+            SELECT
+                COUNT(*) FILTER (WHERE created_at >= $2) as "created!",
+                COALESCE(SUM(file_size_bytes) FILTER (WHERE created_at >= $2), 0)::BIGINT as "created_bytes!",
+                COUNT(*) FILTER (WHERE status = 'failed' AND updated_at >= $2) as "failed!"
             FROM documents
             WHERE user_id = $1 AND deleted_at IS NULL
+            "#,
+            user_id,
+            since
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        
+        Ok((counts.created, counts.created_bytes, counts.failed))
+    }
+    
+    /// Documents created since a time, newest first
+    pub async fn documents_created_since(
+        &self,
+        user_id: Uuid,
+        since: chrono::DateTime<chrono::Utc>,
+        limit: i64,
+    ) -> Result<Vec<DigestDocument>, sqlx::Error> {
+        sqlx::query_as!(
+            DigestDocument,
+            r#"
+            SELECT id as document_id, title, summary, created_at
+            FROM documents
+            WHERE user_id = $1 AND deleted_at IS NULL AND created_at >= $2
             ORDER BY created_at DESC
+            LIMIT $3
+            "#,
+            user_id,
+            since,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+    
+    /// Documents that failed since a time, most recent failure first
+    pub async fn documents_failed_since(
+        &self,
+        user_id: Uuid,
+        since: chrono::DateTime<chrono::Utc>,
+        limit: i64,
+    ) -> Result<Vec<DigestFailure>, sqlx::Error> {
+        sqlx::query_as!(
+            DigestFailure,
+            r#"
+            SELECT id as document_id, title, processing_error as error, updated_at as failed_at
+            FROM documents
+            WHERE user_id = $1 AND deleted_at IS NULL AND status = 'failed' AND updated_at >= $2
+            ORDER BY updated_at DESC
+            LIMIT $3
+            "#,
+            user_id,
+            since,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+    
+    /// Documents never opened and created before `older_than`, largest first
+    pub async fn never_opened_documents(
+        &self,
+        user_id: Uuid,
+        older_than: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<CleanupCandidate>, sqlx::Error> {
+        sqlx::query_as!(
+            CleanupCandidate,
+            r#"
+            SELECT id as document_id, title, COALESCE(file_size_bytes, 0) as "file_size_bytes!", created_at
+            FROM documents
+            WHERE user_id = $1 AND deleted_at IS NULL AND open_count = 0 AND created_at < $2
+            ORDER BY file_size_bytes DESC NULLS LAST
+            "#,
+            user_id,
+            older_than
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+    
+    /// Documents whose file hash another of the user's documents shares,
+    /// ordered by hash and then age
+    pub async fn duplicate_documents(&self, user_id: Uuid) -> Result<Vec<(String, CleanupCandidate)>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id as "id!", title as "title!", file_size_bytes as "file_size_bytes!",
+                created_at as "created_at!", file_hash as "file_hash!"
+            FROM (
+                SELECT id, title, COALESCE(file_size_bytes, 0) as file_size_bytes, created_at, file_hash,
+                    COUNT(*) OVER (PARTITION BY file_hash) as copies
+                FROM documents
+                WHERE user_id = $1 AND deleted_at IS NULL AND file_hash IS NOT NULL
+            ) d
+            WHERE copies > 1
+            ORDER BY file_hash, created_at
             "#,
             user_id
         )
         .fetch_all(&self.pool)
         .await?;
         
-        Ok(docs)
+        Ok(rows
+            .into_iter()
+            .map(|r| {
+                let candidate = CleanupCandidate {
+                    document_id: r.id,
+                    title: r.title,
+                    file_size_bytes: r.file_size_bytes,
+                    created_at: r.created_at,
+                };
+                (r.file_hash, candidate)
+            })
+            .collect())
+    }
+    
+    /// Failed documents that still reference a stored file, largest first
+    pub async fn failed_documents_with_files(&self, user_id: Uuid) -> Result<Vec<CleanupCandidate>, sqlx::Error> {
+        sqlx::query_as!(
+            CleanupCandidate,
+            r#"
+            SELECT id as document_id, title, COALESCE(file_size_bytes, 0) as "file_size_bytes!", created_at
+            FROM documents
+            WHERE user_id = $1 AND deleted_at IS NULL AND status = 'failed' AND file_path IS NOT NULL
+            ORDER BY file_size_bytes DESC NULLS LAST
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+    
+    /// Reload the quick open index from the database, returning its size
+    pub async fn rebuild_quick_index(&self) -> Result<usize, sqlx::Error> {
+        let rows = sqlx::query!(
+            "SELECT id, user_id, title, file_name FROM documents WHERE deleted_at IS NULL"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        self.quick_index
+            .replace_all(rows.into_iter().map(|r| (r.id, r.user_id, r.title, r.file_name)));
+        Ok(self.quick_index.document_count())
     }
     
-    pub async fn update_document_status(
+    pub async fn update_content_and_summary(
         &self,
         doc_id: Uuid,
-        status: DocumentStatus,
-        error: Option<String>,
-    ) -> Result<(), sqlx::Error> {
+        content: &str,
+        summary: Option<&DocumentSummary>,
+        page_count: Option<i32>,
+        warning: Option<&str>,
+    ) -> Result<(), AppError> {
+        // Only a document still being processed may complete; a concurrent
+        // cancel wins by moving it out of 'processing' first
+        // A completed document keeps non-fatal problems, like skipped pages,
+        // in processing_error
+        let reading_time = display::reading_time_minutes(content.split_whitespace().count());
+        // Stored compressed, except empty text, which stays plain so it
+        // reads as processed without content; the search vector is filled
+        // from the text here since the database can't read it compressed
+        let compressed = if content.is_empty() {
+            None
+        } else {
+            Some(text_compression::compress(content)?)
+        };
+        let result = sqlx::query!(
+            r#"
+            UPDATE documents
+            SET content = CASE WHEN $9::bytea IS NULL THEN $2 END, content_zstd = $9,
+                content_tsvector = setweight(to_tsvector('english', $2), 'B'),
+                summary = $3, summary_source = $4, page_count = $5,
+                status = 'completed', processing_error = $6, updated_at = NOW(),
+                derived_versions = derived_versions || $7::jsonb, reading_time_minutes = $8,
+                needs_extractor = FALSE
+            WHERE id = $1 AND status = 'processing'
+            "#,
+            doc_id,
+            content,
+            summary.map(|s| s.summary.as_str()),
+            summary.map(|s| s.source.as_str()),
+            page_count,
+            warning,
+            derived::stamp(DerivedTarget::Summaries),
+            reading_time as i32,
+            compressed
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        if result.rows_affected() == 0 {
+            return Err(AppError::InvalidTransition {
+                from: DocumentStatus::Processing,
+                to: DocumentStatus::Completed,
+            });
+        }
+        self.changes.publish(
+            doc_id,
+            &["content", "summary", "page_count", "processing_error", "status"],
+            Some(DocumentStatus::Completed),
+        );
+        Ok(())
+    }
+    
+    /// Replace a document's summary, e.g. after summarizing it again
+    pub async fn set_summary(&self, doc_id: Uuid, summary: &DocumentSummary) -> Result<(), sqlx::Error> {
         sqlx::query!(
             r#"
             UPDATE documents
-            SET status = $2, processing_error = $3, updated_at = NOW()
+            SET summary = $2, summary_source = $3, updated_at = NOW(),
+                derived_versions = derived_versions || $4::jsonb
             WHERE id = $1
             "#,
             doc_id,
-            status as DocumentStatus,
-            error
+            summary.summary,
+            summary.source.as_str(),
+            derived::stamp(DerivedTarget::Summaries)
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        self.changes.publish(doc_id, &["summary"], None);
+        Ok(())
+    }
+    
+    /// Stamp a document's `target` as made by the current code without
+    /// changing it, e.g. a summary the document is processed without
+    pub async fn mark_derived_current(&self, doc_id: Uuid, target: DerivedTarget) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE documents SET derived_versions = derived_versions || $2::jsonb WHERE id = $1",
+            doc_id,
+            derived::stamp(target)
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(())
+    }
+    
+    /// How many of a user's documents have `target` from before `version`
+    ///
+    /// Only completed documents count for data derived from content; the
+    /// pipeline stamps the others when it gets to them.
+    pub async fn count_outdated(&self, user_id: Uuid, target: DerivedTarget, version: i32) -> Result<i64, sqlx::Error> {
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) as "count!"
+            FROM documents
+            WHERE user_id = $1 AND deleted_at IS NULL
+                AND (NOT $4 OR status = 'completed')
+                AND COALESCE((derived_versions->>$2)::int, 0) < $3
+            "#,
+            user_id,
+            target.as_str(),
+            version,
+            target != DerivedTarget::SortKeys
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        
+        Ok(count)
+    }
+    
+    /// Ids of a user's documents with `target` from before `version`, in id
+    /// order after `after`; see `count_outdated`
+    pub async fn outdated_documents(
+        &self,
+        user_id: Uuid,
+        target: DerivedTarget,
+        version: i32,
+        after: Option<Uuid>,
+        limit: i64,
+    ) -> Result<Vec<Uuid>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT id
+            FROM documents
+            WHERE user_id = $1 AND deleted_at IS NULL
+                AND (NOT $4 OR status = 'completed')
+                AND COALESCE((derived_versions->>$2)::int, 0) < $3
+                AND ($5::uuid IS NULL OR id > $5)
+            ORDER BY id
+            LIMIT $6
+            "#,
+            user_id,
+            target.as_str(),
+            version,
+            target != DerivedTarget::SortKeys,
+            after,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+    
+    /// Replace what the extractor reported about a document's file
+    pub async fn set_extraction_metadata(
+        &self,
+        doc_id: Uuid,
+        metadata: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE documents SET metadata = $2 WHERE id = $1",
+            doc_id,
+            serde_json::Value::Object(metadata.clone())
         )
         .execute(&self.pool)
         .await?;
         
+        self.changes.publish(doc_id, &["metadata"], None);
         Ok(())
     }
+    
+    /// Form fields found when the document was last processed; None for
+    /// documents processed without form extraction, e.g. non-PDFs
+    pub async fn form_fields(&self, doc_id: Uuid) -> Result<Option<PdfForm>, sqlx::Error> {
+        let form = sqlx::query_scalar!(
+            r#"SELECT metadata->'form_fields' as "form_fields: Json<PdfForm>" FROM documents WHERE id = $1"#,
+            doc_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        Ok(form.flatten().map(|Json(form)| form))
+    }
+    
+    /// Invoice fields found when the document was last processed; None when
+    /// it was processed without `extract_fields`
+    pub async fn extracted_fields(&self, doc_id: Uuid) -> Result<Option<Vec<ExtractedField>>, sqlx::Error> {
+        let fields = sqlx::query_scalar!(
+            r#"
+            SELECT metadata->'extracted_fields' as "extracted_fields: Json<Vec<ExtractedField>>"
+            FROM documents
+            WHERE id = $1
+            "#,
+            doc_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        Ok(fields.flatten().map(|Json(fields)| fields))
+    }
+    
+    /// Remember the processing options a document was processed with
+    ///
+    /// Changing whether or in which language it is chunked makes its chunks
+    /// outdated, so the next run doesn't keep any of them.
+    pub async fn set_processing_options(&self, doc_id: Uuid, options: &ProcessingOptions) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE documents
+            SET processing_options = $2,
+                derived_versions = CASE
+                    WHEN processing_options IS NULL
+                      OR processing_options->'chunk' IS DISTINCT FROM $2::jsonb->'chunk'
+                      OR processing_options->'language_hint' IS DISTINCT FROM $2::jsonb->'language_hint'
+                    THEN derived_versions - 'chunks'
+                    ELSE derived_versions
+                END
+            WHERE id = $1
+            "#,
+            doc_id,
+            Json(options) as _
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        self.changes.publish(doc_id, &["processing_options"], None);
+        Ok(())
+    }
+    
+    /// Where a document came from, None when it was imported before
+    /// provenance was recorded
+    pub async fn get_provenance(&self, doc_id: Uuid) -> Result<Option<ProvenanceInfo>, sqlx::Error> {
+        let provenance = sqlx::query_scalar!(
+            r#"SELECT provenance as "provenance: Json<ProvenanceInfo>" FROM documents WHERE id = $1"#,
+            doc_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        Ok(provenance.flatten().map(|json| json.0))
+    }
+    
+    /// Stored processing options, None when the document never had any
+    pub async fn get_processing_options(&self, doc_id: Uuid) -> Result<Option<ProcessingOptions>, sqlx::Error> {
+        let options = sqlx::query_scalar!(
+            r#"SELECT processing_options as "processing_options: Json<ProcessingOptions>" FROM documents WHERE id = $1"#,
+            doc_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        Ok(options.flatten().map(|json| json.0))
+    }
+    
+    /// List a user's documents, optionally with pinned ones on top
+    ///
+    /// Content is left out; lists don't show it. Titles order by their sort
+    /// keys, falling back to the lowercased title for rows whose key hasn't
+    /// been built yet. With `buckets`, documents are ordered by date group
+    /// first so each group is one run; pinning and `sort` then apply within
+    /// a group.
+    pub async fn get_documents_by_user(
+        &self,
+        user_id: Uuid,
+        pinned_first: bool,
+        sort: DocumentSort,
+        unread_only: bool,
+        buckets: Option<&BucketBounds>,
+        with_content: bool,
+    ) -> Result<Vec<Document>, sqlx::Error> {
+        let by_title = sort == DocumentSort::Title;
+        let mut docs = sqlx::query_as!(
+            Document,
+            r#"
+            SELECT 
+                id, user_id, workspace_id, title, CASE WHEN $8 THEN content END as content, summary,
+                file_path, file_name, file_size_bytes, file_type, mime_type,
+                status as "status!: DocumentStatus",
+                processing_error, created_at, updated_at, deleted_at,
+                page_count, parent_document_id, is_pinned, pinned_order, external_file,
+                (manually_unread OR last_opened_at IS NULL) as "is_unread!"
+            FROM documents
+            WHERE user_id = $1 AND deleted_at IS NULL
+              AND (NOT $4 OR manually_unread OR last_opened_at IS NULL)
+            ORDER BY
+                CASE
+                    WHEN $5::timestamptz IS NULL THEN 0
+                    WHEN created_at >= $5 THEN 0
+                    WHEN created_at >= $6::timestamptz THEN 1
+                    WHEN created_at >= $7::timestamptz THEN 2
+                    ELSE 3
+                END,
+                ($2 AND is_pinned) DESC,
+                CASE WHEN $2 THEN pinned_order END,
+                (CASE WHEN $3 THEN COALESCE(title_sort, LOWER(title)) END) COLLATE "C",
+                CASE WHEN $3 THEN title END,
+                created_at DESC
+            "#,
+            user_id,
+            pinned_first,
+            by_title,
+            unread_only,
+            buckets.map(|b| b.today),
+            buckets.map(|b| b.yesterday),
+            buckets.map(|b| b.week),
+            with_content
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        if with_content {
+            inflate_content(&self.pool, &mut docs).await?;
+        }
+        Ok(docs)
+    }
+    
+    /// How many documents get_documents_by_user lists in each date group,
+    /// empty groups included
+    pub async fn counts_by_bucket(
+        &self,
+        user_id: Uuid,
+        unread_only: bool,
+        buckets: &BucketBounds,
+    ) -> Result<Vec<BucketCount>, sqlx::Error> {
+        let counts = sqlx::query!(
+            r#"
+            SELECT
+                COUNT(*) FILTER (WHERE created_at >= $3) as "today!",
+                COUNT(*) FILTER (WHERE created_at < $3 AND created_at >= $4) as "yesterday!",
+                COUNT(*) FILTER (WHERE created_at < $4 AND created_at >= $5) as "this_week!",
+                COUNT(*) FILTER (WHERE created_at < $5) as "earlier!"
+            FROM documents
+            WHERE user_id = $1 AND deleted_at IS NULL
+              AND (NOT $2 OR manually_unread OR last_opened_at IS NULL)
+            "#,
+            user_id,
+            unread_only,
+            buckets.today,
+            buckets.yesterday,
+            buckets.week
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        
+        Ok(vec![
+            BucketCount { bucket: DateBucket::Today, count: counts.today },
+            BucketCount { bucket: DateBucket::Yesterday, count: counts.yesterday },
+            BucketCount { bucket: DateBucket::ThisWeek, count: counts.this_week },
+            BucketCount { bucket: DateBucket::Earlier, count: counts.earlier },
+        ])
+    }
+    
+    /// Every document that hasn't been soft-deleted, across all users
+    pub async fn get_all_documents(&self) -> Result<Vec<Document>, sqlx::Error> {
+        let mut docs = sqlx::query_as!(
+            Document,
+            r#"
+            SELECT
+                id, user_id, workspace_id, title, content, summary,
+                file_path, file_name, file_size_bytes, file_type, mime_type,
+                status as "status!: DocumentStatus",
+                processing_error, created_at, updated_at, deleted_at,
+                page_count, parent_document_id, is_pinned, pinned_order, external_file,
+                (manually_unread OR last_opened_at IS NULL) as "is_unread!"
+            FROM documents
+            WHERE deleted_at IS NULL
+            ORDER BY created_at
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        inflate_content(&self.pool, &mut docs).await?;
+        Ok(docs)
+    }
+    
+    /// Every document that hasn't been soft-deleted, across all users,
+    /// newest first and without content
+    pub async fn list_all_documents(&self) -> Result<Vec<Document>, sqlx::Error> {
+        sqlx::query_as!(
+            Document,
+            r#"
+            SELECT
+                id, user_id, workspace_id, title, NULL::text as content, summary,
+                file_path, file_name, file_size_bytes, file_type, mime_type,
+                status as "status!: DocumentStatus",
+                processing_error, created_at, updated_at, deleted_at,
+                page_count, parent_document_id, is_pinned, pinned_order, external_file,
+                (manually_unread OR last_opened_at IS NULL) as "is_unread!"
+            FROM documents
+            WHERE deleted_at IS NULL
+            ORDER BY created_at DESC
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+    
+    /// A user's live documents matching a parsed search box query, newest first
+    pub async fn search_documents(
+        &self,
+        user_id: Uuid,
+        query: &DocumentQuery,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Document>, sqlx::Error> {
+        let patterns: Vec<String> = query.text_terms.iter().map(|t| query_parser::like_pattern(t)).collect();
+        let mut docs = sqlx::query_as!(
+            Document,
+            r#"
+            SELECT
+                d.id, d.user_id, d.workspace_id, d.title, d.content, d.summary,
+                d.file_path, d.file_name, d.file_size_bytes, d.file_type, d.mime_type,
+                d.status as "status!: DocumentStatus",
+                d.processing_error, d.created_at, d.updated_at, d.deleted_at,
+                d.page_count, d.parent_document_id, d.is_pinned, d.pinned_order, d.external_file,
+                (d.manually_unread OR d.last_opened_at IS NULL) as "is_unread!"
+            FROM documents d
+            WHERE d.user_id = $1
+              AND d.deleted_at IS NULL
+              AND (cardinality($2::text[]) = 0 OR LOWER(d.file_type) = ANY($2))
+              AND (cardinality($3::text[]) = 0 OR d.status::text = ANY($3))
+              AND (cardinality($4::text[]) = 0 OR EXISTS (
+                  SELECT 1 FROM workspaces w
+                  WHERE w.id = d.workspace_id AND LOWER(w.name) = ANY($4)
+              ))
+              AND NOT EXISTS (
+                  SELECT 1 FROM unnest($5::text[]) AS wanted(name)
+                  WHERE NOT EXISTS (
+                      SELECT 1 FROM document_tags dt
+                      JOIN tags t ON t.id = dt.tag_id
+                      WHERE dt.document_id = d.id AND LOWER(t.name) = wanted.name
+                  )
+              )
+              AND ($6::timestamptz IS NULL OR d.created_at >= $6)
+              AND ($7::timestamptz IS NULL OR d.created_at < $7)
+              AND ($8::bigint IS NULL OR d.file_size_bytes > $8)
+              AND ($9::bigint IS NULL OR d.file_size_bytes < $9)
+              AND NOT EXISTS (
+                  SELECT 1 FROM unnest($10::text[], $15::text[]) AS term(pattern, words)
+                  WHERE NOT (
+                      d.title ILIKE term.pattern
+                      OR COALESCE(d.file_name, '') ILIKE term.pattern
+                      OR d.content_tsvector @@ phraseto_tsquery('english', term.words)
+                  )
+              )
+              AND (cardinality($13::text[]) = 0 OR COALESCE(d.provenance->>'method', 'unknown') = ANY($13))
+              AND NOT EXISTS (
+                  SELECT 1 FROM unnest($14::text[]) AS wanted(name)
+                  WHERE NOT EXISTS (
+                      SELECT 1 FROM document_tags dt
+                      WHERE dt.document_id = d.id AND tag_within(dt.tag_id, wanted.name)
+                  )
+              )
+            ORDER BY d.created_at DESC
+            LIMIT $11 OFFSET $12
+            "#,
+            user_id,
+            &query.file_types,
+            &query.statuses,
+            &query.workspaces,
+            &query.tags,
+            query.created_after,
+            query.created_before,
+            query.larger_than_bytes,
+            query.smaller_than_bytes,
+            &patterns,
+            limit,
+            offset,
+            &query.sources,
+            &query.tag_trees,
+            &query.text_terms
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        inflate_content(&self.pool, &mut docs).await?;
+        Ok(docs)
+    }
+    
+    /// The first `per_document` pages of each document containing any of
+    /// `terms`, case-insensitively, with a snippet around the page's first match
+    ///
+    /// Pages are stored as cleaned, with their offsets into the cleaned
+    /// content, so offsets point into the content search matched against.
+    /// Plain pages are filtered in the database; compressed ones are read
+    /// in page order and decompressed until a document has its pages.
+    pub async fn matching_pages(
+        &self,
+        doc_ids: &[Uuid],
+        terms: &[String],
+        per_document: i64,
+    ) -> Result<Vec<(Uuid, SearchPageMatch)>, sqlx::Error> {
+        if doc_ids.is_empty() || terms.is_empty() {
+            return Ok(Vec::new());
+        }
+        let rows = sqlx::query!(
+            r#"
+            SELECT p.document_id, p.page_number, p.start_offset, p.content, p.content_zstd
+            FROM document_pages p
+            WHERE p.document_id = ANY($1)
+              AND (p.content IS NULL OR EXISTS (
+                  SELECT 1 FROM unnest($2::text[]) AS term
+                  WHERE strpos(LOWER(p.content), LOWER(term)) > 0
+              ))
+            ORDER BY p.document_id, p.page_number
+            "#,
+            doc_ids,
+            terms
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        let mut found: HashMap<Uuid, i64> = HashMap::new();
+        let mut matches = Vec::new();
+        for row in rows {
+            let count = found.entry(row.document_id).or_default();
+            if *count >= per_document {
+                continue;
+            }
+            let Some(text) = text_compression::stored_text(row.content, row.content_zstd.as_deref())? else {
+                continue;
+            };
+            let Some((position, snippet)) = text_search::first_match(&text, terms) else {
+                continue;
+            };
+            *count += 1;
+            let page = SearchPageMatch {
+                page_number: row.page_number,
+                char_offset: row.start_offset + position as i32,
+                snippet,
+            };
+            matches.push((row.document_id, page));
+        }
+        Ok(matches)
+    }
+    
+    /// A page of search matches for export, newest first, after the match
+    /// created at `after` with that id
+    ///
+    /// Uses the same filters as `search_documents` but reads only the fields
+    /// an export needs; the snippet is cut from plain content in the
+    /// database, and from compressed content once it is decompressed.
+    pub async fn search_export_page(
+        &self,
+        user_id: Uuid,
+        query: &DocumentQuery,
+        after: Option<(chrono::DateTime<chrono::Utc>, Uuid)>,
+        limit: i64,
+    ) -> Result<Vec<SearchExportRow>, sqlx::Error> {
+        let patterns: Vec<String> = query.text_terms.iter().map(|t| query_parser::like_pattern(t)).collect();
+        let snippet_term = query.text_terms.first().map(String::as_str);
+        let (after_created, after_id) = after.unzip();
+        let mut rows = sqlx::query_as!(
+            SearchExportRow,
+            r#"
+            SELECT
+                d.id, d.title, d.file_name, d.created_at, w.name as "workspace?",
+                (
+                    SELECT string_agg(t.name, ';' ORDER BY t.name)
+                    FROM document_tags dt
+                    JOIN tags t ON t.id = dt.tag_id
+                    WHERE dt.document_id = d.id
+                ) as tags,
+                CASE WHEN strpos(LOWER(d.content), LOWER($11)) > 0 THEN
+                    substr(d.content, GREATEST(strpos(LOWER(d.content), LOWER($11)) - 80, 1), 240)
+                END as snippet
+            FROM documents d
+            LEFT JOIN workspaces w ON w.id = d.workspace_id
+            WHERE d.user_id = $1
+              AND d.deleted_at IS NULL
+              AND (cardinality($2::text[]) = 0 OR LOWER(d.file_type) = ANY($2))
+              AND (cardinality($3::text[]) = 0 OR d.status::text = ANY($3))
+              AND (cardinality($4::text[]) = 0 OR LOWER(w.name) = ANY($4))
+              AND NOT EXISTS (
+                  SELECT 1 FROM unnest($5::text[]) AS wanted(name)
+                  WHERE NOT EXISTS (
+                      SELECT 1 FROM document_tags dt
+                      JOIN tags t ON t.id = dt.tag_id
+                      WHERE dt.document_id = d.id AND LOWER(t.name) = wanted.name
+                  )
+              )
+              AND ($6::timestamptz IS NULL OR d.created_at >= $6)
+              AND ($7::timestamptz IS NULL OR d.created_at < $7)
+              AND ($8::bigint IS NULL OR d.file_size_bytes > $8)
+              AND ($9::bigint IS NULL OR d.file_size_bytes < $9)
+              AND NOT EXISTS (
+                  SELECT 1 FROM unnest($10::text[], $17::text[]) AS term(pattern, words)
+                  WHERE NOT (
+                      d.title ILIKE term.pattern
+                      OR COALESCE(d.file_name, '') ILIKE term.pattern
+                      OR d.content_tsvector @@ phraseto_tsquery('english', term.words)
+                  )
+              )
+              AND ($12::timestamptz IS NULL OR (d.created_at, d.id) < ($12, $13::uuid))
+              AND (cardinality($15::text[]) = 0 OR COALESCE(d.provenance->>'method', 'unknown') = ANY($15))
+              AND NOT EXISTS (
+                  SELECT 1 FROM unnest($16::text[]) AS wanted(name)
+                  WHERE NOT EXISTS (
+                      SELECT 1 FROM document_tags dt
+                      WHERE dt.document_id = d.id AND tag_within(dt.tag_id, wanted.name)
+                  )
+              )
+            ORDER BY d.created_at DESC, d.id DESC
+            LIMIT $14
+            "#,
+            user_id,
+            &query.file_types,
+            &query.statuses,
+            &query.workspaces,
+            &query.tags,
+            query.created_after,
+            query.created_before,
+            query.larger_than_bytes,
+            query.smaller_than_bytes,
+            &patterns,
+            snippet_term,
+            after_created,
+            after_id,
+            limit,
+            &query.sources,
+            &query.tag_trees,
+            &query.text_terms
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        // Compressed content is cut here instead, a page of rows at a time
+        if snippet_term.is_some() {
+            let ids: Vec<Uuid> = rows.iter().filter(|row| row.snippet.is_none()).map(|row| row.id).collect();
+            let mut snippets: HashMap<Uuid, String> = compressed_content(&self.pool, &ids)
+                .await?
+                .into_iter()
+                .filter_map(|(id, content)| {
+                    let (_, snippet) = text_search::first_match(&content, &query.text_terms[..1])?;
+                    Some((id, snippet))
+                })
+                .collect();
+            for row in &mut rows {
+                if row.snippet.is_none() {
+                    row.snippet = snippets.remove(&row.id);
+                }
+            }
+        }
+        Ok(rows)
+    }
+    
+    pub async fn get_document(&self, doc_id: Uuid) -> Result<Option<Document>, sqlx::Error> {
+        let mut doc = sqlx::query_as!(
+            Document,
+            r#"
+            SELECT
+                id, user_id, workspace_id, title, content, summary,
+                file_path, file_name, file_size_bytes, file_type, mime_type,
+                status as "status!: DocumentStatus",
+                processing_error, created_at, updated_at, deleted_at,
+                page_count, parent_document_id, is_pinned, pinned_order, external_file,
+                (manually_unread OR last_opened_at IS NULL) as "is_unread!"
+            FROM documents
+            WHERE id = $1 AND deleted_at IS NULL
+            "#,
+            doc_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if let Some(doc) = doc.as_mut() {
+            inflate_content(&self.pool, std::slice::from_mut(doc)).await?;
+        }
+        Ok(doc)
+    }
+
+    /// Complete a document no extractor supports, without content, and flag
+    /// it for processing once one does
+    pub async fn complete_without_extractor(&self, doc_id: Uuid) -> Result<(), AppError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE documents
+            SET status = 'completed', processing_error = NULL, needs_extractor = TRUE, updated_at = NOW()
+            WHERE id = $1 AND status = 'processing'
+            "#,
+            doc_id
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        if result.rows_affected() == 0 {
+            return Err(AppError::InvalidTransition {
+                from: DocumentStatus::Processing,
+                to: DocumentStatus::Completed,
+            });
+        }
+        self.changes.publish(doc_id, &["status", "processing_error"], Some(DocumentStatus::Completed));
+        Ok(())
+    }
+    
+    /// Set the error of a completed document, e.g. when some of what
+    /// processing derived from its content couldn't be stored
+    pub async fn record_processing_error(&self, doc_id: Uuid, error: &str) -> Result<(), sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE documents
+            SET processing_error = $2, updated_at = NOW()
+            WHERE id = $1 AND status = 'completed'
+            "#,
+            doc_id,
+            error
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        if result.rows_affected() > 0 {
+            self.changes.publish(doc_id, &["processing_error"], None);
+        }
+        Ok(())
+    }
+    
+    /// Completed documents still waiting for an extractor, of one user or
+    /// of everyone: (id, file path, MIME type, external)
+    pub async fn documents_needing_extractor(
+        &self,
+        user_id: Option<Uuid>,
+    ) -> Result<Vec<(Uuid, Option<String>, Option<String>, bool)>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, file_path, mime_type, external_file
+            FROM documents
+            WHERE needs_extractor AND status = 'completed' AND deleted_at IS NULL
+              AND ($1::uuid IS NULL OR user_id = $1)
+            ORDER BY created_at
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.id, row.file_path, row.mime_type, row.external_file))
+            .collect())
+    }
+    
+    /// Queued documents whose file is already stored, e.g. by aks-cli:
+    /// (id, file path, MIME type, external)
+    pub async fn queued_with_files(&self) -> Result<Vec<(Uuid, String, Option<String>, bool)>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, file_path as "file_path!", mime_type, external_file
+            FROM documents
+            WHERE status = 'queued' AND file_path IS NOT NULL AND deleted_at IS NULL
+            ORDER BY created_at
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.id, row.file_path, row.mime_type, row.external_file))
+            .collect())
+    }
+    
+    /// How many of a user's documents wait for an extractor, by MIME type,
+    /// most common first
+    pub async fn unprocessed_type_counts(&self, user_id: Uuid) -> Result<Vec<UnprocessedType>, sqlx::Error> {
+        sqlx::query_as!(
+            UnprocessedType,
+            r#"
+            SELECT COALESCE(mime_type, 'application/octet-stream') as "mime_type!", COUNT(*) as "count!"
+            FROM documents
+            WHERE user_id = $1 AND needs_extractor AND status = 'completed' AND deleted_at IS NULL
+            GROUP BY 1
+            ORDER BY 2 DESC, 1
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+    
+    /// Move a document from `from` to `to`, failing if it is no longer in `from`
+    ///
+    /// The expected status is part of the WHERE clause, so of two racing
+    /// transitions out of the same status exactly one succeeds.
+    pub async fn transition_status(
+        &self,
+        doc_id: Uuid,
+        from: DocumentStatus,
+        to: DocumentStatus,
+        error: Option<String>,
+    ) -> Result<(), AppError> {
+        if !from.can_transition_to(to) {
+            return Err(AppError::InvalidTransition { from, to });
+        }
+        
+        let result = sqlx::query!(
+            r#"
+            UPDATE documents
+            SET status = $3, processing_error = $4, updated_at = NOW()
+            WHERE id = $1 AND status = $2
+            "#,
+            doc_id,
+            from as DocumentStatus,
+            to as DocumentStatus,
+            error
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        if result.rows_affected() == 0 {
+            return Err(AppError::InvalidTransition { from, to });
+        }
+        self.changes.publish(doc_id, &["status", "processing_error"], Some(to));
+        Ok(())
+    }
+    
+    /// Replace the detected identifiers for a document
+    pub async fn replace_identifiers(
+        &self,
+        doc_id: Uuid,
+        identifiers: &[(IdentifierKind, String)],
+    ) -> Result<(), sqlx::Error> {
+        let kinds: Vec<String> = identifiers.iter().map(|(k, _)| k.as_str().to_string()).collect();
+        let values: Vec<String> = identifiers.iter().map(|(_, v)| v.clone()).collect();
+        
+        let mut tx = self.pool.begin().await?;
+        
+        sqlx::query!("DELETE FROM document_identifiers WHERE document_id = $1", doc_id)
+            .execute(&mut *tx)
+            .await?;
+        
+        sqlx::query!(
+            r#"
+            INSERT INTO document_identifiers (document_id, kind, value)
+            SELECT $1, * FROM UNNEST($2::text[], $3::text[])
+            ON CONFLICT DO NOTHING
+            "#,
+            doc_id,
+            &kinds,
+            &values
+        )
+        .execute(&mut *tx)
+        .await?;
+        
+        tx.commit().await?;
+        self.changes.publish(doc_id, &["identifiers"], None);
+        Ok(())
+    }
+    
+    pub async fn get_identifiers(&self, doc_id: Uuid) -> Result<Vec<DocumentIdentifier>, sqlx::Error> {
+        let identifiers = sqlx::query_as!(
+            DocumentIdentifier,
+            r#"
+            SELECT kind, value
+            FROM document_identifiers
+            WHERE document_id = $1
+            ORDER BY kind, value
+            "#,
+            doc_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(identifiers)
+    }
+    
+    pub async fn find_documents_by_identifier(
+        &self,
+        user_id: Uuid,
+        kind: IdentifierKind,
+        value: &str,
+    ) -> Result<Vec<Document>, sqlx::Error> {
+        let mut docs = sqlx::query_as!(
+            Document,
+            r#"
+            SELECT
+                d.id, d.user_id, d.workspace_id, d.title, d.content, d.summary,
+                d.file_path, d.file_name, d.file_size_bytes, d.file_type, d.mime_type,
+                d.status as "status!: DocumentStatus",
+                d.processing_error, d.created_at, d.updated_at, d.deleted_at,
+                d.page_count, d.parent_document_id, d.is_pinned, d.pinned_order, d.external_file,
+                (d.manually_unread OR d.last_opened_at IS NULL) as "is_unread!"
+            FROM documents d
+            JOIN document_identifiers i ON i.document_id = d.id
+            WHERE d.user_id = $1 AND d.deleted_at IS NULL
+                AND i.kind = $2 AND i.value = $3
+            ORDER BY d.created_at DESC
+            "#,
+            user_id,
+            kind.as_str(),
+            value
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        inflate_content(&self.pool, &mut docs).await?;
+        Ok(docs)
+    }
+    
+    /// Replace the stored per-page text for a document
+    ///
+    /// Offsets assume the content is the pages joined with a newline after each.
+    pub async fn replace_pages(&self, doc_id: Uuid, pages: &[String]) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        
+        sqlx::query!("DELETE FROM document_pages WHERE document_id = $1", doc_id)
+            .execute(&mut *tx)
+            .await?;
+        insert_pages(&mut tx, doc_id, pages).await?;
+        
+        tx.commit().await?;
+        self.changes.publish(doc_id, &["pages"], None);
+        Ok(())
+    }
+    
+    /// Pages of a document's last extraction whose chunks can be kept, by
+    /// the hash of their text
+    ///
+    /// Empty when the stored chunks are outdated, e.g. made before chunking
+    /// went page by page or with other processing options. A text on
+    /// several pages maps to the first of them.
+    pub async fn reusable_chunk_pages(&self, doc_id: Uuid) -> Result<HashMap<String, i32>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT p.content_hash as "content_hash!", MIN(p.page_number) as "page_number!"
+            FROM document_pages p
+            JOIN documents d ON d.id = p.document_id
+            WHERE p.document_id = $1 AND p.content_hash IS NOT NULL
+              AND COALESCE((d.derived_versions->>$2)::int, 0) = $3
+            GROUP BY p.content_hash
+            "#,
+            doc_id,
+            DerivedTarget::Chunks.as_str(),
+            derived::current_version(DerivedTarget::Chunks)
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(rows.into_iter().map(|r| (r.content_hash, r.page_number)).collect())
+    }
+    
+    /// Replace a document's pages and chunks, keeping the stored chunks of
+    /// reused pages along with the model they were embedded with
+    ///
+    /// `plan` has an entry per page and `trailing` holds the chunks of the
+    /// text after the pages. Chunks are renumbered in page order.
+    pub async fn replace_pages_and_chunks(
+        &self,
+        doc_id: Uuid,
+        pages: &[String],
+        plan: &[PageChunks],
+        trailing: &[TextChunk],
+    ) -> Result<(), sqlx::Error> {
+        let (_, offsets) = page_offsets(pages);
+        let from_pages: Vec<i32> = plan
+            .iter()
+            .filter_map(|page| match page {
+                PageChunks::Reused { from_page } => Some(*from_page),
+                PageChunks::Chunked(_) => None,
+            })
+            .collect();
+        
+        let mut tx = self.pool.begin().await?;
+        
+        let kept = sqlx::query!(
+            r#"
+            SELECT c.page_number as "page_number!", c.content, c.token_count, c.embedding_model,
+                c.start_offset - p.start_offset as "page_offset!"
+            FROM document_chunks c
+            JOIN document_pages p ON p.document_id = c.document_id AND p.page_number = c.page_number
+            WHERE c.document_id = $1 AND c.page_number = ANY($2)
+            ORDER BY c.chunk_index
+            "#,
+            doc_id,
+            &from_pages
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        let mut kept_by_page: HashMap<i32, Vec<_>> = HashMap::new();
+        for row in kept {
+            kept_by_page.entry(row.page_number).or_default().push(row);
+        }
+        
+        let mut chunks = Vec::new();
+        let mut models = Vec::new();
+        for ((index, page), page_start) in plan.iter().enumerate().zip(&offsets) {
+            match page {
+                PageChunks::Reused { from_page } => {
+                    for row in kept_by_page.get(from_page).into_iter().flatten() {
+                        chunks.push(TextChunk {
+                            start_offset: (page_start + row.page_offset) as usize,
+                            content: row.content.clone(),
+                            token_count: row.token_count as usize,
+                            page_number: Some(index as i32 + 1),
+                        });
+                        models.push(row.embedding_model.clone());
+                    }
+                }
+                PageChunks::Chunked(page_chunks) => {
+                    chunks.extend(page_chunks.iter().cloned());
+                    models.resize(chunks.len(), None);
+                }
+            }
+        }
+        chunks.extend(trailing.iter().cloned());
+        models.resize(chunks.len(), None);
+        
+        sqlx::query!("DELETE FROM document_chunks WHERE document_id = $1", doc_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!("DELETE FROM document_pages WHERE document_id = $1", doc_id)
+            .execute(&mut *tx)
+            .await?;
+        insert_pages(&mut tx, doc_id, pages).await?;
+        insert_embedded_chunks(&mut tx, doc_id, &chunks, &models).await?;
+        stamp_derived(&mut tx, doc_id, DerivedTarget::Chunks).await?;
+        
+        tx.commit().await?;
+        self.changes.publish(doc_id, &["pages", "chunks"], None);
+        Ok(())
+    }
+    
+    /// Replace the stored chunks for a document, dropping any embeddings
+    pub async fn replace_chunks(&self, doc_id: Uuid, chunks: &[TextChunk]) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        
+        sqlx::query!("DELETE FROM document_chunks WHERE document_id = $1", doc_id)
+            .execute(&mut *tx)
+            .await?;
+        insert_chunks(&mut tx, doc_id, chunks).await?;
+        stamp_derived(&mut tx, doc_id, DerivedTarget::Chunks).await?;
+        
+        tx.commit().await?;
+        self.changes.publish(doc_id, &["chunks"], None);
+        Ok(())
+    }
+    
+    /// Replace a document's term counts
+    ///
+    /// Triggers on document_terms move the old counts out of term_stats and
+    /// the new ones in, so the aggregates never need a full recount.
+    pub async fn replace_terms(&self, doc_id: Uuid, terms: &[(String, i32)]) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        
+        sqlx::query!("DELETE FROM document_terms WHERE document_id = $1", doc_id)
+            .execute(&mut *tx)
+            .await?;
+        insert_terms(&mut tx, doc_id, terms).await?;
+        stamp_derived(&mut tx, doc_id, DerivedTarget::TextStats).await?;
+        
+        tx.commit().await?;
+        self.changes.publish(doc_id, &["terms"], None);
+        Ok(())
+    }
+    
+    /// Completed documents with content but no term counts, in id order
+    /// after `after`, with their content
+    pub async fn documents_without_terms(
+        &self,
+        after: Option<Uuid>,
+        limit: i64,
+    ) -> Result<Vec<(Uuid, String)>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT d.id, d.content, d.content_zstd
+            FROM documents d
+            WHERE d.deleted_at IS NULL AND d.status = 'completed'
+                AND (d.content <> '' OR d.content_zstd IS NOT NULL)
+                AND ($1::uuid IS NULL OR d.id > $1)
+                AND NOT EXISTS (SELECT 1 FROM document_terms t WHERE t.document_id = d.id)
+            ORDER BY d.id
+            LIMIT $2
+            "#,
+            after,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        rows.into_iter()
+            .map(|row| -> Result<_, sqlx::Error> {
+                let content = text_compression::stored_text(row.content, row.content_zstd.as_deref())?;
+                Ok((row.id, content.unwrap_or_default()))
+            })
+            .collect()
+    }
+    
+    /// Most frequent terms in a user's documents, or in a workspace's when
+    /// `workspace_id` is given
+    pub async fn top_terms(
+        &self,
+        user_id: Uuid,
+        workspace_id: Option<Uuid>,
+        limit: i64,
+    ) -> Result<Vec<TermCount>, sqlx::Error> {
+        sqlx::query_as!(
+            TermCount,
+            r#"
+            SELECT term,
+                SUM(occurrences)::BIGINT as "occurrences!",
+                SUM(document_count)::BIGINT as "document_count!"
+            FROM term_stats
+            WHERE CASE WHEN $2::uuid IS NULL THEN user_id = $1 ELSE workspace_id = $2 END
+            GROUP BY term
+            ORDER BY 2 DESC, term
+            LIMIT $3
+            "#,
+            user_id,
+            workspace_id,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+    
+    /// Use of one term per period, oldest first; periods without it are left out
+    pub async fn term_trend(
+        &self,
+        user_id: Uuid,
+        workspace_id: Option<Uuid>,
+        term: &str,
+        bucket: TrendBucket,
+    ) -> Result<Vec<TermTrendPoint>, sqlx::Error> {
+        sqlx::query_as!(
+            TermTrendPoint,
+            r#"
+            SELECT date_trunc($4, month::timestamp)::date as "period_start!",
+                SUM(occurrences)::BIGINT as "occurrences!",
+                SUM(document_count)::BIGINT as "document_count!"
+            FROM term_stats
+            WHERE term = $3
+                AND CASE WHEN $2::uuid IS NULL THEN user_id = $1 ELSE workspace_id = $2 END
+            GROUP BY 1
+            ORDER BY 1
+            "#,
+            user_id,
+            workspace_id,
+            term,
+            bucket.as_str()
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+    
+    /// A page of a library catalog: a user's live documents, or a
+    /// workspace's when one is given
+    ///
+    /// Authors are read from an `authors` list or string, or an `author`
+    /// string, and the year from `year`, in the extraction metadata.
+    pub async fn catalog_page(
+        &self,
+        user_id: Uuid,
+        workspace_id: Option<Uuid>,
+        sort: CatalogSort,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<CatalogEntry>, sqlx::Error> {
+        sqlx::query_as!(
+            CatalogEntry,
+            r#"
+            SELECT id as "id!", title as "title!", authors, year, page_count, tags, summary
+            FROM (
+                SELECT d.id, d.title, d.title_sort, d.created_at, d.page_count, d.summary,
+                    CASE jsonb_typeof(d.metadata->'authors')
+                        WHEN 'array' THEN (
+                            SELECT string_agg(author, '; ')
+                            FROM jsonb_array_elements_text(d.metadata->'authors') AS author
+                        )
+                        ELSE COALESCE(d.metadata->>'authors', d.metadata->>'author')
+                    END as authors,
+                    d.metadata->>'year' as year,
+                    (
+                        SELECT string_agg(t.name, '; ' ORDER BY t.name)
+                        FROM document_tags dt
+                        JOIN tags t ON t.id = dt.tag_id
+                        WHERE dt.document_id = d.id
+                    ) as tags
+                FROM documents d
+                WHERE d.deleted_at IS NULL
+                  AND CASE WHEN $2::uuid IS NULL THEN d.user_id = $1 ELSE d.workspace_id = $2 END
+            ) entries
+            ORDER BY
+                CASE WHEN $3 = 'authors' THEN LOWER(authors) END NULLS LAST,
+                CASE WHEN $3 = 'year' THEN year END NULLS LAST,
+                CASE WHEN $3 = 'page_count' THEN page_count END NULLS LAST,
+                CASE WHEN $3 = 'created_at' THEN created_at END,
+                COALESCE(title_sort, LOWER(title)) COLLATE "C",
+                id
+            OFFSET $4
+            LIMIT $5
+            "#,
+            user_id,
+            workspace_id,
+            sort.as_str(),
+            offset,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+    
+    /// A user's documents matching a search box query, in title order, for
+    /// a bulk rename; at most `limit`
+    ///
+    /// Authors and year are read from the extraction metadata as for the
+    /// catalog.
+    pub async fn rename_candidates(
+        &self,
+        user_id: Uuid,
+        query: &DocumentQuery,
+        limit: i64,
+    ) -> Result<Vec<RenameCandidate>, sqlx::Error> {
+        let patterns: Vec<String> = query.text_terms.iter().map(|t| query_parser::like_pattern(t)).collect();
+        sqlx::query_as!(
+            RenameCandidate,
+            r#"
+            SELECT
+                d.id, d.title,
+                CASE jsonb_typeof(d.metadata->'authors')
+                    WHEN 'array' THEN (
+                        SELECT string_agg(author, '; ')
+                        FROM jsonb_array_elements_text(d.metadata->'authors') AS author
+                    )
+                    ELSE COALESCE(d.metadata->>'authors', d.metadata->>'author')
+                END as authors,
+                d.metadata->>'year' as year,
+                d.page_count
+            FROM documents d
+            WHERE d.user_id = $1
+              AND d.deleted_at IS NULL
+              AND (cardinality($2::text[]) = 0 OR LOWER(d.file_type) = ANY($2))
+              AND (cardinality($3::text[]) = 0 OR d.status::text = ANY($3))
+              AND (cardinality($4::text[]) = 0 OR EXISTS (
+                  SELECT 1 FROM workspaces w
+                  WHERE w.id = d.workspace_id AND LOWER(w.name) = ANY($4)
+              ))
+              AND NOT EXISTS (
+                  SELECT 1 FROM unnest($5::text[]) AS wanted(name)
+                  WHERE NOT EXISTS (
+                      SELECT 1 FROM document_tags dt
+                      JOIN tags t ON t.id = dt.tag_id
+                      WHERE dt.document_id = d.id AND LOWER(t.name) = wanted.name
+                  )
+              )
+              AND ($6::timestamptz IS NULL OR d.created_at >= $6)
+              AND ($7::timestamptz IS NULL OR d.created_at < $7)
+              AND ($8::bigint IS NULL OR d.file_size_bytes > $8)
+              AND ($9::bigint IS NULL OR d.file_size_bytes < $9)
+              AND NOT EXISTS (
+                  SELECT 1 FROM unnest($10::text[], $14::text[]) AS term(pattern, words)
+                  WHERE NOT (
+                      d.title ILIKE term.pattern
+                      OR COALESCE(d.file_name, '') ILIKE term.pattern
+                      OR d.content_tsvector @@ phraseto_tsquery('english', term.words)
+                  )
+              )
+              AND (cardinality($12::text[]) = 0 OR COALESCE(d.provenance->>'method', 'unknown') = ANY($12))
+              AND NOT EXISTS (
+                  SELECT 1 FROM unnest($13::text[]) AS wanted(name)
+                  WHERE NOT EXISTS (
+                      SELECT 1 FROM document_tags dt
+                      WHERE dt.document_id = d.id AND tag_within(dt.tag_id, wanted.name)
+                  )
+              )
+            ORDER BY COALESCE(d.title_sort, LOWER(d.title)) COLLATE "C", d.id
+            LIMIT $11
+            "#,
+            user_id,
+            &query.file_types,
+            &query.statuses,
+            &query.workspaces,
+            &query.tags,
+            query.created_after,
+            query.created_before,
+            query.larger_than_bytes,
+            query.smaller_than_bytes,
+            &patterns,
+            limit,
+            &query.sources,
+            &query.tag_trees,
+            &query.text_terms
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+    
+    /// A user's most recently created completed documents with content, at
+    /// most `limit`, for evaluating the pipelines
+    pub async fn eval_samples(&self, user_id: Uuid, limit: i64) -> Result<Vec<EvalSample>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT summary, summary_source, content, content_zstd
+            FROM documents
+            WHERE user_id = $1 AND deleted_at IS NULL
+              AND status = 'completed' AND (content IS NOT NULL OR content_zstd IS NOT NULL)
+            ORDER BY created_at DESC
+            LIMIT $2
+            "#,
+            user_id,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        rows.into_iter()
+            .map(|row| -> Result<_, sqlx::Error> {
+                let content = text_compression::stored_text(row.content, row.content_zstd.as_deref())?;
+                Ok(EvalSample {
+                    summary: row.summary,
+                    summary_source: row.summary_source,
+                    content: content.unwrap_or_default(),
+                })
+            })
+            .collect()
+    }
+    
+    /// Retitle a user's documents in one transaction; each rename is
+    /// (id, title it had when planned, new title, new sort key)
+    ///
+    /// Fails with Busy, renaming nothing, when any of them was retitled or
+    /// deleted since it was planned.
+    pub async fn rename_documents(
+        &self,
+        user_id: Uuid,
+        renames: &[(Uuid, String, String, String)],
+    ) -> Result<(), AppError> {
+        let ids: Vec<Uuid> = renames.iter().map(|r| r.0).collect();
+        let old_titles: Vec<String> = renames.iter().map(|r| r.1.clone()).collect();
+        let titles: Vec<String> = renames.iter().map(|r| r.2.clone()).collect();
+        let keys: Vec<String> = renames.iter().map(|r| r.3.clone()).collect();
+        
+        let mut tx = self.pool.begin().await?;
+        let renamed = sqlx::query!(
+            r#"
+            UPDATE documents d
+            SET title = r.title, title_sort = r.key, updated_at = NOW()
+            FROM UNNEST($2::uuid[], $3::text[], $4::text[], $5::text[]) AS r(id, old_title, title, key)
+            WHERE d.id = r.id AND d.user_id = $1 AND d.deleted_at IS NULL AND d.title = r.old_title
+            RETURNING d.id, d.title, d.file_name
+            "#,
+            user_id,
+            &ids,
+            &old_titles,
+            &titles,
+            &keys
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        if renamed.len() != renames.len() {
+            return Err(AppError::Busy(
+                "Some documents were renamed or deleted meanwhile; preview the rename again".to_string(),
+            ));
+        }
+        tx.commit().await?;
+        
+        for row in renamed {
+            self.quick_index.upsert(row.id, user_id, &row.title, row.file_name.as_deref());
+            self.changes.publish(row.id, &["title"], None);
+        }
+        Ok(())
+    }
+    
+    /// Chunks and approximate tokens of a user's live documents not embedded with `model`
+    pub async fn count_unembedded_chunks(&self, user_id: Uuid, model: &str) -> Result<(i64, i64), sqlx::Error> {
+        let totals = sqlx::query!(
+            r#"
+            SELECT COUNT(*) as "chunk_count!", COALESCE(SUM(c.token_count), 0)::BIGINT as "total_tokens!"
+            FROM document_chunks c
+            JOIN documents d ON d.id = c.document_id
+            WHERE d.user_id = $1 AND d.deleted_at IS NULL
+                AND c.embedding_model IS DISTINCT FROM $2
+            "#,
+            user_id,
+            model
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        
+        Ok((totals.chunk_count, totals.total_tokens))
+    }
+    
+    /// Ids and titles of every document, including soft-deleted ones, or
+    /// only those without a sort key
+    pub async fn list_titles(&self, only_missing_sort_key: bool) -> Result<Vec<(Uuid, String)>, sqlx::Error> {
+        let rows = sqlx::query!(
+            "SELECT id, title FROM documents WHERE NOT $1 OR title_sort IS NULL",
+            only_missing_sort_key
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(rows.into_iter().map(|row| (row.id, row.title)).collect())
+    }
+    
+    /// How many of a user's documents, trashed ones included, still have
+    /// text stored plain; empty content stays plain and doesn't count
+    pub async fn count_plain_text(&self, user_id: Uuid) -> Result<i64, sqlx::Error> {
+        let count = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) as "count!"
+            FROM documents d
+            WHERE d.user_id = $1
+                AND (d.content <> '' OR EXISTS (
+                    SELECT 1 FROM document_pages p
+                    WHERE p.document_id = d.id AND p.content IS NOT NULL
+                ))
+            "#,
+            user_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        
+        Ok(count)
+    }
+    
+    /// Ids of a user's documents with text stored plain, in id order after
+    /// `after`; see `count_plain_text`
+    pub async fn documents_with_plain_text(
+        &self,
+        user_id: Uuid,
+        after: Option<Uuid>,
+        limit: i64,
+    ) -> Result<Vec<Uuid>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT d.id
+            FROM documents d
+            WHERE d.user_id = $1
+                AND (d.content <> '' OR EXISTS (
+                    SELECT 1 FROM document_pages p
+                    WHERE p.document_id = d.id AND p.content IS NOT NULL
+                ))
+                AND ($2::uuid IS NULL OR d.id > $2)
+            ORDER BY d.id
+            LIMIT $3
+            "#,
+            user_id,
+            after,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+    
+    /// Compress a document's content and pages still stored plain, adding
+    /// what was compressed to `report` once it is committed
+    ///
+    /// The document is locked while it is rewritten, so text processing
+    /// writes at the same time is compressed by whichever writes last. The
+    /// search vector is kept as it is, since the text is unchanged.
+    pub async fn compress_content(&self, doc_id: Uuid, report: &mut ContentCompressionReport) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let content = sqlx::query_scalar!("SELECT content FROM documents WHERE id = $1 FOR UPDATE", doc_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .flatten()
+            .filter(|content| !content.is_empty());
+        
+        let mut plain_bytes = 0;
+        let mut compressed_bytes = 0;
+        let compressed_document = content.is_some();
+        if let Some(content) = content {
+            let compressed = text_compression::compress(&content)?;
+            plain_bytes += content.len() as u64;
+            compressed_bytes += compressed.len() as u64;
+            // Reading time is otherwise counted from the plain content
+            let reading_time = display::reading_time_minutes(content.split_whitespace().count());
+            sqlx::query!(
+                r#"
+                UPDATE documents
+                SET content = NULL, content_zstd = $2,
+                    reading_time_minutes = COALESCE(reading_time_minutes, $3)
+                WHERE id = $1
+                "#,
+                doc_id,
+                compressed,
+                reading_time as i32
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        
+        let pages = sqlx::query!(
+            r#"
+            SELECT page_number, content as "content!"
+            FROM document_pages
+            WHERE document_id = $1 AND content IS NOT NULL
+            "#,
+            doc_id
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        let mut numbers = Vec::with_capacity(pages.len());
+        let mut compressed_pages = Vec::with_capacity(pages.len());
+        for page in &pages {
+            let compressed = text_compression::compress(&page.content)?;
+            plain_bytes += page.content.len() as u64;
+            compressed_bytes += compressed.len() as u64;
+            numbers.push(page.page_number);
+            compressed_pages.push(compressed);
+        }
+        if !pages.is_empty() {
+            sqlx::query!(
+                r#"
+                UPDATE document_pages p
+                SET content = NULL, content_zstd = page.content_zstd
+                FROM UNNEST($2::int[], $3::bytea[]) AS page(page_number, content_zstd)
+                WHERE p.document_id = $1 AND p.page_number = page.page_number
+                "#,
+                doc_id,
+                &numbers,
+                &compressed_pages
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        
+        if compressed_document {
+            report.documents_compressed += 1;
+        }
+        report.pages_compressed += pages.len();
+        report.plain_bytes += plain_bytes;
+        report.compressed_bytes += compressed_bytes;
+        Ok(())
+    }
+    
+    /// A user's completed and failed documents stored without a known type,
+    /// as (id, file path, file type, status)
+    pub async fn untyped_documents(
+        &self,
+        user_id: Uuid,
+    ) -> Result<Vec<(Uuid, Option<String>, Option<String>, DocumentStatus)>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, file_path, file_type, status as "status!: DocumentStatus"
+            FROM documents
+            WHERE user_id = $1 AND deleted_at IS NULL
+              AND status IN ('completed', 'failed')
+              AND (mime_type IS NULL OR mime_type = 'application/octet-stream')
+            ORDER BY created_at
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(rows.into_iter().map(|row| (row.id, row.file_path, row.file_type, row.status)).collect())
+    }
+    
+    /// Store a type detected again, keeping the file type when `file_type`
+    /// is None
+    ///
+    /// Returns false, changing nothing, when the document's type is no
+    /// longer unknown.
+    pub async fn set_detected_type(
+        &self,
+        doc_id: Uuid,
+        mime_type: &str,
+        file_type: Option<&str>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE documents
+            SET mime_type = $2, file_type = COALESCE($3, file_type), updated_at = NOW()
+            WHERE id = $1 AND (mime_type IS NULL OR mime_type = 'application/octet-stream')
+            "#,
+            doc_id,
+            mime_type,
+            file_type
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        let updated = result.rows_affected() > 0;
+        if updated {
+            self.changes.publish(doc_id, &["mime_type", "file_type"], None);
+        }
+        Ok(updated)
+    }
+    
+    /// Store title sort keys, `keys[i]` belonging to `ids[i]`
+    ///
+    /// Rebuilds touch every document, so they publish no per-document
+    /// changes; callers refresh lists once the rebuild returns.
+    pub async fn set_title_sort_keys(&self, ids: &[Uuid], keys: &[String]) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE documents d
+            SET title_sort = k.key, derived_versions = d.derived_versions || $3::jsonb
+            FROM UNNEST($1::uuid[], $2::text[]) AS k(id, key)
+            WHERE d.id = k.id
+            "#,
+            ids,
+            keys,
+            derived::stamp(DerivedTarget::SortKeys)
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(result.rows_affected())
+    }
+    
+    pub async fn get_pages(&self, doc_id: Uuid) -> Result<Vec<DocumentPage>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT page_number, content, content_zstd, start_offset
+            FROM document_pages
+            WHERE document_id = $1
+            ORDER BY page_number
+            "#,
+            doc_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        rows.into_iter()
+            .map(|row| -> Result<_, sqlx::Error> {
+                let content = text_compression::stored_text(row.content, row.content_zstd.as_deref())?;
+                Ok(DocumentPage {
+                    page_number: row.page_number,
+                    content: content.unwrap_or_default(),
+                    start_offset: row.start_offset,
+                })
+            })
+            .collect()
+    }
+    
+    /// Char offset at which a page starts, if the document has that page
+    pub async fn page_start_offset(&self, doc_id: Uuid, page_number: i32) -> Result<Option<i32>, sqlx::Error> {
+        sqlx::query_scalar!(
+            "SELECT start_offset FROM document_pages WHERE document_id = $1 AND page_number = $2",
+            doc_id,
+            page_number
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+    
+    pub async fn get_reading_position(
+        &self,
+        user_id: Uuid,
+        doc_id: Uuid,
+    ) -> Result<Option<StoredReadingPosition>, sqlx::Error> {
+        sqlx::query_as!(
+            StoredReadingPosition,
+            r#"
+            SELECT char_offset, page_number, percent, anchor, updated_at
+            FROM reading_positions
+            WHERE user_id = $1 AND document_id = $2
+            "#,
+            user_id,
+            doc_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+    
+    /// Save a user's position in a document, replacing the one saved before
+    pub async fn save_reading_position(
+        &self,
+        user_id: Uuid,
+        doc_id: Uuid,
+        char_offset: i32,
+        page_number: Option<i32>,
+        percent: f32,
+        anchor: &str,
+    ) -> Result<StoredReadingPosition, sqlx::Error> {
+        sqlx::query_as!(
+            StoredReadingPosition,
+            r#"
+            INSERT INTO reading_positions (user_id, document_id, char_offset, page_number, percent, anchor)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (user_id, document_id) DO UPDATE
+            SET char_offset = EXCLUDED.char_offset, page_number = EXCLUDED.page_number,
+                percent = EXCLUDED.percent, anchor = EXCLUDED.anchor, updated_at = NOW()
+            RETURNING char_offset, page_number, percent, anchor, updated_at
+            "#,
+            user_id,
+            doc_id,
+            char_offset,
+            page_number,
+            percent,
+            anchor
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+    
+    /// Every document that points at a stored file, including soft-deleted ones
+    pub async fn list_file_references(&self) -> Result<Vec<FileReference>, sqlx::Error> {
+        let refs = sqlx::query_as!(
+            FileReference,
+            r#"
+            SELECT id, user_id, file_path as "file_path!", status as "status!: DocumentStatus",
+                external_file as external
+            FROM documents
+            WHERE file_path IS NOT NULL
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(refs)
+    }
+    
+    /// Ids of all documents that have not been soft-deleted
+    pub async fn list_live_document_ids(&self) -> Result<Vec<Uuid>, sqlx::Error> {
+        sqlx::query_scalar!("SELECT id FROM documents WHERE deleted_at IS NULL")
+            .fetch_all(&self.pool)
+            .await
+    }
+    
+    /// Pin a document after the user's existing pins
+    pub async fn pin_document(&self, user_id: Uuid, doc_id: Uuid) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        lock_user_orders(&mut tx, user_id).await?;
+        
+        let doc = sqlx::query!(
+            "SELECT is_pinned, deleted_at FROM documents WHERE id = $1 AND user_id = $2",
+            doc_id,
+            user_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Document".to_string()))?;
+        
+        if doc.deleted_at.is_some() {
+            return Err(AppError::InvalidInput("Deleted documents cannot be pinned".to_string()));
+        }
+        if doc.is_pinned {
+            return Ok(());
+        }
+        
+        sqlx::query!(
+            r#"
+            UPDATE documents
+            SET is_pinned = TRUE,
+                pinned_order = (
+                    SELECT COALESCE(MAX(pinned_order) + 1, 0)
+                    FROM documents
+                    WHERE user_id = $2 AND is_pinned
+                ),
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+            doc_id,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        
+        tx.commit().await?;
+        self.changes.publish(doc_id, &["is_pinned", "pinned_order"], None);
+        Ok(())
+    }
+    
+    /// Unpin a document and close the gap it leaves in the ordering
+    pub async fn unpin_document(&self, user_id: Uuid, doc_id: Uuid) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        lock_user_orders(&mut tx, user_id).await?;
+        
+        let result = sqlx::query!(
+            r#"
+            UPDATE documents
+            SET is_pinned = FALSE, pinned_order = NULL, updated_at = NOW()
+            WHERE id = $1 AND user_id = $2
+            "#,
+            doc_id,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound("Document".to_string()));
+        }
+        
+        compact_pinned_order(&mut tx, user_id).await?;
+        tx.commit().await?;
+        self.changes.publish(doc_id, &["is_pinned", "pinned_order"], None);
+        Ok(())
+    }
+    
+    /// Rewrite the pinned order; `doc_ids` must list every pinned document exactly once
+    pub async fn reorder_pinned(&self, user_id: Uuid, doc_ids: &[Uuid]) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        lock_user_orders(&mut tx, user_id).await?;
+        
+        let mut pinned = sqlx::query_scalar!(
+            r#"
+            SELECT id FROM documents
+            WHERE user_id = $1 AND is_pinned AND deleted_at IS NULL
+            "#,
+            user_id
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        
+        let mut requested = doc_ids.to_vec();
+        pinned.sort();
+        requested.sort();
+        if pinned != requested {
+            return Err(AppError::InvalidInput(
+                "Reorder must list each pinned document exactly once".to_string(),
+            ));
+        }
+        
+        sqlx::query!(
+            r#"
+            UPDATE documents d
+            SET pinned_order = o.position - 1
+            FROM UNNEST($2::uuid[]) WITH ORDINALITY AS o(id, position)
+            WHERE d.id = o.id AND d.user_id = $1
+            "#,
+            user_id,
+            doc_ids
+        )
+        .execute(&mut *tx)
+        .await?;
+        
+        // Pinned documents that were soft-deleted keep their relative order after the rest
+        compact_pinned_order(&mut tx, user_id).await?;
+        tx.commit().await?;
+        for &doc_id in doc_ids {
+            self.changes.publish(doc_id, &["pinned_order"], None);
+        }
+        Ok(())
+    }
+    
+    /// Put a document at the end of the user's reading list, moving it
+    /// there if it is listed already
+    pub async fn add_to_reading_list(&self, user_id: Uuid, doc_id: Uuid) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        lock_user_orders(&mut tx, user_id).await?;
+        
+        let deleted_at = sqlx::query_scalar!(
+            "SELECT deleted_at FROM documents WHERE id = $1 AND user_id = $2",
+            doc_id,
+            user_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Document".to_string()))?;
+        if deleted_at.is_some() {
+            return Err(AppError::InvalidInput(
+                "Deleted documents cannot be added to the reading list".to_string(),
+            ));
+        }
+        
+        sqlx::query!(
+            r#"
+            INSERT INTO reading_list (user_id, document_id, position)
+            VALUES ($1, $2, (SELECT COALESCE(MAX(position) + 1, 0) FROM reading_list WHERE user_id = $1))
+            ON CONFLICT (user_id, document_id) DO UPDATE
+            SET position = EXCLUDED.position, added_at = NOW()
+            "#,
+            user_id,
+            doc_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        
+        compact_reading_list(&mut tx, user_id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+    
+    /// Take a document off the user's reading list, closing the gap it
+    /// leaves; returns false if it wasn't listed
+    pub async fn remove_from_reading_list(&self, user_id: Uuid, doc_id: Uuid) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        lock_user_orders(&mut tx, user_id).await?;
+        
+        let result = sqlx::query!(
+            "DELETE FROM reading_list WHERE user_id = $1 AND document_id = $2",
+            user_id,
+            doc_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+        
+        compact_reading_list(&mut tx, user_id).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+    
+    /// Rewrite the reading list order; `doc_ids` must list every listed
+    /// document exactly once
+    pub async fn reorder_reading_list(&self, user_id: Uuid, doc_ids: &[Uuid]) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        lock_user_orders(&mut tx, user_id).await?;
+        
+        let mut listed = sqlx::query_scalar!(
+            r#"
+            SELECT r.document_id FROM reading_list r
+            JOIN documents d ON d.id = r.document_id
+            WHERE r.user_id = $1 AND d.deleted_at IS NULL
+            "#,
+            user_id
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        
+        let mut requested = doc_ids.to_vec();
+        listed.sort();
+        requested.sort();
+        if listed != requested {
+            return Err(AppError::InvalidInput(
+                "Reorder must list each document on the reading list exactly once".to_string(),
+            ));
+        }
+        
+        sqlx::query!(
+            r#"
+            UPDATE reading_list r
+            SET position = o.position - 1
+            FROM UNNEST($2::uuid[]) WITH ORDINALITY AS o(id, position)
+            WHERE r.document_id = o.id AND r.user_id = $1
+            "#,
+            user_id,
+            doc_ids
+        )
+        .execute(&mut *tx)
+        .await?;
+        
+        // Listed documents that were soft-deleted keep their relative order after the rest
+        compact_reading_list(&mut tx, user_id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+    
+    /// The user's reading list in order, without content, each document
+    /// with an estimated reading time
+    ///
+    /// Soft-deleted documents are left out but stay listed, so restoring
+    /// one puts it back in its place.
+    pub async fn get_reading_list(&self, user_id: Uuid) -> Result<Vec<ReadingListEntry>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT 
+                d.id, d.user_id, d.workspace_id, d.title, d.summary,
+                d.file_path, d.file_name, d.file_size_bytes, d.file_type, d.mime_type,
+                d.status as "status!: DocumentStatus",
+                d.processing_error, d.created_at, d.updated_at, d.deleted_at,
+                d.page_count, d.parent_document_id, d.is_pinned, d.pinned_order, d.external_file,
+                (d.manually_unread OR d.last_opened_at IS NULL) as "is_unread!",
+                r.added_at, d.reading_time_minutes,
+                -- Documents processed before reading times were stored are
+                -- counted here, so the content isn't loaded
+                CASE WHEN d.reading_time_minutes IS NULL THEN
+                    COALESCE(array_length(regexp_split_to_array(NULLIF(btrim(d.content), ''), '\s+'), 1), 0)
+                END as word_count
+            FROM reading_list r
+            JOIN documents d ON d.id = r.document_id
+            WHERE r.user_id = $1 AND d.deleted_at IS NULL
+            ORDER BY r.position
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(rows
+            .into_iter()
+            .map(|row| ReadingListEntry {
+                document: Document {
+                    id: row.id,
+                    user_id: row.user_id,
+                    workspace_id: row.workspace_id,
+                    title: row.title,
+                    content: None,
+                    summary: row.summary,
+                    file_path: row.file_path,
+                    file_name: row.file_name,
+                    file_size_bytes: row.file_size_bytes,
+                    file_type: row.file_type,
+                    mime_type: row.mime_type,
+                    status: row.status,
+                    processing_error: row.processing_error,
+                    created_at: row.created_at,
+                    updated_at: row.updated_at,
+                    deleted_at: row.deleted_at,
+                    page_count: row.page_count,
+                    parent_document_id: row.parent_document_id,
+                    is_pinned: row.is_pinned,
+                    pinned_order: row.pinned_order,
+                    external_file: row.external_file,
+                    is_unread: row.is_unread,
+                },
+                added_at: row.added_at,
+                reading_time_minutes: row.reading_time_minutes.map(i64::from).unwrap_or_else(|| {
+                    display::reading_time_minutes(row.word_count.unwrap_or_default().max(0) as usize)
+                }),
+            })
+            .collect())
+    }
+    
+    /// Store sidecar notes unless the stored hash already matches
+    ///
+    /// Returns whether the notes were written.
+    pub async fn set_notes(
+        &self,
+        doc_id: Uuid,
+        content: &str,
+        source_path: &str,
+        content_hash: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE documents
+            SET notes = $2, notes_source_path = $3, notes_hash = $4, notes_updated_at = NOW()
+            WHERE id = $1 AND notes_hash IS DISTINCT FROM $4
+            "#,
+            doc_id,
+            content,
+            source_path,
+            content_hash
+        )
+        .execute(&self.pool)
+        .await?;
+        
+        let written = result.rows_affected() > 0;
+        if written {
+            self.changes.publish(doc_id, &["notes"], None);
+        }
+        Ok(written)
+    }
+    
+    pub async fn get_notes(&self, doc_id: Uuid) -> Result<Option<DocumentNote>, sqlx::Error> {
+        let note = sqlx::query_as!(
+            DocumentNote,
+            r#"
+            SELECT
+                notes as "content!", notes_source_path as source_path,
+                notes_hash as content_hash, notes_updated_at as updated_at
+            FROM documents
+            WHERE id = $1 AND notes IS NOT NULL
+            "#,
+            doc_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        Ok(note)
+    }
+}
+
+/// Fill in the content of documents selected while it is stored compressed
+///
+/// Only the documents given are read, so reading one document never
+/// decompresses another.
+pub(crate) async fn inflate_content(pool: &PgPool, docs: &mut [Document]) -> Result<(), sqlx::Error> {
+    let ids: Vec<Uuid> = docs.iter().filter(|doc| doc.content.is_none()).map(|doc| doc.id).collect();
+    let mut contents: HashMap<Uuid, String> = compressed_content(pool, &ids).await?.into_iter().collect();
+    for doc in docs.iter_mut() {
+        if let Some(content) = contents.remove(&doc.id) {
+            doc.content = Some(content);
+        }
+    }
+    Ok(())
+}
+
+/// The decompressed content of those of `doc_ids` stored compressed
+async fn compressed_content(pool: &PgPool, doc_ids: &[Uuid]) -> Result<Vec<(Uuid, String)>, sqlx::Error> {
+    if doc_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, content_zstd as "content_zstd!"
+        FROM documents
+        WHERE id = ANY($1) AND content_zstd IS NOT NULL
+        "#,
+        doc_ids
+    )
+    .fetch_all(pool)
+    .await?;
+    
+    rows.into_iter()
+        .map(|row| -> Result<_, sqlx::Error> { Ok((row.id, text_compression::decompress(&row.content_zstd)?)) })
+        .collect()
+}
+
+/// 1-based page numbers and character start offsets for pages joined with newlines
+fn page_offsets(pages: &[String]) -> (Vec<i32>, Vec<i32>) {
+    let mut numbers = Vec::with_capacity(pages.len());
+    let mut offsets = Vec::with_capacity(pages.len());
+    let mut offset = 0i32;
+    for (index, page) in pages.iter().enumerate() {
+        numbers.push(index as i32 + 1);
+        offsets.push(offset);
+        offset += page.chars().count() as i32 + 1;
+    }
+    (numbers, offsets)
+}
+
+/// Insert a document's pages, compressed, with the hash of each page's text
+pub(crate) async fn insert_pages(
+    tx: &mut Transaction<'_, Postgres>,
+    doc_id: Uuid,
+    pages: &[String],
+) -> Result<(), sqlx::Error> {
+    if pages.is_empty() {
+        return Ok(());
+    }
+    let (numbers, offsets) = page_offsets(pages);
+    let hashes: Vec<String> = pages.iter().map(|page| file_utils::sha256_text(page)).collect();
+    let compressed = pages
+        .iter()
+        .map(|page| text_compression::compress(page))
+        .collect::<Result<Vec<_>, _>>()?;
+    
+    sqlx::query!(
+        r#"
+        INSERT INTO document_pages (document_id, page_number, content_zstd, start_offset, content_hash)
+        SELECT $1, * FROM UNNEST($2::int[], $3::bytea[], $4::int[], $5::text[])
+        "#,
+        doc_id,
+        &numbers,
+        &compressed,
+        &offsets,
+        &hashes
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Insert a document's chunks, numbered from 0 in order, none of them
+/// embedded yet
+pub(crate) async fn insert_chunks(
+    tx: &mut Transaction<'_, Postgres>,
+    doc_id: Uuid,
+    chunks: &[TextChunk],
+) -> Result<(), sqlx::Error> {
+    insert_embedded_chunks(tx, doc_id, chunks, &vec![None; chunks.len()]).await
+}
+
+/// Insert a document's chunks, numbered from 0 in order, with the model
+/// each was embedded with
+async fn insert_embedded_chunks(
+    tx: &mut Transaction<'_, Postgres>,
+    doc_id: Uuid,
+    chunks: &[TextChunk],
+    models: &[Option<String>],
+) -> Result<(), sqlx::Error> {
+    if chunks.is_empty() {
+        return Ok(());
+    }
+    let indexes: Vec<i32> = (0..chunks.len() as i32).collect();
+    let contents: Vec<String> = chunks.iter().map(|c| c.content.clone()).collect();
+    let offsets: Vec<i32> = chunks.iter().map(|c| c.start_offset as i32).collect();
+    let tokens: Vec<i32> = chunks.iter().map(|c| c.token_count as i32).collect();
+    let page_numbers: Vec<Option<i32>> = chunks.iter().map(|c| c.page_number).collect();
+    
+    sqlx::query!(
+        r#"
+        INSERT INTO document_chunks
+            (document_id, chunk_index, content, start_offset, token_count, page_number, embedding_model)
+        SELECT $1, * FROM UNNEST($2::int[], $3::text[], $4::int[], $5::int[], $6::int[], $7::text[])
+        "#,
+        doc_id,
+        &indexes,
+        &contents,
+        &offsets,
+        &tokens,
+        &page_numbers as &[Option<i32>],
+        models as &[Option<String>]
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Store a document's term counts; the owner, workspace and month come
+/// from the document row
+pub(crate) async fn insert_terms(
+    tx: &mut Transaction<'_, Postgres>,
+    doc_id: Uuid,
+    terms: &[(String, i32)],
+) -> Result<(), sqlx::Error> {
+    if terms.is_empty() {
+        return Ok(());
+    }
+    let (words, occurrences): (Vec<String>, Vec<i32>) = terms.iter().cloned().unzip();
+    
+    sqlx::query!(
+        r#"
+        INSERT INTO document_terms (document_id, term, occurrences, user_id, workspace_id, month)
+        SELECT d.id, t.term, t.occurrences, d.user_id, d.workspace_id,
+            date_trunc('month', d.created_at)::date
+        FROM documents d, UNNEST($2::text[], $3::int[]) AS t(term, occurrences)
+        WHERE d.id = $1 AND d.deleted_at IS NULL
+        "#,
+        doc_id,
+        &words,
+        &occurrences
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Serialize changes to a user's pinned and reading list orders so
+/// concurrent edits can't interleave them
+async fn lock_user_orders(tx: &mut Transaction<'_, Postgres>, user_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!("SELECT id FROM users WHERE id = $1 FOR UPDATE", user_id)
+        .fetch_optional(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+/// Renumber a user's pinned documents 0..n without gaps, keeping their order
+async fn compact_pinned_order(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE documents d
+        SET pinned_order = r.position - 1
+        FROM (
+            SELECT id, ROW_NUMBER() OVER (
+                ORDER BY deleted_at IS NOT NULL, pinned_order, created_at
+            ) AS position
+            FROM documents
+            WHERE user_id = $1 AND is_pinned
+        ) r
+        WHERE d.id = r.id
+        "#,
+        user_id
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Renumber a user's reading list 0..n without gaps, keeping its order
+async fn compact_reading_list(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE reading_list l
+        SET position = o.position - 1
+        FROM (
+            SELECT r.document_id, ROW_NUMBER() OVER (
+                ORDER BY d.deleted_at IS NOT NULL, r.position, r.added_at
+            ) AS position
+            FROM reading_list r
+            JOIN documents d ON d.id = r.document_id
+            WHERE r.user_id = $1
+        ) o
+        WHERE l.user_id = $1 AND l.document_id = o.document_id
+        "#,
+        user_id
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Stamp a document's `target` as made by the current code
+pub(crate) async fn stamp_derived(
+    tx: &mut Transaction<'_, Postgres>,
+    doc_id: Uuid,
+    target: DerivedTarget,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE documents SET derived_versions = derived_versions || $2::jsonb WHERE id = $1",
+        doc_id,
+        derived::stamp(target)
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
 }
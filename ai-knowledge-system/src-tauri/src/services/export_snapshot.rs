@@ -0,0 +1,81 @@
+use crate::models::ExportSnapshot;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Records of exports and the content hash each was made from
+pub struct ExportSnapshotService {
+    pool: PgPool,
+}
+
+impl ExportSnapshotService {
+    pub fn new(pool: PgPool) -> Self {
+        ExportSnapshotService { pool }
+    }
+
+    pub async fn record(
+        &self,
+        user_id: Uuid,
+        document_id: Uuid,
+        document_title: &str,
+        content_hash: &str,
+        format: &str,
+        dest_path: &str,
+    ) -> Result<ExportSnapshot, sqlx::Error> {
+        sqlx::query_as!(
+            ExportSnapshot,
+            r#"
+            INSERT INTO export_snapshots (user_id, document_id, document_title, content_hash, format, dest_path)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, user_id, document_id, document_title, content_hash, format, dest_path, exported_at,
+                false as "document_deleted!"
+            "#,
+            user_id,
+            document_id,
+            document_title,
+            content_hash,
+            format,
+            dest_path
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// A snapshot of this user's; None for unknown ids and other users' snapshots
+    pub async fn get(&self, user_id: Uuid, snapshot_id: Uuid) -> Result<Option<ExportSnapshot>, sqlx::Error> {
+        sqlx::query_as!(
+            ExportSnapshot,
+            r#"
+            SELECT s.id, s.user_id, s.document_id, s.document_title, s.content_hash, s.format,
+                s.dest_path, s.exported_at, d.id IS NULL as "document_deleted!"
+            FROM export_snapshots s
+            LEFT JOIN documents d ON d.id = s.document_id AND d.deleted_at IS NULL
+            WHERE s.id = $1 AND s.user_id = $2
+            "#,
+            snapshot_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Snapshots of a document, newest first, including those of a deleted one
+    pub async fn list_for_document(&self, user_id: Uuid, document_id: Uuid) -> Result<Vec<ExportSnapshot>, sqlx::Error> {
+        let snapshots = sqlx::query_as!(
+            ExportSnapshot,
+            r#"
+            SELECT s.id, s.user_id, s.document_id, s.document_title, s.content_hash, s.format,
+                s.dest_path, s.exported_at, d.id IS NULL as "document_deleted!"
+            FROM export_snapshots s
+            LEFT JOIN documents d ON d.id = s.document_id AND d.deleted_at IS NULL
+            WHERE s.document_id = $1 AND s.user_id = $2
+            ORDER BY s.exported_at DESC
+            "#,
+            document_id,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(snapshots)
+    }
+}
@@ -0,0 +1,171 @@
+use crate::models::{FocusSession, FocusSessionDocument, FocusSessionSearch};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Records focus sessions and what was consulted while one was active
+///
+/// Events are only recorded while the user has an active session; with
+/// none, recording is a no-op.
+pub struct FocusSessionService {
+    pool: PgPool,
+}
+
+impl FocusSessionService {
+    pub fn new(pool: PgPool) -> Self {
+        FocusSessionService { pool }
+    }
+
+    /// Start a session, ending the user's active one first
+    pub async fn start(&self, user_id: Uuid, name: &str) -> Result<FocusSession, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            "UPDATE focus_sessions SET ended_at = NOW() WHERE user_id = $1 AND ended_at IS NULL",
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        let session = sqlx::query_as!(
+            FocusSession,
+            r#"
+            INSERT INTO focus_sessions (user_id, name)
+            VALUES ($1, $2)
+            RETURNING id, name, started_at, ended_at, auto_closed
+            "#,
+            user_id,
+            name
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(session)
+    }
+
+    /// End one of the user's sessions; None if it isn't active
+    pub async fn end(&self, user_id: Uuid, session_id: Uuid) -> Result<Option<FocusSession>, sqlx::Error> {
+        sqlx::query_as!(
+            FocusSession,
+            r#"
+            UPDATE focus_sessions
+            SET ended_at = NOW()
+            WHERE id = $1 AND user_id = $2 AND ended_at IS NULL
+            RETURNING id, name, started_at, ended_at, auto_closed
+            "#,
+            session_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    pub async fn get_session(&self, user_id: Uuid, session_id: Uuid) -> Result<Option<FocusSession>, sqlx::Error> {
+        sqlx::query_as!(
+            FocusSession,
+            r#"
+            SELECT id, name, started_at, ended_at, auto_closed
+            FROM focus_sessions
+            WHERE id = $1 AND user_id = $2
+            "#,
+            session_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    pub async fn record_document_opened(&self, user_id: Uuid, doc_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO focus_session_events (session_id, kind, document_id)
+            SELECT id, 'document_opened', $2 FROM focus_sessions
+            WHERE user_id = $1 AND ended_at IS NULL
+            "#,
+            user_id,
+            doc_id
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn record_search(&self, user_id: Uuid, query: &str, result_count: i32) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO focus_session_events (session_id, kind, query, result_count)
+            SELECT id, 'search', $2, $3 FROM focus_sessions
+            WHERE user_id = $1 AND ended_at IS NULL
+            "#,
+            user_id,
+            query,
+            result_count
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Close sessions started before `before` that are still active, i.e.
+    /// the app was closed during them; returns how many were closed
+    ///
+    /// They end at their last recorded event, or their start if they have
+    /// none, so the time the app was closed isn't counted.
+    pub async fn close_interrupted(&self, before: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE focus_sessions s
+            SET auto_closed = TRUE,
+                ended_at = COALESCE(
+                    (SELECT MAX(e.occurred_at) FROM focus_session_events e WHERE e.session_id = s.id),
+                    s.started_at
+                )
+            WHERE s.ended_at IS NULL AND s.started_at < $1
+            "#,
+            before
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Documents opened during a session with their open counts, most
+    /// opened first
+    pub async fn session_documents(&self, session_id: Uuid) -> Result<Vec<FocusSessionDocument>, sqlx::Error> {
+        sqlx::query_as!(
+            FocusSessionDocument,
+            r#"
+            SELECT e.document_id, d.title as "title?",
+                COUNT(*) as "open_count!",
+                MIN(e.occurred_at) as "first_opened_at!",
+                MAX(e.occurred_at) as "last_opened_at!"
+            FROM focus_session_events e
+            LEFT JOIN documents d ON d.id = e.document_id
+            WHERE e.session_id = $1 AND e.kind = 'document_opened'
+            GROUP BY e.document_id, d.title
+            ORDER BY COUNT(*) DESC, MIN(e.occurred_at)
+            "#,
+            session_id
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Queries searched during a session, in the order first run
+    pub async fn session_searches(&self, session_id: Uuid) -> Result<Vec<FocusSessionSearch>, sqlx::Error> {
+        sqlx::query_as!(
+            FocusSessionSearch,
+            r#"
+            SELECT query as "query!",
+                COUNT(*) as "run_count!",
+                (ARRAY_AGG(result_count ORDER BY occurred_at DESC))[1] as result_count,
+                MAX(occurred_at) as "last_run_at!"
+            FROM focus_session_events
+            WHERE session_id = $1 AND kind = 'search'
+            GROUP BY query
+            ORDER BY MIN(occurred_at)
+            "#,
+            session_id
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+}
@@ -0,0 +1,187 @@
+use crate::models::{DocumentStatus, ImportSession, UnqueuedFile};
+use chrono::{DateTime, Utc};
+use sqlx::types::Json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// A document of an import session as its report shows it
+pub struct ImportedDocument {
+    pub id: Uuid,
+    pub file_name: Option<String>,
+    pub status: DocumentStatus,
+    pub processing_error: Option<String>,
+    pub file_size_bytes: Option<i64>,
+}
+
+pub struct ImportSessionService {
+    pool: PgPool,
+}
+
+impl ImportSessionService {
+    pub fn new(pool: PgPool) -> Self {
+        ImportSessionService { pool }
+    }
+
+    pub async fn start(&self, user_id: Uuid, source_path: &str, total_files: i32) -> Result<Uuid, sqlx::Error> {
+        sqlx::query_scalar!(
+            "INSERT INTO import_sessions (user_id, source_path, total_files) VALUES ($1, $2, $3) RETURNING id",
+            user_id,
+            source_path,
+            total_files
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Count a queued document towards the session
+    pub async fn add_document(&self, session_id: Uuid, doc_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE documents SET import_session_id = $2 WHERE id = $1",
+            doc_id,
+            session_id
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Note a file that won't become a document of the session
+    pub async fn record_unqueued(&self, session_id: Uuid, file: &UnqueuedFile) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE import_sessions
+            SET unqueued_files = unqueued_files || jsonb_build_array($2::jsonb)
+            WHERE id = $1
+            "#,
+            session_id,
+            Json(file) as _
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Mark every file of the session handled, so it can finish once its
+    /// documents are done; `total_files` is how many were reached
+    pub async fn mark_queued(&self, session_id: Uuid, total_files: i32) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE import_sessions SET queued_at = NOW(), total_files = $2 WHERE id = $1",
+            session_id,
+            total_files
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// The unfinished session a document was imported by
+    ///
+    /// None once the session is finished, so reprocessing an imported
+    /// document later is reported on its own.
+    pub async fn open_session_of(&self, doc_id: Uuid) -> Result<Option<Uuid>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT s.id
+            FROM documents d
+            JOIN import_sessions s ON s.id = d.import_session_id
+            WHERE d.id = $1 AND s.finished_at IS NULL
+            "#,
+            doc_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Finish a session whose files were all queued and whose documents all
+    /// reached a final status
+    ///
+    /// Returns the session's owner when this call finished it; None when it
+    /// isn't done yet or was finished already, so only one caller reports it.
+    pub async fn try_finish(&self, session_id: Uuid) -> Result<Option<Uuid>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"
+            UPDATE import_sessions s
+            SET finished_at = NOW()
+            WHERE s.id = $1
+              AND s.finished_at IS NULL
+              AND s.queued_at IS NOT NULL
+              AND NOT EXISTS (
+                  SELECT 1 FROM documents d
+                  WHERE d.import_session_id = s.id
+                    AND d.deleted_at IS NULL
+                    AND d.status IN ('queued', 'uploading', 'processing')
+              )
+            RETURNING s.user_id
+            "#,
+            session_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Finish sessions started before `before` that never completed, e.g.
+    /// because the app was closed mid-import; returns (session, owner) pairs
+    pub async fn finish_interrupted(&self, before: DateTime<Utc>) -> Result<Vec<(Uuid, Uuid)>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            UPDATE import_sessions
+            SET finished_at = NOW(), interrupted = TRUE
+            WHERE finished_at IS NULL AND started_at < $1
+            RETURNING id, user_id
+            "#,
+            before
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| (row.id, row.user_id)).collect())
+    }
+
+    /// A user's session with the files that never became documents
+    pub async fn get_session(
+        &self,
+        user_id: Uuid,
+        session_id: Uuid,
+    ) -> Result<Option<(ImportSession, Vec<UnqueuedFile>)>, sqlx::Error> {
+        let row = sqlx::query!(
+            r#"
+            SELECT id, source_path, total_files, started_at, finished_at, interrupted,
+                unqueued_files as "unqueued_files!: Json<Vec<UnqueuedFile>>"
+            FROM import_sessions
+            WHERE id = $1 AND user_id = $2
+            "#,
+            session_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| {
+            let session = ImportSession {
+                id: row.id,
+                source_path: row.source_path,
+                total_files: row.total_files,
+                started_at: row.started_at,
+                finished_at: row.finished_at,
+                interrupted: row.interrupted,
+            };
+            (session, row.unqueued_files.0)
+        }))
+    }
+
+    /// Documents a session created, deleted ones included, in import order
+    pub async fn session_documents(&self, session_id: Uuid) -> Result<Vec<ImportedDocument>, sqlx::Error> {
+        sqlx::query_as!(
+            ImportedDocument,
+            r#"
+            SELECT id, file_name, status as "status!: DocumentStatus", processing_error, file_size_bytes
+            FROM documents
+            WHERE import_session_id = $1
+            ORDER BY created_at, id
+            "#,
+            session_id
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+}
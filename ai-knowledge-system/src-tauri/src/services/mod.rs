@@ -1,3 +1,31 @@
+pub mod activity;
+pub mod change_feed;
+pub mod changes;
 pub mod document;
+pub mod export_snapshot;
+pub mod focus_session;
+pub mod import_session;
+pub mod notification;
+pub mod processing_run;
+pub mod redaction;
+pub mod share_token;
+pub mod storage_migration;
+pub mod tag;
+pub mod user;
+pub mod workspace;
 
+pub use activity::ActivityLogger;
+pub use change_feed::ChangeFeedService;
+pub use changes::DocumentChanges;
 pub use document::DocumentService;
+pub use export_snapshot::ExportSnapshotService;
+pub use focus_session::FocusSessionService;
+pub use import_session::ImportSessionService;
+pub use notification::NotificationService;
+pub use processing_run::ProcessingRunService;
+pub use redaction::RedactionRuleService;
+pub use share_token::ShareTokenService;
+pub use storage_migration::StorageMigrationService;
+pub use tag::TagService;
+pub use user::UserService;
+pub use workspace::WorkspaceService;
@@ -0,0 +1,136 @@
+use crate::db;
+use crate::models::Notification;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Read notifications older than this are pruned by maintenance
+pub const READ_NOTIFICATIONS_KEPT_DAYS: i32 = 30;
+
+/// A notification about to be recorded
+#[derive(Clone, Copy)]
+pub struct NewNotification<'a> {
+    pub user_id: Uuid,
+    pub kind: &'a str,
+    pub title: &'a str,
+    pub body: Option<&'a str>,
+    pub document_id: Option<Uuid>,
+}
+
+pub struct NotificationService {
+    pool: PgPool,
+}
+
+impl NotificationService {
+    pub fn new(pool: PgPool) -> Self {
+        NotificationService { pool }
+    }
+
+    /// Record a notification, retrying for a while if the connection fails
+    pub async fn create(&self, new: NewNotification<'_>) -> Result<Notification, sqlx::Error> {
+        let pool = &self.pool;
+        db::retry_transient(db::DEFAULT_RETRY_WINDOW, move || async move {
+            sqlx::query_as!(
+                Notification,
+                r#"
+                INSERT INTO notifications (user_id, kind, title, body, document_id)
+                VALUES ($1, $2, $3, $4, $5)
+                RETURNING id, user_id, kind, title, body, document_id, created_at, read_at
+                "#,
+                new.user_id,
+                new.kind,
+                new.title,
+                new.body,
+                new.document_id
+            )
+            .fetch_one(pool)
+            .await
+        })
+        .await
+    }
+
+    /// A user's notifications, newest first
+    pub async fn list(&self, user_id: Uuid, unread_only: bool, limit: i64) -> Result<Vec<Notification>, sqlx::Error> {
+        let notifications = sqlx::query_as!(
+            Notification,
+            r#"
+            SELECT id, user_id, kind, title, body, document_id, created_at, read_at
+            FROM notifications
+            WHERE user_id = $1 AND (NOT $2 OR read_at IS NULL)
+            ORDER BY created_at DESC
+            LIMIT $3
+            "#,
+            user_id,
+            unread_only,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(notifications)
+    }
+
+    pub async fn unread_count(&self, user_id: Uuid) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!" FROM notifications WHERE user_id = $1 AND read_at IS NULL"#,
+            user_id
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Returns whether a notification of this user's exists; marking it again is a no-op
+    pub async fn mark_read(&self, user_id: Uuid, notification_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE notifications
+            SET read_at = COALESCE(read_at, NOW())
+            WHERE id = $1 AND user_id = $2
+            "#,
+            notification_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Returns how many notifications were unread
+    pub async fn mark_all_read(&self, user_id: Uuid) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query!(
+            "UPDATE notifications SET read_at = NOW() WHERE user_id = $1 AND read_at IS NULL",
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Delete notifications read more than `days` ago
+    /// When a user was last sent a notification of `kind`
+    pub async fn last_sent(
+        &self,
+        user_id: Uuid,
+        kind: &str,
+    ) -> Result<Option<chrono::DateTime<chrono::Utc>>, sqlx::Error> {
+        sqlx::query_scalar!(
+            "SELECT MAX(created_at) FROM notifications WHERE user_id = $1 AND kind = $2",
+            user_id,
+            kind
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    pub async fn prune_read(&self, days: i32) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query!(
+            "DELETE FROM notifications WHERE read_at < NOW() - make_interval(days => $1)",
+            days
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,141 @@
+use crate::models::ProcessingRun;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Runs kept per document when the history is pruned
+pub const RUNS_KEPT_PER_DOCUMENT: i64 = 20;
+
+/// How a processing run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunOutcome {
+    Completed,
+    Failed,
+    Cancelled,
+    TimedOut,
+}
+
+impl RunOutcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RunOutcome::Completed => "completed",
+            RunOutcome::Failed => "failed",
+            RunOutcome::Cancelled => "cancelled",
+            RunOutcome::TimedOut => "timed_out",
+        }
+    }
+}
+
+/// Final details recorded when a run ends
+pub struct RunFinish {
+    pub outcome: RunOutcome,
+    pub error: Option<String>,
+    pub extractor_name: Option<String>,
+    pub extractor_version: Option<String>,
+    pub pages_processed: Option<i32>,
+    /// Files embedded in a PDF, for PDFs only
+    pub attachments_found: Option<i32>,
+    /// Bytes of extracted text changed to make it storable, for completed runs
+    pub sanitized_bytes: Option<i32>,
+    /// Pages that kept their chunks from the previous run, for completed
+    /// runs of documents with pages
+    pub pages_reused: Option<i32>,
+    /// The PDF only loaded after its cross-reference table was rebuilt
+    pub repaired: bool,
+}
+
+/// Records one row per processing attempt in processing_runs
+pub struct ProcessingRunService {
+    pool: PgPool,
+}
+
+impl ProcessingRunService {
+    pub fn new(pool: PgPool) -> Self {
+        ProcessingRunService { pool }
+    }
+
+    pub async fn start_run(&self, doc_id: Uuid) -> Result<Uuid, sqlx::Error> {
+        sqlx::query_scalar!(
+            "INSERT INTO processing_runs (document_id) VALUES ($1) RETURNING id",
+            doc_id
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    pub async fn finish_run(&self, run_id: Uuid, finish: RunFinish) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE processing_runs
+            SET finished_at = NOW(), outcome = $2, error = $3,
+                extractor_name = $4, extractor_version = $5, pages_processed = $6,
+                attachments_found = $7, sanitized_bytes = $8, pages_reused = $9, repaired = $10
+            WHERE id = $1
+            "#,
+            run_id,
+            finish.outcome.as_str(),
+            finish.error,
+            finish.extractor_name,
+            finish.extractor_version,
+            finish.pages_processed,
+            finish.attachments_found,
+            finish.sanitized_bytes,
+            finish.pages_reused,
+            finish.repaired
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Runs for a document, newest first
+    pub async fn get_history(
+        &self,
+        doc_id: Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<ProcessingRun>, sqlx::Error> {
+        let runs = sqlx::query_as!(
+            ProcessingRun,
+            r#"
+            SELECT id, document_id, started_at, finished_at, outcome, error,
+                extractor_name, extractor_version, pages_processed, attachments_found,
+                sanitized_bytes, pages_reused, repaired
+            FROM processing_runs
+            WHERE document_id = $1
+            ORDER BY started_at DESC
+            LIMIT $2 OFFSET $3
+            "#,
+            doc_id,
+            limit,
+            offset
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(runs)
+    }
+
+    /// Delete all but the newest `keep` runs of every document
+    pub async fn prune(&self, keep: i64) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM processing_runs
+            WHERE id IN (
+                SELECT id FROM (
+                    SELECT id, ROW_NUMBER() OVER (
+                        PARTITION BY document_id ORDER BY started_at DESC
+                    ) AS position
+                    FROM processing_runs
+                ) ranked
+                WHERE position > $1
+            )
+            "#,
+            keep
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,67 @@
+use crate::models::RedactionRule;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub struct RedactionRuleService {
+    pool: PgPool,
+}
+
+impl RedactionRuleService {
+    pub fn new(pool: PgPool) -> Self {
+        RedactionRuleService { pool }
+    }
+
+    pub async fn list_rules(&self, user_id: Uuid) -> Result<Vec<RedactionRule>, sqlx::Error> {
+        let rules = sqlx::query_as!(
+            RedactionRule,
+            r#"
+            SELECT id, user_id, kind, pattern, label, created_at
+            FROM redaction_rules
+            WHERE user_id = $1
+            ORDER BY created_at
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rules)
+    }
+
+    /// Save a rule; callers validate the pattern first
+    pub async fn add_rule(
+        &self,
+        user_id: Uuid,
+        kind: &str,
+        pattern: &str,
+        label: Option<&str>,
+    ) -> Result<RedactionRule, sqlx::Error> {
+        sqlx::query_as!(
+            RedactionRule,
+            r#"
+            INSERT INTO redaction_rules (user_id, kind, pattern, label)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, user_id, kind, pattern, label, created_at
+            "#,
+            user_id,
+            kind,
+            pattern,
+            label
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Returns whether a rule of this user's was deleted
+    pub async fn delete_rule(&self, user_id: Uuid, rule_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "DELETE FROM redaction_rules WHERE id = $1 AND user_id = $2",
+            rule_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,116 @@
+use crate::models::ShareToken;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// A share token that can still be redeemed
+pub struct ValidShare {
+    pub id: Uuid,
+    pub document_id: Uuid,
+    pub include_original: bool,
+}
+
+pub struct ShareTokenService {
+    pool: PgPool,
+}
+
+impl ShareTokenService {
+    pub fn new(pool: PgPool) -> Self {
+        ShareTokenService { pool }
+    }
+
+    pub async fn create(
+        &self,
+        user_id: Uuid,
+        document_id: Uuid,
+        token_hash: &str,
+        include_original: bool,
+        expires_at: DateTime<Utc>,
+    ) -> Result<ShareToken, sqlx::Error> {
+        sqlx::query_as!(
+            ShareToken,
+            r#"
+            INSERT INTO share_tokens (user_id, document_id, token_hash, include_original, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, document_id, include_original, created_at, expires_at, used_at, revoked_at
+            "#,
+            user_id,
+            document_id,
+            token_hash,
+            include_original,
+            expires_at
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// A user's share tokens, newest first; used, expired and revoked ones
+    /// included
+    pub async fn list(&self, user_id: Uuid) -> Result<Vec<ShareToken>, sqlx::Error> {
+        sqlx::query_as!(
+            ShareToken,
+            r#"
+            SELECT id, document_id, include_original, created_at, expires_at, used_at, revoked_at
+            FROM share_tokens
+            WHERE user_id = $1
+            ORDER BY created_at DESC
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Revoke one of a user's tokens; returns whether it could still have
+    /// been redeemed
+    pub async fn revoke(&self, user_id: Uuid, share_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE share_tokens
+            SET revoked_at = NOW()
+            WHERE id = $1 AND user_id = $2
+              AND used_at IS NULL AND revoked_at IS NULL AND expires_at > NOW()
+            "#,
+            share_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// The unused, unexpired and unrevoked token with this hash, if its
+    /// document still exists
+    pub async fn find_valid(&self, token_hash: &str) -> Result<Option<ValidShare>, sqlx::Error> {
+        sqlx::query_as!(
+            ValidShare,
+            r#"
+            SELECT s.id, s.document_id, s.include_original
+            FROM share_tokens s
+            JOIN documents d ON d.id = s.document_id
+            WHERE s.token_hash = $1
+              AND s.used_at IS NULL AND s.revoked_at IS NULL AND s.expires_at > NOW()
+              AND d.deleted_at IS NULL
+            "#,
+            token_hash
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Mark a token used; false if it was used, revoked or expired since it
+    /// was found, so only one request is served
+    pub async fn consume(&self, share_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE share_tokens
+            SET used_at = NOW()
+            WHERE id = $1 AND used_at IS NULL AND revoked_at IS NULL AND expires_at > NOW()
+            "#,
+            share_id
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
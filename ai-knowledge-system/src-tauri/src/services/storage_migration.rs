@@ -0,0 +1,189 @@
+use super::changes::DocumentChanges;
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, FromRow)]
+pub struct MigrationItem {
+    pub document_id: Uuid,
+    pub source_path: String,
+    pub dest_path: String,
+    pub state: String,
+}
+
+pub struct StorageMigrationService {
+    pool: PgPool,
+    changes: DocumentChanges,
+}
+
+impl StorageMigrationService {
+    pub fn new(pool: PgPool, changes: DocumentChanges) -> Self {
+        StorageMigrationService { pool, changes }
+    }
+
+    /// An unfinished migration towards `target_root`, if one exists
+    pub async fn find_active(&self, target_root: &str) -> Result<Option<Uuid>, sqlx::Error> {
+        let id = sqlx::query_scalar!(
+            r#"
+            SELECT id FROM storage_migrations
+            WHERE target_root = $1 AND completed_at IS NULL
+            ORDER BY created_at DESC
+            LIMIT 1
+            "#,
+            target_root
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(id)
+    }
+
+    pub async fn create(&self, source_root: &str, target_root: &str) -> Result<Uuid, sqlx::Error> {
+        let id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO storage_migrations (source_root, target_root)
+            VALUES ($1, $2)
+            RETURNING id
+            "#,
+            source_root,
+            target_root
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// All documents that currently point at a stored file; external files
+    /// stay where the user keeps them
+    pub async fn list_stored_files(&self) -> Result<Vec<(Uuid, String)>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, file_path as "file_path!"
+            FROM documents
+            WHERE file_path IS NOT NULL AND NOT external_file
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|r| (r.id, r.file_path)).collect())
+    }
+
+    /// Register documents to move; already tracked documents are left untouched
+    pub async fn add_items(
+        &self,
+        migration_id: Uuid,
+        document_ids: &[Uuid],
+        source_paths: &[String],
+        dest_paths: &[String],
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO storage_migration_items (migration_id, document_id, source_path, dest_path)
+            SELECT $1, * FROM UNNEST($2::uuid[], $3::text[], $4::text[])
+            ON CONFLICT (migration_id, document_id) DO NOTHING
+            "#,
+            migration_id,
+            document_ids,
+            source_paths,
+            dest_paths
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_items(&self, migration_id: Uuid) -> Result<Vec<MigrationItem>, sqlx::Error> {
+        let items = sqlx::query_as!(
+            MigrationItem,
+            r#"
+            SELECT document_id, source_path, dest_path, state
+            FROM storage_migration_items
+            WHERE migration_id = $1
+            ORDER BY document_id
+            "#,
+            migration_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(items)
+    }
+
+    pub async fn set_item_state(
+        &self,
+        migration_id: Uuid,
+        document_id: Uuid,
+        state: &str,
+        error: Option<String>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE storage_migration_items
+            SET state = $3, error = $4, updated_at = NOW()
+            WHERE migration_id = $1 AND document_id = $2
+            "#,
+            migration_id,
+            document_id,
+            state,
+            error
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Point documents at their copied files in a single transaction
+    pub async fn commit_paths(
+        &self,
+        migration_id: Uuid,
+        document_ids: &[Uuid],
+        dest_paths: &[String],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE documents d
+            SET file_path = v.dest_path
+            FROM UNNEST($1::uuid[], $2::text[]) AS v(document_id, dest_path)
+            WHERE d.id = v.document_id
+            "#,
+            document_ids,
+            dest_paths
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE storage_migration_items
+            SET state = 'updated', updated_at = NOW()
+            WHERE migration_id = $1 AND document_id = ANY($2)
+            "#,
+            migration_id,
+            document_ids
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        for &document_id in document_ids {
+            self.changes.publish(document_id, &["file_path"], None);
+        }
+        Ok(())
+    }
+
+    pub async fn complete(&self, migration_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE storage_migrations SET completed_at = NOW() WHERE id = $1",
+            migration_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,403 @@
+use super::changes::DocumentChanges;
+use crate::error::{AppError, AppResult};
+use crate::keywords::TagSuggestion;
+use crate::models::{Tag, TagDocumentCount, TagTreeEntry};
+use sqlx::types::Json;
+use sqlx::PgPool;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+pub struct TagService {
+    pool: PgPool,
+    changes: DocumentChanges,
+}
+
+impl TagService {
+    pub fn new(pool: PgPool, changes: DocumentChanges) -> Self {
+        TagService { pool, changes }
+    }
+
+    /// Names of all tags owned by the document's owner
+    pub async fn tag_names_for_document_owner(&self, doc_id: Uuid) -> Result<Vec<String>, sqlx::Error> {
+        let names = sqlx::query_scalar!(
+            r#"
+            SELECT t.name
+            FROM tags t
+            JOIN documents d ON d.user_id = t.user_id
+            WHERE d.id = $1
+            "#,
+            doc_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(names)
+    }
+
+    pub async fn set_suggested_tags(
+        &self,
+        doc_id: Uuid,
+        suggestions: &[TagSuggestion],
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE documents SET suggested_tags = $2 WHERE id = $1",
+            doc_id,
+            Json(suggestions) as _
+        )
+        .execute(&self.pool)
+        .await?;
+
+        self.changes.publish(doc_id, &["suggested_tags"], None);
+        Ok(())
+    }
+
+    pub async fn get_suggested_tags(&self, doc_id: Uuid) -> Result<Vec<TagSuggestion>, sqlx::Error> {
+        let suggestions = sqlx::query_scalar!(
+            r#"
+            SELECT suggested_tags as "suggested_tags!: Json<Vec<TagSuggestion>>"
+            FROM documents
+            WHERE id = $1
+            "#,
+            doc_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(suggestions.map(|s| s.0).unwrap_or_default())
+    }
+
+    /// Find a user's unscoped tag by name, creating it if needed; also
+    /// returns whether it was created
+    pub async fn get_or_create_tag(&self, user_id: Uuid, name: &str) -> Result<(Tag, bool), sqlx::Error> {
+        // tags_unique_name treats NULL workspaces as distinct, so check first
+        let existing = sqlx::query_as!(
+            Tag,
+            r#"
+            SELECT id, user_id, workspace_id, name, color, description, created_at, parent_tag_id
+            FROM tags
+            WHERE user_id = $1 AND workspace_id IS NULL AND lower(name) = lower($2)
+            "#,
+            user_id,
+            name
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if let Some(tag) = existing {
+            return Ok((tag, false));
+        }
+
+        let tag = sqlx::query_as!(
+            Tag,
+            r#"
+            INSERT INTO tags (user_id, name)
+            VALUES ($1, $2)
+            RETURNING id, user_id, workspace_id, name, color, description, created_at, parent_tag_id
+            "#,
+            user_id,
+            name
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok((tag, true))
+    }
+
+    /// Every tag of a user, unscoped and in workspaces, by name
+    pub async fn list_tags(&self, user_id: Uuid) -> Result<Vec<Tag>, sqlx::Error> {
+        sqlx::query_as!(
+            Tag,
+            r#"
+            SELECT id, user_id, workspace_id, name, color, description, created_at, parent_tag_id
+            FROM tags
+            WHERE user_id = $1
+            ORDER BY lower(name), id
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// A user's tags as a tree, in one query; see `TagTreeEntry` for the order
+    pub async fn tag_tree(&self, user_id: Uuid) -> Result<Vec<TagTreeEntry>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            WITH RECURSIVE tree(id, depth, path, sort_key) AS (
+                SELECT id, 0, name::text, ARRAY[lower(name), id::text]
+                FROM tags
+                WHERE user_id = $1 AND parent_tag_id IS NULL
+                UNION ALL
+                SELECT c.id, tree.depth + 1, tree.path || '/' || c.name, tree.sort_key || ARRAY[lower(c.name), c.id::text]
+                FROM tags c
+                JOIN tree ON c.parent_tag_id = tree.id
+            )
+            SELECT
+                t.id, t.user_id, t.workspace_id, t.name, t.color, t.description, t.created_at, t.parent_tag_id,
+                tree.depth as "depth!", tree.path as "path!"
+            FROM tree
+            JOIN tags t ON t.id = tree.id
+            ORDER BY tree.sort_key
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| TagTreeEntry {
+                tag: Tag {
+                    id: row.id,
+                    user_id: row.user_id,
+                    workspace_id: row.workspace_id,
+                    name: row.name,
+                    color: row.color,
+                    description: row.description,
+                    created_at: row.created_at,
+                    parent_tag_id: row.parent_tag_id,
+                },
+                depth: row.depth,
+                path: row.path,
+            })
+            .collect())
+    }
+
+    pub async fn get_tag(&self, tag_id: Uuid) -> Result<Option<Tag>, sqlx::Error> {
+        sqlx::query_as!(
+            Tag,
+            r#"
+            SELECT id, user_id, workspace_id, name, color, description, created_at, parent_tag_id
+            FROM tags
+            WHERE id = $1
+            "#,
+            tag_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Create a tag, in a workspace or unscoped, optionally nested under
+    /// `parent_tag_id`
+    pub async fn create_tag(
+        &self,
+        user_id: Uuid,
+        workspace_id: Option<Uuid>,
+        name: &str,
+        color: Option<&str>,
+        description: Option<&str>,
+        parent_tag_id: Option<Uuid>,
+    ) -> AppResult<Tag> {
+        if self.name_taken(user_id, workspace_id, name, None).await? {
+            return Err(AppError::InvalidInput(format!("A tag named \"{}\" already exists", name)));
+        }
+        if let Some(parent_tag_id) = parent_tag_id {
+            self.check_parent(user_id, workspace_id, parent_tag_id).await?;
+        }
+        let tag = sqlx::query_as!(
+            Tag,
+            r#"
+            INSERT INTO tags (user_id, workspace_id, name, color, description, parent_tag_id)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, user_id, workspace_id, name, color, description, created_at, parent_tag_id
+            "#,
+            user_id,
+            workspace_id,
+            name,
+            color,
+            description,
+            parent_tag_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(tag)
+    }
+
+    /// Rename a tag; documents keep it, so they show the new name
+    ///
+    /// Names are unique per workspace ignoring case, as `get_or_create_tag`
+    /// looks them up.
+    pub async fn rename_tag(&self, tag: &Tag, name: &str) -> AppResult<()> {
+        if self.name_taken(tag.user_id, tag.workspace_id, name, Some(tag.id)).await? {
+            return Err(AppError::InvalidInput(format!("A tag named \"{}\" already exists", name)));
+        }
+
+        sqlx::query!("UPDATE tags SET name = $2 WHERE id = $1", tag.id, name)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Nest a tag under another, or move it to the top level with None
+    ///
+    /// The new parent's ancestors are walked first: a tag can't be nested
+    /// under itself or a tag nested under it. Documents keep their tags.
+    pub async fn set_parent(&self, tag: &Tag, parent_tag_id: Option<Uuid>) -> AppResult<()> {
+        if let Some(parent_tag_id) = parent_tag_id {
+            self.check_parent(tag.user_id, tag.workspace_id, parent_tag_id).await?;
+            let mut seen = HashSet::new();
+            let mut next = Some(parent_tag_id);
+            while let Some(ancestor) = next {
+                if ancestor == tag.id {
+                    return Err(AppError::InvalidInput(
+                        "A tag can't be nested under itself or a tag nested under it".to_string(),
+                    ));
+                }
+                // A cycle that doesn't pass through this tag predates the check
+                if !seen.insert(ancestor) {
+                    break;
+                }
+                next = sqlx::query_scalar!("SELECT parent_tag_id FROM tags WHERE id = $1", ancestor)
+                    .fetch_optional(&self.pool)
+                    .await?
+                    .flatten();
+            }
+        }
+
+        sqlx::query!("UPDATE tags SET parent_tag_id = $2 WHERE id = $1", tag.id, parent_tag_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Whether another tag of the user's in the workspace has `name`,
+    /// ignoring case; `except` is the tag being renamed
+    async fn name_taken(
+        &self,
+        user_id: Uuid,
+        workspace_id: Option<Uuid>,
+        name: &str,
+        except: Option<Uuid>,
+    ) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM tags
+                WHERE user_id = $1 AND workspace_id IS NOT DISTINCT FROM $2
+                  AND lower(name) = lower($3) AND id IS DISTINCT FROM $4
+            ) as "taken!"
+            "#,
+            user_id,
+            workspace_id,
+            name,
+            except
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Fail unless `parent_tag_id` is a tag of the same user and workspace
+    async fn check_parent(&self, user_id: Uuid, workspace_id: Option<Uuid>, parent_tag_id: Uuid) -> AppResult<()> {
+        let parent = self
+            .get_tag(parent_tag_id)
+            .await?
+            .filter(|parent| parent.user_id == user_id)
+            .ok_or_else(|| AppError::NotFound("Parent tag".to_string()))?;
+        if parent.workspace_id != workspace_id {
+            return Err(AppError::InvalidInput(
+                "A tag can only be nested under a tag of the same workspace".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Delete a tag, detaching it from its documents
+    ///
+    /// Tags nested under it move up to its own parent with
+    /// `reparent_children`; without it a tag with nested tags isn't deleted.
+    pub async fn delete_tag(&self, tag: &Tag, reparent_children: bool) -> AppResult<()> {
+        let mut tx = self.pool.begin().await?;
+        let children = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!" FROM tags WHERE parent_tag_id = $1"#,
+            tag.id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if children > 0 && !reparent_children {
+            return Err(AppError::InvalidInput(format!(
+                "\"{}\" has {} nested tags; move or delete them first",
+                tag.name, children
+            )));
+        }
+
+        sqlx::query!(
+            "UPDATE tags SET parent_tag_id = $2 WHERE parent_tag_id = $1",
+            tag.id,
+            tag.parent_tag_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        let documents = sqlx::query_scalar!(
+            "DELETE FROM document_tags WHERE tag_id = $1 RETURNING document_id",
+            tag.id
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        sqlx::query!("DELETE FROM tags WHERE id = $1", tag.id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        for doc_id in documents {
+            self.changes.publish(doc_id, &["tags"], None);
+        }
+        Ok(())
+    }
+
+    /// Replace a tag's color and description
+    pub async fn update_tag_style(
+        &self,
+        tag_id: Uuid,
+        color: Option<&str>,
+        description: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE tags SET color = $2, description = $3 WHERE id = $1",
+            tag_id,
+            color,
+            description
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Every tag of a user with the number of documents carrying it,
+    /// counted from document_tags so attaching, detaching and deleting
+    /// documents are reflected straight away
+    pub async fn document_counts(&self, user_id: Uuid) -> Result<Vec<TagDocumentCount>, sqlx::Error> {
+        sqlx::query_as!(
+            TagDocumentCount,
+            r#"
+            SELECT
+                t.id as tag_id, t.name, t.color, t.workspace_id,
+                COUNT(d.id) as "document_count!"
+            FROM tags t
+            LEFT JOIN document_tags dt ON dt.tag_id = t.id
+            LEFT JOIN documents d ON d.id = dt.document_id AND d.deleted_at IS NULL
+            WHERE t.user_id = $1
+            GROUP BY t.id
+            ORDER BY lower(t.name), t.id
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn attach_tag(&self, doc_id: Uuid, tag_id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO document_tags (document_id, tag_id)
+            VALUES ($1, $2)
+            ON CONFLICT DO NOTHING
+            "#,
+            doc_id,
+            tag_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        self.changes.publish(doc_id, &["tags"], None);
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+use crate::models::{Role, StorageCorrection, User};
+use sqlx::PgPool;
+use std::path::Path;
+use uuid::Uuid;
+
+/// A user's storage figures as maintained by the storage trigger
+#[derive(Debug, Clone, Copy)]
+pub struct StorageUsage {
+    /// Files in the storage root; only these count against the limit
+    pub managed_bytes: i64,
+    /// Files imported by reference, left where they are
+    pub referenced_bytes: i64,
+    pub limit_bytes: i64,
+}
+
+pub struct UserService {
+    pool: PgPool,
+}
+
+impl UserService {
+    pub fn new(pool: PgPool) -> Self {
+        UserService { pool }
+    }
+
+    pub async fn list_users(&self) -> Result<Vec<User>, sqlx::Error> {
+        let users = sqlx::query_as!(
+            User,
+            r#"
+            SELECT
+                id, email, full_name, role as "role!: Role",
+                storage_used_bytes, storage_limit_bytes
+            FROM users
+            WHERE deleted_at IS NULL
+            ORDER BY email
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(users)
+    }
+
+    pub async fn get_user(&self, user_id: Uuid) -> Result<Option<User>, sqlx::Error> {
+        let user = sqlx::query_as!(
+            User,
+            r#"
+            SELECT
+                id, email, full_name, role as "role!: Role",
+                storage_used_bytes, storage_limit_bytes
+            FROM users
+            WHERE id = $1 AND deleted_at IS NULL
+            "#,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    /// Set a user's storage quota; returns the updated user, or None if there is no such user
+    pub async fn set_storage_limit(&self, user_id: Uuid, limit_bytes: i64) -> Result<Option<User>, sqlx::Error> {
+        let user = sqlx::query_as!(
+            User,
+            r#"
+            UPDATE users
+            SET storage_limit_bytes = $2
+            WHERE id = $1 AND deleted_at IS NULL
+            RETURNING
+                id, email, full_name, role as "role!: Role",
+                storage_used_bytes, storage_limit_bytes
+            "#,
+            user_id,
+            limit_bytes
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    /// The workspace a user's uploads go in when they don't name one; None
+    /// without a default, or when the user has left it or it was deleted
+    pub async fn default_workspace(&self, user_id: Uuid) -> Result<Option<Uuid>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT w.id
+            FROM users u
+            JOIN workspaces w ON w.id = u.default_workspace_id AND w.deleted_at IS NULL
+            JOIN workspace_members m ON m.workspace_id = w.id AND m.user_id = u.id
+            WHERE u.id = $1
+            "#,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Set or clear a user's default workspace; membership is checked by the
+    /// caller
+    pub async fn set_default_workspace(&self, user_id: Uuid, workspace_id: Option<Uuid>) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "UPDATE users SET default_workspace_id = $2 WHERE id = $1 AND deleted_at IS NULL",
+            user_id,
+            workspace_id
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Current storage figures as maintained by the storage trigger
+    pub async fn get_storage_usage(&self, user_id: Uuid) -> Result<Option<StorageUsage>, sqlx::Error> {
+        sqlx::query_as!(
+            StorageUsage,
+            r#"
+            SELECT
+                storage_used_bytes as managed_bytes,
+                referenced_bytes,
+                storage_limit_bytes as limit_bytes
+            FROM users
+            WHERE id = $1 AND deleted_at IS NULL
+            "#,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Re-derive storage_used_bytes and referenced_bytes from the documents
+    ///
+    /// Mirrors update_storage_usage(): documents in a workspace are charged to
+    /// the workspace, and soft-deleted files still occupy space until purged.
+    /// A file imported by reference counts as referenced unless its path is
+    /// under `storage_root`; such a file is already where the app keeps its
+    /// own, so it is turned into a managed one and counted against the quota.
+    pub async fn recompute_storage_usage(
+        &self,
+        user_id: Uuid,
+        storage_root: &Path,
+    ) -> Result<Option<StorageCorrection>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let previous = sqlx::query!(
+            "SELECT storage_used_bytes, referenced_bytes FROM users WHERE id = $1 FOR UPDATE",
+            user_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(previous) = previous else {
+            return Ok(None);
+        };
+
+        let mut root_prefix = storage_root.to_string_lossy().to_string();
+        if !root_prefix.ends_with(std::path::MAIN_SEPARATOR) {
+            root_prefix.push(std::path::MAIN_SEPARATOR);
+        }
+        let converted = sqlx::query!(
+            r#"
+            UPDATE documents
+            SET external_file = FALSE, updated_at = NOW()
+            WHERE user_id = $1 AND workspace_id IS NULL AND external_file
+              AND starts_with(file_path, $2)
+            "#,
+            user_id,
+            root_prefix
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        let recomputed = sqlx::query!(
+            r#"
+            SELECT
+                COALESCE(SUM(file_size_bytes) FILTER (WHERE NOT external_file), 0)::BIGINT as "managed!",
+                COALESCE(SUM(file_size_bytes) FILTER (WHERE external_file), 0)::BIGINT as "referenced!"
+            FROM documents
+            WHERE user_id = $1 AND workspace_id IS NULL
+            "#,
+            user_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "UPDATE users SET storage_used_bytes = $2, referenced_bytes = $3 WHERE id = $1",
+            user_id,
+            recomputed.managed,
+            recomputed.referenced
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(Some(StorageCorrection {
+            previous_bytes: previous.storage_used_bytes,
+            recomputed_bytes: recomputed.managed,
+            delta_bytes: recomputed.managed - previous.storage_used_bytes,
+            previous_referenced_bytes: previous.referenced_bytes,
+            recomputed_referenced_bytes: recomputed.referenced,
+            referenced_delta_bytes: recomputed.referenced - previous.referenced_bytes,
+            converted_documents: converted,
+        }))
+    }
+}
@@ -0,0 +1,571 @@
+use super::changes::DocumentChanges;
+use crate::derived;
+use crate::models::{
+    DerivedTarget, Document, DocumentStatus, ProcessingOptions, ProvenanceInfo, Workspace, WorkspaceDocumentCount,
+    WorkspaceTemplate, WorkspaceTemplateInput,
+};
+use crate::quick_open::QuickOpenIndex;
+use crate::text_compression;
+use sqlx::types::Json;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// A document as it is recreated from a workspace package
+pub struct NewWorkspaceDocument<'a> {
+    pub workspace_id: Uuid,
+    pub user_id: Uuid,
+    pub title: &'a str,
+    pub content: Option<&'a str>,
+    pub summary: Option<&'a str>,
+    pub file_path: &'a str,
+    pub file_name: Option<&'a str>,
+    pub file_size_bytes: Option<i64>,
+    pub file_type: Option<&'a str>,
+    pub mime_type: Option<&'a str>,
+    pub file_hash: Option<&'a str>,
+    pub status: DocumentStatus,
+    pub page_count: Option<i32>,
+    pub pages: &'a [String],
+    pub tags: &'a [String],
+    pub title_sort: &'a str,
+    pub provenance: &'a ProvenanceInfo,
+}
+
+/// A workspace_templates row; options are stored as JSON
+struct TemplateRow {
+    id: Uuid,
+    name: String,
+    tag_names: Vec<String>,
+    processing_options: Option<Json<ProcessingOptions>>,
+    created_at: chrono::DateTime<chrono::Utc>,
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<TemplateRow> for WorkspaceTemplate {
+    fn from(row: TemplateRow) -> Self {
+        WorkspaceTemplate {
+            id: row.id,
+            name: row.name,
+            tag_names: row.tag_names,
+            processing_options: row.processing_options.map(|json| json.0),
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+pub struct WorkspaceService {
+    pool: PgPool,
+    quick_index: Arc<QuickOpenIndex>,
+    changes: DocumentChanges,
+}
+
+impl WorkspaceService {
+    pub fn new(pool: PgPool, quick_index: Arc<QuickOpenIndex>, changes: DocumentChanges) -> Self {
+        WorkspaceService {
+            pool,
+            quick_index,
+            changes,
+        }
+    }
+
+    pub async fn get_workspace(&self, workspace_id: Uuid) -> Result<Option<Workspace>, sqlx::Error> {
+        let workspace = sqlx::query_as!(
+            Workspace,
+            r#"
+            SELECT id, name, owner_id, storage_limit_bytes, storage_used_bytes, created_at, updated_at
+            FROM workspaces
+            WHERE id = $1 AND deleted_at IS NULL
+            "#,
+            workspace_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(workspace)
+    }
+
+    /// Workspaces a user owns or was added to, by name
+    pub async fn list_workspaces(&self, user_id: Uuid) -> Result<Vec<Workspace>, sqlx::Error> {
+        sqlx::query_as!(
+            Workspace,
+            r#"
+            SELECT w.id, w.name, w.owner_id, w.storage_limit_bytes, w.storage_used_bytes, w.created_at, w.updated_at
+            FROM workspaces w
+            JOIN workspace_members m ON m.workspace_id = w.id AND m.user_id = $1
+            WHERE w.deleted_at IS NULL
+            ORDER BY lower(w.name), w.id
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// A workspace the user owns with this name, ignoring case
+    pub async fn find_owned_workspace(&self, owner_id: Uuid, name: &str) -> Result<Option<Workspace>, sqlx::Error> {
+        sqlx::query_as!(
+            Workspace,
+            r#"
+            SELECT id, name, owner_id, storage_limit_bytes, storage_used_bytes, created_at, updated_at
+            FROM workspaces
+            WHERE owner_id = $1 AND lower(name) = lower($2) AND deleted_at IS NULL
+            ORDER BY created_at
+            LIMIT 1
+            "#,
+            owner_id,
+            name
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    pub async fn create_workspace(&self, owner_id: Uuid, name: &str) -> Result<Workspace, sqlx::Error> {
+        self.create_workspace_from_template(owner_id, name, None).await
+    }
+
+    /// Create a workspace, applying `template` to it in the same transaction
+    pub async fn create_workspace_from_template(
+        &self,
+        owner_id: Uuid,
+        name: &str,
+        template: Option<&WorkspaceTemplate>,
+    ) -> Result<Workspace, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let workspace = sqlx::query_as!(
+            Workspace,
+            r#"
+            INSERT INTO workspaces (name, owner_id)
+            VALUES ($1, $2)
+            RETURNING id, name, owner_id, storage_limit_bytes, storage_used_bytes, created_at, updated_at
+            "#,
+            name,
+            owner_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "INSERT INTO workspace_members (workspace_id, user_id, role) VALUES ($1, $2, 'owner')",
+            workspace.id,
+            owner_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if let Some(template) = template {
+            apply_template(&mut tx, workspace.id, owner_id, template).await?;
+        }
+
+        tx.commit().await?;
+        Ok(workspace)
+    }
+
+    /// Apply a template to an existing workspace, returning how many tags
+    /// were created
+    ///
+    /// Only adds: tags the workspace has already, by name ignoring case, are
+    /// left alone and no tag is removed, so applying again changes nothing.
+    pub async fn apply_template(
+        &self,
+        workspace_id: Uuid,
+        user_id: Uuid,
+        template: &WorkspaceTemplate,
+    ) -> Result<u64, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let created = apply_template(&mut tx, workspace_id, user_id, template).await?;
+        tx.commit().await?;
+        Ok(created)
+    }
+
+    /// Options uploads into the workspace are processed with, None when it
+    /// has none and the settings defaults apply
+    pub async fn processing_defaults(&self, workspace_id: Uuid) -> Result<Option<ProcessingOptions>, sqlx::Error> {
+        let options = sqlx::query_scalar!(
+            r#"SELECT processing_defaults as "processing_defaults: Json<ProcessingOptions>" FROM workspaces WHERE id = $1"#,
+            workspace_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(options.flatten().map(|json| json.0))
+    }
+
+    /// A user's workspace templates by name
+    pub async fn list_templates(&self, user_id: Uuid) -> Result<Vec<WorkspaceTemplate>, sqlx::Error> {
+        let rows = sqlx::query_as!(
+            TemplateRow,
+            r#"
+            SELECT id, name, tag_names,
+                processing_options as "processing_options: Json<ProcessingOptions>",
+                created_at, updated_at
+            FROM workspace_templates
+            WHERE user_id = $1
+            ORDER BY lower(name), id
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(WorkspaceTemplate::from).collect())
+    }
+
+    /// A template, if it exists and belongs to the user
+    pub async fn get_template(
+        &self,
+        user_id: Uuid,
+        template_id: Uuid,
+    ) -> Result<Option<WorkspaceTemplate>, sqlx::Error> {
+        let row = sqlx::query_as!(
+            TemplateRow,
+            r#"
+            SELECT id, name, tag_names,
+                processing_options as "processing_options: Json<ProcessingOptions>",
+                created_at, updated_at
+            FROM workspace_templates
+            WHERE id = $1 AND user_id = $2
+            "#,
+            template_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(WorkspaceTemplate::from))
+    }
+
+    pub async fn create_template(
+        &self,
+        user_id: Uuid,
+        input: &WorkspaceTemplateInput,
+    ) -> Result<WorkspaceTemplate, sqlx::Error> {
+        let row = sqlx::query_as!(
+            TemplateRow,
+            r#"
+            INSERT INTO workspace_templates (user_id, name, tag_names, processing_options)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, name, tag_names,
+                processing_options as "processing_options: Json<ProcessingOptions>",
+                created_at, updated_at
+            "#,
+            user_id,
+            input.name,
+            &input.tag_names,
+            input.processing_options.as_ref().map(Json) as _
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.into())
+    }
+
+    /// Replace a template's fields; None when it doesn't belong to the user.
+    /// Workspaces it was applied to keep what they got.
+    pub async fn update_template(
+        &self,
+        user_id: Uuid,
+        template_id: Uuid,
+        input: &WorkspaceTemplateInput,
+    ) -> Result<Option<WorkspaceTemplate>, sqlx::Error> {
+        let row = sqlx::query_as!(
+            TemplateRow,
+            r#"
+            UPDATE workspace_templates
+            SET name = $3, tag_names = $4, processing_options = $5, updated_at = NOW()
+            WHERE id = $1 AND user_id = $2
+            RETURNING id, name, tag_names,
+                processing_options as "processing_options: Json<ProcessingOptions>",
+                created_at, updated_at
+            "#,
+            template_id,
+            user_id,
+            input.name,
+            &input.tag_names,
+            input.processing_options.as_ref().map(Json) as _
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(WorkspaceTemplate::from))
+    }
+
+    /// Delete a template, returning false when it doesn't belong to the user
+    pub async fn delete_template(&self, user_id: Uuid, template_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "DELETE FROM workspace_templates WHERE id = $1 AND user_id = $2",
+            template_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn get_documents(&self, workspace_id: Uuid) -> Result<Vec<Document>, sqlx::Error> {
+        let mut docs = sqlx::query_as!(
+            Document,
+            r#"
+            SELECT
+                id, user_id, workspace_id, title, content, summary,
+                file_path, file_name, file_size_bytes, file_type, mime_type,
+                status as "status!: DocumentStatus",
+                processing_error, created_at, updated_at, deleted_at,
+                page_count, parent_document_id, is_pinned, pinned_order, external_file,
+                (manually_unread OR last_opened_at IS NULL) as "is_unread!"
+            FROM documents
+            WHERE workspace_id = $1 AND deleted_at IS NULL
+            ORDER BY created_at
+            "#,
+            workspace_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        super::document::inflate_content(&self.pool, &mut docs).await?;
+        Ok(docs)
+    }
+
+    /// Tag names attached to a document
+    pub async fn get_tag_names(&self, doc_id: Uuid) -> Result<Vec<String>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT t.name
+            FROM tags t
+            JOIN document_tags dt ON dt.tag_id = t.id
+            WHERE dt.document_id = $1
+            ORDER BY t.name
+            "#,
+            doc_id
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Stored files of a user's documents with the given size, as dedup candidates
+    pub async fn find_files_by_size(&self, user_id: Uuid, size_bytes: i64) -> Result<Vec<String>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT DISTINCT file_path as "file_path!"
+            FROM documents
+            WHERE user_id = $1 AND file_size_bytes = $2
+                AND file_path IS NOT NULL AND deleted_at IS NULL
+            "#,
+            user_id,
+            size_bytes
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Insert a document with its pages and tags in one transaction
+    ///
+    /// Tags map onto the user's unscoped tags by case-insensitive name and
+    /// are created when missing.
+    pub async fn insert_document(&self, doc: NewWorkspaceDocument<'_>) -> Result<Uuid, sqlx::Error> {
+        let compressed = match doc.content {
+            Some(content) if !content.is_empty() => Some(text_compression::compress(content)?),
+            _ => None,
+        };
+        let mut tx = self.pool.begin().await?;
+
+        let doc_id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO documents (
+                user_id, workspace_id, title, content, content_zstd, content_tsvector, summary,
+                file_path, file_name, file_size_bytes, file_type, mime_type, status, page_count,
+                file_hash, title_sort, derived_versions, provenance
+            )
+            VALUES (
+                $1, $2, $3, CASE WHEN $17::bytea IS NULL THEN $4 END, $17,
+                setweight(to_tsvector('english', COALESCE($4, '')), 'B'),
+                $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16
+            )
+            RETURNING id
+            "#,
+            doc.user_id,
+            doc.workspace_id,
+            doc.title,
+            doc.content,
+            doc.summary,
+            doc.file_path,
+            doc.file_name,
+            doc.file_size_bytes,
+            doc.file_type,
+            doc.mime_type,
+            doc.status as DocumentStatus,
+            doc.page_count,
+            doc.file_hash,
+            doc.title_sort,
+            derived::stamp(DerivedTarget::SortKeys),
+            Json(doc.provenance) as _,
+            compressed
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        super::document::insert_pages(&mut tx, doc_id, doc.pages).await?;
+
+        if let Some(content) = doc.content {
+            let chunks = if doc.pages.is_empty() {
+                crate::chunking::chunk_text(content, None)
+            } else {
+                crate::chunking::chunk_pages(content, doc.pages, None)
+            };
+            super::document::insert_chunks(&mut tx, doc_id, &chunks).await?;
+            super::document::stamp_derived(&mut tx, doc_id, DerivedTarget::Chunks).await?;
+            let terms = crate::keywords::term_counts(content);
+            super::document::insert_terms(&mut tx, doc_id, &terms).await?;
+            super::document::stamp_derived(&mut tx, doc_id, DerivedTarget::TextStats).await?;
+        }
+
+        for name in doc.tags {
+            let existing = sqlx::query_scalar!(
+                r#"
+                SELECT id FROM tags
+                WHERE user_id = $1 AND workspace_id IS NULL AND lower(name) = lower($2)
+                "#,
+                doc.user_id,
+                name
+            )
+            .fetch_optional(&mut *tx)
+            .await?;
+
+            let tag_id = match existing {
+                Some(id) => id,
+                None => {
+                    sqlx::query_scalar!(
+                        "INSERT INTO tags (user_id, name) VALUES ($1, $2) RETURNING id",
+                        doc.user_id,
+                        name
+                    )
+                    .fetch_one(&mut *tx)
+                    .await?
+                }
+            };
+
+            sqlx::query!(
+                "INSERT INTO document_tags (document_id, tag_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+                doc_id,
+                tag_id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        self.quick_index.upsert(doc_id, doc.user_id, doc.title, doc.file_name);
+        self.changes.publish(doc_id, &["created"], Some(doc.status));
+        Ok(doc_id)
+    }
+
+    /// Whether a user owns or was added to a workspace
+    pub async fn is_member(&self, workspace_id: Uuid, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM workspace_members WHERE workspace_id = $1 AND user_id = $2
+            ) as "exists!"
+            "#,
+            workspace_id,
+            user_id
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Every workspace a user belongs to with the number of documents in
+    /// it, counted from the documents themselves so moves and deletes are
+    /// reflected straight away
+    pub async fn document_counts(&self, user_id: Uuid) -> Result<Vec<WorkspaceDocumentCount>, sqlx::Error> {
+        sqlx::query_as!(
+            WorkspaceDocumentCount,
+            r#"
+            SELECT
+                w.id as workspace_id, w.name,
+                COUNT(d.id) as "document_count!",
+                EXISTS (
+                    SELECT 1 FROM users u WHERE u.id = $1 AND u.default_workspace_id = w.id
+                ) as "is_default!"
+            FROM workspaces w
+            JOIN workspace_members m ON m.workspace_id = w.id AND m.user_id = $1
+            LEFT JOIN documents d ON d.workspace_id = w.id AND d.deleted_at IS NULL
+            WHERE w.deleted_at IS NULL
+            GROUP BY w.id
+            ORDER BY lower(w.name), w.id
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Re-derive a workspace's storage_used_bytes and referenced_bytes from
+    /// its documents; returns storage_used_bytes
+    ///
+    /// Mirrors update_storage_usage(): soft-deleted files still occupy space
+    /// until purged.
+    pub async fn recompute_storage_usage(&self, workspace_id: Uuid) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"
+            UPDATE workspaces
+            SET storage_used_bytes = (
+                SELECT COALESCE(SUM(file_size_bytes), 0)::BIGINT
+                FROM documents
+                WHERE workspace_id = $1 AND NOT external_file
+            ), referenced_bytes = (
+                SELECT COALESCE(SUM(file_size_bytes), 0)::BIGINT
+                FROM documents
+                WHERE workspace_id = $1 AND external_file
+            ), updated_at = NOW()
+            WHERE id = $1
+            RETURNING storage_used_bytes
+            "#,
+            workspace_id
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+}
+
+/// Create the template's tags the workspace lacks and store its processing
+/// options as the workspace's defaults; returns how many tags were created
+async fn apply_template(
+    tx: &mut Transaction<'_, Postgres>,
+    workspace_id: Uuid,
+    user_id: Uuid,
+    template: &WorkspaceTemplate,
+) -> Result<u64, sqlx::Error> {
+    let created = sqlx::query!(
+        r#"
+        INSERT INTO tags (user_id, workspace_id, name)
+        SELECT $1, $2, wanted.name
+        FROM UNNEST($3::text[]) AS wanted(name)
+        WHERE NOT EXISTS (
+            SELECT 1 FROM tags t
+            WHERE t.user_id = $1 AND t.workspace_id = $2 AND lower(t.name) = lower(wanted.name)
+        )
+        ON CONFLICT DO NOTHING
+        "#,
+        user_id,
+        workspace_id,
+        &template.tag_names
+    )
+    .execute(&mut **tx)
+    .await?
+    .rows_affected();
+
+    if let Some(options) = &template.processing_options {
+        sqlx::query!(
+            "UPDATE workspaces SET processing_defaults = $2 WHERE id = $1",
+            workspace_id,
+            Json(options) as _
+        )
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(created)
+}
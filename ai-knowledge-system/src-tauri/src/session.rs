@@ -0,0 +1,25 @@
+use crate::error::AppError;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+/// Tracks which local user the app is currently acting as
+pub struct Session {
+    active_user_id: RwLock<Option<Uuid>>,
+}
+
+impl Session {
+    pub fn new(active_user_id: Option<Uuid>) -> Self {
+        Session {
+            active_user_id: RwLock::new(active_user_id),
+        }
+    }
+
+    /// The active user, or `NoActiveUser` if nobody has been selected
+    pub async fn current_user_id(&self) -> Result<Uuid, AppError> {
+        self.active_user_id.read().await.ok_or(AppError::NoActiveUser)
+    }
+
+    pub async fn set_active_user(&self, user_id: Option<Uuid>) {
+        *self.active_user_id.write().await = user_id;
+    }
+}
@@ -0,0 +1,453 @@
+use crate::file_utils;
+use crate::models::ProcessingOptions;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{watch, Mutex, RwLock};
+use uuid::Uuid;
+
+/// User-facing application settings, persisted as JSON in the app config dir
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    /// User restored into the session on startup
+    pub active_user_id: Option<Uuid>,
+
+    /// Where stored document files live; defaults to app_data_dir/documents
+    pub storage_root: Option<PathBuf>,
+
+    /// Quota usage (percent) at which uploads start warning
+    pub storage_warning_percent: f64,
+
+    /// Quota usage (percent) considered critical
+    pub storage_critical_percent: f64,
+
+    /// Seconds text extraction may run before the document is marked failed
+    pub extraction_timeout_secs: u64,
+
+    /// Seconds a single PDF page may take before it is skipped
+    pub page_timeout_secs: u64,
+
+    /// Whether scheduled backups run
+    pub backup_enabled: bool,
+
+    /// Where backups are written; required when backups are enabled
+    pub backup_dir: Option<PathBuf>,
+
+    /// Hours between scheduled backups
+    pub backup_interval_hours: u64,
+
+    /// Number of backups kept in backup_dir; older ones are deleted
+    pub keep_last_n: usize,
+
+    /// Whether stored files are encrypted; set by enable_encryption
+    pub encryption_enabled: bool,
+
+    /// Argon2 salt for the library key, base64
+    pub encryption_salt: Option<String>,
+
+    /// Known text encrypted with the library key, to reject wrong passphrases
+    pub encryption_check: Option<String>,
+
+    /// Embedding model chunks are embedded with for semantic search
+    pub embedding_model: String,
+
+    /// Price of embedding 1,000 tokens with `embedding_model`, for estimates
+    pub embedding_price_per_1k_tokens: f64,
+
+    /// Language titles are sorted for and sizes, durations and ages are
+    /// formatted in, as a BCP 47 tag such as "de" or "sv-SE"
+    pub locale: String,
+
+    /// Processing options for uploads that don't choose their own
+    pub processing_defaults: ProcessingOptions,
+
+    /// Whether each user gets a weekly digest notification
+    pub weekly_digest_enabled: bool,
+
+    /// Uploads hashed and copied at the same time; the rest wait their turn
+    pub upload_concurrency: usize,
+
+    /// Seconds a processing write keeps retrying after the database
+    /// connection fails; 0 fails on the first error
+    pub db_retry_window_secs: u64,
+
+    /// Whether a document leaves the reading list once the reader saves a
+    /// position at least 90% of the way through it
+    pub reading_list_auto_remove: bool,
+
+    /// Whether files no extractor supports are stored anyway, without
+    /// content, and processed once an extractor supports them; programs
+    /// are refused either way
+    pub accept_unsupported: bool,
+
+    /// Whether processing, backup and import outcomes are also shown as OS
+    /// notifications while the app isn't in front
+    pub notifications_native: bool,
+
+    /// Whether the local HTTP API serves share links; it only listens on
+    /// the loopback interface
+    pub local_api_enabled: bool,
+
+    /// Port the local HTTP API listens on
+    pub local_api_port: u16,
+
+    /// Whether diagnostics meant for development, like `eval_pipelines`,
+    /// may be run
+    pub debug_tools_enabled: bool,
+
+    /// Whether a document's provenance keeps the path it was imported
+    /// from; off, only how it was imported is recorded
+    pub record_import_paths: bool,
+
+    /// Uploads of a type text is extracted from that are smaller than this
+    /// many bytes are flagged as tiny; 0 flags none
+    pub tiny_file_bytes: u64,
+
+    /// Command each upload is scanned with before it is stored, e.g.
+    /// `clamscan --no-summary {path}`, with {path} standing for the file;
+    /// run directly, not through a shell. None stores uploads unscanned
+    pub virus_scan_command: Option<String>,
+
+    /// Seconds a scan may run before the scanner counts as unavailable
+    pub virus_scan_timeout_secs: u64,
+
+    /// Whether uploads are stored unscanned when the scanner can't be
+    /// started or times out; a file the scanner rejects never is
+    pub virus_scan_fail_open: bool,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        AppSettings {
+            active_user_id: None,
+            storage_root: None,
+            storage_warning_percent: 80.0,
+            storage_critical_percent: 95.0,
+            extraction_timeout_secs: 300,
+            page_timeout_secs: 30,
+            backup_enabled: false,
+            backup_dir: None,
+            backup_interval_hours: 24,
+            keep_last_n: 7,
+            encryption_enabled: false,
+            encryption_salt: None,
+            encryption_check: None,
+            embedding_model: "text-embedding-3-small".to_string(),
+            embedding_price_per_1k_tokens: 0.00002,
+            locale: "en".to_string(),
+            processing_defaults: ProcessingOptions::default(),
+            weekly_digest_enabled: false,
+            upload_concurrency: 2,
+            db_retry_window_secs: 30,
+            reading_list_auto_remove: false,
+            accept_unsupported: true,
+            notifications_native: true,
+            local_api_enabled: false,
+            local_api_port: 47_615,
+            debug_tools_enabled: false,
+            record_import_paths: true,
+            tiny_file_bytes: 100,
+            virus_scan_command: None,
+            virus_scan_timeout_secs: 120,
+            virus_scan_fail_open: false,
+        }
+    }
+}
+
+impl AppSettings {
+    /// Load settings from disk, falling back to defaults if missing or unreadable
+    ///
+    /// A file that can't be parsed is moved aside to
+    /// `settings.json.corrupt-<timestamp>` and replaced with defaults; the
+    /// path of the moved copy is returned so the user can be told.
+    pub fn load(path: &Path) -> (Self, Option<PathBuf>) {
+        let raw = match std::fs::read_to_string(path) {
+            Ok(raw) => raw,
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    eprintln!("Failed to read settings at {}: {}", path.display(), e);
+                }
+                return (AppSettings::default(), None);
+            }
+        };
+        let error = match serde_json::from_str(&raw) {
+            Ok(settings) => return (settings, None),
+            Err(e) => e,
+        };
+
+        eprintln!("Failed to parse settings at {}: {}", path.display(), error);
+        let mut corrupt_copy = path.as_os_str().to_owned();
+        corrupt_copy.push(format!(".corrupt-{}", chrono::Utc::now().format("%Y%m%d-%H%M%S")));
+        let corrupt_copy = PathBuf::from(corrupt_copy);
+        if let Err(e) = std::fs::rename(path, &corrupt_copy) {
+            // Leave the file alone rather than overwrite the only copy
+            eprintln!("Failed to move corrupt settings aside: {}", e);
+            return (AppSettings::default(), None);
+        }
+
+        let settings = AppSettings::default();
+        if let Err(e) = settings.save(path) {
+            eprintln!("Failed to write default settings to {}: {}", path.display(), e);
+        }
+        (settings, Some(corrupt_copy))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), std::io::Error> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        file_utils::write_atomic(path, json.as_bytes())
+    }
+}
+
+/// Settings as published to subscribers, numbered in order of change
+#[derive(Clone)]
+struct SettingsSnapshot {
+    version: u64,
+    settings: AppSettings,
+}
+
+/// Latest version each subscribed subsystem has applied, by name
+type Acknowledgements = HashMap<&'static str, u64>;
+
+/// Result of update_settings
+#[derive(Debug, Clone, Serialize)]
+pub struct SettingsUpdate {
+    pub settings: AppSettings,
+    /// Subsystems that applied the new settings before the command returned
+    pub acknowledged_by: Vec<&'static str>,
+}
+
+/// Shared, persisted settings held in AppState
+///
+/// Every change is published to subscribers, so subsystems holding on to
+/// settings, like the upload queue's size, follow changes while running.
+pub struct SettingsStore {
+    path: PathBuf,
+    settings: RwLock<AppSettings>,
+    /// Where an unreadable settings file was moved at startup, until reported
+    corrupt_copy: Mutex<Option<PathBuf>>,
+    changes: watch::Sender<SettingsSnapshot>,
+    acknowledgements: Arc<watch::Sender<Acknowledgements>>,
+}
+
+/// A subsystem's view of settings changes
+///
+/// Each change is to be acknowledged once it has been applied; dropping
+/// the subscriber unsubscribes.
+pub struct SettingsSubscriber {
+    name: &'static str,
+    changes: watch::Receiver<SettingsSnapshot>,
+    acknowledgements: Arc<watch::Sender<Acknowledgements>>,
+    seen: u64,
+}
+
+impl SettingsSubscriber {
+    /// Wait for the next change and return the settings after it
+    ///
+    /// Changes made while the subscriber was busy are merged into the
+    /// latest one. None once the store is gone.
+    pub async fn changed(&mut self) -> Option<AppSettings> {
+        self.changes.changed().await.ok()?;
+        let snapshot = self.changes.borrow_and_update().clone();
+        self.seen = snapshot.version;
+        Some(snapshot.settings)
+    }
+
+    /// Report the settings last returned by `changed` as applied
+    pub fn acknowledge(&self) {
+        let (name, seen) = (self.name, self.seen);
+        self.acknowledgements.send_modify(|acks| {
+            acks.insert(name, seen);
+        });
+    }
+}
+
+impl Drop for SettingsSubscriber {
+    fn drop(&mut self) {
+        let name = self.name;
+        self.acknowledgements.send_modify(|acks| {
+            acks.remove(name);
+        });
+    }
+}
+
+impl SettingsStore {
+    pub fn load(path: PathBuf) -> Self {
+        let (settings, corrupt_copy) = AppSettings::load(&path);
+        let (changes, _) = watch::channel(SettingsSnapshot {
+            version: 0,
+            settings: settings.clone(),
+        });
+        SettingsStore {
+            path,
+            settings: RwLock::new(settings),
+            corrupt_copy: Mutex::new(corrupt_copy),
+            changes,
+            acknowledgements: Arc::new(watch::channel(Acknowledgements::new()).0),
+        }
+    }
+
+    /// Follow settings changes as the subsystem `name`
+    pub fn subscribe(&self, name: &'static str) -> SettingsSubscriber {
+        let changes = self.changes.subscribe();
+        let seen = changes.borrow().version;
+        self.acknowledgements.send_modify(|acks| {
+            acks.insert(name, seen);
+        });
+        SettingsSubscriber {
+            name,
+            changes,
+            acknowledgements: Arc::clone(&self.acknowledgements),
+            seen,
+        }
+    }
+
+    /// Where the settings file was moved if it had to be reset, once
+    pub async fn take_corrupt_copy(&self) -> Option<PathBuf> {
+        self.corrupt_copy.lock().await.take()
+    }
+
+    /// Snapshot of the current settings
+    pub async fn get(&self) -> AppSettings {
+        self.settings.read().await.clone()
+    }
+
+    /// Apply a change and persist it, returning the new settings
+    pub async fn update<F>(&self, change: F) -> Result<AppSettings, std::io::Error>
+    where
+        F: FnOnce(&mut AppSettings),
+    {
+        Ok(self.apply(change).await?.0)
+    }
+
+    /// Apply a change like `update`, then wait up to `wait` for subscribers
+    /// to apply it, returning those that did
+    pub async fn update_acknowledged<F>(&self, change: F, wait: Duration) -> Result<SettingsUpdate, std::io::Error>
+    where
+        F: FnOnce(&mut AppSettings),
+    {
+        let (settings, version) = self.apply(change).await?;
+        let mut acknowledgements = self.acknowledgements.subscribe();
+        // Subscribers still busy when the wait runs out are left out
+        let _ = tokio::time::timeout(
+            wait,
+            acknowledgements.wait_for(|acks| acks.values().all(|&seen| seen >= version)),
+        )
+        .await;
+        let mut acknowledged_by: Vec<&'static str> = acknowledgements
+            .borrow()
+            .iter()
+            .filter(|&(_, &seen)| seen >= version)
+            .map(|(&name, _)| name)
+            .collect();
+        acknowledged_by.sort_unstable();
+        Ok(SettingsUpdate {
+            settings,
+            acknowledged_by,
+        })
+    }
+
+    /// Persist a change and publish it while holding the write lock, so
+    /// subscribers see changes in the order they were made
+    async fn apply<F>(&self, change: F) -> Result<(AppSettings, u64), std::io::Error>
+    where
+        F: FnOnce(&mut AppSettings),
+    {
+        let mut settings = self.settings.write().await;
+        let mut updated = settings.clone();
+        change(&mut updated);
+        updated.save(&self.path)?;
+        *settings = updated.clone();
+        let version = self.changes.borrow().version + 1;
+        self.changes.send_replace(SettingsSnapshot {
+            version,
+            settings: updated.clone(),
+        });
+        Ok((updated, version))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn as_json(settings: &AppSettings) -> serde_json::Value {
+        serde_json::to_value(settings).unwrap()
+    }
+
+    fn corrupt_copies(dir: &Path) -> Vec<PathBuf> {
+        std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.to_string_lossy().contains("settings.json.corrupt-"))
+            .collect()
+    }
+
+    #[test]
+    fn saved_settings_load_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("settings.json");
+        let settings = AppSettings {
+            upload_concurrency: 7,
+            ..AppSettings::default()
+        };
+
+        settings.save(&path).unwrap();
+        let (loaded, corrupt_copy) = AppSettings::load(&path);
+        assert_eq!(as_json(&loaded), as_json(&settings));
+        assert_eq!(corrupt_copy, None);
+    }
+
+    #[test]
+    fn a_missing_file_gives_defaults_and_is_left_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+
+        let (loaded, corrupt_copy) = AppSettings::load(&path);
+        assert_eq!(as_json(&loaded), as_json(&AppSettings::default()));
+        assert_eq!(corrupt_copy, None);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn a_truncated_file_is_kept_aside_and_replaced_with_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        let saved = AppSettings {
+            upload_concurrency: 7,
+            ..AppSettings::default()
+        };
+        let json = serde_json::to_string_pretty(&saved).unwrap();
+        // As a crash part way through a plain write would leave it
+        let truncated = &json[..json.len() / 2];
+        std::fs::write(&path, truncated).unwrap();
+
+        let (loaded, corrupt_copy) = AppSettings::load(&path);
+        assert_eq!(as_json(&loaded), as_json(&AppSettings::default()));
+        let corrupt_copy = corrupt_copy.expect("the corrupt file is moved aside");
+        assert_eq!(corrupt_copies(dir.path()), std::slice::from_ref(&corrupt_copy));
+        assert_eq!(std::fs::read_to_string(&corrupt_copy).unwrap(), truncated);
+
+        // The defaults were written, so the next start loads cleanly
+        let (reloaded, again) = AppSettings::load(&path);
+        assert_eq!(as_json(&reloaded), as_json(&AppSettings::default()));
+        assert_eq!(again, None);
+    }
+
+    #[tokio::test]
+    async fn the_store_reports_a_reset_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        std::fs::write(&path, "{\"upload_concurrency\": ").unwrap();
+
+        let store = SettingsStore::load(path);
+        let corrupt_copy = store.take_corrupt_copy().await.expect("a reset is reported");
+        assert!(corrupt_copy.exists());
+        assert_eq!(store.take_corrupt_copy().await, None);
+    }
+}
@@ -0,0 +1,56 @@
+//! Stopword lists for the languages the keyword extractor recognizes
+
+pub const ENGLISH: &[&str] = &[
+    "a", "about", "above", "after", "again", "against", "all", "also", "am", "an", "and", "any",
+    "are", "as", "at", "be", "because", "been", "before", "being", "below", "between", "both",
+    "but", "by", "can", "could", "did", "do", "does", "doing", "down", "during", "each", "et",
+    "etc", "few", "for", "from", "further", "had", "has", "have", "having", "he", "her", "here",
+    "hers", "him", "his", "how", "however", "i", "if", "in", "into", "is", "it", "its", "itself",
+    "may", "me", "might", "more", "most", "must", "my", "no", "nor", "not", "now", "of", "off",
+    "on", "once", "one", "only", "or", "other", "our", "ours", "out", "over", "own", "per",
+    "same", "she", "should", "so", "some", "such", "than", "that", "the", "their", "theirs",
+    "them", "then", "there", "these", "they", "this", "those", "through", "thus", "to", "too",
+    "two", "under", "until", "up", "upon", "us", "use", "used", "using", "very", "via", "was",
+    "we", "were", "what", "when", "where", "which", "while", "who", "whom", "why", "will",
+    "with", "within", "without", "would", "yet", "you", "your", "yours",
+];
+
+pub const GERMAN: &[&str] = &[
+    "aber", "alle", "als", "also", "am", "an", "auch", "auf", "aus", "bei", "bin", "bis", "bist",
+    "da", "damit", "dann", "das", "dass", "dem", "den", "der", "des", "die", "dies", "diese",
+    "dieser", "doch", "dort", "du", "durch", "ein", "eine", "einem", "einen", "einer", "eines",
+    "er", "es", "für", "hat", "hatte", "ich", "ihr", "im", "in", "ist", "ja", "jede", "kann",
+    "kein", "keine", "man", "mit", "nach", "nicht", "noch", "nur", "ob", "oder", "sein", "sich",
+    "sie", "sind", "so", "über", "um", "und", "uns", "unter", "vom", "von", "vor", "war", "was",
+    "weil", "wenn", "wer", "wie", "wir", "wird", "wurde", "zu", "zum", "zur",
+];
+
+pub const FRENCH: &[&str] = &[
+    "à", "au", "aux", "avec", "ce", "ces", "cette", "dans", "de", "des", "du", "elle", "en",
+    "est", "et", "eux", "il", "ils", "je", "la", "le", "les", "leur", "lui", "mais", "me",
+    "même", "mes", "moi", "mon", "ne", "nos", "notre", "nous", "on", "ou", "où", "par", "pas",
+    "pour", "qu", "que", "qui", "sa", "se", "ses", "son", "sont", "sur", "ta", "te", "tes",
+    "toi", "ton", "tu", "un", "une", "vos", "votre", "vous", "été", "être", "était",
+];
+
+pub const SPANISH: &[&str] = &[
+    "a", "al", "algo", "como", "con", "de", "del", "el", "ella", "ellos", "en", "entre", "era",
+    "es", "esta", "este", "esto", "fue", "ha", "hay", "la", "las", "le", "les", "lo", "los",
+    "más", "me", "mi", "muy", "no", "nos", "o", "para", "pero", "por", "porque", "que", "qué",
+    "se", "sin", "sobre", "son", "su", "sus", "también", "te", "tiene", "todo", "tu", "un",
+    "una", "uno", "y", "ya", "yo",
+];
+
+/// Stopwords for an ISO 639-1 language code, if we have a list for it
+pub fn for_language(code: &str) -> Option<&'static [&'static str]> {
+    match code {
+        "en" => Some(ENGLISH),
+        "de" => Some(GERMAN),
+        "fr" => Some(FRENCH),
+        "es" => Some(SPANISH),
+        _ => None,
+    }
+}
+
+/// Languages with stopword lists, in detection order
+pub const LANGUAGES: &[&str] = &["en", "de", "fr", "es"];
@@ -0,0 +1,516 @@
+use crate::error::{AppError, AppResult};
+use crate::file_store::FileStore;
+use crate::file_utils;
+use crate::host::Host;
+use crate::models::{
+    MigrationFailure, StorageLevel, StorageMigrationProgress, StorageMigrationReport, StorageStatus,
+};
+use crate::operations::OperationHandle;
+use crate::services::storage_migration::MigrationItem;
+use crate::services::user::StorageUsage;
+use crate::services::StorageMigrationService;
+use crate::settings::{AppSettings, SettingsStore};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use uuid::Uuid;
+
+/// Number of documents whose paths are rewritten per transaction
+const COMMIT_BATCH_SIZE: usize = 500;
+
+/// Longest stored file name, well under the 255-byte filesystem limit
+const MAX_STORED_NAME_BYTES: usize = 120;
+
+/// Full path length Windows handles without the extended-length prefix
+const WINDOWS_MAX_PATH: usize = 259;
+
+/// Copies from a locked source are attempted this many times
+const COPY_ATTEMPTS: u32 = 5;
+
+/// Wait before the first retry; doubles after each attempt
+const COPY_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Free space kept on a volume beyond what a copy needs, for the database,
+/// temporary files and encryption overhead
+const DISK_SPACE_MARGIN: u64 = 100 * 1024 * 1024;
+
+/// Directory where uploaded files are stored
+pub fn documents_dir(host: &dyn Host, settings: &AppSettings) -> AppResult<PathBuf> {
+    match &settings.storage_root {
+        Some(root) => Ok(root.clone()),
+        None => Ok(host.app_data_dir()?.join("documents")),
+    }
+}
+
+/// `documents_dir` with the default root under `app_data_dir`, for code
+/// running without the app, like aks-cli
+pub fn documents_dir_in(app_data_dir: &Path, settings: &AppSettings) -> PathBuf {
+    match &settings.storage_root {
+        Some(root) => root.clone(),
+        None => app_data_dir.join("documents"),
+    }
+}
+
+/// `documents_dir`, failing with `StorageOffline` if a custom root can't be reached
+///
+/// The default root lives in app data and is created on demand. A custom
+/// root is never created here: when it is missing, the drive holding it is
+/// most likely disconnected, and files written now would land on the
+/// mount point instead.
+pub fn online_documents_dir(host: &dyn Host, settings: &AppSettings) -> AppResult<PathBuf> {
+    match &settings.storage_root {
+        Some(root) => {
+            ensure_online(root)?;
+            Ok(root.clone())
+        }
+        None => online_documents_dir_in(&host.app_data_dir()?, settings),
+    }
+}
+
+/// `online_documents_dir` with the default root under `app_data_dir`
+pub fn online_documents_dir_in(app_data_dir: &Path, settings: &AppSettings) -> AppResult<PathBuf> {
+    let dir = documents_dir_in(app_data_dir, settings);
+    if settings.storage_root.is_some() {
+        ensure_online(&dir)?;
+    } else {
+        std::fs::create_dir_all(&dir)?;
+    }
+    Ok(dir)
+}
+
+/// Fail with `StorageOffline` unless the storage root itself exists
+///
+/// Tells an unmounted volume apart from a single missing file.
+pub fn ensure_online(root: &Path) -> AppResult<()> {
+    if root.is_dir() {
+        Ok(())
+    } else {
+        Err(AppError::StorageOffline(root.display().to_string()))
+    }
+}
+
+/// Fail with `InsufficientDiskSpace` unless `dir`'s volume has room for
+/// `bytes` plus a safety margin
+///
+/// Checked before copying, since a copy that runs out of space part way
+/// leaves a truncated file behind.
+pub fn ensure_disk_space(dir: &Path, bytes: u64) -> AppResult<()> {
+    let available = file_utils::available_space(dir)?;
+    let needed = bytes.saturating_add(DISK_SPACE_MARGIN);
+    if available < needed {
+        return Err(AppError::InsufficientDiskSpace { needed, available });
+    }
+    Ok(())
+}
+
+/// Copy an uploaded file into `documents_dir` under a short, unique name
+///
+/// The copy goes through `store`, so it is encrypted when encryption is on.
+/// Sources held open by another program (a sharing violation on Windows)
+/// are retried with backoff before giving up with `AppError::FileInUse`.
+/// A failed copy never leaves a partial file behind.
+pub fn store_file(
+    store: &dyn FileStore,
+    source: &Path,
+    documents_dir: &Path,
+    doc_id: Uuid,
+    original_name: &str,
+) -> AppResult<PathBuf> {
+    ensure_disk_space(documents_dir, std::fs::metadata(long_path(source))?.len())?;
+    let dest = documents_dir.join(stored_file_name(documents_dir, doc_id, original_name));
+
+    retry_if_locked(original_name, || {
+        let mut file = std::fs::File::open(long_path(source))?;
+        store.write(&mut file, &long_path(&dest))
+    })
+    .inspect_err(|_| {
+        let _ = std::fs::remove_file(long_path(&dest));
+    })?;
+    Ok(dest)
+}
+
+/// Run a file operation, retrying with backoff while the file is locked
+///
+/// `name` identifies the file in the `AppError::FileInUse` returned once
+/// the attempts run out.
+pub fn retry_if_locked<T>(
+    name: &str,
+    mut operation: impl FnMut() -> std::io::Result<T>,
+) -> AppResult<T> {
+    let mut delay = COPY_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match operation() {
+            Ok(value) => return Ok(value),
+            Err(e) if is_locked(&e) && attempt < COPY_ATTEMPTS => {
+                std::thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(e) if is_locked(&e) => return Err(AppError::FileInUse(name.to_string())),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Id of the document a stored file belongs to, from the `{document id}_`
+/// its name starts with
+pub fn stored_document_id(path: &Path) -> Option<Uuid> {
+    let name = path.file_name()?.to_str()?;
+    let (id, _) = name.split_once('_')?;
+    Uuid::parse_str(id).ok()
+}
+
+/// `{document id}_{original name}`, sanitized and truncated but keeping its
+/// extension
+///
+/// The name is shortened further if the full path would pass the Windows
+/// MAX_PATH limit; below that the document id alone keeps it unique.
+fn stored_file_name(documents_dir: &Path, doc_id: Uuid, original_name: &str) -> String {
+    let original_name = file_utils::sanitize_filename(original_name);
+    let original = Path::new(&original_name);
+    let extension = original
+        .extension()
+        .and_then(|e| e.to_str())
+        .filter(|e| e.len() <= 16)
+        .map(|e| format!(".{}", e))
+        .unwrap_or_default();
+    let stem = original
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default();
+
+    let prefix = format!("{}_", doc_id);
+    let dir_len = documents_dir.as_os_str().len() + 1;
+    let budget = MAX_STORED_NAME_BYTES
+        .min(WINDOWS_MAX_PATH.saturating_sub(dir_len))
+        .saturating_sub(prefix.len() + extension.len());
+
+    let mut end = stem.len().min(budget);
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    let stem = stem[..end].trim_end_matches(['.', ' ']);
+
+    if stem.is_empty() {
+        format!("{}{}", doc_id, extension)
+    } else {
+        format!("{}{}{}", prefix, stem, extension)
+    }
+}
+
+/// Add the `\\?\` prefix to absolute Windows paths longer than MAX_PATH
+#[cfg(windows)]
+pub fn long_path(path: &Path) -> PathBuf {
+    let raw = path.as_os_str().to_string_lossy();
+    if raw.len() <= WINDOWS_MAX_PATH || !path.is_absolute() || raw.starts_with(r"\\?\") {
+        return path.to_path_buf();
+    }
+    match raw.strip_prefix(r"\\") {
+        Some(unc) => PathBuf::from(format!(r"\\?\UNC\{}", unc)),
+        None => PathBuf::from(format!(r"\\?\{}", raw)),
+    }
+}
+
+#[cfg(not(windows))]
+pub fn long_path(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+/// Whether a copy failed because another program holds the file open
+fn is_locked(e: &std::io::Error) -> bool {
+    // ERROR_SHARING_VIOLATION, ERROR_LOCK_VIOLATION
+    cfg!(windows) && matches!(e.raw_os_error(), Some(32) | Some(33))
+}
+
+/// Classify quota usage against the configured thresholds; referenced
+/// files are reported but don't count
+pub fn storage_status(usage: &StorageUsage, settings: &AppSettings) -> StorageStatus {
+    let percentage = if usage.limit_bytes > 0 {
+        usage.managed_bytes as f64 / usage.limit_bytes as f64 * 100.0
+    } else {
+        100.0
+    };
+    let level = if percentage >= settings.storage_critical_percent {
+        StorageLevel::Critical
+    } else if percentage >= settings.storage_warning_percent {
+        StorageLevel::Warning
+    } else {
+        StorageLevel::Ok
+    };
+
+    StorageStatus {
+        managed_bytes: usage.managed_bytes,
+        referenced_bytes: usage.referenced_bytes,
+        limit_bytes: usage.limit_bytes,
+        percentage,
+        level,
+    }
+}
+
+/// Move every stored file to `target_root`, resuming an unfinished run if any
+///
+/// Files are copied and hash-verified first, then all rows are repointed in
+/// batched transactions, and only then are the old copies removed. Per-document
+/// state is persisted so a run interrupted at any point can be re-invoked.
+/// Cancelling stops between copies, before any row is repointed.
+pub async fn migrate_storage(
+    app: &AppHandle,
+    service: &StorageMigrationService,
+    settings: &SettingsStore,
+    current_root: &Path,
+    target_root: &Path,
+    dry_run: bool,
+    operation: &OperationHandle,
+) -> AppResult<StorageMigrationReport> {
+    if target_root == current_root {
+        return Err(AppError::InvalidInput("Target is already the storage location".to_string()));
+    }
+    std::fs::create_dir_all(target_root)?;
+    file_utils::check_writable(target_root)
+        .map_err(|e| AppError::InvalidInput(format!("Target is not writable: {}", e)))?;
+
+    let target_str = target_root.to_string_lossy().to_string();
+    let existing = service.find_active(&target_str).await?;
+    let resumed = existing.is_some();
+
+    // Files still to be copied: anything not already under the target
+    let stored = service.list_stored_files().await?;
+    let pending: Vec<(Uuid, PathBuf)> = stored
+        .into_iter()
+        .map(|(id, path)| (id, PathBuf::from(path)))
+        .filter(|(_, path)| !path.starts_with(target_root))
+        .collect();
+    let required_bytes: u64 = pending
+        .iter()
+        .filter_map(|(_, path)| std::fs::metadata(path).ok())
+        .map(|m| m.len())
+        .sum();
+    let available_bytes = file_utils::available_space(target_root)?;
+
+    let mut report = StorageMigrationReport {
+        migration_id: existing,
+        dry_run,
+        resumed,
+        document_count: pending.len(),
+        required_bytes,
+        available_bytes,
+        migrated: 0,
+        failed: Vec::new(),
+        cancelled_after: None,
+    };
+
+    if dry_run {
+        return Ok(report);
+    }
+    ensure_disk_space(target_root, required_bytes)?;
+
+    let migration_id = match existing {
+        Some(id) => id,
+        None => {
+            service
+                .create(&current_root.to_string_lossy(), &target_str)
+                .await?
+        }
+    };
+    report.migration_id = Some(migration_id);
+
+    let mut ids = Vec::with_capacity(pending.len());
+    let mut sources = Vec::with_capacity(pending.len());
+    let mut dests = Vec::with_capacity(pending.len());
+    for (id, path) in &pending {
+        let Some(file_name) = path.file_name() else {
+            continue;
+        };
+        ids.push(*id);
+        sources.push(path.to_string_lossy().to_string());
+        dests.push(target_root.join(file_name).to_string_lossy().to_string());
+    }
+    service.add_items(migration_id, &ids, &sources, &dests).await?;
+
+    let items = service.get_items(migration_id).await?;
+    let total = items.len();
+
+    // Phase 1: copy and verify
+    for (index, item) in items.iter().enumerate() {
+        if operation.is_cancelled() {
+            report.cancelled_after = Some(index);
+            return Ok(report);
+        }
+        if item.state == "pending" || item.state == "failed" {
+            match copy_verified(item).await {
+                Ok(()) => service.set_item_state(migration_id, item.document_id, "copied", None).await?,
+                Err(e) => {
+                    service
+                        .set_item_state(migration_id, item.document_id, "failed", Some(e.clone()))
+                        .await?;
+                    report.failed.push(MigrationFailure {
+                        document_id: item.document_id,
+                        error: e,
+                    });
+                }
+            }
+        }
+        operation.set_progress(index + 1, total);
+        emit_progress(app, operation, migration_id, "copy", index + 1, total, Some(item.document_id));
+    }
+
+    // Phase 2: repoint rows in batches
+    let items = service.get_items(migration_id).await?;
+    let copied: Vec<&MigrationItem> = items.iter().filter(|i| i.state == "copied").collect();
+    for (batch_index, batch) in copied.chunks(COMMIT_BATCH_SIZE).enumerate() {
+        let ids: Vec<Uuid> = batch.iter().map(|i| i.document_id).collect();
+        let dests: Vec<String> = batch.iter().map(|i| i.dest_path.clone()).collect();
+        service.commit_paths(migration_id, &ids, &dests).await?;
+        let processed = ((batch_index + 1) * COMMIT_BATCH_SIZE).min(copied.len());
+        emit_progress(app, operation, migration_id, "update", processed, copied.len(), None);
+    }
+
+    // New uploads go to the target once every file lives there
+    if report.failed.is_empty() {
+        let new_root = target_root.to_path_buf();
+        settings.update(|s| s.storage_root = Some(new_root)).await?;
+    }
+
+    // Phase 3: remove the originals now that nothing references them
+    let items = service.get_items(migration_id).await?;
+    let updated: Vec<&MigrationItem> = items.iter().filter(|i| i.state == "updated").collect();
+    for (index, item) in updated.iter().enumerate() {
+        match std::fs::remove_file(&item.source_path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => eprintln!("Failed to remove migrated file {}: {}", item.source_path, e),
+        }
+        service.set_item_state(migration_id, item.document_id, "removed", None).await?;
+        emit_progress(app, operation, migration_id, "cleanup", index + 1, updated.len(), Some(item.document_id));
+    }
+
+    report.migrated = items
+        .iter()
+        .filter(|i| i.state == "updated" || i.state == "removed")
+        .count();
+    if report.failed.is_empty() {
+        service.complete(migration_id).await?;
+    }
+
+    Ok(report)
+}
+
+/// Copy a file to its destination and confirm the hashes match
+async fn copy_verified(item: &MigrationItem) -> Result<(), String> {
+    let source = PathBuf::from(&item.source_path);
+    let dest = PathBuf::from(&item.dest_path);
+
+    tokio::task::spawn_blocking(move || {
+        let source_hash = file_utils::calculate_sha256(&source).map_err(|e| e.to_string())?;
+        if let Err(e) = std::fs::copy(&source, &dest) {
+            let _ = std::fs::remove_file(&dest);
+            return Err(e.to_string());
+        }
+        let dest_hash = file_utils::calculate_sha256(&dest).map_err(|e| e.to_string())?;
+        if source_hash != dest_hash {
+            let _ = std::fs::remove_file(&dest);
+            return Err("Hash mismatch after copy".to_string());
+        }
+        Ok(())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+fn emit_progress(
+    app: &AppHandle,
+    operation: &OperationHandle,
+    migration_id: Uuid,
+    phase: &str,
+    processed: usize,
+    total: usize,
+    document_id: Option<Uuid>,
+) {
+    let _ = app.emit(
+        "storage:migration-progress",
+        StorageMigrationProgress {
+            operation_id: operation.id(),
+            migration_id,
+            phase: phase.to_string(),
+            processed,
+            total,
+            document_id,
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC_ID: &str = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+
+    fn stored(documents_dir: &str, original_name: &str) -> String {
+        stored_file_name(Path::new(documents_dir), Uuid::parse_str(DOC_ID).unwrap(), original_name)
+    }
+
+    #[test]
+    fn stored_names_start_with_the_document_id() {
+        let name = stored("/library/documents", "Annual Report.pdf");
+        assert_eq!(name, format!("{}_Annual Report.pdf", DOC_ID));
+        assert_eq!(stored_document_id(Path::new(&name)), Uuid::parse_str(DOC_ID).ok());
+        assert_eq!(stored_document_id(Path::new("/library/notes.txt")), None);
+    }
+
+    #[test]
+    fn stored_names_are_sanitized() {
+        assert_eq!(stored("/library", "a/b\u{7}c.txt"), format!("{}_a_b_c.txt", DOC_ID));
+        assert_eq!(stored("/library", "draft. . .md"), format!("{}_draft.md", DOC_ID));
+    }
+
+    #[test]
+    fn long_names_are_cut_keeping_the_extension() {
+        let name = stored("/library", &format!("{}.pdf", "x".repeat(300)));
+        assert_eq!(name.len(), MAX_STORED_NAME_BYTES);
+        assert!(name.ends_with("xxx.pdf"), "{}", name);
+
+        // Cut on a char boundary, never inside one
+        let name = stored("/library", &format!("{}.pdf", "ü".repeat(100)));
+        assert_eq!(name, format!("{}_{}.pdf", DOC_ID, "ü".repeat(39)));
+    }
+
+    #[test]
+    fn an_overlong_extension_is_not_kept_as_one() {
+        let name = stored("/library", "archive.abcdefghijklmnopq");
+        assert!(!name.ends_with(".abcdefghijklmnopq"), "{}", name);
+        assert!(name.starts_with(DOC_ID));
+    }
+
+    #[test]
+    fn deep_directories_shorten_the_name_to_fit_max_path() {
+        let dir = format!("/{}", "d".repeat(199));
+        let name = stored(&dir, "A fairly long original file name.pdf");
+        assert_eq!(name, format!("{}_A fairly long ori.pdf", DOC_ID));
+        assert_eq!(dir.len() + 1 + name.len(), WINDOWS_MAX_PATH);
+
+        // No room for any of the original name: the id keeps it unique
+        let dir = format!("/{}", "d".repeat(229));
+        assert_eq!(stored(&dir, "Report.pdf"), format!("{}.pdf", DOC_ID));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn long_windows_paths_get_the_extended_length_prefix() {
+        let short = Path::new(r"C:\library\report.pdf");
+        assert_eq!(long_path(short), short);
+
+        let long = format!(r"C:\library\{}.pdf", "x".repeat(300));
+        assert_eq!(long_path(Path::new(&long)), PathBuf::from(format!(r"\\?\{}", long)));
+        let prefixed = PathBuf::from(format!(r"\\?\{}", long));
+        assert_eq!(long_path(&prefixed), prefixed);
+
+        let unc = format!(r"\\server\share\{}.pdf", "x".repeat(300));
+        assert_eq!(
+            long_path(Path::new(&unc)),
+            PathBuf::from(format!(r"\\?\UNC\server\share\{}.pdf", "x".repeat(300)))
+        );
+        let relative = format!(r"library\{}.pdf", "x".repeat(300));
+        assert_eq!(long_path(Path::new(&relative)), PathBuf::from(&relative));
+    }
+}
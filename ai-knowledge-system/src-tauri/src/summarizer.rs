@@ -0,0 +1,471 @@
+//! Summaries made by the kind of file
+//!
+//! Each kind has a strategy that knows where its files keep their gist: the
+//! header of a CSV, the subject of an email, the abstract of a paper, the
+//! comment opening a source file. When a strategy finds nothing, the first
+//! paragraph or leading text is used instead.
+
+use crate::models::{Document, DocumentSummary, SummarySource};
+use serde::{Deserialize, Serialize};
+
+/// Longest summary, in chars
+pub const SUMMARY_CHARS: usize = 500;
+
+/// Version of the summary strategies; bump it when summaries would come out
+/// differently, so `rebuild_derived_data` redoes older ones
+pub const SUMMARIZER_VERSION: i32 = 1;
+
+/// Column names listed in a CSV summary; further ones are only counted
+const MAX_CSV_COLUMNS: usize = 20;
+
+/// Non-empty body lines after an email's subject
+const EMAIL_BODY_LINES: usize = 3;
+
+/// Extensions of source code, summarized by their first comment block
+const CODE_EXTENSIONS: &[&str] = &[
+    "rs", "py", "js", "jsx", "ts", "tsx", "go", "java", "kt", "c", "h", "cc", "cpp", "hpp", "cs", "swift", "rb",
+    "php", "sh", "bash", "sql", "lua", "scala", "r", "toml", "yaml", "yml",
+];
+
+/// Headings that can follow an abstract without a blank line between them
+const AFTER_ABSTRACT_PREFIXES: &[&str] = &["keywords", "key words", "index terms", "ccs concepts"];
+const INTRODUCTION_HEADINGS: &[&str] = &["introduction", "1 introduction", "1. introduction", "i. introduction"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileKind {
+    Csv,
+    Email,
+    /// PDFs and Markdown, which may be papers with an abstract
+    Document,
+    Code,
+    Text,
+    Other,
+}
+
+impl FileKind {
+    /// Kind of a file from its MIME type and lowercase extension
+    pub fn detect(mime: &str, extension: &str) -> Self {
+        match (mime, extension) {
+            ("text/csv" | "text/tab-separated-values", _) | (_, "csv" | "tsv") => FileKind::Csv,
+            ("message/rfc822", _) | (_, "eml") => FileKind::Email,
+            ("application/pdf" | "text/markdown", _) | (_, "pdf" | "md" | "markdown") => FileKind::Document,
+            (_, extension) if CODE_EXTENSIONS.contains(&extension) => FileKind::Code,
+            ("text/plain", _) | (_, "txt" | "text" | "log") => FileKind::Text,
+            _ => FileKind::Other,
+        }
+    }
+
+    /// Kind of a stored document's file, from its MIME type and file name
+    pub fn of_document(document: &Document) -> Self {
+        let extension = document
+            .file_name
+            .as_deref()
+            .and_then(|name| std::path::Path::new(name).extension())
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .unwrap_or_default();
+        FileKind::detect(document.mime_type.as_deref().unwrap_or_default(), &extension)
+    }
+}
+
+/// Summarize extracted text with the strategy for its kind of file
+///
+/// `pages` is the text of each page when the extractor had pages, so an
+/// abstract running onto the next page can be followed there.
+pub fn summarize(kind: FileKind, text: &str, pages: &[String]) -> DocumentSummary {
+    let specific = match kind {
+        FileKind::Csv => csv_summary(text).map(|s| (s, SummarySource::CsvColumns)),
+        FileKind::Email => email_summary(text).map(|s| (s, SummarySource::Email)),
+        FileKind::Document => abstract_summary(text, pages).map(|s| (s, SummarySource::Abstract)),
+        FileKind::Code | FileKind::Text => comment_summary(text).map(|s| (s, SummarySource::CommentBlock)),
+        FileKind::Other => None,
+    };
+
+    match specific {
+        Some((summary, source)) => DocumentSummary {
+            summary: preview(&summary, SUMMARY_CHARS),
+            source,
+        },
+        None => DocumentSummary {
+            summary: generic_summary(text, SUMMARY_CHARS),
+            source: SummarySource::Generic,
+        },
+    }
+}
+
+/// The start of a text, cut at a word boundary
+fn preview(text: &str, max_chars: usize) -> String {
+    let trimmed = text.trim();
+    match trimmed.char_indices().nth(max_chars) {
+        None => trimmed.to_string(),
+        Some((end, _)) => {
+            let preview = &trimmed[..end];
+            // Try to break at word boundary
+            match preview.rfind(' ') {
+                Some(last_space) => format!("{}...", &preview[..last_space]),
+                None => format!("{}...", preview),
+            }
+        }
+    }
+}
+
+/// First paragraph, or the first `max_chars` chars when it's longer
+fn generic_summary(text: &str, max_chars: usize) -> String {
+    let trimmed = text.trim();
+
+    // Try to get first paragraph
+    if let Some(first_para_end) = trimmed.find("\n\n") {
+        let first_para = &trimmed[..first_para_end];
+        if first_para.chars().count() <= max_chars {
+            return first_para.to_string();
+        }
+    }
+
+    // Fall back to character limit
+    preview(trimmed, max_chars)
+}
+
+/// Column names and row count
+///
+/// The delimiter is whichever of comma, tab and semicolon the header line
+/// has most of.
+fn csv_summary(text: &str) -> Option<String> {
+    let header = text.lines().next()?;
+    // Ties go to the comma, the last one checked
+    let delimiter = [b';', b'\t', b',']
+        .into_iter()
+        .max_by_key(|&d| header.matches(d as char).count())
+        .unwrap_or(b',');
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let columns: Vec<String> = reader
+        .headers()
+        .ok()?
+        .iter()
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .map(str::to_string)
+        .collect();
+    if columns.is_empty() {
+        return None;
+    }
+    let rows = reader.into_records().filter_map(Result::ok).count();
+
+    let mut summary = format!("Columns: {}", columns[..columns.len().min(MAX_CSV_COLUMNS)].join(", "));
+    if columns.len() > MAX_CSV_COLUMNS {
+        summary.push_str(&format!(" and {} more", columns.len() - MAX_CSV_COLUMNS));
+    }
+    summary.push_str(&format!("; {} row{}", rows, if rows == 1 { "" } else { "s" }));
+    Some(summary)
+}
+
+/// Subject and the first lines of the body
+///
+/// Headers end at the first blank line; folded headers are joined and
+/// quoted lines of the body skipped. None when the text doesn't start
+/// with a header.
+fn email_summary(text: &str) -> Option<String> {
+    let mut lines = text.lines();
+    let mut headers: Vec<String> = Vec::new();
+    for line in lines.by_ref() {
+        if line.trim().is_empty() {
+            break;
+        }
+        match headers.last_mut() {
+            // Folded: the header continues on an indented line
+            Some(last) if line.starts_with([' ', '\t']) => {
+                last.push(' ');
+                last.push_str(line.trim());
+            }
+            _ => headers.push(line.to_string()),
+        }
+    }
+
+    let is_header = |line: &String| {
+        line.split_once(':')
+            .is_some_and(|(name, _)| !name.is_empty() && !name.contains(char::is_whitespace))
+    };
+    if !headers.first().is_some_and(is_header) {
+        return None;
+    }
+    let subject = headers.iter().find_map(|header| {
+        let (name, value) = header.split_once(':')?;
+        name.eq_ignore_ascii_case("subject")
+            .then(|| value.trim().to_string())
+            .filter(|v| !v.is_empty())
+    });
+    let body: Vec<&str> = lines
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('>'))
+        .take(EMAIL_BODY_LINES)
+        .collect();
+
+    match (subject, body.is_empty()) {
+        (Some(subject), true) => Some(subject),
+        (Some(subject), false) => Some(format!("{}\n\n{}", subject, body.join(" "))),
+        (None, false) => Some(body.join(" ")),
+        (None, true) => None,
+    }
+}
+
+/// The paragraph after an "Abstract" heading
+///
+/// The heading may stand on its own line ("ABSTRACT") or open the
+/// paragraph ("Abstract. We…", "Abstract—We…"). An abstract that reaches
+/// the end of its page mid-sentence continues with the first paragraph of
+/// the next page.
+fn abstract_summary(text: &str, pages: &[String]) -> Option<String> {
+    let pages: Vec<&str> = if pages.is_empty() {
+        vec![text]
+    } else {
+        pages.iter().map(String::as_str).collect()
+    };
+
+    for (index, page) in pages.iter().enumerate() {
+        let Some(start) = find_abstract(page) else {
+            continue;
+        };
+        let (mut summary, reached_page_end) = first_paragraph(start);
+        if reached_page_end && !summary.trim_end().ends_with(['.', '!', '?']) {
+            if let Some(next) = pages.get(index + 1) {
+                let (rest, _) = first_paragraph(next);
+                join_line(&mut summary, &rest);
+            }
+        }
+        if !summary.is_empty() {
+            return Some(summary);
+        }
+    }
+    None
+}
+
+/// Text following the first abstract heading of a page
+fn find_abstract(page: &str) -> Option<&str> {
+    let mut offset = 0;
+    for line in page.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        let rest = trimmed
+            .strip_prefix("ABSTRACT")
+            .map(|rest| (rest, true))
+            .or_else(|| trimmed.strip_prefix("Abstract").map(|rest| (rest, false)));
+
+        if let Some((rest, upper_case)) = rest {
+            let after = rest.trim_start();
+            // "Abstraction…" or "Abstract ideas…" aren't headings
+            let is_heading = !rest.starts_with(char::is_alphanumeric)
+                && (upper_case
+                    || after.is_empty()
+                    || after.starts_with(['.', ':', '—', '–', '-'])
+                    || after.starts_with(char::is_uppercase));
+            if is_heading {
+                let start = offset + line.len() - rest.len();
+                return Some(
+                    page[start..].trim_start_matches(|c: char| c.is_whitespace() || matches!(c, '.' | ':' | '—' | '–' | '-')),
+                );
+            }
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// Lines up to the first blank line or section heading, joined, and whether
+/// the paragraph ran to the end of the text
+///
+/// A page number on a line of its own before the paragraph is skipped.
+fn first_paragraph(text: &str) -> (String, bool) {
+    let mut paragraph = String::new();
+    for line in text.lines().map(str::trim) {
+        if paragraph.is_empty() && (line.is_empty() || line.chars().all(|c| c.is_ascii_digit())) {
+            continue;
+        }
+        if line.is_empty() || starts_section(line) {
+            return (paragraph, false);
+        }
+        join_line(&mut paragraph, line);
+    }
+    (paragraph, true)
+}
+
+fn starts_section(line: &str) -> bool {
+    let lower = line.to_lowercase();
+    AFTER_ABSTRACT_PREFIXES.iter().any(|prefix| lower.starts_with(prefix))
+        || INTRODUCTION_HEADINGS.contains(&lower.trim_end_matches(['.', ':']))
+}
+
+/// Append a line to running text, rejoining a word hyphenated across them
+fn join_line(text: &mut String, line: &str) {
+    if line.is_empty() {
+        return;
+    }
+    let hyphenated = text
+        .strip_suffix('-')
+        .is_some_and(|before| before.ends_with(char::is_alphabetic));
+    if hyphenated {
+        text.pop();
+    } else if !text.is_empty() {
+        text.push(' ');
+    }
+    text.push_str(line);
+}
+
+/// The first comment block: `//`, `#`, `--` or `;` lines, a `/* */` block
+/// or a Python docstring, after any shebang and blank lines
+fn comment_summary(text: &str) -> Option<String> {
+    let mut lines = text.lines().map(str::trim).peekable();
+    lines.next_if(|line| line.starts_with("#!") && !line.starts_with("#!["));
+    while lines.next_if(|line| line.is_empty()).is_some() {}
+    let first = *lines.peek()?;
+
+    let mut comment = String::new();
+    if let Some(delimiter) = ["/*", "\"\"\"", "'''"].into_iter().find(|d| first.starts_with(d)) {
+        let close = if delimiter == "/*" { "*/" } else { delimiter };
+        for (index, line) in lines.enumerate() {
+            let line = if index == 0 { &line[delimiter.len()..] } else { line };
+            let (content, closed) = match line.find(close) {
+                Some(end) => (&line[..end], true),
+                None => (line, false),
+            };
+            join_comment_line(&mut comment, content.trim_start_matches('*'));
+            if closed {
+                break;
+            }
+        }
+    } else if let Some(prefix) = ["//", "#", "--", ";"].into_iter().find(|p| is_line_comment(first, p)) {
+        for line in lines.take_while(|line| is_line_comment(line, prefix)) {
+            join_comment_line(&mut comment, line[prefix.len()..].trim_start_matches(['/', '!', '#', '-', ';']));
+        }
+    }
+
+    (!comment.is_empty()).then_some(comment)
+}
+
+/// Whether a line is a comment starting with `prefix`; `#include` and
+/// `#[attr]` aren't comments
+fn is_line_comment(line: &str, prefix: &str) -> bool {
+    match line.strip_prefix(prefix) {
+        Some(rest) if prefix == "#" => rest.is_empty() || rest.starts_with([' ', '\t', '#']),
+        Some(_) => true,
+        None => false,
+    }
+}
+
+/// Add a comment line's words, leaving out rulers like `*****` or `=====`
+fn join_comment_line(comment: &mut String, line: &str) {
+    let line = line.trim();
+    if line.contains(char::is_alphanumeric) {
+        join_line(comment, line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(kind: FileKind, text: &str) -> (String, SummarySource) {
+        let summary = summarize(kind, text, &[]);
+        (summary.summary, summary.source)
+    }
+
+    #[test]
+    fn kinds_come_from_mime_type_or_extension() {
+        assert_eq!(FileKind::detect("text/plain", "csv"), FileKind::Csv);
+        assert_eq!(FileKind::detect("message/rfc822", ""), FileKind::Email);
+        assert_eq!(FileKind::detect("application/octet-stream", "md"), FileKind::Document);
+        assert_eq!(FileKind::detect("text/plain", "rs"), FileKind::Code);
+        assert_eq!(FileKind::detect("text/plain", ""), FileKind::Text);
+        assert_eq!(FileKind::detect("image/png", "png"), FileKind::Other);
+    }
+
+    #[test]
+    fn csv_summaries_list_columns_and_count_rows() {
+        let text = "date;amount;category\n2024-01-02;12.50;food\n2024-01-03;40;travel\n";
+        assert_eq!(
+            summary(FileKind::Csv, text),
+            ("Columns: date, amount, category; 2 rows".to_string(), SummarySource::CsvColumns)
+        );
+        assert_eq!(summary(FileKind::Csv, "name,email\nAda,ada@example.com").0, "Columns: name, email; 1 row");
+
+        let wide: Vec<String> = (1..=25).map(|i| format!("c{}", i)).collect();
+        let (wide, _) = summary(FileKind::Csv, &wide.join(","));
+        assert!(wide.ends_with("c20 and 5 more; 0 rows"), "{}", wide);
+    }
+
+    #[test]
+    fn email_summaries_take_the_subject_and_unquoted_body() {
+        let text = "From: dana@example.com\nSubject: Backup\n restore drill\n\n> Skip it?\nThursday morning.\n\nBring laptops.\nNine sharp.\nSigned\n";
+        assert_eq!(
+            summary(FileKind::Email, text),
+            (
+                "Backup restore drill\n\nThursday morning. Bring laptops. Nine sharp.".to_string(),
+                SummarySource::Email
+            )
+        );
+        // Without headers it is just text
+        assert_eq!(summary(FileKind::Email, "Dear team,\n\nhello").1, SummarySource::Generic);
+    }
+
+    #[test]
+    fn abstracts_are_found_under_each_heading_style() {
+        let styles = [
+            "Title\n\nABSTRACT\nWe study retrieval.\n\n1 Introduction\n",
+            "Title\n\nAbstract. We study retrieval.\nKeywords: search\n",
+            "Title\nAbstract\u{2014}We study retrieval.\n",
+            "Title\n\nAbstract\n\nWe study retrieval.\n\nIntroduction\n",
+        ];
+        for text in styles {
+            assert_eq!(
+                summary(FileKind::Document, text),
+                ("We study retrieval.".to_string(), SummarySource::Abstract),
+                "{:?}",
+                text
+            );
+        }
+        let (_, source) = summary(FileKind::Document, "Abstraction layers\n\nAbstract ideas are hard.\n");
+        assert_eq!(source, SummarySource::Generic);
+    }
+
+    #[test]
+    fn abstracts_continue_across_a_page_boundary() {
+        let pages = [
+            "Title\n\nAbstract\nWe study sparse retrie-".to_string(),
+            "2\nval for laptops.\n\nIntroduction\n".to_string(),
+        ];
+        let summary = summarize(FileKind::Document, &pages.join("\n"), &pages);
+        assert_eq!(summary.summary, "We study sparse retrieval for laptops.");
+
+        // A finished sentence doesn't continue
+        let pages = ["Abstract\nWe study retrieval.".to_string(), "Unrelated footer".to_string()];
+        assert_eq!(summarize(FileKind::Document, "", &pages).summary, "We study retrieval.");
+    }
+
+    #[test]
+    fn code_summaries_take_the_first_comment_block() {
+        let rust = "//! Rotate logs\n//! older than ninety days\nuse std::fs;\n";
+        assert_eq!(
+            summary(FileKind::Code, rust),
+            ("Rotate logs older than ninety days".to_string(), SummarySource::CommentBlock)
+        );
+        let python = "#!/usr/bin/env python\n\n\"\"\"Archive old\nreports.\"\"\"\nimport gzip\n";
+        assert_eq!(summary(FileKind::Code, python).0, "Archive old reports.");
+        let c = "/*****\n * Parse the config\n *****/\n#include <stdio.h>\n";
+        assert_eq!(summary(FileKind::Code, c).0, "Parse the config");
+        assert_eq!(summary(FileKind::Code, "#include <stdio.h>\nint main();").1, SummarySource::Generic);
+    }
+
+    #[test]
+    fn the_generic_summary_is_the_first_paragraph_or_leading_text() {
+        assert_eq!(
+            summary(FileKind::Other, "  First paragraph.\n\nSecond one."),
+            ("First paragraph.".to_string(), SummarySource::Generic)
+        );
+        let long = "word ".repeat(200);
+        let (cut, _) = summary(FileKind::Other, &long);
+        assert!(cut.ends_with("word..."), "{}", cut);
+        assert!(cut.chars().count() <= SUMMARY_CHARS + 3);
+    }
+}
@@ -0,0 +1,495 @@
+//! Builders for tests that need a database and a library on disk
+//!
+//! They need a PostgreSQL server to create databases on, given as
+//! AKS_TEST_DATABASE_URL, e.g. postgresql://postgres@localhost/postgres.
+//! Without it the builders return None and the tests pass without doing
+//! anything. Each `TestLibrary` gets a database of its own with the app's
+//! schema and a temporary directory for its files. Dropping it removes
+//! both, also when the test panics.
+
+use crate::db::Database;
+use crate::error::AppResult;
+use crate::host::Host;
+use crate::models::{Document, DocumentStatus, StorageMode, TrashedMatchAction, UploadFileRequest, UploadFileResponse, UploadOutcome};
+use crate::processing::ProcessingRegistry;
+use crate::services::DocumentChanges;
+use crate::settings::SettingsStore;
+use crate::AppState;
+use sqlx::postgres::PgConnectOptions;
+use sqlx::{ConnectOptions, Executor, PgPool};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How long `wait_until_processed` waits before failing the test
+const PROCESSING_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The server tests create databases on; None when AKS_TEST_DATABASE_URL
+/// isn't set
+pub fn test_server() -> Option<PgConnectOptions> {
+    let url = std::env::var("AKS_TEST_DATABASE_URL").ok()?;
+    Some(PgConnectOptions::from_str(&url).expect("AKS_TEST_DATABASE_URL is not a valid URL"))
+}
+
+/// A name for a database of one test
+pub fn throwaway_database_name() -> String {
+    format!("aks_test_{}", Uuid::new_v4().simple())
+}
+
+/// Drop a test's database, closing whatever connections it still has
+pub async fn drop_database(server: &PgConnectOptions, database: &str) -> Result<(), sqlx::Error> {
+    let mut conn = server.clone().database("postgres").connect().await?;
+    conn.execute(format!("DROP DATABASE IF EXISTS \"{}\" WITH (FORCE)", database).as_str())
+        .await?;
+    Ok(())
+}
+
+/// A database with the app's schema, dropped with this
+pub struct TestDb {
+    server: PgConnectOptions,
+    name: String,
+    pub pool: PgPool,
+}
+
+impl TestDb {
+    pub async fn create() -> Option<TestDb> {
+        let server = test_server()?;
+        let name = throwaway_database_name();
+        let db = Database::open_with(&server.clone().database(&name))
+            .await
+            .unwrap_or_else(|e| panic!("Could not create test database {}: {:?}", name, e));
+        Some(TestDb {
+            server,
+            name,
+            pool: db.pool,
+        })
+    }
+}
+
+impl Drop for TestDb {
+    fn drop(&mut self) {
+        // Drop can't await, and may run while the test's runtime unwinds, so
+        // the database is dropped from a thread with a runtime of its own
+        let (server, name) = (self.server.clone(), self.name.clone());
+        let dropped = std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+            runtime
+                .block_on(drop_database(&server, &name))
+                .map_err(std::io::Error::other)
+        })
+        .join();
+        if !matches!(dropped, Ok(Ok(()))) {
+            eprintln!("Could not drop test database {}", self.name);
+        }
+    }
+}
+
+/// Stands in for the app window: keeps data in a temporary directory and
+/// remembers the events it was sent
+pub struct TestHost {
+    data_dir: PathBuf,
+    events: Mutex<Vec<(String, serde_json::Value)>>,
+}
+
+impl TestHost {
+    /// Names of the events sent so far, oldest first
+    pub fn event_names(&self) -> Vec<String> {
+        self.events.lock().unwrap().iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    /// Fields of each "documents:changed" event about `document_id` so far,
+    /// with the status it gave
+    pub fn document_changes(&self, document_id: Uuid) -> Vec<(Vec<String>, Option<DocumentStatus>)> {
+        let events = self.events.lock().unwrap();
+        events
+            .iter()
+            .filter(|(name, change)| name == "documents:changed" && change["document_id"] == document_id.to_string())
+            .map(|(_, change)| {
+                (
+                    serde_json::from_value(change["changed_fields"].clone()).unwrap(),
+                    serde_json::from_value(change["status"].clone()).unwrap(),
+                )
+            })
+            .collect()
+    }
+}
+
+impl Host for TestHost {
+    fn app_data_dir(&self) -> AppResult<PathBuf> {
+        Ok(self.data_dir.clone())
+    }
+
+    fn emit_value(&self, event: &str, payload: serde_json::Value) {
+        self.events.lock().unwrap().push((event.to_string(), payload));
+    }
+
+    fn focused(&self) -> bool {
+        true
+    }
+
+    fn show_native(&self, _title: &str, _body: Option<&str>) -> Result<(), String> {
+        Err("no window in tests".to_string())
+    }
+}
+
+/// A user of a test library
+#[derive(Debug, Clone, Copy)]
+pub struct TestUser {
+    pub id: Uuid,
+}
+
+/// A library as the app runs it, without a window: the app's state on a
+/// database of its own, with app data and source files in a temporary
+/// directory
+pub struct TestLibrary {
+    pub state: AppState,
+    pub host: Arc<TestHost>,
+    // Dropped last, once nothing uses them any more
+    dir: tempfile::TempDir,
+    db: TestDb,
+}
+
+impl TestLibrary {
+    pub async fn new() -> Option<TestLibrary> {
+        let db = TestDb::create().await?;
+        let dir = tempfile::tempdir().expect("Could not create a temporary directory");
+        let host = Arc::new(TestHost {
+            data_dir: dir.path().join("data"),
+            events: Mutex::new(Vec::new()),
+        });
+        std::fs::create_dir_all(dir.path().join("sources")).unwrap();
+
+        let settings = Arc::new(SettingsStore::load(dir.path().join("settings.json")));
+        let upload_concurrency = settings.get().await.upload_concurrency;
+        let document_changes = DocumentChanges::new();
+        tokio::spawn(crate::forward_document_changes(
+            Arc::clone(&host) as Arc<dyn Host>,
+            document_changes.subscribe(),
+        ));
+        let state = AppState::new(
+            db.pool.clone(),
+            Arc::clone(&host) as Arc<dyn Host>,
+            settings,
+            Arc::new(ProcessingRegistry::with_builtin()),
+            document_changes,
+            upload_concurrency,
+        );
+        tokio::spawn(crate::follow_upload_concurrency(
+            Arc::clone(&state.upload_queue),
+            state.settings.subscribe("upload_queue"),
+        ));
+        Some(TestLibrary { state, host, dir, db })
+    }
+
+    pub fn pool(&self) -> &PgPool {
+        &self.db.pool
+    }
+
+    /// Add a user and make them the one the app acts as
+    pub async fn user(&self, name: &str) -> TestUser {
+        let id: Uuid = sqlx::query_scalar(
+            "INSERT INTO users (email, password_hash, full_name) VALUES ($1, '', $2) RETURNING id",
+        )
+        .bind(format!("{}@example.com", name.to_lowercase()))
+        .bind(name)
+        .fetch_one(self.pool())
+        .await
+        .unwrap();
+        self.state.session.set_active_user(Some(id)).await;
+        TestUser { id }
+    }
+
+    /// Add a team admin and make them the one the app acts as
+    pub async fn admin(&self, name: &str) -> TestUser {
+        let admin = self.user(name).await;
+        sqlx::query("UPDATE users SET role = 'team_admin' WHERE id = $1")
+            .bind(admin.id)
+            .execute(self.pool())
+            .await
+            .unwrap();
+        admin
+    }
+
+    /// Add a workspace owned by `owner`
+    pub async fn workspace(&self, owner: TestUser, name: &str) -> Uuid {
+        let workspaces = self.state.workspace_service.lock().await;
+        workspaces.create_workspace(owner.id, name).await.unwrap().id
+    }
+
+    /// Write a file for uploading, outside the library; `name` may have
+    /// directories in it
+    pub fn source_file(&self, name: &str, contents: &[u8]) -> PathBuf {
+        let path = self.dir.path().join("sources").join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// Where the library stores uploaded files
+    pub fn documents_dir(&self) -> PathBuf {
+        self.dir.path().join("data").join("documents")
+    }
+
+    /// Files stored in the library
+    pub fn stored_files(&self) -> Vec<PathBuf> {
+        match std::fs::read_dir(self.documents_dir()) {
+            Ok(entries) => entries.map(|entry| entry.unwrap().path()).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Upload a file the way upload_file does, as the signed-in user
+    pub async fn upload(&self, path: &Path) -> AppResult<UploadFileResponse> {
+        self.upload_with(upload_request(path)).await
+    }
+
+    pub async fn upload_with(&self, request: UploadFileRequest) -> AppResult<UploadFileResponse> {
+        crate::queue_upload(&self.state, request, None, None).await
+    }
+
+    /// What upload_file returns for `request`, which may ask instead
+    pub async fn upload_file(&self, request: UploadFileRequest) -> AppResult<UploadOutcome> {
+        crate::run_upload_file(&self.state, request).await
+    }
+
+    /// How many documents the library has, deleted ones included
+    pub async fn document_count(&self) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM documents")
+            .fetch_one(self.pool())
+            .await
+            .unwrap()
+    }
+
+    pub async fn document(&self, document_id: Uuid) -> Document {
+        let service = self.state.document_service.lock().await;
+        service.get_document(document_id).await.unwrap().expect("document exists")
+    }
+
+    /// The document once it is stored and processed, or failed
+    pub async fn wait_until_processed(&self, document_id: Uuid) -> Document {
+        let deadline = tokio::time::Instant::now() + PROCESSING_TIMEOUT;
+        loop {
+            let document = self.document(document_id).await;
+            if !matches!(
+                document.status,
+                DocumentStatus::Queued | DocumentStatus::Uploading | DocumentStatus::Processing
+            ) {
+                return document;
+            }
+            assert!(
+                tokio::time::Instant::now() < deadline,
+                "{} still {:?} after {:?}",
+                document_id,
+                document.status,
+                PROCESSING_TIMEOUT
+            );
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    /// storage_used_bytes and referenced_bytes of a user
+    pub async fn user_usage(&self, user: TestUser) -> (i64, i64) {
+        sqlx::query_as("SELECT storage_used_bytes, referenced_bytes FROM users WHERE id = $1")
+            .bind(user.id)
+            .fetch_one(self.pool())
+            .await
+            .unwrap()
+    }
+
+    /// storage_used_bytes and referenced_bytes of a workspace
+    pub async fn workspace_usage(&self, workspace_id: Uuid) -> (i64, i64) {
+        sqlx::query_as("SELECT storage_used_bytes, referenced_bytes FROM workspaces WHERE id = $1")
+            .bind(workspace_id)
+            .fetch_one(self.pool())
+            .await
+            .unwrap()
+    }
+
+    /// Outcome and error of the document's latest processing run, once it
+    /// has been recorded as finished
+    pub async fn finished_run(&self, document_id: Uuid) -> (String, Option<String>) {
+        let deadline = tokio::time::Instant::now() + PROCESSING_TIMEOUT;
+        loop {
+            let run: Option<(String, Option<String>)> = sqlx::query_as(
+                r#"
+                SELECT outcome, error FROM processing_runs
+                WHERE document_id = $1 AND finished_at IS NOT NULL
+                ORDER BY started_at DESC
+                LIMIT 1
+                "#,
+            )
+            .bind(document_id)
+            .fetch_optional(self.pool())
+            .await
+            .unwrap();
+            if let Some(run) = run {
+                return run;
+            }
+            assert!(
+                tokio::time::Instant::now() < deadline,
+                "{} has no finished run after {:?}",
+                document_id,
+                PROCESSING_TIMEOUT
+            );
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    /// The document once processing has stored its term counts
+    pub async fn wait_until_counted(&self, document_id: Uuid) -> Document {
+        let document = self.wait_until_processed(document_id).await;
+        let deadline = tokio::time::Instant::now() + PROCESSING_TIMEOUT;
+        loop {
+            let counted: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM document_terms WHERE document_id = $1)")
+                .bind(document_id)
+                .fetch_one(self.pool())
+                .await
+                .unwrap();
+            if counted {
+                return document;
+            }
+            assert!(
+                tokio::time::Instant::now() < deadline,
+                "{} has no terms after {:?}",
+                document_id,
+                PROCESSING_TIMEOUT
+            );
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    /// Every row of term_stats as workspace, term, occurrences and
+    /// document count, sorted
+    pub async fn term_stats(&self) -> Vec<(Option<Uuid>, String, i64, i32)> {
+        sqlx::query_as(
+            "SELECT workspace_id, term, occurrences, document_count FROM term_stats ORDER BY workspace_id, term",
+        )
+        .fetch_all(self.pool())
+        .await
+        .unwrap()
+    }
+
+    /// Move a document to the trash
+    pub async fn soft_delete(&self, document_id: Uuid) {
+        sqlx::query("UPDATE documents SET deleted_at = NOW() WHERE id = $1")
+            .bind(document_id)
+            .execute(self.pool())
+            .await
+            .unwrap();
+    }
+
+    /// Delete a document for good, as emptying the trash does
+    pub async fn purge(&self, document_id: Uuid) {
+        sqlx::query("DELETE FROM documents WHERE id = $1")
+            .bind(document_id)
+            .execute(self.pool())
+            .await
+            .unwrap();
+    }
+}
+
+/// Wait until `done` holds, failing the test if it takes longer than
+/// processing may
+pub async fn eventually(what: &str, mut done: impl FnMut() -> bool) {
+    let deadline = tokio::time::Instant::now() + PROCESSING_TIMEOUT;
+    while !done() {
+        assert!(tokio::time::Instant::now() < deadline, "{} after {:?}", what, PROCESSING_TIMEOUT);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+/// What upload_file gets for a file picked in the dialog, with nothing else chosen
+pub fn upload_request(path: &Path) -> UploadFileRequest {
+    UploadFileRequest {
+        source_path: path.to_string_lossy().to_string(),
+        workspace_id: None,
+        processing_options: None,
+        storage_mode: StorageMode::Copy,
+        dropped: false,
+        on_trashed_match: TrashedMatchAction::Restore,
+    }
+}
+
+/// A one-page PDF with `text` on it
+pub fn pdf_with_text(text: &str) -> Vec<u8> {
+    pdf_with_pages(&[&[(72, 720, text)]])
+}
+
+/// A one-page PDF of two columns of 10-point text, with `title` across
+/// both; its content stream alternates between the columns line by line,
+/// as many two-column papers' do
+pub fn two_column_pdf(title: &str, left: &[&str], right: &[&str]) -> Vec<u8> {
+    let mut lines = vec![(72, 780, title)];
+    for row in 0..left.len().max(right.len()) {
+        let y = 740 - 14 * row as i64;
+        lines.extend(left.get(row).map(|&text| (72, y, text)));
+        lines.extend(right.get(row).map(|&text| (320, y, text)));
+    }
+    pdf_with_pages(&[&lines])
+}
+
+/// A PDF with a page of `text` for each text
+pub fn paged_pdf(pages: &[&str]) -> Vec<u8> {
+    let lines: Vec<[(i64, i64, &str); 1]> = pages.iter().map(|&text| [(72, 720, text)]).collect();
+    pdf_with_pages(&lines.iter().map(|line| &line[..]).collect::<Vec<_>>())
+}
+
+/// A PDF with a page for each list of texts, showing each text at its x
+/// and y in the order given
+fn pdf_with_pages(pages: &[&[(i64, i64, &str)]]) -> Vec<u8> {
+    use lopdf::content::{Content, Operation};
+    use lopdf::{dictionary, Document, Object, Stream};
+
+    let mut doc = Document::with_version("1.5");
+    let pages_id = doc.new_object_id();
+    let font_id = doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+    });
+    let resources_id = doc.add_object(dictionary! {
+        "Font" => dictionary! { "F1" => font_id },
+    });
+    let mut kids: Vec<Object> = Vec::new();
+    for lines in pages {
+        let mut operations = Vec::new();
+        for &(x, y, text) in lines.iter() {
+            operations.extend([
+                Operation::new("BT", vec![]),
+                Operation::new("Tf", vec!["F1".into(), 10.into()]),
+                Operation::new("Td", vec![x.into(), y.into()]),
+                Operation::new("Tj", vec![Object::string_literal(text)]),
+                Operation::new("ET", vec![]),
+            ]);
+        }
+        let content = Content { operations };
+        let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => content_id,
+        });
+        kids.push(page_id.into());
+    }
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Count" => kids.len() as i64,
+            "Kids" => kids,
+            "Resources" => resources_id,
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+        }),
+    );
+    let catalog_id = doc.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    doc.trailer.set("Root", catalog_id);
+
+    let mut bytes = Vec::new();
+    doc.save_to(&mut bytes).unwrap();
+    bytes
+}
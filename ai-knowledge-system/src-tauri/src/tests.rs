@@ -0,0 +1,1706 @@
+//! Uploads through processing, as the app runs them, against a throwaway
+//! database; see `test_support` for what they need to run
+
+use crate::error::AppError;
+use crate::models::{
+    DocumentStatus, ImportFolderRequest, MergeStrategy, PdfLayout, ScanOutcome, ScanRecord, SourceFileAction,
+    StorageMode, StructureMode, TrashedMatchAction, UploadFileRequest, UploadOutcome,
+};
+use crate::processing::extractor::BUILTIN_PRIORITY;
+use crate::processing::{ExtractionResult, Extractor};
+use crate::services::DocumentService;
+use crate::settings::AppSettings;
+use crate::test_support::{
+    eventually, paged_pdf, pdf_with_text, test_server, two_column_pdf, upload_request, TestLibrary, TestUser,
+};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn text_file_is_stored_and_processed() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+    let path = library.source_file("notes.txt", b"Meeting notes about the quarterly budget review.");
+
+    let response = library.upload(&path).await.unwrap();
+    assert_eq!(response.document.status, DocumentStatus::Queued);
+    let document = library.wait_until_processed(response.document.id).await;
+
+    assert_eq!(document.status, DocumentStatus::Completed, "{:?}", document.processing_error);
+    assert!(document.content.unwrap().contains("quarterly budget"));
+    assert_eq!(library.stored_files().len(), 1);
+    assert!(library.host.event_names().contains(&"processing:progress".to_string()));
+}
+
+#[tokio::test]
+async fn pdf_text_is_extracted() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+    let path = library.source_file("paper.pdf", &pdf_with_text("Attention is all you need"));
+
+    let response = library.upload(&path).await.unwrap();
+    let document = library.wait_until_processed(response.document.id).await;
+
+    assert_eq!(document.status, DocumentStatus::Completed, "{:?}", document.processing_error);
+    assert_eq!(document.mime_type.as_deref(), Some("application/pdf"));
+    assert!(document.content.unwrap().contains("Attention is all you need"));
+}
+
+#[tokio::test]
+async fn corrupt_pdf_fails_with_an_error() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+    let mut corrupt = pdf_with_text("Truncated");
+    corrupt.truncate(corrupt.len() / 2);
+    let path = library.source_file("broken.pdf", &corrupt);
+
+    let response = library.upload(&path).await.unwrap();
+    let document = library.wait_until_processed(response.document.id).await;
+
+    assert_eq!(document.status, DocumentStatus::Failed);
+    assert!(document.processing_error.is_some());
+}
+
+/// `pdf_with_text` cut off just before its cross-reference table, as by a
+/// download that stopped short: every object but no xref, trailer or %%EOF
+fn pdf_without_tail(text: &str) -> Vec<u8> {
+    let mut pdf = pdf_with_text(text);
+    let xref = pdf.windows(5).rposition(|w| w == b"xref\n").expect("a cross-reference table");
+    pdf.truncate(xref);
+    pdf
+}
+
+/// `pdf_with_text` with a comment added after the header, so every offset
+/// its cross-reference table gives is off
+fn pdf_with_stale_xref(text: &str) -> Vec<u8> {
+    let mut pdf = pdf_with_text(text);
+    let header_end = pdf.iter().position(|&b| b == b'\n').unwrap() + 1;
+    pdf.splice(header_end..header_end, b"% edited carelessly, offsets not updated\n".iter().copied());
+    pdf
+}
+
+async fn assert_read_from_repaired_copy(library: &TestLibrary, document_id: uuid::Uuid, text: &str) {
+    let document = library.wait_until_processed(document_id).await;
+    assert_eq!(document.status, DocumentStatus::Completed, "{:?}", document.processing_error);
+    assert!(document.content.as_deref().unwrap_or_default().contains(text));
+    assert!(document.processing_error.unwrap().contains("read from a repaired copy"));
+
+    library.finished_run(document_id).await;
+    let (repaired, metadata): (bool, Option<serde_json::Value>) = sqlx::query_as(
+        "SELECT r.repaired, d.metadata FROM processing_runs r JOIN documents d ON d.id = r.document_id WHERE r.document_id = $1",
+    )
+    .bind(document_id)
+    .fetch_one(library.pool())
+    .await
+    .unwrap();
+    assert!(repaired);
+    assert_eq!(metadata.unwrap()["repaired"], true);
+}
+
+#[tokio::test]
+async fn pdfs_missing_their_tail_are_read_from_a_repaired_copy() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+    let truncated = pdf_without_tail("Cut off before the end");
+    assert!(lopdf::Document::load_mem(&truncated).is_err());
+    let path = library.source_file("truncated.pdf", &truncated);
+
+    let document_id = library.upload(&path).await.unwrap().document.id;
+    assert_read_from_repaired_copy(&library, document_id, "Cut off before the end").await;
+    // Only the copy read was repaired, never the stored file
+    assert_eq!(std::fs::read(&library.stored_files()[0]).unwrap(), truncated);
+}
+
+#[tokio::test]
+async fn pdfs_with_a_damaged_xref_are_read_from_a_repaired_copy() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+    let damaged = pdf_with_stale_xref("Offsets all wrong");
+    assert!(lopdf::Document::load_mem(&damaged).is_err());
+    let path = library.source_file("damaged.pdf", &damaged);
+
+    let document_id = library.upload(&path).await.unwrap().document.id;
+    assert_read_from_repaired_copy(&library, document_id, "Offsets all wrong").await;
+}
+
+#[tokio::test]
+async fn duplicate_content_is_found_by_hash() {
+    let Some(library) = TestLibrary::new().await else { return };
+    let user = library.user("Ada").await;
+    let first = library.source_file("report.txt", b"The same report, saved twice.");
+    let copy = library.source_file("report (1).txt", b"The same report, saved twice.");
+
+    let original = library.upload(&first).await.unwrap().document.id;
+    library.wait_until_processed(original).await;
+
+    assert_eq!(crate::find_duplicate_file(&library.state, user.id, &copy).await.unwrap(), Some(original));
+    let other = library.source_file("other.txt", b"A different report of the same size!");
+    assert_eq!(crate::find_duplicate_file(&library.state, user.id, &other).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn uploads_count_against_the_quota() {
+    let Some(library) = TestLibrary::new().await else { return };
+    let user = library.user("Ada").await;
+    let contents = b"Forty bytes of text for the quota test..";
+    let path = library.source_file("quota.txt", contents);
+
+    let document = library.upload(&path).await.unwrap().document;
+    library.wait_until_processed(document.id).await;
+    assert_eq!(library.user_usage(user).await, (contents.len() as i64, 0));
+
+    sqlx::query("UPDATE users SET storage_limit_bytes = storage_used_bytes + 10 WHERE id = $1")
+        .bind(user.id)
+        .execute(library.pool())
+        .await
+        .unwrap();
+    let over = library.source_file("over.txt", contents);
+    let error = library.upload(&over).await.unwrap_err();
+    assert!(matches!(error, AppError::QuotaExceeded { .. }), "{:?}", error);
+    assert_eq!(library.user_usage(user).await, (contents.len() as i64, 0));
+}
+
+#[tokio::test]
+async fn library_is_removed_after_a_panic() {
+    let Some(server) = test_server() else { return };
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    let test = tokio::spawn(async move {
+        let library = TestLibrary::new().await.unwrap();
+        let database: String = sqlx::query_scalar("SELECT current_database()")
+            .fetch_one(library.pool())
+            .await
+            .unwrap();
+        sender.send((database, library.documents_dir())).unwrap();
+        panic!("test failed");
+    });
+    assert!(test.await.unwrap_err().is_panic());
+
+    let (database, documents_dir) = receiver.await.unwrap();
+    assert!(!documents_dir.parent().unwrap().exists());
+    let pool = sqlx::PgPool::connect_with(server.database("postgres")).await.unwrap();
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM pg_database WHERE datname = $1)")
+        .bind(&database)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert!(!exists);
+}
+
+#[tokio::test]
+async fn upload_into_a_workspace_is_charged_to_it_until_purged() {
+    let Some(library) = TestLibrary::new().await else { return };
+    let user = library.user("Ada").await;
+    let workspace_id = library.workspace(user, "Client A").await;
+    let contents = b"Filed straight into the client's workspace.";
+    let path = library.source_file("brief.txt", contents);
+
+    let document = library
+        .upload_with(UploadFileRequest {
+            workspace_id: Some(workspace_id),
+            ..upload_request(&path)
+        })
+        .await
+        .unwrap()
+        .document;
+    assert_eq!(document.workspace_id, Some(workspace_id));
+    library.wait_until_processed(document.id).await;
+    assert_eq!(library.workspace_usage(workspace_id).await, (contents.len() as i64, 0));
+    assert_eq!(library.user_usage(user).await, (0, 0));
+
+    library.purge(document.id).await;
+    assert_eq!(library.workspace_usage(workspace_id).await, (0, 0));
+    assert_eq!(library.user_usage(user).await, (0, 0));
+}
+
+#[tokio::test]
+async fn uploads_go_to_the_target_then_the_default_workspace() {
+    let Some(library) = TestLibrary::new().await else { return };
+    let user = library.user("Ada").await;
+    let default = library.workspace(user, "Inbox").await;
+    let target = library.workspace(user, "Client A").await;
+
+    let unfiled = library.source_file("unfiled.txt", b"Uploaded before there was a default.");
+    let document = library.upload(&unfiled).await.unwrap().document;
+    assert_eq!(document.workspace_id, None);
+    assert_eq!(library.user_usage(user).await.0, 36);
+
+    let changed = library
+        .state
+        .user_service
+        .lock()
+        .await
+        .set_default_workspace(user.id, Some(default))
+        .await
+        .unwrap();
+    assert!(changed);
+    let defaulted = library.source_file("defaulted.txt", b"Lands in the default workspace.");
+    let document = library.upload(&defaulted).await.unwrap().document;
+    assert_eq!(document.workspace_id, Some(default));
+    assert_eq!(library.workspace_usage(default).await.0, 31);
+
+    let targeted = library.source_file("targeted.txt", b"Sent to a workspace of its own.");
+    let document = library
+        .upload_with(UploadFileRequest {
+            workspace_id: Some(target),
+            ..upload_request(&targeted)
+        })
+        .await
+        .unwrap()
+        .document;
+    assert_eq!(document.workspace_id, Some(target));
+    assert_eq!(library.workspace_usage(target).await.0, 31);
+    assert_eq!(library.workspace_usage(default).await.0, 31);
+    assert_eq!(library.user_usage(user).await.0, 36);
+}
+
+#[tokio::test]
+async fn another_users_workspace_is_not_a_target() {
+    let Some(library) = TestLibrary::new().await else { return };
+    let owner = library.user("Grace").await;
+    let theirs = library.workspace(owner, "Private").await;
+    let user = library.user("Ada").await;
+    let path = library.source_file("note.txt", b"Not for Grace's workspace.");
+
+    let error = library
+        .upload_with(UploadFileRequest {
+            workspace_id: Some(theirs),
+            ..upload_request(&path)
+        })
+        .await
+        .unwrap_err();
+    assert!(matches!(error, AppError::NotFound(_)), "{:?}", error);
+    assert_eq!(library.document_count().await, 0);
+    assert_eq!(library.workspace_usage(theirs).await, (0, 0));
+    assert_eq!(library.user_usage(user).await, (0, 0));
+}
+
+#[tokio::test]
+async fn folder_import_files_top_level_directories_into_their_workspaces() {
+    let Some(library) = TestLibrary::new().await else { return };
+    let user = library.user("Ada").await;
+    let existing = library.workspace(user, "Client A").await;
+    let target = library.workspace(user, "Imports").await;
+    let a = b"Contract for client A.";
+    let b = b"Invoice for client B, second draft.";
+    let loose = b"Loose notes at the top.";
+    library.source_file("tree/Client A/contract.txt", a);
+    library.source_file("tree/Client B/invoice.txt", b);
+    let root = library.source_file("tree/notes.txt", loose).parent().unwrap().to_path_buf();
+
+    let report = crate::run_folder_import(
+        &library.state,
+        ImportFolderRequest {
+            folder_path: root.to_string_lossy().to_string(),
+            structure_mode: StructureMode::TopLevelAsWorkspace,
+            workspace_id: Some(target),
+            processing_options: None,
+            storage_mode: StorageMode::Copy,
+            after_import: SourceFileAction::Keep,
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!((report.queued, report.workspaces_reused, report.workspaces_created), (3, 1, 1));
+
+    let created: uuid::Uuid = sqlx::query_scalar("SELECT id FROM workspaces WHERE name = 'Client B'")
+        .fetch_one(library.pool())
+        .await
+        .unwrap();
+    assert_eq!(library.workspace_usage(existing).await, (a.len() as i64, 0));
+    assert_eq!(library.workspace_usage(created).await, (b.len() as i64, 0));
+    assert_eq!(library.workspace_usage(target).await, (loose.len() as i64, 0));
+    assert_eq!(library.user_usage(user).await, (0, 0));
+}
+
+#[tokio::test]
+async fn recomputing_trashed_documents_changes_nothing() {
+    let Some(library) = TestLibrary::new().await else { return };
+    let user = library.user("Ada").await;
+    let workspace_id = library.workspace(user, "Client A").await;
+    let filed = library.source_file("filed.txt", b"In the workspace, then trashed.");
+    let unfiled = library.source_file("unfiled.txt", b"Unfiled, then trashed as well.");
+    let filed = library
+        .upload_with(UploadFileRequest {
+            workspace_id: Some(workspace_id),
+            ..upload_request(&filed)
+        })
+        .await
+        .unwrap()
+        .document
+        .id;
+    let unfiled = library.upload(&unfiled).await.unwrap().document.id;
+    library.soft_delete(filed).await;
+    library.soft_delete(unfiled).await;
+    let charged = (library.user_usage(user).await, library.workspace_usage(workspace_id).await);
+    assert_eq!(charged, ((30, 0), (31, 0)));
+
+    let workspace_bytes = {
+        let workspaces = library.state.workspace_service.lock().await;
+        workspaces.recompute_storage_usage(workspace_id).await.unwrap()
+    };
+    assert_eq!(workspace_bytes, 31);
+    let correction = {
+        let users = library.state.user_service.lock().await;
+        users.recompute_storage_usage(user.id, &library.documents_dir()).await.unwrap().unwrap()
+    };
+    assert_eq!(correction.delta_bytes, 0);
+    assert_eq!((library.user_usage(user).await, library.workspace_usage(workspace_id).await), charged);
+
+    library.purge(filed).await;
+    library.purge(unfiled).await;
+    assert_eq!(library.user_usage(user).await, (0, 0));
+    assert_eq!(library.workspace_usage(workspace_id).await, (0, 0));
+}
+
+#[tokio::test]
+async fn backfill_and_recomputes_agree_with_the_trigger() {
+    let Some(library) = TestLibrary::new().await else { return };
+    let user = library.user("Ada").await;
+    let workspace_id = library.workspace(user, "Client A").await;
+    let files = [
+        ("copied.txt", StorageMode::Copy, None, false),
+        ("referenced.txt", StorageMode::Reference, None, false),
+        ("trashed.txt", StorageMode::Copy, None, true),
+        ("trashed reference.txt", StorageMode::Reference, None, true),
+        ("filed.txt", StorageMode::Copy, Some(workspace_id), false),
+        ("filed reference.txt", StorageMode::Reference, Some(workspace_id), false),
+        ("filed trashed.txt", StorageMode::Copy, Some(workspace_id), true),
+        ("filed trashed reference.txt", StorageMode::Reference, Some(workspace_id), true),
+    ];
+    for (name, storage_mode, workspace_id, trashed) in files {
+        let path = library.source_file(name, name.repeat(3).as_bytes());
+        let document = library
+            .upload_with(UploadFileRequest {
+                workspace_id,
+                storage_mode,
+                ..upload_request(&path)
+            })
+            .await
+            .unwrap()
+            .document;
+        if trashed {
+            library.soft_delete(document.id).await;
+        }
+    }
+    let bytes = |names: &[&str]| names.iter().map(|name| 3 * name.len() as i64).sum::<i64>();
+    let user_charged = (bytes(&["copied.txt", "trashed.txt"]), bytes(&["referenced.txt", "trashed reference.txt"]));
+    let workspace_charged = (
+        bytes(&["filed.txt", "filed trashed.txt"]),
+        bytes(&["filed reference.txt", "filed trashed reference.txt"]),
+    );
+    assert_eq!(library.user_usage(user).await, user_charged);
+    assert_eq!(library.workspace_usage(workspace_id).await, workspace_charged);
+
+    sqlx::query("UPDATE users SET storage_used_bytes = 1, referenced_bytes = 1")
+        .execute(library.pool())
+        .await
+        .unwrap();
+    sqlx::query("UPDATE workspaces SET storage_used_bytes = 1, referenced_bytes = 1")
+        .execute(library.pool())
+        .await
+        .unwrap();
+    sqlx::raw_sql(include_str!("../../../migrations/054_storage_counted_until_purge.sql"))
+        .execute(library.pool())
+        .await
+        .unwrap();
+    assert_eq!(library.user_usage(user).await, user_charged);
+    assert_eq!(library.workspace_usage(workspace_id).await, workspace_charged);
+
+    let correction = {
+        let users = library.state.user_service.lock().await;
+        users.recompute_storage_usage(user.id, &library.documents_dir()).await.unwrap().unwrap()
+    };
+    assert_eq!((correction.delta_bytes, correction.referenced_delta_bytes), (0, 0));
+    let workspace_bytes = {
+        let workspaces = library.state.workspace_service.lock().await;
+        workspaces.recompute_storage_usage(workspace_id).await.unwrap()
+    };
+    assert_eq!(workspace_bytes, workspace_charged.0);
+    assert_eq!(library.user_usage(user).await, user_charged);
+    assert_eq!(library.workspace_usage(workspace_id).await, workspace_charged);
+}
+
+#[tokio::test]
+async fn misclassified_documents_are_detected_again_and_processed() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+    let scan = library.source_file("scan", &pdf_with_text("Quarterly scan of receipts"));
+    let scan = library.upload(&scan).await.unwrap().document.id;
+    let blob = library.source_file("blob", &[0xde, 0xad, 0xbe, 0xef, 0x00, 0x01, 0x02, 0x03]);
+    let blob = library.upload(&blob).await.unwrap().document.id;
+    let gone = library.source_file("gone.txt", b"Its file goes missing.");
+    let gone = library.upload(&gone).await.unwrap().document.id;
+    for id in [scan, blob, gone] {
+        library.wait_until_processed(id).await;
+    }
+    // As uploads from before content sniffing were stored
+    sqlx::query(
+        "UPDATE documents SET mime_type = 'application/octet-stream', file_type = 'FILE', status = 'failed',
+         content = NULL WHERE id = ANY($1)",
+    )
+    .bind(vec![scan, blob, gone])
+    .execute(library.pool())
+    .await
+    .unwrap();
+    std::fs::remove_file(library.document(gone).await.file_path.unwrap()).unwrap();
+
+    let report = crate::run_redetect_file_types(&library.state).await.unwrap();
+    assert_eq!(report.checked, 3);
+    assert_eq!(report.reclassified.get("application/pdf"), Some(&1));
+    assert_eq!(report.reclassified.len(), 1);
+    assert_eq!(report.processing_started, 1);
+    assert_eq!(report.still_unknown, 1);
+    assert_eq!(report.missing_files, 1);
+    assert!(report.failed.is_empty(), "{:?}", report.failed);
+
+    let scan = library.wait_until_processed(scan).await;
+    assert_eq!(scan.status, DocumentStatus::Completed, "{:?}", scan.processing_error);
+    assert_eq!((scan.mime_type.as_deref(), scan.file_type.as_deref()), (Some("application/pdf"), Some("PDF")));
+    assert!(scan.content.unwrap().contains("Quarterly scan"));
+    let blob = library.document(blob).await;
+    assert_eq!(blob.mime_type.as_deref(), Some("application/octet-stream"));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn concurrent_uploads_of_one_file_store_it_once() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+    let path = library.source_file("clicked twice.txt", b"The import button was double-clicked.");
+
+    let attempt = || {
+        let (state, request) = (library.state.clone(), upload_request(&path));
+        tokio::spawn(async move { crate::queue_upload(&state, request, None, None).await })
+    };
+    let (first, second) = tokio::join!(attempt(), attempt());
+    let (first, second) = (first.unwrap().unwrap().document, second.unwrap().unwrap().document);
+    assert_eq!(first.id, second.id);
+    library.wait_until_processed(first.id).await;
+
+    assert_eq!(library.document_count().await, 1);
+    assert_eq!(library.stored_files().len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn cancel_and_completion_racing_leave_one_winner() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+    let path = library.source_file("notes.txt", b"Cancelled or completed, never both.");
+    let document_id = library.upload(&path).await.unwrap().document.id;
+    library.wait_until_processed(document_id).await;
+    // Services of their own, so the app's lock doesn't take turns for them
+    let service = || {
+        let state = &library.state;
+        DocumentService::new(library.pool().clone(), state.quick_index.clone(), state.document_changes.clone())
+    };
+    let (canceller, processor) = (service(), service());
+
+    for _ in 0..20 {
+        processor
+            .transition_status(document_id, DocumentStatus::Completed, DocumentStatus::Processing, None)
+            .await
+            .unwrap();
+        let (cancelled, completed) = tokio::join!(
+            canceller.transition_status(
+                document_id,
+                DocumentStatus::Processing,
+                DocumentStatus::Failed,
+                Some("Cancelled by user".to_string()),
+            ),
+            processor.update_content_and_summary(document_id, "Extracted text", None, None, None),
+        );
+
+        let document = library.document(document_id).await;
+        match (cancelled, completed) {
+            (Ok(()), Err(AppError::InvalidTransition { .. })) => {
+                assert_eq!(document.status, DocumentStatus::Failed);
+                assert_eq!(document.processing_error.as_deref(), Some("Cancelled by user"));
+                // Back to Completed for the next round
+                processor
+                    .transition_status(document_id, DocumentStatus::Failed, DocumentStatus::Processing, None)
+                    .await
+                    .unwrap();
+                processor
+                    .update_content_and_summary(document_id, "Extracted text", None, None, None)
+                    .await
+                    .unwrap();
+            }
+            (Err(AppError::InvalidTransition { .. }), Ok(())) => {
+                assert_eq!(document.status, DocumentStatus::Completed);
+                assert_eq!(document.processing_error, None);
+                assert_eq!(document.content.as_deref(), Some("Extracted text"));
+            }
+            outcome => panic!("Expected exactly one winner, got {:?}", outcome),
+        }
+    }
+}
+
+#[tokio::test]
+async fn deleting_a_document_takes_its_terms_out_of_the_counts() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+    let first = library.source_file("first.txt", b"lighthouse lighthouse beacon");
+    let second = library.source_file("second.txt", b"lighthouse harbor");
+    let first = library.upload(&first).await.unwrap().document.id;
+    let second = library.upload(&second).await.unwrap().document.id;
+    library.wait_until_counted(first).await;
+    library.wait_until_counted(second).await;
+    let counts = |stats: Vec<(Option<uuid::Uuid>, String, i64, i32)>| {
+        stats.into_iter().map(|(_, term, occurrences, documents)| (term, occurrences, documents)).collect::<Vec<_>>()
+    };
+    assert_eq!(
+        counts(library.term_stats().await),
+        [("beacon".to_string(), 1, 1), ("harbor".to_string(), 1, 1), ("lighthouse".to_string(), 3, 2)]
+    );
+
+    // Trashing subtracts, dropping terms no document has left
+    library.soft_delete(first).await;
+    assert_eq!(
+        counts(library.term_stats().await),
+        [("harbor".to_string(), 1, 1), ("lighthouse".to_string(), 1, 1)]
+    );
+
+    // So does deleting for good, without going through the trash
+    library.purge(second).await;
+    assert!(library.term_stats().await.is_empty());
+    library.purge(first).await;
+    assert!(library.term_stats().await.is_empty());
+}
+
+#[tokio::test]
+async fn restoring_a_document_counts_its_terms_again() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+    let kept = library.source_file("kept.txt", b"Lighthouse keepers logged the weather every night.");
+    let trashed = library.source_file("trashed.txt", b"The lighthouse weather log was lost at sea.");
+    let kept = library.upload(&kept).await.unwrap().document.id;
+    let trashed = library.upload(&trashed).await.unwrap().document.id;
+    library.wait_until_counted(kept).await;
+    library.wait_until_counted(trashed).await;
+    let counted = library.term_stats().await;
+
+    library.soft_delete(trashed).await;
+    assert_ne!(library.term_stats().await, counted);
+    let restored = {
+        let service = library.state.document_service.lock().await;
+        service.restore_document(trashed).await.unwrap()
+    };
+    assert!(restored);
+    assert_eq!(library.term_stats().await, counted);
+
+    sqlx::query("DELETE FROM document_terms").execute(library.pool()).await.unwrap();
+    assert!(library.term_stats().await.is_empty());
+    assert_eq!(crate::backfill_term_stats(&library.state).await.unwrap(), 2);
+    assert_eq!(library.term_stats().await, counted);
+}
+
+#[tokio::test]
+async fn uploading_a_deleted_file_again_restores_its_document() {
+    let Some(library) = TestLibrary::new().await else { return };
+    let user = library.user("Ada").await;
+    let original = library.source_file("paper.txt", b"A paper the user deleted and wants back.");
+    let original = library.upload(&original).await.unwrap().document.id;
+    library.wait_until_processed(original).await;
+    {
+        let tags = library.state.tag_service.lock().await;
+        let (tag, _) = tags.get_or_create_tag(user.id, "reading").await.unwrap();
+        tags.attach_tag(original, tag.id).await.unwrap();
+    }
+    library.soft_delete(original).await;
+
+    let again = library.source_file("downloads/paper.txt", b"A paper the user deleted and wants back.");
+    let response = library.upload(&again).await.unwrap();
+    assert!(response.restored);
+    assert_eq!(response.document.id, original);
+    assert!(library.document(original).await.deleted_at.is_none());
+    let tags: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM document_tags WHERE document_id = $1")
+        .bind(original)
+        .fetch_one(library.pool())
+        .await
+        .unwrap();
+    assert_eq!(tags, 1);
+    assert_eq!(library.document_count().await, 1);
+    assert_eq!(library.stored_files().len(), 1);
+}
+
+#[tokio::test]
+async fn empty_files_are_refused_and_tiny_ones_flagged() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+
+    for name in ["paper.pdf", "notes.txt"] {
+        let empty = library.source_file(name, b"");
+        match library.upload_file(upload_request(&empty)).await {
+            Err(AppError::EmptyFile(path)) => assert!(path.ends_with(name), "{}", path),
+            other => panic!("expected EmptyFile for {}, got {:?}", name, other),
+        }
+    }
+    assert_eq!(library.document_count().await, 0);
+    assert!(library.stored_files().is_empty());
+
+    let tiny = library.source_file("todo.md", b"# Buy milk");
+    let UploadOutcome::Accepted(response) = library.upload_file(upload_request(&tiny)).await.unwrap() else {
+        panic!("expected todo.md to be accepted");
+    };
+    assert!(response.tiny_file);
+    let document = library.wait_until_processed(response.document.id).await;
+    assert_eq!(document.status, DocumentStatus::Completed, "{:?}", document.processing_error);
+    assert_eq!(document.file_size_bytes, Some(10));
+}
+
+#[tokio::test]
+async fn uploading_a_deleted_file_can_ask_first() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+    let path = library.source_file("paper.txt", b"Deleted, then uploaded asking first.");
+    let original = library.upload(&path).await.unwrap().document.id;
+    library.wait_until_processed(original).await;
+    library.soft_delete(original).await;
+
+    let outcome = library
+        .upload_file(UploadFileRequest {
+            on_trashed_match: TrashedMatchAction::Ask,
+            ..upload_request(&path)
+        })
+        .await
+        .unwrap();
+    let UploadOutcome::RequiresDecision { trashed } = outcome else {
+        panic!("expected a decision, got {:?}", outcome);
+    };
+    assert_eq!(trashed.iter().map(|document| document.id).collect::<Vec<_>>(), [original]);
+    assert_eq!(library.document_count().await, 1);
+
+    // The user chose a new document
+    let outcome = library
+        .upload_file(UploadFileRequest {
+            on_trashed_match: TrashedMatchAction::Import,
+            ..upload_request(&path)
+        })
+        .await
+        .unwrap();
+    let UploadOutcome::Accepted(response) = outcome else {
+        panic!("expected the upload to be accepted, got {:?}", outcome);
+    };
+    assert!(!response.restored);
+    assert_ne!(response.document.id, original);
+    let still_deleted: bool = sqlx::query_scalar("SELECT deleted_at IS NOT NULL FROM documents WHERE id = $1")
+        .bind(original)
+        .fetch_one(library.pool())
+        .await
+        .unwrap();
+    assert!(still_deleted);
+    assert_eq!(library.document_count().await, 2);
+}
+
+#[tokio::test]
+async fn a_purged_document_is_not_restored() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+    let path = library.source_file("paper.txt", b"Deleted and purged before coming back.");
+    let original = library.upload(&path).await.unwrap().document.id;
+    library.wait_until_processed(original).await;
+    library.soft_delete(original).await;
+    library.purge(original).await;
+
+    let response = library.upload(&path).await.unwrap();
+    assert!(!response.restored);
+    assert_ne!(response.document.id, original);
+    let document = library.wait_until_processed(response.document.id).await;
+    assert_eq!(document.status, DocumentStatus::Completed, "{:?}", document.processing_error);
+    assert_eq!(library.document_count().await, 1);
+}
+
+fn folder_import(root: &std::path::Path, storage_mode: StorageMode, after_import: SourceFileAction) -> ImportFolderRequest {
+    ImportFolderRequest {
+        folder_path: root.to_string_lossy().to_string(),
+        structure_mode: StructureMode::Flat,
+        workspace_id: None,
+        processing_options: None,
+        storage_mode,
+        after_import,
+    }
+}
+
+#[tokio::test]
+async fn folder_import_deletes_files_once_the_library_has_them() {
+    let Some(library) = TestLibrary::new().await else { return };
+    let user = library.user("Ada").await;
+    let earlier = library.source_file("earlier.txt", b"Already in the library.");
+    let earlier = library.upload(&earlier).await.unwrap().document.id;
+    library.wait_until_processed(earlier).await;
+    let duplicate = library.source_file("inbox/again.txt", b"Already in the library.");
+    let new = library.source_file("inbox/new.txt", b"New to the library.");
+    let mut corrupt = pdf_with_text("Truncated");
+    corrupt.truncate(corrupt.len() / 2);
+    let broken = library.source_file("inbox/broken.pdf", &corrupt);
+
+    let request = folder_import(duplicate.parent().unwrap(), StorageMode::Copy, SourceFileAction::Delete);
+    let report = crate::run_folder_import(&library.state, request).await.unwrap();
+    assert_eq!((report.queued, report.duplicates.len()), (2, 1));
+    eventually("new.txt not deleted", || !new.exists()).await;
+    assert!(!duplicate.exists());
+
+    let failed: uuid::Uuid = sqlx::query_scalar("SELECT id FROM documents WHERE file_name = 'broken.pdf'")
+        .fetch_one(library.pool())
+        .await
+        .unwrap();
+    assert_eq!(library.wait_until_processed(failed).await.status, DocumentStatus::Failed);
+    assert!(broken.exists());
+
+    let logged: Vec<(String, uuid::Uuid, serde_json::Value)> = sqlx::query_as(
+        "SELECT action, resource_id, metadata FROM activity_log WHERE user_id = $1 ORDER BY metadata->>'path'",
+    )
+    .bind(user.id)
+    .fetch_all(library.pool())
+    .await
+    .unwrap();
+    assert_eq!(logged.len(), 2);
+    assert_eq!((logged[0].0.as_str(), logged[0].1), ("delete_imported_source", earlier));
+    assert_eq!(logged[0].2["duplicate"], true);
+    assert_eq!(logged[1].0, "delete_imported_source");
+    assert_eq!(logged[1].2["duplicate"], false);
+}
+
+#[tokio::test]
+async fn folder_import_moves_files_aside_and_skips_them_next_time() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+    let new = library.source_file("inbox/2026/new.txt", b"Moved once imported.");
+    let root = new.parent().unwrap().parent().unwrap().to_path_buf();
+    let earlier = root.join("imported/2026/new.txt");
+    std::fs::create_dir_all(earlier.parent().unwrap()).unwrap();
+    std::fs::write(&earlier, b"Moved by an earlier import.").unwrap();
+
+    let request = folder_import(&root, StorageMode::Copy, SourceFileAction::MoveToSubfolder);
+    let report = crate::run_folder_import(&library.state, request.clone()).await.unwrap();
+    assert_eq!(report.queued, 1);
+    let moved = root.join("imported/2026/new (2).txt");
+    eventually("new.txt not moved", || moved.exists()).await;
+    assert!(!new.exists());
+    assert_eq!(std::fs::read(&earlier).unwrap(), b"Moved by an earlier import.");
+
+    let again = crate::run_folder_import(&library.state, request).await.unwrap();
+    assert_eq!((again.queued, again.duplicates.len()), (0, 0));
+    assert!(moved.exists());
+}
+
+#[tokio::test]
+async fn referenced_files_are_never_deleted_after_importing() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+    let file = library.source_file("inbox/kept.txt", b"Referenced where it is.");
+
+    let request = folder_import(file.parent().unwrap(), StorageMode::Reference, SourceFileAction::Delete);
+    let error = crate::run_folder_import(&library.state, request).await.unwrap_err();
+    assert!(matches!(error, AppError::InvalidInput(_)), "{:?}", error);
+    assert!(file.exists());
+    assert_eq!(library.document_count().await, 0);
+}
+
+#[tokio::test]
+async fn reading_a_document_in_read_only_mode_writes_nothing() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+    let path = library.source_file("paper.txt", b"Read on a replica.");
+    let document_id = library.upload(&path).await.unwrap().document.id;
+    library.wait_until_processed(document_id).await;
+    let opens = || async {
+        sqlx::query_scalar::<_, i32>("SELECT open_count FROM documents WHERE id = $1")
+            .bind(document_id)
+            .fetch_one(library.pool())
+            .await
+            .unwrap()
+    };
+
+    library.state.read_only.store(true, Ordering::Relaxed);
+    crate::run_get_document(&library.state, document_id, None).await.unwrap();
+    assert_eq!(opens().await, 0);
+
+    library.state.read_only.store(false, Ordering::Relaxed);
+    crate::run_get_document(&library.state, document_id, None).await.unwrap();
+    assert_eq!(opens().await, 1);
+}
+
+#[tokio::test]
+async fn members_cannot_switch_into_an_admin_account() {
+    let Some(library) = TestLibrary::new().await else { return };
+    let admin = library.admin("Grace").await;
+    let member = library.user("Ada").await;
+
+    let switched = crate::run_switch_user(&library.state, admin.id).await;
+    assert!(matches!(switched, Err(AppError::Forbidden)), "{:?}", switched);
+    assert_eq!(library.state.session.current_user_id().await.unwrap(), member.id);
+
+    // Admins may switch to members and back
+    library.state.session.set_active_user(Some(admin.id)).await;
+    crate::run_switch_user(&library.state, member.id).await.unwrap();
+    library.state.session.set_active_user(Some(admin.id)).await;
+    assert_eq!(crate::run_switch_user(&library.state, admin.id).await.unwrap().id, admin.id);
+}
+
+#[tokio::test]
+async fn members_are_refused_admin_commands() {
+    let Some(library) = TestLibrary::new().await else { return };
+    let admin = library.admin("Grace").await;
+    let member = library.user("Ada").await;
+
+    let listed = crate::run_list_all_documents(&library.state).await;
+    assert!(matches!(listed, Err(AppError::Forbidden)), "{:?}", listed);
+    let limited = crate::run_set_storage_limit(&library.state, member.id, 1 << 40).await;
+    assert!(matches!(limited, Err(AppError::Forbidden)), "{:?}", limited);
+    let limit: i64 = sqlx::query_scalar("SELECT storage_limit_bytes FROM users WHERE id = $1")
+        .bind(member.id)
+        .fetch_one(library.pool())
+        .await
+        .unwrap();
+    assert_ne!(limit, 1 << 40);
+
+    let older = library.source_file("older.txt", b"Uploaded first.");
+    let newer = library.source_file("newer.txt", b"Uploaded second.");
+    let older = library.upload(&older).await.unwrap().document.id;
+    let newer = library.upload(&newer).await.unwrap().document.id;
+    library.wait_until_processed(older).await;
+    library.wait_until_processed(newer).await;
+
+    library.state.session.set_active_user(Some(admin.id)).await;
+    let listed = crate::run_list_all_documents(&library.state).await.unwrap();
+    assert_eq!(listed.iter().map(|entry| entry.document.id).collect::<Vec<_>>(), [newer, older]);
+    assert!(listed.iter().all(|entry| entry.document.content.is_none() && entry.owner_name.as_deref() == Some("Ada")));
+    let user = crate::run_set_storage_limit(&library.state, member.id, 1 << 40).await.unwrap();
+    assert_eq!(user.storage_limit_bytes, 1 << 40);
+}
+
+#[tokio::test]
+async fn failing_to_complete_a_file_without_an_extractor_fails_it() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+    sqlx::raw_sql(
+        r#"
+        CREATE FUNCTION refuse_needs_extractor() RETURNS TRIGGER AS $$ BEGIN
+        RAISE EXCEPTION 'refused by test';
+        END;
+        $$ LANGUAGE plpgsql;
+        CREATE TRIGGER refuse_needs_extractor BEFORE UPDATE ON documents
+        FOR EACH ROW WHEN (NEW.needs_extractor) EXECUTE FUNCTION refuse_needs_extractor();
+        "#,
+    )
+    .execute(library.pool())
+    .await
+    .unwrap();
+
+    let path = library.source_file("model.xyz", &[0x00, 0x9f, 0x92, 0x96, 0x01, 0x02, 0x03]);
+    let document = library.upload(&path).await.unwrap().document;
+    let document = library.wait_until_processed(document.id).await;
+    assert_eq!(document.status, DocumentStatus::Failed);
+    let error = document.processing_error.unwrap();
+    assert!(error.starts_with("Failed to save document") && error.contains("refused by test"), "{}", error);
+}
+
+#[tokio::test]
+async fn failing_to_store_derived_data_is_the_documents_error() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+    sqlx::raw_sql(
+        r#"
+        CREATE FUNCTION refuse_terms() RETURNS TRIGGER AS $$ BEGIN
+        RAISE EXCEPTION 'refused by test';
+        END;
+        $$ LANGUAGE plpgsql;
+        CREATE TRIGGER refuse_terms BEFORE INSERT ON document_terms
+        FOR EACH ROW EXECUTE FUNCTION refuse_terms();
+        "#,
+    )
+    .execute(library.pool())
+    .await
+    .unwrap();
+
+    let path = library.source_file("notes.txt", b"Terms of these notes can't be counted.");
+    let document = library.upload(&path).await.unwrap().document;
+    let (outcome, error) = library.finished_run(document.id).await;
+    assert_eq!(outcome, "completed");
+    let error = error.unwrap();
+    assert!(error.starts_with("Failed to store document terms") && error.contains("refused by test"), "{}", error);
+    let document = library.document(document.id).await;
+    assert_eq!(document.status, DocumentStatus::Completed);
+    assert_eq!(document.processing_error, Some(error));
+    assert!(document.content.is_some_and(|content| content.contains("can't be counted")));
+}
+
+/// The page of `large_fixture` with the only "quasar" on it
+const QUASAR_PAGE: i32 = 37;
+
+/// About 3 MB of text in 40 pages
+fn large_fixture(name: &str) -> Vec<String> {
+    (1..=40)
+        .map(|page| {
+            let mut text = format!("{} page {}. ", name, page);
+            while text.len() < 80_000 {
+                text.push_str("Notes on tides, harbours and the keeping of lights through the winter. ");
+                if page == QUASAR_PAGE && text.len() > 40_000 && !text.contains("quasar") {
+                    text.push_str("That night the keeper saw a quasar above the bay. ");
+                }
+            }
+            text
+        })
+        .collect()
+}
+
+/// Upload `pages` as one text file and store them as its pages, as
+/// processing a paged document would
+async fn upload_pages(library: &TestLibrary, name: &str, pages: &[String]) -> uuid::Uuid {
+    let path = library.source_file(name, pages.join("\n").as_bytes());
+    let document = library.upload(&path).await.unwrap().document;
+    let document = library.wait_until_processed(document.id).await;
+    assert_eq!(document.status, DocumentStatus::Completed, "{:?}", document.processing_error);
+    let service = library.state.document_service.lock().await;
+    service.replace_pages(document.id, pages).await.unwrap();
+    service.add_to_reading_list(document.user_id, document.id).await.unwrap();
+    document.id
+}
+
+/// Whether a document's text and pages are stored compressed, or plain
+async fn stored_compressed(library: &TestLibrary, document_id: uuid::Uuid) -> (bool, bool) {
+    sqlx::query_as(
+        r#"
+        SELECT d.content IS NULL AND d.content_zstd IS NOT NULL,
+            bool_and(p.content IS NULL AND p.content_zstd IS NOT NULL)
+        FROM documents d JOIN document_pages p ON p.document_id = d.id
+        WHERE d.id = $1
+        GROUP BY d.id
+        "#,
+    )
+    .bind(document_id)
+    .fetch_one(library.pool())
+    .await
+    .unwrap()
+}
+
+/// Search, page matches, the export snippet and the reading time all find
+/// the quasar of a `large_fixture` document
+async fn assert_quasar_found(library: &TestLibrary, document_id: uuid::Uuid, pages: &[String]) {
+    let text = pages.join("\n");
+    let user_id = library.document(document_id).await.user_id;
+    let query = crate::query_parser::parse_query("quasar").unwrap();
+    let service = library.state.document_service.lock().await;
+
+    let found = service.search_documents(user_id, &query, 10, 0).await.unwrap();
+    assert!(found.iter().any(|document| document.id == document_id));
+    let matches = service.matching_pages(&[document_id], &query.text_terms, 3).await.unwrap();
+    let [(_, page)] = &matches[..] else {
+        panic!("expected one matching page, got {:?}", matches);
+    };
+    assert_eq!(page.page_number, QUASAR_PAGE);
+    assert_eq!(page.char_offset as usize, text.find("quasar").unwrap());
+    assert!(page.snippet.contains("quasar"), "{}", page.snippet);
+
+    let rows = service.search_export_page(user_id, &query, None, 10).await.unwrap();
+    let row = rows.iter().find(|row| row.id == document_id).unwrap();
+    assert!(row.snippet.as_deref().is_some_and(|snippet| snippet.contains("quasar")), "{:?}", row.snippet);
+
+    let reading_list = service.get_reading_list(user_id).await.unwrap();
+    let entry = reading_list.iter().find(|entry| entry.document.id == document_id).unwrap();
+    let words = text.split_whitespace().count();
+    assert_eq!(entry.reading_time_minutes, crate::display::reading_time_minutes(words));
+}
+
+#[tokio::test]
+async fn compressed_and_legacy_text_read_alike() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+
+    let compressed_pages = large_fixture("Compressed");
+    let compressed = upload_pages(&library, "compressed.txt", &compressed_pages).await;
+    assert_eq!(stored_compressed(&library, compressed).await, (true, true));
+    assert_quasar_found(&library, compressed, &compressed_pages).await;
+
+    // Rows written before compression have plain text and no stored reading time
+    let legacy_pages = large_fixture("Legacy");
+    let legacy = upload_pages(&library, "legacy.txt", &legacy_pages).await;
+    sqlx::query("UPDATE documents SET content = $2, content_zstd = NULL, reading_time_minutes = NULL WHERE id = $1")
+        .bind(legacy)
+        .bind(legacy_pages.join("\n"))
+        .execute(library.pool())
+        .await
+        .unwrap();
+    for (index, page) in legacy_pages.iter().enumerate() {
+        sqlx::query(
+            "UPDATE document_pages SET content = $3, content_zstd = NULL WHERE document_id = $1 AND page_number = $2",
+        )
+        .bind(legacy)
+        .bind(index as i32 + 1)
+        .bind(page)
+        .execute(library.pool())
+        .await
+        .unwrap();
+    }
+    assert_eq!(stored_compressed(&library, legacy).await, (false, false));
+    assert_quasar_found(&library, legacy, &legacy_pages).await;
+
+    let report = crate::run_compress_existing_content(&library.state).await.unwrap();
+    assert_eq!((report.documents_compressed, report.pages_compressed), (1, 40));
+    assert!(report.failed.is_empty() && report.compression_ratio > 10.0, "{:?}", report);
+    assert_eq!(stored_compressed(&library, legacy).await, (true, true));
+    assert_quasar_found(&library, legacy, &legacy_pages).await;
+    assert_quasar_found(&library, compressed, &compressed_pages).await;
+}
+
+/// Claims files with its extension ahead of the built-in extractors and
+/// panics, or sleeps past any sensible timeout
+struct FaultyExtractor {
+    extension: &'static str,
+    sleep: Option<std::time::Duration>,
+}
+
+impl Extractor for FaultyExtractor {
+    fn name(&self) -> &str {
+        self.extension
+    }
+
+    fn supports(&self, _mime: &str, extension: &str) -> bool {
+        extension == self.extension
+    }
+
+    fn extract(&self, _path: &std::path::Path) -> Result<ExtractionResult, String> {
+        match self.sleep {
+            Some(sleep) => {
+                std::thread::sleep(sleep);
+                Err("finished too late to matter".to_string())
+            }
+            None => panic!("extractor bug"),
+        }
+    }
+}
+
+fn register_faulty(library: &TestLibrary, extension: &'static str, sleep: Option<std::time::Duration>) {
+    let extractor = Arc::new(FaultyExtractor { extension, sleep });
+    library.state.processing_registry.register_extractor(BUILTIN_PRIORITY + 1, extractor);
+}
+
+#[tokio::test]
+async fn a_panicking_extractor_fails_its_document_and_the_queue_drains() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+    register_faulty(&library, "boom", None);
+
+    let mut panicking = Vec::new();
+    for name in ["first.boom", "second.boom", "third.boom"] {
+        let path = library.source_file(name, name.as_bytes());
+        panicking.push(library.upload(&path).await.unwrap().document.id);
+    }
+    let after = library.source_file("after.txt", b"Processed once the panics are dealt with.");
+    let after = library.upload(&after).await.unwrap().document.id;
+
+    for document_id in panicking {
+        let document = library.wait_until_processed(document_id).await;
+        assert_eq!(document.status, DocumentStatus::Failed);
+        assert_eq!(document.processing_error.as_deref(), Some("Internal error during extraction: extractor bug"));
+        let (outcome, _) = library.finished_run(document_id).await;
+        assert_eq!(outcome, "failed");
+    }
+    let document = library.wait_until_processed(after).await;
+    assert_eq!(document.status, DocumentStatus::Completed, "{:?}", document.processing_error);
+}
+
+#[tokio::test]
+async fn a_slow_extractor_fails_its_document_with_the_timeout() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+    library.state.settings.update(|settings| settings.extraction_timeout_secs = 1).await.unwrap();
+    register_faulty(&library, "slow", Some(std::time::Duration::from_secs(3)));
+
+    let path = library.source_file("report.slow", b"Takes longer than it may.");
+    let document = library.upload(&path).await.unwrap().document;
+    let started = std::time::Instant::now();
+    let document = library.wait_until_processed(document.id).await;
+    assert!(started.elapsed() < std::time::Duration::from_secs(4), "{:?}", started.elapsed());
+    assert_eq!(document.status, DocumentStatus::Failed);
+    assert_eq!(document.processing_error.as_deref(), Some("Extraction timed out after 1s"));
+    let (outcome, error) = library.finished_run(document.id).await;
+    assert_eq!((outcome.as_str(), error.as_deref()), ("timed_out", Some("Extraction timed out after 1s")));
+}
+
+/// The user's tags depth-first, as path and depth
+async fn tag_paths(library: &TestLibrary, user_id: uuid::Uuid) -> Vec<(String, i32)> {
+    let tags = library.state.tag_service.lock().await;
+    let tree = tags.tag_tree(user_id).await.unwrap();
+    tree.into_iter().map(|entry| (entry.path, entry.depth)).collect()
+}
+
+async fn tag_names(library: &TestLibrary, document_id: uuid::Uuid) -> Vec<String> {
+    sqlx::query_scalar("SELECT t.name FROM document_tags dt JOIN tags t ON t.id = dt.tag_id WHERE dt.document_id = $1")
+        .bind(document_id)
+        .fetch_all(library.pool())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn deleting_a_tag_with_nested_tags_refuses_or_moves_them_up() {
+    let Some(library) = TestLibrary::new().await else { return };
+    let user = library.user("Ada").await;
+    let receipt = library.source_file("receipt.txt", b"Tax receipt.");
+    let receipt = library.upload(&receipt).await.unwrap().document.id;
+    let filing = library.source_file("filing.txt", b"The 2025 filing.");
+    let filing = library.upload(&filing).await.unwrap().document.id;
+    let (finance, tax, year) = {
+        let tags = library.state.tag_service.lock().await;
+        let finance = tags.create_tag(user.id, None, "Finance", None, None, None).await.unwrap();
+        let tax = tags.create_tag(user.id, None, "Tax", None, None, Some(finance.id)).await.unwrap();
+        let year = tags.create_tag(user.id, None, "2025", None, None, Some(tax.id)).await.unwrap();
+        tags.attach_tag(receipt, tax.id).await.unwrap();
+        tags.attach_tag(filing, year.id).await.unwrap();
+        (finance, tax, year)
+    };
+    // Refused by default, leaving everything as it was
+    match crate::run_delete_tag(&library.state, tax.id, false).await {
+        Err(AppError::InvalidInput(message)) => assert!(message.contains("nested tags"), "{}", message),
+        other => panic!("expected the delete to be refused, got {:?}", other),
+    }
+    assert_eq!(
+        tag_paths(&library, user.id).await,
+        [("Finance".to_string(), 0), ("Finance/Tax".to_string(), 1), ("Finance/Tax/2025".to_string(), 2)]
+    );
+    assert_eq!(tag_names(&library, receipt).await, ["Tax"]);
+
+    // Reparenting moves the nested tag up and keeps its documents
+    crate::run_delete_tag(&library.state, tax.id, true).await.unwrap();
+    assert_eq!(tag_paths(&library, user.id).await, [("Finance".to_string(), 0), ("Finance/2025".to_string(), 1)]);
+    assert!(tag_names(&library, receipt).await.is_empty());
+    assert_eq!(tag_names(&library, filing).await, ["2025"]);
+
+    // Without nested tags there is nothing to refuse
+    crate::run_delete_tag(&library.state, year.id, false).await.unwrap();
+    crate::run_delete_tag(&library.state, finance.id, false).await.unwrap();
+    assert!(tag_paths(&library, user.id).await.is_empty());
+}
+
+/// Scan uploads with a shell script running `body`, waiting at most
+/// `timeout_secs` for it
+#[cfg(unix)]
+async fn use_fake_scanner(library: &TestLibrary, body: &str, timeout_secs: u64, fail_open: bool) -> String {
+    use std::os::unix::fs::PermissionsExt;
+    let script = library.source_file("scanner/fake-scan", format!("#!/bin/sh\n{}\n", body).as_bytes());
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let command = format!("'{}' --no-summary {{path}}", script.display());
+    library
+        .state
+        .settings
+        .update(|settings| {
+            settings.virus_scan_command = Some(command);
+            settings.virus_scan_timeout_secs = timeout_secs;
+            settings.virus_scan_fail_open = fail_open;
+        })
+        .await
+        .unwrap();
+    script.to_string_lossy().into_owned()
+}
+
+/// Body of the upload_failed notification of a file the scan kept out
+#[cfg(unix)]
+async fn rejected_upload(library: &TestLibrary, user_id: uuid::Uuid) -> String {
+    let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(10);
+    loop {
+        let body: Option<String> =
+            sqlx::query_scalar("SELECT body FROM notifications WHERE user_id = $1 AND kind = 'upload_failed'")
+                .bind(user_id)
+                .fetch_optional(library.pool())
+                .await
+                .unwrap()
+                .flatten();
+        if let Some(body) = body {
+            // Discarded before the owner is told
+            assert_eq!(library.document_count().await, 0);
+            assert!(library.stored_files().is_empty());
+            return body;
+        }
+        assert!(tokio::time::Instant::now() < deadline, "no upload_failed notification");
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+}
+
+#[cfg(unix)]
+async fn scan_of(library: &TestLibrary, document_id: uuid::Uuid) -> ScanRecord {
+    let service = library.state.document_service.lock().await;
+    let provenance = service.get_provenance(document_id).await.unwrap().unwrap();
+    provenance.scan.expect("the scan is part of the provenance")
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn files_the_scanner_accepts_are_stored() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+    let arguments = library.source_file("scanner/arguments", b"");
+    let record_arguments = format!("printf '%s\\n' \"$@\" > '{}'", arguments.display());
+    let scanner = use_fake_scanner(&library, &record_arguments, 10, false).await;
+    let path = library.source_file("clean.txt", b"Nothing to see here.");
+
+    let document_id = library.upload(&path).await.unwrap().document.id;
+    let document = library.wait_until_processed(document_id).await;
+    assert_eq!(document.status, DocumentStatus::Completed, "{:?}", document.processing_error);
+    let scan = scan_of(&library, document_id).await;
+    assert_eq!((scan.scanner, scan.outcome), (scanner, ScanOutcome::Clean));
+
+    // The path is one argument of its own, the copy that was then stored
+    let arguments = std::fs::read_to_string(&arguments).unwrap();
+    let arguments: Vec<&str> = arguments.lines().collect();
+    assert_eq!(arguments.len(), 2, "{:?}", arguments);
+    assert_eq!(arguments[0], "--no-summary");
+    assert!(arguments[1].contains(".scan_"), "{}", arguments[1]);
+    assert!(!std::path::Path::new(arguments[1]).exists());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn files_the_scanner_rejects_are_not_stored() {
+    let Some(library) = TestLibrary::new().await else { return };
+    let user = library.user("Ada").await;
+    use_fake_scanner(&library, "echo \"$2: Eicar-Test-Signature FOUND\"; exit 1", 10, false).await;
+    let path = library.source_file("eicar.txt", b"Pretend this is a virus.");
+
+    library.upload(&path).await.unwrap();
+    let body = rejected_upload(&library, user.id).await;
+    assert!(body.starts_with("eicar.txt:"), "{}", body);
+    assert!(body.contains("Eicar-Test-Signature FOUND"), "{}", body);
+    assert!(path.exists());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn a_hanging_scanner_times_out() {
+    let Some(library) = TestLibrary::new().await else { return };
+    let user = library.user("Ada").await;
+    use_fake_scanner(&library, "exec sleep 30", 1, false).await;
+    let path = library.source_file("slow.txt", b"Scanned forever.");
+
+    library.upload(&path).await.unwrap();
+    let body = rejected_upload(&library, user.id).await;
+    assert!(body.contains("The virus scanner took longer than 1s on slow.txt"), "{}", body);
+
+    // Failing open, the file gets in with the timeout recorded
+    use_fake_scanner(&library, "exec sleep 30", 1, true).await;
+    let document_id = library.upload(&path).await.unwrap().document.id;
+    let document = library.wait_until_processed(document_id).await;
+    assert_eq!(document.status, DocumentStatus::Completed, "{:?}", document.processing_error);
+    assert_eq!(scan_of(&library, document_id).await.outcome, ScanOutcome::TimedOut);
+}
+
+/// Wait for the one "documents:changed" event about `document_id` that
+/// names exactly `fields` and gives `status`
+async fn assert_one_change(library: &TestLibrary, document_id: uuid::Uuid, fields: &[&str], status: Option<DocumentStatus>) {
+    let matching = || {
+        let changes = library.host.document_changes(document_id);
+        changes.into_iter().filter(|change| change.0 == fields && change.1 == status).count()
+    };
+    eventually(&format!("no {:?} change to {}", fields, document_id), || matching() > 0).await;
+    assert_eq!(matching(), 1, "{:?}", library.host.document_changes(document_id));
+}
+
+#[tokio::test]
+async fn every_kind_of_document_change_is_sent_to_the_ui() {
+    let Some(library) = TestLibrary::new().await else { return };
+    let user = library.user("Ada").await;
+    let path = library.source_file("paper.txt", b"Events for every change.");
+    let document_id = library.upload(&path).await.unwrap().document.id;
+    let title = library.wait_until_processed(document_id).await.title;
+
+    // Uploading and processing
+    assert_one_change(&library, document_id, &["created"], Some(DocumentStatus::Queued)).await;
+    assert_one_change(&library, document_id, &["file_path", "mime_type", "file_hash"], None).await;
+    let processing = ["status", "processing_error"];
+    assert_one_change(&library, document_id, &processing, Some(DocumentStatus::Processing)).await;
+    let completed = ["content", "summary", "page_count", "processing_error", "status"];
+    assert_one_change(&library, document_id, &completed, Some(DocumentStatus::Completed)).await;
+    for derived in ["pages", "chunks", "metadata", "terms"] {
+        assert_one_change(&library, document_id, &[derived], None).await;
+    }
+
+    // Edits
+    {
+        let service = library.state.document_service.lock().await;
+        let rename = (document_id, title, "Events".to_string(), "events".to_string());
+        service.rename_documents(user.id, &[rename]).await.unwrap();
+        service.pin_document(user.id, document_id).await.unwrap();
+        service.record_open(document_id).await.unwrap();
+        service.set_unread(user.id, document_id, true).await.unwrap();
+    }
+    {
+        let tags = library.state.tag_service.lock().await;
+        let (tag, _) = tags.get_or_create_tag(user.id, "reading").await.unwrap();
+        tags.attach_tag(document_id, tag.id).await.unwrap();
+    }
+    assert_one_change(&library, document_id, &["title"], None).await;
+    assert_one_change(&library, document_id, &["is_pinned", "pinned_order"], None).await;
+    assert_one_change(&library, document_id, &["open_count", "last_opened_at", "is_unread"], None).await;
+    assert_one_change(&library, document_id, &["is_unread"], None).await;
+    assert_one_change(&library, document_id, &["tags"], None).await;
+
+    // Restoring from the trash
+    library.soft_delete(document_id).await;
+    assert!(library.state.document_service.lock().await.restore_document(document_id).await.unwrap());
+    assert_one_change(&library, document_id, &["deleted_at", "terms"], None).await;
+
+    // An upload whose file went away before it could be stored is removed
+    let slots = library.state.settings.get().await.upload_concurrency;
+    let mut held = Vec::new();
+    for _ in 0..slots {
+        held.push(library.state.upload_queue.acquire(uuid::Uuid::new_v4()).await);
+    }
+    let vanishing = library.source_file("vanishing.txt", b"Gone before it is stored.");
+    let vanished = library.upload(&vanishing).await.unwrap().document.id;
+    std::fs::remove_file(&vanishing).unwrap();
+    drop(held);
+    assert_one_change(&library, vanished, &["created"], Some(DocumentStatus::Queued)).await;
+    assert_one_change(&library, vanished, &["deleted"], None).await;
+}
+
+#[tokio::test]
+async fn opening_a_document_reads_it_until_it_is_marked_unread() {
+    let Some(library) = TestLibrary::new().await else { return };
+    let user = library.user("Ada").await;
+    let opened = library.source_file("opened.txt", b"Opened in the app.");
+    let marked = library.source_file("marked.txt", b"Only ever marked.");
+    let opened = library.upload(&opened).await.unwrap().document.id;
+    let marked = library.upload(&marked).await.unwrap().document.id;
+    library.wait_until_processed(opened).await;
+    library.wait_until_processed(marked).await;
+    let unread = || async {
+        let service = library.state.document_service.lock().await;
+        service.unread_count(user.id).await.unwrap()
+    };
+    let pool = library.pool();
+    let open_count = |document_id| async move {
+        let count: i32 = sqlx::query_scalar("SELECT open_count FROM documents WHERE id = $1")
+            .bind(document_id)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        count
+    };
+    assert!(library.document(opened).await.is_unread);
+    assert_eq!(unread().await, 2);
+
+    // Opening reads it
+    crate::run_get_document(&library.state, opened, None).await.unwrap();
+    assert!(!library.document(opened).await.is_unread);
+    assert_eq!(unread().await, 1);
+
+    // Marked unread, it stays so until the next open
+    crate::set_document_unread(&library.state, opened, true).await.unwrap();
+    assert!(library.document(opened).await.is_unread);
+    assert_eq!(unread().await, 2);
+    crate::run_get_document(&library.state, opened, None).await.unwrap();
+    assert!(!library.document(opened).await.is_unread);
+    assert_eq!(open_count(opened).await, 2);
+
+    // Marking read and unread never counts as opening
+    crate::set_document_unread(&library.state, marked, false).await.unwrap();
+    assert!(!library.document(marked).await.is_unread);
+    assert_eq!(unread().await, 0);
+    crate::set_document_unread(&library.state, marked, true).await.unwrap();
+    assert!(library.document(marked).await.is_unread);
+    assert_eq!(open_count(marked).await, 0);
+
+    match crate::set_document_unread(&library.state, uuid::Uuid::new_v4(), true).await {
+        Err(AppError::NotFound(_)) => {}
+        other => panic!("expected NotFound, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn raising_upload_concurrency_takes_effect_at_once() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+    let settings = library.state.settings.get().await;
+    assert_eq!(settings.upload_concurrency, 2);
+
+    // Two uploads being stored and four waiting
+    let queue = &library.state.upload_queue;
+    let storing = [queue.acquire(uuid::Uuid::new_v4()).await, queue.acquire(uuid::Uuid::new_v4()).await];
+    let waiting: Vec<_> = (0..4)
+        .map(|_| {
+            let queue = Arc::clone(queue);
+            tokio::spawn(async move { queue.acquire(uuid::Uuid::new_v4()).await })
+        })
+        .collect();
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert!(waiting.iter().all(|upload| !upload.is_finished()));
+
+    let update = crate::run_update_settings(&library.state, AppSettings { upload_concurrency: 6, ..settings })
+        .await
+        .unwrap();
+    assert!(update.acknowledged_by.contains(&"upload_queue"), "{:?}", update.acknowledged_by);
+    let mut started = Vec::new();
+    for upload in waiting {
+        started.push(tokio::time::timeout(std::time::Duration::from_secs(5), upload).await.unwrap().unwrap());
+    }
+
+    // Six at a time, and no more
+    let seventh = tokio::spawn({
+        let queue = Arc::clone(queue);
+        async move { queue.acquire(uuid::Uuid::new_v4()).await }
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert!(!seventh.is_finished());
+    drop(storing);
+    tokio::time::timeout(std::time::Duration::from_secs(5), seventh).await.unwrap().unwrap();
+    drop(started);
+
+    // Uploads go through as before
+    let path = library.source_file("after.txt", b"Uploaded after the change.");
+    let document_id = library.upload(&path).await.unwrap().document.id;
+    assert_eq!(library.wait_until_processed(document_id).await.status, DocumentStatus::Completed);
+}
+
+#[tokio::test]
+async fn two_column_pdfs_read_one_column_after_the_other() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+    let left = [
+        "Columns are read top to bottom before",
+        "the reader moves on, so this first",
+        "sentence runs down the left column",
+        "and ends here. A second sentence",
+        "also stays in the left column and",
+        "only ends once the column does.",
+        "Without layout analysis these lines",
+        "would alternate with the right.",
+    ];
+    let right = [
+        "The right column starts its own",
+        "sentence at the top, which should",
+        "follow the whole left column. It",
+        "keeps going line after line until",
+        "the bottom of the page, where the",
+        "second sentence of this column",
+        "comes to an end, closing the",
+        "page as the last words read.",
+    ];
+    let pdf = two_column_pdf("Reading Order in Two Columns", &left, &right);
+    let stream_order = lopdf::Document::load_mem(&pdf).unwrap().extract_text(&[1]).unwrap();
+    assert!(stream_order.contains("before"), "{}", stream_order);
+    assert!(!stream_order.split_whitespace().collect::<Vec<_>>().join(" ").contains(&left[..2].join(" ")));
+    let path = library.source_file("two-columns.pdf", &pdf);
+
+    let document_id = library.upload(&path).await.unwrap().document.id;
+    let document = library.wait_until_processed(document_id).await;
+    assert_eq!(document.status, DocumentStatus::Completed, "{:?}", document.processing_error);
+    let content = document.content.unwrap();
+    let words = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    let expected = format!("Reading Order in Two Columns {} {}", left.join(" "), right.join(" "));
+    assert_eq!(words, expected);
+}
+
+/// Chunks of a document as page number, text and embedding model
+async fn chunks_by_page(library: &TestLibrary, document_id: uuid::Uuid) -> Vec<(i32, String, Option<String>)> {
+    sqlx::query_as(
+        "SELECT page_number, content, embedding_model FROM document_chunks WHERE document_id = $1 ORDER BY chunk_index",
+    )
+    .bind(document_id)
+    .fetch_all(library.pool())
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn reprocessing_rechunks_only_the_pages_that_changed() {
+    let Some(library) = TestLibrary::new().await else { return };
+    library.user("Ada").await;
+    let first = "The first page stays exactly as it was.";
+    let third = "The third page does not change either.";
+    let path = library.source_file("versions.pdf", &paged_pdf(&[first, "The second page, first draft.", third]));
+    let document_id = library.upload(&path).await.unwrap().document.id;
+    library.wait_until_processed(document_id).await;
+    library.finished_run(document_id).await;
+
+    // Chunks embedded since keep their model only if they are kept
+    sqlx::query("UPDATE document_chunks SET embedding_model = 'fixture-model' WHERE document_id = $1")
+        .bind(document_id)
+        .execute(library.pool())
+        .await
+        .unwrap();
+    let stored = library.document(document_id).await.file_path.unwrap();
+    std::fs::write(&stored, paged_pdf(&[first, "The second page, rewritten.", third])).unwrap();
+
+    crate::restart_processing(&library.state, document_id, &[DocumentStatus::Completed], PdfLayout::Auto, None)
+        .await
+        .unwrap();
+    let document = library.wait_until_processed(document_id).await;
+    assert_eq!(document.status, DocumentStatus::Completed, "{:?}", document.processing_error);
+
+    let chunks = chunks_by_page(&library, document_id).await;
+    let model = Some("fixture-model".to_string());
+    assert_eq!(
+        chunks,
+        [
+            (1, first.to_string(), model.clone()),
+            (2, "The second page, rewritten.".to_string(), None),
+            (3, third.to_string(), model),
+        ]
+    );
+    // The second run is recorded as finished after the document is
+    let pool = library.pool();
+    let mut runs = 0;
+    for _ in 0..200 {
+        if runs == 2 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        runs = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM processing_runs WHERE document_id = $1 AND finished_at IS NOT NULL",
+        )
+        .bind(document_id)
+        .fetch_one(pool)
+        .await
+        .unwrap();
+    }
+    assert_eq!(runs, 2, "the reprocessing run was never recorded as finished");
+    let reused: Option<i32> =
+        sqlx::query_scalar("SELECT pages_reused FROM processing_runs WHERE document_id = $1 ORDER BY started_at DESC LIMIT 1")
+            .bind(document_id)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+    assert_eq!(reused, Some(2));
+}
+
+#[tokio::test]
+async fn list_entries_are_a_fraction_of_the_full_documents() {
+    let Some(library) = TestLibrary::new().await else { return };
+    let ada = library.user("Ada").await;
+    sqlx::query(
+        r#"
+        INSERT INTO documents (user_id, title, content, summary, status, file_name, file_size_bytes, file_type)
+        SELECT $1, 'Report ' || n, repeat('Body text of the report. ', 400), repeat('A long summary sentence. ', 60),
+               'completed', 'report-' || n || '.pdf', 250000, 'pdf'
+        FROM generate_series(1, 1000) AS n
+        "#,
+    )
+    .bind(ada.id)
+    .execute(library.pool())
+    .await
+    .unwrap();
+
+    let service = library.state.document_service.lock().await;
+    let sort = crate::models::DocumentSort::default();
+    let full = service.get_documents_by_user(ada.id, false, sort, false, None, true).await.unwrap();
+    let listed = service.get_documents_by_user(ada.id, false, sort, false, None, false).await.unwrap();
+    let selection = Arc::new(crate::FieldSelection::default());
+    let entries = crate::list_entries(listed, crate::DEFAULT_SUMMARY_PREVIEW_CHARS, None, &selection);
+
+    assert_eq!(entries.len(), 1000);
+    let before = serde_json::to_vec(&full).unwrap().len();
+    let after = serde_json::to_vec(&entries).unwrap().len();
+    // Content and summaries were most of the payload; previews are a sliver
+    assert!(after * 10 < before, "{} bytes listed against {} in full", after, before);
+    let entry = serde_json::to_value(&entries[0]).unwrap();
+    assert!(entry.get("content").unwrap().is_null());
+    assert!(entry.get("summary").unwrap().is_null());
+    assert_eq!(entry["summary_truncated"], true);
+    assert!(entry["summary_preview"].as_str().unwrap().chars().count() <= crate::DEFAULT_SUMMARY_PREVIEW_CHARS);
+}
+
+/// A user's tags as workspace name, tag name, color and description
+async fn taxonomy(
+    library: &TestLibrary,
+    user: TestUser,
+) -> Vec<(Option<String>, String, Option<String>, Option<String>)> {
+    sqlx::query_as(
+        r#"
+        SELECT w.name, t.name, t.color, t.description
+        FROM tags t LEFT JOIN workspaces w ON w.id = t.workspace_id
+        WHERE t.user_id = $1
+        ORDER BY w.name NULLS FIRST, t.name
+        "#,
+    )
+    .bind(user.id)
+    .fetch_all(library.pool())
+    .await
+    .unwrap()
+}
+
+async fn import_config(
+    library: &TestLibrary,
+    user: TestUser,
+    path: &std::path::Path,
+    strategy: MergeStrategy,
+) -> crate::models::LibraryConfigImportReport {
+    let workspaces = library.state.workspace_service.lock().await;
+    let tags = library.state.tag_service.lock().await;
+    crate::library_config::import_config(&workspaces, &tags, user.id, path, strategy).await.unwrap()
+}
+
+/// Ada's tags: one outside workspaces, two in "Research"
+async fn seed_taxonomy(library: &TestLibrary) -> TestUser {
+    let ada = library.user("Ada").await;
+    let research = library.workspace(ada, "Research").await;
+    let tags = library.state.tag_service.lock().await;
+    tags.create_tag(ada.id, None, "urgent", Some("#ef4444"), Some("Needs an answer this week"), None)
+        .await
+        .unwrap();
+    tags.create_tag(ada.id, Some(research), "papers", Some("#6366f1"), None, None).await.unwrap();
+    tags.create_tag(ada.id, Some(research), "to read", None, None, None).await.unwrap();
+    ada
+}
+
+#[tokio::test]
+async fn a_library_config_round_trips_into_an_empty_library() {
+    let Some(library) = TestLibrary::new().await else { return };
+    let ada = seed_taxonomy(&library).await;
+    let path = library.source_file("taxonomy.json", b"");
+    {
+        let workspaces = library.state.workspace_service.lock().await;
+        let tags = library.state.tag_service.lock().await;
+        let summary = crate::library_config::export_config(&workspaces, &tags, ada.id, &path).await.unwrap();
+        assert_eq!((summary.workspace_count, summary.tag_count), (1, 3));
+    }
+
+    let before = taxonomy(&library, ada).await;
+    sqlx::query("DELETE FROM tags WHERE user_id = $1").bind(ada.id).execute(library.pool()).await.unwrap();
+    sqlx::query("DELETE FROM workspaces WHERE owner_id = $1").bind(ada.id).execute(library.pool()).await.unwrap();
+
+    let report = import_config(&library, ada, &path, MergeStrategy::Skip).await;
+
+    assert_eq!((report.workspaces_created, report.tags_created), (1, 3));
+    assert!(report.failed.is_empty(), "{:?}", report.failed);
+    assert_eq!(taxonomy(&library, ada).await, before);
+
+    // And into someone else's library
+    let bob = library.user("Bob").await;
+    import_config(&library, bob, &path, MergeStrategy::Skip).await;
+    assert_eq!(taxonomy(&library, bob).await, before);
+}
+
+#[tokio::test]
+async fn importing_a_library_config_again_follows_the_merge_strategy() {
+    let Some(library) = TestLibrary::new().await else { return };
+    let ada = seed_taxonomy(&library).await;
+    let path = library.source_file("taxonomy.json", b"");
+    {
+        let workspaces = library.state.workspace_service.lock().await;
+        let tags = library.state.tag_service.lock().await;
+        crate::library_config::export_config(&workspaces, &tags, ada.id, &path).await.unwrap();
+    }
+    let before = taxonomy(&library, ada).await;
+
+    let skipped = import_config(&library, ada, &path, MergeStrategy::Skip).await;
+    assert_eq!((skipped.workspaces_reused, skipped.tags_skipped, skipped.tags_created), (1, 3, 0));
+    assert_eq!(taxonomy(&library, ada).await, before);
+
+    sqlx::query("UPDATE tags SET color = '#000000' WHERE name = 'urgent'")
+        .execute(library.pool())
+        .await
+        .unwrap();
+    let overwritten = import_config(&library, ada, &path, MergeStrategy::Overwrite).await;
+    assert_eq!((overwritten.workspaces_reused, overwritten.tags_overwritten), (1, 3));
+    assert_eq!(taxonomy(&library, ada).await, before);
+
+    let renamed = import_config(&library, ada, &path, MergeStrategy::Rename).await;
+    let workspaces: Vec<(String, String)> =
+        renamed.workspaces_renamed.into_iter().map(|r| (r.name, r.imported_as)).collect();
+    assert_eq!(workspaces, [("Research".to_string(), "Research (2)".to_string())]);
+    // Tags of the renamed workspace land in the new one, where nothing is taken
+    let tags: Vec<(String, String)> = renamed.tags_renamed.into_iter().map(|r| (r.name, r.imported_as)).collect();
+    assert_eq!(tags, [("urgent".to_string(), "urgent (2)".to_string())]);
+    assert_eq!(renamed.tags_created, 2);
+    assert_eq!(taxonomy(&library, ada).await.len(), 6);
+}
@@ -0,0 +1,133 @@
+//! Tidying extracted text before it is stored
+//!
+//! Cleanup only removes extraction noise: control characters, trailing
+//! whitespace, words hyphenated across a line break and long runs of blank
+//! lines. Documents processed with `clean_text` off keep the text exactly as
+//! the extractor returned it.
+//!
+//! `sanitize_for_storage` is different: it runs on every extraction, since
+//! Postgres refuses text it can't store and the whole save would fail.
+
+use std::borrow::Cow;
+
+/// Blank lines kept in a row; longer runs are cut down to this
+const MAX_BLANK_LINES: usize = 2;
+
+/// Clean one block of extracted text, such as a page
+pub fn clean_text(text: &str) -> String {
+    let mut cleaned = String::with_capacity(text.len());
+    let mut blank_run = 0;
+    let mut lines = text.lines().map(|line| strip_control(line).trim_end().to_string()).peekable();
+
+    while let Some(mut line) = lines.next() {
+        // "infor-" followed by "mation ..." joins into "information ..."
+        while let Some(next) = lines.peek() {
+            if !ends_with_broken_word(&line) || !next.starts_with(char::is_lowercase) {
+                break;
+            }
+            line.pop();
+            let next = lines.next().unwrap_or_default();
+            let (word, rest) = next.split_once(' ').unwrap_or((next.as_str(), ""));
+            line.push_str(word);
+            if !rest.is_empty() {
+                // Keep the rest of the following line on its own line
+                cleaned.push_str(&line);
+                cleaned.push('\n');
+                line = rest.trim_start().to_string();
+            }
+        }
+
+        if line.trim().is_empty() {
+            blank_run += 1;
+            if blank_run > MAX_BLANK_LINES {
+                continue;
+            }
+            cleaned.push('\n');
+            continue;
+        }
+        blank_run = 0;
+        cleaned.push_str(&line);
+        cleaned.push('\n');
+    }
+
+    // Keep the input's trailing newline, or lack of one
+    if !text.ends_with('\n') {
+        cleaned.pop();
+    }
+    cleaned
+}
+
+/// Make text safe to store in Postgres, returning it and how many bytes
+/// were changed
+///
+/// NUL characters, which Postgres text can't hold, are dropped and line
+/// endings become `\n`. Invalid UTF-8 never gets this far: extractors decode
+/// lossily, so it already arrives as U+FFFD. Text with nothing to change is
+/// returned as is, without copying.
+pub fn sanitize_for_storage(text: &str) -> (Cow<'_, str>, usize) {
+    if !text.bytes().any(|b| b == b'\0' || b == b'\r') {
+        return (Cow::Borrowed(text), 0);
+    }
+    let mut sanitized = String::with_capacity(text.len());
+    let mut altered = 0;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\0' => altered += 1,
+            '\r' => {
+                altered += 1;
+                // "\r\n" keeps its "\n"; a lone "\r" becomes one
+                if chars.peek() != Some(&'\n') {
+                    sanitized.push('\n');
+                }
+            }
+            c => sanitized.push(c),
+        }
+    }
+    (Cow::Owned(sanitized), altered)
+}
+
+/// Whether a line ends in a word cut by a hyphen, e.g. "the infor-"
+fn ends_with_broken_word(line: &str) -> bool {
+    let Some(stem) = line.strip_suffix('-') else {
+        return false;
+    };
+    stem.chars().next_back().is_some_and(char::is_alphabetic)
+}
+
+/// Drop control characters other than tabs, which extraction leaves behind
+fn strip_control(line: &str) -> String {
+    line.chars().filter(|&c| c == '\t' || !c.is_control()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_with_nothing_to_change_is_borrowed() {
+        let text = "Plain text\nwith an ending\tand U+FFFD \u{fffd}\n";
+        let (sanitized, altered) = sanitize_for_storage(text);
+        assert!(matches!(sanitized, Cow::Borrowed(_)));
+        assert_eq!((sanitized.as_ref(), altered), (text, 0));
+    }
+
+    #[test]
+    fn nuls_are_dropped_and_line_endings_become_newlines() {
+        let (sanitized, altered) = sanitize_for_storage("a\0b\r\nc\rd\0\0");
+        assert_eq!(sanitized, "ab\nc\nd");
+        assert_eq!(altered, 5);
+    }
+
+    #[test]
+    fn lone_surrogates_arrive_as_replacement_characters_and_stay() {
+        // What extractors make of a lone surrogate in UTF-16 and in UTF-8 bytes
+        let from_utf16 = String::from_utf16_lossy(&[0x0041, 0xD800, 0x0000, 0x0042]);
+        let from_utf8 = String::from_utf8_lossy(b"C\xED\xA0\x80\0D").into_owned();
+
+        let (sanitized, altered) = sanitize_for_storage(&from_utf16);
+        assert_eq!((sanitized.as_ref(), altered), ("A\u{fffd}B", 1));
+        let (sanitized, altered) = sanitize_for_storage(&from_utf8);
+        assert_eq!((sanitized.as_ref(), altered), ("C\u{fffd}\u{fffd}\u{fffd}D", 1));
+    }
+}
@@ -0,0 +1,78 @@
+//! zstd compression of extracted text at rest
+//!
+//! Text is written compressed to `content_zstd` in documents and
+//! document_pages, leaving the plain `content` column NULL. Rows written
+//! before compression stay plain until they are written again or
+//! `compress_existing_content` reaches them, so readers take whichever of
+//! the two a row has. The database never decompresses: searching content
+//! goes through documents.content_tsvector, which writers fill from the
+//! text they compress.
+
+use std::io;
+
+/// zstd's default level: fast to write, with most of the ratio of the
+/// higher levels on text
+const LEVEL: i32 = 3;
+
+pub fn compress(text: &str) -> io::Result<Vec<u8>> {
+    zstd::bulk::compress(text.as_bytes(), LEVEL)
+}
+
+pub fn decompress(bytes: &[u8]) -> io::Result<String> {
+    let raw = zstd::decode_all(bytes)?;
+    String::from_utf8(raw).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// The text of a row stored plain or compressed; None with neither
+pub fn stored_text(plain: Option<String>, compressed: Option<&[u8]>) -> io::Result<Option<String>> {
+    match (plain, compressed) {
+        (Some(text), _) => Ok(Some(text)),
+        (None, Some(bytes)) => decompress(bytes).map(Some),
+        (None, None) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(text: &str) -> String {
+        decompress(&compress(text).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn text_comes_back_as_it_was_compressed() {
+        for text in [
+            "",
+            "plain ASCII",
+            "Größe, façade, naïve – “quoted” — 東京の地図 🗺️",
+            "line one\r\nline two\n\ttabbed\u{0}nul",
+        ] {
+            assert_eq!(round_trip(text), text);
+        }
+    }
+
+    #[test]
+    fn long_repetitive_text_shrinks() {
+        let text = "The lighthouse keeper logged the weather. ".repeat(50_000);
+        let compressed = compress(&text).unwrap();
+        assert!(compressed.len() * 20 < text.len(), "{} bytes", compressed.len());
+        assert_eq!(decompress(&compressed).unwrap(), text);
+    }
+
+    #[test]
+    fn damaged_or_non_utf8_data_is_an_error() {
+        let compressed = compress("some text to damage").unwrap();
+        assert!(decompress(&compressed[..compressed.len() / 2]).is_err());
+        let not_utf8 = zstd::bulk::compress(&[0xff, 0xfe, 0x00], LEVEL).unwrap();
+        assert_eq!(decompress(&not_utf8).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn plain_text_wins_over_compressed() {
+        let compressed = compress("compressed").unwrap();
+        assert_eq!(stored_text(Some("plain".to_string()), Some(&compressed)).unwrap().as_deref(), Some("plain"));
+        assert_eq!(stored_text(None, Some(&compressed)).unwrap().as_deref(), Some("compressed"));
+        assert_eq!(stored_text(None, None).unwrap(), None);
+    }
+}
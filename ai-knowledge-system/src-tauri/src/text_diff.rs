@@ -0,0 +1,315 @@
+//! Word-level diff between two texts
+//!
+//! A Myers diff over tokens: words and the whitespace between them, or,
+//! when whitespace is normalized, words with their trailing whitespace
+//! compared by the word alone. Hunks carry char (not byte) ranges into both
+//! texts and together cover each text completely.
+
+use crate::models::{DiffHunk, DiffKind, DocumentDiff};
+
+/// Edits the diff searches before giving up on a shortest script; beyond
+/// this the unmatched middle is reported as one deletion and one insertion
+const MAX_EDIT_DISTANCE: usize = 1_000;
+
+/// Most hunks returned; later ones are dropped and the diff marked truncated
+pub const MAX_HUNKS: usize = 2_000;
+
+/// A run of text compared as one unit
+struct Token<'a> {
+    text: &'a str,
+    /// What is compared; the word alone when whitespace is normalized
+    key: &'a str,
+    char_len: usize,
+}
+
+fn tokenize(text: &str, normalize_whitespace: bool) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        let starts_with_space = rest.starts_with(char::is_whitespace);
+        let word_end = rest.find(|c: char| c.is_whitespace() != starts_with_space).unwrap_or(rest.len());
+        // Normalized, a word takes its trailing whitespace along; only
+        // leading whitespace of the whole text stands alone
+        let end = if normalize_whitespace && !starts_with_space {
+            rest[word_end..]
+                .find(|c: char| !c.is_whitespace())
+                .map_or(rest.len(), |i| word_end + i)
+        } else {
+            word_end
+        };
+
+        let token_text = &rest[..end];
+        let key = match (normalize_whitespace, starts_with_space) {
+            (true, true) => "",
+            (true, false) => &rest[..word_end],
+            (false, _) => token_text,
+        };
+        tokens.push(Token {
+            text: token_text,
+            key,
+            char_len: token_text.chars().count(),
+        });
+        rest = &rest[end..];
+    }
+    tokens
+}
+
+/// One step of an edit script, by token index
+#[derive(Clone, Copy)]
+enum Edit {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Shortest edit script turning `a` into `b`, or None when it needs more
+/// than `max_d` insertions and deletions
+fn shortest_edit(a: &[&str], b: &[&str], max_d: usize) -> Option<Vec<Edit>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let offset = max_d as isize + 1;
+    let mut v = vec![0isize; 2 * max_d + 3];
+    // v for diagonals -d..=d after each round d, for walking back
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    for d in 0..=max_d as isize {
+        let mut done = false;
+        for k in (-d..=d).step_by(2) {
+            let i = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+                v[i + 1]
+            } else {
+                v[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[i] = x;
+            if x >= n && y >= m {
+                done = true;
+                break;
+            }
+        }
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+        if done {
+            return Some(backtrack(&trace, n, m));
+        }
+    }
+    None
+}
+
+/// Recover the edits from the furthest points reached in each round
+fn backtrack(trace: &[Vec<isize>], n: isize, m: isize) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+
+    for d in (1..trace.len() as isize).rev() {
+        let previous = &trace[d as usize - 1];
+        let furthest = |k: isize| previous[(k + d - 1) as usize];
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && furthest(k - 1) < furthest(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = furthest(previous_k);
+        let previous_y = previous_x - previous_k;
+
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit::Equal(x as usize, y as usize));
+        }
+        if x == previous_x {
+            y -= 1;
+            edits.push(Edit::Insert(y as usize));
+        } else {
+            x -= 1;
+            edits.push(Edit::Delete(x as usize));
+        }
+    }
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        edits.push(Edit::Equal(x as usize, y as usize));
+    }
+
+    edits.reverse();
+    edits
+}
+
+/// Diff two texts word by word
+///
+/// With `normalize_whitespace`, texts differing only in spaces, tabs and
+/// line breaks compare equal; equal hunks then show the text of `a`.
+pub fn diff_texts(a: &str, b: &str, normalize_whitespace: bool) -> DocumentDiff {
+    let a_tokens = tokenize(a, normalize_whitespace);
+    let b_tokens = tokenize(b, normalize_whitespace);
+    let a_keys: Vec<&str> = a_tokens.iter().map(|t| t.key).collect();
+    let b_keys: Vec<&str> = b_tokens.iter().map(|t| t.key).collect();
+
+    // Shared start and end are matched directly, leaving Myers the middle
+    let prefix = a_keys.iter().zip(&b_keys).take_while(|(x, y)| x == y).count();
+    let suffix = a_keys[prefix..]
+        .iter()
+        .rev()
+        .zip(b_keys[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let a_middle = &a_keys[prefix..a_keys.len() - suffix];
+    let b_middle = &b_keys[prefix..b_keys.len() - suffix];
+
+    let mut edits: Vec<Edit> = (0..prefix).map(|i| Edit::Equal(i, i)).collect();
+    let mut truncated = false;
+    match shortest_edit(a_middle, b_middle, MAX_EDIT_DISTANCE) {
+        Some(middle) => edits.extend(middle.into_iter().map(|edit| match edit {
+            Edit::Equal(i, j) => Edit::Equal(prefix + i, prefix + j),
+            Edit::Delete(i) => Edit::Delete(prefix + i),
+            Edit::Insert(j) => Edit::Insert(prefix + j),
+        })),
+        None => {
+            truncated = true;
+            edits.extend((0..a_middle.len()).map(|i| Edit::Delete(prefix + i)));
+            edits.extend((0..b_middle.len()).map(|j| Edit::Insert(prefix + j)));
+        }
+    }
+    let (a_suffix, b_suffix) = (a_keys.len() - suffix, b_keys.len() - suffix);
+    edits.extend((0..suffix).map(|i| Edit::Equal(a_suffix + i, b_suffix + i)));
+
+    let mut hunks = group_hunks(&edits, &a_tokens, &b_tokens);
+    if hunks.len() > MAX_HUNKS {
+        hunks.truncate(MAX_HUNKS);
+        truncated = true;
+    }
+    DocumentDiff { hunks, truncated }
+}
+
+/// Merge consecutive edits of the same kind into hunks
+fn group_hunks(edits: &[Edit], a: &[Token], b: &[Token]) -> Vec<DiffHunk> {
+    let mut hunks: Vec<DiffHunk> = Vec::new();
+    // Where the next token of each text starts, for empty ranges
+    let (mut a_pos, mut b_pos) = (0, 0);
+
+    for edit in edits {
+        let (kind, text, a_len, b_len) = match *edit {
+            Edit::Equal(i, j) => (DiffKind::Equal, a[i].text, a[i].char_len, b[j].char_len),
+            Edit::Delete(i) => (DiffKind::Delete, a[i].text, a[i].char_len, 0),
+            Edit::Insert(j) => (DiffKind::Insert, b[j].text, 0, b[j].char_len),
+        };
+
+        match hunks.last_mut() {
+            Some(hunk) if hunk.kind == kind => {
+                hunk.a_end += a_len;
+                hunk.b_end += b_len;
+                hunk.text.push_str(text);
+            }
+            _ => hunks.push(DiffHunk {
+                kind,
+                a_start: a_pos,
+                a_end: a_pos + a_len,
+                b_start: b_pos,
+                b_end: b_pos + b_len,
+                text: text.to_string(),
+            }),
+        }
+        a_pos += a_len;
+        b_pos += b_len;
+    }
+    hunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hunks as "kind a_start..a_end b_start..b_end text", after checking
+    /// they cover both texts end to end, in chars
+    fn hunks(a: &str, b: &str) -> Vec<String> {
+        let diff = diff_texts(a, b, false);
+        assert!(!diff.truncated);
+        let (mut a_pos, mut b_pos) = (0, 0);
+        let (mut a_text, mut b_text) = (String::new(), String::new());
+        for hunk in &diff.hunks {
+            assert_eq!((hunk.a_start, hunk.b_start), (a_pos, b_pos), "{:?}", hunk);
+            (a_pos, b_pos) = (hunk.a_end, hunk.b_end);
+            if hunk.kind != DiffKind::Insert {
+                a_text.push_str(&hunk.text);
+            }
+            if hunk.kind != DiffKind::Delete {
+                b_text.push_str(&hunk.text);
+            }
+        }
+        assert_eq!((a_text.as_str(), b_text.as_str()), (a, b));
+        assert_eq!((a_pos, b_pos), (a.chars().count(), b.chars().count()));
+
+        diff.hunks
+            .iter()
+            .map(|h| format!("{:?} {}..{} {}..{} {}", h.kind, h.a_start, h.a_end, h.b_start, h.b_end, h.text))
+            .collect()
+    }
+
+    #[test]
+    fn inserts_at_either_end() {
+        assert_eq!(hunks("b c", "a b c"), ["Insert 0..0 0..2 a ", "Equal 0..3 2..5 b c"]);
+        assert_eq!(hunks("a b", "a b c"), ["Equal 0..3 0..3 a b", "Insert 3..3 3..5  c"]);
+        assert_eq!(hunks("", "a"), ["Insert 0..0 0..1 a"]);
+    }
+
+    #[test]
+    fn deletes_at_either_end() {
+        assert_eq!(hunks("a b c", "b c"), ["Delete 0..2 0..0 a ", "Equal 2..5 0..3 b c"]);
+        assert_eq!(hunks("a b c", "a b"), ["Equal 0..3 0..3 a b", "Delete 3..5 3..3  c"]);
+        assert_eq!(hunks("a", ""), ["Delete 0..1 0..0 a"]);
+    }
+
+    #[test]
+    fn replacements_at_either_end() {
+        assert_eq!(
+            hunks("x b c", "y b c"),
+            ["Delete 0..1 0..0 x", "Insert 1..1 0..1 y", "Equal 1..5 1..5  b c"]
+        );
+        assert_eq!(
+            hunks("a b x", "a b yz"),
+            ["Equal 0..4 0..4 a b ", "Delete 4..5 4..4 x", "Insert 5..5 4..6 yz"]
+        );
+    }
+
+    #[test]
+    fn offsets_count_chars_not_bytes() {
+        assert_eq!(
+            hunks("Grüße aus Köln", "Grüße nach Köln"),
+            [
+                "Equal 0..6 0..6 Grüße ",
+                "Delete 6..9 6..6 aus",
+                "Insert 9..9 6..10 nach",
+                "Equal 9..14 10..15  Köln"
+            ]
+        );
+        assert_eq!(
+            hunks("東京 🙂 ok", "東京 🎉🎉 ok"),
+            [
+                "Equal 0..3 0..3 東京 ",
+                "Delete 3..4 3..3 🙂",
+                "Insert 4..4 3..5 🎉🎉",
+                "Equal 4..7 5..8  ok"
+            ]
+        );
+    }
+
+    #[test]
+    fn normalized_whitespace_compares_words_alone() {
+        let diff = diff_texts("one  two\nthree", "one two three", true);
+        assert_eq!(diff.hunks.len(), 1);
+        assert_eq!(diff.hunks[0].kind, DiffKind::Equal);
+        assert_eq!((diff.hunks[0].a_end, diff.hunks[0].b_end), (14, 13));
+        assert_eq!(diff_texts("one  two", "one two", false).hunks.len(), 4);
+    }
+
+    #[test]
+    fn identical_texts_are_one_equal_hunk() {
+        assert_eq!(hunks("same text", "same text"), ["Equal 0..9 0..9 same text"]);
+        assert!(diff_texts("", "", false).hunks.is_empty());
+    }
+}
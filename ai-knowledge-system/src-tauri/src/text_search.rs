@@ -0,0 +1,284 @@
+use crate::models::{DocumentPage, DocumentSearchHits, InDocumentMatch, InDocumentSearchResult, SearchHit};
+use std::collections::HashMap;
+use unicode_normalization::char::{decompose_canonical, is_combining_mark};
+
+/// Most matches returned by a single in-document search
+pub const MAX_MATCHES: usize = 500;
+
+/// Characters of context on each side of a match snippet
+const SNIPPET_CONTEXT_CHARS: usize = 40;
+
+/// Hits in one document closer than this many chars are merged
+#[allow(dead_code)] // until chunk-based search lands
+pub const DEFAULT_MERGE_GAP_CHARS: usize = 200;
+
+/// Hits kept per document in library-wide results
+#[allow(dead_code)] // until chunk-based search lands
+pub const DEFAULT_MAX_HITS_PER_DOCUMENT: usize = 3;
+
+/// Chars before a match where a search result snippet starts
+const RESULT_SNIPPET_LEAD_CHARS: usize = 80;
+
+/// Chars in a search result snippet
+const RESULT_SNIPPET_CHARS: usize = 240;
+
+/// Lowercase and strip diacritics, remembering where each folded char came from
+///
+/// Returns the folded chars and, for each, the index of the original char it
+/// was derived from. Offsets are in chars, never bytes.
+pub fn fold_with_origins(text: &str) -> (Vec<char>, Vec<usize>) {
+    let mut folded = Vec::with_capacity(text.len());
+    let mut origins = Vec::with_capacity(text.len());
+
+    for (index, c) in text.chars().enumerate() {
+        for lower in c.to_lowercase() {
+            decompose_canonical(lower, |d| {
+                if !is_combining_mark(d) {
+                    folded.push(d);
+                    origins.push(index);
+                }
+            });
+        }
+    }
+
+    (folded, origins)
+}
+
+/// Case-insensitive, diacritic-folded search over document text
+///
+/// When pages are supplied (sorted by start offset), each match also reports
+/// the page it starts on.
+pub fn search_text(content: &str, pages: &[DocumentPage], query: &str) -> InDocumentSearchResult {
+    let (needle, _) = fold_with_origins(query.trim());
+    if needle.is_empty() {
+        return InDocumentSearchResult {
+            matches: Vec::new(),
+            has_more: false,
+        };
+    }
+
+    let original: Vec<char> = content.chars().collect();
+    let (haystack, origins) = fold_with_origins(content);
+
+    let mut matches = Vec::new();
+    let mut has_more = false;
+    let mut position = 0;
+
+    while position + needle.len() <= haystack.len() {
+        if haystack[position..position + needle.len()] != needle[..] {
+            position += 1;
+            continue;
+        }
+        if matches.len() == MAX_MATCHES {
+            has_more = true;
+            break;
+        }
+
+        let start = origins[position];
+        let mut end = origins[position + needle.len() - 1] + 1;
+        // Folding drops combining marks; keep those of the last matched char
+        // in the match so it never ends inside a letter
+        while original.get(end).is_some_and(|&c| is_combining_mark(c)) {
+            end += 1;
+        }
+        matches.push(InDocumentMatch {
+            char_offset: start,
+            length: end - start,
+            page_number: page_for_offset(pages, start),
+            snippet: snippet(&original, start, end),
+        });
+        position += needle.len();
+    }
+
+    InDocumentSearchResult { matches, has_more }
+}
+
+/// Page containing a char offset, given pages sorted by start offset
+pub fn page_for_offset(pages: &[DocumentPage], offset: usize) -> Option<i32> {
+    let index = pages.partition_point(|p| p.start_offset as usize <= offset);
+    index.checked_sub(1).map(|i| pages[i].page_number)
+}
+
+fn snippet(chars: &[char], start: usize, end: usize) -> String {
+    let from = start.saturating_sub(SNIPPET_CONTEXT_CHARS);
+    let to = (end + SNIPPET_CONTEXT_CHARS).min(chars.len());
+
+    let mut snippet = String::new();
+    if from > 0 {
+        snippet.push('…');
+    }
+    snippet.extend(chars[from..to].iter().map(|c| if c.is_whitespace() { ' ' } else { *c }));
+    if to < chars.len() {
+        snippet.push('…');
+    }
+    snippet
+}
+
+/// Char offset of the first match of any of `terms`, compared lowercased
+/// like the database's `strpos(LOWER(..))`, with the snippet the database
+/// cuts around it; for text the database can't search, e.g. compressed
+pub fn first_match(text: &str, terms: &[String]) -> Option<(usize, String)> {
+    let lower = |c: char| c.to_lowercase().next().unwrap_or(c);
+    let haystack: Vec<char> = text.chars().map(lower).collect();
+    let position = terms
+        .iter()
+        .filter_map(|term| {
+            let needle: Vec<char> = term.chars().map(lower).collect();
+            if needle.is_empty() {
+                return None;
+            }
+            haystack.windows(needle.len()).position(|window| window == needle.as_slice())
+        })
+        .min()?;
+    let from = position.saturating_sub(RESULT_SNIPPET_LEAD_CHARS);
+    Some((position, char_slice(text, from, RESULT_SNIPPET_CHARS)))
+}
+
+/// Slice text by char offset and length
+pub fn char_slice(text: &str, offset: usize, length: usize) -> String {
+    text.chars().skip(offset).take(length).collect()
+}
+
+/// Group library-wide hits by document, merging near-duplicates
+///
+/// Overlapping chunks make the same passage match more than once. Hits in a
+/// document whose ranges overlap or lie within `merge_gap` chars of each other
+/// become one hit spanning both, with the better score. Each document keeps
+/// its best `max_per_document` hits; documents are ordered by their best hit.
+/// Shared by every search that returns ranked char ranges.
+#[allow(dead_code)] // until chunk-based search lands
+pub fn merge_document_hits(hits: Vec<SearchHit>, merge_gap: usize, max_per_document: usize) -> Vec<DocumentSearchHits> {
+    let mut by_document: HashMap<uuid::Uuid, Vec<SearchHit>> = HashMap::new();
+    for hit in hits {
+        by_document.entry(hit.document_id).or_default().push(hit);
+    }
+
+    let mut results: Vec<DocumentSearchHits> = by_document
+        .into_iter()
+        .map(|(document_id, mut hits)| {
+            hits.sort_by_key(|hit| hit.start);
+            let mut merged: Vec<SearchHit> = Vec::with_capacity(hits.len());
+            for hit in hits {
+                match merged.last_mut() {
+                    Some(last) if hit.start <= last.end.saturating_add(merge_gap) => {
+                        last.end = last.end.max(hit.end);
+                        last.score = last.score.max(hit.score);
+                    }
+                    _ => merged.push(hit),
+                }
+            }
+
+            let total_hits_in_document = merged.len();
+            merged.sort_by(|a, b| b.score.total_cmp(&a.score));
+            merged.truncate(max_per_document);
+            DocumentSearchHits {
+                document_id,
+                hits: merged,
+                total_hits_in_document,
+            }
+        })
+        .collect();
+
+    let best = |doc: &DocumentSearchHits| doc.hits.first().map_or(f32::NEG_INFINITY, |hit| hit.score);
+    results.sort_by(|a, b| best(b).total_cmp(&best(a)));
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn offsets_and_lengths_count_chars_not_bytes() {
+        let result = search_text("😀 Naïve café", &[], "cafe");
+        assert_eq!(result.matches.len(), 1);
+        let found = &result.matches[0];
+        // The emoji is four bytes and "ï" two, but a char each
+        assert_eq!((found.char_offset, found.length), (8, 4));
+        assert_eq!(char_slice("😀 Naïve café", found.char_offset, found.length), "café");
+    }
+
+    #[test]
+    fn decomposed_accents_match_and_stay_inside_the_match() {
+        let content = "Le cafe\u{301} est ouvert";
+        let found = &search_text(content, &[], "café").matches[0];
+        assert_eq!((found.char_offset, found.length), (3, 5));
+        assert_eq!(char_slice(content, found.char_offset, found.length), "cafe\u{301}");
+
+        // And the other way round
+        let found = &search_text("Le café", &[], "cafe\u{301}").matches[0];
+        assert_eq!((found.char_offset, found.length), (3, 4));
+    }
+
+    #[test]
+    fn folding_maps_each_char_to_its_origin() {
+        let (folded, origins) = fold_with_origins("Éa\u{301}😀");
+        assert_eq!(folded, ['e', 'a', '😀']);
+        assert_eq!(origins, [0, 1, 3]);
+    }
+
+    #[test]
+    fn matches_report_the_page_they_start_on() {
+        let page = |page_number, start_offset| DocumentPage {
+            page_number,
+            content: String::new(),
+            start_offset,
+        };
+        let result = search_text("one two one", &[page(1, 0), page(2, 4)], "one");
+        let pages: Vec<Option<i32>> = result.matches.iter().map(|m| m.page_number).collect();
+        assert_eq!(pages, [Some(1), Some(2)]);
+    }
+
+    fn hit(document_id: Uuid, start: usize, end: usize, score: f32) -> SearchHit {
+        SearchHit {
+            document_id,
+            start,
+            end,
+            score,
+        }
+    }
+
+    #[test]
+    fn overlapping_hits_merge_into_one_range_with_the_best_score() {
+        let doc = Uuid::new_v4();
+        let merged = merge_document_hits(vec![hit(doc, 100, 300, 0.4), hit(doc, 250, 500, 0.9)], 0, 3);
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].total_hits_in_document, 1);
+        let only = &merged[0].hits[0];
+        assert_eq!((only.start, only.end), (100, 500));
+        assert_eq!(only.score, 0.9);
+    }
+
+    #[test]
+    fn hits_within_the_gap_merge_and_farther_ones_do_not() {
+        let doc = Uuid::new_v4();
+        let hits = vec![hit(doc, 0, 100, 0.5), hit(doc, 150, 200, 0.6), hit(doc, 400, 450, 0.7)];
+        let merged = merge_document_hits(hits, 50, 3);
+        let ranges: Vec<(usize, usize)> = merged[0].hits.iter().map(|h| (h.start, h.end)).collect();
+        // Best first
+        assert_eq!(ranges, [(400, 450), (0, 200)]);
+        assert_eq!(merged[0].total_hits_in_document, 2);
+    }
+
+    #[test]
+    fn hits_are_capped_per_document_but_all_are_counted() {
+        let doc = Uuid::new_v4();
+        let hits = (0..5).map(|i| hit(doc, i * 1000, i * 1000 + 10, i as f32)).collect();
+        let merged = merge_document_hits(hits, DEFAULT_MERGE_GAP_CHARS, DEFAULT_MAX_HITS_PER_DOCUMENT);
+        assert_eq!(merged[0].hits.len(), 3);
+        assert_eq!(merged[0].total_hits_in_document, 5);
+        let scores: Vec<f32> = merged[0].hits.iter().map(|h| h.score).collect();
+        assert_eq!(scores, [4.0, 3.0, 2.0]);
+    }
+
+    #[test]
+    fn documents_are_ordered_by_their_best_hit_and_never_merged_together() {
+        let (weak, strong) = (Uuid::new_v4(), Uuid::new_v4());
+        let hits = vec![hit(weak, 0, 10, 0.2), hit(strong, 0, 10, 0.8), hit(weak, 5, 20, 0.3)];
+        let merged = merge_document_hits(hits, 0, 3);
+        let order: Vec<Uuid> = merged.iter().map(|d| d.document_id).collect();
+        assert_eq!(order, [strong, weak]);
+        assert_eq!(merged[1].hits.len(), 1);
+    }
+}
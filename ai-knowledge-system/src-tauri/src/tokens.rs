@@ -0,0 +1,116 @@
+//! Token estimates, shared by the chunker and the UI's budgeting
+//!
+//! By default counts come from an approximation rather than a real
+//! tokenizer: runs of ASCII letters and digits count one token per four
+//! chars, other letters (accented, CJK) one token each, and every
+//! punctuation mark one token. That errs on the high side, which is the
+//! safe side for budgets and cost estimates. Built with the `tiktoken`
+//! feature, text is encoded with the BPE of the model named in the hint,
+//! or cl100k_base for models tiktoken doesn't know.
+
+const ASCII_CHARS_PER_TOKEN: usize = 4;
+
+/// Estimated tokens in `text` for the model named by `model_hint`, e.g.
+/// "text-embedding-3-small"; None for the default encoding
+#[cfg(feature = "tiktoken")]
+pub fn estimate_tokens(text: &str, model_hint: Option<&str>) -> usize {
+    match bpe::for_model(model_hint) {
+        Some(bpe) => bpe.encode_ordinary(text).len(),
+        None => approximate_tokens(text),
+    }
+}
+
+/// Estimated tokens in `text`; the model hint only matters with the
+/// `tiktoken` feature
+#[cfg(not(feature = "tiktoken"))]
+pub fn estimate_tokens(text: &str, _model_hint: Option<&str>) -> usize {
+    approximate_tokens(text)
+}
+
+fn approximate_tokens(text: &str) -> usize {
+    text.split_whitespace().map(word_tokens).sum()
+}
+
+/// Approximate tokens in one word, or in a run of CJK text without spaces
+pub fn word_tokens(word: &str) -> usize {
+    let mut tokens = 0;
+    let mut ascii_run: usize = 0;
+    for c in word.chars() {
+        if c.is_ascii_alphanumeric() {
+            ascii_run += 1;
+            continue;
+        }
+        tokens += ascii_run.div_ceil(ASCII_CHARS_PER_TOKEN) + 1;
+        ascii_run = 0;
+    }
+    tokens + ascii_run.div_ceil(ASCII_CHARS_PER_TOKEN)
+}
+
+#[cfg(feature = "tiktoken")]
+mod bpe {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex, OnceLock};
+    use tiktoken_rs::CoreBPE;
+
+    /// Encodings by model name, loaded on first use; None when even the
+    /// default encoding failed to load
+    static ENCODINGS: OnceLock<Mutex<HashMap<String, Option<Arc<CoreBPE>>>>> = OnceLock::new();
+
+    pub fn for_model(model: Option<&str>) -> Option<Arc<CoreBPE>> {
+        let model = model.map(str::trim).unwrap_or_default().to_string();
+        let mut encodings = ENCODINGS.get_or_init(Default::default).lock().expect("encodings poisoned");
+        encodings
+            .entry(model)
+            .or_insert_with_key(|model| {
+                tiktoken_rs::get_bpe_from_model(model)
+                    .or_else(|_| tiktoken_rs::cl100k_base())
+                    .ok()
+                    .map(Arc::new)
+            })
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENGLISH: &str = "The quick brown fox jumps over the lazy dog.";
+    const CJK: &str = "東京は日本の首都です。";
+
+    #[test]
+    fn approximate_counts_of_reference_samples() {
+        // 1 + 2 + 2 + 1 + 2 + 1 + 1 + 1 + (1 + 1 for the full stop)
+        assert_eq!(approximate_tokens(ENGLISH), 13);
+        // One per char, the ideographic full stop included
+        assert_eq!(approximate_tokens(CJK), 11);
+        assert_eq!(approximate_tokens("  \n\t "), 0);
+    }
+
+    #[test]
+    fn ascii_runs_count_a_token_per_four_chars() {
+        assert_eq!(word_tokens("abcd"), 1);
+        assert_eq!(word_tokens("abcde"), 2);
+        assert_eq!(word_tokens("2026-10-15"), 5);
+        assert_eq!(word_tokens("café"), 2);
+        assert_eq!(word_tokens("..."), 3);
+    }
+
+    #[cfg(not(feature = "tiktoken"))]
+    #[test]
+    fn without_tiktoken_the_hint_is_ignored() {
+        assert_eq!(estimate_tokens(ENGLISH, Some("text-embedding-3-small")), 13);
+        assert_eq!(estimate_tokens(CJK, None), 11);
+    }
+
+    /// Counts as cl100k_base encodes the samples
+    #[cfg(feature = "tiktoken")]
+    #[test]
+    fn tiktoken_counts_of_reference_samples() {
+        assert_eq!(estimate_tokens(ENGLISH, Some("text-embedding-3-small")), 10);
+        assert_eq!(estimate_tokens(CJK, Some("text-embedding-3-small")), 11);
+        // Unknown models fall back to cl100k_base
+        assert_eq!(estimate_tokens(ENGLISH, Some("no-such-model")), 10);
+        assert_eq!(estimate_tokens(ENGLISH, None), 10);
+    }
+}
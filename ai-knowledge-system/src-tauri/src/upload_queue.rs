@@ -0,0 +1,331 @@
+//! Queue of uploads waiting to be stored
+//!
+//! A fixed number of slots is handed out in (priority, enqueue order), so a
+//! document the user opens can jump ahead of a bulk import. A released slot
+//! goes straight to the next waiter under the same lock that boosting takes,
+//! so reordering never races with a slot being handed over. The number of
+//! slots can change while uploads run; slots taken away are retired as
+//! they come back.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+/// Order key of a waiting upload; lower goes first
+type QueueKey = (Priority, u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Priority {
+    /// Asked for by the user, e.g. by opening the document
+    Boosted,
+    Normal,
+}
+
+struct QueueState {
+    slots: usize,
+    free_slots: usize,
+    /// Slots in use that are to be retired instead of passed on
+    retiring: usize,
+    next_seq: u64,
+    waiting: BTreeMap<QueueKey, (Uuid, oneshot::Sender<UploadSlot>)>,
+    /// Where each waiting document sits in `waiting`
+    keys: HashMap<Uuid, QueueKey>,
+}
+
+pub struct UploadQueue {
+    state: Mutex<QueueState>,
+}
+
+/// Permission to store one upload; the slot passes on when dropped
+pub struct UploadSlot {
+    queue: Arc<UploadQueue>,
+}
+
+impl UploadQueue {
+    pub fn new(slots: usize) -> Arc<Self> {
+        Arc::new(UploadQueue {
+            state: Mutex::new(QueueState {
+                slots,
+                free_slots: slots,
+                retiring: 0,
+                next_seq: 0,
+                waiting: BTreeMap::new(),
+                keys: HashMap::new(),
+            }),
+        })
+    }
+
+    /// Wait for a slot to store `document_id`'s upload
+    pub async fn acquire(self: &Arc<Self>, document_id: Uuid) -> UploadSlot {
+        let receiver = {
+            let mut state = self.state.lock().unwrap();
+            // Free slots only exist while nobody is waiting
+            if state.free_slots > 0 {
+                state.free_slots -= 1;
+                return UploadSlot {
+                    queue: Arc::clone(self),
+                };
+            }
+            let (sender, receiver) = oneshot::channel();
+            let key = (Priority::Normal, state.next_seq);
+            state.next_seq += 1;
+            state.waiting.insert(key, (document_id, sender));
+            state.keys.insert(document_id, key);
+            receiver
+        };
+
+        match receiver.await {
+            Ok(slot) => slot,
+            // Senders are only dropped by sending, so this can't happen
+            Err(_) => UploadSlot {
+                queue: Arc::clone(self),
+            },
+        }
+    }
+
+    /// Move a waiting upload ahead of every upload that wasn't boosted
+    ///
+    /// Returns false, changing nothing, when the document isn't waiting,
+    /// e.g. because it is being stored already or is done.
+    pub fn boost(&self, document_id: Uuid) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let Some(&key) = state.keys.get(&document_id) else {
+            return false;
+        };
+        if key.0 == Priority::Boosted {
+            return true;
+        }
+        let Some(waiter) = state.waiting.remove(&key) else {
+            return false;
+        };
+        let boosted = (Priority::Boosted, key.1);
+        state.waiting.insert(boosted, waiter);
+        state.keys.insert(document_id, boosted);
+        true
+    }
+
+    /// Change the number of slots
+    ///
+    /// Added slots go to waiting uploads first. Uploads holding a slot that
+    /// is taken away finish; their slot isn't passed on.
+    pub fn resize(self: &Arc<Self>, slots: usize) {
+        let senders = {
+            let mut state = self.state.lock().unwrap();
+            let mut senders = Vec::new();
+            if slots >= state.slots {
+                let mut added = slots - state.slots;
+                // Slots not yet retired are simply kept
+                let kept = added.min(state.retiring);
+                state.retiring -= kept;
+                added -= kept;
+                for _ in 0..added {
+                    match state.waiting.pop_first() {
+                        Some((_, (document_id, sender))) => {
+                            state.keys.remove(&document_id);
+                            senders.push(sender);
+                        }
+                        None => state.free_slots += 1,
+                    }
+                }
+            } else {
+                let removed = state.slots - slots;
+                let unused = removed.min(state.free_slots);
+                state.free_slots -= unused;
+                state.retiring += removed - unused;
+            }
+            state.slots = slots;
+            senders
+        };
+        for sender in senders {
+            let _ = sender.send(UploadSlot {
+                queue: Arc::clone(self),
+            });
+        }
+    }
+
+    fn release(self: &Arc<Self>) {
+        let sender = {
+            let mut state = self.state.lock().unwrap();
+            if state.retiring > 0 {
+                state.retiring -= 1;
+                return;
+            }
+            match state.waiting.pop_first() {
+                Some((_, (document_id, sender))) => {
+                    state.keys.remove(&document_id);
+                    sender
+                }
+                None => {
+                    state.free_slots += 1;
+                    return;
+                }
+            }
+        };
+        // A waiter that gave up hands the slot back when it is dropped, which
+        // passes it on to the next one
+        let _ = sender.send(UploadSlot {
+            queue: Arc::clone(self),
+        });
+    }
+}
+
+impl Drop for UploadSlot {
+    fn drop(&mut self) {
+        self.queue.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::task::JoinHandle;
+
+    /// Waiting uploads in the order they'll get slots
+    fn queued(queue: &UploadQueue) -> Vec<Uuid> {
+        queue
+            .state
+            .lock()
+            .unwrap()
+            .waiting
+            .values()
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    fn free_slots(queue: &UploadQueue) -> usize {
+        queue.state.lock().unwrap().free_slots
+    }
+
+    /// Queue `id` from a task of its own, once it is waiting
+    async fn wait_in_line(queue: &Arc<UploadQueue>, id: Uuid) -> JoinHandle<UploadSlot> {
+        let waiter = tokio::spawn({
+            let queue = Arc::clone(queue);
+            async move { queue.acquire(id).await }
+        });
+        while !queue.state.lock().unwrap().keys.contains_key(&id) {
+            tokio::task::yield_now().await;
+        }
+        waiter
+    }
+
+    /// Let spawned waiters run, then whether `waiter` has its slot
+    async fn has_slot(waiter: &JoinHandle<UploadSlot>) -> bool {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        waiter.is_finished()
+    }
+
+    fn ids(n: usize) -> Vec<Uuid> {
+        (0..n).map(|_| Uuid::new_v4()).collect()
+    }
+
+    #[tokio::test]
+    async fn boosted_uploads_go_ahead_of_the_rest_in_order() {
+        let queue = UploadQueue::new(1);
+        let ids = ids(5);
+        let storing = queue.acquire(ids[0]).await;
+        let mut waiters = Vec::new();
+        for &id in &ids[1..] {
+            waiters.push(wait_in_line(&queue, id).await);
+        }
+        assert_eq!(queued(&queue), ids[1..]);
+
+        assert!(queue.boost(ids[4]));
+        assert!(queue.boost(ids[2]));
+        assert!(queue.boost(ids[2]));
+        assert_eq!(queued(&queue), [ids[2], ids[4], ids[1], ids[3]]);
+        // Not waiting: being stored, or unknown
+        assert!(!queue.boost(ids[0]));
+        assert!(!queue.boost(Uuid::new_v4()));
+
+        drop(storing);
+        let mut order = Vec::new();
+        let mut waiters: Vec<(Uuid, JoinHandle<UploadSlot>)> =
+            ids[1..].iter().copied().zip(waiters).collect();
+        while !waiters.is_empty() {
+            let mut granted = Vec::new();
+            for (index, (_, waiter)) in waiters.iter().enumerate() {
+                if has_slot(waiter).await {
+                    granted.push(index);
+                }
+            }
+            assert_eq!(granted.len(), 1, "one slot, one upload at a time");
+            let (id, waiter) = waiters.remove(granted[0]);
+            order.push(id);
+            drop(waiter.await.unwrap());
+        }
+        assert_eq!(order, [ids[2], ids[4], ids[1], ids[3]]);
+        assert_eq!(free_slots(&queue), 1);
+    }
+
+    #[tokio::test]
+    async fn added_slots_go_to_waiting_uploads_first() {
+        let queue = UploadQueue::new(1);
+        let ids = ids(4);
+        let _storing = queue.acquire(ids[0]).await;
+        let second = wait_in_line(&queue, ids[1]).await;
+        let third = wait_in_line(&queue, ids[2]).await;
+
+        queue.resize(4);
+        assert!(has_slot(&second).await && has_slot(&third).await);
+        assert!(queued(&queue).is_empty());
+        assert_eq!(free_slots(&queue), 1);
+        let _fourth = queue.acquire(ids[3]).await;
+        assert_eq!(free_slots(&queue), 0);
+    }
+
+    #[tokio::test]
+    async fn slots_taken_away_are_retired_as_uploads_finish() {
+        let queue = UploadQueue::new(3);
+        let ids = ids(4);
+        let first = queue.acquire(ids[0]).await;
+        let second = queue.acquire(ids[1]).await;
+        let third = queue.acquire(ids[2]).await;
+        let waiting = wait_in_line(&queue, ids[3]).await;
+
+        // All three in flight keep going; two of their slots are retired
+        queue.resize(1);
+        drop(first);
+        assert!(!has_slot(&waiting).await);
+        drop(second);
+        assert!(!has_slot(&waiting).await);
+        drop(third);
+        assert!(has_slot(&waiting).await);
+
+        drop(waiting.await.unwrap());
+        assert_eq!(free_slots(&queue), 1);
+        assert_eq!(queue.state.lock().unwrap().retiring, 0);
+    }
+
+    #[tokio::test]
+    async fn shrinking_takes_free_slots_before_busy_ones() {
+        let queue = UploadQueue::new(4);
+        let ids = ids(2);
+        let storing = queue.acquire(ids[0]).await;
+
+        queue.resize(2);
+        assert_eq!(free_slots(&queue), 1);
+        assert_eq!(queue.state.lock().unwrap().retiring, 0);
+        drop(storing);
+        assert_eq!(free_slots(&queue), 2);
+    }
+
+    #[tokio::test]
+    async fn growing_again_keeps_slots_not_yet_retired() {
+        let queue = UploadQueue::new(2);
+        let ids = ids(3);
+        let first = queue.acquire(ids[0]).await;
+        let second = queue.acquire(ids[1]).await;
+
+        queue.resize(1);
+        queue.resize(2);
+        let waiting = wait_in_line(&queue, ids[2]).await;
+        drop(first);
+        assert!(has_slot(&waiting).await);
+        drop(second);
+        drop(waiting.await.unwrap());
+        assert_eq!(free_slots(&queue), 2);
+    }
+}
@@ -0,0 +1,124 @@
+//! Ids in request DTOs, for `#[serde(deserialize_with = "...")]`
+//!
+//! Like uuid's own Deserialize they take the hyphenated, simple, braced and
+//! urn forms in either case, but refuse the nil id, and fail with an
+//! InvalidInput message that names the field instead of only saying that
+//! some UUID didn't parse.
+
+use crate::error::AppError;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+use uuid::Uuid;
+
+/// `value` as the id in `field`
+pub fn parse(field: &str, value: &str) -> Result<Uuid, AppError> {
+    let id = Uuid::parse_str(value.trim())
+        .map_err(|_| AppError::InvalidInput(format!("{} is not a valid id: {:?}", field, value)))?;
+    if id.is_nil() {
+        return Err(AppError::InvalidInput(format!("{} can't be the nil id", field)));
+    }
+    Ok(id)
+}
+
+fn required<'de, D: Deserializer<'de>>(field: &str, deserializer: D) -> Result<Uuid, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    parse(field, &value).map_err(D::Error::custom)
+}
+
+fn optional<'de, D: Deserializer<'de>>(field: &str, deserializer: D) -> Result<Option<Uuid>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(value) => parse(field, &value).map(Some).map_err(D::Error::custom),
+        None => Ok(None),
+    }
+}
+
+/// For a required document_id
+pub fn document_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Uuid, D::Error> {
+    required("document_id", deserializer)
+}
+
+/// Also needs `#[serde(default)]`, or a missing workspace is an error
+pub fn workspace_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Uuid>, D::Error> {
+    optional("workspace_id", deserializer)
+}
+
+/// Also needs `#[serde(default)]`, as workspace_id does
+pub fn parent_tag_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Uuid>, D::Error> {
+    optional("parent_tag_id", deserializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CreateTagRequest, ExtractPagesRequest, UploadFileRequest};
+    use serde_json::json;
+
+    const ID: &str = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+
+    fn upload(workspace_id: serde_json::Value) -> Result<UploadFileRequest, serde_json::Error> {
+        serde_json::from_value(json!({ "source_path": "/tmp/paper.pdf", "workspace_id": workspace_id }))
+    }
+
+    #[test]
+    fn every_form_of_an_id_in_either_case_is_accepted() {
+        let id = Uuid::parse_str(ID).unwrap();
+        for form in [
+            ID.to_string(),
+            ID.to_uppercase(),
+            id.simple().to_string(),
+            id.simple().to_string().to_uppercase(),
+            format!("{{{}}}", ID),
+            format!("urn:uuid:{}", ID),
+        ] {
+            assert_eq!(parse("document_id", &form).unwrap(), id, "{}", form);
+        }
+    }
+
+    #[test]
+    fn malformed_ids_are_invalid_input_naming_the_field() {
+        for malformed in ["", "not-an-id", &ID[1..], "67e55044-10b1-426f-9247-bb680e5fe0cz"] {
+            let error = parse("document_id", malformed).unwrap_err();
+            assert!(matches!(error, AppError::InvalidInput(_)), "{:?}", error);
+            assert!(error.to_string().contains("document_id"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn the_nil_id_is_refused() {
+        let error = parse("workspace_id", &Uuid::nil().to_string()).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid input: workspace_id can't be the nil id");
+    }
+
+    #[test]
+    fn requests_name_the_field_that_is_wrong() {
+        let error = upload(json!("not-an-id")).unwrap_err();
+        assert!(error.to_string().contains("workspace_id is not a valid id"), "{}", error);
+        let error = upload(json!(Uuid::nil())).unwrap_err();
+        assert!(error.to_string().contains("workspace_id can't be the nil id"), "{}", error);
+
+        let error = serde_json::from_value::<ExtractPagesRequest>(json!({
+            "document_id": "12345",
+            "from_page": 1,
+            "to_page": 2,
+            "new_title": "Appendix",
+        }))
+        .unwrap_err();
+        assert!(error.to_string().contains("document_id is not a valid id"), "{}", error);
+
+        let error = serde_json::from_value::<CreateTagRequest>(json!({ "name": "reading", "parent_tag_id": "x" }))
+            .unwrap_err();
+        assert!(error.to_string().contains("parent_tag_id is not a valid id"), "{}", error);
+    }
+
+    #[test]
+    fn requests_take_uppercase_and_missing_ids() {
+        let request = upload(json!(ID.to_uppercase())).unwrap();
+        assert_eq!(request.workspace_id, Some(Uuid::parse_str(ID).unwrap()));
+        assert_eq!(upload(json!(null)).unwrap().workspace_id, None);
+
+        let request: UploadFileRequest = serde_json::from_value(json!({ "source_path": "/tmp/paper.pdf" })).unwrap();
+        assert_eq!(request.workspace_id, None);
+        let request: CreateTagRequest = serde_json::from_value(json!({ "name": "reading" })).unwrap();
+        assert_eq!((request.parent_tag_id, request.workspace_id), (None, None));
+    }
+}
@@ -0,0 +1,199 @@
+//! Scanning uploads with an external virus scanner before they are stored
+//!
+//! The scanner is a command template from settings, e.g.
+//! `clamscan --no-summary {path}`. It is split into words once, quotes
+//! grouping words, and run directly rather than through a shell, so a file
+//! name can never become part of the command. `{path}` stands for the file
+//! scanned; a template without it gets the path as its last argument.
+//!
+//! An exit status of 0 passes the file and any other rejects it. A scanner
+//! that can't be started or runs past the timeout rejects the file too,
+//! unless `virus_scan_fail_open` lets it through; either way the upload's
+//! provenance records how the scan went.
+
+use crate::error::{AppError, AppResult};
+use crate::models::{ScanOutcome, ScanRecord};
+use crate::settings::AppSettings;
+use crate::storage;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Chars of the scanner's output kept in the error of a rejected upload
+const OUTPUT_TAIL_CHARS: usize = 500;
+
+/// The placeholder for the scanned file in a command template
+const PATH_PLACEHOLDER: &str = "{path}";
+
+/// A scanner as configured in settings
+pub struct Scanner {
+    program: String,
+    args: Vec<String>,
+    timeout: Duration,
+    fail_open: bool,
+}
+
+impl Scanner {
+    /// The configured scanner; None when no command is set
+    pub fn from_settings(settings: &AppSettings) -> AppResult<Option<Scanner>> {
+        let Some(template) = settings.virus_scan_command.as_deref() else {
+            return Ok(None);
+        };
+        let mut words = split_command(template)?.into_iter();
+        let Some(program) = words.next() else {
+            return Ok(None);
+        };
+        let mut args: Vec<String> = words.collect();
+        if !args.iter().any(|arg| arg.contains(PATH_PLACEHOLDER)) {
+            args.push(PATH_PLACEHOLDER.to_string());
+        }
+        Ok(Some(Scanner {
+            program,
+            args,
+            timeout: Duration::from_secs(settings.virus_scan_timeout_secs),
+            fail_open: settings.virus_scan_fail_open,
+        }))
+    }
+
+    /// Scan `path`, the file of the upload `file_name`
+    ///
+    /// A rejected file fails with `ScanFailed` and the tail of what the
+    /// scanner printed. A scanner that can't be started or times out fails
+    /// with `ScannerUnavailable`, or with `virus_scan_fail_open` passes
+    /// the file with that outcome recorded.
+    pub async fn check(&self, path: &Path, file_name: &str) -> AppResult<ScanRecord> {
+        let mut command = tokio::process::Command::new(&self.program);
+        command
+            .args(self.args.iter().map(|arg| substitute(arg, path)))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        let (outcome, problem) = match command.spawn() {
+            Err(e) => (
+                ScanOutcome::ScannerMissing,
+                format!("The virus scanner {} couldn't be started: {}", self.program, e),
+            ),
+            // Dropping the child on timeout kills it
+            Ok(child) => match tokio::time::timeout(self.timeout, child.wait_with_output()).await {
+                Err(_) => (
+                    ScanOutcome::TimedOut,
+                    format!(
+                        "The virus scanner took longer than {}s on {}",
+                        self.timeout.as_secs(),
+                        file_name
+                    ),
+                ),
+                Ok(Err(e)) => (
+                    ScanOutcome::ScannerMissing,
+                    format!("The virus scanner {} failed to run: {}", self.program, e),
+                ),
+                Ok(Ok(output)) if output.status.success() => return Ok(self.record(ScanOutcome::Clean)),
+                Ok(Ok(output)) => {
+                    let mut printed = String::from_utf8_lossy(&output.stdout).into_owned();
+                    printed.push_str(&String::from_utf8_lossy(&output.stderr));
+                    return Err(AppError::ScanFailed {
+                        file: file_name.to_string(),
+                        output: tail(printed.trim(), OUTPUT_TAIL_CHARS).to_string(),
+                    });
+                }
+            },
+        };
+
+        if !self.fail_open {
+            return Err(AppError::ScannerUnavailable(problem));
+        }
+        eprintln!("{}; storing {} unscanned", problem, file_name);
+        Ok(self.record(outcome))
+    }
+
+    fn record(&self, outcome: ScanOutcome) -> ScanRecord {
+        ScanRecord {
+            scanner: self.program.clone(),
+            outcome,
+            scanned_at: chrono::Utc::now(),
+        }
+    }
+}
+
+/// A plaintext copy of an upload in the documents directory, scanned and
+/// then stored in place of the original so the file stored is the file
+/// scanned; removed on drop
+pub struct ScanCopy {
+    path: PathBuf,
+}
+
+impl ScanCopy {
+    pub fn new(source: &Path, documents_dir: &Path, doc_id: Uuid, original_name: &str) -> AppResult<Self> {
+        let extension = source
+            .extension()
+            .map(|e| format!(".{}", e.to_string_lossy()))
+            .unwrap_or_default();
+        let copy = ScanCopy {
+            path: documents_dir.join(format!(".scan_{}{}", doc_id, extension)),
+        };
+        storage::retry_if_locked(original_name, || {
+            std::fs::copy(storage::long_path(source), storage::long_path(&copy.path))
+        })?;
+        Ok(copy)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ScanCopy {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(storage::long_path(&self.path));
+    }
+}
+
+/// An argument with the scanned file for `{path}`; an argument that is
+/// only the placeholder becomes the path as is, even if it isn't UTF-8
+fn substitute(arg: &str, path: &Path) -> OsString {
+    if arg == PATH_PLACEHOLDER {
+        path.as_os_str().to_owned()
+    } else {
+        arg.replace(PATH_PLACEHOLDER, &path.to_string_lossy()).into()
+    }
+}
+
+/// Split a command template into words at unquoted whitespace; single or
+/// double quotes group words and are removed
+fn split_command(template: &str) -> AppResult<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote: Option<char> = None;
+    for c in template.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => word.get_or_insert_with(String::new).push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            None if c.is_whitespace() => words.extend(word.take()),
+            None => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err(AppError::InvalidInput(
+            "The virus scan command has an unclosed quote".to_string(),
+        ));
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// The last `max_chars` chars of `text`
+fn tail(text: &str, max_chars: usize) -> &str {
+    let skip = text.chars().count().saturating_sub(max_chars);
+    match text.char_indices().nth(skip) {
+        Some((start, _)) => &text[start..],
+        None => text,
+    }
+}
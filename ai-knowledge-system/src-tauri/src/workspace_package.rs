@@ -0,0 +1,437 @@
+//! Workspace packages: a zip holding one workspace's files and metadata
+//!
+//! Backups use the same format for the whole library.
+//!
+//! Layout:
+//! - `manifest.json` — format version, workspace name, and one entry per document
+//! - `files/<document id>/<file name>` — the stored file, when it still existed
+//!
+//! Importing recreates the workspace for another user with new ids. Each
+//! document is inserted in its own transaction, so a failure part-way leaves
+//! the documents imported so far in place and is reported per document.
+
+use crate::collation;
+use crate::error::{AppError, AppResult};
+use crate::file_store::FileStore;
+use crate::file_utils;
+use crate::models::{
+    Document, DocumentStatus, ImportMethod, ImportedDocument, PackageExportSummary, PackageImportFailure,
+    PdfLayout, ProvenanceInfo, Workspace, WorkspaceImportReport,
+};
+use crate::operations::OperationHandle;
+use crate::processing::{self, ProcessingContext};
+use crate::services::workspace::NewWorkspaceDocument;
+use crate::services::{DocumentService, WorkspaceService};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+use zip::write::FileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+const FORMAT_VERSION: u32 = 1;
+const MANIFEST_NAME: &str = "manifest.json";
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    format_version: u32,
+    workspace_name: String,
+    exported_at: chrono::DateTime<chrono::Utc>,
+    documents: Vec<PackageDocument>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct PackageDocument {
+    id: Uuid,
+    /// Original owner and workspace; informational, not used on import
+    #[serde(default)]
+    user_id: Option<Uuid>,
+    #[serde(default)]
+    workspace_id: Option<Uuid>,
+    title: String,
+    content: Option<String>,
+    summary: Option<String>,
+    file_name: Option<String>,
+    file_size_bytes: Option<i64>,
+    file_type: Option<String>,
+    mime_type: Option<String>,
+    status: DocumentStatus,
+    page_count: Option<i32>,
+    #[serde(default)]
+    pages: Vec<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Archive entry holding the file, absent if it was missing at export
+    entry: Option<String>,
+    sha256: Option<String>,
+}
+
+/// Write a workspace's documents, files and tags to a package at `dest`
+///
+/// Files are read through `store`, so packages always hold plaintext.
+pub async fn export_workspace(
+    workspaces: &WorkspaceService,
+    documents: &DocumentService,
+    store: Arc<dyn FileStore>,
+    workspace_id: Uuid,
+    dest: &Path,
+    cancel: CancellationToken,
+) -> AppResult<PackageExportSummary> {
+    let workspace = workspaces
+        .get_workspace(workspace_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Workspace".to_string()))?;
+
+    let docs = workspaces.get_documents(workspace_id).await?;
+    export_documents(workspaces, documents, store, workspace.name, docs, dest, cancel).await
+}
+
+/// Package an arbitrary set of documents; shared by workspace export and backups
+///
+/// Cancelling stops between documents with `AppError::Cancelled` and removes
+/// the partial package.
+pub async fn export_documents(
+    workspaces: &WorkspaceService,
+    documents: &DocumentService,
+    store: Arc<dyn FileStore>,
+    name: String,
+    docs: Vec<Document>,
+    dest: &Path,
+    cancel: CancellationToken,
+) -> AppResult<PackageExportSummary> {
+    let mut entries = Vec::with_capacity(docs.len());
+    for doc in docs {
+        if cancel.is_cancelled() {
+            return Err(AppError::Cancelled);
+        }
+        let tags = workspaces.get_tag_names(doc.id).await?;
+        let pages = documents
+            .get_pages(doc.id)
+            .await?
+            .into_iter()
+            .map(|p| p.content)
+            .collect();
+
+        let entry = PackageDocument {
+            id: doc.id,
+            user_id: Some(doc.user_id),
+            workspace_id: doc.workspace_id,
+            title: doc.title,
+            content: doc.content,
+            summary: doc.summary,
+            file_name: doc.file_name,
+            file_size_bytes: doc.file_size_bytes,
+            file_type: doc.file_type,
+            mime_type: doc.mime_type,
+            status: doc.status,
+            page_count: doc.page_count,
+            pages,
+            tags,
+            entry: None,
+            sha256: None,
+        };
+        entries.push((entry, doc.file_path.map(PathBuf::from)));
+    }
+
+    let dest = dest.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let result = write_package(&*store, &dest, name, entries, &cancel);
+        if result.is_err() {
+            // Don't leave a truncated package behind
+            let _ = std::fs::remove_file(&dest);
+        }
+        result
+    })
+    .await?
+}
+
+fn write_package(
+    store: &dyn FileStore,
+    dest: &Path,
+    workspace_name: String,
+    entries: Vec<(PackageDocument, Option<PathBuf>)>,
+    cancel: &CancellationToken,
+) -> AppResult<PackageExportSummary> {
+    let mut zip = ZipWriter::new(BufWriter::new(File::create(dest)?));
+    let options = FileOptions::default().large_file(true);
+
+    let mut documents = Vec::with_capacity(entries.len());
+    let mut missing_files = Vec::new();
+    for (mut doc, file_path) in entries {
+        if cancel.is_cancelled() {
+            return Err(AppError::Cancelled);
+        }
+        match file_path.filter(|p| p.is_file()) {
+            Some(path) => {
+                let file_name = safe_file_name(doc.file_name.as_deref(), &path);
+                let entry = format!("files/{}/{}", doc.id, file_name);
+                zip.start_file(entry.as_str(), options)?;
+                std::io::copy(&mut store.open(&path)?, &mut zip)?;
+                doc.sha256 = Some(file_utils::sha256_reader(&mut store.open(&path)?)?);
+                doc.entry = Some(entry);
+            }
+            None => missing_files.push(doc.id),
+        }
+        documents.push(doc);
+    }
+
+    let document_count = documents.len();
+    let manifest = Manifest {
+        format_version: FORMAT_VERSION,
+        workspace_name,
+        exported_at: chrono::Utc::now(),
+        documents,
+    };
+    zip.start_file(MANIFEST_NAME, options)?;
+    serde_json::to_writer_pretty(&mut zip, &manifest).map_err(|e| AppError::Other(e.to_string()))?;
+    // Sync so a backup renamed into place after this is complete on disk
+    zip.finish()?.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+    Ok(PackageExportSummary {
+        document_count,
+        missing_files,
+    })
+}
+
+/// Recreate a packaged workspace as a new workspace owned by `user_id`
+///
+/// Files already in the user's library (same size and SHA-256) are reused
+/// rather than copied again. Documents that weren't fully processed at
+/// export are queued for processing. New files are encrypted when
+/// encryption is on. Cancelling stops between documents and keeps the ones
+/// already imported.
+pub async fn import_workspace(
+    workspaces: &WorkspaceService,
+    ctx: &ProcessingContext,
+    package: &Path,
+    user_id: Uuid,
+    documents_dir: &Path,
+    operation: &OperationHandle,
+) -> AppResult<WorkspaceImportReport> {
+    let package = package.to_path_buf();
+    let manifest = {
+        let package = package.clone();
+        tokio::task::spawn_blocking(move || read_manifest(&package)).await??
+    };
+    if manifest.format_version != FORMAT_VERSION {
+        return Err(AppError::InvalidInput(format!(
+            "Unsupported package version {}",
+            manifest.format_version
+        )));
+    }
+
+    // Fail before creating anything if the library is locked
+    ctx.file_store().await?;
+
+    std::fs::create_dir_all(documents_dir)?;
+    let workspace = workspaces.create_workspace(user_id, &manifest.workspace_name).await?;
+
+    let mut imported = Vec::new();
+    let mut failed = Vec::new();
+    let mut cancelled_after = None;
+    let total = manifest.documents.len();
+    for (index, doc) in manifest.documents.into_iter().enumerate() {
+        if operation.is_cancelled() {
+            cancelled_after = Some(index);
+            break;
+        }
+        operation.set_progress(index, total);
+        match import_document(workspaces, ctx, &package, &doc, workspace.id, user_id, documents_dir).await {
+            Ok(result) => imported.push(result),
+            Err(e) => failed.push(PackageImportFailure {
+                source_id: doc.id,
+                title: doc.title.clone(),
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    let storage_used_bytes = workspaces.recompute_storage_usage(workspace.id).await?;
+
+    Ok(WorkspaceImportReport {
+        workspace: Workspace {
+            storage_used_bytes,
+            ..workspace
+        },
+        imported,
+        failed,
+        cancelled_after,
+    })
+}
+
+async fn import_document(
+    workspaces: &WorkspaceService,
+    ctx: &ProcessingContext,
+    package: &Path,
+    doc: &PackageDocument,
+    workspace_id: Uuid,
+    user_id: Uuid,
+    documents_dir: &Path,
+) -> AppResult<ImportedDocument> {
+    let (Some(entry), Some(sha256)) = (&doc.entry, &doc.sha256) else {
+        return Err(AppError::NotFound("Packaged file".to_string()));
+    };
+
+    let store = ctx.file_store().await?;
+    let existing = match doc.file_size_bytes {
+        Some(size) => find_duplicate(workspaces, &store, user_id, size, sha256).await?,
+        None => None,
+    };
+    let deduplicated = existing.is_some();
+    let file_path = match existing {
+        Some(path) => path,
+        None => {
+            let file_name = safe_file_name(doc.file_name.as_deref(), Path::new(entry));
+            let hash_prefix = &sha256[..8.min(sha256.len())];
+            let mut dest = documents_dir.join(format!("{}_{}", hash_prefix, file_name));
+            if dest.exists() {
+                // Same name from someone else's upload; never overwrite it
+                let unique = Uuid::new_v4().simple().to_string();
+                dest = documents_dir.join(format!("{}_{}_{}", hash_prefix, &unique[..8], file_name));
+            }
+            let package = package.to_path_buf();
+            let entry = entry.clone();
+            let expected = sha256.clone();
+            let dest_for_task = dest.clone();
+            let store = Arc::clone(&store);
+            tokio::task::spawn_blocking(move || extract_entry(&*store, &package, &entry, &dest_for_task, &expected))
+                .await??;
+            dest
+        }
+    };
+    let file_path_str = file_path.to_string_lossy().to_string();
+
+    // Only fully processed documents carry over as-is; the rest are reprocessed
+    let completed = doc.status == DocumentStatus::Completed && doc.content.is_some();
+    let status = if completed {
+        DocumentStatus::Completed
+    } else {
+        DocumentStatus::Uploading
+    };
+    let no_pages: &[String] = &[];
+    let settings = ctx.settings.get().await;
+    let title_sort = collation::title_sort_key(&doc.title, &settings.locale);
+    let provenance = ProvenanceInfo::now(
+        ImportMethod::WorkspacePackage,
+        Some(package),
+        None,
+        settings.record_import_paths,
+    );
+
+    let inserted = workspaces
+        .insert_document(NewWorkspaceDocument {
+            workspace_id,
+            user_id,
+            title: &doc.title,
+            content: doc.content.as_deref().filter(|_| completed),
+            summary: doc.summary.as_deref().filter(|_| completed),
+            file_path: &file_path_str,
+            file_name: doc.file_name.as_deref(),
+            file_size_bytes: doc.file_size_bytes,
+            file_type: doc.file_type.as_deref(),
+            mime_type: doc.mime_type.as_deref(),
+            file_hash: Some(sha256.as_str()),
+            status,
+            page_count: doc.page_count.filter(|_| completed),
+            pages: if completed { &doc.pages } else { no_pages },
+            tags: &doc.tags,
+            title_sort: &title_sort,
+            provenance: &provenance,
+        })
+        .await;
+
+    let document_id = match inserted {
+        Ok(id) => id,
+        Err(e) => {
+            if !deduplicated {
+                let _ = std::fs::remove_file(&file_path);
+            }
+            return Err(e.into());
+        }
+    };
+
+    if !completed {
+        processing::spawn_processing(
+            ctx.clone(),
+            document_id,
+            file_path,
+            doc.mime_type.clone().unwrap_or_default(),
+            DocumentStatus::Uploading,
+            PdfLayout::Auto,
+        );
+    }
+
+    Ok(ImportedDocument {
+        source_id: doc.id,
+        document_id,
+        deduplicated,
+    })
+}
+
+/// A stored file of this user's with the same size and hash, if any
+async fn find_duplicate(
+    workspaces: &WorkspaceService,
+    store: &Arc<dyn FileStore>,
+    user_id: Uuid,
+    size_bytes: i64,
+    sha256: &str,
+) -> AppResult<Option<PathBuf>> {
+    let candidates = workspaces.find_files_by_size(user_id, size_bytes).await?;
+    let sha256 = sha256.to_string();
+    let store = Arc::clone(store);
+    let found = tokio::task::spawn_blocking(move || {
+        candidates.into_iter().map(PathBuf::from).find(|path| {
+            store
+                .open(path)
+                .and_then(|mut file| file_utils::sha256_reader(&mut file))
+                .map(|h| h == sha256)
+                .unwrap_or(false)
+        })
+    })
+    .await?;
+
+    Ok(found)
+}
+
+fn read_manifest(package: &Path) -> AppResult<Manifest> {
+    let mut archive = ZipArchive::new(BufReader::new(File::open(package)?))?;
+    let mut raw = String::new();
+    archive.by_name(MANIFEST_NAME)?.read_to_string(&mut raw)?;
+    serde_json::from_str(&raw).map_err(|e| AppError::InvalidInput(format!("Invalid package manifest: {}", e)))
+}
+
+/// Copy one archive entry to `dest`, checking it against the manifest hash
+fn extract_entry(
+    store: &dyn FileStore,
+    package: &Path,
+    entry: &str,
+    dest: &Path,
+    expected_sha256: &str,
+) -> AppResult<()> {
+    let mut archive = ZipArchive::new(BufReader::new(File::open(package)?))?;
+    let mut source = archive.by_name(entry)?;
+    if let Err(e) = store.write(&mut source, dest) {
+        let _ = std::fs::remove_file(dest);
+        return Err(e.into());
+    }
+
+    if file_utils::sha256_reader(&mut store.open(dest)?)? != expected_sha256 {
+        let _ = std::fs::remove_file(dest);
+        return Err(AppError::Other(format!("Checksum mismatch for {}", entry)));
+    }
+    Ok(())
+}
+
+/// Final path component of the recorded name, sanitized, so manifests can't
+/// point outside the storage directory or name a file it can't hold
+fn safe_file_name(recorded: Option<&str>, fallback: &Path) -> String {
+    let name = recorded
+        .and_then(|name| Path::new(name).file_name())
+        .or_else(|| fallback.file_name())
+        .and_then(|name| name.to_str())
+        .unwrap_or("file");
+    file_utils::sanitize_filename(name)
+}
@@ -0,0 +1,197 @@
+//! aks-cli run as a process against a throwaway library
+//!
+//! Like the library's own database tests these need AKS_TEST_DATABASE_URL,
+//! e.g. postgresql://postgres@localhost/postgres, and pass without doing
+//! anything when it isn't set. Each test gets a database of its own and a
+//! temporary directory for settings, app data and source files; both are
+//! removed when it ends, also when it panics.
+
+use ai_knowledge_system_lib::db::{self, Database};
+use ai_knowledge_system_lib::settings::AppSettings;
+use sqlx::{Connection, Executor, PgConnection, PgPool};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use uuid::Uuid;
+
+/// The app's Tauri identifier, which names its data directory
+const IDENTIFIER: &str = "com.sunilkumar.ai-knowledge-system";
+
+struct CliLibrary {
+    server_url: String,
+    database: String,
+    database_url: String,
+    pool: PgPool,
+    dir: tempfile::TempDir,
+}
+
+impl CliLibrary {
+    /// A library with one user, who the settings file makes the active one
+    async fn new() -> Option<CliLibrary> {
+        let server_url = std::env::var("AKS_TEST_DATABASE_URL").ok()?;
+        let database = format!("aks_cli_test_{}", Uuid::new_v4().simple());
+        let database_url = with_database(&server_url, &database);
+        let pool = Database::open(&database_url)
+            .await
+            .unwrap_or_else(|e| panic!("Could not create test database {}: {:?}", database, e))
+            .pool()
+            .clone();
+        let dir = tempfile::tempdir().unwrap();
+        let library = CliLibrary {
+            server_url,
+            database,
+            database_url,
+            pool,
+            dir,
+        };
+
+        let user_id: Uuid = sqlx::query_scalar(
+            "INSERT INTO users (email, password_hash, full_name) VALUES ('ada@example.com', '', 'Ada') RETURNING id",
+        )
+        .fetch_one(&library.pool)
+        .await
+        .unwrap();
+        let settings = AppSettings {
+            active_user_id: Some(user_id),
+            ..AppSettings::default()
+        };
+        std::fs::write(library.settings_path(), serde_json::to_vec(&settings).unwrap()).unwrap();
+        Some(library)
+    }
+
+    fn settings_path(&self) -> PathBuf {
+        self.dir.path().join("settings.json")
+    }
+
+    /// Write a file to import, outside the library
+    fn source_file(&self, name: &str, contents: &[u8]) -> PathBuf {
+        let path = self.dir.path().join("sources").join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// Files stored in the library
+    fn stored_files(&self) -> Vec<PathBuf> {
+        let documents_dir = self.dir.path().join("data").join(IDENTIFIER).join("documents");
+        match std::fs::read_dir(documents_dir) {
+            Ok(entries) => entries.map(|entry| entry.unwrap().path()).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Run aks-cli with `args` and --json, as the library's user
+    fn run(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_aks-cli"))
+            .arg("--json")
+            .arg("--settings")
+            .arg(self.settings_path())
+            .args(args)
+            .env("DATABASE_URL", &self.database_url)
+            .env("HOME", self.dir.path())
+            .env("XDG_DATA_HOME", self.dir.path().join("data"))
+            .current_dir(self.dir.path())
+            .output()
+            .unwrap()
+    }
+}
+
+impl Drop for CliLibrary {
+    fn drop(&mut self) {
+        // Drop can't await, and may run while the test's runtime unwinds, so
+        // the database is dropped from a thread with a runtime of its own
+        let (server_url, database) = (self.server_url.clone(), self.database.clone());
+        let dropped = std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+            runtime.block_on(async {
+                let mut conn = PgConnection::connect(&with_database(&server_url, "postgres")).await?;
+                conn.execute(format!("DROP DATABASE IF EXISTS \"{}\" WITH (FORCE)", database).as_str())
+                    .await
+                    .map(|_| ())
+            })
+            .map_err(std::io::Error::other)
+        })
+        .join();
+        if !matches!(dropped, Ok(Ok(()))) {
+            eprintln!("Could not drop test database {}", self.database);
+        }
+    }
+}
+
+/// `url` with its database replaced by `database`
+fn with_database(url: &str, database: &str) -> String {
+    let (base, query) = url.split_once('?').map_or((url, None), |(base, query)| (base, Some(query)));
+    let (server, _) = base.rsplit_once('/').expect("AKS_TEST_DATABASE_URL has no database");
+    match query {
+        Some(query) => format!("{}/{}?{}", server, database, query),
+        None => format!("{}/{}", server, database),
+    }
+}
+
+fn json(output: &Output) -> serde_json::Value {
+    assert!(
+        output.status.success(),
+        "{:?}: {}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+fn path_arg(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+#[tokio::test]
+async fn import_queues_files_and_skips_duplicates() {
+    let Some(library) = CliLibrary::new().await else { return };
+    let notes = library.source_file("notes.txt", b"Notes from the command line.");
+    let copy = library.source_file("copy/notes.txt", b"Notes from the command line.");
+
+    let imported = json(&library.run(&["import", path_arg(&notes)]));
+    let document_id = imported[0]["document_id"].as_str().unwrap().to_string();
+    let status: String = sqlx::query_scalar("SELECT status::text FROM documents WHERE id = $1::uuid")
+        .bind(&document_id)
+        .fetch_one(&library.pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "queued");
+    assert_eq!(library.stored_files().len(), 1);
+
+    let again = json(&library.run(&["import", path_arg(&copy)]));
+    assert_eq!(again[0]["duplicate_of"].as_str(), Some(document_id.as_str()));
+    assert_eq!(library.stored_files().len(), 1);
+
+    let listed = json(&library.run(&["list", "--status", "queued"]));
+    let listed: Vec<&str> = listed.as_array().unwrap().iter().map(|d| d["id"].as_str().unwrap()).collect();
+    assert_eq!(listed, [document_id.as_str()]);
+}
+
+#[tokio::test]
+async fn changes_are_refused_while_the_app_is_open() {
+    let Some(library) = CliLibrary::new().await else { return };
+    let notes = library.source_file("notes.txt", b"Imported while the app runs.");
+
+    let app = db::try_app_lock(&library.pool).await.unwrap().expect("lock is free");
+    let output = library.run(&["import", path_arg(&notes)]);
+    assert_eq!(output.status.code(), Some(4), "{}", String::from_utf8_lossy(&output.stderr));
+    drop(app);
+
+    let documents: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM documents")
+        .fetch_one(&library.pool)
+        .await
+        .unwrap();
+    assert_eq!(documents, 0);
+    assert!(library.stored_files().is_empty());
+}
+
+#[tokio::test]
+async fn errors_have_their_exit_codes() {
+    let Some(library) = CliLibrary::new().await else { return };
+
+    let missing = library.run(&["export", &Uuid::new_v4().to_string()]);
+    assert_eq!(missing.status.code(), Some(3), "{}", String::from_utf8_lossy(&missing.stderr));
+    let bad_query = library.run(&["search", "\"unbalanced"]);
+    assert_eq!(bad_query.status.code(), Some(2), "{}", String::from_utf8_lossy(&bad_query.stderr));
+    let usage = library.run(&["frobnicate"]);
+    assert_eq!(usage.status.code(), Some(2));
+}
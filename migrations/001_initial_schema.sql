@@ -7,8 +7,8 @@
 -- ========================================
 -- UUID generation
 CREATE EXTENSION IF NOT EXISTS "uuid-ossp";
--- Vector similarity search (using Qdrant instead)
--- CREATE EXTENSION IF NOT EXISTS vector;
+-- Vector similarity search (using Qdrant instead); the embedding columns
+-- are only added where pgvector is installed, see the end of this file
 -- Full-text search (built-in, just verify)
 -- SELECT * FROM pg_extension WHERE extname = 'pg_trgm';
 CREATE EXTENSION IF NOT EXISTS pg_trgm;
@@ -124,8 +124,6 @@ CREATE TABLE documents (
     reading_time_minutes INTEGER,
     complexity_score DECIMAL(3, 2),
     -- Flesch-Kincaid or similar
-    -- Vector embedding (384 dimensions for all-MiniLM-L6-v2)
-    embedding vector(384),
     -- Full-text search
     content_tsvector tsvector GENERATED ALWAYS AS (
         setweight(to_tsvector('english', coalesce(title, '')), 'A') || setweight(
@@ -151,8 +149,6 @@ CREATE INDEX idx_documents_created_at ON documents(created_at DESC);
 CREATE INDEX idx_documents_file_type ON documents(file_type);
 -- Full-text search index
 CREATE INDEX idx_documents_content_search ON documents USING GIN (content_tsvector);
--- Vector similarity search index (HNSW for fast approximate nearest neighbor)
-CREATE INDEX idx_documents_embedding ON documents USING hnsw (embedding vector_cosine_ops) WITH (m = 16, ef_construction = 64);
 -- Tags
 CREATE TABLE tags (
     id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
@@ -186,8 +182,6 @@ CREATE TABLE notes (
     workspace_id UUID REFERENCES workspaces(id) ON DELETE CASCADE,
     title VARCHAR(500) NOT NULL,
     content TEXT NOT NULL,
-    -- Vector embedding
-    embedding vector(384),
     -- Full-text search
     content_tsvector tsvector GENERATED ALWAYS AS (
         setweight(to_tsvector('english', coalesce(title, '')), 'A') || setweight(
@@ -206,7 +200,6 @@ CREATE INDEX idx_notes_workspace ON notes(workspace_id)
 WHERE deleted_at IS NULL;
 CREATE INDEX idx_notes_created_at ON notes(created_at DESC);
 CREATE INDEX idx_notes_content_search ON notes USING GIN (content_tsvector);
-CREATE INDEX idx_notes_embedding ON notes USING hnsw (embedding vector_cosine_ops) WITH (m = 16, ef_construction = 64);
 -- AI Transformations (multi-level understanding)
 CREATE TABLE transformations (
     id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
@@ -356,9 +349,23 @@ GROUP BY u.id;
 -- COMMENTS
 -- ========================================
 COMMENT ON TABLE users IS 'User accounts with subscription and storage information';
-COMMENT ON TABLE documents IS 'Uploaded documents with embeddings and full-text search';
+COMMENT ON TABLE documents IS 'Uploaded documents with full-text search';
 COMMENT ON TABLE tags IS 'User-defined tags for organizing documents';
 COMMENT ON TABLE transformations IS 'AI-generated content at different complexity levels';
-COMMENT ON COLUMN documents.embedding IS 'Vector embedding (384-dim) for semantic search';
 COMMENT ON COLUMN documents.content_tsvector IS 'Full-text search vector (auto-generated)';
-COMMENT ON INDEX idx_documents_embedding IS 'HNSW index for fast approximate nearest neighbor search';
\ No newline at end of file
+-- ========================================
+-- VECTOR EMBEDDINGS (pgvector, optional)
+-- ========================================
+-- A stock PostgreSQL has no pgvector, and nothing reads these columns, so
+-- they are only added where the extension can be installed
+DO $$ BEGIN IF EXISTS (SELECT 1 FROM pg_available_extensions WHERE name = 'vector') THEN
+    CREATE EXTENSION IF NOT EXISTS vector;
+    -- 384 dimensions for all-MiniLM-L6-v2
+    EXECUTE 'ALTER TABLE documents ADD COLUMN IF NOT EXISTS embedding vector(384)';
+    EXECUTE 'ALTER TABLE notes ADD COLUMN IF NOT EXISTS embedding vector(384)';
+    -- HNSW for fast approximate nearest neighbor search
+    EXECUTE 'CREATE INDEX IF NOT EXISTS idx_documents_embedding ON documents USING hnsw (embedding vector_cosine_ops) WITH (m = 16, ef_construction = 64)';
+    EXECUTE 'CREATE INDEX IF NOT EXISTS idx_notes_embedding ON notes USING hnsw (embedding vector_cosine_ops) WITH (m = 16, ef_construction = 64)';
+    EXECUTE 'COMMENT ON COLUMN documents.embedding IS ''Vector embedding (384-dim) for semantic search''';
+END IF;
+END $$;
@@ -65,6 +65,10 @@ WHERE timestamp > NOW() - INTERVAL '7 days'
 ORDER BY timestamp DESC;
 
 -- Grant appropriate permissions
--- Note: Adjust these based on your security requirements
-GRANT SELECT ON audit_logs TO readonly_user;
-GRANT SELECT ON recent_security_events TO readonly_user;
+-- Note: Adjust these based on your security requirements. The role is
+-- only there on servers set up with one, so the grants are skipped without it
+DO $$ BEGIN IF EXISTS (SELECT 1 FROM pg_roles WHERE rolname = 'readonly_user') THEN
+    GRANT SELECT ON audit_logs TO readonly_user;
+    GRANT SELECT ON recent_security_events TO readonly_user;
+END IF;
+END $$;
@@ -0,0 +1,10 @@
+-- Migration: Add page count and parent document link to documents
+-- Date: 2026-10-15
+-- Purpose: Support page-range extraction into derived documents
+
+ALTER TABLE documents
+    ADD COLUMN IF NOT EXISTS page_count INTEGER,
+    ADD COLUMN IF NOT EXISTS parent_document_id UUID REFERENCES documents(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_documents_parent ON documents(parent_document_id)
+WHERE parent_document_id IS NOT NULL;
@@ -0,0 +1,26 @@
+-- Migration: Track moves of the document storage root
+-- Date: 2026-10-15
+-- Purpose: Resumable per-document state for migrate_storage
+
+CREATE TABLE IF NOT EXISTS storage_migrations (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    source_root VARCHAR(1000) NOT NULL,
+    target_root VARCHAR(1000) NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW() NOT NULL,
+    completed_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_storage_migrations_active ON storage_migrations(target_root)
+WHERE completed_at IS NULL;
+
+-- state: pending -> copied -> updated -> removed (or failed)
+CREATE TABLE IF NOT EXISTS storage_migration_items (
+    migration_id UUID NOT NULL REFERENCES storage_migrations(id) ON DELETE CASCADE,
+    document_id UUID NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    source_path VARCHAR(1000) NOT NULL,
+    dest_path VARCHAR(1000) NOT NULL,
+    state VARCHAR(20) DEFAULT 'pending' NOT NULL,
+    error TEXT,
+    updated_at TIMESTAMPTZ DEFAULT NOW() NOT NULL,
+    PRIMARY KEY (migration_id, document_id)
+);
@@ -0,0 +1,13 @@
+-- Migration: Create document_identifiers table
+-- Date: 2026-10-15
+-- Purpose: DOIs, arXiv ids and ISBNs detected in extracted content
+
+CREATE TABLE IF NOT EXISTS document_identifiers (
+    document_id UUID NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    kind VARCHAR(20) NOT NULL CHECK (kind IN ('doi', 'arxiv', 'isbn')),
+    value VARCHAR(255) NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW() NOT NULL,
+    PRIMARY KEY (document_id, kind, value)
+);
+
+CREATE INDEX IF NOT EXISTS idx_document_identifiers_lookup ON document_identifiers(kind, value);
@@ -0,0 +1,6 @@
+-- Migration: Add suggested_tags to documents
+-- Date: 2026-10-15
+-- Purpose: Keyword-based tag suggestions awaiting user confirmation
+
+ALTER TABLE documents
+    ADD COLUMN IF NOT EXISTS suggested_tags JSONB DEFAULT '[]'::jsonb NOT NULL;
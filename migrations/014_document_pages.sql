@@ -0,0 +1,12 @@
+-- Migration: Create document_pages table
+-- Date: 2026-10-15
+-- Purpose: Per-page extracted text with offsets into documents.content
+
+CREATE TABLE IF NOT EXISTS document_pages (
+    document_id UUID NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    page_number INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    -- Character (not byte) offset of the page start within documents.content
+    start_offset INTEGER NOT NULL,
+    PRIMARY KEY (document_id, page_number)
+);
@@ -0,0 +1,5 @@
+-- Migration: Add missing_file document status
+-- Date: 2026-10-15
+-- Purpose: Flag documents whose stored file disappeared (consistency repair)
+
+ALTER TYPE document_status ADD VALUE IF NOT EXISTS 'missing_file';
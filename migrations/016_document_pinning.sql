@@ -0,0 +1,11 @@
+-- Migration: Add document pinning
+-- Date: 2026-10-15
+-- Purpose: Let users pin documents to the top of their list in a manual order
+
+ALTER TABLE documents
+    ADD COLUMN IF NOT EXISTS is_pinned BOOLEAN NOT NULL DEFAULT FALSE,
+    -- Dense 0-based position among the user's pinned documents; NULL when unpinned
+    ADD COLUMN IF NOT EXISTS pinned_order INTEGER;
+
+CREATE INDEX IF NOT EXISTS idx_documents_pinned ON documents(user_id, pinned_order)
+    WHERE is_pinned;
@@ -0,0 +1,11 @@
+-- Migration: Add sidecar notes to documents
+-- Date: 2026-10-15
+-- Purpose: Markdown notes imported from a {name}.md / {name}.notes.md file next to the original
+
+ALTER TABLE documents
+    ADD COLUMN IF NOT EXISTS notes TEXT,
+    -- Sidecar the notes were read from, used to re-sync when it changes
+    ADD COLUMN IF NOT EXISTS notes_source_path TEXT,
+    -- SHA-256 of the sidecar contents, so unchanged files aren't re-imported
+    ADD COLUMN IF NOT EXISTS notes_hash VARCHAR(64),
+    ADD COLUMN IF NOT EXISTS notes_updated_at TIMESTAMPTZ;
@@ -0,0 +1,18 @@
+-- Migration: Create processing_runs table
+-- Date: 2026-10-15
+-- Purpose: History of every processing attempt per document
+
+CREATE TABLE IF NOT EXISTS processing_runs (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    document_id UUID NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    started_at TIMESTAMPTZ DEFAULT NOW() NOT NULL,
+    finished_at TIMESTAMPTZ,
+    -- running, completed, failed, cancelled, timed_out
+    outcome VARCHAR(20) DEFAULT 'running' NOT NULL,
+    error TEXT,
+    extractor_name VARCHAR(100),
+    extractor_version VARCHAR(50),
+    pages_processed INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_processing_runs_document ON processing_runs(document_id, started_at DESC);
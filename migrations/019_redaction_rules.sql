@@ -0,0 +1,15 @@
+-- Migration: Create redaction_rules table
+-- Date: 2026-10-15
+-- Purpose: Per-user literal and regex patterns blanked out on redacted exports
+
+CREATE TABLE IF NOT EXISTS redaction_rules (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- 'literal' (matched case-insensitively) or 'regex'
+    kind VARCHAR(10) NOT NULL CHECK (kind IN ('literal', 'regex')),
+    pattern TEXT NOT NULL,
+    label VARCHAR(100),
+    created_at TIMESTAMPTZ DEFAULT NOW() NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_redaction_rules_user ON redaction_rules(user_id);
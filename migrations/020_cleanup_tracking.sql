@@ -0,0 +1,12 @@
+-- Migration: Track file hashes and opens on documents
+-- Date: 2026-10-15
+-- Purpose: Find duplicate and never-opened documents for cleanup suggestions
+
+ALTER TABLE documents
+    ADD COLUMN IF NOT EXISTS file_hash VARCHAR(64),
+    ADD COLUMN IF NOT EXISTS open_count INTEGER DEFAULT 0 NOT NULL,
+    ADD COLUMN IF NOT EXISTS last_opened_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_documents_user_file_hash
+    ON documents(user_id, file_hash)
+    WHERE file_hash IS NOT NULL AND deleted_at IS NULL;
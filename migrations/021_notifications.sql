@@ -0,0 +1,22 @@
+-- Migration: Create notifications table
+-- Date: 2026-10-15
+-- Purpose: Keep outcomes of background work so users see them even if the UI wasn't open
+
+CREATE TABLE IF NOT EXISTS notifications (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- e.g. 'processing_failed', 'storage_warning', 'backup_failed'
+    kind VARCHAR(50) NOT NULL,
+    title TEXT NOT NULL,
+    body TEXT,
+    document_id UUID REFERENCES documents(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW() NOT NULL,
+    read_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_notifications_user_created
+    ON notifications(user_id, created_at DESC);
+
+CREATE INDEX IF NOT EXISTS idx_notifications_user_unread
+    ON notifications(user_id)
+    WHERE read_at IS NULL;
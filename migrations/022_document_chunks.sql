@@ -0,0 +1,15 @@
+-- Migration: Create document_chunks table
+-- Date: 2026-10-15
+-- Purpose: Chunked document text with approximate token counts, for embedding cost estimates
+
+CREATE TABLE IF NOT EXISTS document_chunks (
+    document_id UUID NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    chunk_index INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    -- Character (not byte) offset of the chunk start within documents.content
+    start_offset INTEGER NOT NULL,
+    token_count INTEGER NOT NULL,
+    -- Model the chunk was last embedded with; NULL until embedded
+    embedding_model VARCHAR(200),
+    PRIMARY KEY (document_id, chunk_index)
+);
@@ -0,0 +1,10 @@
+-- Migration: Add title sort keys to documents
+-- Date: 2026-10-15
+-- Purpose: Locale-aware, numeric-aware title ordering; keys are computed in the app
+
+ALTER TABLE documents
+    ADD COLUMN IF NOT EXISTS title_sort TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_documents_user_title_sort
+    ON documents(user_id, title_sort COLLATE "C")
+    WHERE deleted_at IS NULL;
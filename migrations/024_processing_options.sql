@@ -0,0 +1,6 @@
+-- Migration: Store per-document processing options
+-- Date: 2026-10-15
+-- Purpose: Reprocessing repeats the options chosen at upload; NULL means the settings defaults
+
+ALTER TABLE documents
+    ADD COLUMN IF NOT EXISTS processing_options JSONB;
@@ -0,0 +1,5 @@
+-- Migration: Add queued document status
+-- Date: 2026-10-15
+-- Purpose: Uploads are accepted immediately and copied in the background
+
+ALTER TYPE document_status ADD VALUE IF NOT EXISTS 'queued';
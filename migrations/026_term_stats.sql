@@ -0,0 +1,107 @@
+-- Migration: Create document_terms and term_stats tables
+-- Date: 2026-10-15
+-- Purpose: Word frequencies per document, aggregated per owner, workspace and month
+
+-- Term counts of each document's content, stopwords removed
+CREATE TABLE IF NOT EXISTS document_terms (
+    document_id UUID NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    term TEXT NOT NULL,
+    occurrences INTEGER NOT NULL,
+    -- Copied from the document so the aggregates can be decremented even
+    -- when the rows go away with the document itself
+    user_id UUID NOT NULL,
+    workspace_id UUID,
+    month DATE NOT NULL,
+    PRIMARY KEY (document_id, term)
+);
+
+-- Sums of document_terms by owner, workspace and the month documents were created
+CREATE TABLE IF NOT EXISTS term_stats (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    workspace_id UUID REFERENCES workspaces(id) ON DELETE CASCADE,
+    month DATE NOT NULL,
+    term TEXT NOT NULL,
+    occurrences BIGINT NOT NULL,
+    document_count INTEGER NOT NULL
+);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_term_stats_key ON term_stats (
+    user_id,
+    COALESCE(workspace_id, '00000000-0000-0000-0000-000000000000'::uuid),
+    month,
+    term
+);
+CREATE INDEX IF NOT EXISTS idx_term_stats_workspace ON term_stats(workspace_id, term)
+WHERE workspace_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_term_stats_term ON term_stats(term);
+
+-- Keep term_stats in step with document_terms, one statement at a time
+CREATE OR REPLACE FUNCTION add_term_stats() RETURNS TRIGGER AS $$ BEGIN
+INSERT INTO term_stats (user_id, workspace_id, month, term, occurrences, document_count)
+SELECT user_id, workspace_id, month, term, SUM(occurrences), COUNT(*)
+FROM added_terms
+GROUP BY user_id, workspace_id, month, term
+ON CONFLICT (
+    user_id,
+    COALESCE(workspace_id, '00000000-0000-0000-0000-000000000000'::uuid),
+    month,
+    term
+) DO UPDATE
+SET occurrences = term_stats.occurrences + EXCLUDED.occurrences,
+    document_count = term_stats.document_count + EXCLUDED.document_count;
+RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION subtract_term_stats() RETURNS TRIGGER AS $$ BEGIN
+UPDATE term_stats s
+SET occurrences = s.occurrences - r.occurrences,
+    document_count = s.document_count - r.document_count
+FROM (
+        SELECT user_id, workspace_id, month, term,
+            SUM(occurrences) AS occurrences, COUNT(*) AS document_count
+        FROM removed_terms
+        GROUP BY user_id, workspace_id, month, term
+    ) r
+WHERE s.user_id = r.user_id
+    AND COALESCE(s.workspace_id, '00000000-0000-0000-0000-000000000000'::uuid)
+        = COALESCE(r.workspace_id, '00000000-0000-0000-0000-000000000000'::uuid)
+    AND s.month = r.month
+    AND s.term = r.term;
+-- Terms no document uses any more
+DELETE FROM term_stats s
+USING removed_terms r
+WHERE s.user_id = r.user_id
+    AND COALESCE(s.workspace_id, '00000000-0000-0000-0000-000000000000'::uuid)
+        = COALESCE(r.workspace_id, '00000000-0000-0000-0000-000000000000'::uuid)
+    AND s.month = r.month
+    AND s.term = r.term
+    AND s.document_count <= 0;
+RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS add_term_stats_on_insert ON document_terms;
+CREATE TRIGGER add_term_stats_on_insert
+AFTER INSERT ON document_terms
+REFERENCING NEW TABLE AS added_terms
+FOR EACH STATEMENT EXECUTE FUNCTION add_term_stats();
+
+DROP TRIGGER IF EXISTS subtract_term_stats_on_delete ON document_terms;
+CREATE TRIGGER subtract_term_stats_on_delete
+AFTER DELETE ON document_terms
+REFERENCING OLD TABLE AS removed_terms
+FOR EACH STATEMENT EXECUTE FUNCTION subtract_term_stats();
+
+-- Soft-deleted documents stop counting
+CREATE OR REPLACE FUNCTION drop_terms_of_deleted_document() RETURNS TRIGGER AS $$ BEGIN
+DELETE FROM document_terms WHERE document_id = NEW.id;
+RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS drop_terms_on_soft_delete ON documents;
+CREATE TRIGGER drop_terms_on_soft_delete
+AFTER UPDATE OF deleted_at ON documents
+FOR EACH ROW
+WHEN (OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL)
+EXECUTE FUNCTION drop_terms_of_deleted_document();
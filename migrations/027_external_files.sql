@@ -0,0 +1,39 @@
+-- Migration: Add external file flag to documents
+-- Date: 2026-10-15
+-- Purpose: Documents imported by reference keep their file where it is
+
+-- file_path points at the user's own file, outside the storage root; it is
+-- never copied, encrypted, migrated or deleted, and doesn't use storage quota
+ALTER TABLE documents
+ADD COLUMN IF NOT EXISTS external_file BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE OR REPLACE FUNCTION update_storage_usage() RETURNS TRIGGER AS $$ BEGIN IF TG_OP = 'INSERT' THEN -- Increment storage
+    IF NEW.external_file THEN
+        RETURN NEW;
+    END IF;
+    IF NEW.workspace_id IS NOT NULL THEN
+UPDATE workspaces
+SET storage_used_bytes = storage_used_bytes + COALESCE(NEW.file_size_bytes, 0)
+WHERE id = NEW.workspace_id;
+ELSE
+UPDATE users
+SET storage_used_bytes = storage_used_bytes + COALESCE(NEW.file_size_bytes, 0)
+WHERE id = NEW.user_id;
+END IF;
+ELSIF TG_OP = 'DELETE' THEN -- Decrement storage
+IF OLD.external_file THEN
+    RETURN OLD;
+END IF;
+IF OLD.workspace_id IS NOT NULL THEN
+UPDATE workspaces
+SET storage_used_bytes = storage_used_bytes - COALESCE(OLD.file_size_bytes, 0)
+WHERE id = OLD.workspace_id;
+ELSE
+UPDATE users
+SET storage_used_bytes = storage_used_bytes - COALESCE(OLD.file_size_bytes, 0)
+WHERE id = OLD.user_id;
+END IF;
+END IF;
+RETURN COALESCE(NEW, OLD);
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,6 @@
+-- Migration: Add metadata column to documents
+-- Date: 2026-10-15
+-- Purpose: Keep what extractors report about a file, e.g. PDF form fields
+
+ALTER TABLE documents
+ADD COLUMN IF NOT EXISTS metadata JSONB;
@@ -0,0 +1,11 @@
+-- Migration: Add manual unread flag to documents
+-- Date: 2026-10-15
+-- Purpose: Let users mark opened documents unread again until they next open them
+
+-- A document is unread when it has never been opened (last_opened_at IS
+-- NULL) or this is set
+ALTER TABLE documents
+ADD COLUMN IF NOT EXISTS manually_unread BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX IF NOT EXISTS idx_documents_user_unread ON documents(user_id)
+WHERE deleted_at IS NULL AND (manually_unread OR last_opened_at IS NULL);
@@ -0,0 +1,19 @@
+-- Migration: Bump documents.version on every update
+-- Date: 2026-10-15
+-- Purpose: Let the UI tell whether a document changed while it missed events
+
+ALTER TABLE documents
+ADD COLUMN IF NOT EXISTS version INTEGER DEFAULT 1 NOT NULL;
+
+CREATE OR REPLACE FUNCTION bump_document_version() RETURNS TRIGGER AS $$ BEGIN
+NEW.version = OLD.version + 1;
+RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS bump_document_version_on_update ON documents;
+CREATE TRIGGER bump_document_version_on_update
+BEFORE UPDATE ON documents
+FOR EACH ROW EXECUTE FUNCTION bump_document_version();
+
+CREATE INDEX IF NOT EXISTS idx_documents_user_updated_at ON documents(user_id, updated_at);
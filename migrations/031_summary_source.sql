@@ -0,0 +1,8 @@
+-- Migration: Add summary source to documents
+-- Date: 2026-10-15
+-- Purpose: Record which strategy made a document's summary
+
+-- One of csv_columns, email, abstract, comment_block or generic; NULL for
+-- summaries made before strategies existed
+ALTER TABLE documents
+ADD COLUMN IF NOT EXISTS summary_source VARCHAR(32);
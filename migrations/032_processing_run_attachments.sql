@@ -0,0 +1,7 @@
+-- Migration: Add attachment count to processing runs
+-- Date: 2026-10-15
+-- Purpose: Report how many files were found embedded in a processed PDF
+
+-- NULL for documents other than PDFs and for runs before attachments were extracted
+ALTER TABLE processing_runs
+ADD COLUMN IF NOT EXISTS attachments_found INTEGER;
@@ -0,0 +1,9 @@
+-- Migration: Record which code version derived each document's data
+-- Date: 2026-10-15
+-- Purpose: Find documents whose summaries, chunks, term counts or sort keys
+-- predate the current code, so a rebuild only redoes those
+
+-- e.g. {"summaries": 1, "chunks": 1}; a missing key means the data predates
+-- version stamps
+ALTER TABLE documents
+ADD COLUMN IF NOT EXISTS derived_versions JSONB NOT NULL DEFAULT '{}'::jsonb;
@@ -0,0 +1,23 @@
+-- Migration: Record exports with the content they were made from
+-- Date: 2026-10-15
+-- Purpose: Tell whether an exported file still matches its document after
+-- the document is reprocessed or edited
+
+-- document_id has no foreign key: snapshots outlive the document, and a
+-- snapshot whose document is gone or deleted is reported as such
+CREATE TABLE IF NOT EXISTS export_snapshots (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    document_id UUID NOT NULL,
+    -- Title when exported, shown once the document is gone
+    document_title TEXT NOT NULL,
+    -- SHA-256 of documents.content when exported, hex
+    content_hash VARCHAR(64) NOT NULL,
+    -- 'html' or 'markdown'
+    format VARCHAR(20) NOT NULL,
+    dest_path TEXT NOT NULL,
+    exported_at TIMESTAMPTZ DEFAULT NOW() NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_export_snapshots_document
+    ON export_snapshots(document_id, exported_at DESC);
@@ -0,0 +1,19 @@
+-- Migration: Remember where each user stopped reading a document
+-- Date: 2026-10-15
+-- Purpose: Reopen long documents where the reader left off, even after
+-- the content is extracted again
+
+CREATE TABLE IF NOT EXISTS reading_positions (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    document_id UUID NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    -- Char offset into documents.content
+    char_offset INTEGER NOT NULL,
+    page_number INTEGER,
+    -- Share of the document read, 0 to 100
+    percent REAL NOT NULL,
+    -- Content from char_offset on, up to 64 chars, to find the spot again
+    -- once reprocessing has moved it
+    anchor TEXT NOT NULL,
+    updated_at TIMESTAMPTZ DEFAULT NOW() NOT NULL,
+    PRIMARY KEY (user_id, document_id)
+);
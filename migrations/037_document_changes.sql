@@ -0,0 +1,58 @@
+-- Migration: Create document_changes feed
+-- Date: 2026-10-15
+-- Purpose: Let every window and API client catch up on document changes by
+-- polling, not only the window that receives the app's events
+
+DO $$ BEGIN
+CREATE TYPE document_change_kind AS ENUM ('created', 'updated', 'deleted', 'restored');
+EXCEPTION
+WHEN duplicate_object THEN NULL;
+END $$;
+
+CREATE TABLE IF NOT EXISTS document_changes (
+    seq BIGSERIAL PRIMARY KEY,
+    -- No foreign keys, so entries outlive the documents they report deleted
+    document_id UUID NOT NULL,
+    user_id UUID NOT NULL,
+    change_kind document_change_kind NOT NULL,
+    changed_at TIMESTAMPTZ DEFAULT NOW() NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_document_changes_user_seq ON document_changes(user_id, seq);
+CREATE INDEX IF NOT EXISTS idx_document_changes_changed_at ON document_changes(changed_at);
+
+-- Appended by triggers, so each entry commits or rolls back with its change
+CREATE OR REPLACE FUNCTION record_document_change() RETURNS TRIGGER AS $$ BEGIN
+IF TG_OP = 'INSERT' THEN
+    INSERT INTO document_changes (document_id, user_id, change_kind) VALUES (NEW.id, NEW.user_id, 'created');
+ELSIF TG_OP = 'DELETE' THEN
+    INSERT INTO document_changes (document_id, user_id, change_kind) VALUES (OLD.id, OLD.user_id, 'deleted');
+ELSIF OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL THEN
+    INSERT INTO document_changes (document_id, user_id, change_kind) VALUES (NEW.id, NEW.user_id, 'deleted');
+ELSIF OLD.deleted_at IS NOT NULL AND NEW.deleted_at IS NULL THEN
+    INSERT INTO document_changes (document_id, user_id, change_kind) VALUES (NEW.id, NEW.user_id, 'restored');
+ELSE
+    INSERT INTO document_changes (document_id, user_id, change_kind) VALUES (NEW.id, NEW.user_id, 'updated');
+END IF;
+RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS record_document_change_on_write ON documents;
+CREATE TRIGGER record_document_change_on_write
+AFTER INSERT OR UPDATE OR DELETE ON documents
+FOR EACH ROW EXECUTE FUNCTION record_document_change();
+
+-- Tags live in their own table; attaching or removing one updates the document
+CREATE OR REPLACE FUNCTION record_document_tag_change() RETURNS TRIGGER AS $$ BEGIN
+INSERT INTO document_changes (document_id, user_id, change_kind)
+SELECT id, user_id, 'updated'
+FROM documents
+WHERE id = COALESCE(NEW.document_id, OLD.document_id);
+RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS record_document_tag_change_on_write ON document_tags;
+CREATE TRIGGER record_document_tag_change_on_write
+AFTER INSERT OR DELETE ON document_tags
+FOR EACH ROW EXECUTE FUNCTION record_document_tag_change();
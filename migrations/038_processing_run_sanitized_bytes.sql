@@ -0,0 +1,8 @@
+-- Migration: Add sanitized byte count to processing runs
+-- Date: 2026-10-15
+-- Purpose: Report how much extracted text had to change before it could be stored
+
+-- Bytes dropped or replaced: NUL characters and "\r" line endings. NULL for
+-- runs that didn't complete and for runs before text was sanitized
+ALTER TABLE processing_runs
+ADD COLUMN IF NOT EXISTS sanitized_bytes INTEGER;
@@ -0,0 +1,22 @@
+-- Migration: Workspace templates and per-workspace processing defaults
+-- Date: 2026-10-15
+-- Purpose: Start new workspaces with the same tags and processing options
+-- every time, and process uploads into a workspace with its defaults
+
+CREATE TABLE IF NOT EXISTS workspace_templates (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    -- Tags created in a workspace the template is applied to
+    tag_names TEXT[] DEFAULT '{}' NOT NULL,
+    -- Stored as the workspace's processing_defaults; NULL leaves those alone
+    processing_options JSONB,
+    created_at TIMESTAMPTZ DEFAULT NOW() NOT NULL,
+    updated_at TIMESTAMPTZ DEFAULT NOW() NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_workspace_templates_user ON workspace_templates(user_id);
+
+-- Options for uploads into the workspace; NULL means the settings defaults
+ALTER TABLE workspaces
+    ADD COLUMN IF NOT EXISTS processing_defaults JSONB;
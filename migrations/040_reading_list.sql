@@ -0,0 +1,14 @@
+-- Migration: Reading list
+-- Date: 2026-10-15
+-- Purpose: Let each user queue documents to read in an order of their own
+
+CREATE TABLE IF NOT EXISTS reading_list (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    document_id UUID NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    -- 0-based and without gaps per user; rewritten whenever the list changes
+    position INTEGER NOT NULL,
+    added_at TIMESTAMPTZ DEFAULT NOW() NOT NULL,
+    PRIMARY KEY (user_id, document_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_reading_list_position ON reading_list(user_id, position);
@@ -0,0 +1,30 @@
+-- Migration: Import sessions
+-- Date: 2026-10-15
+-- Purpose: Report a folder import as one outcome once every file in it is
+-- done, instead of a notification per document
+
+CREATE TABLE IF NOT EXISTS import_sessions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- Folder the files came from
+    source_path TEXT NOT NULL,
+    total_files INTEGER NOT NULL,
+    -- Files that never became documents: [{path, error, duplicate_of}]
+    unqueued_files JSONB DEFAULT '[]' NOT NULL,
+    started_at TIMESTAMPTZ DEFAULT NOW() NOT NULL,
+    -- Set once every file was queued or left out; the session can't
+    -- finish before then
+    queued_at TIMESTAMPTZ,
+    -- Set when every document reached a final status, or by the startup
+    -- pass for sessions the app was closed during
+    finished_at TIMESTAMPTZ,
+    interrupted BOOLEAN DEFAULT FALSE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_import_sessions_user ON import_sessions(user_id, started_at DESC);
+
+ALTER TABLE documents
+    ADD COLUMN IF NOT EXISTS import_session_id UUID REFERENCES import_sessions(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_documents_import_session ON documents(import_session_id)
+    WHERE import_session_id IS NOT NULL;
@@ -0,0 +1,20 @@
+-- Migration: Documents waiting for an extractor
+-- Date: 2026-10-15
+-- Purpose: Flag files stored without content because no extractor supported
+-- them, so they are processed once one does
+
+ALTER TABLE documents ADD COLUMN IF NOT EXISTS needs_extractor BOOLEAN DEFAULT FALSE NOT NULL;
+
+-- Completed without content and never handled by an extractor
+UPDATE documents d
+SET needs_extractor = TRUE
+WHERE d.status = 'completed'
+  AND d.content IS NULL
+  AND d.deleted_at IS NULL
+  AND NOT EXISTS (
+      SELECT 1 FROM processing_runs r
+      WHERE r.document_id = d.id AND r.extractor_name IS NOT NULL
+  );
+
+CREATE INDEX IF NOT EXISTS idx_documents_needs_extractor ON documents(user_id)
+    WHERE needs_extractor;
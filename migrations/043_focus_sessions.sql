@@ -0,0 +1,34 @@
+-- Migration: Focus sessions
+-- Date: 2026-10-15
+-- Purpose: Record named, time-boxed sessions with the documents opened and
+-- searches run during them, for billing and research logs
+
+CREATE TABLE IF NOT EXISTS focus_sessions (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    started_at TIMESTAMPTZ DEFAULT NOW() NOT NULL,
+    ended_at TIMESTAMPTZ,
+    -- Closed by the startup pass after the app was closed mid-session
+    auto_closed BOOLEAN DEFAULT FALSE NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_focus_sessions_user ON focus_sessions(user_id, started_at DESC);
+
+-- At most one active session per user
+CREATE UNIQUE INDEX IF NOT EXISTS idx_focus_sessions_active ON focus_sessions(user_id)
+    WHERE ended_at IS NULL;
+
+CREATE TABLE IF NOT EXISTS focus_session_events (
+    id BIGSERIAL PRIMARY KEY,
+    session_id UUID NOT NULL REFERENCES focus_sessions(id) ON DELETE CASCADE,
+    kind VARCHAR(20) NOT NULL CHECK (kind IN ('document_opened', 'search')),
+    -- Set for document_opened; a purged document leaves its opens counted
+    document_id UUID REFERENCES documents(id) ON DELETE SET NULL,
+    -- Set for search
+    query TEXT,
+    result_count INTEGER,
+    occurred_at TIMESTAMPTZ DEFAULT NOW() NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_focus_session_events_session ON focus_session_events(session_id, occurred_at);
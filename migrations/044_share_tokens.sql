@@ -0,0 +1,20 @@
+-- Migration: Share tokens
+-- Date: 2026-10-15
+-- Purpose: One-time links to a document served by the local HTTP API;
+-- only a hash of each token is stored
+
+CREATE TABLE IF NOT EXISTS share_tokens (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    document_id UUID NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    -- SHA-256 of the token, hex
+    token_hash TEXT NOT NULL UNIQUE,
+    -- Serve the original file instead of the extracted content as HTML
+    include_original BOOLEAN NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW() NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_share_tokens_user ON share_tokens(user_id, created_at DESC);
@@ -0,0 +1,17 @@
+-- Migration: Hash document pages and tie chunks to their page
+-- Date: 2026-10-15
+-- Purpose: Let reprocessing keep the chunks, and embeddings, of pages whose text didn't change
+
+-- SHA-256 of the page text; NULL for pages stored before hashing
+ALTER TABLE document_pages
+ADD COLUMN IF NOT EXISTS content_hash VARCHAR(64);
+
+-- Page the chunk lies in; NULL for documents without pages, text after the
+-- last page and chunks made before chunking went page by page
+ALTER TABLE document_chunks
+ADD COLUMN IF NOT EXISTS page_number INTEGER;
+
+-- Pages whose chunks were kept from the previous run; NULL for runs that
+-- didn't complete and older runs
+ALTER TABLE processing_runs
+ADD COLUMN IF NOT EXISTS pages_reused INTEGER;
@@ -0,0 +1,10 @@
+-- Migration: Record where documents came from
+-- Date: 2026-10-15
+-- Purpose: Keep each document's import method, source path, app version and import time
+
+-- {"method", "source_path", "folder_root", "app_version", "imported_at"};
+-- NULL for documents imported before provenance was recorded
+ALTER TABLE documents
+ADD COLUMN IF NOT EXISTS provenance JSONB;
+
+CREATE INDEX IF NOT EXISTS idx_documents_import_method ON documents(user_id, (provenance->>'method'));
@@ -0,0 +1,67 @@
+-- Migration: Account for referenced files apart from stored ones
+-- Date: 2026-10-15
+-- Purpose: Track the size of files imported by reference next to storage_used_bytes
+
+-- storage_used_bytes stays the size of the files in the storage root, which
+-- counts against storage_limit_bytes; referenced_bytes is the size of the
+-- user's own files imported by reference, which doesn't
+ALTER TABLE users
+ADD COLUMN IF NOT EXISTS referenced_bytes BIGINT DEFAULT 0 NOT NULL;
+
+ALTER TABLE workspaces
+ADD COLUMN IF NOT EXISTS referenced_bytes BIGINT DEFAULT 0 NOT NULL;
+
+-- Add (or with a negative size, remove) a document's file from the figures
+-- of the workspace it is in, or else of its owner
+CREATE OR REPLACE FUNCTION charge_document_storage(
+    owner_id UUID,
+    document_workspace_id UUID,
+    external BOOLEAN,
+    bytes BIGINT
+) RETURNS VOID AS $$ BEGIN IF document_workspace_id IS NOT NULL THEN
+UPDATE workspaces
+SET storage_used_bytes = storage_used_bytes + CASE WHEN external THEN 0 ELSE bytes END,
+    referenced_bytes = referenced_bytes + CASE WHEN external THEN bytes ELSE 0 END
+WHERE id = document_workspace_id;
+ELSE
+UPDATE users
+SET storage_used_bytes = storage_used_bytes + CASE WHEN external THEN 0 ELSE bytes END,
+    referenced_bytes = referenced_bytes + CASE WHEN external THEN bytes ELSE 0 END
+WHERE id = owner_id;
+END IF;
+END;
+$$ LANGUAGE plpgsql;
+
+-- An update that converts a referenced file to a stored one, or changes its
+-- size, takes the old row off and puts the new one on
+CREATE OR REPLACE FUNCTION update_storage_usage() RETURNS TRIGGER AS $$ BEGIN IF TG_OP IN ('UPDATE', 'DELETE') THEN
+    PERFORM charge_document_storage(OLD.user_id, OLD.workspace_id, OLD.external_file, -COALESCE(OLD.file_size_bytes, 0));
+END IF;
+IF TG_OP IN ('INSERT', 'UPDATE') THEN
+    PERFORM charge_document_storage(NEW.user_id, NEW.workspace_id, NEW.external_file, COALESCE(NEW.file_size_bytes, 0));
+END IF;
+RETURN COALESCE(NEW, OLD);
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS update_storage_on_document_change ON documents;
+CREATE TRIGGER update_storage_on_document_change
+AFTER
+INSERT
+    OR DELETE
+    OR UPDATE OF external_file, file_size_bytes ON documents FOR EACH ROW EXECUTE FUNCTION update_storage_usage();
+
+-- Referenced files imported so far, counted the way the recompute repairs do
+UPDATE users u
+SET referenced_bytes = (
+    SELECT COALESCE(SUM(d.file_size_bytes), 0)
+    FROM documents d
+    WHERE d.user_id = u.id AND d.workspace_id IS NULL AND d.external_file
+);
+
+UPDATE workspaces w
+SET referenced_bytes = (
+    SELECT COALESCE(SUM(d.file_size_bytes), 0)
+    FROM documents d
+    WHERE d.workspace_id = w.id AND d.deleted_at IS NULL AND d.external_file
+);
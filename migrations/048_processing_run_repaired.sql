@@ -0,0 +1,7 @@
+-- Migration: Record PDF repairs on processing runs
+-- Date: 2026-10-15
+-- Purpose: Show which runs read a PDF only after rebuilding its cross-reference table
+
+-- FALSE for runs that read the file as it is, didn't get that far and older runs
+ALTER TABLE processing_runs
+ADD COLUMN IF NOT EXISTS repaired BOOLEAN DEFAULT FALSE NOT NULL;
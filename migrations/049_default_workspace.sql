@@ -0,0 +1,7 @@
+-- Migration: Add a default workspace to users
+-- Date: 2026-10-15
+-- Purpose: Let uploads that name no workspace go in one the user chose
+
+-- Cleared by the same statement that deletes the workspace
+ALTER TABLE users
+ADD COLUMN IF NOT EXISTS default_workspace_id UUID REFERENCES workspaces(id) ON DELETE SET NULL;
@@ -0,0 +1,26 @@
+-- Migration: Tag hierarchy
+-- Date: 2026-10-15
+-- Purpose: Nest tags under a parent tag, e.g. Tax under Finance
+
+-- Deleting a tag with nested tags goes through the app, which moves them up
+-- a level or refuses; the reference keeps a stray DELETE from orphaning
+-- them. It is checked at the end of the statement, so deleting a user or
+-- workspace still removes all of its tags at once.
+ALTER TABLE tags
+ADD COLUMN IF NOT EXISTS parent_tag_id UUID REFERENCES tags(id);
+
+CREATE INDEX IF NOT EXISTS idx_tags_parent ON tags(parent_tag_id);
+
+-- Whether a tag, or a tag it is nested under, has the lowercase name
+-- `wanted`; tag:name/* filters match nested tags with it. UNION stops at a
+-- tag already visited, should the hierarchy ever contain a cycle.
+CREATE OR REPLACE FUNCTION tag_within(tag UUID, wanted TEXT) RETURNS BOOLEAN AS $$
+WITH RECURSIVE lineage(id, parent_tag_id, name) AS (
+    SELECT id, parent_tag_id, name FROM tags WHERE id = tag
+    UNION
+    SELECT t.id, t.parent_tag_id, t.name
+    FROM tags t
+    JOIN lineage l ON t.id = l.parent_tag_id
+)
+SELECT EXISTS (SELECT 1 FROM lineage WHERE LOWER(name) = wanted);
+$$ LANGUAGE sql STABLE;
@@ -0,0 +1,41 @@
+-- Migration: Compress extracted text at rest
+-- Date: 2026-10-15
+-- Purpose: Store document and page text zstd-compressed, and keep the search vector without the plain text
+
+-- Text written from now on goes here compressed, with content left NULL;
+-- rows written before keep their plain content until rewritten
+ALTER TABLE documents
+ADD COLUMN IF NOT EXISTS content_zstd BYTEA;
+
+ALTER TABLE document_pages
+ADD COLUMN IF NOT EXISTS content_zstd BYTEA;
+
+ALTER TABLE document_pages
+ALTER COLUMN content DROP NOT NULL;
+
+-- The search vector can no longer be generated from content, which is NULL
+-- for compressed rows. Its values stay as they are; from now on a trigger
+-- keeps it, and writers of compressed content set its content part
+-- (weight B) from the text they compress.
+ALTER TABLE documents
+ALTER COLUMN content_tsvector DROP EXPRESSION IF EXISTS;
+
+CREATE OR REPLACE FUNCTION documents_content_tsvector() RETURNS TRIGGER AS $$ BEGIN IF NEW.content IS NOT NULL THEN
+    NEW.content_tsvector := setweight(to_tsvector('english', coalesce(NEW.title, '')), 'A')
+        || setweight(to_tsvector('english', NEW.content), 'B');
+ELSIF NEW.content_zstd IS NOT NULL THEN
+    -- The content part as the writer set it, or as it was
+    NEW.content_tsvector := setweight(to_tsvector('english', coalesce(NEW.title, '')), 'A')
+        || ts_filter(coalesce(NEW.content_tsvector, ''::tsvector), '{b}');
+ELSE
+    NEW.content_tsvector := setweight(to_tsvector('english', coalesce(NEW.title, '')), 'A');
+END IF;
+RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS documents_content_tsvector ON documents;
+CREATE TRIGGER documents_content_tsvector
+BEFORE
+INSERT
+    OR UPDATE OF title, content, content_zstd ON documents FOR EACH ROW EXECUTE FUNCTION documents_content_tsvector();
@@ -0,0 +1,17 @@
+-- Migration: Store file types as text
+-- Date: 2026-10-15
+-- Purpose: Keep the file type the app writes, an upper-case extension such as "PDF" or "FILE"
+
+-- The file_type enum only has a handful of lower-case values, so most
+-- documents the app stores couldn't be inserted; file types are compared
+-- case-insensitively everywhere they are filtered. The view reading the
+-- column is recreated around the change.
+DROP VIEW IF EXISTS active_documents;
+
+ALTER TABLE documents
+ALTER COLUMN file_type TYPE VARCHAR(50) USING file_type::text;
+
+CREATE VIEW active_documents AS
+SELECT *
+FROM documents
+WHERE deleted_at IS NULL;
@@ -0,0 +1,13 @@
+-- Migration: Move storage usage with a document's workspace
+-- Date: 2026-10-15
+-- Purpose: Charge a document's file to the workspace it is moved into instead of leaving it on the old owner
+
+-- Moving a document between workspaces, or in or out of one, didn't fire
+-- the trigger, so its bytes stayed on whoever the row was charged to when
+-- it was inserted
+DROP TRIGGER IF EXISTS update_storage_on_document_change ON documents;
+CREATE TRIGGER update_storage_on_document_change
+AFTER
+INSERT
+    OR DELETE
+    OR UPDATE OF external_file, file_size_bytes, workspace_id ON documents FOR EACH ROW EXECUTE FUNCTION update_storage_usage();
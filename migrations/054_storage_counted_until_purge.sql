@@ -0,0 +1,32 @@
+-- Migration: Recount storage usage the way the trigger charges it
+-- Date: 2026-10-15
+-- Purpose: Make every user's and workspace's figures match charge_document_storage()
+
+-- A document's file is charged to its workspace, or else to its owner,
+-- from insert until it is purged; a soft-deleted file still takes up
+-- space. The 047 backfill skipped soft-deleted documents for workspaces,
+-- and documents moved into a workspace after their insert stayed charged
+-- to their owner.
+UPDATE users u
+SET storage_used_bytes = (
+        SELECT COALESCE(SUM(d.file_size_bytes), 0)
+        FROM documents d
+        WHERE d.user_id = u.id AND d.workspace_id IS NULL AND NOT d.external_file
+    ),
+    referenced_bytes = (
+        SELECT COALESCE(SUM(d.file_size_bytes), 0)
+        FROM documents d
+        WHERE d.user_id = u.id AND d.workspace_id IS NULL AND d.external_file
+    );
+
+UPDATE workspaces w
+SET storage_used_bytes = (
+        SELECT COALESCE(SUM(d.file_size_bytes), 0)
+        FROM documents d
+        WHERE d.workspace_id = w.id AND NOT d.external_file
+    ),
+    referenced_bytes = (
+        SELECT COALESCE(SUM(d.file_size_bytes), 0)
+        FROM documents d
+        WHERE d.workspace_id = w.id AND d.external_file
+    );